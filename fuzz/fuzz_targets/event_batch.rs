@@ -0,0 +1,27 @@
+//! Batches of any number of events in an arena of any contents.
+#![no_main]
+use gers_api::event::event_records;
+use gers_events::{decode_event, ScreenshotTakenEvent};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, Vec<u8>)| {
+    let (count, bytes) = input;
+    // Aligned like the guest's arena.
+    let mut arena = vec![0u64; bytes.len().div_ceil(8)];
+    let start = arena.as_mut_ptr() as *mut u8;
+    // SAFETY: The arena holds at least `bytes.len()` bytes.
+    unsafe { start.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len()) };
+    let end = start as usize + bytes.len();
+
+    // SAFETY: The arena is valid for `bytes.len()` bytes.
+    for (header, data) in unsafe { event_records(start, bytes.len(), count as u32) } {
+        assert!(
+            data as usize <= end,
+            "record data past the end of the arena"
+        );
+        let size = (header.size as usize).min(end - data as usize);
+        // SAFETY: The data is in the arena.
+        let data = unsafe { std::slice::from_raw_parts(data, size) };
+        let _ = decode_event::<ScreenshotTakenEvent>(data);
+    }
+});
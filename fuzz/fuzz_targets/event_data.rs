@@ -0,0 +1,44 @@
+//! Events sent one at a time, with any header and data, at any offset
+//! into or past the event buffer the guest allocated.
+#![no_main]
+use arbitrary::Arbitrary;
+use gers_api::plugin::{event_alloc, EventData};
+use gers_events::{DamageEvent, HelloEvent, ScreenshotTakenEvent};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Size and alignment the host asks for.
+    size: u16,
+    align: u8,
+    /// Offset of the data pointer the host passes.
+    offset: u16,
+    event_type: u8,
+    bytes: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let ptr = event_alloc(input.size as u32, input.align as u32);
+    if ptr.is_null() {
+        return;
+    }
+    let len = input.bytes.len().min(input.size as usize);
+    // SAFETY: The buffer holds at least `size` bytes.
+    unsafe { ptr.copy_from_nonoverlapping(input.bytes.as_ptr(), len) };
+
+    // SAFETY: Pointers outside of the buffer are never read through.
+    let mut data = unsafe { EventData::after_header(ptr.wrapping_add(input.offset as usize)) };
+    match input.event_type % 3 {
+        0 => {
+            let _ = data.decode::<HelloEvent>();
+        }
+        1 => {
+            if let Ok(damage) = data.decode::<DamageEvent>() {
+                damage.amount *= 0.5;
+            }
+        }
+        _ => {
+            let _ = data.deserialize::<ScreenshotTakenEvent>();
+        }
+    }
+});
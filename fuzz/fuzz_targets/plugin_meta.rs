@@ -0,0 +1,27 @@
+//! `plugin.toml` files as mod authors write them.
+#![no_main]
+use gers_plugins::{Permission, PluginMeta};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    let meta: PluginMeta = match toml::from_str(text) {
+        Ok(meta) => meta,
+        Err(_) => return,
+    };
+
+    meta.needs_module();
+    for permission in [
+        Permission::Storage,
+        Permission::Network,
+        Permission::Http,
+        Permission::Time,
+        Permission::Window,
+        Permission::Clipboard,
+    ] {
+        meta.permissions.is_granted(permission);
+    }
+    for decl in meta.settings.values() {
+        let _ = decl.check(decl.default_value());
+        let _ = decl.parse(text);
+    }
+});
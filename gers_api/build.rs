@@ -0,0 +1,10 @@
+//! Generates the raw host imports from the interface in `gers_interface`.
+use gers_interface::{guest, Interface};
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let code = guest::generate(&Interface::gers());
+
+    fs::write(out_dir.join("sys.rs"), code).expect("writing host imports");
+}
@@ -0,0 +1,22 @@
+//! Asset loading.
+//!
+//! Paths are relative to the `assets` directory of the plugin.
+use crate::sys;
+
+/// Handle of a loaded texture, handed out by the host.
+pub type TextureId = u64;
+
+/// Load an image file as a texture, for use in draw commands.
+///
+/// Returns `None` when the file couldn't be loaded.
+pub fn load_texture(path: &str) -> Option<TextureId> {
+    match sys::gers_assets::load_texture(path) {
+        0 => None,
+        texture_id => Some(texture_id),
+    }
+}
+
+/// Release a texture. Returns `false` if it didn't exist.
+pub fn unload(texture: TextureId) -> bool {
+    sys::gers_assets::unload(texture) != 0
+}
@@ -0,0 +1,37 @@
+//! Sound effects and music.
+//!
+//! Each plugin can only have a limited number of sounds
+//! playing at the same time. Further calls to `play` fail
+//! until one of the playing sounds finishes or is stopped.
+use crate::sys;
+
+/// Handle of a loaded sound, handed out by the host.
+pub type SoundId = u64;
+
+/// Handle of a playing voice, handed out by the host.
+pub type VoiceId = u64;
+
+/// Load a sound file from the plugin's `assets` directory.
+///
+/// Returns `None` when the file couldn't be loaded.
+pub fn load(path: &str) -> Option<SoundId> {
+    match sys::gers_audio::load(path) {
+        0 => None,
+        sound_id => Some(sound_id),
+    }
+}
+
+/// Start playing a loaded sound, with volume where `1.0` is unchanged.
+///
+/// Returns the handle of the playing voice.
+pub fn play(sound: SoundId, volume: f32) -> Option<VoiceId> {
+    match sys::gers_audio::play(sound, volume) {
+        0 => None,
+        voice => Some(voice),
+    }
+}
+
+/// Stop a playing voice. Returns `false` if it already finished.
+pub fn stop(voice: VoiceId) -> bool {
+    sys::gers_audio::stop(voice) != 0
+}
@@ -0,0 +1,33 @@
+//! Clipboard text.
+//!
+//! Requires the `clipboard` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+//!
+//! Reading an empty clipboard, or one that doesn't hold text, fails
+//! with `HostError::NotFound`.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Copy the text on the clipboard.
+pub fn get_text() -> Result<String, HostError> {
+    let mut buf = vec![0; 256];
+
+    loop {
+        let code = sys::gers_clipboard::get_text(&mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return String::from_utf8(buf).map_err(|_| HostError::InvalidArgument);
+        }
+
+        // Text didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+/// Put text on the clipboard.
+pub fn set_text(text: &str) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_clipboard::set_text(text)).map(|_| ())
+}
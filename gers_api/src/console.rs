@@ -0,0 +1,35 @@
+//! Console commands.
+//!
+//! Commands are invoked by the user typing their name into the console,
+//! followed by arguments. The plugin receives a `ConsoleCommandEvent`
+//! with the id returned by `register`, and can read the arguments with
+//! `args` while the event is handled.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Command id handed out by the host.
+pub type CommandId = u32;
+
+/// Register a command, with a line of help listed by the console's
+/// `help` command. Names can't contain whitespace.
+pub fn register(name: &str, help: &str) -> Result<CommandId, HostError> {
+    HostError::from_code(sys::gers_console::register(name, help))
+}
+
+/// Copy the arguments of the command that is being handled.
+pub fn args() -> Result<String, HostError> {
+    let mut buf = vec![0; 256];
+
+    loop {
+        let size = HostError::from_code(sys::gers_console::args(&mut buf))? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return String::from_utf8(buf).map_err(|_| HostError::InvalidArgument);
+        }
+
+        // Arguments didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
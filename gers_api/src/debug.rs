@@ -0,0 +1,78 @@
+//! Values watched live while debugging.
+//!
+//! Watched values are read out of the plugin's memory by the host every
+//! frame, and shown by name in the debug overlay toggled with F3, and
+//! printed by the console's `watch` command:
+//!
+//! ```ignore
+//! static mut SCORE: u32 = 0;
+//!
+//! debug::watch("score", unsafe { std::ptr::addr_of!(SCORE) })?;
+//! ```
+//!
+//! Only the address is passed, so the value has to stay in place for as
+//! long as it's watched, like a static or the contents of a box.
+use gers_events::{HostError, WatchType};
+
+use crate::sys;
+
+/// Values the host can read and show.
+pub trait Watchable {
+    const WATCH_TYPE: WatchType;
+}
+
+macro_rules! impl_watchable {
+    ($($ty:ty => $watch_type:ident),* $(,)?) => {
+        $(
+            impl Watchable for $ty {
+                const WATCH_TYPE: WatchType = WatchType::$watch_type;
+            }
+        )*
+    };
+}
+
+impl_watchable!(
+    i32 => I32,
+    u32 => U32,
+    i64 => I64,
+    u64 => U64,
+    f32 => F32,
+    f64 => F64,
+    bool => Bool,
+);
+
+/// Show the value at `value` under the name, which can't contain line
+/// breaks. Watching a name again moves it to the new value.
+pub fn watch<T: Watchable>(name: &str, value: *const T) -> Result<(), HostError> {
+    let code = sys::gers_debug::watch(name, value as usize as u32, T::WATCH_TYPE as u32);
+    HostError::from_code(code).map(|_| ())
+}
+
+/// Stop showing the value watched under the name.
+pub fn unwatch(name: &str) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_debug::unwatch(name)).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size_of<T: Watchable>(_: T) -> (usize, u32) {
+        (std::mem::size_of::<T>(), T::WATCH_TYPE.size())
+    }
+
+    #[test]
+    fn test_watch_types_match_sizes() {
+        for (size, watch_size) in [
+            size_of(0_i32),
+            size_of(0_u32),
+            size_of(0_i64),
+            size_of(0_u64),
+            size_of(0_f32),
+            size_of(0_f64),
+            size_of(false),
+        ] {
+            assert_eq!(size as u32, watch_size);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! Drawing commands.
+//!
+//! Draw calls are queued by the host and rendered in
+//! submission order at the end of the frame. Colors are
+//! packed as `0xRRGGBBAA`.
+use crate::{assets::TextureId, sys};
+
+/// Fill an axis aligned rectangle.
+pub fn rect(x: f32, y: f32, width: f32, height: f32, color: u32) {
+    sys::gers_draw::rect(x, y, width, height, color)
+}
+
+/// Draw a one pixel wide line between two points.
+pub fn line(x0: f32, y0: f32, x1: f32, y1: f32, color: u32) {
+    sys::gers_draw::line(x0, y0, x1, y1, color)
+}
+
+/// Draw a texture with its top left corner at the given position.
+pub fn sprite(texture: TextureId, x: f32, y: f32) {
+    sys::gers_draw::sprite(texture, x, y)
+}
@@ -0,0 +1,189 @@
+//! Subscribing to host events.
+//!
+//! Plugins that never subscribe receive every event. Once subscribed
+//! to an event type, a plugin only receives the types it subscribed to.
+//! Events are sent to plugins with a higher priority first.
+//!
+//! Mutable events, like `DamageEvent`, may be modified or cancelled in
+//! place by the handler, before they reach lower priority plugins.
+//!
+//! Plugins exporting an event arena receive the frame's other events in
+//! one batch, which `event_records` walks.
+//!
+//! Every event comes with an `EventHeader`, holding the frame and fixed
+//! update it was sent in, and the plugin that emitted it, if any, which
+//! `header` returns while the event is handled. Compare the source with
+//! `plugin_id` to attribute events to plugins by name.
+//!
+//! Plugins define their own event types in an event manifest, which
+//! they return from `__gers_event_manifest` with `pack_manifest`. Any
+//! plugin can `publish` a defined type by name, and it's sent as a
+//! `CustomEvent` to the plugins whose manifest handles the type.
+//! Plugins can also `emit` events under any name, without a manifest,
+//! which go to the plugins that called `subscribe_custom` with it.
+pub use gers_events::plugin_id;
+use gers_events::{
+    EventHeader, EventStamp, EventType, HostError, EVENT_HEADER_SIZE, EVENT_RECORD_ALIGN,
+    EVENT_SOURCE_HOST, SUBSCRIBE_CONSUME,
+};
+
+use crate::sys;
+
+// Single threaded, like the event buffers in `plugin`.
+
+/// Header of the event being handled.
+static mut HEADER: EventHeader = EventHeader {
+    event_type: 0,
+    size: 0,
+    stamp: EventStamp {
+        frame_index: 0,
+        tick_index: 0,
+    },
+    flags: 0,
+    source: EVENT_SOURCE_HOST,
+};
+
+/// Header of the event being handled, or of the last event handled
+/// outside of event handlers.
+pub fn header() -> EventHeader {
+    // SAFETY: Single threaded, see above.
+    unsafe { *std::ptr::addr_of!(HEADER) }
+}
+
+/// Frame and fixed update the event being handled was sent in.
+pub fn stamp() -> EventStamp {
+    header().stamp
+}
+
+/// Set the header of the event about to be handled.
+#[doc(hidden)]
+pub fn set_header(header: EventHeader) {
+    // SAFETY: Single threaded, see above.
+    unsafe { *std::ptr::addr_of_mut!(HEADER) = header };
+}
+
+/// Receive events of a type, replacing an earlier subscription to it.
+///
+/// With `consume` the plugin may stop the events from reaching
+/// plugins with a lower priority, by calling `consume` while
+/// handling them.
+pub fn subscribe(event_type: EventType, priority: i32, consume: bool) -> Result<(), HostError> {
+    let flags = if consume { SUBSCRIBE_CONSUME } else { 0 };
+    HostError::from_code(sys::gers_event::subscribe(
+        event_type as i32,
+        priority,
+        flags,
+    ))
+    .map(|_| ())
+}
+
+/// Consume the event that is being handled.
+///
+/// Returns `false` if the plugin didn't subscribe to the
+/// event type with `consume`.
+pub fn consume() -> bool {
+    sys::gers_event::consume() != 0
+}
+
+/// Deal damage to an entity, emitting a `DamageEvent`.
+pub fn emit_damage(entity: u32, source: u32, amount: f32) {
+    sys::gers_event::emit_damage(entity, source, amount)
+}
+
+/// Id the host gave the custom event type with the given name, to
+/// compare against `CustomEvent::type_id`.
+pub fn custom_event_id(name: &str) -> Result<u32, HostError> {
+    HostError::from_code(sys::gers_event::custom_id(name))
+}
+
+/// Publish a custom event by the name of its type, which is sent to the
+/// plugins handling it when events are next dispatched.
+///
+/// The data can hold up to `CUSTOM_EVENT_DATA_SIZE` bytes.
+pub fn publish(name: &str, data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_event::publish(name, data)).map(|_| ())
+}
+
+/// Raise a custom event by name, which is sent to the plugins handling
+/// or subscribed to it when events are next dispatched.
+///
+/// The name doesn't have to be defined in an event manifest. The data
+/// can hold up to `CUSTOM_EVENT_DATA_SIZE` bytes.
+pub fn emit(name: &str, data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_event::emit(name, data)).map(|_| ())
+}
+
+/// Receive custom events raised under a name, returning the id they
+/// arrive with as `CustomEvent::type_id`.
+pub fn subscribe_custom(name: &str) -> Result<u32, HostError> {
+    HostError::from_code(sys::gers_event::subscribe_custom(name))
+}
+
+/// Pack an event manifest into the value `__gers_event_manifest`
+/// returns.
+///
+/// The manifest lists one custom event type per line, as
+/// `defines <name>` or `handles <name>`.
+pub fn pack_manifest(manifest: &'static str) -> u64 {
+    manifest.as_ptr() as u32 as u64 | (manifest.len() as u64) << 32
+}
+
+/// Events the host wrote into the event arena, as their header and a
+/// pointer to the event data.
+///
+/// Records are only read from the `len` bytes of the arena, so a batch
+/// with more records, or larger ones, than fit in it ends early, and
+/// the data of a record is cut off at the end of the arena.
+///
+/// # Safety
+///
+/// The arena must be the one exported through `__gers_event_arena`,
+/// and be valid for `len` bytes.
+pub unsafe fn event_records(arena: *const u8, len: usize, count: u32) -> EventRecords {
+    EventRecords {
+        next: arena,
+        end: arena.add(len),
+        remaining: count,
+    }
+}
+
+/// Iterator over a batch of events in the event arena.
+pub struct EventRecords {
+    next: *const u8,
+    /// End of the arena.
+    end: *const u8,
+    remaining: u32,
+}
+
+impl Iterator for EventRecords {
+    type Item = (EventHeader, *const u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        // Records are aligned by address.
+        let align = EVENT_RECORD_ALIGN as usize;
+        let padding = (align - self.next as usize % align) % align;
+        let available = self.end as usize - self.next as usize;
+        if available < padding + EVENT_HEADER_SIZE {
+            self.remaining = 0;
+            return None;
+        }
+
+        // SAFETY: The header is in the arena, see `event_records`, and
+        //         the data is cut off at its end.
+        unsafe {
+            let record = self.next.add(padding);
+            let header = EventHeader::decode(std::slice::from_raw_parts(record, EVENT_HEADER_SIZE))
+                .unwrap_or_default();
+            let data = record.add(EVENT_HEADER_SIZE);
+            let size = (header.size as usize).min(self.end as usize - data as usize);
+            self.next = data.add(size);
+
+            Some((header, data))
+        }
+    }
+}
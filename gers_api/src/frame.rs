@@ -0,0 +1,76 @@
+//! Values of the current frame, read without calling into the host.
+//!
+//! `delta_time()`, `frame_index()` and `tick_index()` here are plain
+//! reads, for hot loops where calling an import every time adds up. The
+//! host offers them as the globals `gers.delta_time`, `gers.frame_index`
+//! and `gers.tick_index`, which Rust can't import, so `gers_plugin!`
+//! exports `__gers_frame_globals` instead, and the host writes the same
+//! values into the plugin's memory before every frame's updates.
+//!
+//! They're zero until the first frame.
+use gers_events::EventStamp;
+pub use gers_events::FrameGlobals;
+
+// Single threaded, like the event buffers in `plugin`.
+
+/// Written by the host before each frame's updates.
+static mut FRAME_GLOBALS: FrameGlobals = FrameGlobals {
+    frame_index: 0,
+    tick_index: 0,
+    delta_time: 0.0,
+};
+
+/// Variable delta time since the last frame, in seconds, multiplied
+/// by the time scale, like `crate::delta_time`.
+pub fn delta_time() -> f32 {
+    read().delta_time
+}
+
+/// Number of frames before the current one.
+pub fn frame_index() -> u64 {
+    read().frame_index
+}
+
+/// Number of fixed updates before the current frame.
+pub fn tick_index() -> u64 {
+    read().tick_index
+}
+
+/// Stamp of the events sent in the current frame.
+pub fn stamp() -> EventStamp {
+    read().stamp()
+}
+
+fn read() -> FrameGlobals {
+    // SAFETY: Single threaded. The host writes the values between
+    //         calls, which the compiler can't see, so they're read
+    //         from memory every time.
+    unsafe { std::ptr::read_volatile(std::ptr::addr_of!(FRAME_GLOBALS)) }
+}
+
+/// Address the host writes the values of the current frame to.
+#[doc(hidden)]
+pub fn frame_globals() -> *mut FrameGlobals {
+    std::ptr::addr_of_mut!(FRAME_GLOBALS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_host_writes() {
+        // Like the host, through the exported address.
+        unsafe {
+            frame_globals().write(FrameGlobals {
+                frame_index: 7,
+                tick_index: 12,
+                delta_time: 0.5,
+            })
+        };
+
+        assert_eq!(frame_index(), 7);
+        assert_eq!(tick_index(), 12);
+        assert_eq!(delta_time(), 0.5);
+    }
+}
@@ -0,0 +1,59 @@
+//! Files in the plugin's data directory.
+//!
+//! Every plugin has its own data directory, `data/<plugin>/`, which
+//! the host creates when the plugin is loaded and removes when it's
+//! uninstalled. Paths are relative to it, with parts separated by `/`,
+//! and can't reach outside of it. Like keys in `storage`, which are
+//! kept in the same directory, each part may contain ASCII letters,
+//! digits, `-`, `_` and `.`, and can't start with a `.`.
+//!
+//! Requires the `storage` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Path of the plugin's data directory on the host.
+pub fn data_dir() -> Result<String, HostError> {
+    let mut buf = vec![0; 64];
+
+    loop {
+        let size = HostError::from_code(sys::gers_fs::data_dir(&mut buf))? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return String::from_utf8(buf).map_err(|_| HostError::InvalidArgument);
+        }
+
+        // Path didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+/// Read a whole file.
+pub fn read(path: &str) -> Result<Vec<u8>, HostError> {
+    let mut buf = vec![0; 256];
+
+    loop {
+        let size = HostError::from_code(sys::gers_fs::read_file(path, &mut buf))? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return Ok(buf);
+        }
+
+        // File didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+/// Write a file, replacing it if it exists, and creating the
+/// directories it's in.
+pub fn write(path: &str, data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_fs::write_file(path, data)).map(|_| ())
+}
+
+/// Remove a file.
+pub fn remove(path: &str) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_fs::remove_file(path)).map(|_| ())
+}
@@ -0,0 +1,102 @@
+//! What the host running the plugin provides.
+//!
+//! When the plugin is initialised, the host passes its protocol
+//! version and the subsystems it provides to `__gers_init`, generated
+//! by `gers_plugin!`. Check for a subsystem before relying on it, as
+//! the host may be built without it, or the plugin may not have been
+//! granted the permission it needs:
+//!
+//! ```ignore
+//! use gers_api::{host, prelude::*};
+//!
+//! if host::has(Capabilities::AUDIO) {
+//!     // Play the music.
+//! }
+//! ```
+//!
+//! The host passes the plugin's configuration along, the `[config]` of
+//! its `plugin.toml` with the values of its settings, as TOML text:
+//!
+//! ```ignore
+//! let config: Config = toml::from_str(host::config())?;
+//! ```
+use gers_events::Capabilities;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::strings;
+
+/// Host's protocol version, zero until the host is known.
+static VERSION: AtomicU32 = AtomicU32::new(0);
+
+static CAPABILITIES: AtomicU32 = AtomicU32::new(0);
+
+/// Protocol version of the host, or `None` before the plugin is
+/// initialised.
+pub fn version() -> Option<u32> {
+    match VERSION.load(Ordering::Relaxed) {
+        0 => None,
+        version => Some(version),
+    }
+}
+
+/// Subsystems the host provides to the plugin.
+pub fn capabilities() -> Capabilities {
+    Capabilities(CAPABILITIES.load(Ordering::Relaxed))
+}
+
+/// The host provides all subsystems in `capabilities`.
+pub fn has(capabilities: Capabilities) -> bool {
+    self::capabilities().contains(capabilities)
+}
+
+/// Configuration passed to `__gers_init`.
+static mut CONFIG: String = String::new();
+
+/// Configuration of the plugin as TOML text, see above. Empty before
+/// the plugin is initialised, and when it has none.
+pub fn config() -> &'static str {
+    // SAFETY: Single threaded, and only written once, by `init`.
+    unsafe { (*std::ptr::addr_of!(CONFIG)).as_str() }
+}
+
+/// Keep what the host passed to `__gers_init`.
+///
+/// The configuration is copied, as the host releases it once
+/// `__gers_init` returns.
+///
+/// # Safety
+///
+/// The configuration pointer must be valid for `config_len` bytes.
+#[doc(hidden)]
+pub unsafe fn init(version: u32, capabilities: u32, config_ptr: *const u8, config_len: u32) {
+    VERSION.store(version, Ordering::Relaxed);
+    CAPABILITIES.store(capabilities, Ordering::Relaxed);
+
+    if let Ok(config) = strings::recv_str(config_ptr, config_len) {
+        // SAFETY: Single threaded, see `config`.
+        *std::ptr::addr_of_mut!(CONFIG) = config.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init() {
+        let config = "difficulty = 2\n";
+        unsafe {
+            init(
+                2,
+                (Capabilities::DRAW | Capabilities::HTTP).bits(),
+                config.as_ptr(),
+                config.len() as u32,
+            )
+        };
+        assert_eq!(version(), Some(2));
+        assert_eq!(self::config(), config);
+        assert!(has(Capabilities::DRAW));
+        assert!(has(Capabilities::DRAW | Capabilities::HTTP));
+        assert!(!has(Capabilities::AUDIO | Capabilities::HTTP));
+    }
+}
@@ -0,0 +1,42 @@
+//! HTTP requests.
+//!
+//! Requires the `http` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+//!
+//! Requests are executed by the host in the background. The response
+//! is delivered as an `HttpResponseEvent`, and its body can only be
+//! read with `response_body` while that event is handled.
+use gers_events::{HostError, HttpMethod};
+
+use crate::sys;
+
+/// Handle of a request, handed out by the host.
+pub type RequestId = u64;
+
+/// Start a request, with a body unless it's empty.
+pub fn request(method: HttpMethod, url: &str, body: &[u8]) -> Result<RequestId, HostError> {
+    HostError::from_handle_code(sys::gers_http::request(method as u32, url, body))
+}
+
+/// Shorthand for a `GET` request.
+pub fn get(url: &str) -> Result<RequestId, HostError> {
+    request(HttpMethod::Get, url, &[])
+}
+
+/// Copy the body of the response that is being handled.
+pub fn response_body(request: RequestId) -> Result<Vec<u8>, HostError> {
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let code = sys::gers_http::response_body(request, &mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return Ok(buf);
+        }
+
+        // Body didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
@@ -0,0 +1,49 @@
+//! Localized strings.
+//!
+//! Plugins ship a string table for each locale they support, in
+//! `lang/<locale>.toml` next to `plugin.toml`:
+//!
+//! ```toml
+//! greeting = "Hallo"
+//!
+//! [menu]
+//! start = "Spiel starten"
+//! ```
+//!
+//! Nested tables are looked up with dotted keys, like `menu.start`.
+//! Keys the active locale doesn't translate fall back to the host's
+//! fallback locale. The plugin receives a `LocaleChangedEvent` when
+//! the user switches language.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// String of a key in the active locale.
+///
+/// Fails with `HostError::NotFound` when no table has the key.
+pub fn get(key: &str) -> Result<String, HostError> {
+    let mut buf = vec![0; 64];
+
+    loop {
+        let size = HostError::from_code(sys::gers_i18n::get(key, &mut buf))? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return String::from_utf8(buf).map_err(|_| HostError::InvalidArgument);
+        }
+
+        // String didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+/// String of a key in the active locale, or the key itself when it
+/// isn't translated, so missing strings stand out.
+pub fn tr(key: &str) -> String {
+    get(key).unwrap_or_else(|_| key.to_string())
+}
+
+/// The active locale, like `en` or `pt-BR`.
+pub fn locale() -> String {
+    crate::read_host_string(sys::gers_i18n::locale)
+}
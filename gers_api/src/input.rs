@@ -0,0 +1,18 @@
+//! Named input actions.
+//!
+//! Actions are delivered to the plugin as `ActionEvent`s
+//! through the event protocol.
+use crate::sys;
+
+/// Register an action, optionally bound to a default key.
+///
+/// Key names are spelled like winit's `VirtualKeyCode`, for example
+/// `"Space"` or `"W"`. Registering an action that already exists,
+/// including one declared in `plugin.toml`, returns its id.
+pub fn register_action(name: &str, default_key: Option<&str>) -> Option<u32> {
+    let key = default_key.unwrap_or("");
+    match sys::gers_input::register_action(name, key) {
+        0 => None,
+        action_id => Some(action_id),
+    }
+}
@@ -0,0 +1,122 @@
+//! Guest side API for writing gers plugins.
+//!
+//! Wraps the raw host imports in safe shims so plugin code
+//! doesn't have to deal with pointers directly. The exports the host
+//! calls into are generated with `gers_plugin!`, see `plugin`.
+pub mod assets;
+pub mod audio;
+pub mod clipboard;
+pub mod console;
+pub mod debug;
+pub mod draw;
+pub mod event;
+pub mod frame;
+pub mod fs;
+pub mod host;
+pub mod http;
+pub mod i18n;
+pub mod input;
+pub mod log_args;
+pub mod net;
+pub mod panic;
+pub mod plugin;
+pub mod prelude;
+pub mod save;
+pub mod settings;
+pub mod storage;
+pub mod strings;
+mod sys;
+pub mod task;
+pub mod time;
+pub mod window;
+pub mod world;
+
+pub use gers_events::{HostError, UpdateStatus};
+pub use panic::set_panic_hook;
+pub use strings::recv_str;
+
+/// Safe shim for printing a log message.
+///
+/// # Safety
+///
+/// As long as the host doesn't retain the passed pointer, the
+/// borrow should keep the string pointer and data in place and
+/// unmutated for the duration of the call.
+pub fn log(message: &str) {
+    sys::gers::log_info(message);
+}
+
+/// Log a message formatted by the host from the template and packed
+/// arguments, see `log_fmt!`.
+pub fn log_fmt(template: &str, args: &log_args::LogArgs) -> Result<(), HostError> {
+    HostError::from_code(sys::gers::log_fmt(template, args.as_bytes())).map(|_| ())
+}
+
+/// Write the messages logged so far to the plugin's log file, which
+/// are buffered otherwise.
+///
+/// Useful before operations that may crash the plugin, or the game.
+pub fn log_flush() -> Result<(), HostError> {
+    HostError::from_code(sys::gers::log_flush()).map(|_| ())
+}
+
+/// Variable delta time since the last frame, in seconds, multiplied
+/// by the time scale. Zero while time is frozen.
+///
+/// Calls into the host, see `frame::delta_time` for hot loops.
+pub fn delta_time() -> f32 {
+    sys::gers::get_delta_time()
+}
+
+/// Variable delta time since the last frame, in seconds, regardless
+/// of the time scale.
+pub fn unscaled_delta_time() -> f32 {
+    sys::gers::get_unscaled_delta_time()
+}
+
+/// Add a named mark to the host's trace, shown at the time of the
+/// call when the host runs with `--trace`.
+pub fn trace_event(name: &str) {
+    sys::gers::trace_event(name);
+}
+
+/// Tell the host the module's memory grew to `new_pages` pages.
+///
+/// Allocators that grow memory outside of host calls should report
+/// it, so the host drops views of the memory it cached.
+pub fn memory_grown(new_pages: u32) {
+    sys::gers::memory_grown(new_pages)
+}
+
+/// Seed for random number generators, which is the same
+/// for every plugin during a run.
+///
+/// Passing the same `--seed` to the host reproduces a run.
+pub fn seed() -> u64 {
+    sys::gers::get_seed()
+}
+
+/// Name of this plugin, as in its `plugin.toml`.
+pub fn plugin_name() -> String {
+    read_host_string(sys::gers::plugin_name)
+}
+
+/// Version of this plugin, as in its `plugin.toml`.
+pub fn plugin_version() -> String {
+    read_host_string(sys::gers::plugin_version)
+}
+
+/// Call an import that copies a string into a buffer and returns its
+/// full length, growing the buffer until the string fits.
+fn read_host_string(mut read: impl FnMut(&mut [u8]) -> u32) -> String {
+    let mut buf = vec![0u8; 64];
+
+    loop {
+        let len = read(&mut buf) as usize;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return String::from_utf8_lossy(&buf).into_owned();
+        }
+        buf.resize(len, 0);
+    }
+}
@@ -0,0 +1,162 @@
+//! Arguments of messages formatted by the host.
+//!
+//! `log_fmt!` logs like `format!`, but only packs the arguments into a
+//! buffer on the stack, and the host formats the message, so logging
+//! doesn't allocate:
+//!
+//! ```ignore
+//! gers_api::log_fmt!("{} took {} damage", name, amount);
+//! ```
+//!
+//! Only `{}` placeholders are supported, with `{{` and `}}` for braces.
+//! Strings are passed by reference, so they aren't copied either.
+use gers_events::LogArgType;
+use std::marker::PhantomData;
+
+/// Number of arguments a message can have. Arguments past it are left
+/// out, and their placeholders shown as `?`.
+pub const LOG_ARGS_CAPACITY: usize = 16;
+
+/// Largest argument, with its tag.
+const MAX_ARG_SIZE: usize = 9;
+
+/// Values the host can format.
+pub trait LogArg {
+    /// Type and little endian bytes of the value.
+    fn pack(&self) -> (LogArgType, [u8; 8]);
+}
+
+macro_rules! impl_log_arg {
+    ($($ty:ty => $as:ty, $arg_type:ident),* $(,)?) => {
+        $(
+            impl LogArg for $ty {
+                fn pack(&self) -> (LogArgType, [u8; 8]) {
+                    let mut bytes = [0; 8];
+                    let value = (*self as $as).to_le_bytes();
+                    bytes[..value.len()].copy_from_slice(&value);
+                    (LogArgType::$arg_type, bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_log_arg!(
+    i8 => i32, I32,
+    i16 => i32, I32,
+    i32 => i32, I32,
+    u8 => u32, U32,
+    u16 => u32, U32,
+    u32 => u32, U32,
+    i64 => i64, I64,
+    isize => i64, I64,
+    u64 => u64, U64,
+    usize => u64, U64,
+    f32 => f32, F32,
+    f64 => f64, F64,
+);
+
+impl LogArg for bool {
+    fn pack(&self) -> (LogArgType, [u8; 8]) {
+        (LogArgType::Bool, [*self as u8, 0, 0, 0, 0, 0, 0, 0])
+    }
+}
+
+impl LogArg for str {
+    fn pack(&self) -> (LogArgType, [u8; 8]) {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&(self.as_ptr() as usize as u32).to_le_bytes());
+        bytes[4..].copy_from_slice(&(self.len() as u32).to_le_bytes());
+        (LogArgType::Str, bytes)
+    }
+}
+
+impl LogArg for String {
+    fn pack(&self) -> (LogArgType, [u8; 8]) {
+        self.as_str().pack()
+    }
+}
+
+impl<T: LogArg + ?Sized> LogArg for &T {
+    fn pack(&self) -> (LogArgType, [u8; 8]) {
+        (**self).pack()
+    }
+}
+
+/// Packed arguments, which borrow the strings among them for `'a`.
+pub struct LogArgs<'a> {
+    bytes: [u8; LOG_ARGS_CAPACITY * MAX_ARG_SIZE],
+    len: usize,
+    strings: PhantomData<&'a str>,
+}
+
+impl<'a> LogArgs<'a> {
+    pub fn new() -> Self {
+        Self {
+            bytes: [0; LOG_ARGS_CAPACITY * MAX_ARG_SIZE],
+            len: 0,
+            strings: PhantomData,
+        }
+    }
+
+    /// Add an argument, unless there are `LOG_ARGS_CAPACITY` already.
+    pub fn with<T: LogArg + ?Sized>(mut self, value: &'a T) -> Self {
+        let (arg_type, value) = value.pack();
+        let size = arg_type.size();
+        if self.len + 1 + size <= self.bytes.len() {
+            self.bytes[self.len] = arg_type as u8;
+            self.bytes[self.len + 1..self.len + 1 + size].copy_from_slice(&value[..size]);
+            self.len += 1 + size;
+        }
+        self
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl Default for LogArgs<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Log a message formatted by the host, see `log_args`.
+#[macro_export]
+macro_rules! log_fmt {
+    ($template:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log_fmt(
+            $template,
+            &$crate::log_args::LogArgs::new()$(.with(&$arg))*,
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_args() {
+        let name = String::from("orc");
+        let args = LogArgs::new()
+            .with(&-2_i8)
+            .with(&true)
+            .with(&name)
+            .with(&0.5_f64);
+        let bytes = args.as_bytes();
+
+        assert_eq!(bytes[..5], [LogArgType::I32 as u8, 0xfe, 0xff, 0xff, 0xff]);
+        assert_eq!(bytes[5..7], [LogArgType::Bool as u8, 1]);
+        assert_eq!(bytes[7], LogArgType::Str as u8);
+        assert_eq!(bytes[8..12], (name.as_ptr() as usize as u32).to_le_bytes());
+        assert_eq!(bytes[12..16], 3_u32.to_le_bytes());
+        assert_eq!(bytes[16], LogArgType::F64 as u8);
+        assert_eq!(bytes[17..], 0.5_f64.to_le_bytes());
+
+        // Arguments past the capacity are left out.
+        let args = (0..LOG_ARGS_CAPACITY + 1).fold(LogArgs::new(), |args, _| args.with(&1_u64));
+        assert_eq!(args.as_bytes().len(), LOG_ARGS_CAPACITY * MAX_ARG_SIZE);
+    }
+}
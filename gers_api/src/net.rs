@@ -0,0 +1,47 @@
+//! TCP connections.
+//!
+//! Requires the `network` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+//!
+//! Connections are non-blocking. Calls that can't make progress yet,
+//! including any call before the connection is established, fail with
+//! `HostError::WouldBlock` and should be retried on a later frame.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Open TCP connection. Closed when dropped.
+pub struct TcpStream {
+    connection: u32,
+}
+
+impl TcpStream {
+    /// Start connecting to a `host:port` address.
+    pub fn connect(address: &str) -> Result<Self, HostError> {
+        HostError::from_code(sys::gers_net::tcp_connect(address))
+            .map(|connection| Self { connection })
+    }
+
+    /// Send as much of the data as the host accepts.
+    ///
+    /// Returns the number of bytes sent.
+    pub fn send(&self, data: &[u8]) -> Result<usize, HostError> {
+        HostError::from_code(sys::gers_net::tcp_send(self.connection, data))
+            .map(|sent| sent as usize)
+    }
+
+    /// Receive data into the buffer.
+    ///
+    /// Returns the number of bytes received, which is zero once
+    /// the remote end closed the connection.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize, HostError> {
+        HostError::from_code(sys::gers_net::tcp_recv(self.connection, buf))
+            .map(|received| received as usize)
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        sys::gers_net::tcp_close(self.connection);
+    }
+}
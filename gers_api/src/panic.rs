@@ -0,0 +1,25 @@
+//! Panic reporting.
+//!
+//! A panic in WebAssembly ends in an `unreachable` instruction, which
+//! the host only sees as an opaque trap. The hook forwards the panic
+//! message and location to the host before the trap happens, so it
+//! can be attached to the error report.
+use std::sync::Once;
+
+use crate::sys;
+
+/// Install the panic hook that reports to the host.
+///
+/// Safe to call multiple times. Only the first call installs the hook.
+pub fn set_panic_hook() {
+    static SET_HOOK: Once = Once::new();
+
+    SET_HOOK.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            // Display includes message and source location.
+            let message = info.to_string();
+
+            sys::gers::report_panic(&message);
+        }));
+    });
+}
@@ -0,0 +1,726 @@
+//! Exports the host calls into, generated by `gers_plugin!`.
+//!
+//! The host drives plugins through exports like `__gers_update` and
+//! `__gers_event_update`, which pass events as raw pointers into the
+//! plugin's memory. `gers_plugin!` generates those exports, owns the
+//! buffers the host writes events into, and decodes each event before
+//! dispatching it to the handler for its type, so handlers are safe
+//! functions taking the event by reference.
+//!
+//! ```ignore
+//! use gers_api::prelude::*;
+//!
+//! gers_plugin! {
+//!     on_update => update,
+//!     on_event(HelloEvent) => hello,
+//!     on_event(DamageEvent) => damage,
+//! }
+//!
+//! fn update() {}
+//!
+//! fn hello(event: &HelloEvent) {
+//!     log(&format!("received event: {:?}", event));
+//! }
+//!
+//! // Mutable events can be changed before they reach other plugins.
+//! fn damage(event: &mut DamageEvent) {
+//!     event.amount *= 0.5;
+//! }
+//! ```
+//!
+//! The hooks are
+//!
+//! - `on_update => fn()`, called every frame, returning nothing or an
+//!   `UpdateStatus`.
+//! - `on_resume => fn(u32) -> UpdateStatus`, resuming work that didn't
+//!   fit in a frame, with a budget in microseconds.
+//! - `on_render => fn(f32)`, called before a frame is drawn.
+//! - `on_save => fn()`, writing state with `save::write`.
+//! - `on_load => fn(&[u8])`, with the state saved earlier.
+//! - `on_shutdown => fn(u32)`, before the app quits, with a budget in
+//!   microseconds.
+//! - `on_event(T) => fn(&T)` or `fn(&mut T)`, for each event type the
+//!   plugin handles. Events without a handler are ignored.
+//! - `on_encoded_event(T) => fn(&T)`, for each `EncodedEvent` type the
+//!   plugin handles, deserialized rather than read in place.
+//! - `event_manifest => "..."`, the plugin's event manifest.
+//!
+//! Handlers may also return a `Result`, and an error is logged and
+//! reported to the host.
+//!
+//! The exports also report the protocol version the plugin was built
+//! against and the layouts of the event types it handles, so the host
+//! doesn't send events the plugin would misread, receive the host's
+//! capabilities, see `host`, and the values of the current frame, see
+//! `frame`.
+use gers_events::{
+    decode_event, EncodedEvent, EncodingError, Event, EventHeader, UpdateStatus, EVENT_HEADER_SIZE,
+};
+use std::{
+    fmt::{self, Debug},
+    mem,
+};
+
+use crate::event::event_records;
+
+/// Generate the exports of a plugin, dispatching to safe handlers.
+///
+/// See the `plugin` module for the hooks.
+#[macro_export]
+macro_rules! gers_plugin {
+    ($($hook:ident $(($event:ty))? => $handler:expr),* $(,)?) => {
+        $( $crate::__gers_hook!($hook $(($event))? => $handler); )*
+
+        #[no_mangle]
+        pub extern "C" fn __gers_abi_version() -> u32 {
+            $crate::plugin::ABI_VERSION
+        }
+
+        #[no_mangle]
+        /// # Safety
+        ///
+        /// The configuration pointer must be valid for `config_len`
+        /// bytes.
+        pub unsafe extern "C" fn __gers_init(
+            version: u32,
+            capabilities: u32,
+            config_ptr: *const u8,
+            config_len: u32,
+        ) {
+            $crate::set_panic_hook();
+            $crate::host::init(version, capabilities, config_ptr, config_len)
+        }
+
+        #[no_mangle]
+        pub extern "C" fn __gers_event_alloc(size: u32, align: u32) -> *mut u8 {
+            $crate::set_panic_hook();
+            $crate::plugin::event_alloc(size, align)
+        }
+
+        #[no_mangle]
+        pub extern "C" fn __gers_event_arena() -> u64 {
+            $crate::plugin::event_arena()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn __gers_frame_globals() -> *mut $crate::frame::FrameGlobals {
+            $crate::frame::frame_globals()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn __gers_event_layouts() -> u64 {
+            #[allow(unused_mut)]
+            let mut layouts = vec![];
+            $( $crate::__gers_event_layout!(layouts, $hook $(($event))? => $handler); )*
+            $crate::plugin::pack_layouts(layouts)
+        }
+
+        #[no_mangle]
+        /// # Safety
+        ///
+        /// The data pointer must point into the buffer allocated by
+        /// `__gers_event_alloc`.
+        pub unsafe extern "C" fn __gers_event_update(event_type: i32, data_ptr: *mut u8) -> i32 {
+            $crate::set_panic_hook();
+            __gers_dispatch(event_type, $crate::plugin::EventData::after_header(data_ptr))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn __gers_event_batch(count: u32) -> i32 {
+            $crate::set_panic_hook();
+            let mut result = $crate::plugin::EVENT_HANDLED;
+            // SAFETY: The host writes `count` records into the arena
+            //         from `__gers_event_arena` before calling.
+            for (event_type, data) in unsafe { $crate::plugin::event_batch(count) } {
+                if __gers_dispatch(event_type, data) != $crate::plugin::EVENT_HANDLED {
+                    result = $crate::plugin::EVENT_FAILED;
+                }
+            }
+            result
+        }
+
+        #[allow(unused_variables)]
+        fn __gers_dispatch(event_type: i32, data: $crate::plugin::EventData) -> i32 {
+            $( $crate::__gers_event_handler!(event_type, data, $hook $(($event))? => $handler); )*
+            $crate::plugin::EVENT_HANDLED
+        }
+    };
+}
+
+/// Export for one hook of `gers_plugin!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __gers_hook {
+    (on_update => $handler:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_update() -> i32 {
+            $crate::set_panic_hook();
+            $crate::plugin::UpdateResult::status($handler()) as i32
+        }
+    };
+    (on_resume => $handler:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_resume(budget_us: u32) -> i32 {
+            $crate::set_panic_hook();
+            $crate::plugin::UpdateResult::status($handler(budget_us)) as i32
+        }
+    };
+    (on_render => $handler:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_render(alpha: f32) {
+            $crate::set_panic_hook();
+            $handler(alpha)
+        }
+    };
+    (on_save => $handler:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_save() {
+            $crate::set_panic_hook();
+            $handler()
+        }
+    };
+    (on_load => $handler:expr) => {
+        #[no_mangle]
+        /// # Safety
+        ///
+        /// The pointer must be valid for `len` bytes.
+        pub unsafe extern "C" fn __gers_load(ptr: *const u8, len: u32) {
+            $crate::set_panic_hook();
+            $handler($crate::plugin::host_bytes(ptr, len))
+        }
+    };
+    (on_shutdown => $handler:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_shutdown(budget_us: u32) {
+            $crate::set_panic_hook();
+            $handler(budget_us)
+        }
+    };
+    (event_manifest => $manifest:expr) => {
+        #[no_mangle]
+        pub extern "C" fn __gers_event_manifest() -> u64 {
+            $crate::event::pack_manifest($manifest)
+        }
+    };
+    (on_event($event:ty) => $handler:expr) => {};
+    (on_encoded_event($event:ty) => $handler:expr) => {};
+    ($hook:ident $(($event:ty))? => $handler:expr) => {
+        compile_error!(concat!(
+            "unknown gers_plugin hook `",
+            stringify!($hook),
+            "`"
+        ));
+    };
+}
+
+/// Dispatch to one event handler of `gers_plugin!`, when the event is
+/// of its type.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __gers_event_handler {
+    ($event_type:ident, $data:ident, on_event($event:ty) => $handler:expr) => {
+        if $event_type == <$event as $crate::prelude::Event>::TYPE as i32 {
+            let mut data = $data;
+            return match data.decode::<$event>() {
+                Ok(event) => $crate::plugin::EventResult::code($handler(event)),
+                Err(err) => {
+                    $crate::log(&format!(
+                        concat!("malformed ", stringify!($event), ": {}"),
+                        err
+                    ));
+                    $crate::plugin::EVENT_FAILED
+                }
+            };
+        }
+    };
+    ($event_type:ident, $data:ident, on_encoded_event($event:ty) => $handler:expr) => {
+        if $event_type == <$event as $crate::prelude::EncodedEvent>::TYPE as i32
+            && $crate::event::header().is_encoded()
+        {
+            let mut data = $data;
+            return match data.deserialize::<$event>() {
+                Ok(mut event) => $crate::plugin::EventResult::code($handler(&mut event)),
+                Err(err) => {
+                    $crate::log(&format!(
+                        concat!("malformed ", stringify!($event), ": {}"),
+                        err
+                    ));
+                    $crate::plugin::EVENT_FAILED
+                }
+            };
+        }
+    };
+    ($event_type:ident, $data:ident, $hook:ident $(($event:ty))? => $handler:expr) => {};
+}
+
+/// Add the layout of the event type of one event handler of
+/// `gers_plugin!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __gers_event_layout {
+    ($layouts:ident, on_event($event:ty) => $handler:expr) => {
+        $layouts.push($crate::plugin::EventLayout::of::<$event>());
+    };
+    ($layouts:ident, $hook:ident $(($event:ty))? => $handler:expr) => {};
+}
+
+/// Protocol version the plugin is built against.
+#[doc(hidden)]
+pub use gers_events::ABI_VERSION;
+
+#[doc(hidden)]
+pub use gers_events::EventLayout;
+
+/// Returned to the host when the event was handled, or ignored.
+pub const EVENT_HANDLED: i32 = 0;
+
+/// Returned to the host when the event couldn't be decoded, or its
+/// handler failed.
+pub const EVENT_FAILED: i32 = 1;
+
+/// Size of the arena the host writes batches of events into.
+const EVENT_ARENA_SIZE: usize = 0x4000;
+
+#[repr(C, align(8))]
+struct EventArena([u8; EVENT_ARENA_SIZE]);
+
+// We're relying on the WebAssembly module itself not implementing
+// threading. All guest code invoked by the host is single threaded,
+// so the buffers don't need synchronising.
+
+/// Buffer the host writes single events into, kept as words so events
+/// are aligned.
+static mut EVENT_BUFFER: Vec<u64> = Vec::new();
+
+/// Persistent buffer for batches of events, written by the host
+/// between calls to `__gers_event_batch`.
+static mut EVENT_ARENA: EventArena = EventArena([0; EVENT_ARENA_SIZE]);
+
+/// Layouts of the event types the plugin handles, kept for the host
+/// to read.
+static mut EVENT_LAYOUTS: Vec<EventLayout> = Vec::new();
+
+/// Keep the layouts of the event types the plugin handles, returning
+/// the pointer to them in the low and their number in the high 32
+/// bits, for `__gers_event_layouts`.
+#[doc(hidden)]
+pub fn pack_layouts(layouts: Vec<EventLayout>) -> u64 {
+    // SAFETY: Single threaded, see above.
+    unsafe {
+        let kept = &mut *std::ptr::addr_of_mut!(EVENT_LAYOUTS);
+        *kept = layouts;
+        kept.as_ptr() as usize as u64 | (kept.len() as u64) << 32
+    }
+}
+
+/// Resize the event buffer to hold `size` bytes, returning a pointer to
+/// it, which is valid until the buffer is resized again.
+///
+/// Returns null when `align` isn't a power of two the buffer's words
+/// are aligned to.
+#[doc(hidden)]
+pub fn event_alloc(size: u32, align: u32) -> *mut u8 {
+    let align = align as usize;
+    if !align.is_power_of_two() || align > mem::align_of::<u64>() {
+        return std::ptr::null_mut();
+    }
+    let words = (size as usize).div_ceil(mem::size_of::<u64>());
+
+    // SAFETY: Single threaded, see above.
+    unsafe {
+        let buffer = &mut *std::ptr::addr_of_mut!(EVENT_BUFFER);
+        buffer.resize(words, 0);
+        buffer.as_mut_ptr() as *mut u8
+    }
+}
+
+/// The event arena, with the pointer in the low and the length in the
+/// high 32 bits.
+#[doc(hidden)]
+pub fn event_arena() -> u64 {
+    let ptr = std::ptr::addr_of!(EVENT_ARENA) as usize as u64;
+    ptr | (EVENT_ARENA_SIZE as u64) << 32
+}
+
+/// Events of the batch in the event arena.
+///
+/// # Safety
+///
+/// The host must have written `count` records into the arena.
+#[doc(hidden)]
+pub unsafe fn event_batch(count: u32) -> impl Iterator<Item = (i32, EventData)> {
+    let arena = std::ptr::addr_of_mut!(EVENT_ARENA) as *mut u8;
+    let end = arena.add(EVENT_ARENA_SIZE);
+
+    event_records(arena, EVENT_ARENA_SIZE, count).map(move |(header, data_ptr)| {
+        // Each record is handled before the next is read.
+        crate::event::set_header(header);
+        let event_type = header.event_type;
+        let data = EventData {
+            ptr: data_ptr as *mut u8,
+            end,
+        };
+        (event_type, data)
+    })
+}
+
+/// Borrow bytes the host passed as a pointer and length.
+///
+/// # Safety
+///
+/// The pointer must be valid for `len` bytes until the call the bytes
+/// were passed to returns.
+#[doc(hidden)]
+pub unsafe fn host_bytes<'a>(ptr: *const u8, len: u32) -> &'a [u8] {
+    if ptr.is_null() || len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(ptr, len as usize)
+}
+
+/// Event data the host wrote into one of the plugin's buffers.
+#[doc(hidden)]
+pub struct EventData {
+    ptr: *mut u8,
+    /// End of the buffer holding the event.
+    end: *mut u8,
+}
+
+impl EventData {
+    /// Event at the pointer, in the event buffer.
+    ///
+    /// # Safety
+    ///
+    /// The event buffer must not be resized while the event is used.
+    pub unsafe fn from_buffer(ptr: *mut u8) -> Self {
+        let buffer = &mut *std::ptr::addr_of_mut!(EVENT_BUFFER);
+        let start = buffer.as_mut_ptr() as *mut u8;
+        let end = start.add(buffer.len() * mem::size_of::<u64>());
+
+        // Pointers outside of the buffer are rejected by `get`.
+        let ptr = if ptr < start || ptr > end { end } else { ptr };
+        Self { ptr, end }
+    }
+
+    /// Event following the header at the pointer, in the event buffer,
+    /// making it the header of the event being handled.
+    ///
+    /// # Safety
+    ///
+    /// The event buffer must not be resized while the event is used.
+    pub unsafe fn after_header(ptr: *mut u8) -> Self {
+        let header = Self::from_buffer(ptr);
+        let available = header.end as usize - header.ptr as usize;
+        if available < EVENT_HEADER_SIZE {
+            crate::event::set_header(EventHeader::default());
+            return header;
+        }
+
+        let bytes = std::slice::from_raw_parts(header.ptr, EVENT_HEADER_SIZE);
+        crate::event::set_header(EventHeader::decode(bytes).unwrap_or_default());
+        Self::from_buffer(ptr.add(EVENT_HEADER_SIZE))
+    }
+
+    /// The event as its type, unless it doesn't fit in the buffer or
+    /// isn't aligned.
+    pub fn decode<T: Event>(&mut self) -> Result<&mut T, DecodeError> {
+        let available = self.end as usize - self.ptr as usize;
+        if available < mem::size_of::<T>() {
+            return Err(DecodeError::TooShort {
+                expected: mem::size_of::<T>(),
+                available,
+            });
+        }
+        if self.ptr.align_offset(mem::align_of::<T>()) != 0 {
+            let address = self.ptr as usize;
+            return Err(DecodeError::Misaligned {
+                expected: mem::align_of::<T>(),
+                // Largest power of two dividing the address.
+                actual: address & address.wrapping_neg(),
+            });
+        }
+
+        // SAFETY: The host wrote a `T` at the pointer, which is in
+        //         bounds and aligned.
+        Ok(unsafe { &mut *(self.ptr as *mut T) })
+    }
+
+    /// The event deserialized from the data the host encoded, unless
+    /// the header doesn't say it's encoded, or the data doesn't fit in
+    /// the buffer.
+    pub fn deserialize<T: EncodedEvent>(&mut self) -> Result<T, DecodeError> {
+        let header = crate::event::header();
+        if !header.is_encoded() {
+            return Err(DecodeError::NotEncoded);
+        }
+        let available = self.end as usize - self.ptr as usize;
+        let size = header.size as usize;
+        if available < size {
+            return Err(DecodeError::TooShort {
+                expected: size,
+                available,
+            });
+        }
+
+        // SAFETY: The host wrote `size` bytes at the pointer, which are
+        //         in bounds.
+        let bytes = unsafe { std::slice::from_raw_parts(self.ptr, size) };
+        decode_event(bytes).map_err(DecodeError::Encoding)
+    }
+}
+
+/// Why event data couldn't be read as its type.
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Fewer bytes are left in the buffer than the size of the event.
+    TooShort {
+        expected: usize,
+        available: usize,
+    },
+    /// The event's address isn't aligned for its type, with the
+    /// alignment it needs, and the alignment of the address.
+    Misaligned {
+        expected: usize,
+        actual: usize,
+    },
+    /// The host sent the event's data as a `#[repr(C)]` struct.
+    NotEncoded,
+    Encoding(EncodingError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooShort {
+                expected,
+                available,
+            } => write!(f, "expected {} bytes, {} available", expected, available),
+            DecodeError::Misaligned { expected, actual } => write!(
+                f,
+                "expected alignment of {} bytes, address is aligned to {}",
+                expected, actual
+            ),
+            DecodeError::NotEncoded => write!(f, "event isn't encoded"),
+            DecodeError::Encoding(err) => write!(f, "failed to deserialize: {}", err),
+        }
+    }
+}
+
+/// Return values of update handlers.
+pub trait UpdateResult {
+    fn status(self) -> UpdateStatus;
+}
+
+impl UpdateResult for () {
+    fn status(self) -> UpdateStatus {
+        UpdateStatus::Done
+    }
+}
+
+impl UpdateResult for UpdateStatus {
+    fn status(self) -> UpdateStatus {
+        self
+    }
+}
+
+/// Return values of event handlers.
+pub trait EventResult {
+    /// Code returned to the host.
+    fn code(self) -> i32;
+}
+
+impl EventResult for () {
+    fn code(self) -> i32 {
+        EVENT_HANDLED
+    }
+}
+
+impl<E: Debug> EventResult for Result<(), E> {
+    fn code(self) -> i32 {
+        match self {
+            Ok(()) => EVENT_HANDLED,
+            Err(err) => {
+                crate::log(&format!("event handler failed: {:?}", err));
+                EVENT_FAILED
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gers_events::{
+        encode_event, plugin_id, DamageEvent, EventStamp, EventType, HelloEvent,
+        EVENT_FLAG_ENCODED, EVENT_FLAG_MUTABLE,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct ChatMessage {
+        sender: String,
+        lines: Vec<String>,
+    }
+
+    impl EncodedEvent for ChatMessage {
+        const TYPE: EventType = EventType::Custom;
+    }
+
+    #[test]
+    fn test_event_data_bounds() {
+        let ptr = event_alloc(std::mem::size_of::<DamageEvent>() as u32, 8);
+        let mut data = unsafe { EventData::from_buffer(ptr) };
+        assert!(data.decode::<DamageEvent>().is_ok());
+
+        // Past the end of the buffer.
+        let mut data = unsafe { EventData::from_buffer(ptr.add(8)) };
+        assert_eq!(
+            data.decode::<DamageEvent>().unwrap_err(),
+            DecodeError::TooShort {
+                expected: 16,
+                available: 8
+            }
+        );
+
+        // Misaligned.
+        let ptr = event_alloc(64, 8);
+        let mut data = unsafe { EventData::from_buffer(ptr.add(1)) };
+        let err = data.decode::<HelloEvent>().unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::Misaligned {
+                expected: 4,
+                actual: 1
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "expected alignment of 4 bytes, address is aligned to 1"
+        );
+    }
+
+    #[test]
+    fn test_event_alloc_alignment() {
+        assert!(!event_alloc(64, 8).is_null());
+        assert_eq!(event_alloc(64, 4) as usize % 4, 0);
+        assert!(event_alloc(64, 16).is_null());
+        assert!(event_alloc(64, 3).is_null());
+    }
+
+    #[test]
+    fn test_batch_headers() {
+        // Two records without data, like the host writes them.
+        let arena = std::ptr::addr_of_mut!(EVENT_ARENA) as *mut u8;
+        for (index, tick_index) in [(0, 4), (1, 5)] {
+            let header = EventHeader {
+                event_type: 1,
+                size: 0,
+                stamp: EventStamp {
+                    frame_index: 2,
+                    tick_index,
+                },
+                ..EventHeader::default()
+            };
+            let bytes = header.encode();
+            unsafe {
+                let record = arena.add(index * EVENT_HEADER_SIZE);
+                record.copy_from_nonoverlapping(bytes.as_ptr(), EVENT_HEADER_SIZE);
+            }
+        }
+
+        let mut ticks = vec![];
+        for (event_type, _) in unsafe { event_batch(2) } {
+            assert_eq!(event_type, 1);
+            ticks.push(crate::event::stamp().tick_index);
+        }
+        assert_eq!(ticks, [4, 5]);
+    }
+
+    #[test]
+    fn test_batch_records_stay_in_arena() {
+        let mut arena = [0u64; 16];
+        let ptr = arena.as_mut_ptr() as *mut u8;
+        let len = mem::size_of_val(&arena);
+        let header = EventHeader {
+            event_type: 1,
+            size: u32::MAX,
+            ..EventHeader::default()
+        };
+        unsafe { ptr.copy_from_nonoverlapping(header.encode().as_ptr(), EVENT_HEADER_SIZE) };
+
+        // The data runs to the end of the arena, and there's no room
+        // for the other records.
+        let records: Vec<_> = unsafe { event_records(ptr, len, 3) }.collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1, unsafe { ptr.add(EVENT_HEADER_SIZE) }
+            as *const u8);
+
+        // Nor for a header.
+        let records = unsafe { event_records(ptr, EVENT_HEADER_SIZE - 1, 1) };
+        assert_eq!(records.count(), 0);
+    }
+
+    #[test]
+    fn test_event_after_header() {
+        let ptr = event_alloc(256, 8);
+        let header = EventHeader {
+            event_type: 7,
+            size: mem::size_of::<DamageEvent>() as u32,
+            flags: EVENT_FLAG_MUTABLE,
+            source: plugin_id("emitter"),
+            ..EventHeader::default()
+        };
+        let damage = DamageEvent {
+            entity: 3,
+            source: 0,
+            amount: 2.0,
+            cancelled: 0,
+        };
+        unsafe {
+            ptr.copy_from_nonoverlapping(header.encode().as_ptr(), EVENT_HEADER_SIZE);
+            (ptr.add(EVENT_HEADER_SIZE) as *mut DamageEvent).write(damage);
+        }
+
+        let mut data = unsafe { EventData::after_header(ptr) };
+        assert_eq!(data.decode::<DamageEvent>().unwrap().entity, 3);
+        let handled = crate::event::header();
+        assert!(handled.is_mutable());
+        assert_eq!(handled.source, plugin_id("emitter"));
+    }
+
+    #[test]
+    fn test_deserialize_encoded_event() {
+        let message = ChatMessage {
+            sender: "host".to_string(),
+            lines: vec!["hello".to_string(), "there".to_string()],
+        };
+        let bytes = encode_event(&message).unwrap();
+        let header = EventHeader {
+            event_type: EventType::Custom as i32,
+            size: bytes.len() as u32,
+            flags: EVENT_FLAG_ENCODED,
+            ..EventHeader::default()
+        };
+
+        let ptr = event_alloc(256, 8);
+        unsafe {
+            ptr.copy_from_nonoverlapping(header.encode().as_ptr(), EVENT_HEADER_SIZE);
+            ptr.add(EVENT_HEADER_SIZE)
+                .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        }
+        let mut data = unsafe { EventData::after_header(ptr) };
+        assert_eq!(data.deserialize::<ChatMessage>().unwrap(), message);
+
+        // Sent as a struct.
+        let header = EventHeader { flags: 0, ..header };
+        unsafe {
+            ptr.copy_from_nonoverlapping(header.encode().as_ptr(), EVENT_HEADER_SIZE);
+        }
+        let mut data = unsafe { EventData::after_header(ptr) };
+        assert_eq!(
+            data.deserialize::<ChatMessage>().unwrap_err(),
+            DecodeError::NotEncoded
+        );
+    }
+}
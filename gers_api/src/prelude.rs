@@ -0,0 +1,14 @@
+//! Common imports for writing plugins.
+//!
+//! ```ignore
+//! use gers_api::prelude::*;
+//! ```
+pub use crate::gers_plugin;
+pub use crate::{delta_time, log, HostError, UpdateStatus};
+pub use gers_events::{
+    ActionEvent, AppPausedEvent, AppResumedEvent, Capabilities, ConsoleCommandEvent, CustomEvent,
+    DamageEvent, EncodedEvent, Event, EventStamp, EventType, GamepadAxisEvent, GamepadButtonEvent,
+    HelloEvent, HttpResponseEvent, LocaleChangedEvent, MouseMoveEvent, ScreenshotTakenEvent,
+    SettingsChangedEvent, ShutdownRequestedEvent, SimulationLaggingEvent, SimulationStateEvent,
+    TaskCompletedEvent, TimerFiredEvent, WindowEvent,
+};
@@ -0,0 +1,18 @@
+//! Savegames.
+//!
+//! When the game is saved, the host calls the plugin's exported
+//! `__gers_save`, during which the plugin writes its state with
+//! [`write`]. When the game is loaded, the host calls the exported
+//! `__gers_load(ptr, len)` with the same bytes, which the plugin reads
+//! with `gers_api::recv_str` or as a raw slice. The host skips loading
+//! state saved by a different version of the plugin.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Append bytes to this plugin's saved state.
+///
+/// Only has effect while the host is calling `__gers_save`.
+pub fn write(data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_save::write(data)).map(|_| ())
+}
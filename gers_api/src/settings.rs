@@ -0,0 +1,59 @@
+//! Settings the user can change, declared in `plugin.toml`.
+//!
+//! ```toml
+//! [settings]
+//! difficulty = { type = "int", default = 2, min = 0, max = 5 }
+//! ```
+//!
+//! Settings the plugin didn't declare fail with `HostError::NotFound`,
+//! and reading a setting as another type with
+//! `HostError::InvalidArgument`. The plugin receives a
+//! `SettingsChangedEvent` when the user changes a setting.
+use gers_events::{HostError, SettingKind};
+
+use crate::sys;
+
+/// Type of a declared setting.
+pub fn kind(key: &str) -> Result<SettingKind, HostError> {
+    HostError::from_code(sys::gers_settings::kind(key)).map(|kind| SettingKind::from(kind as i32))
+}
+
+/// Fail unless the setting is declared with the type.
+fn expect_kind(key: &str, expected: SettingKind) -> Result<(), HostError> {
+    if kind(key)? == expected {
+        Ok(())
+    } else {
+        Err(HostError::InvalidArgument)
+    }
+}
+
+pub fn get_int(key: &str) -> Result<i64, HostError> {
+    expect_kind(key, SettingKind::Int)?;
+    Ok(sys::gers_settings::get_int(key))
+}
+
+pub fn get_float(key: &str) -> Result<f64, HostError> {
+    expect_kind(key, SettingKind::Float)?;
+    Ok(sys::gers_settings::get_float(key))
+}
+
+pub fn get_bool(key: &str) -> Result<bool, HostError> {
+    HostError::from_code(sys::gers_settings::get_bool(key)).map(|value| value != 0)
+}
+
+pub fn get_string(key: &str) -> Result<String, HostError> {
+    let mut buf = vec![0; 64];
+
+    loop {
+        let code = sys::gers_settings::get_string(key, &mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return String::from_utf8(buf).map_err(|_| HostError::InvalidArgument);
+        }
+
+        // Value didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
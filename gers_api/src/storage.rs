@@ -0,0 +1,32 @@
+//! Persistent key-value storage, in the plugin's data directory.
+//!
+//! Requires the `storage` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+//!
+//! Keys may contain ASCII letters, digits, `-`, `_` and `.`.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Store a value, replacing any previous value of the key.
+pub fn save(key: &str, data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_storage::save_value(key, data)).map(|_| ())
+}
+
+/// Load the value of a key.
+pub fn load(key: &str) -> Result<Vec<u8>, HostError> {
+    let mut buf = vec![0; 256];
+
+    loop {
+        let code = sys::gers_storage::load_value(key, &mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return Ok(buf);
+        }
+
+        // Value didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
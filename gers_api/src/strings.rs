@@ -0,0 +1,48 @@
+//! Receiving strings from the host.
+//!
+//! The host allocates string buffers in guest memory through the
+//! exported `__gers_alloc`, and releases them with `__gers_free`.
+use std::alloc::{self, Layout};
+
+/// Allocate a buffer the host can write into.
+///
+/// Returns a null pointer on failure.
+#[no_mangle]
+pub extern "C" fn __gers_alloc(size: u32) -> *mut u8 {
+    match Layout::from_size_align(size.max(1) as usize, 1) {
+        // SAFETY: Layout size is never zero.
+        Ok(layout) => unsafe { alloc::alloc(layout) },
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Release a buffer allocated with `__gers_alloc`.
+///
+/// # Safety
+///
+/// The pointer must come from `__gers_alloc` called with the same size,
+/// and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn __gers_free(ptr: *mut u8, size: u32) {
+    if ptr.is_null() {
+        return;
+    }
+    let layout = Layout::from_size_align_unchecked(size.max(1) as usize, 1);
+    alloc::dealloc(ptr, layout);
+}
+
+/// Borrow a string the host passed as a pointer and length.
+///
+/// Fails when the bytes aren't valid UTF-8.
+///
+/// # Safety
+///
+/// The pointer must be valid for `len` bytes, and the buffer must
+/// outlive the returned string. Host strings are valid until the
+/// call they were passed to returns.
+pub unsafe fn recv_str<'a>(ptr: *const u8, len: u32) -> Result<&'a str, std::str::Utf8Error> {
+    if len == 0 {
+        return Ok("");
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(ptr, len as usize))
+}
@@ -0,0 +1,6 @@
+//! Host imports, generated from the interface in `gers_interface`.
+//!
+//! Each namespace is a module, like `sys::gers_audio`, with safe
+//! wrappers taking slices where the import takes a pointer and length.
+//! The host doesn't keep the pointers once the call returns.
+include!(concat!(env!("OUT_DIR"), "/sys.rs"));
@@ -0,0 +1,74 @@
+//! Background tasks.
+//!
+//! Heavy operations the host implements run on its worker threads
+//! rather than in the plugin's update. Once a task is done, a
+//! `TaskCompletedEvent` is delivered to the plugin that spawned it, and
+//! the result can only be read with `result` while that event is
+//! handled.
+use gers_events::{HostError, TaskKind};
+
+use crate::sys;
+
+/// Task id handed out by the host.
+pub type TaskId = u32;
+
+/// Start a task of the kind, with its data.
+pub fn spawn(kind: TaskKind, data: &[u8]) -> Result<TaskId, HostError> {
+    HostError::from_code(sys::gers_task::run(kind as u32, data))
+}
+
+/// Hash a file in the plugin with SHA-256, given its path relative to
+/// the plugin's root.
+pub fn hash_file(path: &str) -> Result<TaskId, HostError> {
+    spawn(TaskKind::HashFile, path.as_bytes())
+}
+
+/// Compute the steps from every cell of a `width` by `height` grid to
+/// the goal cell. `walls` has a value per cell, row by row, which is
+/// `true` for cells that can't be walked.
+///
+/// See `distance_field_result` to read the result.
+pub fn distance_field(
+    width: u32,
+    height: u32,
+    goal: (u32, u32),
+    walls: &[bool],
+) -> Result<TaskId, HostError> {
+    let mut data = Vec::with_capacity(16 + walls.len());
+    for value in [width, height, goal.0, goal.1] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend(walls.iter().map(|wall| *wall as u8));
+
+    spawn(TaskKind::DistanceField, &data)
+}
+
+/// Copy the result of the task that is being handled.
+pub fn result(task: TaskId) -> Result<Vec<u8>, HostError> {
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let code = sys::gers_task::result(task, &mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return Ok(buf);
+        }
+
+        // Result didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+/// Steps to the goal from each cell of a distance field task, `None`
+/// for cells that can't reach it.
+pub fn distance_field_result(task: TaskId) -> Result<Vec<Option<u32>>, HostError> {
+    let steps = result(task)?
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .map(|steps| if steps == u32::MAX { None } else { Some(steps) })
+        .collect();
+
+    Ok(steps)
+}
@@ -0,0 +1,42 @@
+//! Timers and the time scale.
+//!
+//! When a timer is due, the host sends a `TimerFiredEvent` to the
+//! plugin that set it. Timers count down with the frame's scaled delta
+//! time, so they fire at most once per frame, and not while time is
+//! frozen.
+use gers_events::HostError;
+use std::time::Duration;
+
+use crate::sys;
+
+/// Handle of a timer, handed out by the host.
+pub type TimerId = u64;
+
+/// Set a timer that fires once after the interval.
+pub fn set_timer(interval: Duration) -> Result<TimerId, HostError> {
+    set(interval, false)
+}
+
+/// Set a timer that keeps firing every interval until it's cancelled.
+pub fn set_interval(interval: Duration) -> Result<TimerId, HostError> {
+    set(interval, true)
+}
+
+fn set(interval: Duration, repeat: bool) -> Result<TimerId, HostError> {
+    let millis = interval.as_millis().min(u32::MAX as u128) as u32;
+
+    HostError::from_handle_code(sys::gers_time::set_timer(millis, repeat as u32))
+}
+
+/// Set the multiplier for the delta time of all plugins, starting with
+/// the next frame. Zero freezes time.
+///
+/// Requires the `time` permission.
+pub fn set_time_scale(time_scale: f32) -> Result<(), HostError> {
+    HostError::from_code(sys::gers::set_time_scale(time_scale)).map(|_| ())
+}
+
+/// Returns `true` if the timer was cancelled before it fired.
+pub fn cancel_timer(timer: TimerId) -> bool {
+    sys::gers_time::cancel_timer(timer) != 0
+}
@@ -0,0 +1,58 @@
+//! Extra windows.
+//!
+//! Requires the `window` permission in `plugin.toml`, otherwise
+//! every call fails with `HostError::PermissionDenied`.
+//!
+//! Windows are opened at the end of the frame, but their handle can
+//! be used straight away. Events of the window are received as
+//! `EventType::Window`. Windows stay open when the user asks to close
+//! them, so the plugin should close them on `CloseRequested`.
+//!
+//! The main window is `MAIN_WINDOW`, which can be changed by any
+//! plugin with the permission, but not closed. Screenshots of it are
+//! received as `EventType::ScreenshotTaken` by every plugin, see
+//! `ScreenshotTakenEvent`.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Handle of the main window.
+pub const MAIN_WINDOW: u32 = 0;
+
+/// Open a window with an inner size in logical pixels.
+///
+/// Returns the window handle.
+pub fn create(title: &str, width: u32, height: u32) -> Result<u32, HostError> {
+    HostError::from_code(sys::gers_window::create(title, width, height))
+}
+
+pub fn close(window: u32) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::close(window)).map(|_| ())
+}
+
+pub fn set_title(window: u32, title: &str) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::set_title(window, title)).map(|_| ())
+}
+
+/// Resize the inner size of a window, in logical pixels.
+pub fn set_size(window: u32, width: u32, height: u32) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::set_size(window, width, height)).map(|_| ())
+}
+
+/// Switch a window between borderless fullscreen and windowed.
+pub fn set_fullscreen(window: u32, fullscreen: bool) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::set_fullscreen(window, fullscreen as u32)).map(|_| ())
+}
+
+/// Show or hide the cursor while it's over a window.
+pub fn set_cursor_visible(window: u32, visible: bool) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::set_cursor_visible(window, visible as u32)).map(|_| ())
+}
+
+/// Take a screenshot of the main window when it's next rendered.
+///
+/// It's saved as a PNG in the screenshots directory. Nothing is taken
+/// when the game runs headless, without a window to render to.
+pub fn screenshot() -> Result<(), HostError> {
+    HostError::from_code(sys::gers_window::screenshot(MAIN_WINDOW)).map(|_| ())
+}
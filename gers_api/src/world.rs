@@ -0,0 +1,156 @@
+//! Entities and components shared with the host and other plugins.
+//!
+//! Components are stored as bytes. Plugins that share a component
+//! id have to agree on the layout of its data.
+//!
+//! Queries can be stepped one entity at a time, or read in bulk with
+//! `Query::next_rows`, which copies as many rows as fit into a buffer
+//! in a single call.
+use gers_events::HostError;
+
+use crate::sys;
+
+/// Entity id handed out by the host.
+pub type Entity = u32;
+
+/// Component id, in the range `0..64`.
+pub type ComponentId = u32;
+
+/// Create an entity without components.
+///
+/// Returns `None` when the host couldn't create it.
+pub fn spawn() -> Option<Entity> {
+    match sys::gers_world::spawn() {
+        0 => None,
+        entity => Some(entity),
+    }
+}
+
+/// Remove an entity and all of its components.
+pub fn despawn(entity: Entity) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_world::despawn(entity)).map(|_| ())
+}
+
+/// Attach component data to an entity, replacing any previous data.
+pub fn set_component(entity: Entity, component: ComponentId, data: &[u8]) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_world::set_component(entity, component, data)).map(|_| ())
+}
+
+/// Copy the data of an entity's component.
+pub fn get_component(entity: Entity, component: ComponentId) -> Result<Vec<u8>, HostError> {
+    let mut buf = vec![0; 64];
+
+    loop {
+        let code = sys::gers_world::get_component(entity, component, &mut buf);
+        let size = HostError::from_code(code)? as usize;
+
+        if size <= buf.len() {
+            buf.truncate(size);
+            return Ok(buf);
+        }
+
+        // Data didn't fit, try again with the full size.
+        buf.resize(size, 0);
+    }
+}
+
+pub fn remove_component(entity: Entity, component: ComponentId) -> Result<(), HostError> {
+    HostError::from_code(sys::gers_world::remove_component(entity, component)).map(|_| ())
+}
+
+/// Iterate the entities that have all the given components.
+///
+/// Component ids out of range match nothing.
+pub fn query(components: &[ComponentId]) -> Query {
+    let mask = components.iter().fold(0u64, |mask, component| {
+        mask | 1u64.checked_shl(*component).unwrap_or(u64::MAX)
+    });
+
+    Query {
+        token: sys::gers_world::query(mask),
+    }
+}
+
+/// Entities matched by `query`.
+///
+/// The matches are collected when the query starts. The host
+/// releases the query when it's dropped.
+pub struct Query {
+    token: u32,
+}
+
+impl Query {
+    /// Copy rows for the next entities into the buffer.
+    ///
+    /// Each row holds the entity and the first `data_size` bytes of its
+    /// component, padded with zeroes. Entities already taken by `next`
+    /// are skipped. The returned rows are empty once the query is
+    /// exhausted. Fails when the buffer can't hold a single row.
+    pub fn next_rows<'a>(
+        &mut self,
+        component: ComponentId,
+        data_size: usize,
+        buf: &'a mut [u8],
+    ) -> Result<Rows<'a>, HostError> {
+        let row_size = ROW_HEADER_SIZE + data_size;
+        let count = if self.token == 0 {
+            0
+        } else {
+            let code = sys::gers_world::query_fill(self.token, component, data_size as u32, buf);
+            HostError::from_code(code)? as usize
+        };
+
+        Ok(Rows {
+            chunks: buf[..count * row_size].chunks_exact(row_size),
+        })
+    }
+}
+
+/// Size of the entity id at the start of a row.
+const ROW_HEADER_SIZE: usize = std::mem::size_of::<Entity>();
+
+/// Rows copied by `Query::next_rows`.
+pub struct Rows<'a> {
+    chunks: std::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = (Entity, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.chunks.next()?;
+        let (entity, data) = row.split_at(ROW_HEADER_SIZE);
+        let entity = Entity::from_le_bytes([entity[0], entity[1], entity[2], entity[3]]);
+
+        Some((entity, data))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Rows<'a> {}
+
+impl Iterator for Query {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        if self.token == 0 {
+            return None;
+        }
+
+        match sys::gers_world::query_next(self.token) {
+            0 => None,
+            entity => Some(entity),
+        }
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        if self.token != 0 {
+            sys::gers_world::query_end(self.token);
+        }
+    }
+}
@@ -1,4 +0,0 @@
-
-pub struct GersApp {
-    
-}
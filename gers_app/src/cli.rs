@@ -0,0 +1,172 @@
+//! Command line arguments.
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use gers_engine::CONFIG_FILENAME;
+
+/// Scriptable game engine, extended with WebAssembly plugins.
+#[derive(Debug, Parser)]
+#[clap(name = "gers", version)]
+pub struct Cli {
+    /// Engine config file.
+    #[clap(long, value_name = "FILE", default_value = CONFIG_FILENAME)]
+    pub config: PathBuf,
+
+    /// Directory to search for plugins, replacing the configured
+    /// search paths. May be repeated.
+    #[clap(long = "plugin-dir", value_name = "DIR")]
+    pub plugin_dirs: Vec<PathBuf>,
+
+    /// Skip loading the plugin with this name. May be repeated.
+    #[clap(long = "disable-plugin", value_name = "NAME")]
+    pub disabled_plugins: Vec<String>,
+
+    /// One of critical, error, warning, info, debug or trace.
+    #[clap(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Locale of the plugins' strings, like en or pt-BR.
+    #[clap(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
+    /// Run the simulation without rendering, as fast as frames run.
+    #[clap(long)]
+    pub headless: bool,
+
+    /// Frame rate limit.
+    #[clap(long, value_name = "FPS")]
+    pub max_fps: Option<u64>,
+
+    /// Wait for vertical sync when presenting frames.
+    #[clap(long)]
+    pub vsync: bool,
+
+    /// Seed provided to plugins, for reproducing a run.
+    /// Generated from the clock when not given.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Development mode, which skips plugin integrity checks.
+    #[clap(long)]
+    pub dev: bool,
+
+    /// Periodically log the plugin timing table.
+    #[clap(long)]
+    pub profile: bool,
+
+    /// Keep snapshots of plugin memory for the last few frames,
+    /// so the simulation can be rewound with F6.
+    #[clap(long)]
+    pub rewind: bool,
+
+    /// Record the events sent to plugins into this file.
+    #[clap(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Play back a recording made with `--record`, in place of
+    /// live input and frame timing.
+    #[clap(long, value_name = "FILE", conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+
+    /// Write a trace of plugin calls into this file, in the
+    /// format of chrome://tracing.
+    #[clap(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools run instead of the engine.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Scaffold a plugin, with a Cargo project building its module.
+    NewPlugin {
+        /// Name of the plugin, and its crate.
+        name: String,
+
+        /// Directory to create the plugin in. Defaults to the name.
+        #[clap(long, value_name = "DIR")]
+        path: Option<PathBuf>,
+
+        /// Local gers_api crate to depend on, instead of the
+        /// repository.
+        #[clap(long, value_name = "DIR")]
+        api: Option<PathBuf>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("gers").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_defaults() {
+        let cli = parse(&[]);
+
+        assert_eq!(cli.config, PathBuf::from(CONFIG_FILENAME));
+        assert!(cli.plugin_dirs.is_empty());
+        assert!(cli.disabled_plugins.is_empty());
+        assert!(!cli.headless);
+        assert!(cli.seed.is_none());
+        assert!(cli.trace.is_none());
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_new_plugin_command() {
+        let cli = parse(&["new-plugin", "my-plugin", "--path", "mods/mine"]);
+
+        match cli.command {
+            Some(Command::NewPlugin { name, path, api }) => {
+                assert_eq!(name, "my-plugin");
+                assert_eq!(path, Some(PathBuf::from("mods/mine")));
+                assert!(api.is_none());
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_repeated_arguments() {
+        let cli = parse(&[
+            "--plugin-dir",
+            "a",
+            "--plugin-dir",
+            "b",
+            "--disable-plugin",
+            "foo",
+            "--disable-plugin",
+            "bar",
+        ]);
+
+        assert_eq!(
+            cli.plugin_dirs,
+            vec![PathBuf::from("a"), PathBuf::from("b")]
+        );
+        assert_eq!(cli.disabled_plugins, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        for args in [
+            &["--max-fps", "fast"][..],
+            &["--seed", "-1"],
+            &["--unknown"],
+        ] {
+            let args = std::iter::once(&"gers").chain(args);
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Scaffolding new plugins, for `gers new-plugin`.
+//!
+//! The plugin directory holds the `plugin.toml` the loader reads, and
+//! a Cargo project building the plugin's module for
+//! `wasm32-unknown-unknown` against `gers_api`. The example handlers
+//! use `gers_plugin!`, so the project builds and runs as it is.
+use anyhow::{anyhow, Context};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Where `gers_api` is taken from when no local checkout is given.
+const GERS_REPOSITORY: &str = "https://github.com/vangroan/gers-platform";
+
+const LIB_RS: &str = r#"use gers_api::prelude::*;
+
+gers_plugin! {
+    on_update => update,
+    on_event(ActionEvent) => action,
+}
+
+fn update() {
+    gers_api::draw::rect(16.0, 16.0, 32.0, 32.0, 0x3070F0FF);
+}
+
+fn action(event: &ActionEvent) {
+    log(&format!("action {} pressed: {}", event.action_id, event.pressed));
+}
+"#;
+
+const CARGO_CONFIG: &str = r#"[build]
+target = "wasm32-unknown-unknown"
+"#;
+
+const GITIGNORE: &str = "/target\n/main.wasm\n";
+
+/// Scaffold a plugin named `name` in `dir`, returning the directory.
+///
+/// `dir` defaults to a directory named after the plugin, and must be
+/// empty if it exists. `gers_api` is depended on by path when `api` is
+/// given, and from the repository otherwise.
+pub fn new_plugin(name: &str, dir: Option<&Path>, api: Option<&Path>) -> anyhow::Result<PathBuf> {
+    validate_name(name)?;
+    let dir = dir.map_or_else(|| PathBuf::from(name), Path::to_path_buf);
+
+    let is_empty = match fs::read_dir(&dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => true,
+    };
+    if !is_empty {
+        return Err(anyhow!("destination {} is not empty", dir.display()));
+    }
+
+    let files = [
+        ("plugin.toml", plugin_toml(name)),
+        ("Cargo.toml", cargo_toml(name, api)),
+        (".cargo/config.toml", CARGO_CONFIG.to_string()),
+        (".gitignore", GITIGNORE.to_string()),
+        ("src/lib.rs", LIB_RS.to_string()),
+        ("README.md", readme(name)),
+    ];
+    for (path, contents) in files.iter() {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    Ok(dir)
+}
+
+/// Plugin names are used as the crate name, so they follow its rules.
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid = matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "invalid plugin name {:?}, use letters, digits, - and _, starting with a letter",
+            name
+        ))
+    }
+}
+
+fn plugin_toml(name: &str) -> String {
+    format!(
+        "name = {:?}\nversion = \"0.1.0\"\n\n# Capabilities the plugin needs, all denied by default.\n[permissions]\n",
+        name
+    )
+}
+
+fn cargo_toml(name: &str, api: Option<&Path>) -> String {
+    let gers_api = match api {
+        Some(path) => format!("{{ path = {:?} }}", path.display().to_string()),
+        None => format!("{{ git = {:?} }}", GERS_REPOSITORY),
+    };
+
+    format!(
+        r#"[package]
+name = {:?}
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+gers_api = {}
+
+[profile.release]
+opt-level = "s"
+lto = true
+
+# Not part of an enclosing workspace.
+[workspace]
+"#,
+        name, gers_api
+    )
+}
+
+fn readme(name: &str) -> String {
+    format!(
+        r#"# {0}
+
+Plugin for gers. Build the module, and copy it next to `plugin.toml`:
+
+```shell
+rustup target add wasm32-unknown-unknown
+cargo build --release
+cp target/wasm32-unknown-unknown/release/{1}.wasm main.wasm
+```
+
+Then run gers with the parent directory as a plugin directory:
+
+```shell
+gers --plugin-dir ..
+```
+"#,
+        name,
+        name.replace('-', "_")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_names() {
+        for name in ["my-plugin", "plugin_2", "P"] {
+            assert!(validate_name(name).is_ok(), "{:?} should be valid", name);
+        }
+        for name in ["", "2fast", "-plugin", "my plugin", "../up", "naïve"] {
+            assert!(validate_name(name).is_err(), "{:?} should be invalid", name);
+        }
+    }
+
+    #[test]
+    fn test_new_plugin() {
+        let dir = std::env::temp_dir().join(format!("gers-new-plugin-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let created = new_plugin("my-plugin", Some(&dir), Some(Path::new("../gers_api"))).unwrap();
+        assert_eq!(created, dir);
+
+        let meta = fs::read_to_string(dir.join("plugin.toml")).unwrap();
+        assert!(meta.starts_with("name = \"my-plugin\"\nversion = \"0.1.0\"\n"));
+        let cargo = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+        assert!(cargo.contains("crate-type = [\"cdylib\"]"));
+        assert!(cargo.contains("gers_api = { path = \"../gers_api\" }"));
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.contains("release/my_plugin.wasm"));
+        assert!(dir.join("src/lib.rs").is_file());
+        assert!(dir.join(".cargo/config.toml").is_file());
+
+        // Existing plugins aren't overwritten.
+        assert!(new_plugin("my-plugin", Some(&dir), None).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
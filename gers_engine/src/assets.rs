@@ -0,0 +1,127 @@
+//! Plugin asset loading.
+//!
+//! Assets are read through the plugin's source, so they
+//! work the same for plugin directories and archives.
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use gers_plugins::PluginSource;
+
+use crate::render::Texture;
+
+/// Directory inside a plugin that holds its assets.
+pub const ASSET_DIR: &str = "assets";
+
+/// Resolve a guest supplied path inside the plugin's asset directory,
+/// relative to the plugin root.
+///
+/// Returns `None` for absolute paths, and paths that would
+/// escape the asset directory.
+pub fn resolve_asset_path(relative: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::from(ASSET_DIR);
+
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(path)
+}
+
+/// Read an asset file out of the plugin.
+pub fn read_asset(source: &PluginSource, relative: &str) -> io::Result<Vec<u8>> {
+    let path = resolve_asset_path(relative).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("path outside of asset directory: {}", relative),
+        )
+    })?;
+
+    source.read_file(path)
+}
+
+/// Decode an image file into RGBA texture data.
+pub fn load_texture(bytes: &[u8]) -> Result<Texture, image::ImageError> {
+    let image = image::load_from_memory(bytes)?.to_rgba8();
+    let (width, height) = image.dimensions();
+
+    Ok(Texture {
+        width,
+        height,
+        pixels: image.into_raw(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// PNG of a red pixel next to a transparent one.
+    fn png() -> Vec<u8> {
+        let image = image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 0, 0]).unwrap();
+        let mut bytes = vec![];
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut bytes, image::ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_resolve_asset_path() {
+        let assets = Path::new(ASSET_DIR);
+        for (relative, resolved) in [
+            ("ship.png", Some(assets.join("ship.png"))),
+            (
+                "./sprites/ship.png",
+                Some(assets.join("sprites").join("ship.png")),
+            ),
+            (
+                "sprites//ship.png",
+                Some(assets.join("sprites").join("ship.png")),
+            ),
+            ("", Some(assets.to_path_buf())),
+            ("../plugin.toml", None),
+            ("sprites/../../main.wasm", None),
+            ("/etc/passwd", None),
+        ] {
+            assert_eq!(resolve_asset_path(relative), resolved, "{:?}", relative);
+        }
+    }
+
+    #[test]
+    fn test_read_asset() {
+        let root = std::env::temp_dir().join(format!("gers-assets-{}", std::process::id()));
+        fs::create_dir_all(root.join(ASSET_DIR)).unwrap();
+        fs::write(root.join(ASSET_DIR).join("a.txt"), b"asset").unwrap();
+        fs::write(root.join("plugin.toml"), b"name = \"a\"").unwrap();
+        let source = PluginSource::Directory(root.clone());
+
+        assert_eq!(read_asset(&source, "a.txt").unwrap(), b"asset");
+        assert_eq!(
+            read_asset(&source, "missing.txt").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        // Files next to the asset directory can't be read.
+        assert_eq!(
+            read_asset(&source, "../plugin.toml").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_load_texture() {
+        let texture = load_texture(&png()).unwrap();
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(texture.pixels, [255, 0, 0, 255, 0, 0, 0, 0]);
+
+        assert!(load_texture(b"not an image").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Audio output backends.
+//!
+//! Output goes through `rodio` when the `audio` feature is enabled,
+//! otherwise sounds are accepted and silently discarded.
+pub use imp::{Device, Mixer, Voice};
+
+#[cfg(feature = "audio")]
+mod imp {
+    use std::{io::Cursor, sync::Arc};
+
+    use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+
+    use crate::audio::PlayError;
+
+    /// Audio output device.
+    ///
+    /// The output stream can't be sent between threads, so it
+    /// stays on the main thread while the `Mixer` is shared.
+    pub struct Device {
+        _stream: Option<OutputStream>,
+        handle: Option<OutputStreamHandle>,
+    }
+
+    impl Device {
+        pub fn open(logger: &slog::Logger) -> Self {
+            match OutputStream::try_default() {
+                Ok((stream, handle)) => Self {
+                    _stream: Some(stream),
+                    handle: Some(handle),
+                },
+                Err(err) => {
+                    slog::warn!(logger, "no audio output device: {}", err);
+                    Self {
+                        _stream: None,
+                        handle: None,
+                    }
+                }
+            }
+        }
+
+        pub fn mixer(&self) -> Mixer {
+            Mixer {
+                handle: self.handle.clone(),
+            }
+        }
+    }
+
+    pub struct Mixer {
+        handle: Option<OutputStreamHandle>,
+    }
+
+    impl Mixer {
+        pub fn play(&self, data: Arc<[u8]>, volume: f32) -> Result<Voice, PlayError> {
+            let handle = self
+                .handle
+                .as_ref()
+                .ok_or_else(|| PlayError::Backend("no output device".to_string()))?;
+            let source = Decoder::new(Cursor::new(data))
+                .map_err(|err| PlayError::Backend(err.to_string()))?;
+            let sink = Sink::try_new(handle).map_err(|err| PlayError::Backend(err.to_string()))?;
+            sink.set_volume(volume);
+            sink.append(source);
+
+            Ok(Voice { sink })
+        }
+    }
+
+    pub struct Voice {
+        sink: Sink,
+    }
+
+    impl Voice {
+        pub fn is_finished(&self) -> bool {
+            self.sink.empty()
+        }
+
+        pub fn stop(&self) {
+            self.sink.stop();
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+mod imp {
+    use std::sync::Arc;
+
+    use crate::audio::PlayError;
+
+    pub struct Device;
+
+    impl Device {
+        pub fn open(logger: &slog::Logger) -> Self {
+            slog::info!(logger, "built without the audio feature, sound is disabled");
+            Self
+        }
+
+        pub fn mixer(&self) -> Mixer {
+            Mixer
+        }
+    }
+
+    pub struct Mixer;
+
+    impl Mixer {
+        pub fn play(&self, _data: Arc<[u8]>, _volume: f32) -> Result<Voice, PlayError> {
+            Ok(Voice)
+        }
+    }
+
+    /// Silent voice, which finishes immediately.
+    pub struct Voice;
+
+    impl Voice {
+        pub fn is_finished(&self) -> bool {
+            true
+        }
+
+        pub fn stop(&self) {}
+    }
+}
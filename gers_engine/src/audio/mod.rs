@@ -0,0 +1,168 @@
+//! Audio playback for plugins.
+//!
+//! Sound files are loaded into memory once, and decoded each time
+//! they are played. Every plugin may only have a limited number of
+//! voices playing at the same time, so one plugin can't exhaust
+//! the mixer.
+use gers_handles::{Handle, HandleTable};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+mod backend;
+
+pub use backend::Device;
+use backend::{Mixer, Voice};
+
+/// Handle given to guests to refer to a loaded sound.
+pub type SoundId = Handle;
+
+/// Handle given to guests to refer to a playing voice.
+pub type VoiceId = Handle;
+
+/// Default number of voices a single plugin may have playing.
+pub const DEFAULT_VOICE_LIMIT: usize = 8;
+
+/// Sounds and voices, with the key of the plugin that loaded or
+/// started each.
+pub struct Audio {
+    mixer: Mixer,
+    sounds: HandleTable<Arc<[u8]>, PathBuf>,
+    voices: HandleTable<Voice, PathBuf>,
+    voice_limit: usize,
+}
+
+#[derive(Debug)]
+pub enum PlayError {
+    UnknownSound(SoundId),
+    VoiceLimit(usize),
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    Backend(String),
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::UnknownSound(id) => write!(f, "unknown sound: {}", id),
+            PlayError::VoiceLimit(limit) => write!(f, "plugin voice limit reached: {}", limit),
+            PlayError::Backend(msg) => write!(f, "audio backend: {}", msg),
+        }
+    }
+}
+
+impl Audio {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            mixer: device.mixer(),
+            sounds: HandleTable::new(),
+            voices: HandleTable::new(),
+            voice_limit: DEFAULT_VOICE_LIMIT,
+        }
+    }
+
+    /// Keep an encoded sound file in memory, for playing, on behalf of
+    /// the plugin at `owner`.
+    pub fn insert(&mut self, owner: &Path, data: Vec<u8>) -> SoundId {
+        self.sounds.insert(owner.to_path_buf(), data.into())
+    }
+
+    /// Start playing a sound on behalf of the plugin at `owner`. Plugins
+    /// can only play the sounds they loaded.
+    pub fn play(
+        &mut self,
+        owner: &Path,
+        sound: SoundId,
+        volume: f32,
+    ) -> Result<VoiceId, PlayError> {
+        let data = self
+            .sounds
+            .get_owned(owner, sound)
+            .map(Arc::clone)
+            .map_err(|_| PlayError::UnknownSound(sound))?;
+
+        self.voices.retain(|_, _, voice| !voice.is_finished());
+        if self.voices.count_owned(owner) >= self.voice_limit {
+            return Err(PlayError::VoiceLimit(self.voice_limit));
+        }
+
+        let voice = self.mixer.play(data, volume.max(0.0))?;
+
+        Ok(self.voices.insert(owner.to_path_buf(), voice))
+    }
+
+    /// Stop a voice. Plugins can only stop the voices they started.
+    ///
+    /// Returns `true` if the voice was playing.
+    pub fn stop(&mut self, owner: &Path, voice: VoiceId) -> bool {
+        match self.voices.remove_owned(owner, voice) {
+            Ok(voice) => {
+                voice.stop();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Stop the voices, and drop the sounds, of the plugin at `owner`.
+    ///
+    /// Returns the number of voices stopped and sounds dropped.
+    pub fn release(&mut self, owner: &Path) -> (usize, usize) {
+        let voices = self.voices.release(owner);
+        for voice in voices.iter() {
+            voice.stop();
+        }
+
+        (voices.len(), self.sounds.release(owner).len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio() -> Audio {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        Audio::new(&Device::open(&logger))
+    }
+
+    fn insert_sound(audio: &mut Audio, data: Vec<u8>) -> SoundId {
+        audio.insert(Path::new("a"), data)
+    }
+
+    #[test]
+    fn test_voice_limit() {
+        let mut audio = audio();
+        let sound = insert_sound(&mut audio, vec![1, 2, 3]);
+        audio.voice_limit = 0;
+
+        assert!(matches!(
+            audio.play(Path::new("a"), sound, 1.0),
+            Err(PlayError::VoiceLimit(0))
+        ));
+    }
+
+    // The silent backend's voices finish straight away.
+    #[cfg(not(feature = "audio"))]
+    #[test]
+    fn test_finished_voices_are_released() {
+        let mut audio = audio();
+        let sound = insert_sound(&mut audio, vec![1, 2, 3]);
+        audio.voice_limit = 1;
+
+        // Only the plugin that loaded a sound can play it.
+        assert!(matches!(
+            audio.play(Path::new("b"), sound, 1.0),
+            Err(PlayError::UnknownSound(_))
+        ));
+        let voice = audio.play(Path::new("a"), sound, 1.0).unwrap();
+        assert!(!voice.is_null());
+        // Only the plugin that started a voice can stop it.
+        assert!(!audio.stop(Path::new("b"), voice));
+        // The finished voice no longer counts towards the limit.
+        let voice = audio.play(Path::new("a"), sound, -1.0).unwrap();
+        assert!(audio.stop(Path::new("a"), voice));
+        assert!(!audio.stop(Path::new("a"), voice));
+    }
+}
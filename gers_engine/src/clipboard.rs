@@ -0,0 +1,98 @@
+//! Text exchange with the clipboard.
+//!
+//! The OS clipboard is used through `arboard` when the `clipboard`
+//! feature is enabled. Otherwise the text is only kept within the app,
+//! so plugins can still exchange text with each other.
+//!
+//! Clipboard contents aren't recorded, so plugins reading them
+//! may diverge from a recording when it's replayed.
+use std::fmt;
+
+pub use imp::Clipboard;
+
+#[derive(Debug)]
+pub enum ClipboardError {
+    /// The clipboard holds no text.
+    Empty,
+    /// The clipboard couldn't be accessed.
+    #[cfg_attr(not(feature = "clipboard"), allow(dead_code))]
+    Unavailable(String),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::Empty => write!(f, "clipboard holds no text"),
+            ClipboardError::Unavailable(reason) => write!(f, "clipboard unavailable: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "clipboard")]
+mod imp {
+    use super::ClipboardError;
+
+    /// The OS clipboard.
+    ///
+    /// Opened on each access, because the handle can't be shared
+    /// between threads on every platform.
+    #[derive(Default)]
+    pub struct Clipboard;
+
+    impl Clipboard {
+        pub fn get_text(&mut self) -> Result<String, ClipboardError> {
+            open()?.get_text().map_err(map_error)
+        }
+
+        pub fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+            open()?.set_text(text).map_err(map_error)
+        }
+    }
+
+    fn open() -> Result<arboard::Clipboard, ClipboardError> {
+        arboard::Clipboard::new().map_err(map_error)
+    }
+
+    fn map_error(err: arboard::Error) -> ClipboardError {
+        match err {
+            arboard::Error::ContentNotAvailable => ClipboardError::Empty,
+            err => ClipboardError::Unavailable(err.to_string()),
+        }
+    }
+}
+
+#[cfg(not(feature = "clipboard"))]
+mod imp {
+    use super::ClipboardError;
+
+    /// Clipboard kept within the app.
+    #[derive(Default)]
+    pub struct Clipboard {
+        text: Option<String>,
+    }
+
+    impl Clipboard {
+        pub fn get_text(&mut self) -> Result<String, ClipboardError> {
+            self.text.clone().ok_or(ClipboardError::Empty)
+        }
+
+        pub fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+            self.text = Some(text);
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_text_is_kept() {
+            let mut clipboard = Clipboard::default();
+            assert!(matches!(clipboard.get_text(), Err(ClipboardError::Empty)));
+
+            clipboard.set_text("copied".to_string()).unwrap();
+            assert_eq!(clipboard.get_text().unwrap(), "copied");
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Frame delta time.
+//!
+//! The time measured between frames is clamped, so a stall, like a
+//! breakpoint or dragging the window, doesn't reach plugins as one huge
+//! step. It's never zero either, because it's frequently used as a
+//! divisor. Plugins see the clamped delta time multiplied by the time
+//! scale, which the host and plugins can change to slow down, speed up
+//! or freeze the game, along with the unscaled delta time.
+//!
+//! Time is read from a `Clock`, which is the system's unless the game
+//! gives the engine another. A `TestClock` only moves when it's told to,
+//! or when the frame loop waits on it, so tests and headless runs drive
+//! time instead of waiting for it.
+use std::{
+    cell::Cell,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shortest delta time reported for a frame.
+pub const MIN_DELTA_TIME: Duration = Duration::from_micros(1);
+
+/// Clamp the time measured between frames to a usable delta time.
+pub fn clamp_delta_time(delta_time: Duration, max: Duration) -> Duration {
+    delta_time.clamp(MIN_DELTA_TIME, max.max(MIN_DELTA_TIME))
+}
+
+/// Time scales are finite and not negative, where zero freezes time.
+pub fn is_valid_time_scale(time_scale: f32) -> bool {
+    time_scale.is_finite() && time_scale >= 0.0
+}
+
+/// Delta time with the time scale applied, which is zero while time
+/// is frozen.
+pub fn scale_delta_time(delta_time: Duration, time_scale: f32) -> Duration {
+    if !is_valid_time_scale(time_scale) {
+        return delta_time;
+    }
+
+    Duration::try_from_secs_f64(delta_time.as_secs_f64() * time_scale as f64)
+        .unwrap_or(Duration::MAX)
+}
+
+/// Source of time for the frame loop.
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /// Block the thread for about the duration.
+    fn sleep(&self, duration: Duration);
+
+    /// Let other threads run while waiting.
+    fn yield_now(&self);
+}
+
+impl<C: Clock + ?Sized> Clock for Rc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+
+    fn yield_now(&self) {
+        (**self).yield_now()
+    }
+}
+
+/// Time as the operating system measures it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+
+    fn yield_now(&self) {
+        thread::yield_now()
+    }
+}
+
+/// Clock that stands still until it's advanced.
+///
+/// Sleeping advances it by the duration, and yielding by
+/// `TestClock::YIELD_STEP`, so waiting for a deadline returns right
+/// away, at the deadline. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl TestClock {
+    /// Time passing each time the clock is yielded on.
+    pub const YIELD_STEP: Duration = Duration::from_micros(100);
+
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Rc::new(Cell::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+
+    /// Time the clock was advanced by since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn yield_now(&self) {
+        self.advance(Self::YIELD_STEP);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_delta_time() {
+        let max = Duration::from_millis(250);
+
+        assert_eq!(clamp_delta_time(Duration::ZERO, max), MIN_DELTA_TIME);
+        assert_eq!(
+            clamp_delta_time(Duration::from_millis(16), max),
+            Duration::from_millis(16)
+        );
+        // A stall is a single long frame.
+        assert_eq!(clamp_delta_time(Duration::from_secs(30), max), max);
+        // Even with a bogus maximum.
+        assert_eq!(
+            clamp_delta_time(Duration::from_secs(1), Duration::ZERO),
+            MIN_DELTA_TIME
+        );
+    }
+
+    #[test]
+    fn test_delta_time_between_frames() {
+        let clock = TestClock::new();
+        let max = Duration::from_millis(250);
+        let mut last_time = clock.now();
+
+        // Frames at the same instant still move time forward.
+        let now = clock.now();
+        assert_eq!(clamp_delta_time(now - last_time, max), MIN_DELTA_TIME);
+        last_time = now;
+
+        clock.advance(Duration::from_millis(16));
+        let now = clock.now();
+        assert_eq!(
+            clamp_delta_time(now - last_time, max),
+            Duration::from_millis(16)
+        );
+
+        // Clones share the time, and waiting passes it.
+        let shared = clock.clone();
+        shared.sleep(Duration::from_secs(1));
+        shared.yield_now();
+        assert_eq!(
+            clock.elapsed(),
+            Duration::from_millis(1016) + TestClock::YIELD_STEP
+        );
+        assert_eq!(clamp_delta_time(clock.now() - now, max), max);
+    }
+
+    #[test]
+    fn test_scale_delta_time() {
+        let delta_time = Duration::from_millis(20);
+
+        assert_eq!(scale_delta_time(delta_time, 1.0), delta_time);
+        assert_eq!(scale_delta_time(delta_time, 0.5), Duration::from_millis(10));
+        assert_eq!(scale_delta_time(delta_time, 0.0), Duration::ZERO);
+        // Invalid scales are ignored.
+        assert_eq!(scale_delta_time(delta_time, -1.0), delta_time);
+        assert_eq!(scale_delta_time(delta_time, f32::NAN), delta_time);
+        assert!(!is_valid_time_scale(f32::INFINITY));
+    }
+}
@@ -0,0 +1,604 @@
+//! Engine settings loaded from `gers.toml`.
+//!
+//! Every setting has a default, so the file and any of
+//! its sections may be left out. The app's command line
+//! arguments override the values in the file.
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use gers_plugins::{MissingImports, TrustPolicy};
+use serde::Deserialize;
+
+use crate::{
+    clock,
+    event_queue::{self, EventQueues, OverflowPolicy},
+    fps::FpsThrottlePolicy,
+    i18n,
+    lag::LagPolicy,
+    plugin_log,
+};
+
+/// Config file looked up in the working directory.
+pub const CONFIG_FILENAME: &str = "gers.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub frame: FrameConfig,
+    pub plugins: PluginsConfig,
+    pub i18n: I18nConfig,
+    pub events: EventsConfig,
+    pub log: LogConfig,
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub vsync: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "gers".to_string(),
+            width: 1024,
+            height: 768,
+            vsync: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FrameConfig {
+    pub target_fps: u64,
+    /// Frame rate while the window is unfocused, or zero to keep
+    /// the target frame rate.
+    pub background_fps: u64,
+    pub throttle: FpsThrottlePolicy,
+    /// Rate of the fixed timestep, in updates per second.
+    pub fixed_rate: f64,
+    /// Time per frame for dispatching events, in milliseconds, within
+    /// which frames catch up on the fixed updates they fell behind on,
+    /// or zero to run at most one fixed update per frame.
+    pub dispatch_budget_ms: f64,
+    /// What happens to fixed updates that don't fit into the dispatch
+    /// budget.
+    pub lag_policy: LagPolicy,
+    /// Time per frame shared by plugins resuming yielded
+    /// updates, in milliseconds.
+    pub work_budget_ms: f64,
+    /// Stop updating plugins while the window is unfocused or minimized.
+    pub pause_updates: bool,
+    /// Keep counting time towards the fixed timestep while paused.
+    pub accumulate_while_paused: bool,
+    /// Longest delta time of a frame, in milliseconds. Longer frames,
+    /// like after a stall, are shortened to this.
+    pub max_delta_ms: f64,
+    /// Multiplier for the delta time plugins see, until a plugin
+    /// changes it.
+    pub time_scale: f32,
+    /// Log a warning with plugin timings when a frame takes longer
+    /// than this, in milliseconds, or zero to never warn.
+    pub warn_frame_ms: f64,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            target_fps: 144,
+            background_fps: 0,
+            throttle: FpsThrottlePolicy::Yield,
+            fixed_rate: 5.0,
+            dispatch_budget_ms: 0.0,
+            lag_policy: LagPolicy::Carryover,
+            work_budget_ms: 4.0,
+            pause_updates: true,
+            accumulate_while_paused: false,
+            max_delta_ms: 250.0,
+            time_scale: 1.0,
+            warn_frame_ms: 0.0,
+        }
+    }
+}
+
+impl FrameConfig {
+    /// Duration of a fixed timestep, in seconds.
+    pub fn fixed_interval(&self) -> f64 {
+        1.0 / self.fixed_rate
+    }
+
+    /// Time per frame for catching up on fixed updates, if any.
+    pub fn dispatch_budget(&self) -> Option<Duration> {
+        if self.dispatch_budget_ms > 0.0 {
+            Some(Duration::from_secs_f64(self.dispatch_budget_ms / 1000.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn work_budget(&self) -> Duration {
+        Duration::from_secs_f64(self.work_budget_ms / 1000.0)
+    }
+
+    pub fn max_delta_time(&self) -> Duration {
+        Duration::from_secs_f64(self.max_delta_ms / 1000.0)
+    }
+
+    /// Frame time over which a warning is logged, if any.
+    pub fn warn_frame_time(&self) -> Option<Duration> {
+        if self.warn_frame_ms > 0.0 {
+            Some(Duration::from_secs_f64(self.warn_frame_ms / 1000.0))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    /// Directories searched for plugins. Each sub-directory
+    /// containing a `plugin.toml` is loaded as a plugin.
+    pub paths: Vec<PathBuf>,
+    /// File listing which discovered plugins are enabled.
+    pub enabled_file: PathBuf,
+    /// File the values of plugin settings are persisted to.
+    pub settings_file: PathBuf,
+    /// One of `development`, `verify` or `requiresigned`.
+    pub trust: TrustPolicy,
+    /// Hex encoded ed25519 keys accepted for plugin signatures.
+    pub trusted_keys: Vec<String>,
+    /// Time shared by plugins shutting down when the app quits,
+    /// in milliseconds.
+    pub shutdown_timeout_ms: u64,
+    /// Milliseconds between logging the memory usage of plugins,
+    /// or zero to only log it on demand.
+    pub memory_report_ms: u64,
+    /// Compile plugins to compute the same results on every machine,
+    /// for lockstep multiplayer. Needs an explicit seed.
+    pub deterministic: bool,
+    /// Milliseconds a single call into a plugin may take before it's
+    /// interrupted and the plugin is faulted, or zero to never
+    /// interrupt plugins.
+    pub watchdog_timeout_ms: u64,
+    /// Directory crash dumps of plugins that trap are written to,
+    /// or empty to not write them.
+    pub crash_dir: PathBuf,
+    /// Number of delivered events each plugin keeps for its crash dump.
+    pub crash_event_history: usize,
+    /// One of `fail`, `trap` or `error`, for plugins importing host
+    /// functions this build doesn't provide.
+    pub missing_imports: MissingImports,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            paths: vec![PathBuf::from("plugins")],
+            enabled_file: PathBuf::from("enabled.toml"),
+            settings_file: PathBuf::from("settings.toml"),
+            trust: TrustPolicy::default(),
+            trusted_keys: vec![],
+            shutdown_timeout_ms: 2000,
+            memory_report_ms: 0,
+            deterministic: false,
+            watchdog_timeout_ms: 5000,
+            crash_dir: PathBuf::from("crashes"),
+            crash_event_history: 32,
+            missing_imports: MissingImports::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// Events each plugin can have queued during a frame.
+    pub queue_capacity: usize,
+    /// What happens to events queued for a plugin whose queue is full.
+    /// One of `drop-oldest`, `drop-newest` or `fail`.
+    pub overflow: OverflowPolicy,
+    /// Overflow policies of single event types, by event name,
+    /// like `gamepad_axis`.
+    pub overflow_policies: HashMap<String, OverflowPolicy>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: event_queue::DEFAULT_QUEUE_CAPACITY,
+            overflow: OverflowPolicy::DropOldest,
+            overflow_policies: HashMap::new(),
+        }
+    }
+}
+
+impl EventsConfig {
+    /// Event queues with the configured capacity and policies.
+    pub fn event_queues(&self) -> anyhow::Result<EventQueues> {
+        let mut queues = EventQueues::new(self.queue_capacity, self.overflow);
+        for (name, policy) in self.overflow_policies.iter() {
+            let event_type = event_queue::parse_event_type(name).ok_or_else(|| {
+                anyhow!("unknown event type in events.overflow_policies: {}", name)
+            })?;
+            queues.set_policy(event_type, *policy);
+        }
+
+        Ok(queues)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct I18nConfig {
+    /// Locale of the plugins' string tables, like `en` or `pt-BR`.
+    pub locale: String,
+    /// Locale of the strings the active locale doesn't translate.
+    pub fallback: String,
+}
+
+impl Default for I18nConfig {
+    fn default() -> Self {
+        Self {
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            fallback: i18n::DEFAULT_LOCALE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// One of `critical`, `error`, `warning`, `info`, `debug` or `trace`.
+    pub level: String,
+    /// Directory messages of each plugin are also written to, as
+    /// `<plugin>.log`, or empty to only log to the terminal.
+    pub plugin_dir: PathBuf,
+    /// Size a plugin's log file grows to before it's rotated, in
+    /// kilobytes.
+    pub rotate_kb: u64,
+    /// Rotated log files kept per plugin.
+    pub keep_rotated: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Address to serve Prometheus metrics on, like `127.0.0.1:9100`,
+    /// or empty to not serve them.
+    pub listen: String,
+    /// File the metrics are written to as JSON, or empty to not
+    /// write them.
+    pub json_path: PathBuf,
+    /// Time between metric updates, in milliseconds.
+    pub interval_ms: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            listen: String::new(),
+            json_path: PathBuf::new(),
+            interval_ms: 5000,
+        }
+    }
+}
+
+impl MetricsConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.listen.is_empty() || self.json_path().is_some()
+    }
+
+    pub fn json_path(&self) -> Option<&Path> {
+        if self.json_path.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.json_path)
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+impl PluginsConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+
+    /// Interval for logging memory usage, if it's logged periodically.
+    pub fn memory_report_interval(&self) -> Option<Duration> {
+        match self.memory_report_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Time a call into a plugin may take, if calls are interrupted.
+    pub fn watchdog_timeout(&self) -> Option<Duration> {
+        match self.watchdog_timeout_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Directory crash dumps are written to, if they're written.
+    pub fn crash_dir(&self) -> Option<&Path> {
+        if self.crash_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.crash_dir)
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            plugin_dir: PathBuf::from("logs"),
+            rotate_kb: plugin_log::DEFAULT_ROTATE_SIZE / 1024,
+            keep_rotated: plugin_log::DEFAULT_KEEP_ROTATED,
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn level(&self) -> anyhow::Result<slog::Level> {
+        slog::Level::from_str(&self.level).map_err(|_| anyhow!("unknown log level: {}", self.level))
+    }
+
+    pub fn plugin_dir(&self) -> Option<&Path> {
+        if self.plugin_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.plugin_dir)
+        }
+    }
+
+    /// Size a plugin's log file grows to before it's rotated, in bytes.
+    pub fn rotate_size(&self) -> u64 {
+        self.rotate_kb.saturating_mul(1024)
+    }
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults when it doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let buf = fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+        let config = toml::from_str(&buf).with_context(|| format!("parsing {:?}", path))?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.frame.target_fps == 0 {
+            return Err(anyhow!("frame.target_fps must be greater than zero"));
+        }
+        if !(self.frame.fixed_rate > 0.0 && self.frame.fixed_rate.is_finite()) {
+            return Err(anyhow!("frame.fixed_rate must be greater than zero"));
+        }
+        if !(self.frame.dispatch_budget_ms >= 0.0 && self.frame.dispatch_budget_ms.is_finite()) {
+            return Err(anyhow!("frame.dispatch_budget_ms must not be negative"));
+        }
+        if !(self.frame.work_budget_ms >= 0.0 && self.frame.work_budget_ms.is_finite()) {
+            return Err(anyhow!("frame.work_budget_ms must not be negative"));
+        }
+        if !(self.frame.max_delta_ms > 0.0 && self.frame.max_delta_ms.is_finite()) {
+            return Err(anyhow!("frame.max_delta_ms must be greater than zero"));
+        }
+        if !(self.frame.warn_frame_ms >= 0.0 && self.frame.warn_frame_ms.is_finite()) {
+            return Err(anyhow!("frame.warn_frame_ms must not be negative"));
+        }
+        if !clock::is_valid_time_scale(self.frame.time_scale) {
+            return Err(anyhow!("frame.time_scale must not be negative"));
+        }
+        if self.events.queue_capacity == 0 {
+            return Err(anyhow!("events.queue_capacity must be greater than zero"));
+        }
+        self.events.event_queues()?;
+        if self.metrics.interval_ms == 0 {
+            return Err(anyhow!("metrics.interval_ms must be greater than zero"));
+        }
+        self.log.level()?;
+        if self.log.rotate_kb == 0 {
+            return Err(anyhow!("log.rotate_kb must be greater than zero"));
+        }
+        for locale in [&self.i18n.locale, &self.i18n.fallback] {
+            if !i18n::is_valid_locale(locale) {
+                return Err(anyhow!("invalid locale in i18n: {:?}", locale));
+            }
+        }
+        for key in self.plugins.trusted_keys.iter() {
+            gers_plugins::parse_public_key(key)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse("");
+        config.validate().unwrap();
+
+        assert_eq!(config.frame.target_fps, 144);
+        assert_eq!(config.frame.background_fps, 0);
+        assert_eq!(config.frame.fixed_rate, 5.0);
+        assert_eq!(config.frame.dispatch_budget(), None);
+        assert_eq!(config.frame.lag_policy, LagPolicy::Carryover);
+        assert_eq!(config.frame.work_budget(), Duration::from_millis(4));
+        assert!(config.frame.pause_updates);
+        assert!(!config.frame.accumulate_while_paused);
+        assert_eq!(config.frame.max_delta_time(), Duration::from_millis(250));
+        assert_eq!(config.frame.time_scale, 1.0);
+        assert_eq!(config.frame.warn_frame_time(), None);
+        assert_eq!(config.plugins.paths, vec![PathBuf::from("plugins")]);
+        assert_eq!(config.plugins.shutdown_timeout(), Duration::from_secs(2));
+        assert_eq!(config.plugins.memory_report_interval(), None);
+        assert_eq!(config.log.level().unwrap(), slog::Level::Info);
+        assert_eq!(config.i18n.locale, "en");
+        assert_eq!(config.events.queue_capacity, 256);
+        assert_eq!(config.events.overflow, OverflowPolicy::DropOldest);
+        assert!(!config.metrics.is_enabled());
+        assert_eq!(config.metrics.interval(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_partial_sections_keep_defaults() {
+        let config = parse(
+            r#"
+            [frame]
+            target_fps = 60
+            "#,
+        );
+        config.validate().unwrap();
+
+        assert_eq!(config.frame.target_fps, 60);
+        assert_eq!(config.frame.fixed_rate, 5.0);
+        assert_eq!(config.window.width, 1024);
+    }
+
+    #[test]
+    fn test_shipped_config_matches_defaults() {
+        let config = parse(include_str!("../../gers.toml"));
+        config.validate().unwrap();
+
+        let defaults = Config::default();
+        assert_eq!(config.frame.target_fps, defaults.frame.target_fps);
+        assert_eq!(config.plugins.paths, defaults.plugins.paths);
+        assert_eq!(
+            config.plugins.missing_imports,
+            defaults.plugins.missing_imports
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_are_ignored() {
+        let config = parse(
+            r#"
+            unknown = 1
+
+            [window]
+            title = "game"
+            colour = "blue"
+
+            [unknown]
+            key = "value"
+            "#,
+        );
+
+        assert_eq!(config.window.title, "game");
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_values_fail_to_parse() {
+        for toml in [
+            "[frame]\ntarget_fps = \"fast\"",
+            "[frame]\ntarget_fps = -1",
+            "[frame]\nthrottle = \"never\"",
+            "[frame]\nlag_policy = \"skip\"",
+            "[plugins]\npaths = \"plugins\"",
+            "[plugins]\nmissing_imports = \"stub\"",
+            "[events]\noverflow = \"drop_oldest\"",
+            "window = 1",
+        ] {
+            assert!(toml::from_str::<Config>(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_values() {
+        for toml in [
+            "[frame]\ntarget_fps = 0",
+            "[frame]\nfixed_rate = 0.0",
+            "[frame]\nfixed_rate = -5.0",
+            "[frame]\nfixed_rate = nan",
+            "[frame]\nfixed_rate = inf",
+            "[frame]\ndispatch_budget_ms = -4.0",
+            "[frame]\nwork_budget_ms = -1.0",
+            "[frame]\nwork_budget_ms = nan",
+            "[frame]\nwork_budget_ms = inf",
+            "[frame]\nmax_delta_ms = 0.0",
+            "[frame]\ntime_scale = -0.5",
+            "[frame]\nwarn_frame_ms = -1.0",
+            "[events]\nqueue_capacity = 0",
+            "[events.overflow_policies]\nshutdown = \"fail\"",
+            "[log]\nlevel = \"loud\"",
+            "[i18n]\nlocale = \"../en\"",
+            "[metrics]\ninterval_ms = 0",
+        ] {
+            assert!(parse(toml).validate().is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn test_event_overflow_policies() {
+        let config = parse(
+            r#"
+            [events]
+            overflow = "fail"
+
+            [events.overflow_policies]
+            gamepad_axis = "drop-oldest"
+            "#,
+        );
+        config.validate().unwrap();
+
+        let queues = config.events.event_queues().unwrap();
+        assert_eq!(
+            queues.policy(gers_events::EventType::GamepadAxis),
+            OverflowPolicy::DropOldest
+        );
+        assert_eq!(
+            queues.policy(gers_events::EventType::Action),
+            OverflowPolicy::Fail
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let root = std::env::temp_dir().join(format!("gers-config-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+
+        let missing = Config::load(root.join(CONFIG_FILENAME)).unwrap();
+        assert_eq!(missing.frame.target_fps, 144);
+
+        let path = root.join(CONFIG_FILENAME);
+        fs::write(&path, "[frame]\ntarget_fps = 30").unwrap();
+        assert_eq!(Config::load(&path).unwrap().frame.target_fps, 30);
+
+        fs::write(&path, "[frame\ntarget_fps = 30").unwrap();
+        assert!(Config::load(&path).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,614 @@
+//! Console for invoking commands registered by plugins.
+//!
+//! Plugins register named commands with a line of help. The user
+//! invokes a command by typing its name followed by arguments, either
+//! into the console view toggled with the backtick key, or on stdin
+//! when running headless. The plugin that registered the command
+//! receives a `ConsoleCommandEvent`, and can read the arguments while
+//! the event is handled. The built-in `help` command lists the commands,
+//! `set <plugin> <key> <value>` changes a setting of a plugin,
+//! `locale <locale>` switches the language of the plugins' strings, and
+//! `watch` prints the values plugins watch.
+//!
+//! Commands aren't part of the frame's event stream, so they aren't
+//! recorded and replays don't invoke them.
+//!
+//! When two plugins register the same command, the one ranked first by
+//! plugin priority has it, even when it registers the command last.
+//! Each such conflict is kept until the host takes it.
+use crate::render::{Canvas, Color};
+use gers_events::ConsoleCommandEvent;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+pub type CommandId = u32;
+
+/// Default number of commands a single plugin may register.
+pub const DEFAULT_COMMAND_LIMIT: usize = 32;
+
+/// Name of the built-in command listing the registered commands.
+const HELP_COMMAND: &str = "help";
+
+/// Name of the built-in command changing a plugin's setting.
+const SET_COMMAND: &str = "set";
+
+/// Name of the built-in command switching the locale.
+const LOCALE_COMMAND: &str = "locale";
+
+/// Name of the built-in command printing the watched values.
+const WATCH_COMMAND: &str = "watch";
+
+/// Lines of output kept by the console view.
+const SCROLLBACK_LEN: usize = 12;
+
+/// Size of a text pixel.
+const TEXT_SCALE: u32 = 2;
+
+const PANEL_COLOR: Color = Color::rgba(0, 0, 0, 200);
+const PADDING: i32 = 8;
+
+/// Commands registered by plugins, shared with the host imports.
+pub struct Console {
+    commands: HashMap<String, Command>,
+    next_id: CommandId,
+    command_limit: usize,
+    /// Plugin the command being dispatched was registered by,
+    /// and its arguments.
+    invoking: Option<(PathBuf, String)>,
+    /// Rank of each plugin by priority, by key. Lower ranks win
+    /// conflicts, and unranked plugins come last.
+    ranks: HashMap<PathBuf, usize>,
+    /// Conflicts since they were last taken.
+    conflicts: Vec<CommandConflict>,
+}
+
+struct Command {
+    id: CommandId,
+    /// Key of the plugin that registered the command.
+    owner: PathBuf,
+    help: String,
+}
+
+/// Command registered by more than one plugin.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandConflict {
+    pub name: String,
+    /// Key of the plugin that has the command.
+    pub winner: PathBuf,
+    /// Key of the plugin whose registration was refused or taken
+    /// over.
+    pub loser: PathBuf,
+}
+
+/// Command to dispatch to the plugin that registered it.
+pub struct Invocation {
+    /// Key of the plugin that registered the command.
+    pub owner: PathBuf,
+    pub event: ConsoleCommandEvent,
+    pub args: String,
+}
+
+/// What a line typed into the console asks for.
+pub enum ConsoleInput {
+    /// Nothing but whitespace.
+    Empty,
+    /// Lines listing the registered commands.
+    Help(Vec<String>),
+    /// Change a plugin's setting to the value as it was typed.
+    Set {
+        plugin: String,
+        key: String,
+        value: String,
+    },
+    /// Switch to the locale.
+    Locale(String),
+    /// Print the values plugins watch.
+    Watch,
+    Command(Invocation),
+}
+
+#[derive(Debug)]
+pub enum ConsoleError {
+    CommandLimit(usize),
+    /// Names can't be empty or contain whitespace.
+    InvalidName(String),
+    /// The name is registered by another plugin, or built in.
+    NameTaken(String),
+    UnknownCommand(String),
+    /// Arguments of a built-in command are missing.
+    Usage(&'static str),
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsoleError::CommandLimit(limit) => {
+                write!(f, "plugin command limit reached: {}", limit)
+            }
+            ConsoleError::InvalidName(name) => write!(f, "invalid command name: {:?}", name),
+            ConsoleError::NameTaken(name) => write!(f, "command already registered: {}", name),
+            ConsoleError::UnknownCommand(name) => {
+                write!(f, "unknown command: {}, type help for a list", name)
+            }
+            ConsoleError::Usage(usage) => write!(f, "usage: {}", usage),
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            commands: HashMap::new(),
+            // Zero is reserved to signal failure to guests.
+            next_id: 1,
+            command_limit: DEFAULT_COMMAND_LIMIT,
+            invoking: None,
+            ranks: HashMap::new(),
+            conflicts: vec![],
+        }
+    }
+}
+
+impl Console {
+    /// Register a command on behalf of the plugin at `owner`.
+    ///
+    /// Registering a command again replaces its help, and keeps its id.
+    /// A command registered by a plugin ranked after `owner` is taken
+    /// over with a new id.
+    pub fn register(
+        &mut self,
+        owner: &Path,
+        name: String,
+        help: String,
+    ) -> Result<CommandId, ConsoleError> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(ConsoleError::InvalidName(name));
+        }
+        if [HELP_COMMAND, SET_COMMAND, LOCALE_COMMAND, WATCH_COMMAND].contains(&name.as_str()) {
+            return Err(ConsoleError::NameTaken(name));
+        }
+
+        let taken_from = match self.commands.get_mut(&name) {
+            Some(command) if command.owner == owner => {
+                command.help = help;
+                return Ok(command.id);
+            }
+            Some(command) => Some(command.owner.clone()),
+            None => None,
+        };
+        if let Some(ref holder) = taken_from {
+            if self.rank(owner) >= self.rank(holder) {
+                self.conflicts.push(CommandConflict {
+                    name: name.clone(),
+                    winner: holder.clone(),
+                    loser: owner.to_path_buf(),
+                });
+                return Err(ConsoleError::NameTaken(name));
+            }
+        }
+
+        let registered = self
+            .commands
+            .values()
+            .filter(|command| command.owner == owner)
+            .count();
+        if registered >= self.command_limit {
+            return Err(ConsoleError::CommandLimit(self.command_limit));
+        }
+
+        if let Some(loser) = taken_from {
+            self.conflicts.push(CommandConflict {
+                name: name.clone(),
+                winner: owner.to_path_buf(),
+                loser,
+            });
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.commands.insert(
+            name,
+            Command {
+                id,
+                owner: owner.to_path_buf(),
+                help,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Rank plugins by priority, with the keys of the plugins in
+    /// the order they win conflicts.
+    pub fn set_ranks<'a>(&mut self, keys: impl IntoIterator<Item = &'a Path>) {
+        self.ranks = keys
+            .into_iter()
+            .enumerate()
+            .map(|(rank, key)| (key.to_path_buf(), rank))
+            .collect();
+    }
+
+    fn rank(&self, owner: &Path) -> usize {
+        self.ranks.get(owner).copied().unwrap_or(usize::MAX)
+    }
+
+    /// Take the conflicts since the last call.
+    pub fn take_conflicts(&mut self) -> Vec<CommandConflict> {
+        std::mem::take(&mut self.conflicts)
+    }
+
+    /// Unregister every command of the plugin at `owner`.
+    ///
+    /// Returns the number of commands unregistered.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let count = self.commands.len();
+        self.commands.retain(|_, command| command.owner != owner);
+        count - self.commands.len()
+    }
+
+    /// Parse a line typed into the console.
+    ///
+    /// The first word is the command, the rest of the line, without
+    /// surrounding whitespace, its arguments.
+    pub fn parse(&self, line: &str) -> Result<ConsoleInput, ConsoleError> {
+        let line = line.trim();
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+
+        if name.is_empty() {
+            return Ok(ConsoleInput::Empty);
+        }
+        if name == HELP_COMMAND {
+            return Ok(ConsoleInput::Help(self.help()));
+        }
+        if name == SET_COMMAND {
+            let (plugin, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let rest = rest.trim_start();
+            let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let value = value.trim();
+            if plugin.is_empty() || key.is_empty() || value.is_empty() {
+                return Err(ConsoleError::Usage("set <plugin> <key> <value>"));
+            }
+            return Ok(ConsoleInput::Set {
+                plugin: plugin.to_string(),
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+        if name == LOCALE_COMMAND {
+            if args.is_empty() || args.contains(char::is_whitespace) {
+                return Err(ConsoleError::Usage("locale <locale>"));
+            }
+            return Ok(ConsoleInput::Locale(args.to_string()));
+        }
+        if name == WATCH_COMMAND {
+            return Ok(ConsoleInput::Watch);
+        }
+
+        match self.commands.get(name) {
+            Some(command) => Ok(ConsoleInput::Command(Invocation {
+                owner: command.owner.clone(),
+                event: ConsoleCommandEvent {
+                    command_id: command.id,
+                    args_len: args.len() as u32,
+                },
+                args: args.to_string(),
+            })),
+            None => Err(ConsoleError::UnknownCommand(name.to_string())),
+        }
+    }
+
+    /// Registered commands with their help, by name.
+    pub fn help(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.commands.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .map(|name| format!("{}  {}", name, self.commands[name].help))
+            .collect()
+    }
+
+    /// Start dispatching a command, making its arguments available
+    /// to the plugin that registered it.
+    pub fn begin_invocation(&mut self, invocation: &Invocation) {
+        self.invoking = Some((invocation.owner.clone(), invocation.args.clone()));
+    }
+
+    /// Arguments of the command being dispatched to the plugin at `owner`.
+    pub fn args(&self, owner: &Path) -> Option<&str> {
+        match self.invoking {
+            Some((ref invoked, ref args)) if invoked == owner => Some(args.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Stop dispatching the command.
+    pub fn end_invocation(&mut self) {
+        self.invoking = None;
+    }
+}
+
+/// Text view of the console, drawn over the game.
+pub struct ConsoleView {
+    visible: bool,
+    input: String,
+    scrollback: VecDeque<String>,
+    /// Lines entered since they were last taken.
+    submitted: Vec<String>,
+}
+
+impl ConsoleView {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            input: String::new(),
+            scrollback: VecDeque::with_capacity(SCROLLBACK_LEN),
+            submitted: vec![],
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Handle a character typed while the console is visible.
+    pub fn handle_char(&mut self, c: char) {
+        if !self.visible {
+            return;
+        }
+
+        match c {
+            '\r' | '\n' => {
+                let line = std::mem::take(&mut self.input);
+                self.print(format!("> {}", line));
+                self.submitted.push(line);
+            }
+            // Backspace
+            '\u{8}' => {
+                self.input.pop();
+            }
+            // The toggle key.
+            '`' => {}
+            c if !c.is_control() => self.input.push(c),
+            _ => {}
+        }
+    }
+
+    /// Add a line of output.
+    pub fn print(&mut self, line: impl Into<String>) {
+        if self.scrollback.len() >= SCROLLBACK_LEN {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(line.into());
+    }
+
+    /// Take the lines entered since the last call.
+    pub fn take_submitted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.submitted)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        if !self.visible {
+            return;
+        }
+
+        let line_height = Canvas::line_height(TEXT_SCALE);
+        let panel_height = line_height * (SCROLLBACK_LEN as u32 + 1) + PADDING as u32 * 2;
+        canvas.fill_rect(0, 0, canvas.width(), panel_height, PANEL_COLOR);
+
+        // Output is aligned to the bottom, right above the input.
+        let mut y = PADDING + (SCROLLBACK_LEN - self.scrollback.len()) as i32 * line_height as i32;
+        for line in self.scrollback.iter() {
+            canvas.draw_text(PADDING, y, line, TEXT_SCALE, Color::WHITE);
+            y += line_height as i32;
+        }
+
+        canvas.draw_text(
+            PADDING,
+            y,
+            &format!("] {}_", self.input),
+            TEXT_SCALE,
+            Color::YELLOW,
+        );
+    }
+}
+
+/// Read console lines from stdin on a background thread.
+///
+/// The channel disconnects once stdin is closed.
+pub fn read_stdin() -> io::Result<Receiver<String>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("gers-console".to_string())
+        .spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        })?;
+
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register() {
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut console = Console::default();
+
+        let id = console
+            .register(owner, "spawn".to_string(), "spawn an enemy".to_string())
+            .unwrap();
+        assert_eq!(
+            console
+                .register(owner, "spawn".to_string(), "spawn enemies".to_string())
+                .unwrap(),
+            id
+        );
+        assert!(matches!(
+            console.register(other, "spawn".to_string(), String::new()),
+            Err(ConsoleError::NameTaken(_))
+        ));
+        assert!(matches!(
+            console.register(other, HELP_COMMAND.to_string(), String::new()),
+            Err(ConsoleError::NameTaken(_))
+        ));
+        for name in ["", "two words"] {
+            assert!(matches!(
+                console.register(other, name.to_string(), String::new()),
+                Err(ConsoleError::InvalidName(_))
+            ));
+        }
+        assert_eq!(console.help(), ["spawn  spawn enemies"]);
+    }
+
+    #[test]
+    fn test_ranked_registration() {
+        let (high, low) = (Path::new("high"), Path::new("low"));
+        let mut console = Console::default();
+        console.set_ranks([high, low]);
+
+        let id = console
+            .register(low, "spawn".to_string(), "low".to_string())
+            .unwrap();
+        let taken = console
+            .register(high, "spawn".to_string(), "high".to_string())
+            .unwrap();
+        assert_ne!(taken, id);
+        assert!(matches!(
+            console.register(low, "spawn".to_string(), String::new()),
+            Err(ConsoleError::NameTaken(_))
+        ));
+        assert_eq!(console.help(), ["spawn  high"]);
+
+        let conflict = CommandConflict {
+            name: "spawn".to_string(),
+            winner: high.to_path_buf(),
+            loser: low.to_path_buf(),
+        };
+        let conflicts = console.take_conflicts();
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts.iter().all(|c| *c == conflict));
+        assert!(console.take_conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_command_limit() {
+        let owner = Path::new("plugin");
+        let mut console = Console::default();
+
+        for index in 0..DEFAULT_COMMAND_LIMIT {
+            console
+                .register(owner, format!("command{}", index), String::new())
+                .unwrap();
+        }
+        assert!(matches!(
+            console.register(owner, "extra".to_string(), String::new()),
+            Err(ConsoleError::CommandLimit(DEFAULT_COMMAND_LIMIT))
+        ));
+        // The limit is per plugin.
+        console
+            .register(Path::new("other"), "extra".to_string(), String::new())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        let owner = Path::new("plugin");
+        let mut console = Console::default();
+        let id = console
+            .register(owner, "give".to_string(), String::new())
+            .unwrap();
+
+        match console.parse("  give  sword 2 ").unwrap() {
+            ConsoleInput::Command(invocation) => {
+                assert_eq!(invocation.owner, owner);
+                assert_eq!(invocation.event.command_id, id);
+                assert_eq!(invocation.event.args_len, 7);
+                assert_eq!(invocation.args, "sword 2");
+            }
+            _ => panic!("expected a command"),
+        }
+        assert!(matches!(console.parse("   "), Ok(ConsoleInput::Empty)));
+        assert!(matches!(console.parse("help"), Ok(ConsoleInput::Help(_))));
+        match console.parse("set my-mod  greeting Hello there").unwrap() {
+            ConsoleInput::Set { plugin, key, value } => {
+                assert_eq!((plugin.as_str(), key.as_str()), ("my-mod", "greeting"));
+                assert_eq!(value, "Hello there");
+            }
+            _ => panic!("expected a setting"),
+        }
+        assert!(matches!(
+            console.parse("set my-mod difficulty"),
+            Err(ConsoleError::Usage(_))
+        ));
+        assert!(matches!(
+            console.parse("locale pt-BR"),
+            Ok(ConsoleInput::Locale(locale)) if locale == "pt-BR"
+        ));
+        assert!(matches!(
+            console.parse("locale"),
+            Err(ConsoleError::Usage(_))
+        ));
+        assert!(matches!(console.parse("watch"), Ok(ConsoleInput::Watch)));
+        assert!(matches!(
+            console.parse("take sword"),
+            Err(ConsoleError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_args_only_while_invoking() {
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut console = Console::default();
+        console
+            .register(owner, "echo".to_string(), String::new())
+            .unwrap();
+
+        let invocation = match console.parse("echo hello").unwrap() {
+            ConsoleInput::Command(invocation) => invocation,
+            _ => panic!("expected a command"),
+        };
+        assert_eq!(console.args(owner), None);
+
+        console.begin_invocation(&invocation);
+        assert_eq!(console.args(owner), Some("hello"));
+        assert_eq!(console.args(other), None);
+        console.end_invocation();
+        assert_eq!(console.args(owner), None);
+    }
+
+    #[test]
+    fn test_view_input() {
+        let mut view = ConsoleView::new();
+        view.handle_char('x');
+        assert!(view.take_submitted().is_empty());
+
+        view.toggle();
+        for c in "`helpo\u{8}\r".chars() {
+            view.handle_char(c);
+        }
+        assert_eq!(view.take_submitted(), ["help"]);
+        assert_eq!(view.scrollback.back().unwrap(), "> help");
+    }
+}
@@ -0,0 +1,297 @@
+//! Crash dumps of plugins that trap.
+//!
+//! The first time a plugin traps, a bundle is written for the plugin's
+//! author to `<crash_dir>/<plugin>-<timestamp>/`, so users can attach
+//! it to their reports:
+//!
+//! - `crash.txt` with the error, the guest's stack trace and panic
+//!   message, the plugin's version and module hash, and the last events
+//!   delivered to it.
+//! - `plugin.toml`, the plugin's meta file.
+//! - `bump.bin`, the region of the guest's bump allocator, when the
+//!   guest exports `__gers_bump_region`.
+//!
+//! Later traps of the same plugin are only logged, so a plugin that
+//! traps every frame doesn't fill the disk.
+use gers_events::EventType;
+use gers_plugins::{Plugin, PLUGIN_FILENAME};
+use slog::{error, warn, Logger};
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use wasmer::RuntimeError;
+
+use crate::{
+    env::GersEnv,
+    error::{print_runtime_error, symbol_lines},
+};
+
+/// Name of the report in a crash dump.
+pub const REPORT_FILENAME: &str = "crash.txt";
+
+/// Name of the copy of the bump allocator region in a crash dump.
+pub const BUMP_FILENAME: &str = "bump.bin";
+
+/// Writes crash dumps, once for each plugin.
+#[derive(Debug, Default)]
+pub struct CrashDumps {
+    /// Directory the dumps are written to, or `None` to not write them.
+    dir: Option<PathBuf>,
+    /// Keys of the plugins a dump was written for.
+    dumped: HashSet<PathBuf>,
+}
+
+impl CrashDumps {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            dumped: HashSet::new(),
+        }
+    }
+
+    /// Write a crash dump for the plugin, unless it already has one.
+    ///
+    /// Returns the directory of the dump that was written.
+    pub fn write(
+        &mut self,
+        plugin: &Plugin,
+        err: &RuntimeError,
+        panic_message: Option<&str>,
+    ) -> io::Result<Option<PathBuf>> {
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return Ok(None),
+        };
+        if !self.dumped.insert(plugin.key().to_path_buf()) {
+            return Ok(None);
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let bundle = dir.join(format!("{}-{}", file_name(&plugin.meta().name), timestamp));
+        fs::create_dir_all(&bundle)?;
+
+        fs::write(
+            bundle.join(REPORT_FILENAME),
+            report(plugin, err, panic_message),
+        )?;
+        if let Ok(meta) = plugin.source().read_file(PLUGIN_FILENAME) {
+            fs::write(bundle.join(PLUGIN_FILENAME), meta)?;
+        }
+        if let Some((_, region)) = plugin.bump_region() {
+            fs::write(bundle.join(BUMP_FILENAME), region)?;
+        }
+
+        Ok(Some(bundle))
+    }
+}
+
+/// Log an error returned by a call into the plugin, and write its
+/// crash dump.
+pub fn report_plugin_error(
+    logger: &Logger,
+    plugin: &Plugin,
+    err: &RuntimeError,
+    gers_env: &GersEnv,
+) {
+    let panic_message = gers_env.take_panic_message();
+    let written = match gers_env.crash_dumps.lock() {
+        Ok(mut crash_dumps) => crash_dumps.write(plugin, err, panic_message.as_deref()),
+        Err(_) => Ok(None),
+    };
+    print_runtime_error(logger, plugin, err, panic_message);
+    // What the plugin logged up to the crash.
+    if let Ok(mut logs) = gers_env.plugin_logs.lock() {
+        let _ = logs.flush(&plugin.meta().name);
+    }
+
+    match written {
+        Ok(Some(bundle)) => error!(
+            logger,
+            "plugin {} crashed, wrote crash dump to {}",
+            plugin.meta().name,
+            bundle.display()
+        ),
+        Ok(None) => {}
+        Err(err) => warn!(
+            logger,
+            "failed writing crash dump of plugin {}: {}",
+            plugin.meta().name,
+            err
+        ),
+    }
+}
+
+/// Plugin name made safe to use as a file name.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Text of the crash report.
+fn report(plugin: &Plugin, err: &RuntimeError, panic_message: Option<&str>) -> String {
+    let meta = plugin.meta();
+    let mut report = String::new();
+
+    // Writing to a `String` can't fail.
+    let _ = writeln!(report, "plugin: {} {}", meta.name, meta.version);
+    let _ = writeln!(report, "root: {}", plugin.root().display());
+    let _ = writeln!(
+        report,
+        "module sha256: {}",
+        plugin.module_hash().unwrap_or("-")
+    );
+    if let Ok(memory) = plugin.memory() {
+        let _ = writeln!(report, "memory pages: {}", memory.size().0);
+    }
+    if let Some((ptr, region)) = plugin.bump_region() {
+        let _ = writeln!(
+            report,
+            "bump region: {} bytes at {:#x}, in {}",
+            region.len(),
+            ptr,
+            BUMP_FILENAME
+        );
+    }
+
+    let _ = writeln!(report, "\nerror: {}", err.message());
+    let frames = err.trace();
+    for (i, frame) in frames.iter().enumerate() {
+        let _ = writeln!(
+            report,
+            "  Frame #{}: {:?}::{:?} at {:#x}",
+            frames.len() - i,
+            frame.module_name(),
+            frame.function_name().unwrap_or("<func>"),
+            frame.module_offset()
+        );
+        for line in symbol_lines(plugin, frame) {
+            let _ = writeln!(report, "{}", line);
+        }
+    }
+    if let Some(panic_message) = panic_message {
+        let _ = writeln!(report, "  Panic: {}", panic_message);
+    }
+
+    let events = plugin.delivered_events();
+    let _ = writeln!(
+        report,
+        "\nlast {} delivered events, oldest first:",
+        events.len()
+    );
+    for event in events {
+        let name = match EventType::from(event.event_type) {
+            EventType::NoOp => "unknown".to_string(),
+            event_type => format!("{:?}", event_type),
+        };
+        let _ = write!(report, "  {} ({}):", name, event.event_type);
+        for byte in event.data {
+            let _ = write!(report, " {:02x}", byte);
+        }
+        report.push('\n');
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gers_events::HelloEvent;
+    use gers_plugins::Plugins;
+    use std::path::Path;
+
+    fn bundles(dir: &Path) -> Vec<PathBuf> {
+        let mut bundles: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        bundles.sort();
+        bundles
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(file_name("my-plugin_2"), "my-plugin_2");
+        assert_eq!(file_name("../evil plugin"), "___evil_plugin");
+    }
+
+    #[test]
+    fn test_crash_dump_once_per_plugin() {
+        let root = std::env::temp_dir().join(format!("gers-crash-{}", std::process::id()));
+        let plugin_dir = root.join("crashing");
+        let crash_dir = root.join("crashes");
+        fs::create_dir_all(&plugin_dir).unwrap();
+        fs::write(
+            plugin_dir.join(PLUGIN_FILENAME),
+            "name = \"crashing\"\nversion = \"1.0.0\"\n",
+        )
+        .unwrap();
+        let wasm = wasmer::wat2wasm(
+            br#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 512) "bump")
+            (func (export "__gers_event_alloc") (param i32) (result i32) (i32.const 256))
+            (func (export "__gers_event_update") (param i32 i32) (result i32) unreachable)
+            (func (export "__gers_bump_region") (result i64) (i64.const 0x4_0000_0200)))"#,
+        )
+        .unwrap();
+        fs::write(plugin_dir.join("main.wasm"), wasm).unwrap();
+
+        let mut plugins = Plugins::builder().event_history(4).build();
+        plugins.load_plugin_dir(&plugin_dir).unwrap();
+        let plugin = plugins.iter_plugins_mut().next().unwrap();
+        plugin.init().unwrap();
+        let hello = HelloEvent {
+            data: 7,
+            padding: 0,
+            div: 1,
+        };
+        let err = plugin
+            .dispatch_event(EventType::Hello as i32, &hello)
+            .unwrap_err();
+
+        let mut crash_dumps = CrashDumps::new(Some(crash_dir.clone()));
+        let bundle = crash_dumps
+            .write(plugin, &err, Some("oh no"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(bundles(&crash_dir), std::slice::from_ref(&bundle));
+        assert!(bundle
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("crashing-"));
+
+        let report = fs::read_to_string(bundle.join(REPORT_FILENAME)).unwrap();
+        assert!(report.contains("plugin: crashing 1.0.0"), "{}", report);
+        assert!(report.contains(plugin.module_hash().unwrap()), "{}", report);
+        assert!(report.contains("Panic: oh no"), "{}", report);
+        assert!(report.contains("Hello (1): 07 00 00 00"), "{}", report);
+        assert_eq!(fs::read(bundle.join(BUMP_FILENAME)).unwrap(), b"bump");
+        assert!(bundle.join(PLUGIN_FILENAME).exists());
+
+        // Only the first crash of a plugin is dumped.
+        assert!(crash_dumps.write(plugin, &err, None).unwrap().is_none());
+        assert_eq!(bundles(&crash_dir).len(), 1);
+
+        // Without a directory, nothing is written.
+        let mut crash_dumps = CrashDumps::default();
+        assert!(crash_dumps.write(plugin, &err, None).unwrap().is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
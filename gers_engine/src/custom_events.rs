@@ -0,0 +1,319 @@
+//! Custom event types defined by plugins.
+//!
+//! Plugins list the custom event types they define and handle in their
+//! event manifest. The types are numbered in priority order once all
+//! plugins are loaded, so a type defined by more than one plugin
+//! belongs to the one with the highest priority. Any plugin can
+//! publish a defined type by name. Published events are sent as a `CustomEvent` to the plugins
+//! whose manifest handles the type, in the same order as other events.
+//!
+//! Plugins can also emit events under names no manifest defines, which
+//! are registered when they're first emitted or subscribed to. Such
+//! events go to the plugins that subscribed to the name at runtime,
+//! so mods can react to each other without declaring anything up front.
+use gers_events::{CustomEvent, CUSTOM_EVENT_DATA_SIZE};
+use gers_plugins::EventManifest;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+pub type CustomTypeId = u32;
+
+#[derive(Default)]
+pub struct CustomEvents {
+    /// Indexed by type id minus one.
+    types: Vec<CustomType>,
+    /// Type ids by name.
+    ids: HashMap<String, CustomTypeId>,
+}
+
+struct CustomType {
+    /// Key of the plugin that defined the type, or `None`
+    /// for types registered by emitting or subscribing to them.
+    owner: Option<PathBuf>,
+    /// Keys of the plugins that handle the type.
+    /// Plugins subscribing at runtime are added to the manifest's.
+    handlers: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum CustomEventError {
+    /// Another plugin defined the type first.
+    AlreadyDefined { name: String, owner: PathBuf },
+    /// No plugin defines the type.
+    Undefined(String),
+    /// Data larger than a custom event can hold.
+    TooLarge(usize),
+    /// Names must be non-empty and can't contain whitespace.
+    InvalidName(String),
+}
+
+impl fmt::Display for CustomEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomEventError::AlreadyDefined { name, owner } => write!(
+                f,
+                "custom event {} is already defined by {}",
+                name,
+                owner.display()
+            ),
+            CustomEventError::Undefined(name) => write!(f, "undefined custom event {}", name),
+            CustomEventError::TooLarge(len) => write!(
+                f,
+                "custom event data is {} bytes, over {}",
+                len, CUSTOM_EVENT_DATA_SIZE
+            ),
+            CustomEventError::InvalidName(name) => {
+                write!(f, "invalid custom event name {:?}", name)
+            }
+        }
+    }
+}
+
+impl CustomEvents {
+    /// Dispatch table for the manifests of the loaded plugins, given
+    /// with their keys in priority order.
+    ///
+    /// Returns the errors of types that are defined more than once, or
+    /// handled without being defined, with the plugin they're from.
+    pub fn from_manifests(
+        manifests: &[(&Path, &EventManifest)],
+    ) -> (Self, Vec<(PathBuf, CustomEventError)>) {
+        let mut custom_events = Self::default();
+        let mut errors = vec![];
+
+        // Types are defined before any handler is resolved, so plugins
+        // can handle types of plugins loaded after them.
+        for &(plugin, manifest) in manifests {
+            for name in manifest.defines.iter() {
+                if let Some(&id) = custom_events.ids.get(name) {
+                    let owner = custom_events.types[id as usize - 1]
+                        .owner
+                        .clone()
+                        .unwrap_or_default();
+                    errors.push((
+                        plugin.to_path_buf(),
+                        CustomEventError::AlreadyDefined {
+                            name: name.clone(),
+                            owner,
+                        },
+                    ));
+                    continue;
+                }
+
+                custom_events.insert(name, Some(plugin.to_path_buf()));
+            }
+        }
+
+        for &(plugin, manifest) in manifests {
+            for name in manifest.handles.iter() {
+                match custom_events.ids.get(name) {
+                    Some(&id) => custom_events.types[id as usize - 1]
+                        .handlers
+                        .push(plugin.to_path_buf()),
+                    None => errors.push((
+                        plugin.to_path_buf(),
+                        CustomEventError::Undefined(name.clone()),
+                    )),
+                }
+            }
+        }
+
+        (custom_events, errors)
+    }
+
+    /// Id of the custom event type with the given name.
+    pub fn id(&self, name: &str) -> Option<CustomTypeId> {
+        self.ids.get(name).copied()
+    }
+
+    /// The plugin `owner` handles the custom event type.
+    pub fn is_handled_by(&self, id: CustomTypeId, owner: &Path) -> bool {
+        self.get(id)
+            .is_some_and(|custom_type| custom_type.handlers.iter().any(|handler| handler == owner))
+    }
+
+    /// Stop sending events of any type to the plugin `owner`.
+    ///
+    /// The types it defined stay defined, so their ids don't change.
+    /// Returns the number of types it no longer handles.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let mut count = 0;
+        for custom_type in self.types.iter_mut() {
+            let handlers = custom_type.handlers.len();
+            custom_type.handlers.retain(|handler| handler != owner);
+            count += handlers - custom_type.handlers.len();
+        }
+
+        count
+    }
+
+    /// Custom event of the type with the given name, holding `data`.
+    pub fn event(&self, name: &str, data: &[u8]) -> Result<CustomEvent, CustomEventError> {
+        let id = self
+            .id(name)
+            .ok_or_else(|| CustomEventError::Undefined(name.to_string()))?;
+
+        CustomEvent::new(id, data).ok_or(CustomEventError::TooLarge(data.len()))
+    }
+
+    /// Id of the type with the given name, registering it when no
+    /// plugin defined it.
+    pub fn register(&mut self, name: &str) -> Result<CustomTypeId, CustomEventError> {
+        if let Some(id) = self.id(name) {
+            return Ok(id);
+        }
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(CustomEventError::InvalidName(name.to_string()));
+        }
+
+        Ok(self.insert(name, None))
+    }
+
+    /// Send events of the type with the given name to the plugin
+    /// `owner`, registering the type when no plugin defined it.
+    pub fn subscribe(
+        &mut self,
+        name: &str,
+        owner: &Path,
+    ) -> Result<CustomTypeId, CustomEventError> {
+        let id = self.register(name)?;
+        let handlers = &mut self.types[id as usize - 1].handlers;
+        if !handlers.iter().any(|handler| handler == owner) {
+            handlers.push(owner.to_path_buf());
+        }
+
+        Ok(id)
+    }
+
+    /// Custom event of the type with the given name holding `data`,
+    /// registering the type when no plugin defined it.
+    pub fn emit(&mut self, name: &str, data: &[u8]) -> Result<CustomEvent, CustomEventError> {
+        if data.len() > CUSTOM_EVENT_DATA_SIZE {
+            return Err(CustomEventError::TooLarge(data.len()));
+        }
+        let id = self.register(name)?;
+
+        CustomEvent::new(id, data).ok_or(CustomEventError::TooLarge(data.len()))
+    }
+
+    /// Number of custom event types.
+    pub fn count(&self) -> usize {
+        self.types.len()
+    }
+
+    fn insert(&mut self, name: &str, owner: Option<PathBuf>) -> CustomTypeId {
+        self.types.push(CustomType {
+            owner,
+            handlers: vec![],
+        });
+        let id = self.types.len() as CustomTypeId;
+        self.ids.insert(name.to_string(), id);
+
+        id
+    }
+
+    fn get(&self, id: CustomTypeId) -> Option<&CustomType> {
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|index| self.types.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(defines: &[&str], handles: &[&str]) -> EventManifest {
+        EventManifest {
+            defines: defines.iter().map(|name| name.to_string()).collect(),
+            handles: handles.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_table() {
+        let (score, hud, rival) = (Path::new("score"), Path::new("hud"), Path::new("rival"));
+        // The handler is loaded before the plugin defining the type.
+        let manifests = [
+            (hud, manifest(&[], &["score_changed", "level_loaded"])),
+            (score, manifest(&["score_changed"], &[])),
+            (rival, manifest(&["score_changed"], &["score_changed"])),
+        ];
+
+        let manifests: Vec<_> = manifests
+            .iter()
+            .map(|(root, manifest)| (*root, manifest))
+            .collect();
+        let (custom_events, errors) = CustomEvents::from_manifests(&manifests);
+        assert_eq!(custom_events.count(), 1);
+        assert!(matches!(
+            errors[..],
+            [
+                (_, CustomEventError::AlreadyDefined { .. }),
+                (_, CustomEventError::Undefined(_))
+            ]
+        ));
+
+        let id = custom_events.id("score_changed").unwrap();
+        assert_eq!(id, 1);
+        assert!(custom_events.is_handled_by(id, hud));
+        assert!(custom_events.is_handled_by(id, rival));
+        assert!(!custom_events.is_handled_by(id, score));
+        assert!(!custom_events.is_handled_by(0, hud));
+    }
+
+    #[test]
+    fn test_publish_by_name() {
+        let defines = manifest(&["ping"], &[]);
+        let (custom_events, _) = CustomEvents::from_manifests(&[(Path::new("a"), &defines)]);
+
+        let event = custom_events.event("ping", b"hello").unwrap();
+        assert_eq!(event.type_id, 1);
+        assert_eq!(event.data(), b"hello");
+
+        assert!(matches!(
+            custom_events.event("pong", &[]),
+            Err(CustomEventError::Undefined(_))
+        ));
+        assert!(matches!(
+            custom_events.event("ping", &[0; CUSTOM_EVENT_DATA_SIZE + 1]),
+            Err(CustomEventError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_emit_registers_type() {
+        let (combat, quests) = (Path::new("combat"), Path::new("quests"));
+        let defines = manifest(&["ping"], &[]);
+        let (mut custom_events, _) = CustomEvents::from_manifests(&[(combat, &defines)]);
+
+        // Subscribing before anything is emitted registers the name.
+        assert_eq!(custom_events.subscribe("boss_killed", quests).unwrap(), 2);
+        assert_eq!(custom_events.subscribe("boss_killed", quests).unwrap(), 2);
+
+        let event = custom_events
+            .emit("boss_killed", &7u32.to_le_bytes())
+            .unwrap();
+        assert_eq!(event.type_id, 2);
+        assert!(custom_events.is_handled_by(event.type_id, quests));
+        assert!(!custom_events.is_handled_by(event.type_id, combat));
+
+        // Types defined in manifests can be subscribed to and emitted too.
+        assert_eq!(custom_events.subscribe("ping", quests).unwrap(), 1);
+        assert_eq!(custom_events.emit("ping", &[]).unwrap().type_id, 1);
+
+        assert_eq!(custom_events.emit("loot_dropped", &[]).unwrap().type_id, 3);
+        assert!(matches!(
+            custom_events.emit("boss killed", &[]),
+            Err(CustomEventError::InvalidName(_))
+        ));
+        assert!(matches!(
+            custom_events.emit("huge", &[0; CUSTOM_EVENT_DATA_SIZE + 1]),
+            Err(CustomEventError::TooLarge(_))
+        ));
+        assert_eq!(custom_events.count(), 3);
+    }
+}
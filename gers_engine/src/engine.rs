@@ -0,0 +1,2505 @@
+//! Event loop running the plugins.
+//!
+//! The engine is configured with an `EngineBuilder`, from the engine
+//! settings and the options the `gers` executable takes on its command
+//! line. Games embedding the engine run their own code around the
+//! plugins' in callbacks, which are given the loaded plugins every
+//! frame:
+//!
+//! - `pre_update`, before the plugins are updated.
+//! - `post_update`, after the frame's events were dispatched.
+//! - `render`, after the plugins rendered, drawing over them.
+use anyhow::{anyhow, Context as _};
+use gers_events::{
+    EncodedEvent, EventType, FrameGlobals, LocaleChangedEvent, MutableEvent, ScreenshotTakenEvent,
+    SettingsChangedEvent, ShutdownRequestedEvent, SimulationState, EVENT_SOURCE_HOST,
+};
+use gers_plugins::{
+    Plugin, PluginError, PluginSource, Plugins, Resource, SettingValue, Settings, ARCHIVE_EXTENSION,
+};
+use slog::{error, info, warn, Drain};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, KeyboardInput, VirtualKeyCode},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use crate::{
+    audio,
+    clock::{self, Clock, SystemClock},
+    config::Config,
+    console::{self, ConsoleInput, ConsoleView},
+    crash::{report_plugin_error, CrashDumps},
+    custom_events::{CustomEventError, CustomEvents},
+    env::{GersEnv, Timing},
+    event_queue::{self, EventQueues, QueueError, QueuedEvent},
+    fps::{self, FpsCounter, FpsThrottle, FpsThrottlePolicy},
+    gamepad::{GamepadEvent, Gamepads},
+    i18n::Localization,
+    input,
+    lag::TickBudget,
+    metrics::{MetricsExporter, MetricsServer, MetricsSnapshot, PluginMetrics},
+    mod_list::{ModEntry, ModListOverlay},
+    mouse::Mice,
+    overlay::{DebugOverlay, OverlayStats},
+    pause::PauseState,
+    plugin_log::PluginLogs,
+    profiler::{CallKind, Profiler},
+    render::{Canvas, Color, Renderer},
+    replay::{FrameEvent, RecordedFrame, Recorder, Replay},
+    resources,
+    savegame::{self, SaveError, SaveGame},
+    scheduler::WorkScheduler,
+    screenshot,
+    simulation::SimulationControl,
+    storage, trace, wasm_api,
+    watch::PluginWatches,
+    window::{self, WindowRegistry},
+};
+
+type Callback = Box<dyn FnMut(&mut Context)>;
+type RenderCallback = Box<dyn FnMut(&mut Context, &mut Canvas)>;
+
+/// Engine state given to the callbacks of the embedding game.
+pub struct Context<'a> {
+    pub plugins: &'a mut Plugins,
+    pub logger: &'a slog::Logger,
+    /// Frame's delta time, scaled by the time scale.
+    pub delta_time: Duration,
+    /// Number of the frame, counting from one.
+    pub frame_index: u64,
+    /// Number of fixed updates before this frame.
+    pub tick_index: u64,
+    /// Plugin updates are paused, while the window is in the background.
+    pub paused: bool,
+    /// Pausing and stepping the simulation while debugging, which is
+    /// ignored while a recording is replayed.
+    pub simulation: &'a mut SimulationControl,
+}
+
+#[derive(Default)]
+struct Callbacks {
+    pre_update: Option<Callback>,
+    post_update: Option<Callback>,
+    render: Option<RenderCallback>,
+}
+
+/// Options given on the command line of the `gers` executable.
+#[derive(Debug, Default)]
+struct Options {
+    plugin_paths: Vec<PathBuf>,
+    disabled_plugins: Vec<String>,
+    headless: bool,
+    seed: Option<u64>,
+    profile: bool,
+    rewind: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    trace: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub struct EngineBuilder {
+    config: Config,
+    options: Options,
+    callbacks: Callbacks,
+    clock: Option<Rc<dyn Clock>>,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Engine settings, usually loaded from `gers.toml`.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Directories searched for plugins, replacing the configured
+    /// search paths unless empty.
+    pub fn with_plugins<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.options.plugin_paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skip loading the plugins with these names.
+    pub fn disable_plugins(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.options.disabled_plugins.extend(names);
+        self
+    }
+
+    /// Run the simulation without showing the window or rendering,
+    /// reading console commands from stdin.
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.options.headless = headless;
+        self
+    }
+
+    /// Seed provided to plugins, or `None` to take one from the clock.
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    /// Periodically log the plugin timing table.
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.options.profile = profile;
+        self
+    }
+
+    /// Keep snapshots of plugin memory, to rewind the last few frames
+    /// with F6.
+    pub fn rewind(mut self, rewind: bool) -> Self {
+        self.options.rewind = rewind;
+        self
+    }
+
+    /// Record the events sent to plugins into a file.
+    pub fn record(mut self, path: Option<PathBuf>) -> Self {
+        self.options.record = path;
+        self
+    }
+
+    /// Play back a recording, in place of live input and frame timing.
+    pub fn replay(mut self, path: Option<PathBuf>) -> Self {
+        self.options.replay = path;
+        self
+    }
+
+    /// Write a trace of plugin calls into a file, in the format of
+    /// chrome://tracing.
+    pub fn trace(mut self, path: Option<PathBuf>) -> Self {
+        self.options.trace = path;
+        self
+    }
+
+    /// Called every frame before the plugins are updated, even while
+    /// their updates are paused.
+    pub fn pre_update(mut self, callback: impl FnMut(&mut Context) + 'static) -> Self {
+        self.callbacks.pre_update = Some(Box::new(callback));
+        self
+    }
+
+    /// Called every frame after the plugins handled the frame's events.
+    pub fn post_update(mut self, callback: impl FnMut(&mut Context) + 'static) -> Self {
+        self.callbacks.post_update = Some(Box::new(callback));
+        self
+    }
+
+    /// Called when a frame is rendered, after the plugins rendered and
+    /// before the debug overlay is drawn. Not called when headless.
+    pub fn render(mut self, callback: impl FnMut(&mut Context, &mut Canvas) + 'static) -> Self {
+        self.callbacks.render = Some(Box::new(callback));
+        self
+    }
+
+    /// Time source of the frame loop, which is the system clock by
+    /// default. Headless runs and tests can drive time with a
+    /// `TestClock`, which passes as the frame loop waits for the next
+    /// frame, rather than in real time.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Rc::new(clock));
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<Engine> {
+        let mut config = self.config;
+        if !self.options.plugin_paths.is_empty() {
+            config.plugins.paths = self.options.plugin_paths.clone();
+        }
+        config.validate().context("invalid config")?;
+
+        Ok(Engine {
+            config,
+            options: self.options,
+            callbacks: self.callbacks,
+            clock: self.clock.unwrap_or_else(|| Rc::new(SystemClock)),
+        })
+    }
+
+    /// Build the engine and run it.
+    pub fn run(self) -> anyhow::Result<()> {
+        self.build()?.run()
+    }
+}
+
+pub struct Engine {
+    config: Config,
+    options: Options,
+    callbacks: Callbacks,
+    clock: Rc<dyn Clock>,
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    /// Load the plugins, open the window, and run the event loop.
+    ///
+    /// Only returns when the engine fails to start. The process exits
+    /// when the window is closed.
+    pub fn run(self) -> anyhow::Result<()> {
+        let Engine {
+            config,
+            options,
+            mut callbacks,
+            clock,
+        } = self;
+
+        // Prints the plugin timing table periodically.
+        let profile = options.profile;
+
+        let log_level = config.log.level().expect("log level validated by config");
+
+        // Logger
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = drain.filter_level(log_level).fuse();
+        let (drain, log_guard) = slog_async::Async::new(drain)
+            .chan_size(1024 * 8)
+            .build_with_guard();
+        // Records logged after the guard flushed the log on shutdown are dropped.
+        let drain = drain.ignore_res();
+        let root = slog::Logger::root(drain, slog::o!());
+        let logger = root.new(slog::o!("lang" => "Rust"));
+
+        let _scope_guard = slog_scope::set_global_logger(logger.clone());
+        slog_stdlog::init_with_level(log::Level::Warn).unwrap();
+
+        // Trace
+        let trace_guard = match options.trace {
+            Some(ref path) => match trace::write_chrome_trace(path) {
+                Ok(guard) => {
+                    info!(logger, "Writing trace to {}", path.display());
+                    Some(guard)
+                }
+                Err(err) => {
+                    error!(
+                        logger,
+                        "failed to write trace to {}: {}",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Replay
+        let mut replay = options
+            .replay
+            .as_ref()
+            .map(Replay::open)
+            .transpose()
+            .context("opening replay")?;
+
+        let seed = match replay.as_ref() {
+            Some(replay) => {
+                if options.seed.is_some() {
+                    warn!(logger, "Ignoring the seed, using the seed of the replay");
+                }
+                replay.seed()
+            }
+            None if config.plugins.deterministic && options.seed.is_none() => {
+                // Peers can't agree on a seed taken from the clock.
+                return Err(anyhow!("deterministic plugins need a seed"));
+            }
+            None => options.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_nanos() as u64)
+                    .unwrap_or_default()
+            }),
+        };
+        info!(logger, "Seed: {}", seed);
+
+        let mut recorder = options
+            .record
+            .as_ref()
+            .map(|path| Recorder::create(path, seed))
+            .transpose()
+            .context("creating recording")?;
+
+        // Audio output must outlive the environment.
+        let audio_device = audio::Device::open(&logger);
+
+        // Wasmer Environment
+        let gers_env = GersEnv {
+            logger: root.new(slog::o!("lang" => "Wasm")),
+            timing: Arc::new(RwLock::new(Timing {
+                time_scale: config.frame.time_scale,
+                ..Default::default()
+            })),
+            seed,
+            panic_message: Default::default(),
+            draw_queue: Default::default(),
+            textures: Default::default(),
+            audio: Arc::new(Mutex::new(audio::Audio::new(&audio_device))),
+            input: Default::default(),
+            world: Default::default(),
+            network: Default::default(),
+            http: Default::default(),
+            timers: Default::default(),
+            tasks: Default::default(),
+            hooks: Default::default(),
+            emitted_events: Default::default(),
+            custom_events: Default::default(),
+            save_buffer: Default::default(),
+            windows: Default::default(),
+            clipboard: Default::default(),
+            console: Default::default(),
+            settings: Default::default(),
+            i18n: Default::default(),
+            crash_dumps: Arc::new(Mutex::new(CrashDumps::new(
+                config.plugins.crash_dir().map(Path::to_path_buf),
+            ))),
+            plugin_logs: Arc::new(Mutex::new(PluginLogs::new(
+                config.log.plugin_dir().map(Path::to_path_buf),
+                config.log.rotate_size(),
+                config.log.keep_rotated,
+            ))),
+            watches: Default::default(),
+            plugin: Default::default(),
+            memory: Default::default(),
+        };
+
+        // Plugin Infrastructure
+        let mut plugins = Plugins::builder()
+            .deterministic(config.plugins.deterministic)
+            .watchdog_timeout(config.plugins.watchdog_timeout())
+            .event_history(config.plugins.crash_event_history)
+            .build();
+        if plugins.is_deterministic() {
+            info!(logger, "Plugins are compiled deterministically");
+        }
+        if let Some(timeout) = plugins.watchdog_timeout() {
+            info!(logger, "Plugin calls are interrupted after {:?}", timeout);
+        }
+        for name in options.disabled_plugins.iter() {
+            plugins.disable(name.clone());
+        }
+        plugins.set_trust_policy(config.plugins.trust);
+        plugins.set_capabilities(wasm_api::capabilities());
+        plugins.set_missing_imports(config.plugins.missing_imports);
+        for key in config.plugins.trusted_keys.iter() {
+            let key = gers_plugins::parse_public_key(key).expect("keys validated by config");
+            plugins.add_trusted_key(key);
+        }
+
+        // WebAssembly API
+        wasm_api::register_imports(plugins.imports_mut(), &gers_env)
+            .context("registering host imports")?;
+
+        // Walk plugin search paths and load
+        let current_dir = std::env::current_dir().expect("getting current working directory");
+        let enabled_file = current_dir.join(&config.plugins.enabled_file);
+        if let Err(err) = plugins.load_enabled_list(&enabled_file) {
+            error!(
+                logger,
+                "failed loading enabled plugin list {:?}: {}", enabled_file, err
+            );
+        }
+        let settings_file = current_dir.join(&config.plugins.settings_file);
+        match Settings::load(&settings_file) {
+            Ok(settings) => {
+                if let Ok(mut lock) = gers_env.settings.write() {
+                    *lock = settings;
+                }
+            }
+            Err(err) => error!(
+                logger,
+                "failed loading plugin settings {:?}: {}", settings_file, err
+            ),
+        }
+        let mut searched_all = true;
+        let mut sources = vec![];
+        for search_path in config.plugins.paths.iter() {
+            let search_path = current_dir.join(search_path);
+            info!(logger, "Loading plugins from directory: {:?}", search_path);
+
+            let entries = match std::fs::read_dir(&search_path) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    error!(
+                        logger,
+                        "failed reading plugin directory {:?}: {}", search_path, err
+                    );
+                    searched_all = false;
+                    continue;
+                }
+            };
+
+            // Mod packs made on case-insensitive file systems may name
+            // their files differently.
+            let is_archive = |path: &std::path::Path| {
+                path.is_file()
+                    && path
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case(ARCHIVE_EXTENSION))
+            };
+            let mut plugin_paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    gers_plugins::find_file(path, gers_plugins::PLUGIN_FILENAME)
+                        .is_some_and(|meta_path| meta_path.is_file())
+                        || is_archive(path)
+                })
+                .collect();
+            // Load order shouldn't depend on the file system.
+            plugin_paths.sort();
+
+            sources.extend(plugin_paths.into_iter().map(|plugin_path| {
+                if is_archive(&plugin_path) {
+                    PluginSource::Archive(plugin_path)
+                } else {
+                    PluginSource::Directory(plugin_path)
+                }
+            }));
+        }
+
+        // Libraries are loaded before the plugins importing from them.
+        let report = plugins.load_all(sources);
+        for err in &report.errors {
+            match err.inner() {
+                PluginError::Disabled(name) => {
+                    info!(logger, "Skipping disabled plugin {}", name);
+                }
+                // Names the plugin and where it was loaded from.
+                _ => error!(logger, "failed loading {}", err),
+            }
+        }
+
+        // Plugins missing from the search paths were uninstalled, unless
+        // some weren't searched, so their data is removed with them.
+        let data_root = Path::new(storage::DATA_DIR);
+        if searched_all && options.plugin_paths.is_empty() {
+            match plugins.prune_enabled_list() {
+                Ok(removed) => {
+                    for name in removed {
+                        match storage::remove_data_dir(data_root, &name) {
+                            Ok(true) => {
+                                info!(logger, "Removed data of uninstalled plugin {}", name)
+                            }
+                            Ok(false) => {}
+                            Err(err) => error!(
+                                logger,
+                                "failed removing data of uninstalled plugin {}: {}", name, err
+                            ),
+                        }
+                    }
+                }
+                Err(err) => error!(
+                    logger,
+                    "failed saving enabled plugin list {:?}: {}", enabled_file, err
+                ),
+            }
+        } else if let Err(err) = plugins.save_enabled_list() {
+            error!(
+                logger,
+                "failed saving enabled plugin list {:?}: {}", enabled_file, err
+            );
+        }
+
+        // Slow or misconfigured plugins stand out in the report.
+        for plugin in &report.plugins {
+            info!(logger, "Loaded plugin {}", plugin);
+            for warning in &plugin.warnings {
+                warn!(logger, "Plugin {} {}", plugin.name, warning);
+            }
+        }
+        info!(logger, "Plugin startup: {}", report);
+
+        for plugin in plugins.iter_plugins() {
+            let permissions = &plugin.meta().permissions;
+            if permissions.storage {
+                let created = storage::plugin_data_dir(data_root, &plugin.meta().name)
+                    .map(std::fs::create_dir_all);
+                if let Some(Err(err)) = created {
+                    error!(
+                        logger,
+                        "failed creating data directory of plugin {}: {}",
+                        plugin.meta().name,
+                        err
+                    );
+                }
+            }
+        }
+
+        // Resources claimed by more than one plugin go to the plugin
+        // with the highest priority.
+        for conflict in plugins.detect_conflicts() {
+            warn!(logger, "Conflict over {}", conflict);
+        }
+
+        if let Ok(mut console) = gers_env.console.lock() {
+            console.set_ranks(plugins.priority_order().into_iter().map(Plugin::key));
+        }
+
+        // Custom event types declared in the plugins' event manifests.
+        let manifests: Vec<_> = plugins
+            .priority_order()
+            .into_iter()
+            .map(|plugin| (plugin.key(), plugin.event_manifest()))
+            .collect();
+        let (custom_events, errors) = CustomEvents::from_manifests(&manifests);
+        for (key, err) in errors {
+            // Reported with the conflicts.
+            if !matches!(err, CustomEventError::AlreadyDefined { .. }) {
+                error!(logger, "plugin at {}: {}", key.display(), err);
+            }
+        }
+        if custom_events.count() > 0 {
+            info!(
+                logger,
+                "Defined {} custom event types",
+                custom_events.count()
+            );
+        }
+        if let Ok(mut lock) = gers_env.custom_events.write() {
+            *lock = custom_events;
+        }
+
+        // String tables of the plugins, in the configured locale.
+        let mut i18n = Localization::new(&config.i18n.locale, &config.i18n.fallback)
+            .expect("locales validated by config");
+        load_string_tables(&plugins, &mut i18n, &logger);
+        info!(logger, "Locale: {}", i18n.locale());
+        if let Ok(mut lock) = gers_env.i18n.write() {
+            *lock = i18n;
+        }
+
+        // Actions declared in plugin meta files.
+        if let Ok(mut action_map) = gers_env.input.lock() {
+            for plugin in plugins.iter_plugins() {
+                for (name, key_names) in plugin.meta().input.iter() {
+                    let mut keys = vec![];
+                    for key_name in key_names {
+                        let resource = Resource::Key(key_name.clone());
+                        if plugins.has_lost(&plugin.meta().name, &resource) {
+                            continue;
+                        }
+                        match input::parse_key(key_name) {
+                            Some(key) => keys.push(key),
+                            None => error!(
+                                logger,
+                                "plugin {} binds unknown key {:?} to action {}",
+                                plugin.meta().name,
+                                key_name,
+                                name
+                            ),
+                        }
+                    }
+                    action_map.register(plugin.key(), name, &keys);
+                }
+            }
+        }
+
+        // Frame Timing
+        let mut fps_throttle = FpsThrottle::new(
+            config.frame.target_fps,
+            config.frame.throttle,
+            clock.clone(),
+        );
+        let mut fps_counter = FpsCounter::new();
+        let mut last_time = clock.now();
+        let max_delta_time = config.frame.max_delta_time();
+        let warn_frame_time = config.frame.warn_frame_time();
+        let lockstep_interval = config.frame.fixed_interval(); // seconds
+        let mut lockstep_timer = Duration::ZERO;
+        let mut tick_budget = TickBudget::new(
+            lockstep_interval,
+            config.frame.dispatch_budget(),
+            config.frame.lag_policy,
+            clock.clone(),
+        );
+        let mut was_lagging = false;
+        let mut work_scheduler = WorkScheduler::new(config.frame.work_budget(), clock.clone());
+        let mut hello_counter: u32 = 0;
+        let mut frame = RecordedFrame::default();
+        let mut frame_index: u64 = 0;
+        // Fixed updates run before the current frame, and in it.
+        let mut tick_index: u64 = 0;
+        let mut frame_ticks: u32 = 0;
+
+        // Pausing while the window is in the background.
+        let mut pause_state = PauseState::new(clock.clone());
+        let mut paused = false;
+        let pause_updates = config.frame.pause_updates;
+        let accumulate_while_paused = config.frame.accumulate_while_paused;
+
+        // Pausing and stepping the simulation while debugging.
+        let mut simulation = SimulationControl::default();
+        let mut simulation_paused = false;
+        let mut stepping = false;
+
+        // Windows opened by plugins, and their events since the last frame.
+        let mut window_registry = WindowRegistry::default();
+        let mut window_events = vec![];
+
+        // Screenshots taken with F12, and those saved since the last frame.
+        let mut screenshot_requested = false;
+        let mut screenshots_taken: Vec<ScreenshotTakenEvent> = vec![];
+
+        // Plugins whose resources were released after they faulted.
+        let mut released_plugins: HashSet<PathBuf> = HashSet::new();
+
+        // Heap compaction of plugins that support it.
+        const MEMORY_PRESSURE_GROWTH: u32 = 16; // pages
+        const COMPACTION_MIN_IDLE: Duration = Duration::from_millis(2);
+        // Index of the plugin to try compacting first, so each plugin under
+        // pressure gets its turn.
+        let mut next_compaction = 0;
+
+        // Rewinding plugin state.
+        const REWIND_FRAMES: usize = 30;
+        let rewind = options.rewind;
+        let mut snapshots = VecDeque::with_capacity(REWIND_FRAMES);
+
+        // Profiling
+        const PROFILER_WINDOW: u32 = 300; // frames
+        let mut profiler = Profiler::new(PROFILER_WINDOW);
+
+        let event_loop = EventLoop::new();
+        let window_title = config.window.title.clone();
+        let window = WindowBuilder::new()
+            .with_title(format!("{} - 0 FPS 0.00ms", window_title))
+            .with_inner_size(LogicalSize::new(config.window.width, config.window.height))
+            .with_visible(!options.headless)
+            .build(&event_loop)
+            .unwrap();
+
+        // Rendering is optional, so the app can keep running
+        // the simulation when no graphics adapter is available.
+        let mut renderer = if options.headless {
+            info!(logger, "Running headless, rendering disabled");
+            None
+        } else {
+            match Renderer::new(&window, config.window.vsync) {
+                Ok(renderer) => Some(renderer),
+                Err(err) => {
+                    error!(logger, "failed to create renderer: {}", err);
+                    None
+                }
+            }
+        };
+
+        // Pacing frames by presenting them needs vsync, and something to present.
+        if let Some(refresh_rate) = fps::refresh_rate(&window) {
+            info!(logger, "Display refresh rate: {}Hz", refresh_rate);
+            fps_throttle.set_refresh_rate(refresh_rate);
+        }
+        if fps_throttle.policy() == FpsThrottlePolicy::Present
+            && (renderer.is_none() || !config.window.vsync)
+        {
+            warn!(
+                logger,
+                "Present throttling requires vsync and a renderer, yielding instead"
+            );
+            fps_throttle.set_policy(FpsThrottlePolicy::Yield);
+        }
+        let target_fps = config.frame.target_fps;
+        let background_fps = config.frame.background_fps;
+
+        // Health metrics for monitoring servers.
+        let start_time = Instant::now();
+        let mut events_dispatched: u64 = 0;
+        let mut metrics_exporter = if config.metrics.is_enabled() {
+            let server = if config.metrics.listen.is_empty() {
+                None
+            } else {
+                match MetricsServer::bind(&config.metrics.listen) {
+                    Ok(server) => {
+                        info!(
+                            logger,
+                            "Serving metrics on http://{}/metrics",
+                            server.local_addr()
+                        );
+                        Some(server)
+                    }
+                    Err(err) => {
+                        error!(
+                            logger,
+                            "failed to serve metrics on {}: {}", config.metrics.listen, err
+                        );
+                        None
+                    }
+                }
+            };
+            Some(MetricsExporter::new(
+                config.metrics.interval(),
+                server,
+                config.metrics.json_path().map(Path::to_path_buf),
+            ))
+        } else {
+            None
+        };
+
+        // Memory usage of plugins, logged periodically or with F8.
+        let memory_report_interval = config.plugins.memory_report_interval();
+        let mut last_memory_report = Instant::now();
+
+        let mut debug_overlay = DebugOverlay::new();
+        let mut mod_list = ModListOverlay::new();
+        let mut console_view = ConsoleView::new();
+        // Without a window to type into, commands are read from stdin.
+        let console_stdin = if options.headless {
+            match console::read_stdin() {
+                Ok(lines) => Some(lines),
+                Err(err) => {
+                    error!(logger, "failed to read console from stdin: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut gamepads = Gamepads::new(logger.clone());
+        let mut mice = Mice::default();
+        let mut event_queue_depth: usize = 0;
+        // Validated with the config.
+        let mut event_queues = config.events.event_queues().unwrap_or_default();
+        for (index, plugin) in plugins.iter_plugins().enumerate() {
+            for (name, delivery) in plugin.meta().events.iter() {
+                match event_queue::parse_event_type(name) {
+                    Some(event_type) => event_queues.set_delivery(index, event_type, *delivery),
+                    None => warn!(
+                        logger,
+                        "Plugin {} limits delivery of unknown event type {}",
+                        plugin.meta().name,
+                        name
+                    ),
+                }
+            }
+        }
+        let mut draw_commands = vec![];
+        let mut render_commands = vec![];
+
+        // Run the init hooks, which allocate the plugins' event buffers,
+        // and pass the guests their configuration.
+        for plugin in plugins.iter_plugins_mut() {
+            if let Ok(settings) = gers_env.settings.read() {
+                if let Err(err) = plugin.apply_settings(&settings) {
+                    error!(
+                        logger,
+                        "failed configuring plugin {}: {}",
+                        plugin.meta().name,
+                        err
+                    );
+                }
+            }
+            if let Err(err) = plugin.init() {
+                report_plugin_error(&logger, plugin, &err, &gers_env);
+            }
+        }
+        record_command_conflicts(&mut plugins, &logger, &gers_env);
+
+        // Shutting down.
+        let shutdown_timeout = config.plugins.shutdown_timeout();
+        let mut log_guard = Some(log_guard);
+        let mut trace_guard = trace_guard;
+        let mut exiting = false;
+
+        use winit::event::{Event as E, WindowEvent as WE};
+
+        event_loop.run(move |event, target, control_flow| {
+            // Plugins were shut down, but the loop finishes
+            // the iteration before it exits.
+            if exiting {
+                return;
+            }
+            *control_flow = ControlFlow::Poll;
+
+            match event {
+                E::NewEvents(_) => {
+                    // Boundary where frame starts.
+                    let now = clock.now();
+                    let mut delta_time = clock::clamp_delta_time(now - last_time, max_delta_time);
+                    last_time = now;
+
+                    frame = match replay.as_mut().map(Replay::next_frame) {
+                        None => RecordedFrame {
+                            index: frame_index,
+                            delta_time,
+                            ..RecordedFrame::default()
+                        },
+                        Some(Ok(Some(recorded))) => {
+                            delta_time = recorded.delta_time;
+                            recorded
+                        }
+                        Some(Ok(None)) => {
+                            info!(logger, "Replay finished after {} frames", frame_index);
+                            flush_plugin_logs(&logger, &gers_env);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                        Some(Err(err)) => {
+                            error!(logger, "failed reading replay: {}", err);
+                            flush_plugin_logs(&logger, &gers_env);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    };
+                    frame_index += 1;
+                    tick_index += frame_ticks as u64;
+                    frame_ticks = 0;
+
+                    // Steps asked for before the simulation was paused wait
+                    // for it to be.
+                    stepping = match replay {
+                        Some(_) => frame.events.iter().any(|event| {
+                            matches!(event, FrameEvent::SimulationState(event_data)
+                                if SimulationState::from(event_data.state) == SimulationState::Stepping)
+                        }),
+                        None => simulation_paused && simulation.take_step(),
+                    };
+
+                    fps_counter.add(delta_time);
+                    debug_overlay.push_frame(delta_time, fps_counter.fps());
+
+                    // The profiler's last frame is the one that just ended.
+                    if let Some(warn_frame_time) = warn_frame_time {
+                        if delta_time > warn_frame_time {
+                            warn!(
+                                logger,
+                                "Frame took {:.2}ms, over {:.2}ms: {}",
+                                delta_time.as_secs_f64() * 1000.0,
+                                warn_frame_time.as_secs_f64() * 1000.0,
+                                profiler.last_frame_summary()
+                            );
+                        }
+                    }
+
+                    // Store timings for access from WASm modules. They're taken
+                    // from the frame, which is the recorded one when replayed.
+                    let mut lock = gers_env
+                        .timing
+                        .write()
+                        .expect("write access to timings lock");
+                    lock.unscaled_delta_time = delta_time;
+                    lock.delta_time = if stepping {
+                        Duration::from_secs_f64(lockstep_interval)
+                    } else if simulation_paused {
+                        Duration::ZERO
+                    } else {
+                        clock::scale_delta_time(delta_time, lock.time_scale)
+                    };
+
+                    // Game time follows the time scale, and stands still
+                    // while the simulation is paused.
+                    if !simulation_paused && (!paused || accumulate_while_paused) {
+                        lockstep_timer += lock.delta_time;
+                    }
+                }
+                E::MainEventsCleared => {
+                    let _frame_span = trace::frame_span(frame_index);
+
+                    // Taken from the frame, which is the recorded one when
+                    // replayed.
+                    let delta_time = gers_env
+                        .timing
+                        .read()
+                        .map(|timing| timing.delta_time)
+                        .unwrap_or(frame.delta_time);
+                    // Counted from the frames, so replays count the same.
+                    plugins.set_frame_globals(&FrameGlobals {
+                        frame_index: frame.index,
+                        tick_index,
+                        delta_time: delta_time.as_secs_f32(),
+                    });
+
+                    if let Some(pre_update) = callbacks.pre_update.as_mut() {
+                        pre_update(&mut Context {
+                            plugins: &mut plugins,
+                            logger: &logger,
+                            delta_time,
+                            frame_index,
+                            tick_index,
+                            paused,
+                            simulation: &mut simulation,
+                        });
+                    }
+
+                    // Write FPS to window title
+                    let fps = fps_counter.fps();
+                    let dt = 1000.0 / fps; // milliseconds
+                    window.set_title(&format!("{} - {:.0} FPS {:.2}ms", window_title, fps, dt));
+
+                    // Dispatch to plugins
+                    let updates_paused = (paused && pause_updates) || (simulation_paused && !stepping);
+                    for plugin in plugins.iter_plugins_mut() {
+                        // Plugins that yielded are resumed below instead, and
+                        // faulted plugins aren't called anymore.
+                        if updates_paused
+                            || plugin.update_fn().is_none()
+                            || plugin.has_pending_work()
+                            || plugin.is_faulted()
+                        {
+                            continue;
+                        }
+
+                        let _span = trace::update_span(&plugin.meta().name);
+                        let start = Instant::now();
+                        let result = plugin.update();
+                        profiler.record(&plugin.meta().name, CallKind::Update, start.elapsed());
+
+                        if let Err(err) = result {
+                            report_plugin_error(&logger, plugin, &err, &gers_env);
+                        }
+                    }
+
+                    // Resume yielded updates within the frame's work budget.
+                    let pending: Vec<usize> = plugins
+                        .iter_plugins()
+                        .enumerate()
+                        .filter(|(_, plugin)| !updates_paused && plugin.has_pending_work())
+                        .map(|(index, _)| index)
+                        .collect();
+                    work_scheduler.run(&pending, |index, budget| {
+                        let plugin = match plugins.iter_plugins_mut().nth(index) {
+                            Some(plugin) => plugin,
+                            None => return,
+                        };
+
+                        let _span = trace::update_span(&plugin.meta().name);
+                        let start = Instant::now();
+                        let result = plugin.resume(budget);
+                        profiler.record(&plugin.meta().name, CallKind::Update, start.elapsed());
+
+                        if let Err(err) = result {
+                            report_plugin_error(&logger, plugin, &err, &gers_env);
+                        }
+                    });
+
+                    // Emitted events are part of the recording, so plugins
+                    // emitting them again during a replay are ignored.
+                    let emitted = gers_env
+                        .emitted_events
+                        .lock()
+                        .map(|mut events| std::mem::take(&mut *events))
+                        .unwrap_or_default();
+
+                    // Pausing is replayed too, rather than following the window.
+                    let pause_events = pause_state.take_events();
+                    let simulation_events = simulation.take_events();
+                    let plugin_window_events = std::mem::take(&mut window_events);
+
+                    // Gather this frame's events, unless they are replayed.
+                    if replay.is_none() {
+                        for (source, frame_event) in emitted {
+                            frame.push_from(source, frame_event);
+                        }
+                        frame.events.extend(pause_events);
+                        frame.events.extend(simulation_events);
+                        frame.events.extend(plugin_window_events);
+
+                        // The fixed updates, as many as fit into the budget.
+                        // A single one when stepped.
+                        let (ticks, lagging) = if simulation_paused {
+                            (stepping as u32, None)
+                        } else {
+                            tick_budget.take(&mut lockstep_timer)
+                        };
+                        for _ in 0..ticks {
+                            frame
+                                .events
+                                .push(FrameEvent::Hello(gers_events::HelloEvent {
+                                    data: hello_counter,
+                                    padding: 0,
+                                    div: (hello_counter / 8) as u16,
+                                }));
+                            hello_counter += 1;
+                        }
+                        // Logged when the simulation starts lagging, rather
+                        // than every frame it lags.
+                        if let (Some(ref event_data), false) = (&lagging, was_lagging) {
+                            warn!(
+                                logger,
+                                "Simulation is lagging, {} fixed updates behind",
+                                event_data.ticks_behind
+                            );
+                        }
+                        was_lagging = lagging.is_some();
+                        if let Some(event_data) = lagging {
+                            frame.events.push(FrameEvent::SimulationLagging(event_data));
+                        }
+
+                        if let Ok(mut action_map) = gers_env.input.lock() {
+                            frame
+                                .events
+                                .extend(action_map.take_events().into_iter().map(FrameEvent::Action));
+                        }
+
+                        // Controllers are polled, rather than delivered by winit.
+                        frame
+                            .events
+                            .extend(gamepads.poll().into_iter().map(|event| match event {
+                                GamepadEvent::Button(event_data) => {
+                                    FrameEvent::GamepadButton(event_data)
+                                }
+                                GamepadEvent::Axis(event_data) => FrameEvent::GamepadAxis(event_data),
+                            }));
+                        frame
+                            .events
+                            .extend(mice.take_events().into_iter().map(FrameEvent::MouseMove));
+                    }
+
+                    // Queue the frame's events for the plugins that receive them.
+                    let loaded: Vec<&Plugin> = plugins.iter_plugins().collect();
+                    let keys: Vec<_> = loaded.iter().map(|plugin| plugin.key()).collect();
+                    for (event_index, frame_event) in frame.events.iter().enumerate() {
+                        // Takes effect from the next frame's updates.
+                        match frame_event {
+                            FrameEvent::AppPaused(_) => paused = true,
+                            FrameEvent::AppResumed(_) => paused = false,
+                            FrameEvent::SimulationState(event_data) => {
+                                match SimulationState::from(event_data.state) {
+                                    SimulationState::Paused | SimulationState::Stepping => {
+                                        simulation_paused = true
+                                    }
+                                    SimulationState::Running => simulation_paused = false,
+                                    SimulationState::Unknown => {}
+                                }
+                            }
+                            // The fixed update, taken out of the timer when the
+                            // frame's events were gathered, unless replayed.
+                            FrameEvent::Hello(_) if replay.is_some() => {
+                                lockstep_timer = Duration::from_secs_f64(
+                                    lockstep_timer.as_secs_f64() % lockstep_interval,
+                                );
+                            }
+                            _ => {}
+                        }
+
+                        // Plugins in order of their subscription priority.
+                        let order = match gers_env.hooks.lock() {
+                            Ok(hooks) => hooks.dispatch_order(frame_event.event_type(), &keys),
+                            Err(_) => (0..loaded.len()).collect(),
+                        };
+                        let receivers: Vec<usize> = order
+                            .into_iter()
+                            .filter(|&index| match frame_event {
+                                FrameEvent::Action(event_data) => gers_env
+                                    .input
+                                    .lock()
+                                    .map(|action_map| {
+                                        action_map.is_subscribed(event_data.action_id, keys[index])
+                                    })
+                                    .unwrap_or(false),
+                                // Only the plugin that opened the window.
+                                FrameEvent::Window(event_data) => gers_env
+                                    .windows
+                                    .lock()
+                                    .map(|windows| windows.owner(event_data.window) == Some(keys[index]))
+                                    .unwrap_or(false),
+                                // Only plugins handling or subscribed to the type.
+                                FrameEvent::Custom(event_data) => gers_env
+                                    .custom_events
+                                    .read()
+                                    .map(|custom_events| {
+                                        custom_events.is_handled_by(event_data.type_id, keys[index])
+                                    })
+                                    .unwrap_or(false),
+                                _ => true,
+                            })
+                            .collect();
+                        queue_event(
+                            &mut event_queues,
+                            frame.source(event_index),
+                            &receivers,
+                            QueuedEvent::Frame(frame_event.clone()),
+                            &loaded,
+                            &logger,
+                        );
+                    }
+
+                    // Timers count down with the frame's scaled delta time,
+                    // so they fire on the same frames when replayed.
+                    let fired = gers_env
+                        .timers
+                        .lock()
+                        .map(|mut timers| timers.advance(delta_time))
+                        .unwrap_or_default();
+                    for (owner, event_data) in fired {
+                        if let Some(index) = keys.iter().position(|key| *key == owner) {
+                            queue_event(
+                                &mut event_queues,
+                                EVENT_SOURCE_HOST,
+                                &[index],
+                                QueuedEvent::TimerFired(event_data),
+                                &loaded,
+                                &logger,
+                            );
+                        }
+                    }
+
+                    // Responses go only to the plugin that made the request.
+                    let responses = gers_env
+                        .http
+                        .lock()
+                        .map(|mut http| http.poll())
+                        .unwrap_or_default();
+                    for response in responses {
+                        if let Some(ref err) = response.error {
+                            warn!(
+                                logger,
+                                "HTTP request {} failed: {}", response.event.request_id, err
+                            );
+                        }
+
+                        if let Some(index) = keys.iter().position(|key| *key == response.owner) {
+                            queue_event(
+                                &mut event_queues,
+                                EVENT_SOURCE_HOST,
+                                &[index],
+                                QueuedEvent::HttpResponse(response.event),
+                                &loaded,
+                                &logger,
+                            );
+                        }
+                    }
+
+                    // Results go only to the plugin that spawned the task.
+                    let results = gers_env
+                        .tasks
+                        .lock()
+                        .map(|mut tasks| tasks.poll())
+                        .unwrap_or_default();
+                    for result in results {
+                        if let Some(ref err) = result.error {
+                            warn!(logger, "Task {} failed: {}", result.event.task_id, err);
+                        }
+
+                        if let Some(index) = keys.iter().position(|key| *key == result.owner) {
+                            queue_event(
+                                &mut event_queues,
+                                EVENT_SOURCE_HOST,
+                                &[index],
+                                QueuedEvent::TaskCompleted(result.event),
+                                &loaded,
+                                &logger,
+                            );
+                        }
+                    }
+                    // Screenshots go to every plugin.
+                    for event_data in screenshots_taken.drain(..) {
+                        let receivers = match gers_env.hooks.lock() {
+                            Ok(hooks) => hooks.dispatch_order(EventType::ScreenshotTaken, &keys),
+                            Err(_) => (0..loaded.len()).collect(),
+                        };
+                        queue_event(
+                            &mut event_queues,
+                            EVENT_SOURCE_HOST,
+                            &receivers,
+                            QueuedEvent::ScreenshotTaken(event_data),
+                            &loaded,
+                            &logger,
+                        );
+                    }
+                    // Dispatch Events
+                    tick_budget.start_dispatch();
+                    frame_ticks = frame
+                        .events
+                        .iter()
+                        .filter(|frame_event| matches!(frame_event, FrameEvent::Hello(_)))
+                        .count() as u32;
+                    event_queue_depth = event_queues.queued();
+                    for queued in event_queues.drain() {
+                        let event_type = queued.event.event_type();
+
+                        // Modified by each plugin in turn.
+                        let mut damage = match queued.event {
+                            QueuedEvent::Frame(FrameEvent::Damage(ref event_data)) => {
+                                Some(event_data.clone())
+                            }
+                            _ => None,
+                        };
+
+                        // Events plugins may modify or consume are sent one at
+                        // a time, after the batches written so far. The rest
+                        // go into the event arenas of plugins that have one.
+                        let batched = damage.is_none()
+                            && !gers_env
+                                .hooks
+                                .lock()
+                                .map(|hooks| hooks.can_consume(event_type))
+                                .unwrap_or(true);
+                        if !batched {
+                            flush_event_batches(&loaded, &mut profiler, &logger, &gers_env);
+                        }
+
+                        if let Ok(mut hooks) = gers_env.hooks.lock() {
+                            hooks.begin_dispatch(event_type);
+                        }
+
+                        for plugin in queued.receivers.iter().map(|index| loaded[*index]) {
+                            match queued.event {
+                                QueuedEvent::Frame(FrameEvent::Hello(ref event_data)) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::Frame(FrameEvent::Action(ref event_data)) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::Frame(FrameEvent::GamepadButton(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::GamepadAxis(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::MouseMove(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::AppPaused(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::AppResumed(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::Window(ref event_data)) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::Frame(FrameEvent::Custom(ref event_data)) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    &**event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::Frame(FrameEvent::SimulationLagging(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::SimulationState(ref event_data)) => {
+                                    deliver_event(
+                                        plugin,
+                                        event_type,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                                QueuedEvent::Frame(FrameEvent::Damage(_)) => {
+                                    if let Some(ref mut event_data) = damage {
+                                        dispatch_mutable_event(
+                                            plugin,
+                                            event_type,
+                                            event_data,
+                                            queued.source,
+                                            &mut profiler,
+                                            &logger,
+                                            &gers_env,
+                                        );
+                                    }
+                                }
+                                QueuedEvent::TimerFired(ref event_data) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::HttpResponse(ref event_data) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::TaskCompleted(ref event_data) => deliver_event(
+                                    plugin,
+                                    event_type,
+                                    event_data,
+                                    queued.source,
+                                    batched,
+                                    &mut profiler,
+                                    &logger,
+                                    &gers_env,
+                                ),
+                                QueuedEvent::ScreenshotTaken(ref event_data) => {
+                                    deliver_encoded_event(
+                                        plugin,
+                                        event_data,
+                                        queued.source,
+                                        batched,
+                                        &mut profiler,
+                                        &logger,
+                                        &gers_env,
+                                    )
+                                }
+                            }
+
+                            let cancelled = damage.as_ref().is_some_and(|event| event.is_cancelled());
+                            if cancelled {
+                                break;
+                            }
+
+                            let consumed = gers_env
+                                .hooks
+                                .lock()
+                                .map(|hooks| hooks.is_consumed())
+                                .unwrap_or(false);
+                            if consumed {
+                                break;
+                            }
+                        }
+
+                        if let Ok(mut hooks) = gers_env.hooks.lock() {
+                            hooks.end_dispatch();
+                        }
+                    }
+                    flush_event_batches(&loaded, &mut profiler, &logger, &gers_env);
+                    tick_budget.end_dispatch(frame_ticks);
+
+                    // Bodies and results could be read while their events were
+                    // handled.
+                    if let Ok(mut http) = gers_env.http.lock() {
+                        http.end_frame();
+                    }
+                    if let Ok(mut tasks) = gers_env.tasks.lock() {
+                        tasks.end_frame();
+                    }
+
+                    // Commands typed into the console.
+                    let mut console_lines = console_view.take_submitted();
+                    if let Some(ref stdin) = console_stdin {
+                        console_lines.extend(stdin.try_iter());
+                    }
+                    for line in console_lines {
+                        if run_console_line(
+                            &line,
+                            &plugins,
+                            &mut console_view,
+                            &mut profiler,
+                            &logger,
+                            &gers_env,
+                        ) {
+                            event_queue_depth += 1;
+                        }
+                    }
+                    record_command_conflicts(&mut plugins, &logger, &gers_env);
+
+                    if let Some(post_update) = callbacks.post_update.as_mut() {
+                        post_update(&mut Context {
+                            plugins: &mut plugins,
+                            logger: &logger,
+                            delta_time,
+                            frame_index,
+                            tick_index,
+                            paused,
+                            simulation: &mut simulation,
+                        });
+                    }
+
+                    let recorded = recorder
+                        .as_mut()
+                        .map(|recorder| recorder.write_frame(&frame));
+                    if let Some(Err(err)) = recorded {
+                        error!(
+                            logger,
+                            "failed writing recording, recording stopped: {}", err
+                        );
+                        recorder = None;
+                    }
+
+                    // Forward output of WASI plugins to the log.
+                    for plugin in plugins.iter_plugins() {
+                        if let Some(output) = plugin.take_wasi_output() {
+                            for line in output.stdout.lines() {
+                                info!(gers_env.logger, "[{}] {}", plugin.meta().name, line);
+                            }
+                            for line in output.stderr.lines() {
+                                warn!(gers_env.logger, "[{}] {}", plugin.meta().name, line);
+                            }
+                        }
+                    }
+
+                    for plugin in plugins.iter_plugins_mut() {
+                        if plugin.check_memory_pressure(MEMORY_PRESSURE_GROWTH) {
+                            info!(
+                                logger,
+                                "plugin {} under memory pressure, requesting compaction",
+                                plugin.meta().name
+                            );
+                        }
+                    }
+
+                    if rewind {
+                        if snapshots.len() == REWIND_FRAMES {
+                            snapshots.pop_front();
+                        }
+                        snapshots.push_back(plugins.snapshot_all());
+                    }
+
+                    if let Some(report) = profiler.end_frame() {
+                        if profile {
+                            info!(logger, "{}", report);
+                        }
+                    }
+
+                    events_dispatched += event_queue_depth as u64;
+                    if let Some(exporter) = metrics_exporter.as_mut().filter(|exporter| exporter.is_due()) {
+                        let report = profiler.report();
+                        let snapshot = MetricsSnapshot {
+                            uptime: start_time.elapsed(),
+                            fps: fps_counter.fps(),
+                            frames: fps_counter.frame_stats(),
+                            frame_count: frame_index,
+                            events_dispatched,
+                            plugins: plugins
+                                .iter_plugins()
+                                .map(|plugin| {
+                                    let timings = report
+                                        .plugins
+                                        .iter()
+                                        .find(|timings| timings.name == plugin.meta().name);
+                                    PluginMetrics {
+                                        name: plugin.meta().name.clone(),
+                                        update: timings.map(|timings| timings.update).unwrap_or_default(),
+                                        event: timings.map(|timings| timings.event).unwrap_or_default(),
+                                        memory_bytes: plugin
+                                            .memory()
+                                            .map(|memory| memory.data_size())
+                                            .unwrap_or(0),
+                                    }
+                                })
+                                .collect(),
+                        };
+                        if let Err(err) = exporter.export(&snapshot) {
+                            warn!(logger, "failed exporting metrics: {}", err);
+                        }
+                    }
+
+                    if let Some(interval) = memory_report_interval {
+                        if last_memory_report.elapsed() >= interval {
+                            last_memory_report = Instant::now();
+                            info!(logger, "Plugin memory usage:\n{}", plugins.memory_report());
+                        }
+                    }
+
+                    // Take this frame's draw batch for rendering.
+                    if let Ok(mut queue) = gers_env.draw_queue.lock() {
+                        std::mem::swap(&mut draw_commands, &mut *queue);
+                        queue.clear();
+                    }
+
+                    // Open and close the windows plugins asked for this frame.
+                    let window_commands = gers_env
+                        .windows
+                        .lock()
+                        .map(|mut windows| windows.take_commands())
+                        .unwrap_or_default();
+                    window_registry.apply(window_commands, &window, target, &logger);
+
+                    window.request_redraw();
+                }
+                E::RedrawRequested(window_id) if window_id == window.id() => {
+                    if let Some(renderer) = renderer.as_mut() {
+                        // How far the frame is between fixed updates, so plugins
+                        // can interpolate what they draw.
+                        let alpha = (lockstep_timer.as_secs_f64() / lockstep_interval).min(1.0) as f32;
+                        let rendered = plugins
+                            .iter_plugins()
+                            .filter(|plugin| plugin.has_render() && !plugin.is_faulted());
+                        for plugin in rendered {
+                            let _span = trace::render_span(&plugin.meta().name);
+                            let start = Instant::now();
+                            let result = plugin.render(alpha);
+                            // Counts towards the plugin's update time.
+                            profiler.record(&plugin.meta().name, CallKind::Update, start.elapsed());
+
+                            if let Err(err) = result {
+                                report_plugin_error(&logger, plugin, &err, &gers_env);
+                            }
+                        }
+
+                        // Drawn on top of what plugins drew during their updates.
+                        if let Ok(mut queue) = gers_env.draw_queue.lock() {
+                            render_commands.clear();
+                            std::mem::swap(&mut render_commands, &mut *queue);
+                        }
+
+                        renderer.canvas().clear(Color::BLACK);
+                        if let Ok(textures) = gers_env.textures.read() {
+                            renderer.draw_commands(&draw_commands, &textures);
+                            renderer.draw_commands(&render_commands, &textures);
+                        }
+
+                        if let Some(render) = callbacks.render.as_mut() {
+                            let delta_time = gers_env
+                                .timing
+                                .read()
+                                .map(|timing| timing.delta_time)
+                                .unwrap_or(frame.delta_time);
+                            render(
+                                &mut Context {
+                                    plugins: &mut plugins,
+                                    logger: &logger,
+                                    delta_time,
+                                    frame_index,
+                                    tick_index,
+                                    paused,
+                                    simulation: &mut simulation,
+                                },
+                                &mut renderer.canvas(),
+                            );
+                        }
+
+                        // Taken before the overlays are drawn over the frame.
+                        let requested = gers_env
+                            .windows
+                            .lock()
+                            .map(|mut windows| windows.take_screenshot_request())
+                            .unwrap_or(false);
+                        if std::mem::take(&mut screenshot_requested) || requested {
+                            match renderer.save_screenshot(Path::new(screenshot::SCREENSHOT_DIR)) {
+                                Ok(path) => {
+                                    info!(logger, "Saved screenshot to {}", path.display());
+                                    screenshots_taken.push(ScreenshotTakenEvent {
+                                        path: path.display().to_string(),
+                                    });
+                                }
+                                Err(err) => error!(logger, "failed taking screenshot: {}", err),
+                            }
+                        }
+
+                        let watches = if debug_overlay.is_visible() {
+                            read_watches(&plugins, &gers_env)
+                        } else {
+                            vec![]
+                        };
+                        debug_overlay.draw(
+                            &mut renderer.canvas(),
+                            &OverlayStats {
+                                fps: fps_counter.fps(),
+                                frames: fps_counter.frame_stats(),
+                                pacing: fps_counter.pacing(),
+                                profiler: profiler.report(),
+                                event_queue_depth,
+                                watches: &watches,
+                            },
+                        );
+                        if mod_list.is_visible() {
+                            mod_list.draw(
+                                &mut renderer.canvas(),
+                                &ModEntry::list(&plugins, profiler.report()),
+                            );
+                        }
+                        console_view.draw(&mut renderer.canvas());
+
+                        if let Err(err) = renderer.present() {
+                            error!(logger, "failed to present frame: {}", err);
+                        }
+                    }
+                }
+                E::RedrawEventsCleared => {
+                    // Emitted after all redraw events have been emitted,
+                    // before control will be taken away from the program.
+                    //
+                    // Frame cleanup can happen here.
+
+                    // Faulted plugins aren't called anymore, so what they
+                    // hold on the host side would only linger.
+                    for plugin in plugins.iter_plugins().filter(|plugin| plugin.is_faulted()) {
+                        if released_plugins.insert(plugin.key().to_path_buf()) {
+                            let released = resources::release_plugin(&gers_env, plugin.key());
+                            if released.total() > 0 {
+                                info!(
+                                    logger,
+                                    "Released resources of faulted plugin {}: {}",
+                                    plugin.meta().name,
+                                    released
+                                );
+                            }
+                        }
+                    }
+
+                    // Spend part of the idle time left in the frame compacting
+                    // guest heaps that are under memory pressure.
+                    let idle = fps_throttle
+                        .target()
+                        .saturating_sub(clock.now().duration_since(last_time));
+                    if idle >= COMPACTION_MIN_IDLE {
+                        // One step per idle frame, taking turns from where the
+                        // last step left off.
+                        let pending: Vec<usize> = plugins
+                            .iter_plugins()
+                            .enumerate()
+                            .filter(|(_, plugin)| plugin.compaction().is_pending())
+                            .map(|(index, _)| index)
+                            .collect();
+                        let turn = pending
+                            .iter()
+                            .find(|index| **index >= next_compaction)
+                            .or_else(|| pending.first())
+                            .copied();
+                        let plugin = turn.and_then(|index| {
+                            next_compaction = index + 1;
+                            plugins.iter_plugins_mut().nth(index)
+                        });
+
+                        if let Some(plugin) = plugin {
+                            match plugin.compact(idle / 2) {
+                                Ok(0) => {
+                                    info!(
+                                        logger,
+                                        "plugin {} compaction done, {} pages reclaimed in total",
+                                        plugin.meta().name,
+                                        plugin.compaction().reclaimed_pages()
+                                    );
+                                }
+                                Ok(_) => {}
+                                Err(err) => {
+                                    report_plugin_error(&logger, plugin, &err, &gers_env);
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(error) = fps_throttle.throttle(last_time) {
+                        fps_counter.add_pacing(error);
+                    }
+                }
+                E::WindowEvent { event, window_id } if window_id == window.id() => match event {
+                    WE::CloseRequested => {
+                        info!(logger, "Shutting down");
+                        shutdown_plugins(
+                            &plugins,
+                            shutdown_timeout,
+                            &mut profiler,
+                            &logger,
+                            &gers_env,
+                        );
+                        flush_plugin_logs(&logger, &gers_env);
+                        drop(trace_guard.take());
+                        drop(log_guard.take());
+
+                        exiting = true;
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Grave),
+                                ..
+                            },
+                        ..
+                    } => {
+                        console_view.toggle();
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F3),
+                                ..
+                            },
+                        ..
+                    } => {
+                        debug_overlay.toggle();
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F4),
+                                ..
+                            },
+                        ..
+                    } => {
+                        mod_list.toggle();
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key @ (VirtualKeyCode::Up | VirtualKeyCode::Down)),
+                                ..
+                            },
+                        ..
+                    } if mod_list.is_visible() && !console_view.is_visible() => {
+                        let offset = if key == VirtualKeyCode::Up { -1 } else { 1 };
+                        mod_list.move_selection(offset, ModEntry::list(&plugins, profiler.report()).len());
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Return),
+                                ..
+                            },
+                        ..
+                    } if mod_list.is_visible() && !console_view.is_visible() => {
+                        let entries = ModEntry::list(&plugins, profiler.report());
+                        match mod_list.toggle_selected(&mut plugins, &entries) {
+                            Some(Ok((name, true))) => info!(logger, "Enabled plugin {}, applies on restart", name),
+                            Some(Ok((name, false))) => info!(logger, "Disabled plugin {}, applies on restart", name),
+                            Some(Err(err)) => error!(logger, "failed saving enabled plugins: {}", err),
+                            None => {}
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    } => {
+                        screenshot_requested = true;
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F7),
+                                ..
+                            },
+                        ..
+                    } if replay.is_none() => {
+                        simulation.toggle();
+                        if simulation.is_paused() {
+                            info!(logger, "Simulation paused, step it with F10");
+                        } else {
+                            info!(logger, "Simulation resumed");
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F10),
+                                ..
+                            },
+                        ..
+                    } if replay.is_none() => {
+                        let stepped = simulation.step();
+                        if !stepped {
+                            info!(logger, "Pause the simulation with F7 to step it");
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F8),
+                                ..
+                            },
+                        ..
+                    } => {
+                        info!(logger, "Plugin memory usage:\n{}", plugins.memory_report());
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F6),
+                                ..
+                            },
+                        ..
+                    } if rewind => {
+                        // Rewind to the oldest frame kept.
+                        let frames = snapshots.len();
+                        if let Some(snapshot) = snapshots.pop_front() {
+                            match plugins.restore_all(&snapshot) {
+                                Ok(()) => info!(logger, "Rewound plugins by {} frames", frames),
+                                Err(err) => error!(logger, "failed rewinding plugins: {}", err),
+                            }
+                            snapshots.clear();
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F5),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let path = Path::new(savegame::SAVE_DIR).join(savegame::QUICKSAVE_FILENAME);
+                        let result = SaveGame::from_plugins(&plugins, &gers_env.save_buffer)
+                            .and_then(|save| save.write(&path).map_err(SaveError::from));
+                        match result {
+                            Ok(()) => info!(logger, "Saved game to {}", path.display()),
+                            Err(err) => error!(logger, "failed saving game: {}", err),
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                ..
+                            },
+                        ..
+                    } if replay.is_none() => {
+                        // Loading would diverge from a recording being replayed.
+                        let path = Path::new(savegame::SAVE_DIR).join(savegame::QUICKSAVE_FILENAME);
+                        let result = SaveGame::read(&path)
+                            .map_err(SaveError::from)
+                            .and_then(|save| save.load_into(&plugins));
+                        match result {
+                            Ok(report) => {
+                                for (name, version) in report.missing.iter() {
+                                    warn!(logger, "Savegame plugin {} {} isn't loaded", name, version);
+                                }
+                                for (name, saved, loaded) in report.mismatched.iter() {
+                                    warn!(
+                                        logger,
+                                        "Savegame plugin {} was saved by version {}, loaded version is {}",
+                                        name,
+                                        saved,
+                                        loaded
+                                    );
+                                }
+                                info!(
+                                    logger,
+                                    "Loaded {} plugins from {}",
+                                    report.load.len(),
+                                    path.display()
+                                );
+                                // Snapshots from before the load would rewind into another game.
+                                snapshots.clear();
+                            }
+                            Err(err) => error!(logger, "failed loading game: {}", err),
+                        }
+                    }
+                    WE::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } if replay.is_none()
+                        && !(console_view.is_visible() && state == ElementState::Pressed) =>
+                    {
+                        // Replayed actions stand in for the keyboard. Typing into the
+                        // console doesn't trigger actions, but releasing keys held
+                        // from before it opened does.
+                        if let Ok(mut action_map) = gers_env.input.lock() {
+                            action_map.handle_key(key, state);
+                        }
+                    }
+                    WE::KeyboardInput { .. } => {}
+                    WE::ReceivedCharacter(c) => console_view.handle_char(c),
+                    WE::MouseInput { .. } => {}
+                    // Replayed moves stand in for the mouse.
+                    WE::CursorMoved {
+                        device_id,
+                        position,
+                        ..
+                    } if replay.is_none() => mice.handle_cursor_moved(device_id, position),
+                    WE::Focused(focused) => {
+                        pause_state.set_focused(focused);
+                        if background_fps > 0 {
+                            fps_throttle.set_target(if focused { target_fps } else { background_fps });
+                        }
+                    }
+                    WE::Resized(size) => {
+                        // Minimized windows are resized to nothing.
+                        pause_state.set_minimized(size.width == 0 || size.height == 0);
+                        if let Some(renderer) = renderer.as_mut() {
+                            renderer.resize(size.width, size.height);
+                        }
+                    }
+                    WE::ScaleFactorChanged { .. } => {}
+                    _ => {}
+                },
+                E::WindowEvent { event, window_id } => {
+                    let event_data = window_registry
+                        .handle(window_id)
+                        .and_then(|handle| window::window_event(handle, &event));
+                    if let Some(event_data) = event_data {
+                        window_events.push(FrameEvent::Window(event_data));
+                    }
+                }
+                _ => (),
+            }
+        });
+    }
+}
+
+/// Record the conflicts over console commands since the last call.
+fn record_command_conflicts(plugins: &mut Plugins, logger: &slog::Logger, gers_env: &GersEnv) {
+    let conflicts = match gers_env.console.lock() {
+        Ok(mut console) => console.take_conflicts(),
+        Err(_) => return,
+    };
+
+    for conflict in conflicts {
+        let name_of = |key: &Path| {
+            plugins
+                .iter_plugins()
+                .find(|plugin| plugin.key() == key)
+                .map_or_else(
+                    || key.display().to_string(),
+                    |plugin| plugin.meta().name.clone(),
+                )
+        };
+        let (winner, loser) = (name_of(&conflict.winner), name_of(&conflict.loser));
+        let resource = Resource::Command(conflict.name);
+        if plugins.record_conflict(resource.clone(), &winner, &loser) {
+            warn!(
+                logger,
+                "Conflict over {}: {} wins over {}", resource, winner, loser
+            );
+        }
+    }
+}
+
+/// Current values of the plugins' watches, for the plugins watching any.
+fn read_watches(plugins: &Plugins, gers_env: &GersEnv) -> Vec<PluginWatches> {
+    let watches = match gers_env.watches.lock() {
+        Ok(watches) if !watches.is_empty() => watches,
+        _ => return vec![],
+    };
+
+    plugins
+        .iter_plugins()
+        .filter_map(|plugin| {
+            let values = watches.read(plugin.key(), plugin.memory().ok()?);
+            (!values.is_empty()).then(|| PluginWatches {
+                plugin: plugin.meta().name.clone(),
+                values,
+            })
+        })
+        .collect()
+}
+
+/// Run a line typed into the console.
+///
+/// Returns `true` if a command was dispatched to a plugin.
+fn run_console_line(
+    line: &str,
+    plugins: &Plugins,
+    console_view: &mut ConsoleView,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) -> bool {
+    let parsed = match gers_env.console.lock() {
+        Ok(console) => console.parse(line),
+        Err(_) => return false,
+    };
+
+    let invocation = match parsed {
+        Ok(ConsoleInput::Empty) => return false,
+        Ok(ConsoleInput::Help(lines)) => {
+            for line in lines {
+                info!(logger, "{}", line);
+                console_view.print(line);
+            }
+            return false;
+        }
+        Ok(ConsoleInput::Set { plugin, key, value }) => {
+            let line =
+                match change_setting(plugins, &plugin, &key, &value, profiler, logger, gers_env) {
+                    Ok(value) => format!("{} {} = {}", plugin, key, value),
+                    Err(err) => err,
+                };
+            info!(logger, "{}", line);
+            console_view.print(line);
+            return false;
+        }
+        Ok(ConsoleInput::Locale(locale)) => {
+            let line = match switch_locale(plugins, &locale, profiler, logger, gers_env) {
+                Ok(()) => format!("locale {}", locale),
+                Err(err) => err,
+            };
+            info!(logger, "{}", line);
+            console_view.print(line);
+            return false;
+        }
+        Ok(ConsoleInput::Watch) => {
+            let watches = read_watches(plugins, gers_env);
+            if watches.is_empty() {
+                console_view.print("no values are watched".to_string());
+            }
+            for line in watches.iter().flat_map(PluginWatches::lines) {
+                info!(logger, "{}", line);
+                console_view.print(line);
+            }
+            return false;
+        }
+        Ok(ConsoleInput::Command(invocation)) => invocation,
+        Err(err) => {
+            warn!(logger, "{}", err);
+            console_view.print(err.to_string());
+            return false;
+        }
+    };
+
+    let owner = plugins
+        .iter_plugins()
+        .find(|plugin| plugin.key() == invocation.owner);
+    let plugin = match owner {
+        Some(plugin) => plugin,
+        None => return false,
+    };
+
+    if let Ok(mut console) = gers_env.console.lock() {
+        console.begin_invocation(&invocation);
+    }
+    dispatch_event(
+        plugin,
+        EventType::ConsoleCommand,
+        &invocation.event,
+        EVENT_SOURCE_HOST,
+        profiler,
+        logger,
+        gers_env,
+    );
+    if let Ok(mut console) = gers_env.console.lock() {
+        console.end_invocation();
+    }
+
+    true
+}
+
+/// Change a plugin's setting to a value typed by the user, persist it,
+/// and send the plugin a `SettingsChangedEvent`.
+fn change_setting(
+    plugins: &Plugins,
+    name: &str,
+    key: &str,
+    text: &str,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) -> Result<SettingValue, String> {
+    let plugin = plugins
+        .get(name)
+        .ok_or_else(|| format!("unknown plugin: {}", name))?;
+    let decl = plugin
+        .meta()
+        .settings
+        .get(key)
+        .ok_or_else(|| format!("plugin {} has no setting {}", name, key))?;
+    let value = decl
+        .parse(text)
+        .map_err(|err| format!("invalid value for {}: {}", key, err))?;
+
+    // The plugin reads the settings while handling the event.
+    let value = {
+        let mut settings = gers_env
+            .settings
+            .write()
+            .map_err(|_| "plugin settings are unavailable".to_string())?;
+        let value = settings
+            .set(name, key, decl, value)
+            .map_err(|err| err.to_string())?;
+        if let Err(err) = settings.save() {
+            error!(logger, "failed saving plugin settings: {}", err);
+        }
+        value
+    };
+
+    if let Some(event) = SettingsChangedEvent::new(key) {
+        dispatch_event(
+            plugin,
+            EventType::SettingsChanged,
+            &event,
+            EVENT_SOURCE_HOST,
+            profiler,
+            logger,
+            gers_env,
+        );
+    }
+
+    Ok(value)
+}
+
+/// Load the string tables of every plugin.
+fn load_string_tables(plugins: &Plugins, i18n: &mut Localization, logger: &slog::Logger) {
+    for plugin in plugins.iter_plugins() {
+        if let Err(err) = i18n.load_plugin(plugin.key(), plugin.source()) {
+            error!(logger, "plugin {}: {}", plugin.meta().name, err);
+        }
+    }
+}
+
+/// Switch the language of the plugins' strings, and send every plugin
+/// a `LocaleChangedEvent`.
+fn switch_locale(
+    plugins: &Plugins,
+    locale: &str,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) -> Result<(), String> {
+    let fallback = match gers_env.i18n.read() {
+        Ok(i18n) => i18n.fallback().to_string(),
+        Err(_) => return Err("string tables are unavailable".to_string()),
+    };
+    let mut i18n = Localization::new(locale, &fallback).map_err(|err| err.to_string())?;
+    load_string_tables(plugins, &mut i18n, logger);
+    if let Ok(mut lock) = gers_env.i18n.write() {
+        *lock = i18n;
+    }
+
+    let event = LocaleChangedEvent::new(locale).expect("locale validated by Localization");
+    let loaded: Vec<&Plugin> = plugins.iter_plugins().collect();
+    let keys: Vec<_> = loaded.iter().map(|plugin| plugin.key()).collect();
+    let order = match gers_env.hooks.lock() {
+        Ok(hooks) => hooks.dispatch_order(EventType::LocaleChanged, &keys),
+        Err(_) => (0..loaded.len()).collect(),
+    };
+    for plugin in order.into_iter().map(|index| loaded[index]) {
+        dispatch_event(
+            plugin,
+            EventType::LocaleChanged,
+            &event,
+            EVENT_SOURCE_HOST,
+            profiler,
+            logger,
+            gers_env,
+        );
+    }
+
+    Ok(())
+}
+
+/// Write what plugins logged to their log files before exiting, as the
+/// event loop doesn't return to drop them.
+fn flush_plugin_logs(logger: &slog::Logger, gers_env: &GersEnv) {
+    if let Ok(mut logs) = gers_env.plugin_logs.lock() {
+        if let Err(err) = logs.flush_all() {
+            error!(logger, "failed flushing plugin logs: {}", err);
+        }
+    }
+}
+
+/// Let plugins persist their state before the app quits.
+///
+/// Sends a `ShutdownRequestedEvent` to the plugins, then calls their
+/// `__gers_shutdown`, each with an equal share of what's left of the
+/// timeout. The hooks can't be interrupted, so plugins that don't get
+/// a turn before the timeout runs out are skipped.
+fn shutdown_plugins(
+    plugins: &Plugins,
+    timeout: Duration,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    let start = Instant::now();
+    let loaded: Vec<&Plugin> = plugins.iter_plugins().collect();
+    let keys: Vec<_> = loaded.iter().map(|plugin| plugin.key()).collect();
+
+    let event_data = ShutdownRequestedEvent {
+        timeout_ms: timeout.as_millis().min(u32::MAX as u128) as u32,
+    };
+    let order = match gers_env.hooks.lock() {
+        Ok(hooks) => hooks.dispatch_order(EventType::ShutdownRequested, &keys),
+        Err(_) => (0..loaded.len()).collect(),
+    };
+    for plugin in order.into_iter().map(|index| loaded[index]) {
+        dispatch_event(
+            plugin,
+            EventType::ShutdownRequested,
+            &event_data,
+            EVENT_SOURCE_HOST,
+            profiler,
+            logger,
+            gers_env,
+        );
+    }
+
+    for (count, plugin) in loaded.iter().enumerate() {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            for plugin in loaded[count..].iter() {
+                warn!(
+                    logger,
+                    "Shutdown timed out before plugin {} was shut down",
+                    plugin.meta().name
+                );
+            }
+            break;
+        }
+
+        let waiting = (loaded.len() - count) as u32;
+        let budget = remaining / waiting;
+        let call_start = Instant::now();
+        match plugin.shutdown(budget) {
+            Ok(_) if call_start.elapsed() > budget => {
+                warn!(
+                    logger,
+                    "plugin {} took {:.2}ms to shut down, over its {:.2}ms budget",
+                    plugin.meta().name,
+                    call_start.elapsed().as_secs_f64() * 1000.0,
+                    budget.as_secs_f64() * 1000.0
+                );
+            }
+            Ok(_) => {}
+            Err(err) => report_plugin_error(logger, plugin, &err, gers_env),
+        }
+    }
+
+    info!(
+        logger,
+        "Plugins shut down in {:.2}ms",
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+}
+
+/// Send an event the plugin may modify, recording the call in the profiler.
+fn dispatch_mutable_event<T: MutableEvent>(
+    plugin: &Plugin,
+    event_type: EventType,
+    event_data: &mut T,
+    source: u32,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    if plugin.is_faulted() {
+        return;
+    }
+
+    let _span = trace::event_span(&plugin.meta().name, event_type);
+    let start = Instant::now();
+    let result = plugin.dispatch_mutable_event_from(source, event_type as i32, event_data);
+    profiler.record(&plugin.meta().name, CallKind::Event, start.elapsed());
+
+    if let Err(err) = result {
+        report_plugin_error(logger, plugin, &err, gers_env);
+    }
+}
+
+/// Queue an event for plugins, logging those it was lost for.
+fn queue_event(
+    event_queues: &mut EventQueues,
+    source: u32,
+    receivers: &[usize],
+    event: QueuedEvent,
+    plugins: &[&Plugin],
+    logger: &slog::Logger,
+) {
+    for err in event_queues.push_from(source, receivers, event) {
+        match err {
+            QueueError::Full { plugin, .. } => {
+                error!(logger, "{}: {}", plugins[plugin].meta().name, err);
+            }
+        }
+    }
+}
+
+/// Write an event into the plugin's event arena when `batched`, or
+/// send it straight away.
+#[allow(clippy::too_many_arguments)]
+fn deliver_event<T: Clone>(
+    plugin: &Plugin,
+    event_type: EventType,
+    event_data: &T,
+    source: u32,
+    batched: bool,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    if plugin.is_faulted() {
+        return;
+    }
+
+    if batched {
+        match plugin.queue_event_from(source, event_type as i32, event_data) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => {
+                report_plugin_error(logger, plugin, &err, gers_env);
+                return;
+            }
+        }
+    }
+
+    dispatch_event(
+        plugin, event_type, event_data, source, profiler, logger, gers_env,
+    );
+}
+
+/// Write an encoded event into the plugin's event arena when `batched`,
+/// or send it straight away, like `deliver_event`.
+///
+/// Plugins from before encoded events were supported don't receive it.
+fn deliver_encoded_event<T: EncodedEvent>(
+    plugin: &Plugin,
+    event_data: &T,
+    source: u32,
+    batched: bool,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    if plugin.is_faulted() {
+        return;
+    }
+
+    if batched {
+        match plugin.queue_encoded_event_from(source, event_data) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => {
+                report_plugin_error(logger, plugin, &err, gers_env);
+                return;
+            }
+        }
+    }
+
+    let _span = trace::event_span(&plugin.meta().name, T::TYPE);
+    let start = Instant::now();
+    let result = plugin.dispatch_encoded_event_from(source, event_data);
+    profiler.record(&plugin.meta().name, CallKind::Event, start.elapsed());
+
+    if let Err(err) = result {
+        report_plugin_error(logger, plugin, &err, gers_env);
+    }
+}
+
+/// Deliver the events written into the plugins' event arenas.
+fn flush_event_batches(
+    plugins: &[&Plugin],
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    for plugin in plugins
+        .iter()
+        .filter(|plugin| plugin.has_queued_events() && !plugin.is_faulted())
+    {
+        let _span = trace::batch_span(&plugin.meta().name);
+        let start = Instant::now();
+        let result = plugin.flush_events();
+        profiler.record(&plugin.meta().name, CallKind::Event, start.elapsed());
+
+        if let Err(err) = result {
+            report_plugin_error(logger, plugin, &err, gers_env);
+        }
+    }
+}
+
+/// Send an event to a plugin, recording the call in the profiler.
+fn dispatch_event<T: Clone>(
+    plugin: &Plugin,
+    event_type: EventType,
+    event_data: &T,
+    source: u32,
+    profiler: &mut Profiler,
+    logger: &slog::Logger,
+    gers_env: &GersEnv,
+) {
+    if plugin.is_faulted() {
+        return;
+    }
+
+    let _span = trace::event_span(&plugin.meta().name, event_type);
+    let start = Instant::now();
+    let result = plugin.dispatch_event_from(source, event_type as i32, event_data);
+    profiler.record(&plugin.meta().name, CallKind::Event, start.elapsed());
+
+    if let Err(err) = result {
+        report_plugin_error(logger, plugin, &err, gers_env);
+    }
+}
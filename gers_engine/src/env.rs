@@ -0,0 +1,248 @@
+use crate::{
+    audio::Audio,
+    clipboard::Clipboard,
+    clock,
+    console::Console,
+    crash::CrashDumps,
+    custom_events::CustomEvents,
+    hooks::EventHooks,
+    http::Http,
+    i18n::Localization,
+    input::ActionMap,
+    net::Network,
+    plugin_log::PluginLogs,
+    render::{DrawCommand, TextureRegistry},
+    replay::FrameEvent,
+    storage,
+    tasks::Tasks,
+    timer::Timers,
+    watch::Watches,
+    window::WindowRequests,
+};
+use gers_plugins::{PluginContext, PluginSource, SettingDecl, Settings};
+use gers_world::World;
+use slog::Logger;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use wasmer::{LazyInit, Memory, WasmerEnv};
+
+#[derive(WasmerEnv, Clone)]
+pub struct GersEnv {
+    pub logger: Logger,
+    pub timing: Arc<RwLock<Timing>>,
+
+    /// Seed for plugins' random number generators.
+    pub seed: u64,
+
+    /// Message reported by the guest's panic hook,
+    /// just before the panic traps.
+    pub panic_message: Arc<Mutex<Option<String>>>,
+
+    /// Draw commands submitted by plugins during the current frame.
+    pub draw_queue: Arc<Mutex<Vec<DrawCommand>>>,
+
+    /// Textures loaded by plugins.
+    pub textures: Arc<RwLock<TextureRegistry>>,
+
+    /// Sounds and voices played by plugins.
+    pub audio: Arc<Mutex<Audio>>,
+
+    /// Actions registered by plugins.
+    pub input: Arc<Mutex<ActionMap>>,
+
+    /// Entities and components shared by all plugins.
+    pub world: Arc<Mutex<World>>,
+
+    /// Connections opened by plugins.
+    pub network: Arc<Mutex<Network>>,
+
+    /// HTTP requests made by plugins.
+    pub http: Arc<Mutex<Http>>,
+
+    /// Timers set by plugins.
+    pub timers: Arc<Mutex<Timers>>,
+
+    /// Background tasks spawned by plugins.
+    pub tasks: Arc<Mutex<Tasks>>,
+
+    /// Event subscriptions of plugins.
+    pub hooks: Arc<Mutex<EventHooks>>,
+
+    /// Events emitted by plugins, sent to all plugins when
+    /// events are next dispatched, with the `plugin_id` of their source.
+    pub emitted_events: Arc<Mutex<Vec<(u32, FrameEvent)>>>,
+
+    /// Custom event types defined in the plugins' event manifests.
+    pub custom_events: Arc<RwLock<CustomEvents>>,
+
+    /// State written by the plugin that is being saved.
+    pub save_buffer: Arc<Mutex<Vec<u8>>>,
+
+    /// Windows opened by plugins.
+    pub windows: Arc<Mutex<WindowRequests>>,
+
+    pub clipboard: Arc<Mutex<Clipboard>>,
+
+    /// Console commands registered by plugins.
+    pub console: Arc<Mutex<Console>>,
+
+    /// Values of the settings plugins declare.
+    pub settings: Arc<RwLock<Settings>>,
+
+    /// String tables of the plugins, in the active locale.
+    pub i18n: Arc<RwLock<Localization>>,
+
+    /// Crash dumps written for plugins that trapped.
+    pub crash_dumps: Arc<Mutex<CrashDumps>>,
+
+    /// Log files messages of plugins are written to.
+    pub plugin_logs: Arc<Mutex<PluginLogs>>,
+
+    /// Values in plugins' memories watched while debugging.
+    pub watches: Arc<Mutex<Watches>>,
+
+    /// Plugin the environment is bound to.
+    pub plugin: Arc<PluginScope>,
+
+    #[wasmer(export)]
+    pub memory: LazyInit<Memory>,
+}
+
+/// Plugin specific part of the environment.
+#[derive(Debug, Default)]
+pub struct PluginScope {
+    /// Name from the plugin's meta file.
+    pub name: String,
+    pub version: String,
+    /// Tells the plugin apart from the others as the owner of host
+    /// resources, see `Plugin::key`.
+    pub key: PathBuf,
+    /// Reads the plugin's files.
+    pub source: Option<PluginSource>,
+    /// Directory the plugin stores its data in, if its name can be a
+    /// directory name.
+    pub data_dir: Option<PathBuf>,
+    /// Settings declared in the plugin's meta file.
+    pub settings: BTreeMap<String, SettingDecl>,
+}
+
+impl GersEnv {
+    /// Create an environment bound to a plugin that is being instantiated.
+    ///
+    /// Shared engine state is kept, while the plugin's memory
+    /// export is initialised separately for each instance.
+    pub fn for_plugin(&self, context: &PluginContext) -> Self {
+        Self {
+            plugin: Arc::new(PluginScope {
+                name: context.meta.name.clone(),
+                version: context.meta.version.clone(),
+                key: context.key.to_path_buf(),
+                source: Some(context.source.clone()),
+                data_dir: storage::plugin_data_dir(
+                    Path::new(storage::DATA_DIR),
+                    &context.meta.name,
+                ),
+                settings: context.meta.settings.clone(),
+            }),
+            memory: LazyInit::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Take the last reported guest panic message, if any.
+    pub fn take_panic_message(&self) -> Option<String> {
+        self.panic_message
+            .lock()
+            .ok()
+            .and_then(|mut lock| lock.take())
+    }
+}
+
+/// Event loop timing information.
+pub struct Timing {
+    /// Variable delta time since last event loop iteration,
+    /// multiplied by the time scale.
+    pub delta_time: Duration,
+    /// Variable delta time since last event loop iteration.
+    pub unscaled_delta_time: Duration,
+    /// Applied to the delta time from the next frame on.
+    pub time_scale: f32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            delta_time: clock::MIN_DELTA_TIME,
+            unscaled_delta_time: clock::MIN_DELTA_TIME,
+            time_scale: 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl GersEnv {
+    /// Environment with fresh engine state, bound to a plugin named
+    /// `test` with a page of memory, for calling host imports in tests.
+    pub fn for_test() -> Self {
+        use crate::audio::{self, Audio};
+        use wasmer::{MemoryType, Store};
+
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let audio_device = audio::Device::open(&logger);
+        let mut env = GersEnv {
+            logger,
+            timing: Default::default(),
+            seed: 0,
+            panic_message: Default::default(),
+            draw_queue: Default::default(),
+            textures: Default::default(),
+            audio: Arc::new(Mutex::new(Audio::new(&audio_device))),
+            input: Default::default(),
+            world: Default::default(),
+            network: Default::default(),
+            http: Default::default(),
+            timers: Default::default(),
+            tasks: Default::default(),
+            hooks: Default::default(),
+            emitted_events: Default::default(),
+            custom_events: Default::default(),
+            save_buffer: Default::default(),
+            windows: Default::default(),
+            clipboard: Default::default(),
+            console: Default::default(),
+            settings: Default::default(),
+            i18n: Default::default(),
+            crash_dumps: Default::default(),
+            plugin_logs: Default::default(),
+            watches: Default::default(),
+            plugin: Arc::new(PluginScope {
+                name: "test".to_string(),
+                version: "1.0.0".to_string(),
+                ..Default::default()
+            }),
+            memory: LazyInit::new(),
+        };
+        let memory = Memory::new(&Store::default(), MemoryType::new(1, None, false)).unwrap();
+        env.memory.initialize(memory);
+
+        env
+    }
+
+    /// Copy bytes into the guest memory.
+    pub fn write_memory(&self, offset: u32, bytes: &[u8]) {
+        let view = self.memory.get_ref().unwrap().view::<u8>();
+        for (cell, byte) in view[offset as usize..].iter().zip(bytes) {
+            cell.set(*byte);
+        }
+    }
+
+    /// Copy bytes out of the guest memory.
+    pub fn read_memory(&self, offset: u32, len: u32) -> Vec<u8> {
+        let memory = self.memory.get_ref().unwrap();
+        gers_plugins::strings::read_bytes(memory, wasmer::WasmPtr::new(offset), len).unwrap()
+    }
+}
@@ -0,0 +1,55 @@
+use gers_plugins::Plugin;
+use slog::{error, Logger};
+use wasmer::{FrameInfo, RuntimeError};
+
+/// Utility for printing a `RuntimeError` of a plugin.
+///
+/// Frames are followed by their functions and source locations, when
+/// the plugin's module has symbols. The panic message reported by the
+/// guest, if any, is appended to the report.
+pub fn print_runtime_error(
+    logger: &Logger,
+    plugin: &Plugin,
+    err: &RuntimeError,
+    panic_message: Option<String>,
+) {
+    let mut message = String::new();
+    message.push_str(err.message().as_str());
+    message.push('\n');
+
+    let frames = err.trace();
+    let frames_len = frames.len();
+
+    for (i, frame) in frames.iter().enumerate() {
+        let frame_message = format!(
+            "  Frame #{}: {:?}::{:?}\n",
+            frames_len - i,
+            frame.module_name(),
+            frame.function_name().unwrap_or("<func>")
+        );
+
+        message.push_str(frame_message.as_str());
+        for line in symbol_lines(plugin, frame) {
+            message.push_str(line.as_str());
+            message.push('\n');
+        }
+    }
+
+    if let Some(panic_message) = panic_message {
+        message.push_str("  Panic: ");
+        message.push_str(panic_message.as_str());
+        message.push('\n');
+    }
+
+    error!(logger, "update error: {}", message);
+}
+
+/// Lines with the functions and source locations of a frame, innermost
+/// inlined function first.
+pub fn symbol_lines(plugin: &Plugin, frame: &FrameInfo) -> Vec<String> {
+    plugin
+        .symbolicate(frame)
+        .into_iter()
+        .map(|symbol| format!("      in {}", symbol))
+        .collect()
+}
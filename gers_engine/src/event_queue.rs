@@ -0,0 +1,546 @@
+//! Per-plugin event queues.
+//!
+//! Events from input, timers, HTTP responses, background tasks,
+//! screenshots and the event bus are
+//! queued for the plugins that receive them while the frame's events
+//! are gathered, and dispatched together in one phase afterwards. Each
+//! plugin has a bounded ring buffer, so a plugin flooded with events
+//! can't hold up the frame. When a queue is full, the overflow policy
+//! of the event's type decides which event is lost.
+//!
+//! Draining delivers events by their priority class, so input and
+//! lifecycle events come before gameplay events, and otherwise keeps the
+//! order the events were queued in. Each event is still dispatched to
+//! its receivers in turn, so consuming and modifying events works like
+//! it does without the queues.
+//!
+//! Plugins that can't keep up with frequent events, like gamepad axes,
+//! limit how many of a type they receive each frame under `[events]`
+//! in their `plugin.toml`. Queuing an event past the limit drops the
+//! plugin's oldest queued event of the type, so the latest ones are
+//! delivered.
+use crate::replay::FrameEvent;
+use gers_events::{
+    EventPriority, EventType, HttpResponseEvent, ScreenshotTakenEvent, TaskCompletedEvent,
+    TimerFiredEvent, EVENT_SOURCE_HOST,
+};
+use gers_plugins::EventDelivery;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+};
+
+/// Default number of events a single plugin can have queued.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+/// What happens to an event queued for a plugin whose queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest event in the queue to make room.
+    #[default]
+    DropOldest,
+    /// Drop the event being queued.
+    DropNewest,
+    /// Drop the event being queued, and report it as an error.
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub enum QueuedEvent {
+    Frame(FrameEvent),
+    TimerFired(TimerFiredEvent),
+    HttpResponse(HttpResponseEvent),
+    TaskCompleted(TaskCompletedEvent),
+    ScreenshotTaken(ScreenshotTakenEvent),
+}
+
+impl QueuedEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            QueuedEvent::Frame(event) => event.event_type(),
+            QueuedEvent::TimerFired(_) => EventType::TimerFired,
+            QueuedEvent::HttpResponse(_) => EventType::HttpResponse,
+            QueuedEvent::TaskCompleted(_) => EventType::TaskCompleted,
+            QueuedEvent::ScreenshotTaken(_) => EventType::ScreenshotTaken,
+        }
+    }
+
+    /// What the event is about, so coalescing keeps the latest event of
+    /// each gamepad axis or mouse, rather than of all of them.
+    fn coalesce_key(&self) -> (u32, u32) {
+        match self {
+            QueuedEvent::Frame(FrameEvent::Action(event)) => (event.action_id, 0),
+            QueuedEvent::Frame(FrameEvent::GamepadButton(event)) => (event.device_id, event.button),
+            QueuedEvent::Frame(FrameEvent::GamepadAxis(event)) => (event.device_id, event.axis),
+            QueuedEvent::Frame(FrameEvent::MouseMove(event)) => (event.device_id, 0),
+            QueuedEvent::Frame(FrameEvent::Window(event)) => (event.window, event.kind),
+            QueuedEvent::Frame(FrameEvent::Custom(event)) => (event.type_id, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// An event queued for one or more plugins, ready to be dispatched.
+#[derive(Debug)]
+pub struct QueuedDispatch {
+    pub event: QueuedEvent,
+    /// The `plugin_id` of the plugin that emitted the event, or
+    /// `EVENT_SOURCE_HOST`.
+    pub source: u32,
+    /// Indices of the plugins that receive the event, in the order
+    /// they were given when it was queued.
+    pub receivers: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum QueueError {
+    /// The plugin's queue was full, and the event type's policy is `fail`.
+    Full {
+        plugin: usize,
+        event_type: EventType,
+        capacity: usize,
+    },
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Full {
+                event_type,
+                capacity,
+                ..
+            } => write!(
+                f,
+                "event queue full ({} events), dropped {:?} event",
+                capacity, event_type
+            ),
+        }
+    }
+}
+
+/// Rank of a receiver with its plugin index.
+type Ranked = (usize, usize);
+
+struct Entry {
+    /// Position of the event in the order events were queued.
+    sequence: u64,
+    /// Position of the plugin among the event's receivers.
+    rank: usize,
+    source: u32,
+    event: QueuedEvent,
+}
+
+/// Event queues of the loaded plugins, indexed in load order.
+pub struct EventQueues {
+    queues: Vec<VecDeque<Entry>>,
+    capacity: usize,
+    default_policy: OverflowPolicy,
+    policies: HashMap<EventType, OverflowPolicy>,
+    /// Deliveries of event types other than `all`, by plugin index.
+    deliveries: HashMap<(usize, EventType), EventDelivery>,
+    next_sequence: u64,
+}
+
+impl Default for EventQueues {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_CAPACITY, OverflowPolicy::default())
+    }
+}
+
+impl EventQueues {
+    /// Queues holding up to `capacity` events per plugin. The capacity
+    /// must be greater than zero.
+    pub fn new(capacity: usize, default_policy: OverflowPolicy) -> Self {
+        Self {
+            queues: vec![],
+            capacity,
+            default_policy,
+            policies: HashMap::new(),
+            deliveries: HashMap::new(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Use a different overflow policy for events of a type.
+    pub fn set_policy(&mut self, event_type: EventType, policy: OverflowPolicy) {
+        self.policies.insert(event_type, policy);
+    }
+
+    pub fn policy(&self, event_type: EventType) -> OverflowPolicy {
+        self.policies
+            .get(&event_type)
+            .copied()
+            .unwrap_or(self.default_policy)
+    }
+
+    /// Limit the events of a type the plugin at the `plugin` index
+    /// receives each frame.
+    pub fn set_delivery(&mut self, plugin: usize, event_type: EventType, delivery: EventDelivery) {
+        if delivery == EventDelivery::All {
+            self.deliveries.remove(&(plugin, event_type));
+        } else {
+            self.deliveries.insert((plugin, event_type), delivery);
+        }
+    }
+
+    pub fn delivery(&self, plugin: usize, event_type: EventType) -> EventDelivery {
+        self.deliveries
+            .get(&(plugin, event_type))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Queue an event for the plugins at the `receivers` indices, which
+    /// receive it in the given order.
+    ///
+    /// Returns an error for every plugin the event was lost for under
+    /// the `fail` policy. The event is still queued for the others.
+    pub fn push(&mut self, receivers: &[usize], event: QueuedEvent) -> Vec<QueueError> {
+        self.push_from(EVENT_SOURCE_HOST, receivers, event)
+    }
+
+    /// Queue an event emitted by the plugin with the `plugin_id`
+    /// `source`, like `push`.
+    pub fn push_from(
+        &mut self,
+        source: u32,
+        receivers: &[usize],
+        event: QueuedEvent,
+    ) -> Vec<QueueError> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let event_type = event.event_type();
+        let policy = self.policy(event_type);
+        let mut errors = vec![];
+
+        for (rank, &plugin) in receivers.iter().enumerate() {
+            if plugin >= self.queues.len() {
+                self.queues.resize_with(plugin + 1, VecDeque::new);
+            }
+            let queue = &mut self.queues[plugin];
+
+            // Events the plugin receives fewer of make room first.
+            match self.deliveries.get(&(plugin, event_type)) {
+                None | Some(EventDelivery::All) => {}
+                Some(EventDelivery::Coalesce) => {
+                    let key = event.coalesce_key();
+                    queue.retain(|entry| {
+                        entry.event.event_type() != event_type || entry.event.coalesce_key() != key
+                    });
+                }
+                Some(EventDelivery::PerFrame(limit)) => {
+                    let is_type = |entry: &Entry| entry.event.event_type() == event_type;
+                    if queue.iter().filter(|entry| is_type(entry)).count() >= *limit as usize {
+                        if let Some(oldest) = queue.iter().position(is_type) {
+                            queue.remove(oldest);
+                        }
+                    }
+                }
+            }
+
+            if queue.len() >= self.capacity {
+                match policy {
+                    OverflowPolicy::DropOldest => {
+                        queue.pop_front();
+                    }
+                    OverflowPolicy::DropNewest => continue,
+                    OverflowPolicy::Fail => {
+                        errors.push(QueueError::Full {
+                            plugin,
+                            event_type,
+                            capacity: self.capacity,
+                        });
+                        continue;
+                    }
+                }
+            }
+
+            queue.push_back(Entry {
+                sequence,
+                rank,
+                source,
+                event: event.clone(),
+            });
+        }
+
+        errors
+    }
+
+    /// Number of events queued for all plugins.
+    pub fn queued(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Empty the queues, returning the events by priority class and
+    /// then in the order they were queued, each with the plugins that
+    /// still have it queued.
+    pub fn drain(&mut self) -> Vec<QueuedDispatch> {
+        // Receivers are collected with their rank, to be sorted.
+        let mut dispatches: BTreeMap<(EventPriority, u64), (QueuedEvent, u32, Vec<Ranked>)> =
+            BTreeMap::new();
+
+        for (plugin, queue) in self.queues.iter_mut().enumerate() {
+            for entry in queue.drain(..) {
+                dispatches
+                    .entry((entry.event.event_type().priority(), entry.sequence))
+                    .or_insert_with(|| (entry.event, entry.source, vec![]))
+                    .2
+                    .push((entry.rank, plugin));
+            }
+        }
+
+        dispatches
+            .into_values()
+            .map(|(event, source, mut receivers)| {
+                receivers.sort_unstable();
+                QueuedDispatch {
+                    event,
+                    source,
+                    receivers: receivers.into_iter().map(|(_, plugin)| plugin).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Event type by the name used in the config, like `gamepad_axis`.
+///
+/// Only types of events that are queued have a name.
+pub fn parse_event_type(name: &str) -> Option<EventType> {
+    match name {
+        "hello" => Some(EventType::Hello),
+        "action" => Some(EventType::Action),
+        "gamepad_button" => Some(EventType::GamepadButton),
+        "gamepad_axis" => Some(EventType::GamepadAxis),
+        "mouse_move" => Some(EventType::MouseMove),
+        "http_response" => Some(EventType::HttpResponse),
+        "timer_fired" => Some(EventType::TimerFired),
+        "task_completed" => Some(EventType::TaskCompleted),
+        "damage" => Some(EventType::Damage),
+        "app_paused" => Some(EventType::AppPaused),
+        "app_resumed" => Some(EventType::AppResumed),
+        "window" => Some(EventType::Window),
+        "custom" => Some(EventType::Custom),
+        "simulation_lagging" => Some(EventType::SimulationLagging),
+        "simulation_state" => Some(EventType::SimulationState),
+        "screenshot_taken" => Some(EventType::ScreenshotTaken),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gers_events::{ActionEvent, AppPausedEvent, GamepadAxisEvent, MouseMoveEvent};
+
+    fn timer(timer_id: u64) -> QueuedEvent {
+        QueuedEvent::TimerFired(TimerFiredEvent { timer_id })
+    }
+
+    fn timer_ids(dispatches: &[QueuedDispatch]) -> Vec<u64> {
+        dispatches
+            .iter()
+            .map(|dispatch| match dispatch.event {
+                QueuedEvent::TimerFired(ref event) => event.timer_id,
+                _ => panic!("unexpected event {:?}", dispatch.event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_queued_events_stay_small() {
+        // Every receiver's queue holds a copy of the event, so one large
+        // event type would grow every entry of every queue.
+        let size = std::mem::size_of::<QueuedEvent>();
+        assert!(size <= 64, "QueuedEvent is {} bytes", size);
+    }
+
+    #[test]
+    fn test_drain_keeps_order() {
+        let mut queues = EventQueues::default();
+        assert!(queues.push(&[2, 0], timer(1)).is_empty());
+        assert!(queues.push_from(7, &[1], timer(2)).is_empty());
+        assert!(queues.push(&[0, 1, 2], timer(3)).is_empty());
+        assert_eq!(queues.queued(), 6);
+
+        let dispatches = queues.drain();
+        assert_eq!(timer_ids(&dispatches), [1, 2, 3]);
+        assert_eq!(dispatches[0].receivers, [2, 0]);
+        assert_eq!(dispatches[1].receivers, [1]);
+        assert_eq!(dispatches[2].receivers, [0, 1, 2]);
+        let sources: Vec<_> = dispatches.iter().map(|dispatch| dispatch.source).collect();
+        assert_eq!(sources, [EVENT_SOURCE_HOST, 7, EVENT_SOURCE_HOST]);
+        assert_eq!(queues.queued(), 0);
+    }
+
+    #[test]
+    fn test_drain_by_priority() {
+        let mut queues = EventQueues::default();
+        queues.push(&[0], timer(1));
+        queues.push(
+            &[0],
+            QueuedEvent::Frame(FrameEvent::Action(ActionEvent {
+                action_id: 7,
+                pressed: true,
+                value: 1.0,
+            })),
+        );
+        queues.push(
+            &[0],
+            QueuedEvent::Frame(FrameEvent::AppPaused(AppPausedEvent { reason: 0 })),
+        );
+        queues.push(&[0], timer(2));
+
+        let event_types: Vec<_> = queues
+            .drain()
+            .iter()
+            .map(|queued| queued.event.event_type())
+            .collect();
+        assert_eq!(
+            event_types,
+            [
+                EventType::AppPaused,
+                EventType::Action,
+                EventType::TimerFired,
+                EventType::TimerFired,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let mut queues = EventQueues::new(2, OverflowPolicy::DropOldest);
+        queues.set_policy(EventType::HttpResponse, OverflowPolicy::Fail);
+
+        for timer_id in 1..=3 {
+            assert!(queues.push(&[0], timer(timer_id)).is_empty());
+        }
+        assert_eq!(timer_ids(&queues.drain()), [2, 3]);
+
+        queues.set_policy(EventType::TimerFired, OverflowPolicy::DropNewest);
+        for timer_id in 1..=3 {
+            assert!(queues.push(&[0], timer(timer_id)).is_empty());
+        }
+
+        // The other plugin's queue has room.
+        let response = QueuedEvent::HttpResponse(HttpResponseEvent {
+            request_id: 1,
+            status: 200,
+            body_len: 0,
+        });
+        let errors = queues.push(&[0, 1], response);
+        assert!(matches!(
+            errors[..],
+            [QueueError::Full {
+                plugin: 0,
+                event_type: EventType::HttpResponse,
+                capacity: 2
+            }]
+        ));
+
+        let dispatches = queues.drain();
+        assert_eq!(timer_ids(&dispatches[..2]), [1, 2]);
+        assert_eq!(dispatches[2].receivers, [1]);
+    }
+
+    fn axis(axis: u32, value: f32) -> QueuedEvent {
+        QueuedEvent::Frame(FrameEvent::GamepadAxis(GamepadAxisEvent {
+            device_id: 0,
+            axis,
+            value,
+        }))
+    }
+
+    #[test]
+    fn test_event_delivery() {
+        let mut queues = EventQueues::default();
+        queues.set_delivery(0, EventType::GamepadAxis, EventDelivery::Coalesce);
+        queues.set_delivery(0, EventType::TimerFired, EventDelivery::PerFrame(2));
+        assert_eq!(
+            queues.delivery(1, EventType::GamepadAxis),
+            EventDelivery::All
+        );
+
+        queues.push(&[0, 1], axis(0, 0.1));
+        queues.push(&[0, 1], axis(1, 0.5));
+        queues.push(&[0, 1], timer(1));
+        queues.push(&[0, 1], axis(0, 0.2));
+        for timer_id in 2..=3 {
+            queues.push(&[0, 1], timer(timer_id));
+        }
+
+        // The other plugin receives every event.
+        let dispatches = queues.drain();
+        let received = |plugin: usize| -> Vec<String> {
+            dispatches
+                .iter()
+                .filter(|dispatch| dispatch.receivers.contains(&plugin))
+                .map(|dispatch| match dispatch.event {
+                    QueuedEvent::Frame(FrameEvent::GamepadAxis(ref event)) => {
+                        format!("axis {} {}", event.axis, event.value)
+                    }
+                    QueuedEvent::TimerFired(ref event) => format!("timer {}", event.timer_id),
+                    ref event => panic!("unexpected event {:?}", event),
+                })
+                .collect()
+        };
+        assert_eq!(
+            received(0),
+            ["axis 1 0.5", "axis 0 0.2", "timer 2", "timer 3"]
+        );
+        assert_eq!(received(1).len(), 6);
+
+        // Deliveries apply to each frame's events.
+        queues.set_delivery(0, EventType::GamepadAxis, EventDelivery::All);
+        queues.push(&[0], axis(0, 0.3));
+        queues.push(&[0], axis(0, 0.4));
+        assert_eq!(queues.drain().len(), 2);
+    }
+
+    fn mouse_move(device_id: u32, x: f32) -> QueuedEvent {
+        QueuedEvent::Frame(FrameEvent::MouseMove(MouseMoveEvent {
+            device_id,
+            x,
+            y: 0.0,
+        }))
+    }
+
+    #[test]
+    fn test_coalesce_mouse_moves() {
+        let mut queues = EventQueues::default();
+        queues.set_delivery(
+            0,
+            parse_event_type("mouse_move").unwrap(),
+            EventDelivery::Coalesce,
+        );
+
+        queues.push(&[0], mouse_move(0, 1.0));
+        queues.push(&[0], mouse_move(1, 2.0));
+        queues.push(&[0], mouse_move(0, 3.0));
+        queues.push(&[0], mouse_move(0, 4.0));
+
+        // The latest position of each mouse.
+        let moves: Vec<(u32, f32)> = queues
+            .drain()
+            .iter()
+            .map(|dispatch| match dispatch.event {
+                QueuedEvent::Frame(FrameEvent::MouseMove(ref event)) => (event.device_id, event.x),
+                ref event => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(moves, [(1, 2.0), (0, 4.0)]);
+    }
+
+    #[test]
+    fn test_parse_event_type() {
+        assert_eq!(
+            parse_event_type("gamepad_axis"),
+            Some(EventType::GamepadAxis)
+        );
+        assert_eq!(parse_event_type("console_command"), None);
+        assert_eq!(parse_event_type("GamepadAxis"), None);
+    }
+}
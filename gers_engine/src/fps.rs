@@ -0,0 +1,418 @@
+//! Tools for measuring and throttling FPS
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use winit::window::Window;
+
+use crate::clock::Clock;
+
+/// Time before the deadline the hybrid policy stops sleeping, and
+/// yields instead, because sleeps overshoot by up to the resolution
+/// of the OS timer.
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+pub struct FpsThrottle<C: Clock> {
+    target: Duration,
+    last_time: Instant,
+    policy: FpsThrottlePolicy,
+    /// Time between refreshes of the display, when known.
+    refresh_interval: Option<Duration>,
+    clock: C,
+}
+
+impl<C: Clock> FpsThrottle<C> {
+    /// Throttle waiting on the given clock.
+    pub fn new(target_fps: u64, policy: FpsThrottlePolicy, clock: C) -> Self {
+        Self {
+            target: frame_duration(target_fps),
+            last_time: clock.now(),
+            policy,
+            refresh_interval: None,
+            clock,
+        }
+    }
+
+    /// Target duration of a frame.
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    /// Change the target frame rate, which must be greater than zero.
+    pub fn set_target(&mut self, target_fps: u64) {
+        self.target = frame_duration(target_fps);
+    }
+
+    pub fn policy(&self) -> FpsThrottlePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: FpsThrottlePolicy) {
+        self.policy = policy;
+    }
+
+    /// Refresh rate of the display the window is on, in hertz.
+    pub fn set_refresh_rate(&mut self, refresh_rate: f64) {
+        self.refresh_interval = Some(Duration::from_secs_f64(1.0 / refresh_rate));
+    }
+
+    /// Time since the last frame to block for.
+    ///
+    /// With the present policy, presenting the frame blocks until the
+    /// display's next refresh, so that part of the target is left to
+    /// the vsync.
+    fn wait_time(&self) -> Duration {
+        match (self.policy, self.refresh_interval) {
+            (FpsThrottlePolicy::Present, Some(refresh_interval)) => {
+                self.target.saturating_sub(refresh_interval)
+            }
+            (FpsThrottlePolicy::Present, None) => Duration::ZERO,
+            _ => self.target,
+        }
+    }
+
+    /// Block the current thread until the target delta time has passed.
+    ///
+    /// Provide the instant measurement given during the last frame's call.
+    ///
+    /// Returns how far from the deadline the wait ended, in seconds, where
+    /// late is positive. Returns `None` when there was nothing to wait for.
+    pub fn throttle(&mut self, last_time: Instant) -> Option<f32> {
+        use FpsThrottlePolicy as P;
+
+        self.last_time = last_time;
+        let mut elapsed = self.clock.now() - self.last_time;
+        let wait_time = self.wait_time();
+        if elapsed > wait_time {
+            return None;
+        }
+
+        while elapsed <= wait_time {
+            match self.policy {
+                P::Off => {
+                    return None;
+                }
+                P::Present if wait_time.is_zero() => {
+                    return None;
+                }
+                P::Yield | P::Present => {
+                    self.clock.yield_now();
+                }
+                P::Sleep => {
+                    let target_end = last_time + self.target;
+                    let now = self.clock.now();
+                    if now < target_end {
+                        self.clock.sleep(Duration::from_millis(1));
+                    } else {
+                        self.clock.yield_now();
+                    }
+                }
+                P::Hybrid => {
+                    // One long sleep, then yield for the rest.
+                    let remaining = wait_time - elapsed;
+                    if remaining > SPIN_MARGIN {
+                        self.clock.sleep(remaining - SPIN_MARGIN);
+                    } else {
+                        self.clock.yield_now();
+                    }
+                }
+            }
+
+            elapsed = self.clock.now() - self.last_time;
+        }
+
+        Some((elapsed.as_secs_f64() - wait_time.as_secs_f64()) as f32)
+    }
+}
+
+fn frame_duration(target_fps: u64) -> Duration {
+    Duration::from_secs_f64(1.0 / target_fps as f64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FpsThrottlePolicy {
+    Off,
+    Yield,
+    Sleep,
+    /// Leave pacing to presenting with vsync, only yielding when the
+    /// target frame rate is below the display's refresh rate.
+    Present,
+    /// Sleep until shortly before the deadline, then yield.
+    Hybrid,
+}
+
+/// Refresh rate of the display the window is on, in hertz.
+///
+/// Monitors only report the refresh rates of their video modes, so this
+/// is the highest rate of the modes matching the monitor's resolution.
+pub fn refresh_rate(window: &Window) -> Option<f64> {
+    let monitor = window.current_monitor()?;
+    let size = monitor.size();
+
+    monitor
+        .video_modes()
+        .filter(|mode| mode.size() == size)
+        .map(|mode| mode.refresh_rate())
+        .filter(|refresh_rate| *refresh_rate > 0)
+        .max()
+        .map(f64::from)
+}
+
+/// Utility for measuring frame rate per second.
+///
+/// It takes periodic snapshots of the measured
+/// fps to slow down the value being printed.
+/// This makes it easier to read when presented to
+/// the user.
+pub struct FpsCounter {
+    dt: Box<[f32; Self::DATA_POINT_COUNT]>,
+    snapshot: f32,
+    cursor: usize,
+    /// Number of frames measured, up to the data point count.
+    dt_len: usize,
+    /// How late throttled frames ended, in seconds.
+    pacing: Box<[f32; Self::DATA_POINT_COUNT]>,
+    pacing_len: usize,
+    pacing_cursor: usize,
+}
+
+/// Frame times over the last measured frames, in milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub worst: f32,
+}
+
+/// How far from their deadline throttled frames ended, over
+/// the last measured frames, in milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PacingStats {
+    /// Late is positive.
+    pub mean: f32,
+    pub max_overshoot: f32,
+    pub max_undershoot: f32,
+}
+
+impl FpsCounter {
+    const DATA_POINT_COUNT: usize = 100;
+
+    pub fn new() -> Self {
+        Self {
+            dt: Box::new([0.0; Self::DATA_POINT_COUNT]),
+            snapshot: 0.0,
+            cursor: 0,
+            dt_len: 0,
+            pacing: Box::new([0.0; Self::DATA_POINT_COUNT]),
+            pacing_len: 0,
+            pacing_cursor: 0,
+        }
+    }
+
+    /// Record how late a throttled frame ended, as returned
+    /// by `FpsThrottle::throttle`.
+    pub fn add_pacing(&mut self, error: f32) {
+        self.pacing[self.pacing_cursor] = error;
+        self.pacing_cursor = (self.pacing_cursor + 1) % self.pacing.len();
+        self.pacing_len = (self.pacing_len + 1).min(self.pacing.len());
+    }
+
+    pub fn pacing(&self) -> PacingStats {
+        let errors = &self.pacing[..self.pacing_len];
+        if errors.is_empty() {
+            return PacingStats::default();
+        }
+
+        let sum: f32 = errors.iter().sum();
+        let max = errors.iter().copied().fold(0.0_f32, f32::max);
+        let min = errors.iter().copied().fold(0.0_f32, f32::min);
+
+        PacingStats {
+            mean: sum / errors.len() as f32 * 1000.0,
+            max_overshoot: max * 1000.0,
+            max_undershoot: -min * 1000.0,
+        }
+    }
+
+    pub fn add(&mut self, delta_time: Duration) {
+        self.dt[self.cursor] = delta_time.as_secs_f32();
+        if self.cursor == 0 {
+            self.take_snapshot();
+        }
+        self.cursor = (self.cursor + 1) % self.dt.len();
+        self.dt_len = (self.dt_len + 1).min(self.dt.len());
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        let mut frame_times = self.dt[..self.dt_len].to_vec();
+        if frame_times.is_empty() {
+            return FrameStats::default();
+        }
+        frame_times.sort_by(|a, b| a.total_cmp(b));
+
+        // Nearest rank.
+        let percentile = |p: f32| {
+            let rank = (p * frame_times.len() as f32).ceil() as usize;
+            frame_times[rank.clamp(1, frame_times.len()) - 1] * 1000.0
+        };
+
+        FrameStats {
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            worst: frame_times[frame_times.len() - 1] * 1000.0,
+        }
+    }
+
+    fn take_snapshot(&mut self) {
+        let sum: f32 = self.dt.iter().fold(0.0, |acc, el| acc + *el);
+        let avg = sum / self.dt.len() as f32;
+        // Approximately not zero
+        if avg.abs() > f32::EPSILON {
+            self.snapshot = 1.0 / avg;
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[test]
+    fn test_present_leaves_refresh_to_vsync() {
+        let mut throttle = FpsThrottle::new(100, FpsThrottlePolicy::Present, SystemClock);
+        // Unknown refresh rate, so vsync does all the pacing.
+        assert_eq!(throttle.wait_time(), Duration::ZERO);
+
+        throttle.set_refresh_rate(250.0);
+        assert_eq!(throttle.wait_time(), Duration::from_millis(6));
+
+        // Targets above the refresh rate are paced by vsync alone.
+        throttle.set_refresh_rate(60.0);
+        assert_eq!(throttle.wait_time(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_hybrid_waits_for_target() {
+        let mut throttle = FpsThrottle::new(200, FpsThrottlePolicy::Hybrid, SystemClock);
+        let start = Instant::now();
+
+        let error = throttle.throttle(start).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(5));
+        assert!(error >= 0.0);
+
+        // Already past the target.
+        std::thread::sleep(Duration::from_millis(6));
+        assert_eq!(throttle.throttle(start), None);
+    }
+
+    #[test]
+    fn test_throttle_on_test_clock() {
+        use crate::clock::TestClock;
+
+        let frame = Duration::from_millis(10);
+        let waits = [
+            (FpsThrottlePolicy::Yield, frame + TestClock::YIELD_STEP),
+            // Sleeps to the deadline, then yields past it.
+            (FpsThrottlePolicy::Sleep, frame + TestClock::YIELD_STEP),
+            // Sleeps until the spin margin, then yields.
+            (FpsThrottlePolicy::Hybrid, frame + TestClock::YIELD_STEP),
+        ];
+        for (policy, waited) in waits {
+            let clock = TestClock::new();
+            let mut throttle = FpsThrottle::new(100, policy, clock.clone());
+
+            let error = throttle.throttle(clock.now()).unwrap();
+            assert_eq!(clock.elapsed(), waited, "{:?}", policy);
+            let late = (waited - frame).as_secs_f32();
+            assert!((error - late).abs() < 1e-6, "{:?}: {}", policy, error);
+
+            // Already past the target.
+            let last_time = clock.now();
+            clock.advance(Duration::from_millis(20));
+            assert_eq!(throttle.throttle(last_time), None);
+            assert_eq!(clock.elapsed(), waited + Duration::from_millis(20));
+        }
+
+        let clock = TestClock::new();
+        let mut throttle = FpsThrottle::new(100, FpsThrottlePolicy::Off, clock.clone());
+        assert_eq!(throttle.throttle(clock.now()), None);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_fps_snapshot() {
+        let mut counter = FpsCounter::new();
+        // Snapshots are taken each time the window wraps around, and
+        // one of frames too short to measure is skipped, rather than
+        // divided by.
+        counter.add(Duration::ZERO);
+        assert_eq!(counter.fps(), 0.0);
+
+        for _ in 0..FpsCounter::DATA_POINT_COUNT {
+            counter.add(Duration::from_millis(20));
+        }
+        assert!((counter.fps() - 50.0).abs() < 1e-3);
+
+        // The last snapshot is kept.
+        for _ in 0..FpsCounter::DATA_POINT_COUNT {
+            counter.add(Duration::ZERO);
+        }
+        assert!((counter.fps() - 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut counter = FpsCounter::new();
+        assert_eq!(counter.frame_stats(), FrameStats::default());
+
+        // 1ms to 100ms, out of order.
+        for millis in (1..=100).rev() {
+            counter.add(Duration::from_millis(millis));
+        }
+        let stats = counter.frame_stats();
+        assert!((stats.p50 - 50.0).abs() < 1e-3);
+        assert!((stats.p95 - 95.0).abs() < 1e-3);
+        assert!((stats.p99 - 99.0).abs() < 1e-3);
+        assert!((stats.worst - 100.0).abs() < 1e-3);
+
+        // The worst frame drops out of the window.
+        counter.add(Duration::from_millis(1));
+        assert!((counter.frame_stats().worst - 99.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_pacing_stats() {
+        let mut counter = FpsCounter::new();
+        assert_eq!(counter.pacing(), PacingStats::default());
+
+        counter.add_pacing(0.002);
+        counter.add_pacing(-0.001);
+        counter.add_pacing(0.002);
+        let pacing = counter.pacing();
+        assert!((pacing.mean - 1.0).abs() < 1e-4);
+        assert!((pacing.max_overshoot - 2.0).abs() < 1e-4);
+        assert!((pacing.max_undershoot - 1.0).abs() < 1e-4);
+
+        // Only the last frames are kept.
+        for _ in 0..FpsCounter::DATA_POINT_COUNT {
+            counter.add_pacing(0.0005);
+        }
+        assert!((counter.pacing().max_overshoot - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_set_target() {
+        let mut throttle = FpsThrottle::new(100, FpsThrottlePolicy::Yield, SystemClock);
+        assert_eq!(throttle.target(), Duration::from_millis(10));
+
+        throttle.set_target(10);
+        assert_eq!(throttle.target(), Duration::from_millis(100));
+        assert_eq!(throttle.wait_time(), throttle.target());
+    }
+}
@@ -0,0 +1,200 @@
+//! Gamepad and controller input.
+//!
+//! Controllers are read through `gilrs` when the `gamepad` feature
+//! is enabled, otherwise no gamepad events are produced.
+use gers_events::{GamepadAxisEvent, GamepadButtonEvent};
+
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+pub enum GamepadEvent {
+    Button(GamepadButtonEvent),
+    Axis(GamepadAxisEvent),
+}
+
+pub use imp::Gamepads;
+
+#[cfg(feature = "gamepad")]
+mod imp {
+    use gers_events::{GamepadAxis, GamepadAxisEvent, GamepadButton, GamepadButtonEvent};
+    use gilrs::{Axis, Button, EventType, Gilrs};
+    use slog::Logger;
+
+    use super::GamepadEvent;
+
+    pub struct Gamepads {
+        gilrs: Option<Gilrs>,
+        logger: Logger,
+    }
+
+    impl Gamepads {
+        pub fn new(logger: Logger) -> Self {
+            let gilrs = match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(err) => {
+                    slog::warn!(logger, "gamepad support unavailable: {}", err);
+                    None
+                }
+            };
+
+            Self { gilrs, logger }
+        }
+
+        /// Take the gamepad events received since the last poll.
+        pub fn poll(&mut self) -> Vec<GamepadEvent> {
+            let gilrs = match self.gilrs.as_mut() {
+                Some(gilrs) => gilrs,
+                None => return vec![],
+            };
+
+            let mut events = vec![];
+
+            while let Some(event) = gilrs.next_event() {
+                let device_id = Into::<usize>::into(event.id) as u32;
+
+                match event.event {
+                    EventType::ButtonPressed(button, _) => {
+                        events.push(GamepadEvent::Button(GamepadButtonEvent {
+                            device_id,
+                            button: map_button(button) as u32,
+                            pressed: true,
+                            value: 1.0,
+                        }));
+                    }
+                    EventType::ButtonReleased(button, _) => {
+                        events.push(GamepadEvent::Button(GamepadButtonEvent {
+                            device_id,
+                            button: map_button(button) as u32,
+                            pressed: false,
+                            value: 0.0,
+                        }));
+                    }
+                    EventType::ButtonChanged(button, value, _) => {
+                        events.push(GamepadEvent::Button(GamepadButtonEvent {
+                            device_id,
+                            button: map_button(button) as u32,
+                            pressed: value > 0.5,
+                            value,
+                        }));
+                    }
+                    EventType::AxisChanged(axis, value, _) => {
+                        events.push(GamepadEvent::Axis(GamepadAxisEvent {
+                            device_id,
+                            axis: map_axis(axis) as u32,
+                            value,
+                        }));
+                    }
+                    EventType::Connected => {
+                        let name = gilrs.gamepad(event.id).name().to_string();
+                        slog::info!(self.logger, "gamepad {} connected: {}", device_id, name);
+                    }
+                    EventType::Disconnected => {
+                        slog::info!(self.logger, "gamepad {} disconnected", device_id);
+                    }
+                    EventType::ButtonRepeated(..) | EventType::Dropped => {}
+                }
+            }
+
+            events
+        }
+    }
+
+    fn map_button(button: Button) -> GamepadButton {
+        match button {
+            Button::South => GamepadButton::South,
+            Button::East => GamepadButton::East,
+            Button::North => GamepadButton::North,
+            Button::West => GamepadButton::West,
+            Button::LeftTrigger => GamepadButton::LeftTrigger,
+            Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+            Button::RightTrigger => GamepadButton::RightTrigger,
+            Button::RightTrigger2 => GamepadButton::RightTrigger2,
+            Button::Select => GamepadButton::Select,
+            Button::Start => GamepadButton::Start,
+            Button::Mode => GamepadButton::Mode,
+            Button::LeftThumb => GamepadButton::LeftThumb,
+            Button::RightThumb => GamepadButton::RightThumb,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            Button::C | Button::Z | Button::Unknown => GamepadButton::Unknown,
+        }
+    }
+
+    fn map_axis(axis: Axis) -> GamepadAxis {
+        match axis {
+            Axis::LeftStickX => GamepadAxis::LeftStickX,
+            Axis::LeftStickY => GamepadAxis::LeftStickY,
+            Axis::LeftZ => GamepadAxis::LeftZ,
+            Axis::RightStickX => GamepadAxis::RightStickX,
+            Axis::RightStickY => GamepadAxis::RightStickY,
+            Axis::RightZ => GamepadAxis::RightZ,
+            Axis::DPadX => GamepadAxis::DPadX,
+            Axis::DPadY => GamepadAxis::DPadY,
+            Axis::Unknown => GamepadAxis::Unknown,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_map_button() {
+            assert_eq!(
+                map_button(Button::South) as u32,
+                GamepadButton::South as u32
+            );
+            assert_eq!(
+                map_button(Button::DPadLeft) as u32,
+                GamepadButton::DPadLeft as u32
+            );
+            // Buttons without a counterpart are reported as unknown.
+            for button in [Button::C, Button::Z, Button::Unknown] {
+                assert_eq!(map_button(button) as u32, GamepadButton::Unknown as u32);
+            }
+        }
+
+        #[test]
+        fn test_map_axis() {
+            assert_eq!(
+                map_axis(Axis::LeftStickY) as u32,
+                GamepadAxis::LeftStickY as u32
+            );
+            assert_eq!(map_axis(Axis::RightZ) as u32, GamepadAxis::RightZ as u32);
+            assert_eq!(map_axis(Axis::Unknown) as u32, GamepadAxis::Unknown as u32);
+        }
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+mod imp {
+    use slog::Logger;
+
+    use super::GamepadEvent;
+
+    pub struct Gamepads;
+
+    impl Gamepads {
+        pub fn new(logger: Logger) -> Self {
+            slog::info!(
+                logger,
+                "built without the gamepad feature, controllers are disabled"
+            );
+            Self
+        }
+
+        pub fn poll(&mut self) -> Vec<GamepadEvent> {
+            vec![]
+        }
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_no_events_without_the_feature() {
+            let mut gamepads = Gamepads::new(Logger::root(slog::Discard, slog::o!()));
+            assert!(gamepads.poll().is_empty());
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Event subscriptions of plugins.
+//!
+//! Plugins that never subscribe receive every event, in load order.
+//! Once a plugin subscribes to an event type, it only receives the
+//! event types it subscribed to. Each event is dispatched to plugins
+//! from the highest priority down, where plugins that didn't subscribe
+//! have priority zero. A plugin that subscribed with the consume flag
+//! can consume the event while handling it, so lower priority plugins
+//! don't receive it.
+use gers_events::{EventType, SUBSCRIBE_CONSUME};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(Default)]
+pub struct EventHooks {
+    hooks: HashMap<EventType, Vec<Hook>>,
+    /// Plugins with at least one subscription.
+    subscribers: HashSet<PathBuf>,
+    /// Event that is being dispatched.
+    dispatching: Option<EventType>,
+    consumed: bool,
+}
+
+struct Hook {
+    /// Key of the subscribed plugin.
+    owner: PathBuf,
+    priority: i32,
+    flags: u32,
+}
+
+impl EventHooks {
+    /// Subscribe the plugin at `owner` to an event type, replacing
+    /// its earlier subscription to the same type.
+    pub fn subscribe(&mut self, owner: &Path, event_type: EventType, priority: i32, flags: u32) {
+        let hooks = self.hooks.entry(event_type).or_default();
+        hooks.retain(|hook| hook.owner != owner);
+        hooks.push(Hook {
+            owner: owner.to_path_buf(),
+            priority,
+            flags,
+        });
+
+        self.subscribers.insert(owner.to_path_buf());
+    }
+
+    /// Plugins that receive an event, as indices into `keys`, in the
+    /// order they receive it.
+    ///
+    /// The keys are of the loaded plugins, in load order.
+    pub fn dispatch_order(&self, event_type: EventType, keys: &[&Path]) -> Vec<usize> {
+        let hooks = self.hooks.get(&event_type);
+        let mut order: Vec<(usize, i32)> = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(index, key)| {
+                let hook = hooks.and_then(|hooks| hooks.iter().find(|hook| hook.owner == *key));
+                match hook {
+                    Some(hook) => Some((index, hook.priority)),
+                    None if !self.subscribers.contains(*key) => Some((index, 0)),
+                    None => None,
+                }
+            })
+            .collect();
+
+        // Stable, so equal priorities keep the load order.
+        order.sort_by_key(|(_, priority)| Reverse(*priority));
+        order.into_iter().map(|(index, _)| index).collect()
+    }
+
+    /// Some plugin subscribed to the event type with the consume flag,
+    /// so the event may not reach every plugin.
+    pub fn can_consume(&self, event_type: EventType) -> bool {
+        self.hooks
+            .get(&event_type)
+            .is_some_and(|hooks| hooks.iter().any(|hook| hook.flags & SUBSCRIBE_CONSUME != 0))
+    }
+
+    /// Start dispatching an event, clearing the consumed flag.
+    pub fn begin_dispatch(&mut self, event_type: EventType) {
+        self.dispatching = Some(event_type);
+        self.consumed = false;
+    }
+
+    /// Consume the event being dispatched, on behalf of the plugin at `owner`.
+    ///
+    /// Returns `true` if the plugin subscribed to the event with the
+    /// consume flag.
+    pub fn consume(&mut self, owner: &Path) -> bool {
+        let allowed = self
+            .dispatching
+            .and_then(|event_type| self.hooks.get(&event_type))
+            .and_then(|hooks| hooks.iter().find(|hook| hook.owner == owner))
+            .map(|hook| hook.flags & SUBSCRIBE_CONSUME != 0)
+            .unwrap_or(false);
+        self.consumed |= allowed;
+
+        allowed
+    }
+
+    /// Drop every subscription of the plugin at `owner`, which then
+    /// receives every event again.
+    ///
+    /// Returns the number of subscriptions dropped.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let mut count = 0;
+        for hooks in self.hooks.values_mut() {
+            let subscribed = hooks.len();
+            hooks.retain(|hook| hook.owner != owner);
+            count += subscribed - hooks.len();
+        }
+        self.subscribers.remove(owner);
+
+        count
+    }
+
+    /// The event was consumed since dispatch began, and shouldn't be
+    /// sent to the remaining plugins.
+    pub fn is_consumed(&self) -> bool {
+        self.consumed
+    }
+
+    /// Stop dispatching the event.
+    pub fn end_dispatch(&mut self) {
+        self.dispatching = None;
+        self.consumed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_order() {
+        let roots = [
+            Path::new("a"),
+            Path::new("b"),
+            Path::new("c"),
+            Path::new("d"),
+        ];
+        let mut hooks = EventHooks::default();
+
+        // Without subscriptions everyone receives everything in load order.
+        assert_eq!(
+            hooks.dispatch_order(EventType::Action, &roots),
+            [0, 1, 2, 3]
+        );
+
+        hooks.subscribe(roots[2], EventType::Action, 10, 0);
+        hooks.subscribe(roots[3], EventType::Action, -5, 0);
+        hooks.subscribe(roots[1], EventType::Hello, 1, 0);
+        assert_eq!(hooks.dispatch_order(EventType::Action, &roots), [2, 0, 3]);
+        assert_eq!(hooks.dispatch_order(EventType::Hello, &roots), [1, 0]);
+
+        // Subscribing again replaces the priority.
+        hooks.subscribe(roots[2], EventType::Action, -10, 0);
+        assert_eq!(hooks.dispatch_order(EventType::Action, &roots), [0, 3, 2]);
+    }
+
+    #[test]
+    fn test_consume() {
+        let (ui, game) = (Path::new("ui"), Path::new("game"));
+        let mut hooks = EventHooks::default();
+        hooks.subscribe(ui, EventType::Action, 100, SUBSCRIBE_CONSUME);
+        hooks.subscribe(game, EventType::Action, 0, 0);
+        assert!(hooks.can_consume(EventType::Action));
+        assert!(!hooks.can_consume(EventType::Hello));
+
+        // Only while dispatching.
+        assert!(!hooks.consume(ui));
+
+        hooks.begin_dispatch(EventType::Action);
+        assert!(!hooks.consume(game));
+        assert!(!hooks.is_consumed());
+        assert!(hooks.consume(ui));
+        assert!(hooks.is_consumed());
+        hooks.end_dispatch();
+
+        // Subscriptions are per event type.
+        hooks.begin_dispatch(EventType::Hello);
+        assert!(!hooks.consume(ui));
+        hooks.end_dispatch();
+    }
+}
@@ -0,0 +1,380 @@
+//! HTTP requests for plugins.
+//!
+//! Requests are executed on a small pool of worker threads, so they
+//! don't stall the frame. Once a request completes, the response is
+//! delivered to the plugin that made it as an `HttpResponseEvent`. The
+//! response body can be read while the event is handled, and is dropped
+//! at the end of the frame. Plugins need the `http` permission.
+use gers_events::{HttpMethod, HttpResponseEvent};
+use gers_handles::{Handle, HandleTable};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Handle given to guests to refer to a request.
+pub type RequestId = Handle;
+
+/// Default number of requests a single plugin may have in flight.
+pub const DEFAULT_REQUEST_LIMIT: usize = 8;
+
+/// Number of threads executing requests.
+const WORKER_COUNT: usize = 2;
+
+/// Response bodies are cut off at this size.
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+pub struct Http {
+    /// Queue of the worker threads, started on the first request.
+    jobs: Option<Sender<Job>>,
+    completed_sender: Sender<Completed>,
+    completed: Receiver<Completed>,
+    /// Requests in flight, with the key of the plugin that made
+    /// each.
+    pending: HandleTable<(), PathBuf>,
+    /// Responses delivered during the current frame.
+    delivered: HashMap<RequestId, Delivered>,
+    request_limit: usize,
+}
+
+struct Job {
+    id: RequestId,
+    method: &'static str,
+    url: String,
+    body: Vec<u8>,
+}
+
+struct Completed {
+    id: RequestId,
+    result: Result<(u16, Vec<u8>), String>,
+}
+
+struct Delivered {
+    /// Key of the plugin that made the request.
+    owner: PathBuf,
+    body: Vec<u8>,
+}
+
+/// Completed request, to be dispatched to the plugin that made it.
+pub struct HttpResponse {
+    /// Key of the plugin that made the request.
+    pub owner: PathBuf,
+    pub event: HttpResponseEvent,
+    /// Why the request failed without a response.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    RequestLimit(usize),
+    UnknownRequest(RequestId),
+    UnknownMethod,
+    Io(io::Error),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::RequestLimit(limit) => {
+                write!(f, "plugin request limit reached: {}", limit)
+            }
+            HttpError::UnknownRequest(id) => write!(f, "unknown request: {}", id),
+            HttpError::UnknownMethod => write!(f, "unknown request method"),
+            HttpError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> Self {
+        HttpError::Io(err)
+    }
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        let (completed_sender, completed) = mpsc::channel();
+
+        Self {
+            jobs: None,
+            completed_sender,
+            completed,
+            pending: HandleTable::new(),
+            delivered: HashMap::new(),
+            request_limit: DEFAULT_REQUEST_LIMIT,
+        }
+    }
+}
+
+impl Http {
+    /// Queue a request on behalf of the plugin at `owner`.
+    pub fn request(
+        &mut self,
+        owner: &Path,
+        method: HttpMethod,
+        url: String,
+        body: Vec<u8>,
+    ) -> Result<RequestId, HttpError> {
+        let method = method_name(method).ok_or(HttpError::UnknownMethod)?;
+
+        if self.pending.count_owned(owner) >= self.request_limit {
+            return Err(HttpError::RequestLimit(self.request_limit));
+        }
+
+        let jobs = self.jobs()?.clone();
+        let id = self.pending.insert(owner.to_path_buf(), ());
+        let job = Job {
+            id,
+            method,
+            url,
+            body,
+        };
+        if jobs.send(job).is_err() {
+            let _ = self.pending.remove(id);
+            return Err(io::Error::other("request workers stopped").into());
+        }
+
+        Ok(id)
+    }
+
+    /// Take the requests that completed since the last poll.
+    ///
+    /// Their bodies are kept until `end_frame`.
+    pub fn poll(&mut self) -> Vec<HttpResponse> {
+        let mut responses = vec![];
+
+        while let Ok(Completed { id, result }) = self.completed.try_recv() {
+            // Requests of released plugins are no longer pending.
+            let owner = match self.pending.owner(id) {
+                Ok(owner) => owner.clone(),
+                Err(_) => continue,
+            };
+            let _ = self.pending.remove(id);
+
+            let (status, body, error) = match result {
+                Ok((status, body)) => (status as u32, body, None),
+                Err(err) => (0, vec![], Some(err)),
+            };
+
+            responses.push(HttpResponse {
+                owner: owner.clone(),
+                event: HttpResponseEvent {
+                    request_id: id.to_raw(),
+                    status,
+                    body_len: body.len() as u32,
+                },
+                error,
+            });
+            self.delivered.insert(id, Delivered { owner, body });
+        }
+
+        responses
+    }
+
+    /// Body of a response delivered during the current frame.
+    pub fn body(&self, owner: &Path, id: RequestId) -> Result<&[u8], HttpError> {
+        match self.delivered.get(&id) {
+            Some(delivered) if delivered.owner == owner => Ok(&delivered.body),
+            _ => Err(HttpError::UnknownRequest(id)),
+        }
+    }
+
+    /// Forget the requests the plugin at `owner` made. Requests still
+    /// in flight complete, but their responses are dropped.
+    ///
+    /// Returns the number of requests in flight.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        self.delivered
+            .retain(|_, delivered| delivered.owner != owner);
+        self.pending.release(owner).len()
+    }
+
+    /// Drop the bodies of the responses delivered during the frame.
+    pub fn end_frame(&mut self) {
+        self.delivered.clear();
+    }
+
+    fn jobs(&mut self) -> io::Result<&Sender<Job>> {
+        if self.jobs.is_none() {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            let receiver = Arc::new(Mutex::new(receiver));
+
+            for _ in 0..WORKER_COUNT {
+                let receiver = receiver.clone();
+                let completed = self.completed_sender.clone();
+                thread::Builder::new()
+                    .name("gers-http".to_string())
+                    .spawn(move || loop {
+                        let job = match receiver.lock().map(|receiver| receiver.recv()) {
+                            Ok(Ok(job)) => job,
+                            // Queue is gone when the host shuts down.
+                            _ => return,
+                        };
+                        let result = execute(&job);
+                        if completed.send(Completed { id: job.id, result }).is_err() {
+                            return;
+                        }
+                    })?;
+            }
+
+            self.jobs = Some(sender);
+        }
+
+        Ok(self.jobs.as_ref().expect("workers started"))
+    }
+}
+
+fn method_name(method: HttpMethod) -> Option<&'static str> {
+    match method {
+        HttpMethod::Get => Some("GET"),
+        HttpMethod::Post => Some("POST"),
+        HttpMethod::Put => Some("PUT"),
+        HttpMethod::Delete => Some("DELETE"),
+        HttpMethod::Head => Some("HEAD"),
+        HttpMethod::Patch => Some("PATCH"),
+        HttpMethod::Unknown => None,
+    }
+}
+
+/// Perform the request, returning the status and body of the response.
+///
+/// Error statuses are still responses, only requests that failed
+/// without a response are errors.
+fn execute(job: &Job) -> Result<(u16, Vec<u8>), String> {
+    let request = ureq::request(job.method, &job.url);
+    let result = if job.body.is_empty() {
+        request.call()
+    } else {
+        request.send_bytes(&job.body)
+    };
+
+    let response = match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let status = response.status();
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_end(&mut body)
+        .map_err(|err| err.to_string())?;
+
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        net::TcpListener,
+        time::{Duration, Instant},
+    };
+
+    /// Serve a single canned response on a local port.
+    fn serve_once(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(response).unwrap();
+        });
+
+        url
+    }
+
+    fn wait_response(http: &mut Http) -> HttpResponse {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(response) = http.poll().pop() {
+                return response;
+            }
+            assert!(Instant::now() < deadline, "request timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_request_and_body() {
+        let url = serve_once(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope");
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut http = Http::default();
+
+        let id = http.request(owner, HttpMethod::Get, url, vec![]).unwrap();
+        assert!(!id.is_null());
+        let response = wait_response(&mut http);
+        assert_eq!(response.owner, owner);
+        assert_eq!(response.event.request_id, id.to_raw());
+        // Error statuses are delivered like any other response.
+        assert_eq!(response.event.status, 404);
+        assert_eq!(response.event.body_len, 4);
+        assert!(response.error.is_none());
+
+        assert_eq!(http.body(owner, id).unwrap(), b"nope");
+        assert!(matches!(
+            http.body(other, id),
+            Err(HttpError::UnknownRequest(_))
+        ));
+
+        http.end_frame();
+        assert!(matches!(
+            http.body(owner, id),
+            Err(HttpError::UnknownRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_request() {
+        let owner = Path::new("plugin");
+        let mut http = Http::default();
+
+        let id = http
+            .request(owner, HttpMethod::Post, "not a url".to_string(), vec![1])
+            .unwrap();
+        let response = wait_response(&mut http);
+        assert_eq!(response.event.request_id, id.to_raw());
+        assert_eq!(response.event.status, 0);
+        assert!(response.error.is_some());
+    }
+
+    #[test]
+    fn test_request_limit() {
+        let owner = Path::new("plugin");
+        let mut http = Http::default();
+
+        assert!(matches!(
+            http.request(owner, HttpMethod::Unknown, String::new(), vec![]),
+            Err(HttpError::UnknownMethod)
+        ));
+
+        // Requests are only counted, not completed, until polled.
+        for _ in 0..DEFAULT_REQUEST_LIMIT {
+            http.request(owner, HttpMethod::Get, "not a url".to_string(), vec![])
+                .unwrap();
+        }
+        assert!(matches!(
+            http.request(owner, HttpMethod::Get, "not a url".to_string(), vec![]),
+            Err(HttpError::RequestLimit(DEFAULT_REQUEST_LIMIT))
+        ));
+        // The limit is per plugin.
+        http.request(
+            Path::new("other"),
+            HttpMethod::Get,
+            "not a url".to_string(),
+            vec![],
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! Localized strings of plugins.
+//!
+//! Plugins ship a string table for each locale they support, in
+//! `lang/<locale>.toml`, mapping keys to strings. Nested tables are
+//! flattened into dotted keys:
+//!
+//! ```toml
+//! greeting = "Hallo"
+//!
+//! [menu]
+//! start = "Spiel starten" # menu.start
+//! ```
+//!
+//! The tables of the active locale are loaded for every plugin, merged
+//! over those of the fallback locale, so untranslated keys still
+//! resolve. Switching locale loads the tables again, and plugins
+//! receive a `LocaleChangedEvent`.
+use gers_events::LOCALE_SIZE;
+use gers_plugins::PluginSource;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Directory inside a plugin holding its string tables.
+pub const LANG_DIR: &str = "lang";
+
+/// Locale used when none is configured.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// String tables of the loaded plugins, in the active locale.
+pub struct Localization {
+    locale: String,
+    fallback: String,
+    /// Strings by key, by key of the plugin.
+    tables: HashMap<PathBuf, HashMap<String, String>>,
+}
+
+#[derive(Debug)]
+pub enum LocaleError {
+    /// Locales are ASCII letters, digits, `-` and `_`.
+    InvalidLocale(String),
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for LocaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocaleError::InvalidLocale(locale) => write!(f, "invalid locale: {:?}", locale),
+            LocaleError::Read(path, err) => {
+                write!(f, "failed reading {}: {}", path.display(), err)
+            }
+            LocaleError::Parse(path, err) => {
+                write!(f, "invalid string table {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self {
+            locale: DEFAULT_LOCALE.to_string(),
+            fallback: DEFAULT_LOCALE.to_string(),
+            tables: HashMap::new(),
+        }
+    }
+}
+
+/// Locale fits a `LocaleChangedEvent`, and a file name.
+pub fn is_valid_locale(locale: &str) -> bool {
+    !locale.is_empty()
+        && locale.len() <= LOCALE_SIZE
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+impl Localization {
+    /// Empty tables, in `locale` falling back to `fallback`.
+    pub fn new(locale: &str, fallback: &str) -> Result<Self, LocaleError> {
+        for locale in [locale, fallback] {
+            if !is_valid_locale(locale) {
+                return Err(LocaleError::InvalidLocale(locale.to_string()));
+            }
+        }
+
+        Ok(Self {
+            locale: locale.to_string(),
+            fallback: fallback.to_string(),
+            tables: HashMap::new(),
+        })
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn fallback(&self) -> &str {
+        &self.fallback
+    }
+
+    /// Load the string tables of the plugin `owner` from its source.
+    ///
+    /// Plugins without a table for either locale have no strings.
+    pub fn load_plugin(&mut self, owner: &Path, source: &PluginSource) -> Result<(), LocaleError> {
+        let mut strings = HashMap::new();
+        let mut locales = vec![self.fallback.as_str()];
+        if self.locale != self.fallback {
+            locales.push(&self.locale);
+        }
+
+        for locale in locales {
+            let path = Path::new(LANG_DIR).join(format!("{}.toml", locale));
+            let bytes = match source.read_file(&path) {
+                Ok(bytes) => bytes,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(LocaleError::Read(source.path().join(path), err)),
+            };
+            parse_table(&bytes, &mut strings)
+                .map_err(|err| LocaleError::Parse(source.path().join(&path), err))?;
+        }
+
+        self.tables.insert(owner.to_path_buf(), strings);
+
+        Ok(())
+    }
+
+    /// String of a key in the plugin `owner`.
+    pub fn get(&self, owner: &Path, key: &str) -> Option<&str> {
+        self.tables
+            .get(owner)
+            .and_then(|strings| strings.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// Add the strings of a table to `strings`, replacing existing keys.
+fn parse_table(bytes: &[u8], strings: &mut HashMap<String, String>) -> Result<(), String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "not valid UTF-8".to_string())?;
+    let table: toml::value::Table = toml::from_str(text).map_err(|err| err.to_string())?;
+
+    flatten("", table, strings)
+}
+
+fn flatten(
+    prefix: &str,
+    table: toml::value::Table,
+    strings: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            toml::Value::String(string) => {
+                strings.insert(key, string);
+            }
+            toml::Value::Table(table) => flatten(&key, table, strings)?,
+            other => {
+                return Err(format!(
+                    "{}: expected a string, found {}",
+                    key,
+                    other.type_str()
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_locales() {
+        for locale in ["en", "pt-BR", "zh_Hant"] {
+            assert!(is_valid_locale(locale), "{:?} should be valid", locale);
+        }
+        for locale in ["", "../en", "en us", "a".repeat(LOCALE_SIZE + 1).as_str()] {
+            assert!(!is_valid_locale(locale), "{:?} should be invalid", locale);
+        }
+        assert!(matches!(
+            Localization::new("de", "../en"),
+            Err(LocaleError::InvalidLocale(_))
+        ));
+    }
+
+    #[test]
+    fn test_string_tables() {
+        let root = std::env::temp_dir().join(format!("gers-i18n-{}", std::process::id()));
+        fs::create_dir_all(root.join(LANG_DIR)).unwrap();
+        fs::write(
+            root.join(LANG_DIR).join("en.toml"),
+            "greeting = \"Hello\"\nfarewell = \"Bye\"\n[menu]\nstart = \"Start game\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(LANG_DIR).join("de.toml"),
+            "greeting = \"Hallo\"\n[menu]\nstart = \"Spiel starten\"\n",
+        )
+        .unwrap();
+        fs::write(root.join(LANG_DIR).join("fr.toml"), "greeting = 1\n").unwrap();
+        let source = PluginSource::Directory(root.clone());
+
+        let mut i18n = Localization::new("de", "en").unwrap();
+        i18n.load_plugin(&root, &source).unwrap();
+        assert_eq!(i18n.get(&root, "greeting"), Some("Hallo"));
+        assert_eq!(i18n.get(&root, "menu.start"), Some("Spiel starten"));
+        // Untranslated keys fall back.
+        assert_eq!(i18n.get(&root, "farewell"), Some("Bye"));
+        assert_eq!(i18n.get(&root, "missing"), None);
+        assert_eq!(i18n.get(Path::new("other"), "greeting"), None);
+
+        // Locales without a table have the fallback's strings.
+        let mut i18n = Localization::new("es", "en").unwrap();
+        i18n.load_plugin(&root, &source).unwrap();
+        assert_eq!(i18n.get(&root, "greeting"), Some("Hello"));
+
+        let mut i18n = Localization::new("fr", "en").unwrap();
+        assert!(matches!(
+            i18n.load_plugin(&root, &source),
+            Err(LocaleError::Parse(..))
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
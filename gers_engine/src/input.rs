@@ -0,0 +1,238 @@
+//! Mapping of device input to named actions.
+//!
+//! Plugins register actions with default bindings, either in the
+//! `[input]` section of `plugin.toml` or at runtime through the
+//! `gers_input` imports. The host resolves key presses to actions
+//! and sends `ActionEvent`s to the plugins that registered them.
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use gers_events::ActionEvent;
+use serde::{de::IntoDeserializer, Deserialize};
+use winit::event::{ElementState, VirtualKeyCode};
+
+pub type ActionId = u32;
+
+#[derive(Default)]
+pub struct ActionMap {
+    /// Actions indexed by their id minus one.
+    actions: Vec<Action>,
+    by_name: HashMap<String, ActionId>,
+    bindings: HashMap<VirtualKeyCode, Vec<ActionId>>,
+    /// Keys currently held, to filter out key repeats.
+    held_keys: HashSet<VirtualKeyCode>,
+    events: Vec<ActionEvent>,
+}
+
+struct Action {
+    /// Keys of the plugins that registered the action.
+    subscribers: Vec<PathBuf>,
+    /// Number of bound keys currently held.
+    held: u32,
+}
+
+impl ActionMap {
+    /// Register an action on behalf of the plugin at `owner`.
+    ///
+    /// Actions are shared by name, so registering an existing action
+    /// subscribes the plugin to it and adds the default bindings.
+    /// Zero is never used as an action id.
+    pub fn register(
+        &mut self,
+        owner: &Path,
+        name: &str,
+        default_keys: &[VirtualKeyCode],
+    ) -> ActionId {
+        let action_id = match self.by_name.get(name) {
+            Some(action_id) => *action_id,
+            None => {
+                self.actions.push(Action {
+                    subscribers: vec![],
+                    held: 0,
+                });
+                let action_id = self.actions.len() as ActionId;
+                self.by_name.insert(name.to_string(), action_id);
+                action_id
+            }
+        };
+
+        let action = &mut self.actions[action_id as usize - 1];
+        if !action.subscribers.iter().any(|root| root == owner) {
+            action.subscribers.push(owner.to_path_buf());
+        }
+
+        for key in default_keys {
+            let bound = self.bindings.entry(*key).or_default();
+            if !bound.contains(&action_id) {
+                bound.push(action_id);
+            }
+        }
+
+        action_id
+    }
+
+    /// Resolve a key press or release to action events.
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        let changed = match state {
+            ElementState::Pressed => self.held_keys.insert(key),
+            ElementState::Released => self.held_keys.remove(&key),
+        };
+        if !changed {
+            return;
+        }
+
+        let action_ids = match self.bindings.get(&key) {
+            Some(action_ids) => action_ids,
+            None => return,
+        };
+
+        for action_id in action_ids {
+            let action = &mut self.actions[*action_id as usize - 1];
+            let was_pressed = action.held > 0;
+
+            match state {
+                ElementState::Pressed => action.held += 1,
+                ElementState::Released => action.held = action.held.saturating_sub(1),
+            }
+
+            let pressed = action.held > 0;
+            if pressed != was_pressed {
+                self.events.push(ActionEvent {
+                    action_id: *action_id,
+                    pressed,
+                    value: if pressed { 1.0 } else { 0.0 },
+                });
+            }
+        }
+    }
+
+    /// Plugin at `owner` registered the action.
+    pub fn is_subscribed(&self, action_id: ActionId, owner: &Path) -> bool {
+        self.actions
+            .get((action_id as usize).wrapping_sub(1))
+            .map(|action| action.subscribers.iter().any(|root| root == owner))
+            .unwrap_or(false)
+    }
+
+    /// Unsubscribe the plugin at `owner` from every action it
+    /// registered. Actions and their bindings stay, as other plugins
+    /// may share them.
+    ///
+    /// Returns the number of actions unsubscribed from.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let mut count = 0;
+        for action in self.actions.iter_mut() {
+            let subscribers = action.subscribers.len();
+            action.subscribers.retain(|root| root != owner);
+            count += subscribers - action.subscribers.len();
+        }
+
+        count
+    }
+
+    /// Take the action events resolved since the last call.
+    pub fn take_events(&mut self) -> Vec<ActionEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Parse a key name, as spelled in winit's `VirtualKeyCode`.
+pub fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    VirtualKeyCode::deserialize(name.into_deserializer())
+        .map_err(|_: serde::de::value::Error| ())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ElementState::{Pressed, Released};
+    use VirtualKeyCode::{Space, A, W};
+
+    /// Action ids and states of the events since the last call.
+    fn events(map: &mut ActionMap) -> Vec<(ActionId, bool)> {
+        map.take_events()
+            .iter()
+            .map(|event| (event.action_id, event.pressed))
+            .collect()
+    }
+
+    #[test]
+    fn test_register() {
+        let mut map = ActionMap::default();
+        let jump = map.register(Path::new("a"), "jump", &[Space]);
+        let fire = map.register(Path::new("a"), "fire", &[]);
+        assert_eq!((jump, fire), (1, 2));
+
+        // Actions are shared by name.
+        assert_eq!(map.register(Path::new("b"), "jump", &[]), jump);
+        assert!(map.is_subscribed(jump, Path::new("a")));
+        assert!(map.is_subscribed(jump, Path::new("b")));
+        assert!(!map.is_subscribed(fire, Path::new("b")));
+        assert!(!map.is_subscribed(0, Path::new("a")));
+        assert!(!map.is_subscribed(3, Path::new("a")));
+    }
+
+    #[test]
+    fn test_press_and_release_across_frames() {
+        let mut map = ActionMap::default();
+        let jump = map.register(Path::new("a"), "jump", &[Space, W]);
+
+        map.handle_key(Space, Pressed);
+        assert_eq!(events(&mut map), [(jump, true)]);
+
+        // Key repeats while held don't press the action again.
+        map.handle_key(Space, Pressed);
+        assert!(events(&mut map).is_empty());
+
+        // The action stays pressed while any bound key is held.
+        map.handle_key(W, Pressed);
+        map.handle_key(Space, Released);
+        assert!(events(&mut map).is_empty());
+        map.handle_key(W, Released);
+        assert_eq!(events(&mut map), [(jump, false)]);
+
+        // Releasing a key that wasn't held, or isn't bound, does nothing.
+        map.handle_key(W, Released);
+        map.handle_key(A, Pressed);
+        assert!(events(&mut map).is_empty());
+    }
+
+    #[test]
+    fn test_event_values() {
+        let mut map = ActionMap::default();
+        map.register(Path::new("a"), "jump", &[Space]);
+        map.handle_key(Space, Pressed);
+        map.handle_key(Space, Released);
+
+        let values: Vec<f32> = map.take_events().iter().map(|event| event.value).collect();
+        assert_eq!(values, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let mut map = ActionMap::default();
+        let jump = map.register(Path::new("a"), "jump", &[Space]);
+        // Registering again adds bindings, and keeps the old ones.
+        map.register(Path::new("a"), "jump", &[W, Space]);
+
+        map.handle_key(W, Pressed);
+        map.handle_key(W, Released);
+        map.handle_key(Space, Pressed);
+        assert_eq!(
+            events(&mut map),
+            [(jump, true), (jump, false), (jump, true)]
+        );
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("Space"), Some(Space));
+        assert_eq!(parse_key("A"), Some(A));
+        assert_eq!(parse_key("Key1"), Some(VirtualKeyCode::Key1));
+        assert_eq!(parse_key("space"), None);
+        assert_eq!(parse_key(""), None);
+    }
+}
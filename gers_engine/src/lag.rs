@@ -0,0 +1,221 @@
+//! Budget for the fixed updates of a frame.
+//!
+//! Without a budget, a frame runs at most one fixed update, and skips
+//! any it fell behind on. With a budget, a frame catches up on the fixed
+//! updates that are due, as many as fit into the budget going by how
+//! long dispatching the frame's events took per update before. The rest
+//! are carried over to the next frames or dropped, depending on the
+//! `LagPolicy`, and plugins receive a `SimulationLaggingEvent`, so games
+//! can respond, like by slowing down while they catch up.
+//!
+//! Updates are counted when the frame's events are gathered, so
+//! recordings hold the updates that ran, and replays run the same ones.
+//! Dispatch is timed on the frame loop's clock.
+use gers_events::SimulationLaggingEvent;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+
+/// What happens to fixed updates that don't fit into a frame's budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LagPolicy {
+    /// Run them in the next frames, catching up with real time.
+    #[default]
+    Carryover,
+    /// Skip them, so the simulation falls behind real time.
+    Drop,
+}
+
+/// Weight of the latest measurement in the estimated cost of an update.
+const COST_SMOOTHING: f64 = 0.25;
+
+/// Decides how many fixed updates each frame runs.
+pub struct TickBudget<C: Clock> {
+    /// Duration of a fixed update, in seconds.
+    interval: f64,
+    budget: Option<Duration>,
+    policy: LagPolicy,
+    /// Estimated time to dispatch a frame's events per fixed update,
+    /// in seconds, or zero before anything was measured.
+    tick_cost: f64,
+    clock: C,
+    dispatch_start: Option<Instant>,
+}
+
+impl<C: Clock> TickBudget<C> {
+    /// Budget measuring dispatch on the given clock.
+    pub fn new(interval: f64, budget: Option<Duration>, policy: LagPolicy, clock: C) -> Self {
+        Self {
+            interval,
+            budget,
+            policy,
+            tick_cost: 0.0,
+            clock,
+            dispatch_start: None,
+        }
+    }
+
+    /// Take the fixed updates to run this frame out of the time
+    /// accumulated in `timer`.
+    ///
+    /// Returns the number of updates, and the event for plugins when
+    /// some that were due don't run.
+    pub fn take(&self, timer: &mut Duration) -> (u32, Option<SimulationLaggingEvent>) {
+        let elapsed = timer.as_secs_f64();
+        let due = (elapsed / self.interval) as u32;
+        if due == 0 {
+            return (0, None);
+        }
+
+        let budget = match self.budget {
+            Some(budget) => budget.as_secs_f64(),
+            None => {
+                *timer = Duration::from_secs_f64(elapsed % self.interval);
+                return (1, None);
+            }
+        };
+
+        // At least one update runs, so the simulation never stalls.
+        let fits = if self.tick_cost > 0.0 {
+            ((budget / self.tick_cost) as u32).clamp(1, due)
+        } else {
+            due
+        };
+        let remaining = (elapsed - fits as f64 * self.interval).max(0.0);
+        let ticks_behind = due - fits;
+        if ticks_behind == 0 {
+            *timer = Duration::from_secs_f64(remaining);
+            return (fits, None);
+        }
+
+        let dropped = self.policy == LagPolicy::Drop;
+        *timer = if dropped {
+            Duration::from_secs_f64(remaining % self.interval)
+        } else {
+            Duration::from_secs_f64(remaining)
+        };
+
+        (
+            fits,
+            Some(SimulationLaggingEvent {
+                ticks_behind,
+                dropped: dropped as u32,
+            }),
+        )
+    }
+
+    /// Start timing the dispatch of a frame's events.
+    pub fn start_dispatch(&mut self) {
+        self.dispatch_start = Some(self.clock.now());
+    }
+
+    /// Measure how long dispatching a frame's events took since
+    /// `start_dispatch`, with the number of fixed updates the frame ran.
+    pub fn end_dispatch(&mut self, ticks: u32) {
+        let start = match self.dispatch_start.take() {
+            Some(start) => start,
+            None => return,
+        };
+        if ticks == 0 {
+            return;
+        }
+
+        let elapsed = self.clock.now().duration_since(start);
+
+        let cost = elapsed.as_secs_f64() / ticks as f64;
+        self.tick_cost = if self.tick_cost > 0.0 {
+            self.tick_cost + (cost - self.tick_cost) * COST_SMOOTHING
+        } else {
+            cost
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    const INTERVAL: f64 = 0.1;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    fn tick_budget(budget: Option<Duration>, policy: LagPolicy) -> TickBudget<TestClock> {
+        TickBudget::new(INTERVAL, budget, policy, TestClock::new())
+    }
+
+    /// Dispatch the events of a frame with `ticks` fixed updates, taking
+    /// `elapsed` on the clock.
+    fn dispatch(budget: &mut TickBudget<TestClock>, ticks: u32, elapsed: Duration) {
+        budget.start_dispatch();
+        budget.clock.advance(elapsed);
+        budget.end_dispatch(ticks);
+    }
+
+    #[test]
+    fn test_without_budget_skips_ticks() {
+        let budget = tick_budget(None, LagPolicy::Carryover);
+
+        let mut timer = secs(0.05);
+        assert!(matches!(budget.take(&mut timer), (0, None)));
+        assert_eq!(timer, secs(0.05));
+
+        let mut timer = secs(0.35);
+        assert!(matches!(budget.take(&mut timer), (1, None)));
+        assert!((timer.as_secs_f64() - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_catch_up_within_budget() {
+        let mut budget = tick_budget(Some(secs(0.004)), LagPolicy::Carryover);
+
+        // Nothing is known about the cost of an update yet.
+        let mut timer = secs(0.35);
+        assert!(matches!(budget.take(&mut timer), (3, None)));
+        assert!((timer.as_secs_f64() - 0.05).abs() < 1e-9);
+
+        dispatch(&mut budget, 3, secs(0.003));
+        let mut timer = secs(0.35);
+        assert!(matches!(budget.take(&mut timer), (3, None)));
+    }
+
+    #[test]
+    fn test_lag_policies() {
+        let mut carryover = tick_budget(Some(secs(0.004)), LagPolicy::Carryover);
+        dispatch(&mut carryover, 1, secs(0.002));
+
+        let mut timer = secs(0.55);
+        let (ticks, lagging) = carryover.take(&mut timer);
+        assert_eq!(ticks, 2);
+        let lagging = lagging.unwrap();
+        assert_eq!((lagging.ticks_behind, lagging.dropped), (3, 0));
+        assert!((timer.as_secs_f64() - 0.35).abs() < 1e-9);
+
+        let mut drop = tick_budget(Some(secs(0.004)), LagPolicy::Drop);
+        dispatch(&mut drop, 1, secs(0.002));
+
+        let mut timer = secs(0.55);
+        let (ticks, lagging) = drop.take(&mut timer);
+        assert_eq!(ticks, 2);
+        let lagging = lagging.unwrap();
+        assert_eq!((lagging.ticks_behind, lagging.dropped), (3, 1));
+        assert!((timer.as_secs_f64() - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_runs_one_tick_over_budget() {
+        let mut budget = tick_budget(Some(secs(0.004)), LagPolicy::Carryover);
+        dispatch(&mut budget, 1, secs(0.010));
+        dispatch(&mut budget, 1, secs(0.030));
+        assert!((budget.tick_cost - 0.015).abs() < 1e-9);
+
+        let mut timer = secs(0.25);
+        let (ticks, lagging) = budget.take(&mut timer);
+        assert_eq!(ticks, 1);
+        assert_eq!(lagging.unwrap().ticks_behind, 1);
+    }
+}
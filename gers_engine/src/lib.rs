@@ -0,0 +1,68 @@
+//! Modding runtime of gers, for embedding in games.
+//!
+//! The engine loads WebAssembly plugins, provides them the host
+//! imports, and runs the event loop timing frames, updating and
+//! rendering the plugins, and dispatching events to them. The `gers`
+//! executable is a command line around it, and games embed it the
+//! same way:
+//!
+//! ```ignore
+//! use gers_engine::{Config, Engine};
+//!
+//! Engine::builder()
+//!     .config(Config::load("game.toml")?)
+//!     .with_plugins(["mods"])
+//!     .post_update(|ctx| {
+//!         // Game logic, after the plugins handled the frame's events.
+//!     })
+//!     .run()?;
+//! ```
+mod assets;
+mod audio;
+mod clipboard;
+mod clock;
+mod config;
+mod console;
+mod crash;
+mod custom_events;
+mod engine;
+mod env;
+mod error;
+mod event_queue;
+mod fps;
+mod gamepad;
+mod hooks;
+mod http;
+mod i18n;
+mod input;
+mod lag;
+mod log_fmt;
+mod metrics;
+mod mod_list;
+mod mouse;
+mod net;
+mod overlay;
+mod pause;
+mod plugin_log;
+mod profiler;
+mod render;
+mod replay;
+mod resources;
+mod savegame;
+mod scheduler;
+mod screenshot;
+mod simulation;
+mod storage;
+mod tasks;
+mod timer;
+mod trace;
+mod wasm_api;
+mod wasm_impl;
+mod watch;
+mod window;
+
+pub use clock::{Clock, SystemClock, TestClock};
+pub use config::{Config, CONFIG_FILENAME};
+pub use engine::{Context, Engine, EngineBuilder};
+pub use render::{Canvas, Color};
+pub use simulation::SimulationControl;
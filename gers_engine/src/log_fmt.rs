@@ -0,0 +1,151 @@
+//! Messages guests log with a template and packed arguments.
+//!
+//! Formatting a message in the guest allocates a string for every log
+//! call. With `gers.log_fmt`, guests pass the template and the
+//! arguments, packed as `LogArgType` tags and their values, and the
+//! host formats the message instead. Strings are passed by pointer and
+//! length, so they aren't copied into the pack either.
+//!
+//! Each `{}` in the template is replaced with the next argument, and
+//! `{{` and `}}` are literal braces. Placeholders without an argument,
+//! and strings that can't be read, are shown as `?`.
+use gers_events::LogArgType;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum LogFmtError {
+    UnknownType(u8),
+    /// The pack ended in the middle of the argument.
+    Truncated(LogArgType),
+}
+
+impl fmt::Display for LogFmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFmtError::UnknownType(tag) => write!(f, "unknown log argument type: {}", tag),
+            LogFmtError::Truncated(arg_type) => {
+                write!(f, "log arguments end in {:?} argument", arg_type)
+            }
+        }
+    }
+}
+
+/// Format the template with the packed arguments, reading strings with
+/// `read_str`, from a pointer and a length.
+pub fn format(
+    template: &str,
+    mut args: &[u8],
+    read_str: impl Fn(u32, u32) -> Option<String>,
+) -> Result<String, LogFmtError> {
+    let mut values = vec![];
+    while let Some((&tag, rest)) = args.split_first() {
+        let arg_type = LogArgType::from(tag);
+        if arg_type == LogArgType::Unknown {
+            return Err(LogFmtError::UnknownType(tag));
+        }
+        if rest.len() < arg_type.size() {
+            return Err(LogFmtError::Truncated(arg_type));
+        }
+        let (value, rest) = rest.split_at(arg_type.size());
+        values.push(format_value(arg_type, value, &read_str));
+        args = rest;
+    }
+
+    let mut values = values.into_iter();
+    let mut message = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('}')) => {
+                chars.next();
+                let value = values.next().unwrap_or_else(|| "?".to_string());
+                message.push_str(&value);
+            }
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                message.push(c);
+            }
+            _ => message.push(c),
+        }
+    }
+
+    Ok(message)
+}
+
+/// Value of an argument, in little endian bytes of the type's size.
+fn format_value(
+    arg_type: LogArgType,
+    bytes: &[u8],
+    read_str: impl Fn(u32, u32) -> Option<String>,
+) -> String {
+    let u32_at = |offset: usize| {
+        let mut value = [0; 4];
+        value.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(value)
+    };
+    let u64_at = || u64::from(u32_at(0)) | u64::from(u32_at(4)) << 32;
+
+    match arg_type {
+        LogArgType::Unknown => "?".to_string(),
+        LogArgType::I32 => (u32_at(0) as i32).to_string(),
+        LogArgType::U32 => u32_at(0).to_string(),
+        LogArgType::I64 => (u64_at() as i64).to_string(),
+        LogArgType::U64 => u64_at().to_string(),
+        LogArgType::F32 => f32::from_bits(u32_at(0)).to_string(),
+        LogArgType::F64 => f64::from_bits(u64_at()).to_string(),
+        LogArgType::Bool => (bytes[0] != 0).to_string(),
+        LogArgType::Str => read_str(u32_at(0), u32_at(4)).unwrap_or_else(|| "?".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(args: &[(LogArgType, &[u8])]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for (arg_type, value) in args {
+            bytes.push(*arg_type as u8);
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    /// Strings at pointer 16 read as `orc`.
+    fn read_str(ptr: u32, len: u32) -> Option<String> {
+        (ptr == 16 && len == 3).then(|| "orc".to_string())
+    }
+
+    #[test]
+    fn test_format_args() {
+        let str_at = |ptr: u32| [ptr.to_le_bytes(), 3_u32.to_le_bytes()].concat();
+        let args = pack(&[
+            (LogArgType::Str, &str_at(16)),
+            (LogArgType::I32, &(-12_i32).to_le_bytes()),
+            (LogArgType::U64, &u64::MAX.to_le_bytes()),
+            (LogArgType::F32, &0.5_f32.to_le_bytes()),
+            (LogArgType::Bool, &[1]),
+            (LogArgType::Str, &str_at(64)),
+        ]);
+
+        assert_eq!(
+            format("{} took {} damage, {} {} {} {} {} {{}}", &args, read_str).unwrap(),
+            format!("orc took -12 damage, {} 0.5 true ? ? {{}}", u64::MAX)
+        );
+        // Arguments without a placeholder are left out.
+        assert_eq!(format("{}!", &args, read_str).unwrap(), "orc!");
+        assert_eq!(format("}{", &[], read_str).unwrap(), "}{");
+    }
+
+    #[test]
+    fn test_malformed_args() {
+        assert!(matches!(
+            format("{}", &[9, 0, 0, 0, 0], read_str),
+            Err(LogFmtError::UnknownType(9))
+        ));
+        assert!(matches!(
+            format("{}", &pack(&[(LogArgType::F64, &[0; 4])]), read_str),
+            Err(LogFmtError::Truncated(LogArgType::F64))
+        ));
+    }
+}
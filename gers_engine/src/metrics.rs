@@ -0,0 +1,412 @@
+//! Health metrics for monitoring long running servers.
+//!
+//! When enabled in the `[metrics]` section of the config, a snapshot
+//! of the engine's statistics is taken every interval. The latest
+//! snapshot is served in the Prometheus text format over HTTP, and
+//! written to a JSON file, depending on which of the two are configured.
+use crate::{fps::FrameStats, profiler::TimingStats};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Path the Prometheus metrics are served at.
+const METRICS_PATH: &str = "/metrics";
+
+/// Name, help and value of a per-plugin metric.
+type PluginMetric = (&'static str, &'static str, fn(&PluginMetrics) -> f64);
+
+/// Engine statistics at a point in time.
+#[derive(Debug, Default)]
+pub struct MetricsSnapshot {
+    pub uptime: Duration,
+    pub fps: f32,
+    pub frames: FrameStats,
+    /// Frames since the app started.
+    pub frame_count: u64,
+    /// Events dispatched to plugins since the app started.
+    pub events_dispatched: u64,
+    pub plugins: Vec<PluginMetrics>,
+}
+
+#[derive(Debug, Default)]
+pub struct PluginMetrics {
+    pub name: String,
+    /// Time per frame spent in the update hook, over the profiler's window.
+    pub update: TimingStats,
+    /// Time per frame spent in the event hook, over the profiler's window.
+    pub event: TimingStats,
+    /// Size of the plugin's linear memory.
+    pub memory_bytes: u64,
+}
+
+impl MetricsSnapshot {
+    /// Metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        metric_header(
+            &mut out,
+            "gers_uptime_seconds",
+            "gauge",
+            "Time since the app started.",
+        );
+        let _ = writeln!(out, "gers_uptime_seconds {}", self.uptime.as_secs_f64());
+        metric_header(&mut out, "gers_fps", "gauge", "Frames per second.");
+        let _ = writeln!(out, "gers_fps {}", self.fps);
+        metric_header(
+            &mut out,
+            "gers_frame_time_ms",
+            "gauge",
+            "Frame time over recent frames, in milliseconds.",
+        );
+        for (quantile, value) in [
+            ("0.5", self.frames.p50),
+            ("0.95", self.frames.p95),
+            ("0.99", self.frames.p99),
+            ("1", self.frames.worst),
+        ] {
+            let _ = writeln!(
+                out,
+                "gers_frame_time_ms{{quantile=\"{}\"}} {}",
+                quantile, value
+            );
+        }
+        metric_header(
+            &mut out,
+            "gers_frames_total",
+            "counter",
+            "Frames since the app started.",
+        );
+        let _ = writeln!(out, "gers_frames_total {}", self.frame_count);
+        metric_header(
+            &mut out,
+            "gers_events_dispatched_total",
+            "counter",
+            "Events dispatched to plugins.",
+        );
+        let _ = writeln!(
+            out,
+            "gers_events_dispatched_total {}",
+            self.events_dispatched
+        );
+
+        let plugin_metrics: [PluginMetric; 5] = [
+            (
+                "gers_plugin_update_ms_avg",
+                "Average time per frame in the update hook, in milliseconds.",
+                |plugin| millis(plugin.update.avg),
+            ),
+            (
+                "gers_plugin_update_ms_max",
+                "Longest time per frame in the update hook, in milliseconds.",
+                |plugin| millis(plugin.update.max),
+            ),
+            (
+                "gers_plugin_event_ms_avg",
+                "Average time per frame in the event hook, in milliseconds.",
+                |plugin| millis(plugin.event.avg),
+            ),
+            (
+                "gers_plugin_event_ms_max",
+                "Longest time per frame in the event hook, in milliseconds.",
+                |plugin| millis(plugin.event.max),
+            ),
+            (
+                "gers_plugin_memory_bytes",
+                "Size of the plugin's linear memory.",
+                |plugin| plugin.memory_bytes as f64,
+            ),
+        ];
+        for (name, help, value) in plugin_metrics {
+            metric_header(&mut out, name, "gauge", help);
+            for plugin in self.plugins.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}{{plugin=\"{}\"}} {}",
+                    name,
+                    escape_label(&plugin.name),
+                    value(plugin)
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Metrics as a JSON object, with durations in milliseconds.
+    pub fn to_json(&self) -> String {
+        let plugins: Vec<String> = self
+            .plugins
+            .iter()
+            .map(|plugin| {
+                format!(
+                    "{{\"name\":{},\"update_ms_avg\":{},\"update_ms_max\":{},\
+                     \"event_ms_avg\":{},\"event_ms_max\":{},\"memory_bytes\":{}}}",
+                    json_string(&plugin.name),
+                    millis(plugin.update.avg),
+                    millis(plugin.update.max),
+                    millis(plugin.event.avg),
+                    millis(plugin.event.max),
+                    plugin.memory_bytes,
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"uptime_s\":{},\"fps\":{},\"frame_time_ms\":{{\"p50\":{},\"p95\":{},\"p99\":{},\
+             \"worst\":{}}},\"frames\":{},\"events_dispatched\":{},\"plugins\":[{}]}}",
+            self.uptime.as_secs_f64(),
+            json_number(self.fps),
+            json_number(self.frames.p50),
+            json_number(self.frames.p95),
+            json_number(self.frames.p99),
+            json_number(self.frames.worst),
+            self.frame_count,
+            self.events_dispatched,
+            plugins.join(","),
+        )
+    }
+}
+
+/// Publishes snapshots every interval.
+pub struct MetricsExporter {
+    interval: Duration,
+    last_export: Option<Instant>,
+    server: Option<MetricsServer>,
+    json_path: Option<PathBuf>,
+}
+
+impl MetricsExporter {
+    pub fn new(
+        interval: Duration,
+        server: Option<MetricsServer>,
+        json_path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            interval,
+            last_export: None,
+            server,
+            json_path,
+        }
+    }
+
+    /// A snapshot should be exported.
+    pub fn is_due(&self) -> bool {
+        match self.last_export {
+            Some(last_export) => last_export.elapsed() >= self.interval,
+            None => true,
+        }
+    }
+
+    pub fn export(&mut self, snapshot: &MetricsSnapshot) -> io::Result<()> {
+        self.last_export = Some(Instant::now());
+
+        if let Some(ref server) = self.server {
+            server.publish(snapshot.to_prometheus());
+        }
+
+        if let Some(ref path) = self.json_path {
+            // Replaced in one go, so readers never see a partial file.
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, snapshot.to_json())?;
+            fs::rename(&tmp_path, path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tiny HTTP endpoint serving the latest metrics.
+pub struct MetricsServer {
+    body: Arc<Mutex<String>>,
+    local_addr: SocketAddr,
+}
+
+impl MetricsServer {
+    /// Serve metrics on a background thread.
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let body = Arc::new(Mutex::new(String::new()));
+
+        let served = body.clone();
+        thread::Builder::new()
+            .name("gers-metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // A misbehaving client only loses its own response.
+                    let _ = respond(stream, &served);
+                }
+            })?;
+
+        Ok(Self { body, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn publish(&self, body: String) {
+        if let Ok(mut served) = self.body.lock() {
+            *served = body;
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, body: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    // Only the request line matters, which fits the first read.
+    let mut request = [0; 1024];
+    let len = stream.read(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..len]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let response = if path == METRICS_PATH {
+        let body = body.lock().map(|body| body.clone()).unwrap_or_default();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    stream.write_all(response.as_bytes())
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1_000_000.0
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+/// JSON has no representation for infinity and NaN.
+fn json_number(value: f32) -> f32 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> MetricsSnapshot {
+        MetricsSnapshot {
+            uptime: Duration::from_secs(90),
+            fps: 60.0,
+            frames: FrameStats {
+                p50: 16.5,
+                p95: 17.0,
+                p99: 20.0,
+                worst: 33.0,
+            },
+            frame_count: 5400,
+            events_dispatched: 12,
+            plugins: vec![PluginMetrics {
+                name: "core \"v2\"".to_string(),
+                update: TimingStats {
+                    avg: Duration::from_micros(1500),
+                    ..Default::default()
+                },
+                memory_bytes: 65536,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_prometheus_format() {
+        let text = snapshot().to_prometheus();
+
+        assert!(text.contains("# TYPE gers_fps gauge\ngers_fps 60\n"));
+        assert!(text.contains("gers_frame_time_ms{quantile=\"0.99\"} 20\n"));
+        assert!(text.contains("gers_frames_total 5400\n"));
+        assert!(text.contains("gers_plugin_update_ms_avg{plugin=\"core \\\"v2\\\"\"} 1.5\n"));
+        assert!(text.contains("gers_plugin_memory_bytes{plugin=\"core \\\"v2\\\"\"} 65536\n"));
+    }
+
+    #[test]
+    fn test_json_format() {
+        let json = snapshot().to_json();
+
+        assert!(json.starts_with("{\"uptime_s\":90,\"fps\":60,"));
+        assert!(json.contains("\"frames\":5400,\"events_dispatched\":12,"));
+        assert!(json.contains("\"name\":\"core \\\"v2\\\"\",\"update_ms_avg\":1.5,"));
+        assert_eq!(json_string("a\nb"), "\"a\\u000ab\"");
+    }
+
+    #[test]
+    fn test_server_serves_latest_snapshot() {
+        let server = MetricsServer::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.local_addr());
+        let mut exporter = MetricsExporter::new(Duration::from_secs(60), Some(server), None);
+
+        assert!(exporter.is_due());
+        exporter.export(&snapshot()).unwrap();
+        assert!(!exporter.is_due());
+
+        let body = ureq::get(&format!("{}{}", url, METRICS_PATH))
+            .call()
+            .unwrap()
+            .into_string()
+            .unwrap();
+        assert_eq!(body, snapshot().to_prometheus());
+
+        match ureq::get(&format!("{}/other", url)).call() {
+            Err(ureq::Error::Status(status, _)) => assert_eq!(status, 404),
+            _ => panic!("expected not found"),
+        }
+    }
+
+    #[test]
+    fn test_json_file() {
+        let path = std::env::temp_dir().join(format!("gers-metrics-{}.json", std::process::id()));
+        let mut exporter = MetricsExporter::new(Duration::ZERO, None, Some(path.clone()));
+
+        exporter.export(&snapshot()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), snapshot().to_json());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! In-game list of installed plugins.
+//!
+//! Lets players enable and disable plugins without editing the enabled
+//! list by hand. Changes are persisted right away, and take effect the
+//! next time plugins are loaded.
+use gers_plugins::{PluginError, PluginState, Plugins};
+
+use crate::{
+    profiler::ProfilerReport,
+    render::{Canvas, Color},
+};
+
+/// Size of a text pixel.
+const TEXT_SCALE: u32 = 2;
+
+const PANEL_COLOR: Color = Color::rgba(0, 0, 0, 200);
+const SELECTED_COLOR: Color = Color::rgba(255, 255, 255, 48);
+const PADDING: i32 = 8;
+
+/// A plugin in the list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModEntry {
+    pub name: String,
+    pub version: String,
+    /// State of the loaded plugin, or `None` when it isn't loaded.
+    pub state: Option<PluginState>,
+    /// Average time spent in the plugin per frame, in milliseconds.
+    pub frame_ms: f64,
+    /// Whether the plugin is loaded the next time.
+    pub enabled: bool,
+}
+
+impl ModEntry {
+    /// Entries for the loaded plugins, in execution order, followed by
+    /// the disabled plugins that weren't loaded.
+    pub fn list(plugins: &Plugins, profiler: &ProfilerReport) -> Vec<ModEntry> {
+        let enabled = plugins.enabled_list();
+        let mut entries: Vec<ModEntry> = plugins
+            .infos()
+            .into_iter()
+            .map(|info| {
+                let frame_ms = profiler
+                    .plugins
+                    .iter()
+                    .find(|report| report.name == info.name)
+                    .map(|report| (report.update.avg + report.event.avg).as_secs_f64() * 1000.0)
+                    .unwrap_or_default();
+                ModEntry {
+                    enabled: enabled.is_enabled(&info.name),
+                    name: info.name,
+                    version: info.version,
+                    state: Some(info.state),
+                    frame_ms,
+                }
+            })
+            .collect();
+
+        for (name, is_enabled) in enabled.iter() {
+            if !is_enabled && !entries.iter().any(|entry| entry.name == name) {
+                entries.push(ModEntry {
+                    name: name.to_string(),
+                    version: String::new(),
+                    state: None,
+                    frame_ms: 0.0,
+                    enabled: false,
+                });
+            }
+        }
+
+        entries
+    }
+
+    fn state_label(&self) -> &'static str {
+        match self.state {
+            None => "NOT LOADED",
+            Some(PluginState::DataOnly) => "DATA",
+            Some(PluginState::Faulted) => "FAULTED",
+            Some(PluginState::Uninitialised) => "UNINIT",
+            Some(PluginState::Running) => "RUNNING",
+            Some(PluginState::WorkPending) => "PENDING",
+            Some(PluginState::Compacting) => "COMPACTING",
+        }
+    }
+}
+
+pub struct ModListOverlay {
+    visible: bool,
+    /// Index of the highlighted entry.
+    selected: usize,
+}
+
+impl ModListOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            selected: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Move the highlight by `offset` entries, wrapping around.
+    pub fn move_selection(&mut self, offset: isize, len: usize) {
+        if len == 0 {
+            self.selected = 0;
+            return;
+        }
+
+        let selected = self.selected.min(len - 1) as isize + offset;
+        self.selected = selected.rem_euclid(len as isize) as usize;
+    }
+
+    /// Enable or disable the highlighted plugin, and persist the change.
+    ///
+    /// Returns the name of the plugin and whether it's enabled now, or
+    /// `None` when there are no plugins.
+    pub fn toggle_selected(
+        &self,
+        plugins: &mut Plugins,
+        entries: &[ModEntry],
+    ) -> Option<Result<(String, bool), PluginError>> {
+        let entry = entries.get(self.selected.min(entries.len().checked_sub(1)?))?;
+        let enabled = !entry.enabled;
+        Some(
+            plugins
+                .set_enabled(&entry.name, enabled)
+                .map(|()| (entry.name.clone(), enabled)),
+        )
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, entries: &[ModEntry]) {
+        if !self.visible {
+            return;
+        }
+
+        let mut lines: Vec<(String, Color)> = vec![
+            ("PLUGINS".to_string(), Color::WHITE),
+            (
+                "ENTER TOGGLES  APPLIES ON RESTART".to_string(),
+                Color::WHITE,
+            ),
+        ];
+        let header_len = lines.len();
+
+        for entry in entries.iter() {
+            let color = match entry.state {
+                Some(PluginState::Faulted) => Color::RED,
+                _ if !entry.enabled => Color::rgba(160, 160, 160, 255),
+                _ => Color::YELLOW,
+            };
+            lines.push((
+                format!(
+                    "[{}] {} {}  {}  {:.3}MS",
+                    if entry.enabled { "X" } else { " " },
+                    entry.name,
+                    entry.version,
+                    entry.state_label(),
+                    entry.frame_ms,
+                ),
+                color,
+            ));
+        }
+        if entries.is_empty() {
+            lines.push(("NO PLUGINS INSTALLED".to_string(), Color::WHITE));
+        }
+
+        // Centred panel sized to fit content.
+        let line_height = Canvas::line_height(TEXT_SCALE);
+        let text_width = lines
+            .iter()
+            .map(|(text, _)| Canvas::text_width(text, TEXT_SCALE))
+            .max()
+            .unwrap_or(0);
+        let panel_width = text_width + PADDING as u32 * 2;
+        let panel_height = line_height * lines.len() as u32 + PADDING as u32 * 3;
+        let x = (canvas.width() as i32 - panel_width as i32).max(0) / 2;
+        let y = (canvas.height() as i32 - panel_height as i32).max(0) / 2;
+        canvas.fill_rect(x, y, panel_width, panel_height, PANEL_COLOR);
+
+        let mut line_y = y + PADDING;
+        for (i, (text, color)) in lines.iter().enumerate() {
+            if i == header_len {
+                // Gap between the header and the list.
+                line_y += PADDING;
+            }
+            if !entries.is_empty() && i == header_len + self.selected.min(entries.len() - 1) {
+                canvas.fill_rect(
+                    x + PADDING / 2,
+                    line_y - 1,
+                    panel_width - PADDING as u32,
+                    line_height,
+                    SELECTED_COLOR,
+                );
+            }
+            canvas.draw_text(x + PADDING, line_y, text, TEXT_SCALE, *color);
+            line_y += line_height as i32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 320;
+    const HEIGHT: u32 = 240;
+
+    fn entry(name: &str, enabled: bool) -> ModEntry {
+        ModEntry {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            state: Some(PluginState::Running),
+            frame_ms: 0.25,
+            enabled,
+        }
+    }
+
+    /// Number of pixels the overlay draws on a blank canvas.
+    fn drawn(overlay: &ModListOverlay, entries: &[ModEntry]) -> usize {
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        overlay.draw(&mut Canvas::new(&mut frame, WIDTH, HEIGHT), entries);
+
+        frame.chunks_exact(4).filter(|pixel| pixel[3] != 0).count()
+    }
+
+    #[test]
+    fn test_hidden_list_draws_nothing() {
+        let mut overlay = ModListOverlay::new();
+        let entries = [entry("foo", true), entry("bar", false)];
+        assert_eq!(drawn(&overlay, &entries), 0);
+
+        overlay.toggle();
+        assert!(overlay.is_visible());
+        assert!(drawn(&overlay, &entries) > 0);
+        assert!(drawn(&overlay, &[]) > 0);
+    }
+
+    #[test]
+    fn test_selection_wraps() {
+        let mut overlay = ModListOverlay::new();
+        overlay.move_selection(-1, 3);
+        assert_eq!(overlay.selected, 2);
+        overlay.move_selection(1, 3);
+        assert_eq!(overlay.selected, 0);
+
+        // Plugins can disappear from the list while it's open.
+        overlay.selected = 5;
+        overlay.move_selection(1, 2);
+        assert_eq!(overlay.selected, 0);
+        overlay.move_selection(1, 0);
+        assert_eq!(overlay.selected, 0);
+    }
+
+    #[test]
+    fn test_toggle_selected() {
+        let root = std::env::temp_dir().join(format!("gers-mod-list-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut plugins = Plugins::new();
+        plugins
+            .load_enabled_list(root.join("enabled.toml"))
+            .unwrap();
+
+        let mut overlay = ModListOverlay::new();
+        assert!(overlay.toggle_selected(&mut plugins, &[]).is_none());
+
+        let entries = [entry("foo", true), entry("bar", false)];
+        let toggled = overlay.toggle_selected(&mut plugins, &entries).unwrap();
+        assert_eq!(toggled.unwrap(), ("foo".to_string(), false));
+        assert!(!plugins.enabled_list().is_enabled("foo"));
+
+        overlay.move_selection(1, entries.len());
+        let toggled = overlay.toggle_selected(&mut plugins, &entries).unwrap();
+        assert_eq!(toggled.unwrap(), ("bar".to_string(), true));
+
+        // Disabled plugins that weren't loaded are still listed.
+        let entries = ModEntry::list(&plugins, &ProfilerReport::default());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "foo");
+        assert_eq!(entries[0].state, None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+//! Mouse input over the main window.
+//!
+//! winit identifies mice with opaque device ids, so they're numbered
+//! in the order they're first seen, and plugins can tell them apart.
+use gers_events::MouseMoveEvent;
+use winit::{dpi::PhysicalPosition, event::DeviceId};
+
+#[derive(Default)]
+pub struct Mice {
+    /// Devices indexed by their number.
+    devices: Vec<DeviceId>,
+    events: Vec<MouseMoveEvent>,
+}
+
+impl Mice {
+    /// Number of the device, assigning the next one when it's new.
+    fn device_number(&mut self, device_id: DeviceId) -> u32 {
+        match self.devices.iter().position(|device| *device == device_id) {
+            Some(index) => index as u32,
+            None => {
+                self.devices.push(device_id);
+                (self.devices.len() - 1) as u32
+            }
+        }
+    }
+
+    pub fn handle_cursor_moved(&mut self, device_id: DeviceId, position: PhysicalPosition<f64>) {
+        let device_id = self.device_number(device_id);
+        self.events.push(MouseMoveEvent {
+            device_id,
+            x: position.x as f32,
+            y: position.y as f32,
+        });
+    }
+
+    /// Take the events since the last call.
+    pub fn take_events(&mut self) -> Vec<MouseMoveEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_moved() {
+        // SAFETY: Only compared, never passed to winit.
+        let device_id = unsafe { DeviceId::dummy() };
+        let mut mice = Mice::default();
+
+        mice.handle_cursor_moved(device_id, PhysicalPosition::new(10.0, 20.5));
+        mice.handle_cursor_moved(device_id, PhysicalPosition::new(11.0, 21.5));
+
+        let events = mice.take_events();
+        let moves: Vec<(u32, f32, f32)> = events
+            .iter()
+            .map(|event| (event.device_id, event.x, event.y))
+            .collect();
+        assert_eq!(moves, [(0, 10.0, 20.5), (0, 11.0, 21.5)]);
+        assert!(mice.take_events().is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+//! TCP connections for plugins.
+//!
+//! Connections are established on a background thread, so resolving
+//! and connecting doesn't stall the frame. Once connected the socket
+//! is non-blocking, and sends and receives that can't make progress
+//! fail with `NetError::WouldBlock`. Every plugin may only have a
+//! limited number of connections open at the same time. Plugins
+//! need the `network` permission.
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+pub type ConnectionId = u32;
+
+/// Default number of connections a single plugin may have open.
+pub const DEFAULT_CONNECTION_LIMIT: usize = 4;
+
+/// Time allowed for each resolved address to accept the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Network {
+    connections: HashMap<ConnectionId, Connection>,
+    next_id: ConnectionId,
+    connection_limit: usize,
+}
+
+struct Connection {
+    /// Key of the plugin that opened the connection.
+    owner: PathBuf,
+    state: ConnectionState,
+}
+
+enum ConnectionState {
+    Connecting(Receiver<io::Result<TcpStream>>),
+    Open(TcpStream),
+    Failed(io::ErrorKind),
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    ConnectionLimit(usize),
+    UnknownConnection(ConnectionId),
+    /// The operation can't make progress yet, try again next frame.
+    WouldBlock,
+    Io(io::Error),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::ConnectionLimit(limit) => {
+                write!(f, "plugin connection limit reached: {}", limit)
+            }
+            NetError::UnknownConnection(id) => write!(f, "unknown connection id: {}", id),
+            NetError::WouldBlock => write!(f, "operation would block"),
+            NetError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::WouldBlock => NetError::WouldBlock,
+            _ => NetError::Io(err),
+        }
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            connections: HashMap::new(),
+            // Zero is reserved to signal failure to guests.
+            next_id: 1,
+            connection_limit: DEFAULT_CONNECTION_LIMIT,
+        }
+    }
+}
+
+impl Network {
+    /// Start connecting to a `host:port` address on behalf of the plugin at `owner`.
+    pub fn connect(&mut self, owner: &Path, address: String) -> Result<ConnectionId, NetError> {
+        let open_count = self
+            .connections
+            .values()
+            .filter(|connection| connection.owner == owner)
+            .count();
+        if open_count >= self.connection_limit {
+            return Err(NetError::ConnectionLimit(self.connection_limit));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("gers-net-connect".to_string())
+            .spawn(move || {
+                // The receiver is gone when the connection was closed while connecting.
+                let _ = sender.send(open_stream(&address));
+            })?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.connections.insert(
+            id,
+            Connection {
+                owner: owner.to_path_buf(),
+                state: ConnectionState::Connecting(receiver),
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Send as much of the data as the socket accepts.
+    ///
+    /// Returns the number of bytes sent.
+    pub fn send(&mut self, owner: &Path, id: ConnectionId, data: &[u8]) -> Result<usize, NetError> {
+        let stream = self.stream(owner, id)?;
+        Ok(stream.write(data)?)
+    }
+
+    /// Receive the data that has arrived, up to the size of the buffer.
+    ///
+    /// Returns the number of bytes received, which is zero once the
+    /// remote end has closed the connection.
+    pub fn recv(
+        &mut self,
+        owner: &Path,
+        id: ConnectionId,
+        buf: &mut [u8],
+    ) -> Result<usize, NetError> {
+        let stream = self.stream(owner, id)?;
+        Ok(stream.read(buf)?)
+    }
+
+    /// Close a connection. Plugins can only close the connections they opened.
+    ///
+    /// Returns `true` if the connection existed.
+    pub fn close(&mut self, owner: &Path, id: ConnectionId) -> bool {
+        match self.connections.get(&id) {
+            Some(connection) if connection.owner == owner => {}
+            _ => return false,
+        }
+
+        self.connections.remove(&id).is_some()
+    }
+
+    /// Close every connection the plugin at `owner` opened.
+    ///
+    /// Returns the number of connections closed.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let count = self.connections.len();
+        self.connections
+            .retain(|_, connection| connection.owner != owner);
+        count - self.connections.len()
+    }
+
+    /// Open stream of a connection, completing the connection
+    /// attempt when the background thread is done.
+    fn stream(&mut self, owner: &Path, id: ConnectionId) -> Result<&mut TcpStream, NetError> {
+        let connection = match self.connections.get_mut(&id) {
+            Some(connection) if connection.owner == owner => connection,
+            _ => return Err(NetError::UnknownConnection(id)),
+        };
+
+        if let ConnectionState::Connecting(ref receiver) = connection.state {
+            connection.state = match receiver.try_recv() {
+                Ok(Ok(stream)) => ConnectionState::Open(stream),
+                Ok(Err(err)) => ConnectionState::Failed(err.kind()),
+                Err(TryRecvError::Empty) => return Err(NetError::WouldBlock),
+                Err(TryRecvError::Disconnected) => ConnectionState::Failed(io::ErrorKind::Other),
+            };
+        }
+
+        match connection.state {
+            ConnectionState::Open(ref mut stream) => Ok(stream),
+            ConnectionState::Failed(kind) => Err(NetError::Io(kind.into())),
+            ConnectionState::Connecting(_) => Err(NetError::WouldBlock),
+        }
+    }
+}
+
+/// Resolve the address and connect to the first address that accepts.
+fn open_stream(address: &str) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "address didn't resolve");
+
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_nonblocking(true)?;
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+
+    Err(last_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, time::Instant};
+
+    /// Poll a connection until the background thread finished connecting.
+    fn wait_connected(
+        network: &mut Network,
+        owner: &Path,
+        id: ConnectionId,
+    ) -> Result<(), NetError> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match network.send(owner, id, &[]) {
+                Err(NetError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_send_and_recv() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let owner = Path::new("plugin");
+        let mut network = Network::default();
+
+        let id = network.connect(owner, address).unwrap();
+        assert_ne!(id, 0);
+        wait_connected(&mut network, owner, id).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        assert_eq!(network.send(owner, id, b"ping").unwrap(), 4);
+        let mut buf = [0; 4];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Nothing has arrived yet.
+        assert!(matches!(
+            network.recv(owner, id, &mut buf),
+            Err(NetError::WouldBlock)
+        ));
+        peer.write_all(b"pong").unwrap();
+        drop(peer);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let received = loop {
+            match network.recv(owner, id, &mut buf) {
+                Err(NetError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                result => break result.unwrap(),
+            }
+        };
+        assert_eq!(&buf[..received], b"pong");
+
+        assert!(network.close(owner, id));
+        assert!(matches!(
+            network.send(owner, id, b"ping"),
+            Err(NetError::UnknownConnection(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_connection() {
+        let owner = Path::new("plugin");
+        let mut network = Network::default();
+
+        let id = network
+            .connect(owner, "not an address".to_string())
+            .unwrap();
+        assert!(matches!(
+            wait_connected(&mut network, owner, id),
+            Err(NetError::Io(_))
+        ));
+        // The failure sticks.
+        assert!(matches!(
+            network.recv(owner, id, &mut [0; 4]),
+            Err(NetError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_connections_are_owned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut network = Network::default();
+
+        let ids: Vec<_> = (0..DEFAULT_CONNECTION_LIMIT)
+            .map(|_| network.connect(owner, address.clone()).unwrap())
+            .collect();
+        assert!(matches!(
+            network.connect(owner, address.clone()),
+            Err(NetError::ConnectionLimit(DEFAULT_CONNECTION_LIMIT))
+        ));
+        // The limit is per plugin.
+        let other_id = network.connect(other, address).unwrap();
+
+        assert!(matches!(
+            network.send(other, ids[0], b"ping"),
+            Err(NetError::UnknownConnection(_))
+        ));
+        assert!(!network.close(other, ids[0]));
+        assert!(network.close(owner, ids[0]));
+        assert!(!network.close(owner, ids[0]));
+        assert!(network.close(other, other_id));
+    }
+}
@@ -0,0 +1,236 @@
+//! On-screen debug overlay.
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    fps::{FrameStats, PacingStats},
+    profiler::ProfilerReport,
+    render::{Canvas, Color},
+    watch::PluginWatches,
+};
+
+/// Number of frames kept in the history graphs.
+const HISTORY_LEN: usize = 120;
+
+/// Size of a text pixel.
+const TEXT_SCALE: u32 = 2;
+
+/// Frame time which fills the graph's height, in milliseconds.
+const GRAPH_MAX_MS: f32 = 33.3;
+const GRAPH_HEIGHT: u32 = 48;
+
+const PANEL_COLOR: Color = Color::rgba(0, 0, 0, 180);
+const PADDING: i32 = 8;
+
+pub struct DebugOverlay {
+    visible: bool,
+    fps_history: VecDeque<f32>,
+    /// Frame times in milliseconds.
+    frame_times: VecDeque<f32>,
+}
+
+/// Engine statistics displayed by the overlay.
+pub struct OverlayStats<'a> {
+    pub fps: f32,
+    pub frames: FrameStats,
+    pub pacing: PacingStats,
+    pub profiler: &'a ProfilerReport,
+    /// Events waiting to be dispatched to plugins.
+    pub event_queue_depth: usize,
+    /// Values plugins watch.
+    pub watches: &'a [PluginWatches],
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            fps_history: VecDeque::with_capacity(HISTORY_LEN),
+            frame_times: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Record a frame's timing in the history.
+    pub fn push_frame(&mut self, delta_time: Duration, fps: f32) {
+        if self.frame_times.len() >= HISTORY_LEN {
+            self.frame_times.pop_front();
+            self.fps_history.pop_front();
+        }
+        self.frame_times
+            .push_back(delta_time.as_secs_f32() * 1000.0);
+        self.fps_history.push_back(fps);
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, stats: &OverlayStats) {
+        if !self.visible {
+            return;
+        }
+
+        let mut lines: Vec<(String, Color)> = vec![];
+
+        let frame_ms = self.frame_times.back().copied().unwrap_or_default();
+        lines.push((
+            format!("FPS {:.0}  {:.2}MS", stats.fps, frame_ms),
+            Color::WHITE,
+        ));
+
+        let (fps_min, fps_max) = self
+            .fps_history
+            .iter()
+            .fold((f32::MAX, 0.0_f32), |(min, max), fps| {
+                (min.min(*fps), max.max(*fps))
+            });
+        if !self.fps_history.is_empty() {
+            lines.push((
+                format!("FPS MIN {:.0}  MAX {:.0}", fps_min, fps_max),
+                Color::WHITE,
+            ));
+        }
+
+        lines.push((
+            format!(
+                "P50 {:.2}MS  P95 {:.2}MS  P99 {:.2}MS  WORST {:.2}MS",
+                stats.frames.p50, stats.frames.p95, stats.frames.p99, stats.frames.worst
+            ),
+            Color::WHITE,
+        ));
+
+        lines.push((
+            format!(
+                "PACING AVG {:+.2}MS  LATE {:.2}MS  EARLY {:.2}MS",
+                stats.pacing.mean, stats.pacing.max_overshoot, stats.pacing.max_undershoot
+            ),
+            Color::WHITE,
+        ));
+
+        lines.push((
+            format!("EVENT QUEUE {}", stats.event_queue_depth),
+            Color::WHITE,
+        ));
+
+        for plugin in stats.profiler.plugins.iter() {
+            lines.push((
+                format!(
+                    "{}  UPD {:.3}MS  EVT {:.3}MS",
+                    plugin.name,
+                    plugin.update.avg.as_secs_f64() * 1000.0,
+                    plugin.event.avg.as_secs_f64() * 1000.0,
+                ),
+                Color::YELLOW,
+            ));
+        }
+
+        for line in stats.watches.iter().flat_map(PluginWatches::lines) {
+            lines.push((line, Color::GREEN));
+        }
+
+        // Background panel sized to fit content.
+        let line_height = Canvas::line_height(TEXT_SCALE);
+        let graph_width = HISTORY_LEN as u32 * 2;
+        let text_width = lines
+            .iter()
+            .map(|(text, _)| Canvas::text_width(text, TEXT_SCALE))
+            .max()
+            .unwrap_or(0);
+        let panel_width = text_width.max(graph_width) + PADDING as u32 * 2;
+        let panel_height = line_height * lines.len() as u32 + GRAPH_HEIGHT + PADDING as u32 * 3;
+        canvas.fill_rect(0, 0, panel_width, panel_height, PANEL_COLOR);
+
+        let mut y = PADDING;
+        for (text, color) in lines.iter() {
+            canvas.draw_text(PADDING, y, text, TEXT_SCALE, *color);
+            y += line_height as i32;
+        }
+
+        self.draw_frame_graph(canvas, PADDING, y + PADDING);
+    }
+
+    /// Bar graph of frame times, newest on the right.
+    fn draw_frame_graph(&self, canvas: &mut Canvas, x: i32, y: i32) {
+        let bottom = y + GRAPH_HEIGHT as i32;
+
+        for (i, ms) in self.frame_times.iter().enumerate() {
+            let height = ((ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT as f32) as i32;
+            let color = if *ms > 16.7 {
+                Color::RED
+            } else if *ms > 8.4 {
+                Color::YELLOW
+            } else {
+                Color::GREEN
+            };
+            let bar_x = x + i as i32 * 2;
+            canvas.draw_line(bar_x, bottom, bar_x, bottom - height, color);
+        }
+
+        // 60 FPS reference line.
+        let line_y = bottom - ((16.7 / GRAPH_MAX_MS) * GRAPH_HEIGHT as f32) as i32;
+        canvas.draw_line(
+            x,
+            line_y,
+            x + HISTORY_LEN as i32 * 2,
+            line_y,
+            Color::rgba(255, 255, 255, 96),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 320;
+    const HEIGHT: u32 = 240;
+
+    /// Number of pixels the overlay draws on a blank canvas.
+    fn drawn(overlay: &DebugOverlay) -> usize {
+        let report = ProfilerReport::default();
+        let stats = OverlayStats {
+            fps: 60.0,
+            frames: FrameStats::default(),
+            pacing: PacingStats::default(),
+            profiler: &report,
+            event_queue_depth: 3,
+            watches: &[],
+        };
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        overlay.draw(&mut Canvas::new(&mut frame, WIDTH, HEIGHT), &stats);
+
+        frame.chunks_exact(4).filter(|pixel| pixel[3] != 0).count()
+    }
+
+    #[test]
+    fn test_hidden_overlay_draws_nothing() {
+        let mut overlay = DebugOverlay::new();
+        overlay.push_frame(Duration::from_millis(16), 60.0);
+        assert!(!overlay.visible);
+        assert_eq!(drawn(&overlay), 0);
+
+        overlay.toggle();
+        assert!(overlay.visible);
+        assert!(drawn(&overlay) > 0);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut overlay = DebugOverlay::new();
+        for i in 0..HISTORY_LEN + 10 {
+            overlay.push_frame(Duration::from_millis(i as u64), i as f32);
+        }
+
+        assert_eq!(overlay.frame_times.len(), HISTORY_LEN);
+        assert_eq!(overlay.fps_history.len(), HISTORY_LEN);
+        // The oldest frames are dropped.
+        assert_eq!(overlay.fps_history.front(), Some(&10.0));
+        assert_eq!(
+            overlay.frame_times.back(),
+            Some(&(HISTORY_LEN as f32 + 9.0))
+        );
+    }
+}
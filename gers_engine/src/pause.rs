@@ -0,0 +1,141 @@
+//! Pausing the app while its window is in the background.
+//!
+//! The app is paused while the window is unfocused or minimized, and
+//! resumed once it's both focused and restored. Plugins are told with
+//! `AppPausedEvent` and `AppResumedEvent`, which are part of the frame's
+//! event stream, so a replay pauses on the same frames. How long the
+//! app was paused is measured on the frame loop's clock.
+use crate::{clock::Clock, replay::FrameEvent};
+use gers_events::{AppPausedEvent, AppResumedEvent, PauseReason};
+use std::time::Instant;
+
+pub struct PauseState<C: Clock> {
+    unfocused: bool,
+    minimized: bool,
+    /// When the app was paused.
+    paused_at: Option<Instant>,
+    events: Vec<FrameEvent>,
+    clock: C,
+}
+
+impl<C: Clock> PauseState<C> {
+    /// Pause state measuring pauses on the given clock.
+    pub fn new(clock: C) -> Self {
+        Self {
+            unfocused: false,
+            minimized: false,
+            paused_at: None,
+            events: vec![],
+            clock,
+        }
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.unfocused = !focused;
+        self.update(PauseReason::FocusLost);
+    }
+
+    pub fn set_minimized(&mut self, minimized: bool) {
+        self.minimized = minimized;
+        self.update(PauseReason::Minimized);
+    }
+
+    /// Take the pause and resume events since the last call.
+    pub fn take_events(&mut self) -> Vec<FrameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn update(&mut self, reason: PauseReason) {
+        let pause = self.unfocused || self.minimized;
+
+        match self.paused_at {
+            None if pause => {
+                self.paused_at = Some(self.clock.now());
+                self.events.push(FrameEvent::AppPaused(AppPausedEvent {
+                    reason: reason as u32,
+                }));
+            }
+            Some(paused_at) if !pause => {
+                self.paused_at = None;
+                let paused_ms = self
+                    .clock
+                    .now()
+                    .duration_since(paused_at)
+                    .as_millis()
+                    .min(u32::MAX as u128) as u32;
+                self.events
+                    .push(FrameEvent::AppResumed(AppResumedEvent { paused_ms }));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use std::time::Duration;
+
+    fn event_names(state: &mut PauseState<TestClock>) -> Vec<&'static str> {
+        state
+            .take_events()
+            .into_iter()
+            .map(|event| match event {
+                FrameEvent::AppPaused(_) => "paused",
+                FrameEvent::AppResumed(_) => "resumed",
+                _ => "other",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_focus_pauses() {
+        let mut state = PauseState::new(TestClock::new());
+        state.set_focused(true);
+        assert_eq!(event_names(&mut state), Vec::<&str>::new());
+
+        state.set_focused(false);
+        match &state.take_events()[..] {
+            [FrameEvent::AppPaused(event)] => {
+                assert_eq!(PauseReason::from(event.reason), PauseReason::FocusLost)
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+
+        state.set_focused(true);
+        assert_eq!(event_names(&mut state), ["resumed"]);
+    }
+
+    #[test]
+    fn test_resumes_when_focused_and_restored() {
+        let mut state = PauseState::new(TestClock::new());
+        state.set_minimized(true);
+        state.set_focused(false);
+        assert_eq!(event_names(&mut state), ["paused"]);
+
+        // Still unfocused.
+        state.set_minimized(false);
+        assert_eq!(event_names(&mut state), Vec::<&str>::new());
+
+        state.set_focused(true);
+        state.set_focused(false);
+        assert_eq!(event_names(&mut state), ["resumed", "paused"]);
+    }
+
+    #[test]
+    fn test_paused_time_on_clock() {
+        let clock = TestClock::new();
+        let mut state = PauseState::new(clock.clone());
+        state.set_focused(false);
+        clock.advance(Duration::from_millis(1500));
+        state.set_focused(true);
+
+        match &state.take_events()[..] {
+            [FrameEvent::AppPaused(_), FrameEvent::AppResumed(event)] => {
+                assert_eq!(event.paused_ms, 1500)
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+    }
+}
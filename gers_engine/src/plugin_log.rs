@@ -0,0 +1,200 @@
+//! Log files of plugins.
+//!
+//! Messages plugins log are written to the terminal with the engine's
+//! own, and also to `<plugin>.log` in the configured directory, so a
+//! mod's output can be found without sifting through everything else.
+//! Instances of a plugin log to `<plugin>/<instance>.log`.
+//! Once a file reaches its size limit it's rotated to `<plugin>.log.1`,
+//! shifting older files up, and the oldest beyond the number kept is
+//! removed.
+//!
+//! Writes are buffered, and flushed on shutdown, or when the plugin
+//! asks for it with `gers.log_flush`.
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::storage;
+
+/// Default size a log file grows to before it's rotated, in bytes.
+pub const DEFAULT_ROTATE_SIZE: u64 = 1024 * 1024;
+
+/// Default number of rotated files kept per plugin.
+pub const DEFAULT_KEEP_ROTATED: u32 = 3;
+
+struct LogFile {
+    writer: BufWriter<File>,
+    /// Bytes in the file, including those still buffered.
+    size: u64,
+}
+
+/// Open log files of plugins, shared with the host imports.
+pub struct PluginLogs {
+    /// Directory the files are in, or `None` to not write any.
+    dir: Option<PathBuf>,
+    rotate_size: u64,
+    keep_rotated: u32,
+    files: HashMap<String, LogFile>,
+}
+
+impl Default for PluginLogs {
+    /// Plugin logs that aren't written anywhere.
+    fn default() -> Self {
+        Self::new(None, DEFAULT_ROTATE_SIZE, DEFAULT_KEEP_ROTATED)
+    }
+}
+
+impl PluginLogs {
+    pub fn new(dir: Option<PathBuf>, rotate_size: u64, keep_rotated: u32) -> Self {
+        Self {
+            dir,
+            rotate_size,
+            keep_rotated,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Append a message to the plugin's log file, opening or rotating
+    /// it as needed.
+    ///
+    /// Plugins whose name can't be a file name aren't logged to a file.
+    pub fn write(&mut self, plugin_name: &str, level: &str, message: &str) -> io::Result<()> {
+        let path = match self.path(plugin_name) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {} {}\n",
+            millis / 1000,
+            millis % 1000,
+            level,
+            message
+        );
+
+        let rotate = match self.files.get(plugin_name) {
+            Some(file) => file.size > 0 && file.size + line.len() as u64 > self.rotate_size,
+            None => false,
+        };
+        if rotate {
+            if let Some(mut file) = self.files.remove(plugin_name) {
+                file.writer.flush()?;
+            }
+            rotate_files(&path, self.keep_rotated)?;
+        }
+
+        let file = match self.files.get_mut(plugin_name) {
+            Some(file) => file,
+            None => {
+                let file = open(&path)?;
+                self.files.entry(plugin_name.to_string()).or_insert(file)
+            }
+        };
+        file.writer.write_all(line.as_bytes())?;
+        file.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Write the plugin's buffered messages to its file.
+    pub fn flush(&mut self, plugin_name: &str) -> io::Result<()> {
+        match self.files.get_mut(plugin_name) {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the buffered messages of every plugin to their files.
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        for file in self.files.values_mut() {
+            file.writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, plugin_name: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        storage::plugin_path(dir, plugin_name, ".log")
+    }
+}
+
+fn open(path: &Path) -> io::Result<LogFile> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+
+    Ok(LogFile {
+        writer: BufWriter::new(file),
+        size,
+    })
+}
+
+/// Shift `path` to `path.1`, and each rotated file to the next number,
+/// removing those beyond `keep`.
+fn rotate_files(path: &Path, keep: u32) -> io::Result<()> {
+    let rotated = |index: u32| PathBuf::from(format!("{}.{}", path.display(), index));
+
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+
+    let oldest = rotated(keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..keep).rev() {
+        let from = rotated(index);
+        if from.exists() {
+            fs::rename(&from, rotated(index + 1))?;
+        }
+    }
+
+    fs::rename(path, rotated(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_plugin_logs() {
+        let root = std::env::temp_dir().join(format!("gers-logs-{}", std::process::id()));
+        let mut logs = PluginLogs::new(Some(root.clone()), 64, 2);
+
+        for i in 0..8 {
+            logs.write("foo", "INFO", &format!("message {}", i))
+                .unwrap();
+        }
+        logs.write("../escape", "INFO", "message").unwrap();
+        logs.flush_all().unwrap();
+
+        let log = fs::read_to_string(root.join("foo.log")).unwrap();
+        assert!(log.ends_with(" INFO message 7\n"));
+        assert!(log.len() <= 64);
+        assert!(root.join("foo.log.1").is_file());
+        assert!(root.join("foo.log.2").is_file());
+        assert!(!root.join("foo.log.3").exists());
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 3);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_no_dir_writes_nothing() {
+        let mut logs = PluginLogs::default();
+        logs.write("foo", "INFO", "message").unwrap();
+        logs.flush("foo").unwrap();
+        assert!(logs.files.is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+//! Per-plugin frame profiling.
+//!
+//! Measures the time spent in each plugin's hooks, and aggregates
+//! the per-frame totals over a window of frames.
+use std::{fmt, time::Duration};
+
+/// Kind of guest call being measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Update,
+    Event,
+}
+
+pub struct Profiler {
+    /// Number of frames aggregated into a report.
+    window_size: u32,
+    frame_count: u32,
+    /// Plugins in the order they were first recorded.
+    plugins: Vec<PluginProfile>,
+    report: ProfilerReport,
+}
+
+struct PluginProfile {
+    name: String,
+    /// Time accumulated during the current frame.
+    frame_update: Option<Duration>,
+    frame_event: Option<Duration>,
+    /// Time spent during the last ended frame.
+    last_update: Duration,
+    last_event: Duration,
+    update: Accumulator,
+    event: Accumulator,
+}
+
+/// Running min/max/sum over a window.
+#[derive(Default)]
+struct Accumulator {
+    min: Option<Duration>,
+    max: Duration,
+    sum: Duration,
+    frames: u32,
+    calls: u32,
+}
+
+impl Profiler {
+    pub fn new(window_size: u32) -> Self {
+        Self {
+            window_size: window_size.max(1),
+            frame_count: 0,
+            plugins: vec![],
+            report: ProfilerReport::default(),
+        }
+    }
+
+    /// Record the duration of a single guest call.
+    pub fn record(&mut self, plugin_name: &str, kind: CallKind, elapsed: Duration) {
+        let index = match self.plugins.iter().position(|p| p.name == plugin_name) {
+            Some(index) => index,
+            None => {
+                self.plugins.push(PluginProfile::new(plugin_name));
+                self.plugins.len() - 1
+            }
+        };
+        let profile = &mut self.plugins[index];
+
+        let (frame_time, acc) = match kind {
+            CallKind::Update => (&mut profile.frame_update, &mut profile.update),
+            CallKind::Event => (&mut profile.frame_event, &mut profile.event),
+        };
+        *frame_time = Some(frame_time.unwrap_or_default() + elapsed);
+        acc.calls += 1;
+    }
+
+    /// Fold the current frame's timings into the window.
+    ///
+    /// Returns the new report when a window has been completed.
+    pub fn end_frame(&mut self) -> Option<&ProfilerReport> {
+        for profile in self.plugins.iter_mut() {
+            profile.last_update = profile.frame_update.unwrap_or_default();
+            profile.last_event = profile.frame_event.unwrap_or_default();
+
+            if let Some(elapsed) = profile.frame_update.take() {
+                profile.update.add(elapsed);
+            }
+            if let Some(elapsed) = profile.frame_event.take() {
+                profile.event.add(elapsed);
+            }
+        }
+
+        self.frame_count += 1;
+
+        if self.frame_count >= self.window_size {
+            self.report = ProfilerReport {
+                frames: self.frame_count,
+                plugins: self
+                    .plugins
+                    .iter_mut()
+                    .map(|profile| PluginReport {
+                        name: profile.name.clone(),
+                        update: profile.update.take_stats(),
+                        event: profile.event.take_stats(),
+                    })
+                    .collect(),
+            };
+            self.frame_count = 0;
+
+            Some(&self.report)
+        } else {
+            None
+        }
+    }
+
+    /// Report of the last completed window.
+    pub fn report(&self) -> &ProfilerReport {
+        &self.report
+    }
+
+    /// Time each plugin spent in its hooks during the last ended frame,
+    /// for logging, slowest first.
+    pub fn last_frame_summary(&self) -> String {
+        let mut plugins: Vec<_> = self.plugins.iter().collect();
+        plugins.sort_by_key(|profile| std::cmp::Reverse(profile.last_update + profile.last_event));
+
+        plugins
+            .iter()
+            .map(|profile| {
+                format!(
+                    "{} upd {:.3}ms evt {:.3}ms",
+                    profile.name,
+                    profile.last_update.as_secs_f64() * 1000.0,
+                    profile.last_event.as_secs_f64() * 1000.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl PluginProfile {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            frame_update: None,
+            frame_event: None,
+            last_update: Duration::ZERO,
+            last_event: Duration::ZERO,
+            update: Accumulator::default(),
+            event: Accumulator::default(),
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, elapsed: Duration) {
+        self.min = Some(self.min.map_or(elapsed, |min| min.min(elapsed)));
+        self.max = self.max.max(elapsed);
+        self.sum += elapsed;
+        self.frames += 1;
+    }
+
+    /// Produce the statistics and reset for the next window.
+    fn take_stats(&mut self) -> TimingStats {
+        let acc = std::mem::take(self);
+        TimingStats {
+            min: acc.min.unwrap_or_default(),
+            avg: if acc.frames > 0 {
+                acc.sum / acc.frames
+            } else {
+                Duration::ZERO
+            },
+            max: acc.max,
+            calls: acc.calls,
+        }
+    }
+}
+
+/// Aggregated plugin timings over a window of frames.
+#[derive(Debug, Default, Clone)]
+pub struct ProfilerReport {
+    /// Number of frames in the window.
+    pub frames: u32,
+    pub plugins: Vec<PluginReport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PluginReport {
+    pub name: String,
+    /// Time per frame spent in the update hook.
+    pub update: TimingStats,
+    /// Time per frame spent in the event hook.
+    pub event: TimingStats,
+}
+
+/// Statistics of per-frame durations.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimingStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Total number of calls in the window.
+    pub calls: u32,
+}
+
+impl fmt::Display for ProfilerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "plugin timings over {} frames (ms)", self.frames)?;
+        writeln!(
+            f,
+            "{:<24} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "plugin", "upd min", "upd avg", "upd max", "evt min", "evt avg", "evt max", "calls"
+        )?;
+
+        for plugin in self.plugins.iter() {
+            writeln!(
+                f,
+                "{:<24} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8}",
+                plugin.name,
+                plugin.update.min.as_secs_f64() * 1000.0,
+                plugin.update.avg.as_secs_f64() * 1000.0,
+                plugin.update.max.as_secs_f64() * 1000.0,
+                plugin.event.min.as_secs_f64() * 1000.0,
+                plugin.event.avg.as_secs_f64() * 1000.0,
+                plugin.event.max.as_secs_f64() * 1000.0,
+                plugin.update.calls + plugin.event.calls,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_window_stats() {
+        let mut profiler = Profiler::new(3);
+
+        // Calls within a frame add up to the frame's time.
+        profiler.record("a", CallKind::Update, ms(1));
+        profiler.record("a", CallKind::Update, ms(1));
+        profiler.record("a", CallKind::Event, ms(4));
+        assert!(profiler.end_frame().is_none());
+
+        profiler.record("a", CallKind::Update, ms(6));
+        assert!(profiler.end_frame().is_none());
+
+        profiler.record("a", CallKind::Update, ms(4));
+        let report = profiler.end_frame().unwrap();
+
+        assert_eq!(report.frames, 3);
+        let plugin = &report.plugins[0];
+        assert_eq!(plugin.name, "a");
+        assert_eq!(plugin.update.min, ms(2));
+        assert_eq!(plugin.update.avg, ms(4));
+        assert_eq!(plugin.update.max, ms(6));
+        assert_eq!(plugin.update.calls, 4);
+        // Frames without calls don't count towards the average.
+        assert_eq!(plugin.event.min, ms(4));
+        assert_eq!(plugin.event.avg, ms(4));
+        assert_eq!(plugin.event.calls, 1);
+    }
+
+    #[test]
+    fn test_windows_start_over() {
+        let mut profiler = Profiler::new(2);
+        for _ in 0..2 {
+            profiler.record("a", CallKind::Update, ms(10));
+            profiler.end_frame();
+        }
+        assert_eq!(profiler.report().plugins[0].update.avg, ms(10));
+
+        profiler.record("a", CallKind::Update, ms(2));
+        profiler.end_frame();
+        // The last completed window is kept until the next one ends.
+        assert_eq!(profiler.report().plugins[0].update.avg, ms(10));
+        profiler.end_frame();
+
+        let update = profiler.report().plugins[0].update;
+        assert_eq!(update.min, ms(2));
+        assert_eq!(update.avg, ms(2));
+        assert_eq!(update.max, ms(2));
+        assert_eq!(update.calls, 1);
+    }
+
+    #[test]
+    fn test_window_size_edge_cases() {
+        // A window of zero frames would never report.
+        let mut profiler = Profiler::new(0);
+        assert!(profiler.end_frame().is_some());
+
+        let mut profiler = Profiler::new(1);
+        assert!(profiler.end_frame().unwrap().plugins.is_empty());
+        profiler.record("a", CallKind::Update, ms(3));
+        let report = profiler.end_frame().unwrap();
+        assert_eq!(report.frames, 1);
+        assert_eq!(report.plugins[0].update.avg, ms(3));
+    }
+
+    #[test]
+    fn test_last_frame_summary() {
+        let mut profiler = Profiler::new(10);
+        profiler.record("fast", CallKind::Update, ms(1));
+        profiler.record("slow", CallKind::Update, ms(5));
+        profiler.record("slow", CallKind::Event, ms(2));
+        profiler.end_frame();
+
+        assert_eq!(
+            profiler.last_frame_summary(),
+            "slow upd 5.000ms evt 2.000ms, fast upd 1.000ms evt 0.000ms"
+        );
+
+        // Plugins that didn't run are idle in the next frame.
+        profiler.record("fast", CallKind::Update, ms(1));
+        profiler.end_frame();
+        assert_eq!(
+            profiler.last_frame_summary(),
+            "fast upd 1.000ms evt 0.000ms, slow upd 0.000ms evt 0.000ms"
+        );
+    }
+}
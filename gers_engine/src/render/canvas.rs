@@ -0,0 +1,278 @@
+//! Drawing primitives on a CPU side RGBA framebuffer.
+use super::{
+    font::{self, GLYPH_ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH},
+    texture::Texture,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgba(0, 0, 0, 255);
+    pub const WHITE: Color = Color::rgba(255, 255, 255, 255);
+    pub const GREEN: Color = Color::rgba(64, 220, 96, 255);
+    pub const YELLOW: Color = Color::rgba(240, 210, 64, 255);
+    pub const RED: Color = Color::rgba(230, 64, 64, 255);
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Unpack from `0xRRGGBBAA`.
+    pub const fn from_u32(rgba: u32) -> Self {
+        Self::rgba(
+            (rgba >> 24) as u8,
+            (rgba >> 16) as u8,
+            (rgba >> 8) as u8,
+            rgba as u8,
+        )
+    }
+}
+
+/// View over a framebuffer with 4 bytes per pixel in RGBA order.
+pub struct Canvas<'a> {
+    frame: &'a mut [u8],
+    width: u32,
+    height: u32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(frame: &'a mut [u8], width: u32, height: u32) -> Self {
+        debug_assert_eq!(frame.len(), (width * height * 4) as usize);
+        Self {
+            frame,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+    }
+
+    /// Blend a single pixel over the framebuffer.
+    ///
+    /// Coordinates outside of the canvas are clipped.
+    #[inline]
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+
+        let index = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let pixel = &mut self.frame[index..index + 4];
+
+        match color.a {
+            0 => {}
+            255 => pixel.copy_from_slice(&[color.r, color.g, color.b, 255]),
+            alpha => {
+                let alpha = alpha as u32;
+                let blend = |src: u8, dst: u8| -> u8 {
+                    ((src as u32 * alpha + dst as u32 * (255 - alpha)) / 255) as u8
+                };
+                pixel[0] = blend(color.r, pixel[0]);
+                pixel[1] = blend(color.g, pixel[1]);
+                pixel[2] = blend(color.b, pixel[2]);
+                pixel[3] = 255;
+            }
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        // Clip to canvas up front so huge rectangles stay cheap.
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x.saturating_add(width as i32)).min(self.width as i32);
+        let y1 = (y.saturating_add(height as i32)).min(self.height as i32);
+
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.put_pixel(px, py, color);
+            }
+        }
+    }
+
+    /// Draw a texture with its top left corner at the given position.
+    pub fn blit(&mut self, x: i32, y: i32, texture: &Texture) {
+        for (row, line) in texture
+            .pixels
+            .chunks_exact(texture.width as usize * 4)
+            .take(texture.height as usize)
+            .enumerate()
+        {
+            for (col, pixel) in line.chunks_exact(4).enumerate() {
+                self.put_pixel(
+                    x + col as i32,
+                    y + row as i32,
+                    Color::rgba(pixel[0], pixel[1], pixel[2], pixel[3]),
+                );
+            }
+        }
+    }
+
+    /// Bresenham line between two points, inclusive.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+
+        loop {
+            self.put_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draw a line of text with the debug font.
+    ///
+    /// Returns the width of the drawn text in pixels.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, scale: u32, color: Color) -> u32 {
+        let mut cursor = x;
+
+        for c in text.chars() {
+            let rows = font::glyph(c);
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        self.fill_rect(
+                            cursor + (col * scale) as i32,
+                            y + (row as u32 * scale) as i32,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+            cursor += (GLYPH_ADVANCE * scale) as i32;
+        }
+
+        (cursor - x) as u32
+    }
+
+    /// Width of a line of text at the given scale.
+    pub fn text_width(text: &str, scale: u32) -> u32 {
+        text.chars().count() as u32 * GLYPH_ADVANCE * scale
+    }
+
+    /// Height of a line of text at the given scale, including spacing.
+    pub fn line_height(scale: u32) -> u32 {
+        (GLYPH_HEIGHT + 2) * scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 8;
+    const HEIGHT: u32 = 6;
+
+    /// Positions of the pixels drawn on a blank canvas.
+    fn drawn(draw: impl FnOnce(&mut Canvas)) -> Vec<(u32, u32)> {
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+        draw(&mut canvas);
+
+        frame
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, pixel)| pixel[3] != 0)
+            .map(|(index, _)| (index as u32 % WIDTH, index as u32 / WIDTH))
+            .collect()
+    }
+
+    #[test]
+    fn test_color_from_u32() {
+        assert_eq!(
+            Color::from_u32(0x11223344),
+            Color::rgba(0x11, 0x22, 0x33, 0x44)
+        );
+    }
+
+    #[test]
+    fn test_put_pixel_blends() {
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+        canvas.put_pixel(0, 0, Color::rgba(255, 0, 0, 255));
+        canvas.put_pixel(0, 0, Color::rgba(0, 0, 255, 0));
+        assert_eq!(&canvas.frame[..4], [255, 0, 0, 255]);
+
+        canvas.put_pixel(0, 0, Color::rgba(0, 0, 255, 51));
+        assert_eq!(&canvas.frame[..4], [204, 0, 51, 255]);
+
+        // Outside of the canvas.
+        canvas.put_pixel(-1, 0, Color::WHITE);
+        canvas.put_pixel(0, HEIGHT as i32, Color::WHITE);
+        assert_eq!(canvas.frame[4..].iter().filter(|b| **b != 0).count(), 0);
+    }
+
+    #[test]
+    fn test_draw_text() {
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+        assert_eq!(canvas.draw_text(0, 0, "1", 1, Color::WHITE), GLYPH_ADVANCE);
+
+        assert_eq!(
+            drawn(|canvas| {
+                canvas.draw_text(0, 0, "1", 1, Color::WHITE);
+            }),
+            vec![
+                (1, 0),
+                (0, 1),
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (0, 4),
+                (1, 4),
+                (2, 4)
+            ]
+        );
+        // Lowercase letters are drawn as uppercase, and glyphs past
+        // the edge are clipped.
+        assert_eq!(
+            drawn(|canvas| {
+                canvas.draw_text(0, 0, "ab", 2, Color::WHITE);
+            }),
+            drawn(|canvas| {
+                canvas.draw_text(0, 0, "AB", 2, Color::WHITE);
+            })
+        );
+    }
+
+    #[test]
+    fn test_text_size() {
+        assert_eq!(Canvas::text_width("", 2), 0);
+        assert_eq!(Canvas::text_width("FPS 60", 1), 6 * GLYPH_ADVANCE);
+        assert_eq!(Canvas::text_width("FPS 60", 2), 12 * GLYPH_ADVANCE);
+        assert_eq!(Canvas::line_height(2), (GLYPH_HEIGHT + 2) * 2);
+    }
+}
@@ -0,0 +1,147 @@
+//! Draw commands submitted by plugins.
+//!
+//! Guests don't draw directly. Their draw calls are recorded
+//! into a queue during the frame's update, and the host renders
+//! the batch in submission order when the frame is redrawn.
+use super::{
+    texture::{TextureId, TextureRegistry},
+    Canvas, Color,
+};
+
+/// Size of the placeholder drawn for unknown textures.
+const MISSING_TEXTURE_SIZE: u32 = 16;
+const MISSING_TEXTURE_COLOR: Color = Color::rgba(255, 0, 255, 255);
+
+#[derive(Debug, Clone, Copy)]
+pub enum DrawCommand {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: Color,
+    },
+    Line {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        color: Color,
+    },
+    Sprite {
+        texture_id: TextureId,
+        x: f32,
+        y: f32,
+    },
+}
+
+/// Render a batch of draw commands in order.
+pub fn render_commands(canvas: &mut Canvas, commands: &[DrawCommand], textures: &TextureRegistry) {
+    for command in commands {
+        match *command {
+            DrawCommand::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+            } => {
+                canvas.fill_rect(
+                    x.round() as i32,
+                    y.round() as i32,
+                    width.round().max(0.0) as u32,
+                    height.round().max(0.0) as u32,
+                    color,
+                );
+            }
+            DrawCommand::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                color,
+            } => {
+                canvas.draw_line(
+                    x0.round() as i32,
+                    y0.round() as i32,
+                    x1.round() as i32,
+                    y1.round() as i32,
+                    color,
+                );
+            }
+            DrawCommand::Sprite { texture_id, x, y } => {
+                let (x, y) = (x.round() as i32, y.round() as i32);
+                match textures.get(texture_id) {
+                    Some(texture) => canvas.blit(x, y, texture),
+                    None => canvas.fill_rect(
+                        x,
+                        y,
+                        MISSING_TEXTURE_SIZE,
+                        MISSING_TEXTURE_SIZE,
+                        MISSING_TEXTURE_COLOR,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_commands_in_order() {
+        let mut frame = vec![0; 32 * 32 * 4];
+        let mut canvas = Canvas::new(&mut frame, 32, 32);
+        let commands = [
+            DrawCommand::Rect {
+                x: 0.4,
+                y: 0.0,
+                width: 2.0,
+                height: 1.0,
+                color: Color::RED,
+            },
+            DrawCommand::Line {
+                x0: 1.0,
+                y0: 0.0,
+                x1: 1.0,
+                y1: 1.0,
+                color: Color::GREEN,
+            },
+            DrawCommand::Rect {
+                x: 4.0,
+                y: 4.0,
+                width: -3.0,
+                height: 2.0,
+                color: Color::WHITE,
+            },
+            // Unknown textures draw a placeholder.
+            DrawCommand::Sprite {
+                texture_id: TextureId::from_raw(7),
+                x: 8.0,
+                y: 8.0,
+            },
+        ];
+        render_commands(&mut canvas, &commands, &TextureRegistry::default());
+
+        let pixel = |x: usize, y: usize| {
+            let index = (y * 32 + x) * 4;
+            Color::rgba(
+                frame[index],
+                frame[index + 1],
+                frame[index + 2],
+                frame[index + 3],
+            )
+        };
+        assert_eq!(pixel(0, 0), Color::RED);
+        // Later commands draw over earlier ones.
+        assert_eq!(pixel(1, 0), Color::GREEN);
+        assert_eq!(pixel(1, 1), Color::GREEN);
+        // Negative sizes draw nothing.
+        assert_eq!(pixel(4, 4).a, 0);
+        assert_eq!(pixel(8, 8), MISSING_TEXTURE_COLOR);
+        assert_eq!(pixel(23, 23), MISSING_TEXTURE_COLOR);
+        assert_eq!(pixel(24, 24).a, 0);
+    }
+}
@@ -0,0 +1,72 @@
+//! Minimal software rendering layer.
+//!
+//! Drawing happens on a CPU side RGBA framebuffer, which is
+//! uploaded and presented to the window by `pixels`.
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use std::path::{Path, PathBuf};
+use winit::window::Window;
+
+mod canvas;
+mod draw;
+mod font;
+mod texture;
+
+pub use canvas::{Canvas, Color};
+pub use draw::DrawCommand;
+pub use texture::{Texture, TextureId, TextureRegistry};
+
+pub struct Renderer {
+    pixels: Pixels,
+    width: u32,
+    height: u32,
+}
+
+impl Renderer {
+    pub fn new(window: &Window, vsync: bool) -> Result<Self, pixels::Error> {
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, window);
+        let pixels = PixelsBuilder::new(size.width, size.height, surface)
+            .enable_vsync(vsync)
+            .build()?;
+
+        Ok(Self {
+            pixels,
+            width: size.width,
+            height: size.height,
+        })
+    }
+
+    /// Resize the framebuffer to match the window.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // Minimized windows report zero size.
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.pixels.resize_surface(width, height);
+        self.pixels.resize_buffer(width, height);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Canvas for drawing the next frame.
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        Canvas::new(self.pixels.get_frame(), self.width, self.height)
+    }
+
+    /// Render a batch of plugin draw commands.
+    pub fn draw_commands(&mut self, commands: &[DrawCommand], textures: &TextureRegistry) {
+        let mut canvas = Canvas::new(self.pixels.get_frame(), self.width, self.height);
+        draw::render_commands(&mut canvas, commands, textures);
+    }
+
+    /// Save what was drawn so far as a PNG in `dir`, see `screenshot::save`.
+    pub fn save_screenshot(&mut self, dir: &Path) -> image::ImageResult<PathBuf> {
+        crate::screenshot::save(dir, self.pixels.get_frame(), self.width, self.height)
+    }
+
+    /// Present the framebuffer to the window.
+    pub fn present(&mut self) -> Result<(), pixels::Error> {
+        self.pixels.render()
+    }
+}
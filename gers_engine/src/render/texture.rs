@@ -0,0 +1,49 @@
+//! Host side texture storage.
+use gers_handles::{Handle, HandleTable};
+use std::path::{Path, PathBuf};
+
+/// Handle given to guests to refer to a texture.
+pub type TextureId = Handle;
+
+/// Image data in RGBA order, 4 bytes per pixel.
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Textures, with the key of the plugin that loaded each.
+#[derive(Default)]
+pub struct TextureRegistry {
+    textures: HandleTable<Texture, PathBuf>,
+}
+
+impl TextureRegistry {
+    /// Keep a texture on behalf of the plugin at `owner`.
+    pub fn insert(&mut self, owner: &Path, texture: Texture) -> TextureId {
+        self.textures.insert(owner.to_path_buf(), texture)
+    }
+
+    /// Drop a texture. Plugins can only drop the textures they loaded.
+    ///
+    /// Returns `true` if the texture existed.
+    pub fn remove(&mut self, owner: &Path, id: TextureId) -> bool {
+        self.textures.remove_owned(owner, id).is_ok()
+    }
+
+    /// Drop every texture the plugin at `owner` loaded.
+    ///
+    /// Returns the number of textures dropped.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        self.textures.release(owner).len()
+    }
+
+    pub fn get(&self, id: TextureId) -> Option<&Texture> {
+        self.textures.get(id).ok()
+    }
+
+    /// Returns `true` if the plugin at `owner` loaded the texture.
+    pub fn is_owned(&self, owner: &Path, id: TextureId) -> bool {
+        self.textures.get_owned(owner, id).is_ok()
+    }
+}
@@ -0,0 +1,595 @@
+//! Recording and replaying the event stream sent to plugins.
+//!
+//! A recording starts with a header holding the seed, followed by one
+//! record per frame. Each frame record holds the frame index, its delta
+//! time, and the events dispatched to plugins during that frame, with
+//! their payloads encoded as little endian fields. Since version 2, each
+//! event also holds the `plugin_id` of the plugin that emitted it, or
+//! `EVENT_SOURCE_HOST`.
+//!
+//! Replaying feeds the recorded frames back in place of the live clock
+//! and input devices, so a run can be reproduced from a user's file.
+use gers_events::{
+    ActionEvent, AppPausedEvent, AppResumedEvent, CustomEvent, DamageEvent, EventType,
+    GamepadAxisEvent, GamepadButtonEvent, HelloEvent, MouseMoveEvent, SimulationLaggingEvent,
+    SimulationStateEvent, WindowEvent, EVENT_SOURCE_HOST,
+};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+const MAGIC: &[u8; 8] = b"GERSRPLY";
+const FORMAT_VERSION: u32 = 2;
+/// Oldest version that can still be replayed, without event sources.
+const MIN_FORMAT_VERSION: u32 = 1;
+
+/// Event sent to plugins during a frame.
+#[derive(Debug, Clone)]
+pub enum FrameEvent {
+    Hello(HelloEvent),
+    Action(ActionEvent),
+    GamepadButton(GamepadButtonEvent),
+    GamepadAxis(GamepadAxisEvent),
+    MouseMove(MouseMoveEvent),
+    Damage(DamageEvent),
+    AppPaused(AppPausedEvent),
+    AppResumed(AppResumedEvent),
+    Window(WindowEvent),
+    /// Boxed, as its data makes it far larger than the other events.
+    Custom(Box<CustomEvent>),
+    SimulationLagging(SimulationLaggingEvent),
+    SimulationState(SimulationStateEvent),
+}
+
+impl FrameEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            FrameEvent::Hello(_) => EventType::Hello,
+            FrameEvent::Action(_) => EventType::Action,
+            FrameEvent::GamepadButton(_) => EventType::GamepadButton,
+            FrameEvent::GamepadAxis(_) => EventType::GamepadAxis,
+            FrameEvent::MouseMove(_) => EventType::MouseMove,
+            FrameEvent::Damage(_) => EventType::Damage,
+            FrameEvent::AppPaused(_) => EventType::AppPaused,
+            FrameEvent::AppResumed(_) => EventType::AppResumed,
+            FrameEvent::Window(_) => EventType::Window,
+            FrameEvent::Custom(_) => EventType::Custom,
+            FrameEvent::SimulationLagging(_) => EventType::SimulationLagging,
+            FrameEvent::SimulationState(_) => EventType::SimulationState,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            FrameEvent::Hello(event) => {
+                out.extend_from_slice(&event.data.to_le_bytes());
+                out.push(event.padding);
+                out.extend_from_slice(&event.div.to_le_bytes());
+            }
+            FrameEvent::Action(event) => {
+                out.extend_from_slice(&event.action_id.to_le_bytes());
+                out.push(event.pressed as u8);
+                out.extend_from_slice(&event.value.to_le_bytes());
+            }
+            FrameEvent::GamepadButton(event) => {
+                out.extend_from_slice(&event.device_id.to_le_bytes());
+                out.extend_from_slice(&event.button.to_le_bytes());
+                out.push(event.pressed as u8);
+                out.extend_from_slice(&event.value.to_le_bytes());
+            }
+            FrameEvent::GamepadAxis(event) => {
+                out.extend_from_slice(&event.device_id.to_le_bytes());
+                out.extend_from_slice(&event.axis.to_le_bytes());
+                out.extend_from_slice(&event.value.to_le_bytes());
+            }
+            FrameEvent::MouseMove(event) => {
+                out.extend_from_slice(&event.device_id.to_le_bytes());
+                out.extend_from_slice(&event.x.to_le_bytes());
+                out.extend_from_slice(&event.y.to_le_bytes());
+            }
+            FrameEvent::Damage(event) => {
+                out.extend_from_slice(&event.entity.to_le_bytes());
+                out.extend_from_slice(&event.source.to_le_bytes());
+                out.extend_from_slice(&event.amount.to_le_bytes());
+                out.extend_from_slice(&event.cancelled.to_le_bytes());
+            }
+            FrameEvent::AppPaused(event) => {
+                out.extend_from_slice(&event.reason.to_le_bytes());
+            }
+            FrameEvent::AppResumed(event) => {
+                out.extend_from_slice(&event.paused_ms.to_le_bytes());
+            }
+            FrameEvent::Window(event) => {
+                out.extend_from_slice(&event.window.to_le_bytes());
+                out.extend_from_slice(&event.kind.to_le_bytes());
+                out.extend_from_slice(&event.width.to_le_bytes());
+                out.extend_from_slice(&event.height.to_le_bytes());
+            }
+            // Only the used part of the data is recorded.
+            FrameEvent::Custom(event) => {
+                out.extend_from_slice(&event.type_id.to_le_bytes());
+                out.extend_from_slice(event.data());
+            }
+            FrameEvent::SimulationLagging(event) => {
+                out.extend_from_slice(&event.ticks_behind.to_le_bytes());
+                out.extend_from_slice(&event.dropped.to_le_bytes());
+            }
+            FrameEvent::SimulationState(event) => {
+                out.extend_from_slice(&event.state.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(event_type: EventType, payload: &[u8]) -> io::Result<Self> {
+        let mut payload = Payload(payload);
+
+        let event = match event_type {
+            EventType::Hello => FrameEvent::Hello(HelloEvent {
+                data: payload.u32()?,
+                padding: payload.u8()?,
+                div: payload.u16()?,
+            }),
+            EventType::Action => FrameEvent::Action(ActionEvent {
+                action_id: payload.u32()?,
+                pressed: payload.u8()? != 0,
+                value: payload.f32()?,
+            }),
+            EventType::GamepadButton => FrameEvent::GamepadButton(GamepadButtonEvent {
+                device_id: payload.u32()?,
+                button: payload.u32()?,
+                pressed: payload.u8()? != 0,
+                value: payload.f32()?,
+            }),
+            EventType::GamepadAxis => FrameEvent::GamepadAxis(GamepadAxisEvent {
+                device_id: payload.u32()?,
+                axis: payload.u32()?,
+                value: payload.f32()?,
+            }),
+            EventType::MouseMove => FrameEvent::MouseMove(MouseMoveEvent {
+                device_id: payload.u32()?,
+                x: payload.f32()?,
+                y: payload.f32()?,
+            }),
+            EventType::Damage => FrameEvent::Damage(DamageEvent {
+                entity: payload.u32()?,
+                source: payload.u32()?,
+                amount: payload.f32()?,
+                cancelled: payload.u32()?,
+            }),
+            EventType::AppPaused => FrameEvent::AppPaused(AppPausedEvent {
+                reason: payload.u32()?,
+            }),
+            EventType::AppResumed => FrameEvent::AppResumed(AppResumedEvent {
+                paused_ms: payload.u32()?,
+            }),
+            EventType::Window => FrameEvent::Window(WindowEvent {
+                window: payload.u32()?,
+                kind: payload.u32()?,
+                width: payload.u32()?,
+                height: payload.u32()?,
+            }),
+            EventType::Custom => {
+                let type_id = payload.u32()?;
+                let event = CustomEvent::new(type_id, payload.0)
+                    .ok_or_else(|| invalid_data("custom event data too large"))?;
+                FrameEvent::Custom(Box::new(event))
+            }
+            EventType::SimulationLagging => FrameEvent::SimulationLagging(SimulationLaggingEvent {
+                ticks_behind: payload.u32()?,
+                dropped: payload.u32()?,
+            }),
+            EventType::SimulationState => FrameEvent::SimulationState(SimulationStateEvent {
+                state: payload.u32()?,
+            }),
+            // HTTP responses, timers, tasks, console commands, settings,
+            // locales, screenshots and shutdown are delivered outside the
+            // frame's event stream.
+            EventType::NoOp
+            | EventType::HttpResponse
+            | EventType::TimerFired
+            | EventType::TaskCompleted
+            | EventType::ScreenshotTaken
+            | EventType::ShutdownRequested
+            | EventType::ConsoleCommand
+            | EventType::SettingsChanged
+            | EventType::LocaleChanged => {
+                return Err(invalid_data("unexpected event type in recording"))
+            }
+        };
+
+        Ok(event)
+    }
+}
+
+/// Events dispatched during one frame.
+#[derive(Debug, Default)]
+pub struct RecordedFrame {
+    pub index: u64,
+    pub delta_time: Duration,
+    pub events: Vec<FrameEvent>,
+    /// Sources of the events emitted by plugins, by their index in
+    /// `events`. The others come from the host.
+    pub sources: BTreeMap<usize, u32>,
+}
+
+impl RecordedFrame {
+    /// Add an event emitted by the plugin with the `plugin_id` `source`.
+    pub fn push_from(&mut self, source: u32, event: FrameEvent) {
+        if source != EVENT_SOURCE_HOST {
+            self.sources.insert(self.events.len(), source);
+        }
+        self.events.push(event);
+    }
+
+    /// The `plugin_id` of the event's source, or `EVENT_SOURCE_HOST`.
+    pub fn source(&self, index: usize) -> u32 {
+        self.sources
+            .get(&index)
+            .copied()
+            .unwrap_or(EVENT_SOURCE_HOST)
+    }
+}
+
+/// Writes the event stream to a recording file.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    buf: Vec<u8>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, seed: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&seed.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            buf: vec![],
+        })
+    }
+
+    /// Append a frame to the recording.
+    ///
+    /// The file is flushed after every frame, so the recording
+    /// survives the application exiting abruptly.
+    pub fn write_frame(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        let delta_nanos = frame.delta_time.as_nanos() as u64;
+
+        self.writer.write_all(&frame.index.to_le_bytes())?;
+        self.writer.write_all(&delta_nanos.to_le_bytes())?;
+        self.writer
+            .write_all(&(frame.events.len() as u32).to_le_bytes())?;
+
+        for (index, event) in frame.events.iter().enumerate() {
+            self.buf.clear();
+            event.encode(&mut self.buf);
+
+            self.writer
+                .write_all(&(event.event_type() as i32).to_le_bytes())?;
+            self.writer.write_all(&frame.source(index).to_le_bytes())?;
+            self.writer
+                .write_all(&(self.buf.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&self.buf)?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+/// Reads frames back from a recording file.
+pub struct Replay {
+    reader: BufReader<File>,
+    version: u32,
+    seed: u64,
+}
+
+impl Replay {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a gers recording"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(invalid_data(format!(
+                "unsupported recording version {}",
+                version
+            )));
+        }
+
+        let seed = read_u64(&mut reader)?;
+
+        Ok(Self {
+            reader,
+            version,
+            seed,
+        })
+    }
+
+    /// Seed of the recorded run.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Read the next frame, or `None` when the recording has ended.
+    pub fn next_frame(&mut self) -> io::Result<Option<RecordedFrame>> {
+        let index = match read_u64(&mut self.reader) {
+            Ok(index) => index,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let delta_time = Duration::from_nanos(read_u64(&mut self.reader)?);
+        let event_count = read_u32(&mut self.reader)?;
+
+        let mut frame = RecordedFrame {
+            index,
+            delta_time,
+            events: vec![],
+            sources: BTreeMap::new(),
+        };
+        let mut payload = vec![];
+        for _ in 0..event_count {
+            let event_type = EventType::from(read_u32(&mut self.reader)? as i32);
+            let source = if self.version >= 2 {
+                read_u32(&mut self.reader)?
+            } else {
+                EVENT_SOURCE_HOST
+            };
+            let len = read_u32(&mut self.reader)?;
+
+            // Don't trust the length with the allocation.
+            payload.clear();
+            self.reader
+                .by_ref()
+                .take(len as u64)
+                .read_to_end(&mut payload)?;
+            if payload.len() != len as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            frame.push_from(source, FrameEvent::decode(event_type, &payload)?);
+        }
+
+        Ok(Some(frame))
+    }
+}
+
+/// Cursor over an event payload.
+struct Payload<'a>(&'a [u8]);
+
+impl<'a> Payload<'a> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid_data("event payload too short"));
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(head);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        self.take::<1>().map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gers_events::{plugin_id, PauseReason, SimulationState, WindowEventKind};
+    use std::{fs, path::PathBuf};
+
+    fn recording_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gers-replay-{}-{}.rec", name, std::process::id()))
+    }
+
+    fn events() -> Vec<FrameEvent> {
+        vec![
+            FrameEvent::Hello(HelloEvent {
+                data: 42,
+                padding: 1,
+                div: 7,
+            }),
+            FrameEvent::Action(ActionEvent {
+                action_id: 3,
+                pressed: true,
+                value: 0.5,
+            }),
+            FrameEvent::GamepadButton(GamepadButtonEvent {
+                device_id: 1,
+                button: 2,
+                pressed: false,
+                value: 0.25,
+            }),
+            FrameEvent::GamepadAxis(GamepadAxisEvent {
+                device_id: 1,
+                axis: 4,
+                value: -1.0,
+            }),
+            FrameEvent::MouseMove(MouseMoveEvent {
+                device_id: 0,
+                x: 320.5,
+                y: 12.0,
+            }),
+            FrameEvent::Damage(DamageEvent {
+                entity: 5,
+                source: 0,
+                amount: 12.5,
+                cancelled: 0,
+            }),
+            FrameEvent::AppPaused(AppPausedEvent {
+                reason: PauseReason::Minimized as u32,
+            }),
+            FrameEvent::AppResumed(AppResumedEvent { paused_ms: 1500 }),
+            FrameEvent::Window(WindowEvent {
+                window: 1,
+                kind: WindowEventKind::Resized as u32,
+                width: 640,
+                height: 480,
+            }),
+            FrameEvent::Custom(Box::new(CustomEvent::new(2, b"score=120").unwrap())),
+            FrameEvent::SimulationLagging(SimulationLaggingEvent {
+                ticks_behind: 3,
+                dropped: 1,
+            }),
+            FrameEvent::SimulationState(SimulationStateEvent {
+                state: SimulationState::Stepping as u32,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = recording_path("round-trip");
+        let frames = [
+            RecordedFrame {
+                index: 0,
+                delta_time: Duration::from_millis(16),
+                events: events(),
+                sources: [(4, plugin_id("combat"))].into_iter().collect(),
+            },
+            RecordedFrame {
+                index: 1,
+                delta_time: Duration::from_nanos(16_666_667),
+                ..RecordedFrame::default()
+            },
+        ];
+
+        let mut recorder = Recorder::create(&path, 1234).unwrap();
+        for frame in frames.iter() {
+            recorder.write_frame(frame).unwrap();
+        }
+        drop(recorder);
+
+        let mut replay = Replay::open(&path).unwrap();
+        assert_eq!(replay.seed(), 1234);
+        for frame in frames.iter() {
+            let replayed = replay.next_frame().unwrap().unwrap();
+            assert_eq!(replayed.index, frame.index);
+            assert_eq!(replayed.delta_time, frame.delta_time);
+            // Events don't implement `PartialEq`, so their fields
+            // are compared through their debug output.
+            assert_eq!(
+                format!("{:?}", replayed.events),
+                format!("{:?}", frame.events)
+            );
+            assert_eq!(replayed.sources, frame.sources);
+        }
+        assert!(replay.next_frame().unwrap().is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_recordings() {
+        let path = recording_path("invalid");
+        let header = |magic: &[u8], version: u32| {
+            let mut bytes = magic.to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+            bytes
+        };
+
+        for bytes in [
+            vec![],
+            b"GERS".to_vec(),
+            header(b"NOTGERS!", FORMAT_VERSION),
+            header(MAGIC, MIN_FORMAT_VERSION - 1),
+            header(MAGIC, FORMAT_VERSION + 1),
+        ] {
+            fs::write(&path, bytes).unwrap();
+            assert!(Replay::open(&path).is_err());
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let path = recording_path("invalid-frames");
+        // In the oldest format, without the event's source.
+        let frame = |event_type: EventType, payload: &[u8]| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&MIN_FORMAT_VERSION.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&(event_type as i32).to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(payload);
+            bytes
+        };
+
+        for bytes in [
+            // Payload shorter than the event.
+            frame(EventType::Action, &[0; 4]),
+            // Events that are never recorded.
+            frame(EventType::NoOp, &[]),
+        ] {
+            fs::write(&path, bytes).unwrap();
+            let err = Replay::open(&path).unwrap().next_frame().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // Frames cut off part way through.
+        let mut bytes = frame(EventType::GamepadAxis, &[0; 12]);
+        bytes.truncate(bytes.len() - 2);
+        fs::write(&path, bytes).unwrap();
+        let err = Replay::open(&path).unwrap().next_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Counts and lengths past the end of the file, which aren't
+        // allocated up front.
+        let mut huge_len = frame(EventType::GamepadAxis, &[0; 12]);
+        let len_at = huge_len.len() - 16;
+        huge_len[len_at..len_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut huge_count = frame(EventType::GamepadAxis, &[0; 12]);
+        huge_count[36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+        for bytes in [huge_len, huge_count] {
+            fs::write(&path, bytes).unwrap();
+            let err = Replay::open(&path).unwrap().next_frame().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! Host-side resources owned by plugins.
+//!
+//! Every registry of resources plugins create through the host imports,
+//! like timers, subscriptions, textures and connections, keeps the key
+//! of the plugin owning each handle, see `Plugin::key`. When a plugin is
+//! torn down mid-session, like when it faults, everything it owns is
+//! released at once, rather than lingering until the game exits.
+use std::{fmt, path::Path};
+
+use crate::env::GersEnv;
+
+/// Number of resources of each kind released for a plugin.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Released {
+    pub timers: usize,
+    pub subscriptions: usize,
+    /// Custom event types the plugin no longer handles.
+    pub custom_events: usize,
+    pub actions: usize,
+    pub commands: usize,
+    pub requests: usize,
+    pub tasks: usize,
+    pub connections: usize,
+    pub windows: usize,
+    pub voices: usize,
+    pub sounds: usize,
+    pub textures: usize,
+    pub watches: usize,
+}
+
+impl Released {
+    fn counts(&self) -> [(&'static str, usize); 13] {
+        [
+            ("timers", self.timers),
+            ("subscriptions", self.subscriptions),
+            ("custom events", self.custom_events),
+            ("actions", self.actions),
+            ("commands", self.commands),
+            ("requests", self.requests),
+            ("tasks", self.tasks),
+            ("connections", self.connections),
+            ("windows", self.windows),
+            ("voices", self.voices),
+            ("sounds", self.sounds),
+            ("textures", self.textures),
+            ("watches", self.watches),
+        ]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts().iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for Released {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (kind, count) in self.counts() {
+            if count == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", count, kind)?;
+            first = false;
+        }
+        if first {
+            write!(f, "nothing")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Release every host-side resource the plugin `owner` owns.
+///
+/// The plugin must not be called anymore, as its handles are invalid
+/// afterwards, and may be handed out to other plugins.
+pub fn release_plugin(gers_env: &GersEnv, owner: &Path) -> Released {
+    let mut released = Released::default();
+
+    if let Ok(mut timers) = gers_env.timers.lock() {
+        released.timers = timers.release(owner);
+    }
+    if let Ok(mut hooks) = gers_env.hooks.lock() {
+        released.subscriptions = hooks.release(owner);
+    }
+    if let Ok(mut custom_events) = gers_env.custom_events.write() {
+        released.custom_events = custom_events.release(owner);
+    }
+    if let Ok(mut input) = gers_env.input.lock() {
+        released.actions = input.release(owner);
+    }
+    if let Ok(mut console) = gers_env.console.lock() {
+        released.commands = console.release(owner);
+    }
+    if let Ok(mut http) = gers_env.http.lock() {
+        released.requests = http.release(owner);
+    }
+    if let Ok(mut tasks) = gers_env.tasks.lock() {
+        released.tasks = tasks.release(owner);
+    }
+    if let Ok(mut network) = gers_env.network.lock() {
+        released.connections = network.release(owner);
+    }
+    if let Ok(mut windows) = gers_env.windows.lock() {
+        released.windows = windows.release(owner);
+    }
+    if let Ok(mut audio) = gers_env.audio.lock() {
+        let (voices, sounds) = audio.release(owner);
+        released.voices = voices;
+        released.sounds = sounds;
+    }
+    if let Ok(mut textures) = gers_env.textures.write() {
+        released.textures = textures.release(owner);
+    }
+    if let Ok(mut watches) = gers_env.watches.lock() {
+        released.watches = watches.release(owner);
+    }
+
+    released
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Texture;
+    use gers_events::EventType;
+    use std::time::Duration;
+
+    #[test]
+    fn test_release_plugin() {
+        let env = GersEnv::for_test();
+        let (faulted, other) = (Path::new("faulted"), Path::new("other"));
+
+        for root in [faulted, other] {
+            env.timers
+                .lock()
+                .unwrap()
+                .set(root, Duration::from_secs(1), true)
+                .unwrap();
+            env.hooks
+                .lock()
+                .unwrap()
+                .subscribe(root, EventType::Hello, 0, 0);
+            env.custom_events
+                .write()
+                .unwrap()
+                .subscribe("chat", root)
+                .unwrap();
+            env.input.lock().unwrap().register(root, "jump", &[]);
+            env.textures.write().unwrap().insert(
+                root,
+                Texture {
+                    width: 1,
+                    height: 1,
+                    pixels: vec![0; 4],
+                },
+            );
+        }
+        env.console
+            .lock()
+            .unwrap()
+            .register(faulted, "spawn".to_string(), String::new())
+            .unwrap();
+        env.windows
+            .lock()
+            .unwrap()
+            .create(faulted, "Map".to_string(), 64, 64)
+            .unwrap();
+        env.windows.lock().unwrap().take_commands();
+        env.watches
+            .lock()
+            .unwrap()
+            .watch(faulted, "hp".to_string(), 0, 1)
+            .unwrap();
+
+        let released = release_plugin(&env, faulted);
+        assert_eq!(
+            released,
+            Released {
+                timers: 1,
+                subscriptions: 1,
+                custom_events: 1,
+                actions: 1,
+                commands: 1,
+                windows: 1,
+                textures: 1,
+                watches: 1,
+                ..Released::default()
+            }
+        );
+        assert_eq!(released.total(), 8);
+        assert_eq!(
+            released.to_string(),
+            "1 timers, 1 subscriptions, 1 custom events, 1 actions, 1 commands, 1 windows, 1 textures, 1 watches"
+        );
+        assert_eq!(env.windows.lock().unwrap().take_commands().len(), 1);
+
+        // Resources of other plugins are kept.
+        let released = release_plugin(&env, other);
+        assert_eq!(released.total(), 5);
+        assert_eq!(release_plugin(&env, faulted).to_string(), "nothing");
+    }
+}
@@ -0,0 +1,330 @@
+//! Savegames holding the state of every plugin.
+//!
+//! Saving calls each plugin's `__gers_save`, during which the plugin
+//! writes its state into a host buffer. The buffers are collected into
+//! a single file, starting with a header, followed by one record per
+//! plugin holding its name, version and state.
+//!
+//! Loading passes each record to the `__gers_load` of the plugin with
+//! the same name. Records of plugins that aren't loaded, or that were
+//! saved by a different version of the plugin, are skipped and reported.
+use gers_plugins::Plugins;
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+use wasmer::RuntimeError;
+
+const MAGIC: &[u8; 8] = b"GERSSAVE";
+const FORMAT_VERSION: u32 = 1;
+
+/// Directory, relative to the working directory, holding savegames.
+pub const SAVE_DIR: &str = "saves";
+
+/// Savegame written and read by the quick save keys.
+pub const QUICKSAVE_FILENAME: &str = "quicksave.gsav";
+
+#[derive(Debug, Default)]
+pub struct SaveGame {
+    pub records: Vec<SaveRecord>,
+}
+
+/// State of a single plugin.
+#[derive(Debug)]
+pub struct SaveRecord {
+    pub name: String,
+    pub version: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// The plugin with this name trapped in its save or load hook.
+    Plugin(String, RuntimeError),
+    Io(io::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Plugin(name, err) => write!(f, "plugin {}: {}", name, err.message()),
+            SaveError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+/// Outcome of loading a savegame.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    /// Indices of the records to pass to their plugins.
+    pub load: Vec<usize>,
+    /// Name and version of saved plugins that aren't loaded.
+    pub missing: Vec<(String, String)>,
+    /// Name, saved version and loaded version of plugins that changed
+    /// version since the game was saved.
+    pub mismatched: Vec<(String, String, String)>,
+}
+
+impl LoadReport {
+    /// Match the records of a savegame with the loaded plugins,
+    /// given by name and version.
+    pub fn new<'a>(save: &SaveGame, loaded: impl Iterator<Item = (&'a str, &'a str)>) -> Self {
+        let loaded: Vec<_> = loaded.collect();
+        let mut report = LoadReport::default();
+
+        for (index, record) in save.records.iter().enumerate() {
+            match loaded.iter().find(|(name, _)| *name == record.name) {
+                Some((_, version)) if *version == record.version => report.load.push(index),
+                Some((_, version)) => report.mismatched.push((
+                    record.name.clone(),
+                    record.version.clone(),
+                    version.to_string(),
+                )),
+                None => report
+                    .missing
+                    .push((record.name.clone(), record.version.clone())),
+            }
+        }
+
+        report
+    }
+}
+
+impl SaveGame {
+    /// Collect the state of every plugin that exports `__gers_save`.
+    ///
+    /// The buffer is the one the host's save imports write into.
+    pub fn from_plugins(plugins: &Plugins, buffer: &Mutex<Vec<u8>>) -> Result<Self, SaveError> {
+        let mut save = SaveGame::default();
+
+        for plugin in plugins.iter_plugins().filter(|plugin| plugin.is_saved()) {
+            let name = &plugin.meta().name;
+            clear_buffer(buffer);
+            plugin
+                .save()
+                .map_err(|err| SaveError::Plugin(name.clone(), err))?;
+
+            save.records.push(SaveRecord {
+                name: name.clone(),
+                version: plugin.meta().version.clone(),
+                data: take_buffer(buffer),
+            });
+        }
+
+        Ok(save)
+    }
+
+    /// Pass the saved state to the plugins it was saved from.
+    pub fn load_into(&self, plugins: &Plugins) -> Result<LoadReport, SaveError> {
+        let report = LoadReport::new(
+            self,
+            plugins
+                .iter_plugins()
+                .map(|plugin| (plugin.meta().name.as_str(), plugin.meta().version.as_str())),
+        );
+
+        for record in report.load.iter().map(|index| &self.records[*index]) {
+            let plugin = plugins
+                .iter_plugins()
+                .find(|plugin| plugin.meta().name == record.name);
+            if let Some(plugin) = plugin {
+                plugin
+                    .load(&record.data)
+                    .map_err(|err| SaveError::Plugin(record.name.clone(), err))?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.records.len() as u32).to_le_bytes())?;
+
+        for record in self.records.iter() {
+            write_bytes(&mut writer, record.name.as_bytes())?;
+            write_bytes(&mut writer, record.version.as_bytes())?;
+            write_bytes(&mut writer, &record.data)?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a gers savegame"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported savegame version {}",
+                version
+            )));
+        }
+
+        let count = read_u32(&mut reader)?;
+        let mut records = vec![];
+        for _ in 0..count {
+            records.push(SaveRecord {
+                name: read_string(&mut reader)?,
+                version: read_string(&mut reader)?,
+                data: read_bytes(&mut reader)?,
+            });
+        }
+
+        Ok(SaveGame { records })
+    }
+}
+
+fn clear_buffer(buffer: &Mutex<Vec<u8>>) {
+    if let Ok(mut buffer) = buffer.lock() {
+        buffer.clear();
+    }
+}
+
+fn take_buffer(buffer: &Mutex<Vec<u8>>) -> Vec<u8> {
+    buffer
+        .lock()
+        .map(|mut buffer| std::mem::take(&mut *buffer))
+        .unwrap_or_default()
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)?;
+    let mut bytes = vec![];
+    // Don't trust the length with the allocation.
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid_data("record name isn't UTF-8"))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn save_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gers-save-{}-{}.gsav", name, std::process::id()))
+    }
+
+    fn record(name: &str, version: &str, data: &[u8]) -> SaveRecord {
+        SaveRecord {
+            name: name.to_string(),
+            version: version.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = save_path("round-trip");
+        let save = SaveGame {
+            records: vec![
+                record("core", "1.0.0", b"state"),
+                record("empty", "0.1.0", b""),
+            ],
+        };
+
+        save.write(&path).unwrap();
+        let read = SaveGame::read(&path).unwrap();
+        assert_eq!(format!("{:?}", read), format!("{:?}", save));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_savegames() {
+        let path = save_path("invalid");
+        let header = |magic: &[u8], version: u32, count: u32| {
+            let mut bytes = magic.to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes
+        };
+        let mut truncated = header(MAGIC, FORMAT_VERSION, 1);
+        truncated.extend_from_slice(&100u32.to_le_bytes());
+        truncated.extend_from_slice(b"core");
+
+        for bytes in [
+            vec![],
+            header(b"NOTGERS!", FORMAT_VERSION, 0),
+            header(MAGIC, FORMAT_VERSION + 1, 0),
+            header(MAGIC, FORMAT_VERSION, 1),
+            truncated,
+        ] {
+            fs::write(&path, bytes).unwrap();
+            assert!(SaveGame::read(&path).is_err());
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_report() {
+        let save = SaveGame {
+            records: vec![
+                record("core", "1.0.0", b""),
+                record("removed", "1.0.0", b""),
+                record("updated", "1.0.0", b""),
+            ],
+        };
+        let loaded = [("updated", "2.0.0"), ("core", "1.0.0"), ("new", "1.0.0")];
+
+        let report = LoadReport::new(&save, loaded.iter().copied());
+        assert_eq!(report.load, [0]);
+        assert_eq!(
+            report.missing,
+            [("removed".to_string(), "1.0.0".to_string())]
+        );
+        assert_eq!(
+            report.mismatched,
+            [(
+                "updated".to_string(),
+                "1.0.0".to_string(),
+                "2.0.0".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Spreading yielded plugin work across frames.
+//!
+//! Plugins with more work than fits in a frame yield from their update,
+//! and are resumed on later frames. Each frame the plugins with pending
+//! work share a time budget, so heavy work can't stall the frame.
+use std::time::Duration;
+
+use crate::clock::Clock;
+
+pub struct WorkScheduler<C: Clock> {
+    /// Time shared by the resumed plugins each frame.
+    budget: Duration,
+    /// Rotates which plugin is resumed first, so a plugin that overruns
+    /// its share doesn't starve the same plugins every frame.
+    turn: usize,
+    clock: C,
+}
+
+impl<C: Clock> WorkScheduler<C> {
+    /// Scheduler measuring the budget on the given clock.
+    pub fn new(budget: Duration, clock: C) -> Self {
+        Self {
+            budget,
+            turn: 0,
+            clock,
+        }
+    }
+
+    /// Resume the plugins with pending work, given by their index.
+    ///
+    /// Each plugin gets an equal share of what's left of the budget.
+    /// Plugins that don't get a turn before the budget runs out are
+    /// resumed on a later frame.
+    pub fn run(&mut self, pending: &[usize], mut resume: impl FnMut(usize, Duration)) {
+        if pending.is_empty() {
+            return;
+        }
+
+        let start = self.clock.now();
+        let first = self.turn % pending.len();
+        self.turn = self.turn.wrapping_add(1);
+
+        let order = pending[first..].iter().chain(pending[..first].iter());
+        for (count, index) in order.enumerate() {
+            let remaining = self
+                .budget
+                .saturating_sub(self.clock.now().duration_since(start));
+            if remaining.is_zero() {
+                break;
+            }
+
+            let waiting = (pending.len() - count) as u32;
+            resume(*index, remaining / waiting);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[test]
+    fn test_budget_is_shared() {
+        let mut scheduler = WorkScheduler::new(Duration::from_secs(30), SystemClock);
+        let mut calls = vec![];
+
+        scheduler.run(&[2, 5], |index, budget| calls.push((index, budget)));
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, 2);
+        assert!(calls[0].1 <= Duration::from_secs(15));
+        assert!(calls[0].1 > Duration::from_secs(14));
+        // Gets what's left over.
+        assert!(calls[1].1 > Duration::from_secs(14));
+    }
+
+    #[test]
+    fn test_first_turn_rotates() {
+        let mut scheduler = WorkScheduler::new(Duration::from_secs(30), SystemClock);
+        let mut first = vec![];
+
+        for _ in 0..4 {
+            let mut calls = vec![];
+            scheduler.run(&[1, 3, 4], |index, _| calls.push(index));
+            assert_eq!(calls.len(), 3);
+            first.push(calls[0]);
+        }
+
+        assert_eq!(first, [1, 3, 4, 1]);
+    }
+
+    #[test]
+    fn test_budget_runs_out() {
+        let mut scheduler = WorkScheduler::new(Duration::from_millis(1), SystemClock);
+        let mut calls = vec![];
+
+        scheduler.run(&[0, 1, 2], |index, budget| {
+            calls.push(index);
+            // Overrun the whole budget.
+            std::thread::sleep(budget * 4);
+        });
+
+        assert_eq!(calls, [0]);
+    }
+
+    #[test]
+    fn test_shares_on_test_clock() {
+        use crate::clock::TestClock;
+
+        let clock = TestClock::new();
+        let mut scheduler = WorkScheduler::new(Duration::from_millis(30), clock.clone());
+        let mut calls = vec![];
+
+        scheduler.run(&[0, 1, 2], |index, budget| {
+            calls.push((index, budget));
+            match index {
+                // Done early, leaving the rest to the others.
+                0 => clock.advance(budget / 2),
+                // Overruns, so the last plugin waits for the next frame.
+                _ => clock.advance(budget * 2),
+            }
+        });
+
+        assert_eq!(
+            calls,
+            [
+                (0, Duration::from_millis(10)),
+                (1, Duration::from_micros(12_500))
+            ]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Screenshots of the main window.
+//!
+//! Taken with F12, or by plugins with `gers_window.screenshot`, once the
+//! game and the plugins rendered the frame, before the debug overlays
+//! are drawn over it. Each is saved as a PNG in `SCREENSHOT_DIR`, and
+//! plugins receive a `ScreenshotTakenEvent` with its path in the next
+//! frame.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory screenshots are saved to, relative to the working directory.
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Save an RGBA framebuffer as a PNG in `dir`, named after the time
+/// it's taken.
+///
+/// Returns the path of the file.
+pub fn save(dir: &Path, frame: &[u8], width: u32, height: u32) -> image::ImageResult<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let mut path = dir.join(format!("screenshot-{}.png", millis));
+    let mut count = 1;
+    while path.exists() {
+        path = dir.join(format!("screenshot-{}-{}.png", millis, count));
+        count += 1;
+    }
+
+    // The framebuffer's alpha is left over from blending.
+    let mut pixels = frame.to_vec();
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8)?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_screenshots() {
+        let root = std::env::temp_dir().join(format!("gers-screenshots-{}", std::process::id()));
+        let dir = root.join(SCREENSHOT_DIR);
+        let frame = [255, 0, 0, 0, 0, 0, 255, 128];
+
+        let first = save(&dir, &frame, 2, 1).unwrap();
+        let second = save(&dir, &frame, 2, 1).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.extension().unwrap(), "png");
+
+        let image = image::open(&first).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.into_raw(), [255, 0, 0, 255, 0, 0, 255, 255]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
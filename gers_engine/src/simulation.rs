@@ -0,0 +1,127 @@
+//! Pausing and stepping the simulation while debugging plugins.
+//!
+//! Pausing freezes the world, so its state can be inspected: neither
+//! fixed updates nor plugin updates run, and game time stands still, so
+//! timers don't count down. Input, windows and the console keep working.
+//! While paused, the simulation can be stepped a frame at a time, which
+//! runs the updates and a single fixed update, with the delta time of
+//! one fixed update.
+//!
+//! Controlled with F7 and F10, or from the embedding game through
+//! `Context::simulation`. Plugins are told with a `SimulationStateEvent`,
+//! which is part of the frame's event stream, so a replay pauses and
+//! steps on the same frames.
+use crate::replay::FrameEvent;
+use gers_events::{SimulationState, SimulationStateEvent};
+
+#[derive(Default)]
+pub struct SimulationControl {
+    paused: bool,
+    /// A step was asked for, and is taken by the next frame.
+    step_requested: bool,
+    events: Vec<FrameEvent>,
+}
+
+impl SimulationControl {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.paused = true;
+            self.push(SimulationState::Paused);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.paused = false;
+            self.step_requested = false;
+            self.push(SimulationState::Running);
+        }
+    }
+
+    /// Pause the simulation when it's running, or resume it.
+    pub fn toggle(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Run the next frame's updates and a single fixed update, when the
+    /// simulation is paused.
+    ///
+    /// Returns `false` when it's running.
+    pub fn step(&mut self) -> bool {
+        if self.paused {
+            self.step_requested = true;
+        }
+        self.paused
+    }
+
+    /// The frame that's starting is stepped, which is told to plugins.
+    pub(crate) fn take_step(&mut self) -> bool {
+        let step = std::mem::take(&mut self.step_requested);
+        if step {
+            self.push(SimulationState::Stepping);
+        }
+        step
+    }
+
+    /// Take the events since the last call.
+    pub(crate) fn take_events(&mut self) -> Vec<FrameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn push(&mut self, state: SimulationState) {
+        self.events
+            .push(FrameEvent::SimulationState(SimulationStateEvent {
+                state: state as u32,
+            }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(control: &mut SimulationControl) -> Vec<SimulationState> {
+        control
+            .take_events()
+            .into_iter()
+            .map(|event| match event {
+                FrameEvent::SimulationState(event) => SimulationState::from(event.state),
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pause_step_resume() {
+        let mut control = SimulationControl::default();
+        // Steps only apply while paused.
+        assert!(!control.step());
+        assert!(!control.take_step());
+
+        control.toggle();
+        control.pause();
+        assert!(control.is_paused());
+        assert_eq!(states(&mut control), [SimulationState::Paused]);
+
+        // Steps asked for in the same frame are one step.
+        assert!(control.step());
+        assert!(control.step());
+        assert!(control.take_step());
+        assert!(!control.take_step());
+        assert_eq!(states(&mut control), [SimulationState::Stepping]);
+
+        // Resuming drops the step that wasn't taken.
+        control.step();
+        control.toggle();
+        assert!(!control.take_step());
+        assert_eq!(states(&mut control), [SimulationState::Running]);
+    }
+}
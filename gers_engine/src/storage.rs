@@ -0,0 +1,159 @@
+//! Persistent storage for plugins.
+//!
+//! Each plugin has its own data directory, `data/<plugin>/`, which
+//! the host creates when the plugin is loaded, and removes once the
+//! plugin is uninstalled. Plugins store key-value pairs as files in it,
+//! and read and write files at relative paths within it, but can't
+//! reach outside of it, so they can't clobber each other's files.
+//! Plugins need the `storage` permission.
+//!
+//! Instances of a plugin each have their own directory, in the
+//! plugin's.
+use gers_plugins::split_instance;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Directory, relative to the working directory, holding plugin data.
+pub const DATA_DIR: &str = "data";
+
+/// Data directory of the plugin with the given name, or `None` when
+/// the name can't be a directory name.
+///
+/// Instances of a plugin have theirs in the plugin's directory, as
+/// `data/<plugin>/<instance>/`, so they're removed with it.
+pub fn plugin_data_dir(data_root: &Path, plugin_name: &str) -> Option<PathBuf> {
+    plugin_path(data_root, plugin_name, "")
+}
+
+/// File of the plugin in `root`, named after it with the suffix, like
+/// `<plugin>.log`, or `<plugin>/<instance>.log` for an instance.
+pub fn plugin_path(root: &Path, plugin_name: &str, suffix: &str) -> Option<PathBuf> {
+    match split_instance(plugin_name) {
+        (plugin, Some(instance)) => {
+            key_path(&key_path(root, plugin)?, &format!("{}{}", instance, suffix))
+        }
+        (plugin, None) => key_path(root, &format!("{}{}", plugin, suffix)),
+    }
+}
+
+/// Names are limited to ASCII letters, digits, `-`, `_` and `.`, and
+/// can't start with a `.`, so they can't escape the directory.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// File storing the value of a key.
+pub fn key_path(data_dir: &Path, key: &str) -> Option<PathBuf> {
+    if is_valid_name(key) {
+        Some(data_dir.join(key))
+    } else {
+        None
+    }
+}
+
+/// File at a relative path in the data directory.
+///
+/// Paths are separated by `/`, and each part is limited like keys.
+pub fn resolve(data_dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = data_dir.to_path_buf();
+    for name in path.split('/') {
+        if !is_valid_name(name) {
+            return None;
+        }
+        resolved.push(name);
+    }
+
+    Some(resolved)
+}
+
+/// Remove the data directory of an uninstalled plugin.
+///
+/// Returns whether there was a directory to remove.
+pub fn remove_data_dir(data_root: &Path, plugin_name: &str) -> io::Result<bool> {
+    match plugin_data_dir(data_root, plugin_name) {
+        Some(data_dir) if data_dir.is_dir() => fs::remove_dir_all(data_dir).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_path() {
+        let data_dir = Path::new("data").join("plugin");
+        assert_eq!(key_path(&data_dir, "score"), Some(data_dir.join("score")));
+
+        for key in [
+            "",
+            "..",
+            "../score",
+            "a/b",
+            r"a\b",
+            "/etc/passwd",
+            "score\0",
+        ] {
+            assert_eq!(key_path(&data_dir, key), None, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let data_dir = Path::new("data").join("plugin");
+        assert_eq!(
+            resolve(&data_dir, "saves/slot-1.bin"),
+            Some(data_dir.join("saves").join("slot-1.bin"))
+        );
+
+        for path in [
+            "",
+            "/saves",
+            "saves/",
+            "saves//a",
+            "saves/../../other",
+            ".hidden",
+        ] {
+            assert_eq!(resolve(&data_dir, path), None, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_plugin_data_dir() {
+        let root = Path::new("data");
+        assert_eq!(plugin_data_dir(root, "spawner"), Some(root.join("spawner")));
+        assert_eq!(
+            plugin_data_dir(root, "spawner#wolf"),
+            Some(root.join("spawner").join("wolf"))
+        );
+        assert_eq!(
+            plugin_path(root, "spawner#wolf", ".log"),
+            Some(root.join("spawner").join("wolf.log"))
+        );
+        assert_eq!(plugin_data_dir(root, "spawner#.."), None);
+        assert_eq!(plugin_data_dir(root, "#wolf"), None);
+    }
+
+    #[test]
+    fn test_remove_data_dir() {
+        let root = std::env::temp_dir().join(format!("gers-data-{}", std::process::id()));
+        fs::create_dir_all(root.join("plugin").join("saves")).unwrap();
+        fs::write(root.join("plugin").join("saves").join("a"), b"1").unwrap();
+
+        assert!(remove_data_dir(&root, "plugin").unwrap());
+        assert!(!root.join("plugin").exists());
+        assert!(!remove_data_dir(&root, "plugin").unwrap());
+
+        // Names that aren't directory names never remove anything.
+        assert!(!remove_data_dir(&root, "..").unwrap());
+        assert!(root.exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,447 @@
+//! Background tasks for plugins.
+//!
+//! Plugins can't use threads, so heavy operations the host implements,
+//! like hashing files, are spawned as tasks and run on a pool of worker
+//! threads instead of stalling the frame. Once a task is done, its
+//! result is delivered to the plugin that spawned it as a
+//! `TaskCompletedEvent`. The result can be read while the event is
+//! handled, and is dropped at the end of the frame.
+use gers_events::{TaskCompletedEvent, TaskKind};
+use gers_plugins::PluginSource;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+pub type TaskId = u32;
+
+/// Default number of tasks a single plugin may have running.
+pub const DEFAULT_TASK_LIMIT: usize = 16;
+
+/// Most threads running tasks.
+const MAX_WORKER_COUNT: usize = 4;
+
+/// Cells of the largest grid a distance field is computed for.
+const MAX_GRID_CELLS: usize = 4096 * 4096;
+
+/// Runs a task on a worker thread, given its data.
+type TaskFn = fn(&PluginSource, &[u8]) -> Result<Vec<u8>, String>;
+
+pub struct Tasks {
+    /// Queue of the worker threads, started on the first task.
+    jobs: Option<Sender<Job>>,
+    completed_sender: Sender<Completed>,
+    completed: Receiver<Completed>,
+    /// Owners of the running tasks.
+    pending: HashMap<TaskId, PathBuf>,
+    /// Results delivered during the current frame.
+    delivered: HashMap<TaskId, Delivered>,
+    next_id: TaskId,
+    task_limit: usize,
+}
+
+struct Job {
+    id: TaskId,
+    run: TaskFn,
+    source: PluginSource,
+    data: Vec<u8>,
+}
+
+struct Completed {
+    id: TaskId,
+    result: Result<Vec<u8>, String>,
+}
+
+struct Delivered {
+    /// Key of the plugin that spawned the task.
+    owner: PathBuf,
+    result: Vec<u8>,
+}
+
+/// Finished task, to be dispatched to the plugin that spawned it.
+pub struct TaskResult {
+    /// Key of the plugin that spawned the task.
+    pub owner: PathBuf,
+    pub event: TaskCompletedEvent,
+    /// Why the task failed.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum TaskError {
+    TaskLimit(usize),
+    UnknownTask(TaskId),
+    UnknownKind(u32),
+    Io(io::Error),
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::TaskLimit(limit) => write!(f, "plugin task limit reached: {}", limit),
+            TaskError::UnknownTask(id) => write!(f, "unknown task id: {}", id),
+            TaskError::UnknownKind(kind) => write!(f, "unknown task kind: {}", kind),
+            TaskError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for TaskError {
+    fn from(err: io::Error) -> Self {
+        TaskError::Io(err)
+    }
+}
+
+impl Default for Tasks {
+    fn default() -> Self {
+        let (completed_sender, completed) = mpsc::channel();
+
+        Self {
+            jobs: None,
+            completed_sender,
+            completed,
+            pending: HashMap::new(),
+            delivered: HashMap::new(),
+            // Zero is reserved to signal failure to guests.
+            next_id: 1,
+            task_limit: DEFAULT_TASK_LIMIT,
+        }
+    }
+}
+
+/// Operation implementing a kind of task.
+fn task_fn(kind: TaskKind) -> Option<TaskFn> {
+    match kind {
+        TaskKind::HashFile => Some(hash_file),
+        TaskKind::DistanceField => Some(distance_field),
+        TaskKind::Unknown => None,
+    }
+}
+
+impl Tasks {
+    /// Spawn a task on behalf of the plugin at `owner`, which reads
+    /// its files from `source`.
+    pub fn spawn(
+        &mut self,
+        owner: &Path,
+        source: &PluginSource,
+        kind: u32,
+        data: Vec<u8>,
+    ) -> Result<TaskId, TaskError> {
+        let run = task_fn(TaskKind::from(kind)).ok_or(TaskError::UnknownKind(kind))?;
+
+        let running = self
+            .pending
+            .values()
+            .filter(|pending| pending.as_path() == owner)
+            .count();
+        if running >= self.task_limit {
+            return Err(TaskError::TaskLimit(self.task_limit));
+        }
+
+        let id = self.next_id;
+        let job = Job {
+            id,
+            run,
+            source: source.clone(),
+            data,
+        };
+        if self.jobs()?.send(job).is_err() {
+            return Err(io::Error::other("task workers stopped").into());
+        }
+
+        self.next_id += 1;
+        self.pending.insert(id, owner.to_path_buf());
+
+        Ok(id)
+    }
+
+    /// Take the tasks that finished since the last poll.
+    ///
+    /// Their results are kept until `end_frame`.
+    pub fn poll(&mut self) -> Vec<TaskResult> {
+        let mut results = vec![];
+
+        while let Ok(Completed { id, result }) = self.completed.try_recv() {
+            let owner = match self.pending.remove(&id) {
+                Some(owner) => owner,
+                None => continue,
+            };
+
+            let (failed, result, error) = match result {
+                Ok(result) => (0, result, None),
+                Err(err) => (1, err.clone().into_bytes(), Some(err)),
+            };
+
+            results.push(TaskResult {
+                owner: owner.clone(),
+                event: TaskCompletedEvent {
+                    task_id: id,
+                    failed,
+                    result_len: result.len() as u32,
+                },
+                error,
+            });
+            self.delivered.insert(id, Delivered { owner, result });
+        }
+
+        results
+    }
+
+    /// Result of a task delivered during the current frame.
+    pub fn result(&self, owner: &Path, id: TaskId) -> Result<&[u8], TaskError> {
+        match self.delivered.get(&id) {
+            Some(delivered) if delivered.owner == owner => Ok(&delivered.result),
+            _ => Err(TaskError::UnknownTask(id)),
+        }
+    }
+
+    /// Forget the tasks the plugin at `owner` spawned. Running tasks
+    /// finish, but their results are dropped.
+    ///
+    /// Returns the number of running tasks.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let count = self.pending.len();
+        self.pending.retain(|_, pending| pending.as_path() != owner);
+        self.delivered
+            .retain(|_, delivered| delivered.owner != owner);
+        count - self.pending.len()
+    }
+
+    /// Drop the results of the tasks delivered during the frame.
+    pub fn end_frame(&mut self) {
+        self.delivered.clear();
+    }
+
+    fn jobs(&mut self) -> io::Result<&Sender<Job>> {
+        if self.jobs.is_none() {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            let receiver = Arc::new(Mutex::new(receiver));
+
+            // Leave a core for the frame.
+            let worker_count = thread::available_parallelism()
+                .map(|count| count.get().saturating_sub(1))
+                .unwrap_or(1)
+                .clamp(1, MAX_WORKER_COUNT);
+            for _ in 0..worker_count {
+                let receiver = receiver.clone();
+                let completed = self.completed_sender.clone();
+                thread::Builder::new()
+                    .name("gers-task".to_string())
+                    .spawn(move || loop {
+                        let job = match receiver.lock().map(|receiver| receiver.recv()) {
+                            Ok(Ok(job)) => job,
+                            // Queue is gone when the host shuts down.
+                            _ => return,
+                        };
+                        let result = (job.run)(&job.source, &job.data);
+                        if completed.send(Completed { id: job.id, result }).is_err() {
+                            return;
+                        }
+                    })?;
+            }
+
+            self.jobs = Some(sender);
+        }
+
+        Ok(self.jobs.as_ref().expect("workers started"))
+    }
+}
+
+/// SHA-256 hash of the file in the plugin at the path in `data`.
+fn hash_file(source: &PluginSource, data: &[u8]) -> Result<Vec<u8>, String> {
+    let path = std::str::from_utf8(data).map_err(|_| "path is not valid UTF-8".to_string())?;
+    // Only files inside the plugin.
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(format!("invalid path: {:?}", path));
+    }
+
+    let bytes = source
+        .read_file(relative)
+        .map_err(|err| format!("failed reading {}: {}", path, err))?;
+
+    Ok(Sha256::digest(&bytes).to_vec())
+}
+
+/// Steps from every cell of a grid to the goal, see `TaskKind::DistanceField`.
+fn distance_field(_: &PluginSource, data: &[u8]) -> Result<Vec<u8>, String> {
+    const HEADER_SIZE: usize = 16;
+    if data.len() < HEADER_SIZE {
+        return Err("grid header is missing".to_string());
+    }
+    let header: Vec<usize> = data[..HEADER_SIZE]
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        .collect();
+    let (width, height, goal_x, goal_y) = (header[0], header[1], header[2], header[3]);
+    let cells = &data[HEADER_SIZE..];
+
+    let cell_count = width
+        .checked_mul(height)
+        .filter(|count| *count <= MAX_GRID_CELLS)
+        .ok_or_else(|| format!("grid of {}x{} is too large", width, height))?;
+    if cells.len() != cell_count {
+        return Err(format!(
+            "grid of {}x{} needs {} cells, found {}",
+            width,
+            height,
+            cell_count,
+            cells.len()
+        ));
+    }
+    if goal_x >= width || goal_y >= height {
+        return Err(format!("goal {},{} is outside the grid", goal_x, goal_y));
+    }
+
+    let mut distances = vec![u32::MAX; cell_count];
+    let mut frontier = VecDeque::new();
+    let goal = goal_y * width + goal_x;
+    if cells[goal] == 0 {
+        distances[goal] = 0;
+        frontier.push_back(goal);
+    }
+    while let Some(index) = frontier.pop_front() {
+        let (x, y) = (index % width, index / width);
+        let neighbours = [
+            (x > 0).then(|| index - 1),
+            (x + 1 < width).then(|| index + 1),
+            (y > 0).then(|| index - width),
+            (y + 1 < height).then(|| index + width),
+        ];
+        for neighbour in neighbours.into_iter().flatten() {
+            if cells[neighbour] == 0 && distances[neighbour] == u32::MAX {
+                distances[neighbour] = distances[index] + 1;
+                frontier.push_back(neighbour);
+            }
+        }
+    }
+
+    Ok(distances
+        .into_iter()
+        .flat_map(|distance| distance.to_le_bytes())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        time::{Duration, Instant},
+    };
+
+    fn wait_result(tasks: &mut Tasks) -> TaskResult {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(result) = tasks.poll().pop() {
+                return result;
+            }
+            assert!(Instant::now() < deadline, "task timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let root = std::env::temp_dir().join(format!("gers-tasks-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("data.txt"), b"abc").unwrap();
+        let source = PluginSource::Directory(root.clone());
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut tasks = Tasks::default();
+
+        let id = tasks
+            .spawn(
+                owner,
+                &source,
+                TaskKind::HashFile as u32,
+                b"data.txt".to_vec(),
+            )
+            .unwrap();
+        assert_ne!(id, 0);
+        let result = wait_result(&mut tasks);
+        assert_eq!(result.owner, owner);
+        assert_eq!(result.event.task_id, id);
+        assert_eq!(result.event.failed, 0);
+        assert_eq!(result.event.result_len, 32);
+        assert_eq!(
+            hex(tasks.result(owner, id).unwrap()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(matches!(
+            tasks.result(other, id),
+            Err(TaskError::UnknownTask(_))
+        ));
+        tasks.end_frame();
+        assert!(tasks.result(owner, id).is_err());
+
+        // Files outside the plugin can't be hashed.
+        let id = tasks
+            .spawn(owner, &source, TaskKind::HashFile as u32, b"../x".to_vec())
+            .unwrap();
+        let result = wait_result(&mut tasks);
+        assert_eq!(result.event.failed, 1);
+        assert_eq!(tasks.result(owner, id).unwrap(), b"invalid path: \"../x\"");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_distance_field() {
+        let mut data = vec![];
+        for value in [3u32, 2, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        // The wall leaves the bottom right cell the long way round,
+        // and the top right cell walled in.
+        data.extend_from_slice(&[0, 1, 0, 0, 0, 0]);
+
+        let distances: Vec<u32> = distance_field(&PluginSource::Directory(PathBuf::new()), &data)
+            .unwrap()
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(distances, [0, u32::MAX, 4, 1, 2, 3]);
+
+        data.pop();
+        assert!(distance_field(&PluginSource::Directory(PathBuf::new()), &data).is_err());
+    }
+
+    #[test]
+    fn test_task_limits() {
+        let source = PluginSource::Directory(PathBuf::from("plugin"));
+        let owner = Path::new("plugin");
+        let mut tasks = Tasks {
+            task_limit: 1,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            tasks.spawn(owner, &source, 99, vec![]),
+            Err(TaskError::UnknownKind(99))
+        ));
+        tasks
+            .spawn(owner, &source, TaskKind::HashFile as u32, vec![])
+            .unwrap();
+        assert!(matches!(
+            tasks.spawn(owner, &source, TaskKind::HashFile as u32, vec![]),
+            Err(TaskError::TaskLimit(1))
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+//! Timers set by plugins.
+//!
+//! Timers count down with the frame's scaled delta time, rather than
+//! the wall clock, so they fire on the same frames when a recording is
+//! replayed. A timer fires at most once per frame, and repeating timers
+//! that fall behind skip the intervals they missed.
+use gers_events::TimerFiredEvent;
+use gers_handles::{Handle, HandleTable};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Handle given to guests to refer to a timer.
+pub type TimerId = Handle;
+
+/// Default number of timers a single plugin may have set.
+pub const DEFAULT_TIMER_LIMIT: usize = 64;
+
+/// Timers, with the key of the plugin that set each.
+pub struct Timers {
+    timers: HandleTable<Timer, PathBuf>,
+    /// Sequence number of the next timer set.
+    next_seq: u64,
+    timer_limit: usize,
+}
+
+struct Timer {
+    /// Timers due on the same frame fire in the order they were set,
+    /// rather than the order of their slots.
+    seq: u64,
+    interval: Duration,
+    remaining: Duration,
+    repeat: bool,
+}
+
+#[derive(Debug)]
+pub enum TimerError {
+    TimerLimit(usize),
+}
+
+impl fmt::Display for TimerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimerError::TimerLimit(limit) => write!(f, "plugin timer limit reached: {}", limit),
+        }
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self {
+            timers: HandleTable::new(),
+            next_seq: 0,
+            timer_limit: DEFAULT_TIMER_LIMIT,
+        }
+    }
+}
+
+impl Timers {
+    /// Set a timer on behalf of the plugin at `owner`.
+    pub fn set(
+        &mut self,
+        owner: &Path,
+        interval: Duration,
+        repeat: bool,
+    ) -> Result<TimerId, TimerError> {
+        if self.timers.count_owned(owner) >= self.timer_limit {
+            return Err(TimerError::TimerLimit(self.timer_limit));
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        Ok(self.timers.insert(
+            owner.to_path_buf(),
+            Timer {
+                seq,
+                interval,
+                remaining: interval,
+                repeat,
+            },
+        ))
+    }
+
+    /// Cancel a timer. Plugins can only cancel the timers they set.
+    ///
+    /// Returns `true` if the timer existed.
+    pub fn cancel(&mut self, owner: &Path, id: TimerId) -> bool {
+        self.timers.remove_owned(owner, id).is_ok()
+    }
+
+    /// Cancel every timer the plugin at `owner` set.
+    ///
+    /// Returns the number of timers cancelled.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        self.timers.release(owner).len()
+    }
+
+    /// Count down all timers by the frame's delta time.
+    ///
+    /// Returns the timers that fired, with the plugins to send them to.
+    /// Timers that don't repeat are removed once fired.
+    pub fn advance(&mut self, delta_time: Duration) -> Vec<(PathBuf, TimerFiredEvent)> {
+        let mut fired = vec![];
+
+        self.timers.retain(|id, owner, timer| {
+            if timer.remaining > delta_time {
+                timer.remaining -= delta_time;
+                return true;
+            }
+
+            let event = TimerFiredEvent {
+                timer_id: id.to_raw(),
+            };
+            fired.push((timer.seq, owner.clone(), event));
+            if !timer.repeat {
+                return false;
+            }
+
+            let overshoot = (delta_time - timer.remaining).as_nanos();
+            timer.remaining = match timer.interval.as_nanos() {
+                0 => Duration::ZERO,
+                interval => Duration::from_nanos((interval - overshoot % interval) as u64),
+            };
+            true
+        });
+
+        fired.sort_unstable_by_key(|(seq, _, _)| *seq);
+        fired
+            .into_iter()
+            .map(|(_, owner, event)| (owner, event))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fired_ids(timers: &mut Timers, millis: u64) -> Vec<TimerId> {
+        timers
+            .advance(Duration::from_millis(millis))
+            .into_iter()
+            .map(|(_, event)| TimerId::from_raw(event.timer_id))
+            .collect()
+    }
+
+    #[test]
+    fn test_one_shot_and_repeating() {
+        let owner = Path::new("plugin");
+        let mut timers = Timers::default();
+        let once = timers.set(owner, Duration::from_millis(30), false).unwrap();
+        let repeat = timers.set(owner, Duration::from_millis(20), true).unwrap();
+
+        assert!(fired_ids(&mut timers, 16).is_empty());
+        assert_eq!(fired_ids(&mut timers, 16), [once, repeat]);
+        // Fired 12ms late, so the next interval is 8ms out.
+        assert!(fired_ids(&mut timers, 7).is_empty());
+        assert_eq!(fired_ids(&mut timers, 1), [repeat]);
+
+        // Missed intervals are skipped, rather than fired in a burst.
+        assert_eq!(fired_ids(&mut timers, 100), [repeat]);
+        assert!(fired_ids(&mut timers, 19).is_empty());
+        assert_eq!(fired_ids(&mut timers, 1), [repeat]);
+
+        // The one-shot timer is gone.
+        assert!(!timers.cancel(owner, once));
+        assert!(timers.cancel(owner, repeat));
+        assert!(fired_ids(&mut timers, 100).is_empty());
+    }
+
+    #[test]
+    fn test_timers_are_owned() {
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut timers = Timers::default();
+
+        for _ in 0..DEFAULT_TIMER_LIMIT {
+            timers.set(owner, Duration::ZERO, true).unwrap();
+        }
+        assert!(matches!(
+            timers.set(owner, Duration::ZERO, true),
+            Err(TimerError::TimerLimit(DEFAULT_TIMER_LIMIT))
+        ));
+        // The limit is per plugin.
+        let id = timers.set(other, Duration::ZERO, false).unwrap();
+
+        assert!(!timers.cancel(owner, id));
+        let fired = timers.advance(Duration::from_millis(16));
+        assert_eq!(fired.len(), DEFAULT_TIMER_LIMIT + 1);
+        assert_eq!(fired.last().unwrap().0, other);
+    }
+
+    #[test]
+    fn test_fire_in_order_set() {
+        let owner = Path::new("plugin");
+        let mut timers = Timers::default();
+        let first = timers.set(owner, Duration::ZERO, false).unwrap();
+        let second = timers.set(owner, Duration::ZERO, false).unwrap();
+        assert!(timers.cancel(owner, first));
+
+        // The third timer reuses the first one's slot, but fires last.
+        let third = timers.set(owner, Duration::ZERO, false).unwrap();
+        assert_eq!(third.index(), first.index());
+        assert_eq!(fired_ids(&mut timers, 16), [second, third]);
+        assert!(!timers.cancel(owner, first));
+    }
+}
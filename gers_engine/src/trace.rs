@@ -0,0 +1,60 @@
+//! Tracing of plugin calls, for finding frame hitches.
+//!
+//! Each frame, and every plugin update, render and event call within
+//! it, runs inside a `tracing` span. Plugin spans are tagged with the
+//! plugin's name, and event spans with the event type. Plugins can add
+//! marks of their own with the `trace_event` import.
+//!
+//! Given `--trace`, spans are written to a file in the Chrome trace
+//! format, which can be opened in chrome://tracing or Perfetto.
+//! Without it no subscriber is installed, and spans cost next to nothing.
+use gers_events::EventType;
+use std::{fs::File, io, path::Path};
+use tracing::span::EnteredSpan;
+use tracing_chrome::{ChromeLayerBuilder, EventOrSpan, FlushGuard};
+use tracing_subscriber::prelude::*;
+
+/// Write spans to a trace file, which is completed when the
+/// returned guard is dropped.
+pub fn write_chrome_trace(path: &Path) -> io::Result<FlushGuard> {
+    // The layer panics when it can't create the file.
+    File::create(path)?;
+
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .file(path.display().to_string())
+        .include_args(true)
+        .name_fn(Box::new(|event_or_span| match event_or_span {
+            // Plugin marks are the only events.
+            EventOrSpan::Event(_) => "mark".to_string(),
+            EventOrSpan::Span(span) => span.name().to_string(),
+        }))
+        .build();
+    tracing_subscriber::registry().with(layer).init();
+
+    Ok(guard)
+}
+
+pub fn frame_span(frame: u64) -> EnteredSpan {
+    tracing::info_span!("frame", frame).entered()
+}
+
+pub fn update_span(plugin: &str) -> EnteredSpan {
+    tracing::info_span!("update", plugin).entered()
+}
+
+pub fn render_span(plugin: &str) -> EnteredSpan {
+    tracing::info_span!("render", plugin).entered()
+}
+
+pub fn event_span(plugin: &str, event_type: EventType) -> EnteredSpan {
+    tracing::info_span!("event", plugin, event = ?event_type).entered()
+}
+
+pub fn batch_span(plugin: &str) -> EnteredSpan {
+    tracing::info_span!("event_batch", plugin).entered()
+}
+
+/// Mark added by a plugin.
+pub fn mark(plugin: &str, name: &str) {
+    tracing::info!(plugin, name, "mark");
+}
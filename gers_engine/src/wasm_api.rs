@@ -0,0 +1,31 @@
+//! Host imports, generated from the interface in `gers_interface`.
+//!
+//! Imports are added in `gers.interface`, and implemented in
+//! `wasm_impl` with a matching signature, which is checked when the
+//! app is compiled.
+use gers_events::Capabilities;
+
+include!(concat!(env!("OUT_DIR"), "/host_imports.rs"));
+
+/// Subsystems the host provides to plugins, passed to them when they're
+/// initialised. Those behind a permission are left out for plugins that
+/// weren't granted it.
+pub fn capabilities() -> Capabilities {
+    let mut capabilities = Capabilities::DRAW
+        | Capabilities::STORAGE
+        | Capabilities::NETWORK
+        | Capabilities::HTTP
+        | Capabilities::WINDOW
+        | Capabilities::TASKS;
+    if cfg!(feature = "audio") {
+        capabilities.insert(Capabilities::AUDIO);
+    }
+    if cfg!(feature = "clipboard") {
+        capabilities.insert(Capabilities::CLIPBOARD);
+    }
+    if cfg!(feature = "gamepad") {
+        capabilities.insert(Capabilities::GAMEPAD);
+    }
+
+    capabilities
+}
@@ -0,0 +1,2528 @@
+use crate::{
+    assets,
+    audio::{SoundId, VoiceId},
+    clipboard::ClipboardError,
+    clock,
+    console::ConsoleError,
+    custom_events::CustomEventError,
+    env::GersEnv,
+    http::{HttpError, RequestId},
+    i18n, input, log_fmt,
+    net::NetError,
+    render::{Color, DrawCommand, TextureId},
+    replay::FrameEvent,
+    storage,
+    tasks::TaskError,
+    timer::TimerId,
+    trace,
+    watch::WatchError,
+    window::{WindowError, MAIN_WINDOW},
+};
+use gers_events::{plugin_id, DamageEvent, EventType, HostError, HttpMethod};
+use gers_plugins::{strings, SettingValue};
+use gers_world::WorldError;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use wasmer::{Array, WasmPtr};
+
+pub fn log_info(env: &GersEnv, str_ptr: WasmPtr<u8, Array>, str_len: u32) {
+    if let Some(string) = read_string(env, str_ptr, str_len) {
+        slog::info!(env.logger, "{}", string);
+        write_plugin_log(env, "INFO", &string);
+    }
+}
+
+/// Log a message formatted from a template and packed arguments, see
+/// `log_fmt`.
+///
+/// Returns zero, or a negative `HostError` code.
+pub fn log_fmt(
+    env: &GersEnv,
+    template_ptr: WasmPtr<u8, Array>,
+    template_len: u32,
+    args_ptr: WasmPtr<u8, Array>,
+    args_len: u32,
+) -> i32 {
+    let args = env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, args_ptr, args_len));
+    let (template, args) = match (read_string(env, template_ptr, template_len), args) {
+        (Some(template), Some(args)) => (template, args),
+        _ => return HostError::InvalidArgument.code(),
+    };
+
+    let read_str = |ptr, len| read_string(env, WasmPtr::new(ptr), len);
+    match log_fmt::format(&template, &args, read_str) {
+        Ok(message) => {
+            slog::info!(env.logger, "{}", message);
+            write_plugin_log(env, "INFO", &message);
+            0
+        }
+        Err(err) => {
+            slog::warn!(
+                env.logger,
+                "plugin {} logged malformed message: {}",
+                env.plugin.name,
+                err
+            );
+            HostError::InvalidArgument.code()
+        }
+    }
+}
+
+/// Also write a message of the plugin into its log file.
+fn write_plugin_log(env: &GersEnv, level: &str, message: &str) {
+    if let Ok(mut logs) = env.plugin_logs.lock() {
+        if let Err(err) = logs.write(&env.plugin.name, level, message) {
+            slog::warn!(
+                env.logger,
+                "failed writing log of plugin {}: {}",
+                env.plugin.name,
+                err
+            );
+        }
+    }
+}
+
+/// Write the messages the plugin logged so far to its log file.
+///
+/// Returns zero, or a negative `HostError` code.
+pub fn log_flush(env: &GersEnv) -> i32 {
+    match env.plugin_logs.lock() {
+        Ok(mut logs) => match logs.flush(&env.plugin.name) {
+            Ok(()) => 0,
+            Err(err) => {
+                slog::warn!(
+                    env.logger,
+                    "failed flushing log of plugin {}: {}",
+                    env.plugin.name,
+                    err
+                );
+                HostError::Io.code()
+            }
+        },
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Delta time of the frame multiplied by the time scale, which is
+/// zero while time is frozen.
+pub fn get_delta_time(env: &GersEnv) -> f32 {
+    match env.timing.read() {
+        Ok(ref timing) => timing.delta_time.as_secs_f32(),
+        Err(_) => f32::EPSILON,
+    }
+}
+
+pub fn get_unscaled_delta_time(env: &GersEnv) -> f32 {
+    match env.timing.read() {
+        Ok(ref timing) => timing.unscaled_delta_time.as_secs_f32(),
+        Err(_) => f32::EPSILON,
+    }
+}
+
+/// Set the multiplier for the delta time of the next frames.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn set_time_scale(env: &GersEnv, time_scale: f32) -> i32 {
+    if !clock::is_valid_time_scale(time_scale) {
+        return HostError::InvalidArgument.code();
+    }
+
+    match env.timing.write() {
+        Ok(mut timing) => {
+            timing.time_scale = time_scale;
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+pub fn get_seed(env: &GersEnv) -> u64 {
+    env.seed
+}
+
+/// Copy a string into a guest buffer, truncating it to the buffer's capacity.
+///
+/// Returns the full length of the string, so the guest can retry with
+/// a larger buffer.
+fn write_string(env: &GersEnv, string: &str, out_ptr: WasmPtr<u8, Array>, out_cap: u32) -> u32 {
+    let len = string.len().min(out_cap as usize);
+    if let Some(mem) = env.memory.get_ref() {
+        strings::write_bytes(mem, out_ptr, &string.as_bytes()[..len]);
+    }
+    string.len() as u32
+}
+
+/// Copy bytes into a guest buffer, truncating them to the buffer's
+/// capacity.
+///
+/// Returns the full length of the bytes, so the guest can retry with a
+/// larger buffer, or `HostError::InvalidArgument` when the buffer isn't
+/// in the guest's memory.
+fn copy_to_guest(env: &GersEnv, bytes: &[u8], buf_ptr: WasmPtr<u8, Array>, buf_len: u32) -> i32 {
+    let copy_len = bytes.len().min(buf_len as usize);
+    let written = env
+        .memory
+        .get_ref()
+        .map(|mem| strings::write_bytes(mem, buf_ptr, &bytes[..copy_len]))
+        .unwrap_or(false);
+    if !written {
+        return HostError::InvalidArgument.code();
+    }
+
+    bytes.len().min(i32::MAX as usize) as i32
+}
+
+pub fn plugin_name(env: &GersEnv, out_ptr: WasmPtr<u8, Array>, out_cap: u32) -> u32 {
+    write_string(env, &env.plugin.name, out_ptr, out_cap)
+}
+
+pub fn plugin_version(env: &GersEnv, out_ptr: WasmPtr<u8, Array>, out_cap: u32) -> u32 {
+    write_string(env, &env.plugin.version, out_ptr, out_cap)
+}
+
+/// Copy a string out of guest memory.
+fn read_string(env: &GersEnv, str_ptr: WasmPtr<u8, Array>, str_len: u32) -> Option<String> {
+    env.memory
+        .get_ref()
+        .and_then(|mem| strings::read_str(mem, str_ptr, str_len))
+}
+
+/// Add a mark to the trace, named by the plugin.
+pub fn trace_event(env: &GersEnv, name_ptr: WasmPtr<u8, Array>, name_len: u32) {
+    if let Some(name) = read_string(env, name_ptr, name_len) {
+        trace::mark(&env.plugin.name, &name);
+    }
+}
+
+pub fn report_panic(env: &GersEnv, str_ptr: WasmPtr<u8, Array>, str_len: u32) {
+    let maybe = read_string(env, str_ptr, str_len);
+
+    if let Some(ref string) = maybe {
+        write_plugin_log(env, "PANIC", string);
+    }
+    if let (Some(string), Ok(mut lock)) = (maybe, env.panic_message.lock()) {
+        *lock = Some(string);
+    }
+}
+
+fn push_draw(env: &GersEnv, command: DrawCommand) {
+    if let Ok(mut queue) = env.draw_queue.lock() {
+        queue.push(command);
+    }
+}
+
+pub fn draw_rect(env: &GersEnv, x: f32, y: f32, width: f32, height: f32, color: u32) {
+    push_draw(
+        env,
+        DrawCommand::Rect {
+            x,
+            y,
+            width,
+            height,
+            color: Color::from_u32(color),
+        },
+    );
+}
+
+pub fn draw_line(env: &GersEnv, x0: f32, y0: f32, x1: f32, y1: f32, color: u32) {
+    push_draw(
+        env,
+        DrawCommand::Line {
+            x0,
+            y0,
+            x1,
+            y1,
+            color: Color::from_u32(color),
+        },
+    );
+}
+
+/// Queue a sprite. Textures the plugin didn't load draw the missing
+/// texture placeholder.
+pub fn draw_sprite(env: &GersEnv, texture: u64, x: f32, y: f32) {
+    let texture = TextureId::from_raw(texture);
+    let texture_id = match env.textures.read() {
+        Ok(textures) if textures.is_owned(&env.plugin.key, texture) => texture,
+        _ => TextureId::NULL,
+    };
+    push_draw(env, DrawCommand::Sprite { texture_id, x, y });
+}
+
+/// Read an asset out of the plugin, logging failures.
+fn read_asset(env: &GersEnv, path_ptr: WasmPtr<u8, Array>, path_len: u32) -> Option<Vec<u8>> {
+    let relative = read_string(env, path_ptr, path_len)?;
+    let source = env.plugin.source.as_ref()?;
+
+    match assets::read_asset(source, &relative) {
+        Ok(bytes) => Some(bytes),
+        Err(err) => {
+            slog::warn!(env.logger, "failed to read asset {}: {}", relative, err);
+            None
+        }
+    }
+}
+
+/// Load a texture from the plugin's asset directory.
+///
+/// Returns the texture handle, or zero on failure.
+pub fn load_texture(env: &GersEnv, path_ptr: WasmPtr<u8, Array>, path_len: u32) -> u64 {
+    let bytes = match read_asset(env, path_ptr, path_len) {
+        Some(bytes) => bytes,
+        None => return 0,
+    };
+
+    match assets::load_texture(&bytes) {
+        Ok(texture) => match env.textures.write() {
+            Ok(mut textures) => textures.insert(&env.plugin.key, texture).to_raw(),
+            Err(_) => 0,
+        },
+        Err(err) => {
+            slog::warn!(env.logger, "failed to decode texture: {}", err);
+            0
+        }
+    }
+}
+
+/// Returns 1 if the texture was unloaded, 0 if the plugin didn't load it.
+pub fn unload_texture(env: &GersEnv, texture: u64) -> u32 {
+    match env.textures.write() {
+        Ok(mut textures) => textures.remove(&env.plugin.key, TextureId::from_raw(texture)) as u32,
+        Err(_) => 0,
+    }
+}
+
+/// Load a sound file from the plugin's asset directory.
+///
+/// Returns the sound handle, or zero on failure.
+pub fn audio_load(env: &GersEnv, path_ptr: WasmPtr<u8, Array>, path_len: u32) -> u64 {
+    let bytes = match read_asset(env, path_ptr, path_len) {
+        Some(bytes) => bytes,
+        None => return 0,
+    };
+
+    match env.audio.lock() {
+        Ok(mut audio) => audio.insert(&env.plugin.key, bytes).to_raw(),
+        Err(_) => 0,
+    }
+}
+
+/// Play a sound the plugin loaded.
+///
+/// Returns the voice handle, or zero when the sound couldn't be played.
+pub fn audio_play(env: &GersEnv, sound: u64, volume: f32) -> u64 {
+    let result = match env.audio.lock() {
+        Ok(mut audio) => audio.play(&env.plugin.key, SoundId::from_raw(sound), volume),
+        Err(_) => return 0,
+    };
+
+    match result {
+        Ok(voice) => voice.to_raw(),
+        Err(err) => {
+            slog::warn!(env.logger, "failed to play sound: {}", err);
+            0
+        }
+    }
+}
+
+/// Returns 1 if the voice was stopped, 0 if it wasn't playing.
+pub fn audio_stop(env: &GersEnv, voice: u64) -> u32 {
+    match env.audio.lock() {
+        Ok(mut audio) => audio.stop(&env.plugin.key, VoiceId::from_raw(voice)) as u32,
+        Err(_) => 0,
+    }
+}
+
+/// Register an action, with an optional default key binding.
+///
+/// Returns the action id, or zero on failure.
+pub fn register_action(
+    env: &GersEnv,
+    name_ptr: WasmPtr<u8, Array>,
+    name_len: u32,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+) -> u32 {
+    let name = match read_string(env, name_ptr, name_len) {
+        Some(name) if !name.is_empty() => name,
+        _ => return 0,
+    };
+
+    let mut default_keys = vec![];
+    if key_len > 0 {
+        match read_string(env, key_ptr, key_len).and_then(|key| input::parse_key(&key)) {
+            Some(key) => default_keys.push(key),
+            None => {
+                slog::warn!(env.logger, "unknown key for action {}", name);
+                return 0;
+            }
+        }
+    }
+
+    match env.input.lock() {
+        Ok(mut action_map) => action_map.register(&env.plugin.key, &name, &default_keys),
+        Err(_) => 0,
+    }
+}
+
+/// File in the plugin's data directory, at a path read from guest
+/// memory and checked by `resolve`.
+fn data_file(
+    env: &GersEnv,
+    path_ptr: WasmPtr<u8, Array>,
+    path_len: u32,
+    resolve: fn(&Path, &str) -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    let data_dir = env.plugin.data_dir.as_ref()?;
+    let path = read_string(env, path_ptr, path_len)?;
+
+    resolve(data_dir, &path)
+}
+
+/// Write a file in the plugin's data directory, creating the
+/// directories it's in.
+///
+/// Returns zero on success, or a negative `HostError` code.
+fn write_data_file(
+    env: &GersEnv,
+    path: Option<PathBuf>,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let path = match path {
+        Some(path) => path,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let data = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len))
+    {
+        Some(data) => data,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(&path, data)),
+        None => fs::write(&path, data),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to save {:?}: {}", path, err);
+            HostError::Io.code()
+        }
+    }
+}
+
+/// Read a file in the plugin's data directory into the guest buffer.
+///
+/// Returns the full size of the file, which may be larger than the
+/// buffer, in which case only the part that fits is copied. Returns a
+/// negative `HostError` code on failure.
+fn read_data_file(
+    env: &GersEnv,
+    path: Option<PathBuf>,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let path = match path {
+        Some(path) => path,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return HostError::NotFound.code()
+        }
+        Err(err) => {
+            slog::warn!(env.logger, "failed to load {:?}: {}", path, err);
+            return HostError::Io.code();
+        }
+    };
+
+    copy_to_guest(env, &data, buf_ptr, buf_len)
+}
+
+/// Store a value under a key in the plugin's data directory.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn storage_save(
+    env: &GersEnv,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let path = data_file(env, key_ptr, key_len, storage::key_path);
+    write_data_file(env, path, data_ptr, data_len)
+}
+
+/// Load the value stored under a key into the guest buffer.
+///
+/// Returns the full size of the value, or a negative `HostError` code.
+pub fn storage_load(
+    env: &GersEnv,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let path = data_file(env, key_ptr, key_len, storage::key_path);
+    read_data_file(env, path, buf_ptr, buf_len)
+}
+
+/// Copy the path of the plugin's data directory into the guest buffer.
+///
+/// Returns the full size of the path, or a negative `HostError` code.
+pub fn fs_data_dir(env: &GersEnv, out_ptr: WasmPtr<u8, Array>, out_cap: u32) -> i32 {
+    let data_dir = match env.plugin.data_dir.as_ref().and_then(|dir| dir.to_str()) {
+        Some(data_dir) => data_dir,
+        None => return HostError::NotFound.code(),
+    };
+    let len = write_string(env, data_dir, out_ptr, out_cap);
+
+    len.min(i32::MAX as u32) as i32
+}
+
+/// Read a file at a relative path in the plugin's data directory into
+/// the guest buffer.
+///
+/// Returns the full size of the file, or a negative `HostError` code.
+pub fn fs_read(
+    env: &GersEnv,
+    path_ptr: WasmPtr<u8, Array>,
+    path_len: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let path = data_file(env, path_ptr, path_len, storage::resolve);
+    read_data_file(env, path, buf_ptr, buf_len)
+}
+
+/// Write a file at a relative path in the plugin's data directory,
+/// replacing it if it exists.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn fs_write(
+    env: &GersEnv,
+    path_ptr: WasmPtr<u8, Array>,
+    path_len: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let path = data_file(env, path_ptr, path_len, storage::resolve);
+    write_data_file(env, path, data_ptr, data_len)
+}
+
+/// Remove a file at a relative path in the plugin's data directory.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn fs_remove(env: &GersEnv, path_ptr: WasmPtr<u8, Array>, path_len: u32) -> i32 {
+    let path = match data_file(env, path_ptr, path_len, storage::resolve) {
+        Some(path) => path,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match fs::remove_file(&path) {
+        Ok(_) => 0,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => HostError::NotFound.code(),
+        Err(err) => {
+            slog::warn!(env.logger, "failed to remove {:?}: {}", path, err);
+            HostError::Io.code()
+        }
+    }
+}
+
+/// Copy the plugin's string of a key, in the active locale, into the
+/// guest buffer.
+///
+/// Returns the full size of the string, or a negative `HostError` code.
+pub fn i18n_get(
+    env: &GersEnv,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let key = match read_string(env, key_ptr, key_len) {
+        Some(key) => key,
+        None => return HostError::InvalidArgument.code(),
+    };
+    let i18n = match env.i18n.read() {
+        Ok(i18n) => i18n,
+        Err(_) => return HostError::Io.code(),
+    };
+    let string = match i18n.get(&env.plugin.key, &key) {
+        Some(string) => string,
+        None => return HostError::NotFound.code(),
+    };
+
+    copy_to_guest(env, string.as_bytes(), buf_ptr, buf_len)
+}
+
+pub fn i18n_locale(env: &GersEnv, out_ptr: WasmPtr<u8, Array>, out_cap: u32) -> u32 {
+    let locale = match env.i18n.read() {
+        Ok(i18n) => i18n.locale().to_string(),
+        Err(_) => i18n::DEFAULT_LOCALE.to_string(),
+    };
+    write_string(env, &locale, out_ptr, out_cap)
+}
+
+/// Value of one of the plugin's settings, or `None` when the plugin
+/// didn't declare it.
+fn setting_value(env: &GersEnv, key_ptr: WasmPtr<u8, Array>, key_len: u32) -> Option<SettingValue> {
+    let key = read_string(env, key_ptr, key_len)?;
+    let decl = env.plugin.settings.get(&key)?;
+    let settings = env.settings.read().ok()?;
+
+    Some(settings.get(&env.plugin.name, &key, decl))
+}
+
+/// Returns the `SettingKind` of a declared setting, or a negative
+/// `HostError` code.
+pub fn settings_kind(env: &GersEnv, key_ptr: WasmPtr<u8, Array>, key_len: u32) -> i32 {
+    let decl = read_string(env, key_ptr, key_len).and_then(|key| env.plugin.settings.get(&key));
+    match decl {
+        Some(decl) => decl.kind() as i32,
+        None => HostError::NotFound.code(),
+    }
+}
+
+/// Returns zero when the setting isn't an integer.
+pub fn settings_get_int(env: &GersEnv, key_ptr: WasmPtr<u8, Array>, key_len: u32) -> i64 {
+    match setting_value(env, key_ptr, key_len) {
+        Some(SettingValue::Int(value)) => value,
+        _ => 0,
+    }
+}
+
+/// Returns zero when the setting isn't a float.
+pub fn settings_get_float(env: &GersEnv, key_ptr: WasmPtr<u8, Array>, key_len: u32) -> f64 {
+    match setting_value(env, key_ptr, key_len) {
+        Some(SettingValue::Float(value)) => value,
+        _ => 0.0,
+    }
+}
+
+/// Returns one or zero, or a negative `HostError` code.
+pub fn settings_get_bool(env: &GersEnv, key_ptr: WasmPtr<u8, Array>, key_len: u32) -> i32 {
+    match setting_value(env, key_ptr, key_len) {
+        Some(SettingValue::Bool(value)) => value as i32,
+        Some(_) => HostError::InvalidArgument.code(),
+        None => HostError::NotFound.code(),
+    }
+}
+
+/// Copy the value of a string setting into the guest buffer.
+///
+/// Returns the full size of the value, or a negative `HostError` code.
+pub fn settings_get_string(
+    env: &GersEnv,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let value = match setting_value(env, key_ptr, key_len) {
+        Some(SettingValue::String(value)) => value,
+        Some(_) => return HostError::InvalidArgument.code(),
+        None => return HostError::NotFound.code(),
+    };
+
+    copy_to_guest(env, value.as_bytes(), buf_ptr, buf_len)
+}
+
+fn task_error_code(err: TaskError) -> i32 {
+    match err {
+        TaskError::TaskLimit(_) => HostError::LimitReached.code(),
+        TaskError::UnknownTask(_) => HostError::NotFound.code(),
+        TaskError::UnknownKind(_) => HostError::InvalidArgument.code(),
+        TaskError::Io(_) => HostError::Io.code(),
+    }
+}
+
+/// Run a host operation, given its `TaskKind` and data, on a
+/// background thread.
+///
+/// Returns the task id, or a negative `HostError` code. The result is
+/// delivered later as a `TaskCompletedEvent`.
+pub fn task_spawn(env: &GersEnv, kind: u32, data_ptr: WasmPtr<u8, Array>, data_len: u32) -> i32 {
+    let data = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len))
+    {
+        Some(data) => data,
+        None => return HostError::InvalidArgument.code(),
+    };
+    let source = match env.plugin.source {
+        Some(ref source) => source,
+        None => return HostError::Io.code(),
+    };
+
+    let result = match env.tasks.lock() {
+        Ok(mut tasks) => tasks.spawn(&env.plugin.key, source, kind, data),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(id) => id.min(i32::MAX as u32) as i32,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to spawn task: {}", err);
+            task_error_code(err)
+        }
+    }
+}
+
+/// Copy the result of a task into the guest buffer, while its
+/// `TaskCompletedEvent` is handled.
+///
+/// Returns the full size of the result, which may be larger than the
+/// buffer, in which case only the part that fits is copied. Returns a
+/// negative `HostError` code on failure.
+pub fn task_result(env: &GersEnv, task: u32, buf_ptr: WasmPtr<u8, Array>, buf_len: u32) -> i32 {
+    let tasks = match env.tasks.lock() {
+        Ok(tasks) => tasks,
+        Err(_) => return HostError::Io.code(),
+    };
+    let result = match tasks.result(&env.plugin.key, task) {
+        Ok(result) => result,
+        Err(err) => return task_error_code(err),
+    };
+
+    copy_to_guest(env, result, buf_ptr, buf_len)
+}
+
+fn world_error_code(err: WorldError) -> i32 {
+    match err {
+        WorldError::NoEntity(_) | WorldError::NoComponent(..) | WorldError::NoQuery(_) => {
+            HostError::NotFound.code()
+        }
+        WorldError::InvalidComponent(_)
+        | WorldError::EntitiesExhausted
+        | WorldError::BufferTooSmall => HostError::InvalidArgument.code(),
+    }
+}
+
+/// Create an entity in the shared world.
+///
+/// Returns the entity id, or zero on failure.
+pub fn world_spawn(env: &GersEnv) -> u32 {
+    match env.world.lock() {
+        Ok(mut world) => world.spawn().unwrap_or_default(),
+        Err(_) => 0,
+    }
+}
+
+/// Returns zero on success, or a negative `HostError` code.
+pub fn world_despawn(env: &GersEnv, entity: u32) -> i32 {
+    match env.world.lock() {
+        Ok(mut world) => world.despawn(entity).map_or_else(world_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Copy component data out of the guest and attach it to an entity.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn world_set_component(
+    env: &GersEnv,
+    entity: u32,
+    component: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let data = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len))
+    {
+        Some(data) => data,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match env.world.lock() {
+        Ok(mut world) => world
+            .set_component(entity, component, data)
+            .map_or_else(world_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Copy an entity's component data into the guest buffer.
+///
+/// Returns the full size of the data, which may be larger than the
+/// buffer, in which case only the part that fits is copied. Returns a
+/// negative `HostError` code on failure.
+pub fn world_get_component(
+    env: &GersEnv,
+    entity: u32,
+    component: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let world = match env.world.lock() {
+        Ok(world) => world,
+        Err(_) => return HostError::Io.code(),
+    };
+    let data = match world.component(entity, component) {
+        Ok(data) => data,
+        Err(err) => return world_error_code(err),
+    };
+
+    copy_to_guest(env, data, buf_ptr, buf_len)
+}
+
+/// Returns zero on success, or a negative `HostError` code.
+pub fn world_remove_component(env: &GersEnv, entity: u32, component: u32) -> i32 {
+    match env.world.lock() {
+        Ok(mut world) => world
+            .remove_component(entity, component)
+            .map_or_else(world_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Start a query for entities with all the components in the mask.
+///
+/// Returns a token for stepping through the results, or zero on failure.
+pub fn world_query(env: &GersEnv, mask: u64) -> u32 {
+    match env.world.lock() {
+        Ok(mut world) => world.query(mask),
+        Err(_) => 0,
+    }
+}
+
+/// Returns the next entity of a query, or zero when it's exhausted.
+pub fn world_query_next(env: &GersEnv, token: u32) -> u32 {
+    match env.world.lock() {
+        Ok(mut world) => world.query_next(token).unwrap_or_default(),
+        Err(_) => 0,
+    }
+}
+
+/// Fill the guest buffer with rows for the next entities of a query.
+///
+/// Each row is the entity id followed by `data_size` bytes of the
+/// component. The query token doubles as the continuation, so the guest
+/// calls again until no rows are returned. Returns the number of rows
+/// written, or a negative `HostError` code.
+pub fn world_query_fill(
+    env: &GersEnv,
+    token: u32,
+    component: u32,
+    data_size: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    // The buffer is checked before the query moves on, so no rows are
+    // skipped that can't be handed to the guest.
+    let cells = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| buf_ptr.deref(mem, 0, buf_len))
+    {
+        Some(cells) => cells,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let row_size = gers_world::ROW_HEADER_SIZE + data_size as usize;
+    let max_rows = buf_len as usize / row_size;
+
+    let mut rows = vec![];
+    let count = match env.world.lock() {
+        Ok(mut world) => {
+            match world.query_rows(token, component, data_size as usize, max_rows, &mut rows) {
+                Ok(count) => count,
+                Err(err) => return world_error_code(err),
+            }
+        }
+        Err(_) => return HostError::Io.code(),
+    };
+    for (cell, byte) in cells.iter().zip(&rows) {
+        cell.set(*byte);
+    }
+
+    count.min(i32::MAX as usize) as i32
+}
+
+pub fn world_query_end(env: &GersEnv, token: u32) {
+    if let Ok(mut world) = env.world.lock() {
+        world.end_query(token);
+    }
+}
+
+/// Most bytes a single `net_tcp_recv` receives.
+const NET_RECV_CHUNK: usize = 64 * 1024;
+
+fn net_error_code(err: NetError) -> i32 {
+    match err {
+        NetError::ConnectionLimit(_) => HostError::LimitReached.code(),
+        NetError::UnknownConnection(_) => HostError::NotFound.code(),
+        NetError::WouldBlock => HostError::WouldBlock.code(),
+        NetError::Io(_) => HostError::Io.code(),
+    }
+}
+
+/// Start opening a TCP connection to a `host:port` address.
+///
+/// Returns the connection id, or a negative `HostError` code. Sends and
+/// receives fail with `HostError::WouldBlock` until the connection is
+/// established.
+pub fn net_tcp_connect(env: &GersEnv, addr_ptr: WasmPtr<u8, Array>, addr_len: u32) -> i32 {
+    let address = match read_string(env, addr_ptr, addr_len) {
+        Some(address) => address,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.network.lock() {
+        Ok(mut network) => network.connect(&env.plugin.key, address),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(id) => id.min(i32::MAX as u32) as i32,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to connect: {}", err);
+            net_error_code(err)
+        }
+    }
+}
+
+/// Send data from the guest buffer.
+///
+/// Returns the number of bytes sent, or a negative `HostError` code.
+pub fn net_tcp_send(
+    env: &GersEnv,
+    connection: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let data = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len))
+    {
+        Some(data) => data,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match env.network.lock() {
+        Ok(mut network) => match network.send(&env.plugin.key, connection, &data) {
+            Ok(sent) => sent.min(i32::MAX as usize) as i32,
+            Err(err) => net_error_code(err),
+        },
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Receive data into the guest buffer.
+///
+/// Returns the number of bytes received, zero once the remote end
+/// closed the connection, or a negative `HostError` code.
+pub fn net_tcp_recv(
+    env: &GersEnv,
+    connection: u32,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    // The buffer is checked before receiving, so no data is taken off
+    // the connection that can't be handed to the guest.
+    let cells = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| buf_ptr.deref(mem, 0, buf_len))
+    {
+        Some(cells) => cells,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    // Received through a bounded buffer, the guest receives the rest
+    // with the next calls.
+    let mut buf = vec![0; cells.len().min(NET_RECV_CHUNK)];
+    let received = match env.network.lock() {
+        Ok(mut network) => match network.recv(&env.plugin.key, connection, &mut buf) {
+            Ok(received) => received,
+            Err(err) => return net_error_code(err),
+        },
+        Err(_) => return HostError::Io.code(),
+    };
+    for (cell, byte) in cells.iter().zip(&buf[..received]) {
+        cell.set(*byte);
+    }
+
+    received as i32
+}
+
+/// Returns zero if the connection was closed, or a negative `HostError` code.
+pub fn net_tcp_close(env: &GersEnv, connection: u32) -> i32 {
+    match env.network.lock() {
+        Ok(mut network) => {
+            if network.close(&env.plugin.key, connection) {
+                0
+            } else {
+                HostError::NotFound.code()
+            }
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+fn http_error_code(err: HttpError) -> i32 {
+    match err {
+        HttpError::RequestLimit(_) => HostError::LimitReached.code(),
+        HttpError::UnknownRequest(_) => HostError::NotFound.code(),
+        HttpError::UnknownMethod => HostError::InvalidArgument.code(),
+        HttpError::Io(_) => HostError::Io.code(),
+    }
+}
+
+/// Queue an HTTP request, with an optional body.
+///
+/// Returns the request handle, or a negative `HostError` code. The
+/// response is delivered later as an `HttpResponseEvent`.
+pub fn http_request(
+    env: &GersEnv,
+    method: u32,
+    url_ptr: WasmPtr<u8, Array>,
+    url_len: u32,
+    body_ptr: WasmPtr<u8, Array>,
+    body_len: u32,
+) -> i64 {
+    let url = match read_string(env, url_ptr, url_len) {
+        Some(url) => url,
+        None => return HostError::InvalidArgument.code().into(),
+    };
+
+    let body = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, body_ptr, body_len))
+    {
+        Some(body) => body,
+        None => return HostError::InvalidArgument.code().into(),
+    };
+
+    let result = match env.http.lock() {
+        Ok(mut http) => http.request(&env.plugin.key, HttpMethod::from(method), url, body),
+        Err(_) => return HostError::Io.code().into(),
+    };
+
+    match result {
+        Ok(id) => id.to_raw() as i64,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to make request: {}", err);
+            http_error_code(err).into()
+        }
+    }
+}
+
+/// Copy the body of a response into the guest buffer, while its
+/// `HttpResponseEvent` is handled.
+///
+/// Returns the full size of the body, which may be larger than the
+/// buffer, in which case only the part that fits is copied. Returns a
+/// negative `HostError` code on failure.
+pub fn http_response_body(
+    env: &GersEnv,
+    request: u64,
+    buf_ptr: WasmPtr<u8, Array>,
+    buf_len: u32,
+) -> i32 {
+    let http = match env.http.lock() {
+        Ok(http) => http,
+        Err(_) => return HostError::Io.code(),
+    };
+    let body = match http.body(&env.plugin.key, RequestId::from_raw(request)) {
+        Ok(body) => body,
+        Err(err) => return http_error_code(err),
+    };
+
+    copy_to_guest(env, body, buf_ptr, buf_len)
+}
+
+/// Set a timer that fires a `TimerFiredEvent` after the interval,
+/// and keeps firing every interval if `repeat` is non-zero.
+///
+/// Returns the timer handle, or a negative `HostError` code.
+pub fn set_timer(env: &GersEnv, millis: u32, repeat: u32) -> i64 {
+    let interval = Duration::from_millis(millis as u64);
+    let result = match env.timers.lock() {
+        Ok(mut timers) => timers.set(&env.plugin.key, interval, repeat != 0),
+        Err(_) => return HostError::Io.code().into(),
+    };
+
+    match result {
+        Ok(id) => id.to_raw() as i64,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to set timer: {}", err);
+            HostError::LimitReached.code().into()
+        }
+    }
+}
+
+/// Returns 1 if the timer was cancelled, 0 if the plugin didn't set it.
+pub fn cancel_timer(env: &GersEnv, timer: u64) -> u32 {
+    match env.timers.lock() {
+        Ok(mut timers) => timers.cancel(&env.plugin.key, TimerId::from_raw(timer)) as u32,
+        Err(_) => 0,
+    }
+}
+
+/// Subscribe to an event type with a priority, where higher priority
+/// plugins receive the event first. See `gers_events::SUBSCRIBE_CONSUME`
+/// for the flags.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn event_subscribe(env: &GersEnv, event_id: i32, priority: i32, flags: u32) -> i32 {
+    let event_type = match EventType::from(event_id) {
+        EventType::NoOp => return HostError::InvalidArgument.code(),
+        event_type => event_type,
+    };
+
+    match env.hooks.lock() {
+        Ok(mut hooks) => {
+            hooks.subscribe(&env.plugin.key, event_type, priority, flags);
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Consume the event that is being handled, so plugins with a lower
+/// priority don't receive it.
+///
+/// Returns 1 if the event was consumed, 0 if the plugin didn't
+/// subscribe to it with the consume flag.
+pub fn event_consume(env: &GersEnv) -> u32 {
+    match env.hooks.lock() {
+        Ok(mut hooks) => hooks.consume(&env.plugin.key) as u32,
+        Err(_) => 0,
+    }
+}
+
+/// Emit a `DamageEvent`, which is sent to all plugins when events
+/// are next dispatched.
+pub fn emit_damage(env: &GersEnv, entity: u32, source: u32, amount: f32) {
+    if let Ok(mut events) = env.emitted_events.lock() {
+        events.push((
+            plugin_id(&env.plugin.name),
+            FrameEvent::Damage(DamageEvent {
+                entity,
+                source,
+                amount,
+                cancelled: 0,
+            }),
+        ));
+    }
+}
+
+/// Id of a custom event type, by the name it's defined under in an
+/// event manifest.
+///
+/// Returns the id, or a negative `HostError` code.
+pub fn event_custom_id(env: &GersEnv, name_ptr: WasmPtr<u8, Array>, name_len: u32) -> i32 {
+    let name = match read_string(env, name_ptr, name_len) {
+        Some(name) => name,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match env.custom_events.read() {
+        Ok(custom_events) => match custom_events.id(&name) {
+            Some(id) => id.min(i32::MAX as u32) as i32,
+            None => HostError::NotFound.code(),
+        },
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Publish a custom event by name, which is sent to the plugins
+/// handling it when events are next dispatched.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn event_publish(
+    env: &GersEnv,
+    name_ptr: WasmPtr<u8, Array>,
+    name_len: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let data = env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len));
+    let (name, data) = match (read_string(env, name_ptr, name_len), data) {
+        (Some(name), Some(data)) => (name, data),
+        _ => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.custom_events.read() {
+        Ok(custom_events) => custom_events.event(&name, &data),
+        Err(_) => return HostError::Io.code(),
+    };
+    let event = match result {
+        Ok(event) => event,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to publish custom event: {}", err);
+            return match err {
+                CustomEventError::Undefined(_) => HostError::NotFound.code(),
+                _ => HostError::InvalidArgument.code(),
+            };
+        }
+    };
+
+    match env.emitted_events.lock() {
+        Ok(mut events) => {
+            events.push((
+                plugin_id(&env.plugin.name),
+                FrameEvent::Custom(Box::new(event)),
+            ));
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Raise an event by name, which is sent to the plugins handling or
+/// subscribed to it when events are next dispatched.
+///
+/// Unlike `event_publish`, the name doesn't have to be defined in an
+/// event manifest, and is registered when it's first used.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn event_emit(
+    env: &GersEnv,
+    name_ptr: WasmPtr<u8, Array>,
+    name_len: u32,
+    data_ptr: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> i32 {
+    let data = env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len));
+    let (name, data) = match (read_string(env, name_ptr, name_len), data) {
+        (Some(name), Some(data)) => (name, data),
+        _ => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.custom_events.write() {
+        Ok(mut custom_events) => custom_events.emit(&name, &data),
+        Err(_) => return HostError::Io.code(),
+    };
+    let event = match result {
+        Ok(event) => event,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to emit custom event: {}", err);
+            return HostError::InvalidArgument.code();
+        }
+    };
+
+    match env.emitted_events.lock() {
+        Ok(mut events) => {
+            events.push((
+                plugin_id(&env.plugin.name),
+                FrameEvent::Custom(Box::new(event)),
+            ));
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Receive custom events raised under a name, whether or not a plugin
+/// defined it in an event manifest.
+///
+/// Returns the id of the custom event type, or a negative `HostError`
+/// code.
+pub fn event_subscribe_custom(env: &GersEnv, name_ptr: WasmPtr<u8, Array>, name_len: u32) -> i32 {
+    let name = match read_string(env, name_ptr, name_len) {
+        Some(name) => name,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.custom_events.write() {
+        Ok(mut custom_events) => custom_events.subscribe(&name, &env.plugin.key),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(id) => id.min(i32::MAX as u32) as i32,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to subscribe to custom event: {}", err);
+            HostError::InvalidArgument.code()
+        }
+    }
+}
+
+/// Append state to the plugin's savegame record, while the plugin
+/// is being saved.
+///
+/// Returns zero on success, or a negative `HostError` code.
+pub fn save_write(env: &GersEnv, data_ptr: WasmPtr<u8, Array>, data_len: u32) -> i32 {
+    let data = match env
+        .memory
+        .get_ref()
+        .and_then(|mem| strings::read_bytes(mem, data_ptr, data_len))
+    {
+        Some(data) => data,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match env.save_buffer.lock() {
+        Ok(mut buffer) => {
+            buffer.extend_from_slice(&data);
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+fn window_error_code(err: WindowError) -> i32 {
+    match err {
+        WindowError::WindowLimit(_) => HostError::LimitReached.code(),
+        WindowError::UnknownWindow(_) => HostError::NotFound.code(),
+    }
+}
+
+/// Open a window, which is created at the end of the frame.
+///
+/// Returns the window handle, or a negative `HostError` code.
+pub fn window_create(
+    env: &GersEnv,
+    title_ptr: WasmPtr<u8, Array>,
+    title_len: u32,
+    width: u32,
+    height: u32,
+) -> i32 {
+    let title = match read_string(env, title_ptr, title_len) {
+        Some(title) => title,
+        None => return HostError::InvalidArgument.code(),
+    };
+    if width == 0 || height == 0 {
+        return HostError::InvalidArgument.code();
+    }
+
+    let result = match env.windows.lock() {
+        Ok(mut windows) => windows.create(&env.plugin.key, title, width, height),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(handle) => handle.min(i32::MAX as u32) as i32,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to open window: {}", err);
+            window_error_code(err)
+        }
+    }
+}
+
+/// Returns zero if the window was closed, or a negative `HostError` code.
+pub fn window_close(env: &GersEnv, window: u32) -> i32 {
+    match env.windows.lock() {
+        Ok(mut windows) => windows
+            .close(&env.plugin.key, window)
+            .map_or_else(window_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+pub fn window_set_title(
+    env: &GersEnv,
+    window: u32,
+    title_ptr: WasmPtr<u8, Array>,
+    title_len: u32,
+) -> i32 {
+    let title = match read_string(env, title_ptr, title_len) {
+        Some(title) => title,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    match env.windows.lock() {
+        Ok(mut windows) => windows
+            .set_title(&env.plugin.key, window, title)
+            .map_or_else(window_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Resize the inner size of a window, in logical pixels.
+pub fn window_set_size(env: &GersEnv, window: u32, width: u32, height: u32) -> i32 {
+    if width == 0 || height == 0 {
+        return HostError::InvalidArgument.code();
+    }
+
+    match env.windows.lock() {
+        Ok(mut windows) => windows
+            .set_size(&env.plugin.key, window, width, height)
+            .map_or_else(window_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+pub fn window_set_fullscreen(env: &GersEnv, window: u32, fullscreen: u32) -> i32 {
+    match env.windows.lock() {
+        Ok(mut windows) => windows
+            .set_fullscreen(&env.plugin.key, window, fullscreen != 0)
+            .map_or_else(window_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+pub fn window_set_cursor_visible(env: &GersEnv, window: u32, visible: u32) -> i32 {
+    match env.windows.lock() {
+        Ok(mut windows) => windows
+            .set_cursor_visible(&env.plugin.key, window, visible != 0)
+            .map_or_else(window_error_code, |_| 0),
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+/// Take a screenshot of the main window, which is saved when it's
+/// next rendered.
+///
+/// Windows opened by plugins aren't rendered to, so only the main
+/// window can be taken.
+pub fn window_screenshot(env: &GersEnv, window: u32) -> i32 {
+    if window != MAIN_WINDOW {
+        return HostError::InvalidArgument.code();
+    }
+
+    match env.windows.lock() {
+        Ok(mut windows) => {
+            windows.request_screenshot();
+            0
+        }
+        Err(_) => HostError::Io.code(),
+    }
+}
+
+fn console_error_code(err: ConsoleError) -> i32 {
+    match err {
+        ConsoleError::CommandLimit(_) => HostError::LimitReached.code(),
+        ConsoleError::InvalidName(_) | ConsoleError::NameTaken(_) | ConsoleError::Usage(_) => {
+            HostError::InvalidArgument.code()
+        }
+        ConsoleError::UnknownCommand(_) => HostError::NotFound.code(),
+    }
+}
+
+/// Register a console command, invoked with a `ConsoleCommandEvent`.
+///
+/// Returns the command id, or a negative `HostError` code.
+pub fn console_register(
+    env: &GersEnv,
+    name_ptr: WasmPtr<u8, Array>,
+    name_len: u32,
+    help_ptr: WasmPtr<u8, Array>,
+    help_len: u32,
+) -> i32 {
+    let (name, help) = match (
+        read_string(env, name_ptr, name_len),
+        read_string(env, help_ptr, help_len),
+    ) {
+        (Some(name), Some(help)) => (name, help),
+        _ => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.console.lock() {
+        Ok(mut console) => console.register(&env.plugin.key, name, help),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(id) => id.min(i32::MAX as u32) as i32,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to register console command: {}", err);
+            console_error_code(err)
+        }
+    }
+}
+
+/// Copy the arguments of the console command that is being handled.
+///
+/// Returns the full size of the arguments, which may be larger than
+/// the buffer, in which case only the part that fits is copied. Returns
+/// a negative `HostError` code on failure.
+pub fn console_args(env: &GersEnv, buf_ptr: WasmPtr<u8, Array>, buf_len: u32) -> i32 {
+    let console = match env.console.lock() {
+        Ok(console) => console,
+        Err(_) => return HostError::Io.code(),
+    };
+    let args = match console.args(&env.plugin.key) {
+        Some(args) => args,
+        None => return HostError::NotFound.code(),
+    };
+
+    copy_to_guest(env, args.as_bytes(), buf_ptr, buf_len)
+}
+
+fn clipboard_error_code(err: ClipboardError) -> i32 {
+    match err {
+        ClipboardError::Empty => HostError::NotFound.code(),
+        ClipboardError::Unavailable(_) => HostError::Io.code(),
+    }
+}
+
+/// Copy the text on the clipboard into the guest buffer.
+///
+/// Returns the full size of the text in bytes, which may be larger
+/// than the buffer, in which case only the part that fits is copied.
+/// Returns a negative `HostError` code on failure.
+pub fn clipboard_get_text(env: &GersEnv, buf_ptr: WasmPtr<u8, Array>, buf_len: u32) -> i32 {
+    let result = match env.clipboard.lock() {
+        Ok(mut clipboard) => clipboard.get_text(),
+        Err(_) => return HostError::Io.code(),
+    };
+    let text = match result {
+        Ok(text) => text,
+        Err(err) => {
+            if let ClipboardError::Unavailable(_) = err {
+                slog::warn!(env.logger, "failed to read clipboard: {}", err);
+            }
+            return clipboard_error_code(err);
+        }
+    };
+
+    copy_to_guest(env, text.as_bytes(), buf_ptr, buf_len)
+}
+
+/// Returns zero if the text was put on the clipboard, or a negative
+/// `HostError` code.
+pub fn clipboard_set_text(env: &GersEnv, text_ptr: WasmPtr<u8, Array>, text_len: u32) -> i32 {
+    let text = match read_string(env, text_ptr, text_len) {
+        Some(text) => text,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.clipboard.lock() {
+        Ok(mut clipboard) => clipboard.set_text(text),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to write clipboard: {}", err);
+            clipboard_error_code(err)
+        }
+    }
+}
+
+fn watch_error_code(err: WatchError) -> i32 {
+    match err {
+        WatchError::WatchLimit(_) => HostError::LimitReached.code(),
+        WatchError::InvalidName(_) | WatchError::UnknownType(_) => {
+            HostError::InvalidArgument.code()
+        }
+    }
+}
+
+/// Watch the value of a `WatchType` at `ptr` in the plugin's memory,
+/// shown under the name in the debug overlay.
+///
+/// Returns zero if the value is watched, or a negative `HostError` code.
+pub fn debug_watch(
+    env: &GersEnv,
+    name_ptr: WasmPtr<u8, Array>,
+    name_len: u32,
+    ptr: u32,
+    type_id: u32,
+) -> i32 {
+    let name = match read_string(env, name_ptr, name_len) {
+        Some(name) => name,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let result = match env.watches.lock() {
+        Ok(mut watches) => watches.watch(&env.plugin.key, name, ptr, type_id),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            slog::warn!(env.logger, "failed to watch value: {}", err);
+            watch_error_code(err)
+        }
+    }
+}
+
+/// Returns zero if the value is no longer watched, or a negative
+/// `HostError` code.
+pub fn debug_unwatch(env: &GersEnv, name_ptr: WasmPtr<u8, Array>, name_len: u32) -> i32 {
+    let name = match read_string(env, name_ptr, name_len) {
+        Some(name) => name,
+        None => return HostError::InvalidArgument.code(),
+    };
+
+    let unwatched = match env.watches.lock() {
+        Ok(mut watches) => watches.unwatch(&env.plugin.key, &name),
+        Err(_) => return HostError::Io.code(),
+    };
+
+    if unwatched {
+        0
+    } else {
+        HostError::NotFound.code()
+    }
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted.
+pub fn denied_f32(env: &GersEnv, _: f32) -> i32 {
+    warn_denied(env)
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted.
+pub fn denied_1(env: &GersEnv, _: u32) -> i32 {
+    warn_denied(env)
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted.
+pub fn denied_2(env: &GersEnv, _: u32, _: u32) -> i32 {
+    warn_denied(env)
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted.
+pub fn denied_3(env: &GersEnv, _: u32, _: u32, _: u32) -> i32 {
+    warn_denied(env)
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted.
+pub fn denied_4(env: &GersEnv, _: u32, _: u32, _: u32, _: u32) -> i32 {
+    warn_denied(env)
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted,
+/// which return a handle.
+pub fn denied_5_i64(env: &GersEnv, _: u32, _: u32, _: u32, _: u32, _: u32) -> i64 {
+    warn_denied(env).into()
+}
+
+/// Stand-in for imports of a capability the plugin wasn't granted,
+/// which take a handle.
+pub fn denied_handle_2(env: &GersEnv, _: u64, _: u32, _: u32) -> i32 {
+    warn_denied(env)
+}
+
+fn warn_denied(env: &GersEnv) -> i32 {
+    slog::warn!(
+        env.logger,
+        "plugin {:?} called an import it lacks permission for",
+        env.plugin.key
+    );
+    HostError::PermissionDenied.code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::ConsoleInput, env::PluginScope, render::Texture, window::WindowCommand};
+    use gers_plugins::PluginSource;
+    use std::{fs, sync::Arc};
+
+    #[test]
+    fn test_report_panic() {
+        let env = GersEnv::for_test();
+        assert_eq!(env.take_panic_message(), None);
+
+        let message = "panicked at 'oops', src/lib.rs:3:5";
+        env.write_memory(64, message.as_bytes());
+        report_panic(&env, WasmPtr::new(64), message.len() as u32);
+
+        assert_eq!(env.take_panic_message().as_deref(), Some(message));
+        // Taken once, so a later trap isn't blamed on the same panic.
+        assert_eq!(env.take_panic_message(), None);
+    }
+
+    #[test]
+    fn test_report_panic_out_of_bounds() {
+        let env = GersEnv::for_test();
+        report_panic(&env, WasmPtr::new(u32::MAX - 4), 16);
+
+        assert_eq!(env.take_panic_message(), None);
+    }
+
+    #[test]
+    fn test_draw_calls_are_queued() {
+        let env = GersEnv::for_test();
+        draw_rect(&env, 1.0, 2.0, 3.0, 4.0, 0xff0000ff);
+        draw_line(&env, 0.0, 0.0, 5.0, 5.0, 0x00ff0080);
+
+        let batch = env.draw_queue.lock().unwrap();
+        assert!(matches!(
+            batch[..],
+            [
+                DrawCommand::Rect {
+                    width,
+                    color: Color { r: 255, a: 255, .. },
+                    ..
+                },
+                DrawCommand::Line {
+                    x1,
+                    color: Color { g: 255, a: 128, .. },
+                    ..
+                },
+            ] if width == 3.0 && x1 == 5.0
+        ));
+    }
+
+    #[test]
+    fn test_load_and_unload_texture() {
+        let root = std::env::temp_dir().join(format!("gers-textures-{}", std::process::id()));
+        fs::create_dir_all(root.join(assets::ASSET_DIR)).unwrap();
+        fs::write(
+            root.join(assets::ASSET_DIR).join("bad.png"),
+            b"not an image",
+        )
+        .unwrap();
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                key: root.clone(),
+                source: Some(PluginSource::Directory(root.clone())),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+
+        for path in ["missing.png", "bad.png", "../bad.png"] {
+            env.write_memory(0, path.as_bytes());
+            assert_eq!(load_texture(&env, WasmPtr::new(0), path.len() as u32), 0);
+        }
+
+        let texture = Texture {
+            width: 1,
+            height: 1,
+            pixels: vec![0; 4],
+        };
+        let id = env
+            .textures
+            .write()
+            .unwrap()
+            .insert(&env.plugin.key, texture);
+
+        // Other plugins can neither draw nor unload the texture.
+        let other = GersEnv {
+            plugin: Arc::new(PluginScope::default()),
+            ..env.clone()
+        };
+        draw_sprite(&other, id.to_raw(), 0.0, 0.0);
+        draw_sprite(&env, id.to_raw(), 0.0, 0.0);
+        assert!(matches!(
+            env.draw_queue.lock().unwrap().as_slice(),
+            [
+                DrawCommand::Sprite {
+                    texture_id: TextureId::NULL,
+                    ..
+                },
+                DrawCommand::Sprite { texture_id, .. },
+            ] if *texture_id == id
+        ));
+        assert_eq!(unload_texture(&other, id.to_raw()), 0);
+
+        assert_eq!(unload_texture(&env, id.to_raw()), 1);
+        assert_eq!(unload_texture(&env, id.to_raw()), 0);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_register_action() {
+        let env = GersEnv::for_test();
+        env.write_memory(0, b"jumpSpaceBogus");
+        let name = WasmPtr::new(0);
+
+        assert_eq!(register_action(&env, name, 4, WasmPtr::new(4), 5), 1);
+        // Without a default binding.
+        assert_eq!(
+            register_action(&env, WasmPtr::new(4), 5, WasmPtr::new(0), 0),
+            2
+        );
+        // Unknown keys and empty names fail.
+        assert_eq!(register_action(&env, name, 4, WasmPtr::new(9), 5), 0);
+        assert_eq!(register_action(&env, name, 0, WasmPtr::new(4), 5), 0);
+
+        let mut input = env.input.lock().unwrap();
+        input.handle_key(
+            winit::event::VirtualKeyCode::Space,
+            winit::event::ElementState::Pressed,
+        );
+        assert_eq!(input.take_events()[0].action_id, 1);
+    }
+
+    const KEY: u32 = 0;
+    const DATA: u32 = 64;
+    const BUF: u32 = 128;
+
+    fn save(env: &GersEnv, key: &str, data: &[u8]) -> i32 {
+        env.write_memory(KEY, key.as_bytes());
+        env.write_memory(DATA, data);
+        storage_save(
+            env,
+            WasmPtr::new(KEY),
+            key.len() as u32,
+            WasmPtr::new(DATA),
+            data.len() as u32,
+        )
+    }
+
+    fn load(env: &GersEnv, key: &str, buf_len: u32) -> i32 {
+        env.write_memory(KEY, key.as_bytes());
+        storage_load(
+            env,
+            WasmPtr::new(KEY),
+            key.len() as u32,
+            WasmPtr::new(BUF),
+            buf_len,
+        )
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let root = std::env::temp_dir().join(format!("gers-storage-{}", std::process::id()));
+        let data_dir = root.join("data").join("test");
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                data_dir: Some(data_dir.clone()),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+
+        assert_eq!(load(&env, "score", 8), HostError::NotFound.code());
+        assert_eq!(save(&env, "score", b"12345"), 0);
+        assert_eq!(fs::read(data_dir.join("score")).unwrap(), b"12345");
+
+        assert_eq!(load(&env, "score", 8), 5);
+        assert_eq!(env.read_memory(BUF, 5), b"12345");
+
+        // Values larger than the buffer are truncated.
+        env.write_memory(BUF, &[0; 5]);
+        assert_eq!(load(&env, "score", 2), 5);
+        assert_eq!(env.read_memory(BUF, 5), b"12\0\0\0");
+
+        for key in ["", "..", "../escape", "a/b"] {
+            assert_eq!(save(&env, key, b"1"), HostError::InvalidArgument.code());
+            assert_eq!(load(&env, key, 8), HostError::InvalidArgument.code());
+        }
+        assert!(!root.join("data").join("escape").exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_data_dir_files() {
+        let root = std::env::temp_dir().join(format!("gers-fs-{}", std::process::id()));
+        let data_dir = root.join("data").join("test");
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                data_dir: Some(data_dir.clone()),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+        let path = |path: &str| {
+            env.write_memory(KEY, path.as_bytes());
+            (WasmPtr::new(KEY), path.len() as u32)
+        };
+
+        let expected = data_dir.to_str().unwrap();
+        assert_eq!(
+            fs_data_dir(&env, WasmPtr::new(BUF), 64),
+            expected.len() as i32
+        );
+        assert_eq!(
+            env.read_memory(BUF, expected.len() as u32),
+            expected.as_bytes()
+        );
+
+        env.write_memory(DATA, b"level");
+        let (ptr, len) = path("saves/slot-1");
+        assert_eq!(fs_write(&env, ptr, len, WasmPtr::new(DATA), 5), 0);
+        assert_eq!(
+            fs::read(data_dir.join("saves").join("slot-1")).unwrap(),
+            b"level"
+        );
+        let (ptr, len) = path("saves/slot-1");
+        assert_eq!(fs_read(&env, ptr, len, WasmPtr::new(BUF), 8), 5);
+        assert_eq!(env.read_memory(BUF, 5), b"level");
+
+        let (ptr, len) = path("saves/slot-1");
+        assert_eq!(fs_remove(&env, ptr, len), 0);
+        let (ptr, len) = path("saves/slot-1");
+        assert_eq!(fs_remove(&env, ptr, len), HostError::NotFound.code());
+
+        // Other plugins' files are out of reach.
+        for escape in ["../other/file", "/etc/passwd", "saves/../../other"] {
+            let (ptr, len) = path(escape);
+            assert_eq!(
+                fs_write(&env, ptr, len, WasmPtr::new(DATA), 5),
+                HostError::InvalidArgument.code()
+            );
+            let (ptr, len) = path(escape);
+            assert_eq!(
+                fs_read(&env, ptr, len, WasmPtr::new(BUF), 8),
+                HostError::InvalidArgument.code()
+            );
+        }
+        assert!(!root.join("data").join("other").exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_plugin_name_and_version() {
+        let env = GersEnv::for_test();
+
+        assert_eq!(plugin_name(&env, WasmPtr::new(0), 16), 4);
+        assert_eq!(env.read_memory(0, 4), b"test");
+        assert_eq!(plugin_version(&env, WasmPtr::new(16), 16), 5);
+        assert_eq!(env.read_memory(16, 5), b"1.0.0");
+    }
+
+    #[test]
+    fn test_plugin_name_is_truncated() {
+        let env = GersEnv::for_test();
+        env.write_memory(0, &[0; 4]);
+
+        // The full length is returned, so the guest can retry.
+        assert_eq!(plugin_name(&env, WasmPtr::new(0), 2), 4);
+        assert_eq!(env.read_memory(0, 4), b"te\0\0");
+        assert_eq!(plugin_name(&env, WasmPtr::new(0), 0), 4);
+    }
+
+    #[test]
+    fn test_components_through_guest_memory() {
+        let env = GersEnv::for_test();
+        let entity = world_spawn(&env);
+        assert_ne!(entity, 0);
+
+        env.write_memory(0, &[1, 2, 3, 4]);
+        assert_eq!(world_set_component(&env, entity, 2, WasmPtr::new(0), 4), 0);
+        assert_eq!(world_get_component(&env, entity, 2, WasmPtr::new(16), 8), 4);
+        assert_eq!(env.read_memory(16, 4), [1, 2, 3, 4]);
+
+        // Data larger than the buffer is truncated.
+        env.write_memory(32, &[0; 4]);
+        assert_eq!(world_get_component(&env, entity, 2, WasmPtr::new(32), 2), 4);
+        assert_eq!(env.read_memory(32, 4), [1, 2, 0, 0]);
+
+        assert_eq!(world_remove_component(&env, entity, 2), 0);
+        assert_eq!(world_despawn(&env, entity), 0);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let env = GersEnv::for_test();
+        let entity = world_spawn(&env);
+        let not_found = HostError::NotFound.code();
+        let invalid = HostError::InvalidArgument.code();
+
+        assert_eq!(
+            world_get_component(&env, entity, 2, WasmPtr::new(0), 8),
+            not_found
+        );
+        assert_eq!(world_remove_component(&env, entity, 2), not_found);
+        assert_eq!(world_despawn(&env, entity + 1), not_found);
+        assert_eq!(
+            world_set_component(&env, entity, 64, WasmPtr::new(0), 4),
+            invalid
+        );
+        // Data out of the guest's memory.
+        assert_eq!(
+            world_set_component(&env, entity, 2, WasmPtr::new(u32::MAX - 2), 4),
+            invalid
+        );
+        assert_eq!(world_query_next(&env, 99), 0);
+    }
+
+    #[test]
+    fn test_query_fill() {
+        let env = GersEnv::for_test();
+        let entities: Vec<_> = (0..3).map(|_| world_spawn(&env)).collect();
+        for (index, entity) in entities.iter().enumerate() {
+            env.write_memory(0, &[index as u8 + 1]);
+            assert_eq!(world_set_component(&env, *entity, 0, WasmPtr::new(0), 1), 0);
+        }
+
+        // Room for two rows of 4 + 2 bytes, and a bit over.
+        let token = world_query(&env, 1);
+        assert_eq!(world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 14), 2);
+        let rows = env.read_memory(64, 12);
+        assert_eq!(rows[..4], entities[0].to_le_bytes());
+        assert_eq!(rows[4..6], [1, 0]);
+        assert_eq!(rows[6..10], entities[1].to_le_bytes());
+        assert_eq!(rows[10..12], [2, 0]);
+
+        assert_eq!(world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 14), 1);
+        assert_eq!(env.read_memory(64, 4), entities[2].to_le_bytes());
+        assert_eq!(world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 14), 0);
+
+        world_query_end(&env, token);
+        assert_eq!(
+            world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 14),
+            HostError::NotFound.code()
+        );
+
+        let token = world_query(&env, 1);
+        assert_eq!(
+            world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 5),
+            HostError::InvalidArgument.code()
+        );
+
+        // Rows aren't skipped when the buffer is out of the guest's memory.
+        assert_eq!(
+            world_query_fill(&env, token, 0, 2, WasmPtr::new(u32::MAX - 2), 14),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(world_query_fill(&env, token, 0, 2, WasmPtr::new(64), 14), 2);
+        assert_eq!(env.read_memory(64, 4), entities[0].to_le_bytes());
+    }
+
+    #[test]
+    fn test_net_error_codes() {
+        let env = GersEnv::for_test();
+
+        // Addresses out of the guest's memory.
+        assert_eq!(
+            net_tcp_connect(&env, WasmPtr::new(u32::MAX - 2), 8),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(
+            net_tcp_send(&env, 1, WasmPtr::new(0), 4),
+            HostError::NotFound.code()
+        );
+        assert_eq!(
+            net_tcp_recv(&env, 1, WasmPtr::new(0), 4),
+            HostError::NotFound.code()
+        );
+        assert_eq!(net_tcp_close(&env, 1), HostError::NotFound.code());
+
+        let address = "not an address";
+        env.write_memory(0, address.as_bytes());
+        let id = net_tcp_connect(&env, WasmPtr::new(0), address.len() as u32);
+        assert!(id > 0);
+        assert_eq!(net_tcp_close(&env, id as u32), 0);
+    }
+
+    #[test]
+    fn test_net_recv_into_invalid_buffer() {
+        use std::{io::Write, net::TcpListener, thread, time::Instant};
+
+        let env = GersEnv::for_test();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        env.write_memory(0, address.as_bytes());
+        let id = net_tcp_connect(&env, WasmPtr::new(0), address.len() as u32) as u32;
+        let (mut remote, _) = listener.accept().unwrap();
+
+        // Poll until the connection is open, and the data arrived.
+        let poll = |call: &dyn Fn() -> i32| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            loop {
+                let result = call();
+                if result != HostError::WouldBlock.code() || Instant::now() > deadline {
+                    return result;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        assert_eq!(poll(&|| net_tcp_send(&env, id, WasmPtr::new(0), 0)), 0);
+        remote.write_all(b"hello").unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        assert_eq!(
+            net_tcp_recv(&env, id, WasmPtr::new(u32::MAX - 2), 8),
+            HostError::InvalidArgument.code()
+        );
+        // Nothing was lost.
+        assert_eq!(poll(&|| net_tcp_recv(&env, id, WasmPtr::new(64), 16)), 5);
+        assert_eq!(env.read_memory(64, 5), b"hello");
+    }
+
+    #[test]
+    fn test_http_error_codes() {
+        let env = GersEnv::for_test();
+
+        // Addresses out of the guest's memory.
+        assert_eq!(
+            http_request(&env, 1, WasmPtr::new(u32::MAX - 2), 8, WasmPtr::new(0), 0),
+            i64::from(HostError::InvalidArgument.code())
+        );
+        assert_eq!(
+            http_response_body(&env, 1, WasmPtr::new(0), 4),
+            HostError::NotFound.code()
+        );
+
+        let url = "not a url";
+        env.write_memory(0, url.as_bytes());
+        let request = |method| {
+            let url_len = url.len() as u32;
+            http_request(&env, method, WasmPtr::new(0), url_len, WasmPtr::new(0), 0)
+        };
+        // Unknown method.
+        assert_eq!(request(0), i64::from(HostError::InvalidArgument.code()));
+        assert!(request(1) > 0);
+    }
+
+    #[test]
+    fn test_set_and_cancel_timer() {
+        let env = GersEnv::for_test();
+
+        let id = set_timer(&env, 10, 0);
+        assert!(id > 0);
+        let fired = env
+            .timers
+            .lock()
+            .unwrap()
+            .advance(Duration::from_millis(10));
+        assert_eq!(fired[0].1.timer_id, id as u64);
+
+        // Fired one-shot timers can't be cancelled.
+        assert_eq!(cancel_timer(&env, id as u64), 0);
+        let id = set_timer(&env, 10, 1);
+        assert_eq!(cancel_timer(&env, id as u64), 1);
+    }
+
+    #[test]
+    fn test_instances_own_their_timers() {
+        let root = std::env::temp_dir().join(format!("gers-instances-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join(gers_plugins::PLUGIN_FILENAME),
+            "name = \"spawner\"\nversion = \"1.0.0\"\n\
+             [[instance]]\nname = \"wolf\"\n[[instance]]\nname = \"bat\"\n",
+        )
+        .unwrap();
+        let mut plugins = gers_plugins::Plugins::new();
+        plugins.load_plugin_dir(&root).unwrap();
+
+        // The instances share their root, and each sets a timer.
+        let env = GersEnv::for_test();
+        let envs: Vec<_> = plugins
+            .iter_plugins()
+            .map(|plugin| {
+                env.for_plugin(&gers_plugins::PluginContext {
+                    meta: plugin.meta(),
+                    key: plugin.key(),
+                    source: plugin.source(),
+                })
+            })
+            .collect();
+        let timers: Vec<_> = envs
+            .iter()
+            .map(|env| (env.plugin.key.clone(), set_timer(env, 10, 1) as u64))
+            .collect();
+        assert_ne!(timers[0].0, timers[1].0);
+
+        // Each timer fires to the instance that set it.
+        let fired: Vec<_> = env
+            .timers
+            .lock()
+            .unwrap()
+            .advance(Duration::from_millis(10))
+            .into_iter()
+            .map(|(owner, event)| (owner, event.timer_id))
+            .collect();
+        assert_eq!(fired, timers);
+
+        // Instances can't cancel each other's timers.
+        assert_eq!(cancel_timer(&envs[1], timers[0].1), 0);
+        assert_eq!(cancel_timer(&envs[0], timers[0].1), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_event_subscribe_and_consume() {
+        let env = GersEnv::for_test();
+
+        assert_eq!(
+            event_subscribe(&env, 0, 0, 0),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(
+            event_subscribe(&env, 2, 10, gers_events::SUBSCRIBE_CONSUME),
+            0
+        );
+        assert_eq!(event_consume(&env), 0);
+
+        env.hooks.lock().unwrap().begin_dispatch(EventType::Action);
+        assert_eq!(event_consume(&env), 1);
+        assert!(env.hooks.lock().unwrap().is_consumed());
+    }
+
+    #[test]
+    fn test_publish_custom_event() {
+        use crate::custom_events::CustomEvents;
+        use gers_plugins::EventManifest;
+
+        let env = GersEnv::for_test();
+        let manifest = EventManifest {
+            defines: vec!["score_changed".to_string()],
+            handles: vec![],
+        };
+        let (custom_events, _) =
+            CustomEvents::from_manifests(&[(env.plugin.key.as_path(), &manifest)]);
+        *env.custom_events.write().unwrap() = custom_events;
+        env.write_memory(32, b"score_changed");
+        env.write_memory(64, &120u32.to_le_bytes());
+
+        assert_eq!(event_custom_id(&env, WasmPtr::new(32), 13), 1);
+        assert_eq!(
+            event_custom_id(&env, WasmPtr::new(32), 5),
+            HostError::NotFound.code()
+        );
+
+        assert_eq!(
+            event_publish(&env, WasmPtr::new(32), 13, WasmPtr::new(64), 4),
+            0
+        );
+        assert_eq!(
+            event_publish(&env, WasmPtr::new(32), 5, WasmPtr::new(64), 4),
+            HostError::NotFound.code()
+        );
+        assert_eq!(
+            event_publish(&env, WasmPtr::new(32), 13, WasmPtr::new(64), 4096),
+            HostError::InvalidArgument.code()
+        );
+
+        let events = env.emitted_events.lock().unwrap();
+        match events[..] {
+            [(source, FrameEvent::Custom(ref event))] => {
+                assert_eq!(source, plugin_id("test"));
+                assert_eq!(event.type_id, 1);
+                assert_eq!(event.data(), 120u32.to_le_bytes());
+            }
+            _ => panic!("unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_emit_custom_event() {
+        let env = GersEnv::for_test();
+        env.write_memory(32, b"boss_killed");
+        env.write_memory(64, &9u32.to_le_bytes());
+
+        assert_eq!(event_subscribe_custom(&env, WasmPtr::new(32), 11), 1);
+        assert_eq!(
+            event_emit(&env, WasmPtr::new(32), 11, WasmPtr::new(64), 4),
+            0
+        );
+        assert_eq!(
+            event_emit(&env, WasmPtr::new(32), 0, WasmPtr::new(64), 4),
+            HostError::InvalidArgument.code()
+        );
+
+        let events = env.emitted_events.lock().unwrap();
+        match events[..] {
+            [(_, FrameEvent::Custom(ref event))] => {
+                assert_eq!(event.type_id, 1);
+                assert_eq!(event.data(), 9u32.to_le_bytes());
+                assert!(env
+                    .custom_events
+                    .read()
+                    .unwrap()
+                    .is_handled_by(event.type_id, &env.plugin.key));
+            }
+            _ => panic!("unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_save_write_appends() {
+        let env = GersEnv::for_test();
+        env.write_memory(32, b"level=3;hp=10");
+
+        assert_eq!(save_write(&env, WasmPtr::new(32), 8), 0);
+        assert_eq!(save_write(&env, WasmPtr::new(40), 5), 0);
+        assert_eq!(
+            save_write(&env, WasmPtr::new(u32::MAX - 4), 16),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(&env.save_buffer.lock().unwrap()[..], b"level=3;hp=10");
+    }
+
+    #[test]
+    fn test_window_imports() {
+        let env = GersEnv::for_test();
+        env.write_memory(64, b"tools");
+
+        let window = window_create(&env, WasmPtr::new(64), 5, 320, 240);
+        assert!(window > 0);
+        assert_eq!(
+            window_create(&env, WasmPtr::new(64), 5, 0, 240),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(
+            window_set_title(&env, window as u32, WasmPtr::new(64), 4),
+            0
+        );
+        assert_eq!(window_close(&env, window as u32), 0);
+        assert_eq!(
+            window_close(&env, window as u32),
+            HostError::NotFound.code()
+        );
+        assert_eq!(env.windows.lock().unwrap().take_commands().len(), 3);
+    }
+
+    #[test]
+    fn test_main_window_imports() {
+        let env = GersEnv::for_test();
+
+        assert_eq!(window_set_size(&env, MAIN_WINDOW, 1280, 720), 0);
+        assert_eq!(
+            window_set_size(&env, MAIN_WINDOW, 1280, 0),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(window_set_fullscreen(&env, MAIN_WINDOW, 1), 0);
+        assert_eq!(window_set_cursor_visible(&env, MAIN_WINDOW, 0), 0);
+        assert_eq!(
+            window_set_cursor_visible(&env, 42, 0),
+            HostError::NotFound.code()
+        );
+        assert_eq!(
+            window_screenshot(&env, 42),
+            HostError::InvalidArgument.code()
+        );
+        assert_eq!(window_screenshot(&env, MAIN_WINDOW), 0);
+        assert!(env.windows.lock().unwrap().take_screenshot_request());
+        assert!(!env.windows.lock().unwrap().take_screenshot_request());
+        assert_eq!(
+            env.windows.lock().unwrap().take_commands(),
+            [
+                WindowCommand::SetSize(MAIN_WINDOW, 1280, 720),
+                WindowCommand::SetFullscreen(MAIN_WINDOW, true),
+                WindowCommand::SetCursorVisible(MAIN_WINDOW, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_console_imports() {
+        let env = GersEnv::for_test();
+        env.write_memory(32, b"godmode");
+
+        let id = console_register(&env, WasmPtr::new(32), 3, WasmPtr::new(32), 7);
+        assert!(id > 0);
+        assert_eq!(
+            console_register(&env, WasmPtr::new(32), 0, WasmPtr::new(32), 0),
+            HostError::InvalidArgument.code()
+        );
+        // Only while the command is handled.
+        assert_eq!(
+            console_args(&env, WasmPtr::new(64), 16),
+            HostError::NotFound.code()
+        );
+
+        let invocation = match env.console.lock().unwrap().parse("god on").unwrap() {
+            ConsoleInput::Command(invocation) => invocation,
+            _ => panic!("expected a command"),
+        };
+        env.console.lock().unwrap().begin_invocation(&invocation);
+        assert_eq!(console_args(&env, WasmPtr::new(64), 16), 2);
+        assert_eq!(env.read_memory(64, 2), b"on");
+    }
+
+    #[test]
+    fn test_i18n_imports() {
+        use crate::i18n::{Localization, LANG_DIR};
+
+        let root = std::env::temp_dir().join(format!("gers-i18n-imports-{}", std::process::id()));
+        fs::create_dir_all(root.join(LANG_DIR)).unwrap();
+        fs::write(
+            root.join(LANG_DIR).join("de.toml"),
+            "greeting = \"Hallo\"\n",
+        )
+        .unwrap();
+        let mut i18n = Localization::new("de", "en").unwrap();
+        i18n.load_plugin(&root, &PluginSource::Directory(root.clone()))
+            .unwrap();
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                key: root.clone(),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+        *env.i18n.write().unwrap() = i18n;
+        env.write_memory(32, b"greetingfarewell");
+
+        assert_eq!(i18n_get(&env, WasmPtr::new(32), 8, WasmPtr::new(64), 16), 5);
+        assert_eq!(env.read_memory(64, 5), b"Hallo");
+        assert_eq!(
+            i18n_get(&env, WasmPtr::new(40), 8, WasmPtr::new(64), 16),
+            HostError::NotFound.code()
+        );
+        assert_eq!(i18n_locale(&env, WasmPtr::new(64), 16), 2);
+        assert_eq!(env.read_memory(64, 2), b"de");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_settings_imports() {
+        use gers_events::SettingKind;
+        use gers_plugins::SettingDecl;
+
+        let decls = [
+            (
+                "difficulty",
+                SettingDecl::Int {
+                    default: 2,
+                    min: Some(0),
+                    max: Some(5),
+                },
+            ),
+            (
+                "greeting",
+                SettingDecl::String {
+                    default: "Hello".to_string(),
+                },
+            ),
+        ];
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                name: "test".to_string(),
+                settings: decls
+                    .iter()
+                    .map(|(key, decl)| (key.to_string(), decl.clone()))
+                    .collect(),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+        env.write_memory(32, b"difficultygreetingvolume");
+        let (difficulty, greeting, volume) = (WasmPtr::new(32), WasmPtr::new(42), WasmPtr::new(50));
+
+        assert_eq!(settings_kind(&env, difficulty, 10), SettingKind::Int as i32);
+        assert_eq!(settings_kind(&env, volume, 6), HostError::NotFound.code());
+        assert_eq!(settings_get_int(&env, difficulty, 10), 2);
+
+        env.settings
+            .write()
+            .unwrap()
+            .set("test", "difficulty", &decls[0].1, SettingValue::Int(4))
+            .unwrap();
+        assert_eq!(settings_get_int(&env, difficulty, 10), 4);
+        assert_eq!(
+            settings_get_bool(&env, difficulty, 10),
+            HostError::InvalidArgument.code()
+        );
+
+        assert_eq!(
+            settings_get_string(&env, greeting, 8, WasmPtr::new(64), 3),
+            5
+        );
+        assert_eq!(env.read_memory(64, 3), b"Hel");
+        assert_eq!(
+            settings_get_string(&env, volume, 6, WasmPtr::new(64), 16),
+            HostError::NotFound.code()
+        );
+    }
+
+    #[test]
+    fn test_task_imports() {
+        use gers_events::TaskKind;
+        use std::time::Instant;
+
+        let root = std::env::temp_dir().join(format!("gers-task-imports-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("data.txt"), b"abc").unwrap();
+        let env = GersEnv {
+            plugin: Arc::new(PluginScope {
+                key: root.clone(),
+                source: Some(PluginSource::Directory(root.clone())),
+                ..Default::default()
+            }),
+            ..GersEnv::for_test()
+        };
+        env.write_memory(0, b"data.txt");
+
+        assert_eq!(
+            task_spawn(&env, 0, WasmPtr::new(0), 8),
+            HostError::InvalidArgument.code()
+        );
+        let id = task_spawn(&env, TaskKind::HashFile as u32, WasmPtr::new(0), 8);
+        assert!(id > 0);
+        assert_eq!(
+            task_result(&env, id as u32, WasmPtr::new(64), 32),
+            HostError::NotFound.code()
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while env.tasks.lock().unwrap().poll().is_empty() {
+            assert!(Instant::now() < deadline, "task timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // Only the part of the hash that fits is copied.
+        assert_eq!(task_result(&env, id as u32, WasmPtr::new(64), 4), 32);
+        assert_eq!(env.read_memory(64, 4), [0xba, 0x78, 0x16, 0xbf]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // The OS clipboard may not be reachable where tests run.
+    #[cfg(not(feature = "clipboard"))]
+    #[test]
+    fn test_clipboard_imports() {
+        let env = GersEnv::for_test();
+        assert_eq!(
+            clipboard_get_text(&env, WasmPtr::new(64), 16),
+            HostError::NotFound.code()
+        );
+
+        env.write_memory(32, b"copied text");
+        assert_eq!(clipboard_set_text(&env, WasmPtr::new(32), 11), 0);
+
+        // Only the part that fits is copied.
+        assert_eq!(clipboard_get_text(&env, WasmPtr::new(64), 6), 11);
+        assert_eq!(env.read_memory(64, 7), b"copied\0");
+    }
+
+    #[test]
+    fn test_debug_watch_imports() {
+        let env = GersEnv::for_test();
+        env.write_memory(0, b"hp");
+        env.write_memory(64, &12_u32.to_le_bytes());
+
+        assert_eq!(debug_watch(&env, WasmPtr::new(0), 2, 64, 2), 0);
+        assert_eq!(
+            debug_watch(&env, WasmPtr::new(0), 2, 64, 0),
+            HostError::InvalidArgument.code()
+        );
+        let memory = env.memory.get_ref().unwrap();
+        let read = env.watches.lock().unwrap().read(&env.plugin.key, memory);
+        assert_eq!(read, [("hp".to_string(), "12".to_string())]);
+
+        assert_eq!(debug_unwatch(&env, WasmPtr::new(0), 2), 0);
+        assert_eq!(
+            debug_unwatch(&env, WasmPtr::new(0), 2),
+            HostError::NotFound.code()
+        );
+        assert!(env.watches.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_time_scale() {
+        let env = GersEnv::for_test();
+
+        assert_eq!(set_time_scale(&env, 0.5), 0);
+        assert_eq!(set_time_scale(&env, 0.0), 0);
+        for time_scale in [-1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(
+                set_time_scale(&env, time_scale),
+                HostError::InvalidArgument.code()
+            );
+        }
+        assert_eq!(env.timing.read().unwrap().time_scale, 0.0);
+    }
+}
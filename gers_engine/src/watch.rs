@@ -0,0 +1,268 @@
+//! Values in plugins' memories watched while debugging.
+//!
+//! Plugins register named watches on values in their memory, with the
+//! address and type of each. The host reads them every frame the debug
+//! overlay is shown, and when the console's `watch` command is typed,
+//! so mod authors can follow their state live without logging it.
+//!
+//! Watches only keep the address, so the value has to stay in place,
+//! like in a static or a box. Addresses outside of the memory read as
+//! `?`, rather than failing the plugin.
+use gers_events::WatchType;
+use gers_plugins::strings;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use wasmer::{Memory, WasmPtr};
+
+/// Default number of values a single plugin may watch.
+pub const DEFAULT_WATCH_LIMIT: usize = 32;
+
+/// Watches of all plugins, in the order they were registered.
+pub struct Watches {
+    watches: Vec<Watch>,
+    watch_limit: usize,
+}
+
+/// Current values of a plugin's watches, by name.
+pub struct PluginWatches {
+    pub plugin: String,
+    pub values: Vec<(String, String)>,
+}
+
+impl PluginWatches {
+    /// A line for each value.
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.values
+            .iter()
+            .map(move |(name, value)| format!("{} {} = {}", self.plugin, name, value))
+    }
+}
+
+struct Watch {
+    /// Key of the plugin that registered the watch.
+    owner: PathBuf,
+    name: String,
+    ptr: u32,
+    ty: WatchType,
+}
+
+#[derive(Debug)]
+pub enum WatchError {
+    WatchLimit(usize),
+    /// Names can't be empty or contain line breaks.
+    InvalidName(String),
+    UnknownType(u32),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::WatchLimit(limit) => write!(f, "plugin watch limit reached: {}", limit),
+            WatchError::InvalidName(name) => write!(f, "invalid watch name: {:?}", name),
+            WatchError::UnknownType(type_id) => write!(f, "unknown watch type: {}", type_id),
+        }
+    }
+}
+
+impl Default for Watches {
+    fn default() -> Self {
+        Self {
+            watches: vec![],
+            watch_limit: DEFAULT_WATCH_LIMIT,
+        }
+    }
+}
+
+impl Watches {
+    /// Watch the value at `ptr` on behalf of the plugin at `owner`.
+    ///
+    /// Watching a name again moves the watch to the new address and type.
+    pub fn watch(
+        &mut self,
+        owner: &Path,
+        name: String,
+        ptr: u32,
+        type_id: u32,
+    ) -> Result<(), WatchError> {
+        if name.is_empty() || name.contains(['\n', '\r']) {
+            return Err(WatchError::InvalidName(name));
+        }
+        let ty = match WatchType::from(type_id) {
+            WatchType::Unknown => return Err(WatchError::UnknownType(type_id)),
+            ty => ty,
+        };
+
+        if let Some(watch) = self
+            .watches
+            .iter_mut()
+            .find(|watch| watch.owner == owner && watch.name == name)
+        {
+            watch.ptr = ptr;
+            watch.ty = ty;
+            return Ok(());
+        }
+
+        let watched = self
+            .watches
+            .iter()
+            .filter(|watch| watch.owner == owner)
+            .count();
+        if watched >= self.watch_limit {
+            return Err(WatchError::WatchLimit(self.watch_limit));
+        }
+
+        self.watches.push(Watch {
+            owner: owner.to_path_buf(),
+            name,
+            ptr,
+            ty,
+        });
+
+        Ok(())
+    }
+
+    /// Stop watching a value of the plugin at `owner`.
+    ///
+    /// Returns `false` when it wasn't watched.
+    pub fn unwatch(&mut self, owner: &Path, name: &str) -> bool {
+        let count = self.watches.len();
+        self.watches
+            .retain(|watch| watch.owner != owner || watch.name != name);
+        count != self.watches.len()
+    }
+
+    /// Stop watching every value of the plugin at `owner`.
+    ///
+    /// Returns the number of watches removed.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let count = self.watches.len();
+        self.watches.retain(|watch| watch.owner != owner);
+        count - self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Names and current values of the watches of the plugin at `owner`,
+    /// read from its memory.
+    pub fn read(&self, owner: &Path, memory: &Memory) -> Vec<(String, String)> {
+        self.watches
+            .iter()
+            .filter(|watch| watch.owner == owner)
+            .map(|watch| {
+                let bytes = strings::read_bytes(memory, WasmPtr::new(watch.ptr), watch.ty.size());
+                let value = bytes
+                    .and_then(|bytes| format_value(watch.ty, &bytes))
+                    .unwrap_or_else(|| "?".to_string());
+                (watch.name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+/// Value of the type in little endian bytes.
+fn format_value(ty: WatchType, bytes: &[u8]) -> Option<String> {
+    let value = match ty {
+        WatchType::Unknown => return None,
+        WatchType::I32 => i32::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::U32 => u32::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::I64 => i64::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::U64 => u64::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::F32 => f32::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::F64 => f64::from_le_bytes(bytes.try_into().ok()?).to_string(),
+        WatchType::Bool => (*bytes.first()? != 0).to_string(),
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::{MemoryType, Store};
+
+    #[test]
+    fn test_read_watches() {
+        let memory = Memory::new(&Store::default(), MemoryType::new(1, None, false)).unwrap();
+        let bytes = [(-5_i32).to_le_bytes(), 2.5_f32.to_le_bytes(), [1, 0, 0, 0]].concat();
+        let view = memory.view::<u8>();
+        for (cell, byte) in view[64..].iter().zip(bytes) {
+            cell.set(byte);
+        }
+
+        let (plugin, other) = (Path::new("plugin"), Path::new("other"));
+        let mut watches = Watches::default();
+        watches
+            .watch(plugin, "hp".to_string(), 64, WatchType::I32 as u32)
+            .unwrap();
+        watches
+            .watch(plugin, "speed".to_string(), 68, WatchType::F32 as u32)
+            .unwrap();
+        watches
+            .watch(plugin, "alive".to_string(), 72, WatchType::Bool as u32)
+            .unwrap();
+        watches
+            .watch(
+                plugin,
+                "far".to_string(),
+                u32::MAX - 2,
+                WatchType::U64 as u32,
+            )
+            .unwrap();
+        watches
+            .watch(other, "hp".to_string(), 64, WatchType::U32 as u32)
+            .unwrap();
+
+        let value = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            watches.read(plugin, &memory),
+            [
+                value("hp", "-5"),
+                value("speed", "2.5"),
+                value("alive", "true"),
+                value("far", "?"),
+            ]
+        );
+        assert_eq!(watches.read(other, &memory), [value("hp", "4294967291")]);
+
+        // Watching a name again moves the watch.
+        watches
+            .watch(plugin, "hp".to_string(), 68, WatchType::F32 as u32)
+            .unwrap();
+        assert_eq!(watches.read(plugin, &memory)[0], value("hp", "2.5"));
+
+        assert!(watches.unwatch(plugin, "far"));
+        assert!(!watches.unwatch(plugin, "far"));
+        assert_eq!(watches.release(plugin), 3);
+        assert!(watches.read(plugin, &memory).is_empty());
+        assert!(!watches.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_watches() {
+        let owner = Path::new("plugin");
+        let mut watches = Watches {
+            watch_limit: 1,
+            ..Watches::default()
+        };
+
+        assert!(matches!(
+            watches.watch(owner, "a\nb".to_string(), 0, WatchType::I32 as u32),
+            Err(WatchError::InvalidName(_))
+        ));
+        assert!(matches!(
+            watches.watch(owner, "hp".to_string(), 0, 99),
+            Err(WatchError::UnknownType(99))
+        ));
+        watches
+            .watch(owner, "hp".to_string(), 0, WatchType::I32 as u32)
+            .unwrap();
+        assert!(matches!(
+            watches.watch(owner, "mp".to_string(), 4, WatchType::I32 as u32),
+            Err(WatchError::WatchLimit(1))
+        ));
+    }
+}
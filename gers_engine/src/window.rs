@@ -0,0 +1,425 @@
+//! Windows opened by plugins.
+//!
+//! Windows can only be created on the thread running the event loop,
+//! while it hands out its window target, so the host imports queue
+//! commands, which are applied at the end of the frame. Window handles
+//! are handed out when the command is queued, so plugins can use them
+//! straight away. Plugins need the `window` permission, and may only
+//! have a limited number of windows open at the same time.
+//!
+//! Events of the windows, like closing or resizing, are sent as
+//! `WindowEvent` to the plugin that opened the window. Windows opened
+//! by plugins aren't rendered to.
+//!
+//! The main window has the handle `MAIN_WINDOW`. Any plugin with the
+//! permission can change it, or take a screenshot of it, but it can't
+//! be closed.
+use gers_events::{WindowEvent, WindowEventKind};
+use slog::Logger;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+use winit::{
+    dpi::LogicalSize,
+    event::WindowEvent as WinitEvent,
+    event_loop::EventLoopWindowTarget,
+    window::{Fullscreen, Window, WindowBuilder, WindowId},
+};
+
+pub type WindowHandle = u32;
+
+/// Handle of the main window.
+pub const MAIN_WINDOW: WindowHandle = 0;
+
+/// Default number of windows a single plugin may have open.
+pub const DEFAULT_WINDOW_LIMIT: usize = 4;
+
+/// Windows requested by plugins, shared with the host imports.
+pub struct WindowRequests {
+    /// Key of the plugin that opened each window.
+    owners: HashMap<WindowHandle, PathBuf>,
+    next_handle: WindowHandle,
+    window_limit: usize,
+    commands: Vec<WindowCommand>,
+    /// A screenshot of the main window is taken when it's next rendered.
+    screenshot: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WindowCommand {
+    Create {
+        handle: WindowHandle,
+        title: String,
+        width: u32,
+        height: u32,
+    },
+    Close(WindowHandle),
+    SetTitle(WindowHandle, String),
+    SetSize(WindowHandle, u32, u32),
+    SetFullscreen(WindowHandle, bool),
+    SetCursorVisible(WindowHandle, bool),
+}
+
+#[derive(Debug)]
+pub enum WindowError {
+    WindowLimit(usize),
+    UnknownWindow(WindowHandle),
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowError::WindowLimit(limit) => write!(f, "plugin window limit reached: {}", limit),
+            WindowError::UnknownWindow(handle) => write!(f, "unknown window handle: {}", handle),
+        }
+    }
+}
+
+impl Default for WindowRequests {
+    fn default() -> Self {
+        Self {
+            owners: HashMap::new(),
+            // Zero is the main window.
+            next_handle: MAIN_WINDOW + 1,
+            window_limit: DEFAULT_WINDOW_LIMIT,
+            commands: vec![],
+            screenshot: false,
+        }
+    }
+}
+
+impl WindowRequests {
+    /// Open a window on behalf of the plugin at `owner`.
+    pub fn create(
+        &mut self,
+        owner: &Path,
+        title: String,
+        width: u32,
+        height: u32,
+    ) -> Result<WindowHandle, WindowError> {
+        let open_count = self.owners.values().filter(|root| *root == owner).count();
+        if open_count >= self.window_limit {
+            return Err(WindowError::WindowLimit(self.window_limit));
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.owners.insert(handle, owner.to_path_buf());
+        self.commands.push(WindowCommand::Create {
+            handle,
+            title,
+            width,
+            height,
+        });
+
+        Ok(handle)
+    }
+
+    /// Close a window. Plugins can only close the windows they opened.
+    pub fn close(&mut self, owner: &Path, handle: WindowHandle) -> Result<(), WindowError> {
+        if handle == MAIN_WINDOW {
+            return Err(WindowError::UnknownWindow(handle));
+        }
+        self.check_owner(owner, handle)?;
+        self.owners.remove(&handle);
+        self.commands.push(WindowCommand::Close(handle));
+
+        Ok(())
+    }
+
+    pub fn set_title(
+        &mut self,
+        owner: &Path,
+        handle: WindowHandle,
+        title: String,
+    ) -> Result<(), WindowError> {
+        self.check_owner(owner, handle)?;
+        self.commands.push(WindowCommand::SetTitle(handle, title));
+
+        Ok(())
+    }
+
+    /// Resize the inner size of a window, in logical pixels.
+    pub fn set_size(
+        &mut self,
+        owner: &Path,
+        handle: WindowHandle,
+        width: u32,
+        height: u32,
+    ) -> Result<(), WindowError> {
+        self.check_owner(owner, handle)?;
+        self.commands
+            .push(WindowCommand::SetSize(handle, width, height));
+
+        Ok(())
+    }
+
+    /// Switch a window between borderless fullscreen and windowed.
+    pub fn set_fullscreen(
+        &mut self,
+        owner: &Path,
+        handle: WindowHandle,
+        fullscreen: bool,
+    ) -> Result<(), WindowError> {
+        self.check_owner(owner, handle)?;
+        self.commands
+            .push(WindowCommand::SetFullscreen(handle, fullscreen));
+
+        Ok(())
+    }
+
+    /// Show or hide the cursor while it's over a window.
+    pub fn set_cursor_visible(
+        &mut self,
+        owner: &Path,
+        handle: WindowHandle,
+        visible: bool,
+    ) -> Result<(), WindowError> {
+        self.check_owner(owner, handle)?;
+        self.commands
+            .push(WindowCommand::SetCursorVisible(handle, visible));
+
+        Ok(())
+    }
+
+    /// Take a screenshot of the main window when it's next rendered.
+    ///
+    /// Requests made before it's rendered are taken together.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot = true;
+    }
+
+    /// Whether a screenshot was requested since the last call.
+    pub fn take_screenshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot)
+    }
+
+    /// Close every window the plugin at `owner` opened.
+    ///
+    /// Returns the number of windows closed.
+    pub fn release(&mut self, owner: &Path) -> usize {
+        let mut handles: Vec<WindowHandle> = self
+            .owners
+            .iter()
+            .filter(|(_, root)| *root == owner)
+            .map(|(handle, _)| *handle)
+            .collect();
+        handles.sort_unstable();
+        for handle in handles.iter() {
+            self.owners.remove(handle);
+            self.commands.push(WindowCommand::Close(*handle));
+        }
+
+        handles.len()
+    }
+
+    /// Key of the plugin that opened the window.
+    pub fn owner(&self, handle: WindowHandle) -> Option<&Path> {
+        self.owners.get(&handle).map(PathBuf::as_path)
+    }
+
+    /// Take the commands queued since the last call, in order.
+    pub fn take_commands(&mut self) -> Vec<WindowCommand> {
+        std::mem::take(&mut self.commands)
+    }
+
+    fn check_owner(&self, owner: &Path, handle: WindowHandle) -> Result<(), WindowError> {
+        if handle == MAIN_WINDOW {
+            return Ok(());
+        }
+
+        match self.owners.get(&handle) {
+            Some(root) if root == owner => Ok(()),
+            _ => Err(WindowError::UnknownWindow(handle)),
+        }
+    }
+}
+
+/// Windows opened for plugins, owned by the event loop's thread.
+#[derive(Default)]
+pub struct WindowRegistry {
+    windows: HashMap<WindowHandle, Window>,
+    handles: HashMap<WindowId, WindowHandle>,
+}
+
+impl WindowRegistry {
+    /// Apply queued commands. `main` is the window `MAIN_WINDOW` refers to.
+    pub fn apply<T>(
+        &mut self,
+        commands: Vec<WindowCommand>,
+        main: &Window,
+        target: &EventLoopWindowTarget<T>,
+        logger: &Logger,
+    ) {
+        for command in commands {
+            match command {
+                WindowCommand::Create {
+                    handle,
+                    title,
+                    width,
+                    height,
+                } => {
+                    let result = WindowBuilder::new()
+                        .with_title(title)
+                        .with_inner_size(LogicalSize::new(width, height))
+                        .build(target);
+                    match result {
+                        Ok(window) => {
+                            self.handles.insert(window.id(), handle);
+                            self.windows.insert(handle, window);
+                        }
+                        Err(err) => slog::error!(logger, "failed to create window: {}", err),
+                    }
+                }
+                WindowCommand::Close(handle) => {
+                    if let Some(window) = self.windows.remove(&handle) {
+                        self.handles.remove(&window.id());
+                    }
+                }
+                WindowCommand::SetTitle(handle, title) => {
+                    if let Some(window) = self.window(main, handle) {
+                        window.set_title(&title);
+                    }
+                }
+                WindowCommand::SetSize(handle, width, height) => {
+                    if let Some(window) = self.window(main, handle) {
+                        window.set_inner_size(LogicalSize::new(width, height));
+                    }
+                }
+                WindowCommand::SetFullscreen(handle, fullscreen) => {
+                    if let Some(window) = self.window(main, handle) {
+                        // Borderless, on the monitor the window is on.
+                        window.set_fullscreen(fullscreen.then(|| Fullscreen::Borderless(None)));
+                    }
+                }
+                WindowCommand::SetCursorVisible(handle, visible) => {
+                    if let Some(window) = self.window(main, handle) {
+                        window.set_cursor_visible(visible);
+                    }
+                }
+            }
+        }
+    }
+
+    fn window<'a>(&'a self, main: &'a Window, handle: WindowHandle) -> Option<&'a Window> {
+        if handle == MAIN_WINDOW {
+            Some(main)
+        } else {
+            self.windows.get(&handle)
+        }
+    }
+
+    /// Handle of a window opened for a plugin.
+    pub fn handle(&self, window_id: WindowId) -> Option<WindowHandle> {
+        self.handles.get(&window_id).copied()
+    }
+}
+
+/// Plugin event for a winit event of a plugin's window, if it's one
+/// plugins receive.
+pub fn window_event(handle: WindowHandle, event: &WinitEvent) -> Option<WindowEvent> {
+    let (kind, width, height) = match event {
+        WinitEvent::CloseRequested => (WindowEventKind::CloseRequested, 0, 0),
+        WinitEvent::Resized(size) => (WindowEventKind::Resized, size.width, size.height),
+        WinitEvent::Focused(true) => (WindowEventKind::Focused, 0, 0),
+        WinitEvent::Focused(false) => (WindowEventKind::Unfocused, 0, 0),
+        _ => return None,
+    };
+
+    Some(WindowEvent {
+        window: handle,
+        kind: kind as u32,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_are_owned() {
+        let (owner, other) = (Path::new("plugin"), Path::new("other"));
+        let mut requests = WindowRequests::default();
+
+        let handle = requests
+            .create(owner, "tools".to_string(), 320, 240)
+            .unwrap();
+        assert_eq!(requests.owner(handle), Some(owner));
+        assert!(matches!(
+            requests.set_title(other, handle, "mine".to_string()),
+            Err(WindowError::UnknownWindow(_))
+        ));
+        assert!(requests.close(other, handle).is_err());
+
+        requests
+            .set_title(owner, handle, "editor".to_string())
+            .unwrap();
+        requests.close(owner, handle).unwrap();
+        assert_eq!(requests.owner(handle), None);
+        assert!(requests.close(owner, handle).is_err());
+
+        assert_eq!(
+            requests.take_commands(),
+            [
+                WindowCommand::Create {
+                    handle,
+                    title: "tools".to_string(),
+                    width: 320,
+                    height: 240,
+                },
+                WindowCommand::SetTitle(handle, "editor".to_string()),
+                WindowCommand::Close(handle),
+            ]
+        );
+        assert!(requests.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_window_limit() {
+        let owner = Path::new("plugin");
+        let mut requests = WindowRequests::default();
+
+        for _ in 0..DEFAULT_WINDOW_LIMIT {
+            requests.create(owner, String::new(), 1, 1).unwrap();
+        }
+        assert!(matches!(
+            requests.create(owner, String::new(), 1, 1),
+            Err(WindowError::WindowLimit(DEFAULT_WINDOW_LIMIT))
+        ));
+        // The limit is per plugin.
+        requests
+            .create(Path::new("other"), String::new(), 1, 1)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_main_window() {
+        let owner = Path::new("plugin");
+        let mut requests = WindowRequests::default();
+
+        requests.set_size(owner, MAIN_WINDOW, 800, 600).unwrap();
+        requests.set_fullscreen(owner, MAIN_WINDOW, true).unwrap();
+        requests
+            .set_cursor_visible(owner, MAIN_WINDOW, false)
+            .unwrap();
+        assert!(requests.close(owner, MAIN_WINDOW).is_err());
+        assert_eq!(requests.owner(MAIN_WINDOW), None);
+
+        assert_eq!(
+            requests.take_commands(),
+            [
+                WindowCommand::SetSize(MAIN_WINDOW, 800, 600),
+                WindowCommand::SetFullscreen(MAIN_WINDOW, true),
+                WindowCommand::SetCursorVisible(MAIN_WINDOW, false),
+            ]
+        );
+
+        // Handles of plugin windows never refer to the main window.
+        let handle = requests.create(owner, String::new(), 1, 1).unwrap();
+        assert_ne!(handle, MAIN_WINDOW);
+    }
+}
@@ -1,17 +1,231 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EventType {
     NoOp = 0,
     Hello = 1,
+    Action = 2,
+    GamepadButton = 3,
+    GamepadAxis = 4,
+    HttpResponse = 5,
+    TimerFired = 6,
+    Damage = 7,
+    ShutdownRequested = 8,
+    AppPaused = 9,
+    AppResumed = 10,
+    Window = 11,
+    ConsoleCommand = 12,
+    Custom = 13,
+    SettingsChanged = 14,
+    LocaleChanged = 15,
+    TaskCompleted = 16,
+    SimulationLagging = 17,
+    ScreenshotTaken = 18,
+    SimulationState = 19,
+    MouseMove = 20,
 }
 
 impl From<i32> for EventType {
     fn from(value: i32) -> EventType {
         match value {
             1 => Self::Hello,
+            2 => Self::Action,
+            3 => Self::GamepadButton,
+            4 => Self::GamepadAxis,
+            5 => Self::HttpResponse,
+            6 => Self::TimerFired,
+            7 => Self::Damage,
+            8 => Self::ShutdownRequested,
+            9 => Self::AppPaused,
+            10 => Self::AppResumed,
+            11 => Self::Window,
+            12 => Self::ConsoleCommand,
+            13 => Self::Custom,
+            14 => Self::SettingsChanged,
+            15 => Self::LocaleChanged,
+            16 => Self::TaskCompleted,
+            17 => Self::SimulationLagging,
+            18 => Self::ScreenshotTaken,
+            19 => Self::SimulationState,
+            20 => Self::MouseMove,
             _ => Self::NoOp,
         }
     }
 }
 
+/// Priority class of an event type.
+///
+/// Within a frame, events are delivered by class, from lifecycle to
+/// gameplay, so plugins see input before the simulation reacts to it.
+/// Events of the same class keep the order they were raised in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventPriority {
+    /// Changes to the app and its windows.
+    Lifecycle = 0,
+    /// Input from the user.
+    Input = 1,
+    /// Simulation events, like fixed updates, timers and damage.
+    Gameplay = 2,
+}
+
+impl EventType {
+    /// Priority class the event type is delivered in.
+    pub fn priority(self) -> EventPriority {
+        match self {
+            Self::ShutdownRequested
+            | Self::AppPaused
+            | Self::AppResumed
+            | Self::Window
+            | Self::SettingsChanged
+            | Self::LocaleChanged
+            | Self::ScreenshotTaken
+            | Self::SimulationState => EventPriority::Lifecycle,
+            Self::Action
+            | Self::GamepadButton
+            | Self::GamepadAxis
+            | Self::MouseMove
+            | Self::ConsoleCommand => EventPriority::Input,
+            Self::NoOp
+            | Self::Hello
+            | Self::HttpResponse
+            | Self::TimerFired
+            | Self::Damage
+            | Self::Custom
+            | Self::TaskCompleted
+            | Self::SimulationLagging => EventPriority::Gameplay,
+        }
+    }
+}
+
+/// Data of an event type, as it's sent to plugins.
+pub trait Event {
+    const TYPE: EventType;
+
+    /// Hash of the event's memory layout, from its size, its alignment,
+    /// and the names, offsets and sizes of its fields.
+    ///
+    /// Hosts and guests built against different versions of the event,
+    /// or by compilers laying it out differently, have different hashes.
+    fn layout_hash() -> u64;
+}
+
+/// Layout hash of an event type, as guests list them in the value
+/// `__gers_event_layouts` returns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct EventLayout {
+    pub event_type: i32,
+    pub padding: u32,
+    pub hash: u64,
+}
+
+impl EventLayout {
+    pub fn of<T: Event>() -> Self {
+        Self {
+            event_type: T::TYPE as i32,
+            padding: 0,
+            hash: T::layout_hash(),
+        }
+    }
+}
+
+/// Computes `Event::layout_hash`, with 64 bit FNV-1a.
+#[doc(hidden)]
+pub struct LayoutHasher(u64);
+
+impl LayoutHasher {
+    pub fn new<T>(name: &str) -> Self {
+        let mut hasher = Self(0xcbf2_9ce4_8422_2325);
+        hasher.write(name.as_bytes());
+        hasher.write_usize(std::mem::size_of::<T>());
+        hasher.write_usize(std::mem::align_of::<T>());
+        hasher
+    }
+
+    /// Add the field at the address `field`, inside the event at `base`.
+    pub fn field<F>(&mut self, name: &str, base: *const u8, field: *const F) {
+        self.write(name.as_bytes());
+        self.write_usize(field as usize - base as usize);
+        self.write_usize(std::mem::size_of::<F>());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+
+    /// Sizes are hashed as 64 bits, so 32 and 64 bit targets agree.
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Implements `Event` for the event types, with their fields listed in
+/// the order they're declared.
+macro_rules! impl_event {
+    ($($event:ident => $event_type:ident { $($field:ident),* },)*) => {
+        $(
+            impl Event for $event {
+                const TYPE: EventType = EventType::$event_type;
+
+                fn layout_hash() -> u64 {
+                    // Fails to compile when a field is missing from the list.
+                    let _ = |event: &$event| {
+                        let $event { $($field: _),* } = event;
+                    };
+
+                    let event = std::mem::MaybeUninit::<$event>::uninit();
+                    let base = event.as_ptr();
+                    let mut hasher = LayoutHasher::new::<$event>(stringify!($event));
+                    $(
+                        // SAFETY: Only the address of the field is taken,
+                        //         the uninitialised event isn't read.
+                        let field = unsafe { std::ptr::addr_of!((*base).$field) };
+                        hasher.field(stringify!($field), base as *const u8, field);
+                    )*
+                    hasher.finish()
+                }
+            }
+        )*
+
+        /// Layouts of all event types, as the host expects them.
+        pub fn event_layouts() -> Vec<EventLayout> {
+            vec![$(EventLayout::of::<$event>()),*]
+        }
+    };
+}
+
+impl_event! {
+    HelloEvent => Hello { data, padding, div },
+    ActionEvent => Action { action_id, pressed, value },
+    GamepadButtonEvent => GamepadButton { device_id, button, pressed, value },
+    GamepadAxisEvent => GamepadAxis { device_id, axis, value },
+    HttpResponseEvent => HttpResponse { request_id, status, body_len },
+    TimerFiredEvent => TimerFired { timer_id },
+    DamageEvent => Damage { entity, source, amount, cancelled },
+    ShutdownRequestedEvent => ShutdownRequested { timeout_ms },
+    AppPausedEvent => AppPaused { reason },
+    AppResumedEvent => AppResumed { paused_ms },
+    WindowEvent => Window { window, kind, width, height },
+    ConsoleCommandEvent => ConsoleCommand { command_id, args_len },
+    CustomEvent => Custom { type_id, len, data },
+    SettingsChangedEvent => SettingsChanged { key_len, key },
+    LocaleChangedEvent => LocaleChanged { locale_len, locale },
+    TaskCompletedEvent => TaskCompleted { task_id, failed, result_len },
+    SimulationLaggingEvent => SimulationLagging { ticks_behind, dropped },
+    SimulationStateEvent => SimulationState { state },
+    MouseMoveEvent => MouseMove { device_id, x, y },
+}
+
+/// Subscription flag allowing the plugin to consume the event, so
+/// plugins with a lower priority don't receive it.
+pub const SUBSCRIBE_CONSUME: u32 = 1;
+
 /// Data for `Hello` event.
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -20,3 +234,972 @@ pub struct HelloEvent {
     pub padding: u8,
     pub div: u16,
 }
+
+/// Data for `Action` event.
+///
+/// Sent when device input bound to an action changes.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct ActionEvent {
+    pub action_id: u32,
+    pub pressed: bool,
+    /// Analog value of the input, `1.0` or `0.0` for buttons.
+    pub value: f32,
+}
+
+/// Data for `GamepadButton` event.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct GamepadButtonEvent {
+    /// Identifies the gamepad for as long as it stays connected.
+    pub device_id: u32,
+    /// See `GamepadButton`.
+    pub button: u32,
+    pub pressed: bool,
+    /// Analog value of the button, in the range `0.0` to `1.0`.
+    pub value: f32,
+}
+
+/// Data for `GamepadAxis` event.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct GamepadAxisEvent {
+    /// Identifies the gamepad for as long as it stays connected.
+    pub device_id: u32,
+    /// See `GamepadAxis`.
+    pub axis: u32,
+    /// Position of the axis, in the range `-1.0` to `1.0`.
+    pub value: f32,
+}
+
+/// Data for `MouseMove` event.
+///
+/// Sent when the cursor moves over the main window.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct MouseMoveEvent {
+    /// Identifies the mouse for as long as the app runs.
+    pub device_id: u32,
+    /// Position of the cursor, in pixels from the top left corner of
+    /// the window.
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Data for `HttpResponse` event.
+///
+/// Sent to the plugin that made the request once it completes.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct HttpResponseEvent {
+    /// Handle returned when the request was made.
+    pub request_id: u64,
+    /// HTTP status code, or `0` when the request failed
+    /// without a response.
+    pub status: u32,
+    /// Size of the response body in bytes.
+    pub body_len: u32,
+}
+
+/// Data for `TimerFired` event.
+///
+/// Sent to the plugin that set the timer when it's due.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct TimerFiredEvent {
+    /// Handle returned when the timer was set.
+    pub timer_id: u64,
+}
+
+/// Data for `TaskCompleted` event.
+///
+/// Sent to the plugin that spawned a background task once it's done.
+/// The result can be read with the task's `result` import while the
+/// event is handled.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct TaskCompletedEvent {
+    pub task_id: u32,
+    /// Non-zero when the task failed, and the result is the error message.
+    pub failed: u32,
+    /// Size of the result in bytes.
+    pub result_len: u32,
+}
+
+/// Data for `SimulationLagging` event.
+///
+/// Sent after the fixed updates of a frame when more were due than fit
+/// into the frame's dispatch budget.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct SimulationLaggingEvent {
+    /// Fixed updates that were due, but didn't run this frame.
+    pub ticks_behind: u32,
+    /// Non-zero when the updates were dropped, rather than carried
+    /// over to the next frames.
+    pub dropped: u32,
+}
+
+/// Data for `SimulationState` event.
+///
+/// Sent when the simulation is paused or resumed for debugging, and in
+/// each frame stepped while it's paused, before that frame's fixed
+/// update.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct SimulationStateEvent {
+    /// See `SimulationState`.
+    pub state: u32,
+}
+
+/// Data for `ScreenshotTaken` event.
+///
+/// Sent to every plugin once a screenshot of the main window was saved.
+/// Sent encoded, see `EncodedEvent`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenshotTakenEvent {
+    /// Path of the PNG file, relative to the working directory.
+    pub path: String,
+}
+
+impl EncodedEvent for ScreenshotTakenEvent {
+    const TYPE: EventType = EventType::ScreenshotTaken;
+}
+
+/// Operations the host runs on background threads for plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    Unknown = 0,
+    /// SHA-256 hash of a file in the plugin, given its path.
+    HashFile = 1,
+    /// Steps from every cell of a grid to a goal cell, moving between
+    /// neighbouring walkable cells.
+    ///
+    /// The data is the width, height, goal x and goal y of the grid as
+    /// little endian `u32`s, followed by a byte per cell, row by row,
+    /// which is zero for walkable cells. The result is a little endian
+    /// `u32` per cell, `u32::MAX` for cells that can't reach the goal.
+    DistanceField = 2,
+}
+
+impl From<u32> for TaskKind {
+    fn from(value: u32) -> TaskKind {
+        match value {
+            1 => Self::HashFile,
+            2 => Self::DistanceField,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Data for `ShutdownRequested` event.
+///
+/// Sent to all plugins when the app is about to quit, before their
+/// `__gers_shutdown` is called.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct ShutdownRequestedEvent {
+    /// Time all plugins share for shutting down, in milliseconds.
+    pub timeout_ms: u32,
+}
+
+/// Data for `AppPaused` event.
+///
+/// Sent when the window loses focus or is minimized.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct AppPausedEvent {
+    /// See `PauseReason`.
+    pub reason: u32,
+}
+
+/// Data for `AppResumed` event.
+///
+/// Sent when the window is focused and restored again.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct AppResumedEvent {
+    /// Time the app was paused, in milliseconds.
+    pub paused_ms: u32,
+}
+
+/// Data for `Window` event.
+///
+/// Sent to the plugin that opened a window when something
+/// happens to the window.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct WindowEvent {
+    /// Handle returned when the window was created.
+    pub window: u32,
+    /// See `WindowEventKind`.
+    pub kind: u32,
+    /// New inner size of the window, for `Resized`.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Data for `ConsoleCommand` event.
+///
+/// Sent to the plugin that registered the command when the user
+/// invokes it from the console. The arguments can be read with the
+/// console's `args` import while the event is handled.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct ConsoleCommandEvent {
+    pub command_id: u32,
+    /// Size of the arguments in bytes.
+    pub args_len: u32,
+}
+
+/// Maximum size of the data of a custom event in bytes.
+pub const CUSTOM_EVENT_DATA_SIZE: usize = 248;
+
+/// Data for `Custom` event.
+///
+/// Published by plugins under a name defined in a plugin's event
+/// manifest, and sent to the plugins whose manifest handles it.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct CustomEvent {
+    /// Identifies the custom event type while the plugins are loaded.
+    pub type_id: u32,
+    /// Size of the data in bytes.
+    pub len: u32,
+    pub data: [u8; CUSTOM_EVENT_DATA_SIZE],
+}
+
+impl CustomEvent {
+    /// Event with a copy of `data`, or `None` when the data is larger
+    /// than `CUSTOM_EVENT_DATA_SIZE`.
+    pub fn new(type_id: u32, data: &[u8]) -> Option<Self> {
+        if data.len() > CUSTOM_EVENT_DATA_SIZE {
+            return None;
+        }
+
+        let mut event = Self {
+            type_id,
+            len: data.len() as u32,
+            data: [0; CUSTOM_EVENT_DATA_SIZE],
+        };
+        event.data[..data.len()].copy_from_slice(data);
+        Some(event)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        let len = (self.len as usize).min(CUSTOM_EVENT_DATA_SIZE);
+        &self.data[..len]
+    }
+}
+
+/// Maximum size of the key of a plugin setting in bytes.
+pub const SETTING_KEY_SIZE: usize = 60;
+
+/// Data for `SettingsChanged` event.
+///
+/// Sent to a plugin when the user changes one of the settings declared
+/// in its `plugin.toml`. The new value is read with the settings imports.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct SettingsChangedEvent {
+    /// Size of the key in bytes.
+    pub key_len: u32,
+    pub key: [u8; SETTING_KEY_SIZE],
+}
+
+impl SettingsChangedEvent {
+    /// Event for the setting `key`, or `None` when the key is longer
+    /// than `SETTING_KEY_SIZE`.
+    pub fn new(key: &str) -> Option<Self> {
+        if key.len() > SETTING_KEY_SIZE {
+            return None;
+        }
+
+        let mut event = Self {
+            key_len: key.len() as u32,
+            key: [0; SETTING_KEY_SIZE],
+        };
+        event.key[..key.len()].copy_from_slice(key.as_bytes());
+        Some(event)
+    }
+
+    /// Key of the setting, or an empty string when it isn't valid UTF-8.
+    pub fn key(&self) -> &str {
+        let len = (self.key_len as usize).min(SETTING_KEY_SIZE);
+        std::str::from_utf8(&self.key[..len]).unwrap_or_default()
+    }
+}
+
+/// Maximum size of a locale, like `en` or `pt-BR`, in bytes.
+pub const LOCALE_SIZE: usize = 32;
+
+/// Data for `LocaleChanged` event.
+///
+/// Sent to all plugins when the user switches language, after the
+/// string tables of the new locale are loaded.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct LocaleChangedEvent {
+    /// Size of the locale in bytes.
+    pub locale_len: u32,
+    pub locale: [u8; LOCALE_SIZE],
+}
+
+impl LocaleChangedEvent {
+    /// Event for switching to `locale`, or `None` when the locale is
+    /// longer than `LOCALE_SIZE`.
+    pub fn new(locale: &str) -> Option<Self> {
+        if locale.len() > LOCALE_SIZE {
+            return None;
+        }
+
+        let mut event = Self {
+            locale_len: locale.len() as u32,
+            locale: [0; LOCALE_SIZE],
+        };
+        event.locale[..locale.len()].copy_from_slice(locale.as_bytes());
+        Some(event)
+    }
+
+    /// The new locale, or an empty string when it isn't valid UTF-8.
+    pub fn locale(&self) -> &str {
+        let len = (self.locale_len as usize).min(LOCALE_SIZE);
+        std::str::from_utf8(&self.locale[..len]).unwrap_or_default()
+    }
+}
+
+/// Types of plugin settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Unknown = 0,
+    Int = 1,
+    Float = 2,
+    Bool = 3,
+    String = 4,
+}
+
+impl From<i32> for SettingKind {
+    fn from(value: i32) -> SettingKind {
+        match value {
+            1 => Self::Int,
+            2 => Self::Float,
+            3 => Self::Bool,
+            4 => Self::String,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Types of the values guests watch with `gers_debug.watch`, stored in
+/// the guest's memory in little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchType {
+    Unknown = 0,
+    I32 = 1,
+    U32 = 2,
+    I64 = 3,
+    U64 = 4,
+    F32 = 5,
+    F64 = 6,
+    /// A byte, where zero is `false`.
+    Bool = 7,
+}
+
+impl WatchType {
+    /// Size of a value in bytes, zero when the type is unknown.
+    pub fn size(self) -> u32 {
+        match self {
+            Self::Unknown => 0,
+            Self::Bool => 1,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+        }
+    }
+}
+
+impl From<u32> for WatchType {
+    fn from(value: u32) -> WatchType {
+        match value {
+            1 => Self::I32,
+            2 => Self::U32,
+            3 => Self::I64,
+            4 => Self::U64,
+            5 => Self::F32,
+            6 => Self::F64,
+            7 => Self::Bool,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Types of the arguments guests pass to `gers.log_fmt`. Each argument
+/// is a tag byte followed by the value in little endian, and strings
+/// are a pointer and a length into the guest's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogArgType {
+    Unknown = 0,
+    I32 = 1,
+    U32 = 2,
+    I64 = 3,
+    U64 = 4,
+    F32 = 5,
+    F64 = 6,
+    /// A byte, where zero is `false`.
+    Bool = 7,
+    /// A `u32` pointer to UTF-8 bytes, and a `u32` length.
+    Str = 8,
+}
+
+impl LogArgType {
+    /// Size of a value in bytes, after the tag, zero when the type is
+    /// unknown.
+    pub fn size(self) -> usize {
+        match self {
+            Self::Unknown => 0,
+            Self::Bool => 1,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 | Self::Str => 8,
+        }
+    }
+}
+
+impl From<u8> for LogArgType {
+    fn from(value: u8) -> LogArgType {
+        match value {
+            1 => Self::I32,
+            2 => Self::U32,
+            3 => Self::I64,
+            4 => Self::U64,
+            5 => Self::F32,
+            6 => Self::F64,
+            7 => Self::Bool,
+            8 => Self::Str,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Header of every event the host writes into a guest's memory.
+///
+/// Events start with the header, at an address aligned to
+/// `EVENT_RECORD_ALIGN`, and the event data follows it directly. Guests
+/// built against an older ABI version get a shorter header, without the
+/// fields added since, see `size_for`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct EventHeader {
+    /// See `EventType`.
+    pub event_type: i32,
+    /// Size of the event data in bytes.
+    pub size: u32,
+    pub stamp: EventStamp,
+    /// `EVENT_FLAG_*` bits, the others are zero.
+    pub flags: u32,
+    /// `EVENT_SOURCE_HOST`, or the `plugin_id` of the plugin that
+    /// emitted the event.
+    pub source: u32,
+}
+
+/// Size of an `EventHeader` in bytes, as guests of this version get it.
+pub const EVENT_HEADER_SIZE: usize = std::mem::size_of::<EventHeader>();
+
+/// Source of events the host raised itself.
+pub const EVENT_SOURCE_HOST: u32 = 0;
+
+/// Changes the handler makes to the event reach the plugins receiving
+/// it after, see `MutableEvent`.
+pub const EVENT_FLAG_MUTABLE: u32 = 1 << 0;
+
+/// The event data is encoded with postcard, see `EncodedEvent`.
+pub const EVENT_FLAG_ENCODED: u32 = 1 << 1;
+
+impl EventHeader {
+    /// Size of the header for guests built against `abi_version`.
+    ///
+    /// Before version 4, the header is only the event type and size, and
+    /// before version 5, it has no flags or source. Those guests only
+    /// get headers in the event arena.
+    pub fn size_for(abi_version: u32) -> usize {
+        match abi_version {
+            0..=3 => 8,
+            4 => 24,
+            _ => EVENT_HEADER_SIZE,
+        }
+    }
+
+    /// Encode the header as little endian fields, of which guests of an
+    /// older version get the first `size_for` bytes.
+    pub fn encode(&self) -> [u8; EVENT_HEADER_SIZE] {
+        let mut bytes = [0; EVENT_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.event_type.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.stamp.frame_index.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.stamp.tick_index.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.source.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a header, or `None` when there are fewer than
+    /// `EVENT_HEADER_SIZE` bytes.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < EVENT_HEADER_SIZE {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+
+        Some(Self {
+            event_type: u32_at(0) as i32,
+            size: u32_at(4),
+            stamp: EventStamp {
+                frame_index: u64_at(8),
+                tick_index: u64_at(16),
+            },
+            flags: u32_at(24),
+            source: u32_at(28),
+        })
+    }
+
+    /// The host raised the event, rather than a plugin.
+    pub fn is_from_host(&self) -> bool {
+        self.source == EVENT_SOURCE_HOST
+    }
+
+    pub fn is_mutable(&self) -> bool {
+        self.flags & EVENT_FLAG_MUTABLE != 0
+    }
+
+    pub fn is_encoded(&self) -> bool {
+        self.flags & EVENT_FLAG_ENCODED != 0
+    }
+}
+
+/// Id of the plugin with the given name in `EventHeader::source`.
+///
+/// A hash of the name, so it's the same every run and on every machine,
+/// and guests can compute the ids of the plugins they know by name.
+pub fn plugin_id(name: &str) -> u32 {
+    // 32 bit FNV-1a.
+    let hash = name.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    // Zero is the host.
+    hash.max(1)
+}
+
+/// Frame and fixed update an event was sent in.
+///
+/// Both counters start at zero and only ever increase, and replays
+/// count the same frames and fixed updates as the recording, so plugins
+/// can order and de-duplicate what they handle deterministically.
+/// Stamps compare by frame first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct EventStamp {
+    /// Number of frames before the one the event was sent in.
+    pub frame_index: u64,
+    /// Number of fixed updates before the frame the event was sent in.
+    pub tick_index: u64,
+}
+
+/// Alignment of event records in a guest's event arena.
+pub const EVENT_RECORD_ALIGN: u32 = 8;
+
+/// Alignment the host requests for a guest's event buffer, which is
+/// enough for every event type.
+pub const EVENT_BUFFER_ALIGN: u32 = 8;
+
+/// Values of the current frame, which the host writes into a guest's
+/// memory before the frame's updates.
+///
+/// Mirrors the `gers.delta_time`, `gers.frame_index` and
+/// `gers.tick_index` globals, for guests that can't import globals.
+/// Guests export its address from `__gers_frame_globals`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct FrameGlobals {
+    /// Number of frames before this one.
+    pub frame_index: u64,
+    /// Number of fixed updates before this frame.
+    pub tick_index: u64,
+    /// Scaled delta time since the last frame, in seconds.
+    pub delta_time: f32,
+}
+
+impl FrameGlobals {
+    /// Stamp of the events sent in this frame.
+    pub fn stamp(&self) -> EventStamp {
+        EventStamp {
+            frame_index: self.frame_index,
+            tick_index: self.tick_index,
+        }
+    }
+}
+
+/// Version of the protocol between the host and guests.
+///
+/// Guests report the version they were built against from
+/// `__gers_abi_version`, and the host passes its own to `__gers_init`,
+/// along with its `Capabilities`. Since version 3, the host passes the
+/// alignment of the event buffer to `__gers_event_alloc(size, align)`.
+/// Since version 4, event records carry an `EventStamp`, and since
+/// version 5, events sent one at a time have an `EventHeader` too.
+/// Since version 6, `__gers_init` may also take the plugin's
+/// configuration.
+pub const ABI_VERSION: u32 = 6;
+
+/// Version of guests that don't export `__gers_abi_version`, from
+/// before the handshake. They run in compatibility mode, and aren't
+/// told the host's capabilities.
+pub const LEGACY_ABI_VERSION: u32 = 1;
+
+/// Optional subsystems the host provides to a plugin, as a bitfield.
+///
+/// Guests check these before using a subsystem. Its imports are
+/// still there without the capability, but fail or do nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Drawing to the window.
+    pub const DRAW: Self = Self(1 << 0);
+    /// Sound output.
+    pub const AUDIO: Self = Self(1 << 1);
+    /// Files in the plugin's data directory.
+    pub const STORAGE: Self = Self(1 << 2);
+    /// Network connections.
+    pub const NETWORK: Self = Self(1 << 3);
+    /// HTTP requests.
+    pub const HTTP: Self = Self(1 << 4);
+    /// The OS clipboard.
+    pub const CLIPBOARD: Self = Self(1 << 5);
+    /// Opening windows.
+    pub const WINDOW: Self = Self(1 << 6);
+    /// Controller input.
+    pub const GAMEPAD: Self = Self(1 << 7);
+    /// Background tasks.
+    pub const TASKS: Self = Self(1 << 8);
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// All capabilities in `other` are provided.
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Capabilities) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Capabilities) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Event with data that can't be laid out as a `#[repr(C)]` struct,
+/// like strings, vectors and enums.
+///
+/// Implementing the trait selects the encoding for the type: the host
+/// serializes the event with postcard into the guest's memory, and
+/// guests deserialize it with serde, rather than reading it in place.
+/// The event header has `EVENT_FLAG_ENCODED` set, and the size of the
+/// encoded data. Only guests of ABI version 5 or later receive them.
+pub trait EncodedEvent: Serialize + DeserializeOwned {
+    const TYPE: EventType;
+}
+
+/// Error from encoding or decoding an `EncodedEvent`.
+pub use postcard::Error as EncodingError;
+
+/// Encode an event as it's sent to guests.
+pub fn encode_event<T: EncodedEvent>(event: &T) -> Result<Vec<u8>, EncodingError> {
+    postcard::to_allocvec(event)
+}
+
+/// Decode an event from the data the host sent.
+pub fn decode_event<T: EncodedEvent>(bytes: &[u8]) -> Result<T, EncodingError> {
+    postcard::from_bytes(bytes)
+}
+
+/// Marker for events that plugins may modify or cancel.
+///
+/// After each plugin handles a mutable event, the host reads the event
+/// back out of the plugin's memory, and passes the modified event on
+/// to the next plugin, unless it was cancelled.
+///
+/// # Safety
+///
+/// The guest may write anything into the event, so every bit
+/// pattern must be a valid value of the type. Use integers
+/// rather than `bool` for flags.
+pub unsafe trait MutableEvent: Clone {
+    fn is_cancelled(&self) -> bool;
+}
+
+/// Data for `Damage` event.
+///
+/// Emitted by plugins, and delivered to all plugins when events are
+/// next dispatched.
+/// Plugins may change the amount, or cancel the damage, before it
+/// reaches plugins with a lower priority.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct DamageEvent {
+    pub entity: u32,
+    /// Entity dealing the damage, or `0` for none.
+    pub source: u32,
+    pub amount: f32,
+    /// Non-zero once cancelled.
+    pub cancelled: u32,
+}
+
+unsafe impl MutableEvent for DamageEvent {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled != 0
+    }
+}
+
+/// Methods of HTTP requests made by plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Unknown = 0,
+    Get = 1,
+    Post = 2,
+    Put = 3,
+    Delete = 4,
+    Head = 5,
+    Patch = 6,
+}
+
+impl From<u32> for HttpMethod {
+    fn from(value: u32) -> HttpMethod {
+        match value {
+            1 => Self::Get,
+            2 => Self::Post,
+            3 => Self::Put,
+            4 => Self::Delete,
+            5 => Self::Head,
+            6 => Self::Patch,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Gamepad buttons, laid out like a standard controller.
+///
+/// Action pad buttons are named by compass direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadButton {
+    Unknown = 0,
+    South = 1,
+    East = 2,
+    North = 3,
+    West = 4,
+    LeftTrigger = 5,
+    LeftTrigger2 = 6,
+    RightTrigger = 7,
+    RightTrigger2 = 8,
+    Select = 9,
+    Start = 10,
+    Mode = 11,
+    LeftThumb = 12,
+    RightThumb = 13,
+    DPadUp = 14,
+    DPadDown = 15,
+    DPadLeft = 16,
+    DPadRight = 17,
+}
+
+impl From<u32> for GamepadButton {
+    fn from(value: u32) -> GamepadButton {
+        match value {
+            1 => Self::South,
+            2 => Self::East,
+            3 => Self::North,
+            4 => Self::West,
+            5 => Self::LeftTrigger,
+            6 => Self::LeftTrigger2,
+            7 => Self::RightTrigger,
+            8 => Self::RightTrigger2,
+            9 => Self::Select,
+            10 => Self::Start,
+            11 => Self::Mode,
+            12 => Self::LeftThumb,
+            13 => Self::RightThumb,
+            14 => Self::DPadUp,
+            15 => Self::DPadDown,
+            16 => Self::DPadLeft,
+            17 => Self::DPadRight,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Why the app was paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Unknown = 0,
+    FocusLost = 1,
+    Minimized = 2,
+}
+
+impl From<u32> for PauseReason {
+    fn from(value: u32) -> PauseReason {
+        match value {
+            1 => Self::FocusLost,
+            2 => Self::Minimized,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// State of the simulation, as controlled while debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationState {
+    Unknown = 0,
+    Running = 1,
+    /// Neither fixed updates nor plugin updates run, and timers don't
+    /// count down.
+    Paused = 2,
+    /// Paused, except for the current frame, which runs the updates and
+    /// a single fixed update.
+    Stepping = 3,
+}
+
+impl From<u32> for SimulationState {
+    fn from(value: u32) -> SimulationState {
+        match value {
+            1 => Self::Running,
+            2 => Self::Paused,
+            3 => Self::Stepping,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEventKind {
+    Unknown = 0,
+    /// The user asked to close the window. It stays open
+    /// until the plugin closes it.
+    CloseRequested = 1,
+    Resized = 2,
+    Focused = 3,
+    Unfocused = 4,
+}
+
+impl From<u32> for WindowEventKind {
+    fn from(value: u32) -> WindowEventKind {
+        match value {
+            1 => Self::CloseRequested,
+            2 => Self::Resized,
+            3 => Self::Focused,
+            4 => Self::Unfocused,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadAxis {
+    Unknown = 0,
+    LeftStickX = 1,
+    LeftStickY = 2,
+    LeftZ = 3,
+    RightStickX = 4,
+    RightStickY = 5,
+    RightZ = 6,
+    DPadX = 7,
+    DPadY = 8,
+}
+
+impl From<u32> for GamepadAxis {
+    fn from(value: u32) -> GamepadAxis {
+        match value {
+            1 => Self::LeftStickX,
+            2 => Self::LeftStickY,
+            3 => Self::LeftZ,
+            4 => Self::RightStickX,
+            5 => Self::RightStickY,
+            6 => Self::RightZ,
+            7 => Self::DPadX,
+            8 => Self::DPadY,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Status returned by the guest's `__gers_update` and `__gers_resume`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// The frame's work is done.
+    Done = 0,
+    /// There is more work, resume it on a later frame.
+    Continue = 1,
+}
+
+impl From<i32> for UpdateStatus {
+    fn from(value: i32) -> UpdateStatus {
+        match value {
+            1 => Self::Continue,
+            _ => Self::Done,
+        }
+    }
+}
+
+/// Error codes returned by host imports as negative integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostError {
+    /// The plugin wasn't granted the capability in its `plugin.toml`.
+    PermissionDenied = -1,
+    NotFound = -2,
+    InvalidArgument = -3,
+    Io = -4,
+    /// The operation can't make progress yet, try again later.
+    WouldBlock = -5,
+    /// The plugin reached its limit for a resource.
+    LimitReached = -6,
+    /// The host doesn't provide the import, and stubbed it.
+    Unsupported = -7,
+}
+
+impl HostError {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Interpret a return value, where non-negative values are success.
+    pub fn from_code(code: i32) -> Result<u32, HostError> {
+        match code {
+            code if code >= 0 => Ok(code as u32),
+            -1 => Err(Self::PermissionDenied),
+            -2 => Err(Self::NotFound),
+            -3 => Err(Self::InvalidArgument),
+            -5 => Err(Self::WouldBlock),
+            -6 => Err(Self::LimitReached),
+            -7 => Err(Self::Unsupported),
+            _ => Err(Self::Io),
+        }
+    }
+
+    /// Interpret a return value of an import creating a resource, where
+    /// non-negative values are handles.
+    pub fn from_handle_code(code: i64) -> Result<u64, HostError> {
+        match code {
+            code if code >= 0 => Ok(code as u64),
+            code => Self::from_code(i32::try_from(code).unwrap_or(i32::MIN)).map(u64::from),
+        }
+    }
+}
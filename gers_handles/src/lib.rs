@@ -0,0 +1,386 @@
+//! Tables of host-side resources handed to plugins as opaque handles.
+//!
+//! Guests never see the host's objects, only `u64` handles, each made
+//! of a slot index in the low 32 bits and the slot's generation in the
+//! high 32 bits. Removing a value bumps its slot's generation, so stale
+//! handles are told apart from the value that reuses the slot with a
+//! single comparison. Every value is kept with its owner, and lookups on
+//! behalf of a plugin fail for handles another plugin owns, so handles
+//! can't be guessed or passed around to reach someone else's resources.
+//!
+//! Generations start at one, so the zero handle is never valid, and stop
+//! below `2^31`, so handles stay positive when returned as `i64`, with
+//! negative values left for error codes. Slots whose generation runs out
+//! are retired rather than reused.
+use std::{borrow::Borrow, fmt};
+use thiserror::Error;
+
+/// Generation a slot starts at.
+const FIRST_GENERATION: u32 = 1;
+
+/// Slots aren't reused past this generation.
+const MAX_GENERATION: u32 = i32::MAX as u32;
+
+/// Opaque reference to a value in a `HandleTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(u64);
+
+impl Handle {
+    /// Never refers to a value, used to signal failure to guests.
+    pub const NULL: Handle = Handle(0);
+
+    fn new(index: u32, generation: u32) -> Self {
+        Self((generation as u64) << 32 | index as u64)
+    }
+
+    /// Handle as passed across the import boundary.
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    pub const fn to_raw(self) -> u64 {
+        self.0
+    }
+
+    pub fn index(self) -> u32 {
+        self.0 as u32
+    }
+
+    pub fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index(), self.generation())
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    /// Never handed out by the table.
+    #[error("invalid handle {0}")]
+    Invalid(Handle),
+
+    /// Referred to a value that has since been removed.
+    #[error("expired handle {0}")]
+    Expired(Handle),
+
+    /// Refers to a value of another owner.
+    #[error("handle {0} is owned by another plugin")]
+    NotOwned(Handle),
+}
+
+struct Slot<T, O> {
+    generation: u32,
+    entry: Option<(O, T)>,
+}
+
+/// Values of type `T`, each with an owner of type `O`, behind handles.
+pub struct HandleTable<T, O> {
+    slots: Vec<Slot<T, O>>,
+    /// Indices of the empty slots that can be reused.
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T, O> Default for HandleTable<T, O> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl<T, O> HandleTable<T, O> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Keep a value on behalf of `owner`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the table has `u32::MAX` slots.
+    pub fn insert(&mut self, owner: O, value: T) -> Handle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                let index = u32::try_from(self.slots.len())
+                    .ok()
+                    .filter(|index| *index < u32::MAX)
+                    .expect("handle table is full");
+                self.slots.push(Slot {
+                    generation: FIRST_GENERATION,
+                    entry: None,
+                });
+                index
+            }
+        };
+
+        let slot = &mut self.slots[index as usize];
+        slot.entry = Some((owner, value));
+        self.len += 1;
+
+        Handle::new(index, slot.generation)
+    }
+
+    /// Value behind a handle, regardless of its owner.
+    pub fn get(&self, handle: Handle) -> Result<&T, HandleError> {
+        self.entry(handle).map(|(_, value)| value)
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Result<&mut T, HandleError> {
+        self.entry_mut(handle).map(|(_, value)| value)
+    }
+
+    pub fn owner(&self, handle: Handle) -> Result<&O, HandleError> {
+        self.entry(handle).map(|(owner, _)| owner)
+    }
+
+    /// Remove a value regardless of its owner, expiring its handle.
+    pub fn remove(&mut self, handle: Handle) -> Result<T, HandleError> {
+        self.entry(handle)?;
+
+        let index = handle.index();
+        let slot = &mut self.slots[index as usize];
+        let (_, value) = slot.entry.take().expect("entry was checked");
+        self.len -= 1;
+
+        slot.generation += 1;
+        if slot.generation <= MAX_GENERATION {
+            self.free.push(index);
+        }
+
+        Ok(value)
+    }
+
+    /// Handles, owners and values, in the order of their slots.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &O, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let (owner, value) = slot.entry.as_ref()?;
+            Some((Handle::new(index as u32, slot.generation), owner, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &O, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let (owner, value) = slot.entry.as_mut()?;
+                Some((Handle::new(index as u32, slot.generation), &*owner, value))
+            })
+    }
+
+    /// Remove the values for which `keep` returns `false`.
+    pub fn retain(&mut self, mut keep: impl FnMut(Handle, &O, &mut T) -> bool) {
+        let removed: Vec<Handle> = self
+            .iter_mut()
+            .filter_map(|(handle, owner, value)| (!keep(handle, owner, value)).then_some(handle))
+            .collect();
+        for handle in removed {
+            let _ = self.remove(handle);
+        }
+    }
+
+    fn entry(&self, handle: Handle) -> Result<&(O, T), HandleError> {
+        let slot = self
+            .slots
+            .get(handle.index() as usize)
+            .filter(|_| handle.generation() >= FIRST_GENERATION)
+            .ok_or(HandleError::Invalid(handle))?;
+
+        match slot.entry {
+            Some(ref entry) if slot.generation == handle.generation() => Ok(entry),
+            _ if handle.generation() < slot.generation => Err(HandleError::Expired(handle)),
+            _ => Err(HandleError::Invalid(handle)),
+        }
+    }
+
+    fn entry_mut(&mut self, handle: Handle) -> Result<&mut (O, T), HandleError> {
+        self.entry(handle)?;
+        Ok(self.slots[handle.index() as usize]
+            .entry
+            .as_mut()
+            .expect("entry was checked"))
+    }
+
+    /// Value behind a handle, if `owner` owns it.
+    pub fn get_owned<Q>(&self, owner: &Q, handle: Handle) -> Result<&T, HandleError>
+    where
+        O: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        match self.entry(handle)? {
+            (entry_owner, value) if entry_owner.borrow() == owner => Ok(value),
+            _ => Err(HandleError::NotOwned(handle)),
+        }
+    }
+
+    pub fn get_owned_mut<Q>(&mut self, owner: &Q, handle: Handle) -> Result<&mut T, HandleError>
+    where
+        O: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.get_owned(owner, handle)?;
+        self.get_mut(handle)
+    }
+
+    /// Remove a value, if `owner` owns it.
+    pub fn remove_owned<Q>(&mut self, owner: &Q, handle: Handle) -> Result<T, HandleError>
+    where
+        O: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.get_owned(owner, handle)?;
+        self.remove(handle)
+    }
+
+    /// Number of values `owner` owns.
+    pub fn count_owned<Q>(&self, owner: &Q) -> usize
+    where
+        O: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.iter()
+            .filter(|(_, entry_owner, _)| (*entry_owner).borrow() == owner)
+            .count()
+    }
+
+    /// Remove every value `owner` owns.
+    ///
+    /// Returns the removed values.
+    pub fn release<Q>(&mut self, owner: &Q) -> Vec<T>
+    where
+        O: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        let owned: Vec<Handle> = self
+            .iter()
+            .filter(|(_, entry_owner, _)| (*entry_owner).borrow() == owner)
+            .map(|(handle, _, _)| handle)
+            .collect();
+
+        owned
+            .into_iter()
+            .filter_map(|handle| self.remove(handle).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut table = HandleTable::new();
+        let a = table.insert("a", 1);
+        let b = table.insert("b", 2);
+        assert_ne!(a, b);
+        assert!(!a.is_null());
+        assert_eq!(table.len(), 2);
+
+        assert_eq!(table.get(a), Ok(&1));
+        assert_eq!(table.get_owned("b", b), Ok(&2));
+        assert_eq!(table.owner(b), Ok(&"b"));
+        *table.get_owned_mut("a", a).unwrap() += 10;
+        assert_eq!(table.remove(a), Ok(11));
+        assert_eq!(table.get(a), Err(HandleError::Expired(a)));
+        assert_eq!(table.remove(a), Err(HandleError::Expired(a)));
+        assert_eq!(table.len(), 1);
+
+        assert_eq!(
+            table.get(Handle::NULL),
+            Err(HandleError::Invalid(Handle::NULL))
+        );
+        let unknown = Handle::from_raw(1 << 32 | 100);
+        assert_eq!(table.get(unknown), Err(HandleError::Invalid(unknown)));
+    }
+
+    #[test]
+    fn test_reused_slots_expire_old_handles() {
+        let mut table = HandleTable::new();
+        let old = table.insert((), "old");
+        table.remove(old).unwrap();
+        let new = table.insert((), "new");
+
+        assert_eq!(old.index(), new.index());
+        assert_eq!(new.generation(), old.generation() + 1);
+        assert_eq!(table.get(old), Err(HandleError::Expired(old)));
+        assert_eq!(table.get(new), Ok(&"new"));
+        // Handles from the future aren't valid either.
+        let forged = Handle::new(new.index(), new.generation() + 1);
+        assert_eq!(table.get(forged), Err(HandleError::Invalid(forged)));
+        assert_eq!(Handle::from_raw(new.to_raw()), new);
+    }
+
+    #[test]
+    fn test_exhausted_slots_are_retired() {
+        let mut table = HandleTable::new();
+        table.insert((), 0);
+        table.slots[0].generation = MAX_GENERATION;
+        let last = Handle::new(0, MAX_GENERATION);
+        assert_eq!(table.remove(last), Ok(0));
+
+        let handle = table.insert((), 1);
+        assert_eq!(handle.index(), 1);
+        assert!((handle.to_raw() as i64) > 0);
+    }
+
+    #[test]
+    fn test_handles_are_owned() {
+        let mut table: HandleTable<u32, String> = HandleTable::new();
+        let mine = table.insert("mine".to_string(), 1);
+        let theirs = table.insert("theirs".to_string(), 2);
+        table.insert("theirs".to_string(), 3);
+
+        assert_eq!(
+            table.get_owned("mine", theirs),
+            Err(HandleError::NotOwned(theirs))
+        );
+        assert_eq!(
+            table.remove_owned("mine", theirs),
+            Err(HandleError::NotOwned(theirs))
+        );
+        assert_eq!(table.remove_owned("mine", mine), Ok(1));
+
+        assert_eq!(table.count_owned("theirs"), 2);
+        let mut released = table.release("theirs");
+        released.sort_unstable();
+        assert_eq!(released, [2, 3]);
+        assert!(table.is_empty());
+        assert!(table.release("theirs").is_empty());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut table = HandleTable::new();
+        for value in 0..6 {
+            table.insert((), value);
+        }
+        table.retain(|_, _, value| {
+            *value += 1;
+            *value % 2 == 0
+        });
+
+        let values: Vec<i32> = table.iter().map(|(_, _, value)| *value).collect();
+        assert_eq!(values, [2, 4, 6]);
+    }
+}
@@ -0,0 +1,119 @@
+//! Guest side of the interface, for `gers_api`.
+//!
+//! Every namespace becomes a module, with the raw extern declarations
+//! in a private `raw` module, and a safe wrapper for each of them that
+//! takes slices instead of pointers and lengths.
+use std::fmt::Write;
+
+use crate::{Function, Interface, Type};
+
+/// Extern declarations and safe wrappers of the imports.
+pub fn generate(interface: &Interface) -> String {
+    let mut out = String::from("// Generated from gers.interface by gers_interface. Don't edit.\n");
+
+    for namespace in interface.namespaces.iter() {
+        writeln!(out, "\n#[allow(dead_code)]\npub mod {} {{", namespace.name).unwrap();
+        writeln!(out, "    mod raw {{").unwrap();
+        writeln!(
+            out,
+            "        #[link(wasm_import_module = {:?})]",
+            namespace.name
+        )
+        .unwrap();
+        writeln!(out, "        extern \"C\" {{").unwrap();
+        for function in namespace.functions.iter() {
+            writeln!(
+                out,
+                "            pub fn {}({}){};",
+                function.name,
+                raw_params(function),
+                function.ret()
+            )
+            .unwrap();
+        }
+        writeln!(out, "        }}\n    }}").unwrap();
+
+        for function in namespace.functions.iter() {
+            writeln!(
+                out,
+                "\n    pub fn {}({}){} {{\n        unsafe {{ raw::{}({}) }}\n    }}",
+                function.name,
+                params(function),
+                function.ret(),
+                function.name,
+                args(function)
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    out
+}
+
+fn raw_params(function: &Function) -> String {
+    let params: Vec<_> = function
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Type::Str | Type::Bytes => {
+                format!("{0}_ptr: *const u8, {0}_len: u32", param.name)
+            }
+            Type::Buf => format!("{0}_ptr: *mut u8, {0}_len: u32", param.name),
+            ty => format!("{}: {}", param.name, ty.primitive()),
+        })
+        .collect();
+
+    params.join(", ")
+}
+
+fn params(function: &Function) -> String {
+    let params: Vec<_> = function
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Type::Str => format!("{}: &str", param.name),
+            Type::Bytes => format!("{}: &[u8]", param.name),
+            Type::Buf => format!("{}: &mut [u8]", param.name),
+            ty => format!("{}: {}", param.name, ty.primitive()),
+        })
+        .collect();
+
+    params.join(", ")
+}
+
+fn args(function: &Function) -> String {
+    let args: Vec<_> = function
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Type::Str | Type::Bytes => format!("{0}.as_ptr(), {0}.len() as u32", param.name),
+            Type::Buf => format!("{0}.as_mut_ptr(), {0}.len() as u32", param.name),
+            _ => param.name.clone(),
+        })
+        .collect();
+
+    args.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_guest() {
+        let interface = Interface::parse(
+            "namespace gers_net permission network\n\
+             fn tcp_recv(connection: u32, buf: buf) -> i32 = net_tcp_recv\n",
+        )
+        .unwrap();
+        let code = generate(&interface);
+
+        assert!(code.contains("#[link(wasm_import_module = \"gers_net\")]"));
+        assert!(code
+            .contains("pub fn tcp_recv(connection: u32, buf_ptr: *mut u8, buf_len: u32) -> i32;"));
+        assert!(code.contains("pub fn tcp_recv(connection: u32, buf: &mut [u8]) -> i32 {"));
+        assert!(code
+            .contains("unsafe { raw::tcp_recv(connection, buf.as_mut_ptr(), buf.len() as u32) }"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Host side of the interface, for `gers_engine`.
+//!
+//! Generates `register_imports`, which registers every namespace with
+//! the plugin loader's imports builder, binding each import to its
+//! function in `crate::wasm_impl`. The signatures of those functions
+//! are checked against the interface at compile time, rather than when
+//! a plugin fails to link.
+use std::fmt::Write;
+
+use crate::{Function, Interface, Namespace, Type};
+
+/// `register_imports` and the signature checks of the host functions.
+pub fn generate(interface: &Interface) -> String {
+    let mut out = String::from(
+        "// Generated from gers.interface by gers_interface. Don't edit.
+
+/// Register the host imports with the plugin loader.
+///
+/// Each namespace is built for every plugin that is loaded, bound to
+/// an environment for that plugin. Imports of capabilities the plugin
+/// wasn't granted are replaced with stubs that return
+/// `HostError::PermissionDenied`, so the module still instantiates.
+pub fn register_imports(
+    imports: &mut gers_plugins::ImportsBuilder,
+    env: &crate::env::GersEnv,
+) -> Result<(), gers_plugins::PluginError> {
+",
+    );
+
+    for namespace in interface.namespaces.iter() {
+        if namespace.functions.iter().all(|function| function.loader) {
+            continue;
+        }
+
+        match namespace.permission {
+            Some(ref permission) => {
+                writeln!(
+                    out,
+                    "    let (granted, denied) = (env.clone(), env.clone());"
+                )
+                .unwrap();
+                writeln!(out, "    imports.register_guarded(").unwrap();
+                writeln!(out, "        {:?},", namespace.name).unwrap();
+                writeln!(out, "        {},", permission_path(permission)).unwrap();
+                write_exports(&mut out, namespace, "granted", false);
+                write_exports(&mut out, namespace, "denied", true);
+                writeln!(out, "    )?;\n").unwrap();
+            }
+            None => {
+                writeln!(out, "    let base = env.clone();").unwrap();
+                writeln!(out, "    imports.register(").unwrap();
+                writeln!(out, "        {:?},", namespace.name).unwrap();
+                write_exports(&mut out, namespace, "base", false);
+                writeln!(out, "    )?;\n").unwrap();
+            }
+        }
+    }
+    writeln!(out, "    Ok(())\n}}").unwrap();
+
+    writeln!(out, "\n// Host functions, checked against the interface.").unwrap();
+    for function in host_functions(interface) {
+        writeln!(
+            out,
+            "#[allow(clippy::type_complexity)]\nconst _: fn(&crate::env::GersEnv{}){} = crate::wasm_impl::{};",
+            params(function),
+            function.ret(),
+            function.host_fn
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+/// Closure building the namespace's exports for a plugin.
+fn write_exports(out: &mut String, namespace: &Namespace, base: &str, denied: bool) {
+    writeln!(out, "        move |store, context| {{").unwrap();
+    writeln!(out, "            let env = {}.for_plugin(context);", base).unwrap();
+    writeln!(out, "            let mut exports = wasmer::Exports::new();").unwrap();
+    for function in namespace
+        .functions
+        .iter()
+        .filter(|function| !function.loader)
+    {
+        let export = match (&function.permission, denied) {
+            (_, true) => host_fn(&denied_fn(function)),
+            (Some(permission), false) => format!(
+                "if context.meta.permissions.is_granted({}) {{ {} }} else {{ {} }}",
+                permission_path(permission),
+                host_fn(&function.host_fn),
+                host_fn(&denied_fn(function))
+            ),
+            (None, false) => host_fn(&function.host_fn),
+        };
+        writeln!(
+            out,
+            "            exports.insert({:?}, {});",
+            function.name, export
+        )
+        .unwrap();
+    }
+    writeln!(out, "            exports\n        }},").unwrap();
+}
+
+fn host_fn(name: &str) -> String {
+    format!(
+        "wasmer::Function::new_native_with_env(store, env.clone(), crate::wasm_impl::{})",
+        name
+    )
+}
+
+fn denied_fn(function: &Function) -> String {
+    function
+        .denied_fn()
+        .expect("guarded functions are checked when parsed")
+}
+
+fn permission_path(permission: &str) -> String {
+    let mut chars = permission.chars();
+    let first = chars.next().map(|c| c.to_ascii_uppercase());
+
+    format!(
+        "gers_plugins::Permission::{}{}",
+        first.unwrap_or_default(),
+        chars.as_str()
+    )
+}
+
+/// Functions implemented by the host, each once.
+fn host_functions(interface: &Interface) -> Vec<&Function> {
+    let mut functions: Vec<&Function> = vec![];
+    let all = interface
+        .namespaces
+        .iter()
+        .flat_map(|namespace| namespace.functions.iter());
+    for function in all.filter(|function| !function.loader) {
+        if !functions
+            .iter()
+            .any(|seen| seen.host_fn == function.host_fn)
+        {
+            functions.push(function);
+        }
+    }
+
+    functions
+}
+
+fn params(function: &Function) -> String {
+    function
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Type::Str | Type::Bytes | Type::Buf => {
+                ", wasmer::WasmPtr<u8, wasmer::Array>, u32".to_string()
+            }
+            ty => format!(", {}", ty.primitive()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_host() {
+        let interface = Interface::parse(
+            "namespace gers\n\
+             fn log_info(message: str)\n\
+             fn set_time_scale(time_scale: f32) -> i32 permission time\n\
+             loader fn memory_grown(new_pages: u32)\n\
+             namespace gers_clipboard permission clipboard\n\
+             fn set_text(text: str) -> i32 = clipboard_set_text\n",
+        )
+        .unwrap();
+        let code = generate(&interface);
+
+        assert!(code.contains("gers_plugins::Permission::Time"));
+        assert!(code.contains("crate::wasm_impl::denied_f32"));
+        assert!(code.contains("imports.register_guarded(\n        \"gers_clipboard\","));
+        assert!(code.contains("crate::wasm_impl::denied_2"));
+        assert!(!code.contains("memory_grown"));
+        assert!(code.contains(
+            "#[allow(clippy::type_complexity)]\nconst _: fn(&crate::env::GersEnv, wasmer::WasmPtr<u8, wasmer::Array>, u32) -> i32 = crate::wasm_impl::clipboard_set_text;"
+        ));
+    }
+}
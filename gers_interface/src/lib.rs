@@ -0,0 +1,416 @@
+//! Interface definition of the host imports.
+//!
+//! The functions the host provides to plugins are defined once, in
+//! `gers.interface`, and both sides of the import boundary are
+//! generated from it by build scripts. The host's registration with the
+//! plugin loader comes from `host::generate`, and the guest's extern
+//! declarations and safe wrappers from `guest::generate`, so their
+//! signatures can't drift apart.
+//!
+//! See `gers.interface` for the format.
+pub mod guest;
+pub mod host;
+
+/// Interface of the gers host.
+pub const GERS_INTERFACE: &str = include_str!("../gers.interface");
+
+/// Parameter and return types of imports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    /// String passed as a pointer and length.
+    Str,
+    /// Bytes passed as a pointer and length.
+    Bytes,
+    /// Buffer the host writes into, passed as a pointer and capacity.
+    Buf,
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "i32" => Some(Type::I32),
+            "u32" => Some(Type::U32),
+            "i64" => Some(Type::I64),
+            "u64" => Some(Type::U64),
+            "f32" => Some(Type::F32),
+            "f64" => Some(Type::F64),
+            "str" => Some(Type::Str),
+            "bytes" => Some(Type::Bytes),
+            "buf" => Some(Type::Buf),
+            _ => None,
+        }
+    }
+
+    /// Passed as a pointer and length.
+    pub fn is_slice(self) -> bool {
+        matches!(self, Type::Str | Type::Bytes | Type::Buf)
+    }
+
+    /// Rust type of a primitive.
+    fn primitive(self) -> &'static str {
+        match self {
+            Type::I32 => "i32",
+            Type::U32 => "u32",
+            Type::I64 => "i64",
+            Type::U64 => "u64",
+            Type::F32 => "f32",
+            Type::F64 => "f64",
+            Type::Str | Type::Bytes | Type::Buf => unreachable!("slices aren't primitives"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    /// Name of the import.
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret: Option<Type>,
+    /// Host function implementing the import.
+    pub host_fn: String,
+    /// Permission guarding only this function.
+    pub permission: Option<String>,
+    /// Provided by the plugin loader rather than the host.
+    pub loader: bool,
+}
+
+impl Function {
+    /// Number of WebAssembly parameters, with slices taking two.
+    pub fn wasm_param_count(&self) -> usize {
+        self.params
+            .iter()
+            .map(|param| if param.ty.is_slice() { 2 } else { 1 })
+            .sum()
+    }
+
+    /// Return type, as written after the parameters in Rust.
+    fn ret(&self) -> String {
+        match self.ret {
+            Some(ty) => format!(" -> {}", ty.primitive()),
+            None => String::new(),
+        }
+    }
+
+    /// Host stand-in for plugins that weren't granted the permission
+    /// guarding the function.
+    ///
+    /// Stand-ins take up to five 32-bit integers, or one f32, and return
+    /// an i32 code. Those returning an i64 code end in `_i64`, and those
+    /// taking a leading 64-bit handle are named `denied_handle_<n>`.
+    pub fn denied_fn(&self) -> Option<String> {
+        let suffix = match self.ret {
+            Some(Type::I32) => "",
+            Some(Type::I64) => "_i64",
+            _ => return None,
+        };
+        // Number of 32-bit WebAssembly parameters the stand-in takes.
+        let (prefix, params, counts) = match self.params.as_slice() {
+            [Param { ty: Type::F32, .. }] if suffix.is_empty() => {
+                return Some("denied_f32".to_string())
+            }
+            [Param {
+                ty: Type::I64 | Type::U64,
+                ..
+            }, rest @ ..] => ("denied_handle", rest, 0..=4),
+            params => ("denied", params, 1..=5),
+        };
+
+        let mut count = 0;
+        for param in params {
+            count += match param.ty {
+                ty if ty.is_slice() => 2,
+                Type::I32 | Type::U32 => 1,
+                _ => return None,
+            };
+        }
+
+        if counts.contains(&count) {
+            Some(format!("{}_{}{}", prefix, count, suffix))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    pub name: String,
+    /// Permission guarding every function in the namespace.
+    pub permission: Option<String>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub namespaces: Vec<Namespace>,
+}
+
+/// Permissions of `plugin.toml` that can guard imports.
+const PERMISSIONS: &[&str] = &["storage", "network", "http", "time", "window", "clipboard"];
+
+impl Interface {
+    /// The interface of the gers host.
+    pub fn gers() -> Self {
+        Self::parse(GERS_INTERFACE).expect("gers.interface is valid")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut interface = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix("namespace ") {
+                let namespace = parse_namespace(rest).map_err(error)?;
+                if interface
+                    .namespaces
+                    .iter()
+                    .any(|existing| existing.name == namespace.name)
+                {
+                    return Err(error(format!(
+                        "namespace {} is defined twice",
+                        namespace.name
+                    )));
+                }
+                interface.namespaces.push(namespace);
+                continue;
+            }
+
+            let (loader, rest) = match line.strip_prefix("loader ") {
+                Some(rest) => (true, rest.trim_start()),
+                None => (false, line),
+            };
+            let rest = rest
+                .strip_prefix("fn ")
+                .ok_or_else(|| error("expected `namespace` or `fn`".to_string()))?;
+            let namespace = interface
+                .namespaces
+                .last_mut()
+                .ok_or_else(|| error("function outside of a namespace".to_string()))?;
+
+            let mut function = parse_function(rest).map_err(error)?;
+            function.loader = loader;
+            if namespace
+                .functions
+                .iter()
+                .any(|existing| existing.name == function.name)
+            {
+                return Err(error(format!(
+                    "function {} is defined twice",
+                    function.name
+                )));
+            }
+            if namespace.permission.is_some() && function.permission.is_some() {
+                return Err(error(format!(
+                    "namespace {} is already behind a permission",
+                    namespace.name
+                )));
+            }
+            if (namespace.permission.is_some() || function.permission.is_some())
+                && function.denied_fn().is_none()
+            {
+                return Err(error(format!(
+                    "function {} behind a permission must take 32-bit integers after an optional 64-bit handle, or one f32, and return i32 or i64",
+                    function.name
+                )));
+            }
+            namespace.functions.push(function);
+        }
+
+        Ok(interface)
+    }
+}
+
+fn parse_permission(name: &str) -> Result<String, String> {
+    if PERMISSIONS.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!("unknown permission {:?}", name))
+    }
+}
+
+fn parse_name(name: &str) -> Result<String, String> {
+    let valid = matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!("invalid name {:?}", name))
+    }
+}
+
+/// Parse `<name> [permission <name>]`.
+fn parse_namespace(text: &str) -> Result<Namespace, String> {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let (name, permission) = match words.as_slice() {
+        [name] => (name, None),
+        [name, "permission", permission] => (name, Some(parse_permission(permission)?)),
+        _ => return Err("expected `namespace <name> [permission <name>]`".to_string()),
+    };
+
+    Ok(Namespace {
+        name: parse_name(name)?,
+        permission,
+        functions: vec![],
+    })
+}
+
+/// Parse `<name>(<params>) [-> <type>] [= <host function>] [permission <name>]`.
+fn parse_function(text: &str) -> Result<Function, String> {
+    let (name, rest) = text
+        .split_once('(')
+        .ok_or_else(|| "expected `(` after the function name".to_string())?;
+    let (params, rest) = rest
+        .split_once(')')
+        .ok_or_else(|| "expected `)` after the parameters".to_string())?;
+    let name = parse_name(name.trim())?;
+
+    let params = params
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, ty) = param
+                .split_once(':')
+                .ok_or_else(|| format!("expected `<name>: <type>`, found {:?}", param))?;
+            let ty = ty.trim();
+            Ok(Param {
+                name: parse_name(name.trim())?,
+                ty: Type::parse(ty).ok_or_else(|| format!("unknown type {:?}", ty))?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut words = rest.split_whitespace().peekable();
+    let ret = match words.next_if_eq(&"->") {
+        Some(_) => {
+            let ty = words.next().unwrap_or_default();
+            match Type::parse(ty) {
+                Some(ty) if !ty.is_slice() => Some(ty),
+                _ => return Err(format!("invalid return type {:?}", ty)),
+            }
+        }
+        None => None,
+    };
+    let host_fn = match words.next_if_eq(&"=") {
+        Some(_) => parse_name(words.next().unwrap_or_default())?,
+        None => name.clone(),
+    };
+    let permission = match words.next_if_eq(&"permission") {
+        Some(_) => Some(parse_permission(words.next().unwrap_or_default())?),
+        None => None,
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("unexpected {:?}", word));
+    }
+
+    Ok(Function {
+        name,
+        params,
+        ret,
+        host_fn,
+        permission,
+        loader: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interface() {
+        let interface = Interface::parse(
+            "# Comment\n\
+             namespace gers\n\
+             fn log_info(message: str)\n\
+             fn set_time_scale(time_scale: f32) -> i32 permission time\n\
+             loader fn memory_grown(new_pages: u32)\n\
+             \n\
+             namespace gers_net permission network\n\
+             fn tcp_send(connection: u32, data: bytes) -> i32 = net_tcp_send\n",
+        )
+        .unwrap();
+
+        let (gers, net) = (&interface.namespaces[0], &interface.namespaces[1]);
+        assert_eq!(gers.functions[0].params[0].ty, Type::Str);
+        assert_eq!(gers.functions[0].host_fn, "log_info");
+        assert_eq!(gers.functions[1].permission.as_deref(), Some("time"));
+        assert_eq!(gers.functions[1].denied_fn().as_deref(), Some("denied_f32"));
+        assert!(gers.functions[2].loader);
+        assert_eq!(net.permission.as_deref(), Some("network"));
+        assert_eq!(net.functions[0].host_fn, "net_tcp_send");
+        assert_eq!(net.functions[0].wasm_param_count(), 3);
+        assert_eq!(net.functions[0].denied_fn().as_deref(), Some("denied_3"));
+    }
+
+    #[test]
+    fn test_denied_fns() {
+        let denied = |text: &str| {
+            let text = format!("namespace gers_http permission http\n{}", text);
+            Interface::parse(&text).map(|interface| {
+                interface.namespaces[0].functions[0]
+                    .denied_fn()
+                    .expect("parsed functions have one")
+            })
+        };
+
+        assert_eq!(
+            denied("fn request(method: u32, url: str, body: bytes) -> i64").as_deref(),
+            Ok("denied_5_i64")
+        );
+        assert_eq!(
+            denied("fn response_body(request: u64, buf: buf) -> i32").as_deref(),
+            Ok("denied_handle_2")
+        );
+        assert_eq!(
+            denied("fn cancel(request: u64) -> i32").as_deref(),
+            Ok("denied_handle_0")
+        );
+        assert!(denied("fn now() -> i32").is_err());
+        assert!(denied("fn wait(millis: u32, request: u64) -> i32").is_err());
+        assert!(denied("fn lookup(a: u64, b: str, c: str, d: u32) -> i32").is_err());
+        assert!(denied("fn scale(scale: f32) -> i64").is_err());
+    }
+
+    #[test]
+    fn test_invalid_interfaces() {
+        for text in [
+            "fn log_info(message: str)",
+            "namespace gers\nfn log_info(message: string)",
+            "namespace gers\nfn get_name() -> str",
+            "namespace gers\nfn log_info(message: str)\nfn log_info(message: str)",
+            "namespace gers\nnamespace gers",
+            "namespace gers permission root",
+            "namespace gers permission time\nfn delta() -> f32",
+            "namespace gers\nfn log_info(message: str) extra",
+        ] {
+            assert!(Interface::parse(text).is_err(), "{:?} should fail", text);
+        }
+    }
+
+    #[test]
+    fn test_gers_interface() {
+        let interface = Interface::gers();
+        assert!(interface
+            .namespaces
+            .iter()
+            .any(|namespace| namespace.name == "gers"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Cost of crossing the boundary between the host and guests.
+//!
+//! Measures calls in both directions, sending events one at a time and
+//! in batches, and copying bytes in and out of guest memory, so changes
+//! to the protocol can be weighed against numbers:
+//!
+//! ```shell
+//! cargo bench -p gers_plugins --bench boundary
+//! ```
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gers_events::{HelloEvent, EVENT_BUFFER_ALIGN};
+use gers_plugins::{strings, Plugin, Plugins, EVENT_BUFFER_SIZE};
+use wasmer::{Exports, Function, WasmPtr};
+
+/// Guest counting the events it's sent, one at a time and in batches,
+/// with an empty function for the host to call, and one calling the
+/// host's empty function `HOST_CALLS` times.
+const GUEST: &str = r#"(module
+    (import "bench" "noop" (func $host_noop))
+    (memory (export "memory") 1)
+    (global $handled (mut i32) (i32.const 0))
+    (func (export "noop"))
+    (func (export "call_host") (param $count i32)
+        (block $done (loop $next
+            (br_if $done (i32.eqz (local.get $count)))
+            (call $host_noop)
+            (local.set $count (i32.sub (local.get $count) (i32.const 1)))
+            (br $next))))
+    (func (export "__gers_event_alloc") (param i32 i32) (result i32) (i32.const 64))
+    (func (export "__gers_event_update") (param i32 i32) (result i32)
+        (global.set $handled (i32.add (global.get $handled) (i32.const 1)))
+        (i32.const 0))
+    (func (export "__gers_event_arena") (result i64)
+        ;; 32KB at 8192.
+        (i64.const 0x0000800000002000))
+    (func (export "__gers_event_batch") (param $count i32) (result i32)
+        (global.set $handled (i32.add (global.get $handled) (local.get $count)))
+        (i32.const 0)))"#;
+
+/// Calls into the host per call of the guest's `call_host`.
+const HOST_CALLS: u64 = 1000;
+
+/// Events sent per frame in the dispatch benchmarks.
+const EVENT_COUNTS: [u64; 4] = [1, 16, 64, 256];
+
+/// Sizes of the buffers copied in the memory benchmarks.
+const COPY_SIZES: [usize; 3] = [16, 256, 4096];
+
+const EVENT: HelloEvent = HelloEvent {
+    data: 1,
+    padding: 0,
+    div: 0,
+};
+
+fn load_guest() -> Plugins {
+    let root = std::env::temp_dir().join(format!("gers-boundary-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(
+        root.join(gers_plugins::PLUGIN_FILENAME),
+        "name = \"boundary\"\nversion = \"1.0.0\"\n",
+    )
+    .unwrap();
+    fs::write(root.join("main.wasm"), GUEST).unwrap();
+
+    let mut plugins = Plugins::new();
+    plugins
+        .imports_mut()
+        .register("bench", |store, _| {
+            let mut exports = Exports::new();
+            exports.insert("noop", Function::new_native(store, || {}));
+            exports
+        })
+        .unwrap();
+    let result = plugins.load_plugin_dir(&root);
+    fs::remove_dir_all(&root).unwrap();
+    result.unwrap();
+
+    plugins
+}
+
+fn guest(plugins: &mut Plugins) -> &mut Plugin {
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+    plugin
+}
+
+fn bench_calls(c: &mut Criterion) {
+    let mut plugins = load_guest();
+    let plugin = guest(&mut plugins);
+    let exports = &plugin.instance().unwrap().exports;
+    let noop = exports.get_native_function::<(), ()>("noop").unwrap();
+    let call_host = exports.get_native_function::<u32, ()>("call_host").unwrap();
+
+    let mut group = c.benchmark_group("calls");
+    group.bench_function("host_to_guest", |b| b.iter(|| noop.call().unwrap()));
+    group.throughput(Throughput::Elements(HOST_CALLS));
+    group.bench_function("guest_to_host", |b| {
+        b.iter(|| call_host.call(HOST_CALLS as u32).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_event_round_trip(c: &mut Criterion) {
+    let mut plugins = load_guest();
+    let plugin = guest(&mut plugins);
+
+    // Allocating the buffer, writing the event to it, and handling it.
+    c.bench_function("event_round_trip", |b| {
+        b.iter(|| {
+            let alloc_fn = plugin.event_alloc_fn().unwrap();
+            plugin.data_ptr = Some(
+                alloc_fn
+                    .call(EVENT_BUFFER_SIZE, EVENT_BUFFER_ALIGN)
+                    .unwrap(),
+            );
+            assert!(plugin.dispatch_event(1, &EVENT).unwrap());
+        })
+    });
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let mut plugins = load_guest();
+    let plugin = guest(&mut plugins);
+    assert!(plugin.has_event_arena());
+
+    let mut group = c.benchmark_group("dispatch");
+    for count in EVENT_COUNTS {
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::new("unbatched", count), &count, |b, count| {
+            b.iter(|| {
+                for _ in 0..*count {
+                    plugin.dispatch_event(1, &EVENT).unwrap();
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("batched", count), &count, |b, count| {
+            b.iter(|| {
+                for _ in 0..*count {
+                    assert!(plugin.queue_event(1, &EVENT).unwrap());
+                }
+                plugin.flush_events().unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_memory(c: &mut Criterion) {
+    let mut plugins = load_guest();
+    let plugin = guest(&mut plugins);
+    let memory = plugin.memory().unwrap();
+    let ptr = WasmPtr::new(0xA000);
+
+    let mut group = c.benchmark_group("memory");
+    for size in COPY_SIZES {
+        let bytes = vec![7; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("write", size), &bytes, |b, bytes| {
+            b.iter(|| assert!(strings::write_bytes(memory, ptr, bytes)))
+        });
+        group.bench_with_input(BenchmarkId::new("read", size), &size, |b, size| {
+            b.iter(|| strings::read_bytes(memory, ptr, *size as u32).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_calls,
+    bench_event_round_trip,
+    bench_dispatch,
+    bench_memory
+);
+criterion_main!(benches);
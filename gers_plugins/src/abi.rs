@@ -0,0 +1,231 @@
+//! Signatures of the hooks guests export.
+//!
+//! Hooks are looked up by name once the module is instantiated, which
+//! only notices a wrong signature for the hooks the host looks up, and
+//! hides misspelled ones. Every `__gers_*` export is checked against the
+//! table here when the module is compiled instead, and the plugin is
+//! refused with all the mismatches at once.
+//!
+//! Every hook is optional, unless the plugin is `strict` in its
+//! `plugin.toml`, in which case the hooks it lists must be exported.
+use wasmer::{
+    ExternType, FunctionType, Module, Type,
+    Type::{F32, I32, I64},
+};
+
+use crate::{PluginError, PluginMeta};
+
+/// Signature of a hook, as its parameters and results.
+type Signature = (&'static [Type], &'static [Type]);
+
+/// Prefix of the names of hooks.
+const HOOK_PREFIX: &str = "__gers_";
+
+/// Hooks, with the signatures they may have.
+const HOOKS: &[(&str, &[Signature])] = &[
+    // Updates that return nothing are always done.
+    ("__gers_abi_version", &[(&[], &[I32])]),
+    // Guests from before ABI version 6 don't take their configuration.
+    (
+        "__gers_init",
+        &[(&[I32, I32, I32, I32], &[]), (&[I32, I32], &[])],
+    ),
+    ("__gers_update", &[(&[], &[]), (&[], &[I32])]),
+    ("__gers_resume", &[(&[I32], &[I32])]),
+    ("__gers_render", &[(&[F32], &[])]),
+    // Guests from before ABI version 3 don't take the alignment.
+    (
+        "__gers_event_alloc",
+        &[(&[I32, I32], &[I32]), (&[I32], &[I32])],
+    ),
+    ("__gers_event_update", &[(&[I32, I32], &[I32])]),
+    ("__gers_event_arena", &[(&[], &[I64])]),
+    ("__gers_event_batch", &[(&[I32], &[I32])]),
+    ("__gers_event_manifest", &[(&[], &[I64])]),
+    ("__gers_event_layouts", &[(&[], &[I64])]),
+    ("__gers_alloc", &[(&[I32], &[I32])]),
+    ("__gers_free", &[(&[I32, I32], &[])]),
+    ("__gers_save", &[(&[], &[])]),
+    ("__gers_load", &[(&[I32, I32], &[])]),
+    ("__gers_shutdown", &[(&[I32], &[])]),
+    ("__gers_compact", &[(&[I32], &[I32])]),
+    ("__gers_bump_stats", &[(&[], &[I32])]),
+    ("__gers_bump_region", &[(&[], &[I64])]),
+    ("__gers_frame_globals", &[(&[], &[I32])]),
+];
+
+/// Check the module's hooks against their signatures, before it's
+/// instantiated.
+pub(crate) fn validate_exports(module: &Module) -> Result<(), PluginError> {
+    let mut problems = vec![];
+
+    for export in module.exports() {
+        let name = export.name();
+        if !name.starts_with(HOOK_PREFIX) {
+            continue;
+        }
+
+        let signatures = match HOOKS.iter().find(|(hook, _)| *hook == name) {
+            Some((_, signatures)) => signatures,
+            None => {
+                problems.push(format!("{} is not a known hook", name));
+                continue;
+            }
+        };
+        let expected = signatures
+            .iter()
+            .map(|(params, results)| format_signature(params, results))
+            .collect::<Vec<_>>()
+            .join(" or ");
+
+        match export.ty() {
+            ExternType::Function(ty) => {
+                if !signatures
+                    .iter()
+                    .any(|signature| has_signature(ty, signature))
+                {
+                    problems.push(format!(
+                        "{}: expected {}, found {}",
+                        name,
+                        expected,
+                        format_signature(ty.params(), ty.results())
+                    ));
+                }
+            }
+            _ => problems.push(format!("{}: expected function {}", name, expected)),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(PluginError::InvalidHooks(problems.join("; ")))
+    }
+}
+
+/// Hooks strict plugins must export when they don't list their own.
+const CORE_HOOKS: &[&str] = &["update", "event_alloc", "event_update"];
+
+/// Check a strict plugin's module exports the hooks it lists, before
+/// it's instantiated.
+pub(crate) fn check_required(module: &Module, meta: &PluginMeta) -> Result<(), PluginError> {
+    if !meta.strict {
+        return Ok(());
+    }
+
+    let required: Vec<&str> = if meta.hooks.is_empty() {
+        CORE_HOOKS.to_vec()
+    } else {
+        meta.hooks.iter().map(String::as_str).collect()
+    };
+    let mut problems = vec![];
+    for hook in required {
+        let name = format!("{}{}", HOOK_PREFIX, hook);
+        if !HOOKS.iter().any(|(known, _)| *known == name) {
+            problems.push(format!("{} is not a known hook", hook));
+        } else if !module.exports().any(|export| export.name() == name) {
+            problems.push(name);
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(PluginError::MissingHooks(problems.join(", ")))
+    }
+}
+
+/// Known hooks a plugin exports and doesn't, from `Plugins::lint`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HookLint {
+    pub found: Vec<&'static str>,
+    pub missing: Vec<&'static str>,
+}
+
+/// Sort the known hooks by whether the instance exports them.
+pub(crate) fn lint(instance: Option<&wasmer::Instance>) -> HookLint {
+    let mut lint = HookLint::default();
+    for (name, _) in HOOKS.iter() {
+        if matches!(instance, Some(instance) if instance.exports.get_function(name).is_ok()) {
+            lint.found.push(name);
+        } else {
+            lint.missing.push(name);
+        }
+    }
+
+    lint
+}
+
+fn has_signature(ty: &FunctionType, (params, results): &Signature) -> bool {
+    ty.params() == *params && ty.results() == *results
+}
+
+/// Signature as `(i32, i32) -> i32`.
+fn format_signature(params: &[Type], results: &[Type]) -> String {
+    let format_types = |types: &[Type]| {
+        types
+            .iter()
+            .map(|ty| match ty {
+                I32 => "i32",
+                I64 => "i64",
+                F32 => "f32",
+                Type::F64 => "f64",
+                Type::V128 => "v128",
+                Type::ExternRef => "externref",
+                Type::FuncRef => "funcref",
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match results.len() {
+        1 => format!("({}) -> {}", format_types(params), format_types(results)),
+        _ => format!("({}) -> ({})", format_types(params), format_types(results)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::Store;
+
+    fn validate(wat: &str) -> Result<(), PluginError> {
+        let module = Module::new(&Store::default(), wat).unwrap();
+        validate_exports(&module)
+    }
+
+    #[test]
+    fn test_valid_hooks() {
+        validate(
+            r#"(module
+            (memory (export "memory") 1)
+            (func (export "__gers_update") (result i32) (i32.const 0))
+            (func (export "__gers_event_update") (param i32 i32) (result i32) (i32.const 0))
+            (func (export "other") (param i64)))"#,
+        )
+        .unwrap();
+        validate(r#"(module (func (export "__gers_update")))"#).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_hooks_are_reported() {
+        let err = validate(
+            r#"(module
+            (func (export "__gers_event_alloc") (param i32 i64) (result i32) (i32.const 0))
+            (func (export "__gers_updte"))
+            (global (export "__gers_render") i32 (i32.const 0)))"#,
+        )
+        .unwrap_err();
+
+        match err {
+            PluginError::InvalidHooks(report) => assert_eq!(
+                report,
+                "__gers_event_alloc: expected (i32, i32) -> i32 or (i32) -> i32, \
+                 found (i32, i64) -> i32; \
+                 __gers_updte is not a known hook; \
+                 __gers_render: expected function (f32) -> ()"
+            ),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,527 @@
+//! Batched event dispatch through a guest arena.
+//!
+//! Dispatching events one at a time calls the guest's
+//! `__gers_event_update` for every event and plugin receiving it.
+//! Guests that export `__gers_event_arena() -> u64` instead hand the
+//! host a persistent buffer in their memory once, when they're loaded,
+//! with the pointer in the low and the length in the high 32 bits. The
+//! host writes the frame's events into the arena at its own cursor, and
+//! delivers them with a single `__gers_event_batch(count)` call. The
+//! batch is only flushed early when the arena fills up. Events the host
+//! needs an answer to, like mutable events, are still sent one at a time
+//! through `__gers_event_update`, which guests keep exporting.
+//!
+//! Each record is an `EventHeader` followed by the event data, starting
+//! at an address aligned to `EVENT_RECORD_ALIGN`. Guests built against
+//! an older ABI version get the header of their version, see
+//! `EventHeader::size_for`. The arena is addressed by offset, so it
+//! stays valid when the guest grows its memory.
+//!
+//! Records are reserved as `ArenaHandle`s, tagged with the generation
+//! of the arena, which advances every time the arena is reset. Reading
+//! or writing through a handle from before the last reset fails with
+//! `ArenaError::StaleHandle`, rather than touching whatever records
+//! were written since.
+//!
+//! A frame with `n` events for a plugin costs one call into the guest
+//! instead of `n`, and writing a record is a copy into linear memory.
+//! The ignored `bench_event_dispatch` test compares the throughput of
+//! both protocols:
+//!
+//! ```shell
+//! cargo test -p gers_plugins --release -- --ignored --nocapture bench_event_dispatch
+//! ```
+//!
+//! The `boundary` benchmark compares them across batch sizes.
+use gers_events::{
+    encode_event, EncodedEvent, EventHeader, EVENT_FLAG_ENCODED, EVENT_RECORD_ALIGN,
+    EVENT_SOURCE_HOST,
+};
+use std::cell::Cell;
+use thiserror::Error;
+use wasmer::{Array, Memory, RuntimeError, WasmPtr};
+
+use crate::{strings, Plugin, HEADED_EVENTS_ABI_VERSION};
+
+/// Arena exported by the guest, with the host's cursor into it.
+#[derive(Debug)]
+pub(crate) struct EventArena {
+    ptr: u32,
+    len: u32,
+    /// Offset of the end of the last record from the arena's start.
+    cursor: Cell<u32>,
+    /// Records written since the last batch.
+    count: Cell<u32>,
+    /// Number of times the arena was reset.
+    generation: Cell<u32>,
+    /// Size of the header of the guest's ABI version.
+    header_size: u32,
+}
+
+/// Record reserved in the arena, valid until the arena is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ArenaHandle {
+    /// Offset of the record from the arena's start.
+    offset: u32,
+    len: u32,
+    generation: u32,
+}
+
+/// Access to the arena through a handle that failed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ArenaError {
+    #[error("stale event arena handle from generation {handle}, arena is at generation {arena}")]
+    StaleHandle { handle: u32, arena: u32 },
+
+    #[error("event arena out of bounds")]
+    OutOfBounds,
+}
+
+impl EventArena {
+    /// Unpack the arena returned by `__gers_event_arena`, for a guest
+    /// built against `abi_version`.
+    ///
+    /// Returns `None` when the guest has no arena to give.
+    pub(crate) fn from_packed(packed: u64, abi_version: u32) -> Option<Self> {
+        let (ptr, len) = (packed as u32, (packed >> 32) as u32);
+        if ptr == 0 || len == 0 || ptr.checked_add(len).is_none() {
+            return None;
+        }
+
+        Some(Self {
+            ptr,
+            len,
+            cursor: Cell::new(0),
+            count: Cell::new(0),
+            generation: Cell::new(0),
+            header_size: EventHeader::size_for(abi_version) as u32,
+        })
+    }
+
+    /// Offsets of the start and end of a record with `size` bytes of
+    /// event data, or `None` when it doesn't fit in the space left.
+    fn reserve(&self, size: u32) -> Option<(u32, u32)> {
+        // Records are aligned by address, which the guest reads them at.
+        let address = self.ptr as u64 + self.cursor.get() as u64;
+        let align = EVENT_RECORD_ALIGN as u64;
+        let start = address.div_ceil(align) * align - self.ptr as u64;
+        let end = start + self.header_size as u64 + size as u64;
+
+        if end <= self.len as u64 {
+            Some((start as u32, end as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Reserve a record with `size` bytes of event data, or `None` when
+    /// it doesn't fit in the space left.
+    fn alloc(&self, size: u32) -> Option<ArenaHandle> {
+        let (start, end) = self.reserve(size)?;
+        self.cursor.set(end);
+
+        Some(ArenaHandle {
+            offset: start,
+            len: end - start,
+            generation: self.generation.get(),
+        })
+    }
+
+    /// Count a record whose header and data were written.
+    fn commit(&self) {
+        self.count.set(self.count.get() + 1);
+    }
+
+    /// Start over at the beginning of the arena, invalidating the
+    /// handles reserved so far.
+    fn reset(&self) {
+        self.cursor.set(0);
+        self.count.set(0);
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+
+    /// Address in guest memory of `len` bytes at `at` into the record.
+    fn address(&self, handle: ArenaHandle, at: u32, len: u32) -> Result<u32, ArenaError> {
+        if handle.generation != self.generation.get() {
+            return Err(ArenaError::StaleHandle {
+                handle: handle.generation,
+                arena: self.generation.get(),
+            });
+        }
+        match at.checked_add(len) {
+            Some(end) if end <= handle.len => Ok(self.ptr + handle.offset + at),
+            _ => Err(ArenaError::OutOfBounds),
+        }
+    }
+
+    /// Copy a value into the record at `at`.
+    fn write<T: Clone>(
+        &self,
+        memory: &Memory,
+        handle: ArenaHandle,
+        at: u32,
+        value: &T,
+    ) -> Result<(), ArenaError> {
+        let address = self.address(handle, at, std::mem::size_of::<T>() as u32)?;
+        if write_value(memory, WasmPtr::new(address), value) {
+            Ok(())
+        } else {
+            Err(ArenaError::OutOfBounds)
+        }
+    }
+
+    /// Copy bytes into the record at `at`.
+    fn write_bytes(
+        &self,
+        memory: &Memory,
+        handle: ArenaHandle,
+        at: u32,
+        bytes: &[u8],
+    ) -> Result<(), ArenaError> {
+        let address = self.address(handle, at, bytes.len() as u32)?;
+        if strings::write_bytes(memory, WasmPtr::new(address), bytes) {
+            Ok(())
+        } else {
+            Err(ArenaError::OutOfBounds)
+        }
+    }
+
+    /// Copy `len` bytes out of the record at `at`.
+    fn read(
+        &self,
+        memory: &Memory,
+        handle: ArenaHandle,
+        at: u32,
+        len: u32,
+    ) -> Result<Vec<u8>, ArenaError> {
+        let address = self.address(handle, at, len)?;
+        strings::read_bytes(memory, WasmPtr::new(address), len).ok_or(ArenaError::OutOfBounds)
+    }
+}
+
+/// Copy a value into guest memory at `ptr`.
+///
+/// Returns `false` when the range is out of bounds, or misaligned
+/// for the type.
+pub(crate) fn write_value<T: Clone>(memory: &Memory, ptr: WasmPtr<u8, Array>, value: &T) -> bool {
+    let cell_slice = match unsafe { ptr.deref_mut(memory, 0, std::mem::size_of::<T>() as u32) } {
+        Some(cell_slice) => cell_slice,
+        None => return false,
+    };
+    let data_slice: &mut [u8] = unsafe { std::mem::transmute(cell_slice) };
+    let (_, struct_slice, _) = unsafe { data_slice.align_to_mut::<T>() };
+
+    if struct_slice.is_empty() {
+        return false;
+    }
+
+    // Copy into memory. The previous contents are uninterpreted
+    // bytes, so they must not be dropped.
+    unsafe { std::ptr::write(&mut struct_slice[0], value.clone()) };
+
+    true
+}
+
+impl Plugin {
+    /// The guest exports an event arena, and receives events in batches.
+    pub fn has_event_arena(&self) -> bool {
+        self.event_arena.is_some()
+    }
+
+    /// Events were written into the arena since the last batch.
+    pub fn has_queued_events(&self) -> bool {
+        self.event_arena
+            .as_ref()
+            .is_some_and(|arena| arena.count.get() > 0)
+    }
+
+    /// Write an event into the guest's arena, to be delivered with the
+    /// next `flush_events`.
+    ///
+    /// Flushes the batch first when the arena is full. Returns `false`
+    /// when the guest has no arena, or the event doesn't fit in it, in
+    /// which case the event should be sent with `dispatch_event`.
+    pub fn queue_event<T: Clone>(&self, event_type: i32, event: &T) -> Result<bool, RuntimeError> {
+        self.queue_event_from(EVENT_SOURCE_HOST, event_type, event)
+    }
+
+    /// Write an event a plugin emitted into the guest's arena, like
+    /// `queue_event`, with the `plugin_id` of its source.
+    pub fn queue_event_from<T: Clone>(
+        &self,
+        source: u32,
+        event_type: i32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        let size = std::mem::size_of::<T>() as u32;
+        self.queue_with(source, 0, event_type, size, |arena, memory, handle, at| {
+            arena.write(memory, handle, at, event)
+        })
+    }
+
+    /// Write an event encoded with postcard into the guest's arena, like
+    /// `queue_event`, see `EncodedEvent`.
+    ///
+    /// Returns `false` for guests from before ABI version 5, which can't
+    /// tell the event is encoded.
+    pub fn queue_encoded_event<T: EncodedEvent>(&self, event: &T) -> Result<bool, RuntimeError> {
+        self.queue_encoded_event_from(EVENT_SOURCE_HOST, event)
+    }
+
+    /// Write an encoded event a plugin emitted into the guest's arena,
+    /// like `queue_encoded_event`, with the `plugin_id` of its source.
+    pub fn queue_encoded_event_from<T: EncodedEvent>(
+        &self,
+        source: u32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        if self.abi_version < HEADED_EVENTS_ABI_VERSION {
+            return Ok(false);
+        }
+        let bytes = encode_event(event).map_err(|err| RuntimeError::new(err.to_string()))?;
+
+        let size = bytes.len() as u32;
+        self.queue_with(
+            source,
+            EVENT_FLAG_ENCODED,
+            T::TYPE as i32,
+            size,
+            |arena, memory, handle, at| arena.write_bytes(memory, handle, at, &bytes),
+        )
+    }
+
+    /// Allocate a record with the header and `size` bytes of event data,
+    /// written with `write` at the offset it's given.
+    fn queue_with(
+        &self,
+        source: u32,
+        flags: u32,
+        event_type: i32,
+        size: u32,
+        write: impl FnOnce(&EventArena, &Memory, ArenaHandle, u32) -> Result<(), ArenaError>,
+    ) -> Result<bool, RuntimeError> {
+        let arena = match self.event_arena {
+            Some(ref arena) if self.accepts_layout(event_type) => arena,
+            _ => return Ok(false),
+        };
+
+        let memory = self
+            .memory()
+            .map_err(|err| RuntimeError::new(err.to_string()))?;
+        let handle = match arena.alloc(size) {
+            Some(handle) => handle,
+            None => {
+                self.flush_events()?;
+                match arena.alloc(size) {
+                    Some(handle) => handle,
+                    None => return Ok(false),
+                }
+            }
+        };
+
+        let header_size = arena.header_size;
+        let header = self.event_header(event_type, size, flags, source).encode();
+        arena
+            .write_bytes(memory, handle, 0, &header[..header_size as usize])
+            .and_then(|_| write(arena, memory, handle, header_size))
+            .map_err(|err| RuntimeError::new(err.to_string()))?;
+        arena.commit();
+        if self.event_history.is_enabled() {
+            if let Ok(data) = arena.read(memory, handle, header_size, size) {
+                self.event_history.record(event_type, data);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Deliver the events written into the arena with one call to the
+    /// guest's `__gers_event_batch`.
+    ///
+    /// Returns the number of events delivered.
+    pub fn flush_events(&self) -> Result<u32, RuntimeError> {
+        let (arena, batch_fn) = match (&self.event_arena, &self.event_batch_fn) {
+            (Some(arena), Some(batch_fn)) => (arena, batch_fn),
+            _ => return Ok(0),
+        };
+
+        let count = arena.count.get();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // A guest that traps doesn't get the same events again.
+        arena.reset();
+        let result = self.intercept("__gers_event_batch", || batch_fn.call(count));
+        self.sync_memory();
+        result?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PluginError, Plugins, TrustPolicy};
+    use gers_events::{HelloEvent, ABI_VERSION, LEGACY_ABI_VERSION};
+    use std::time::Instant;
+
+    /// Size of the headers of legacy guests.
+    const HEADER_SIZE: u32 = 8;
+
+    fn arena(ptr: u32, len: u32) -> EventArena {
+        EventArena::from_packed(ptr as u64 | (len as u64) << 32, LEGACY_ABI_VERSION).unwrap()
+    }
+
+    #[test]
+    fn test_unpack_arena() {
+        assert!(EventArena::from_packed(0, ABI_VERSION).is_none());
+        assert!(EventArena::from_packed(1024, ABI_VERSION).is_none());
+        assert!(EventArena::from_packed(u32::MAX as u64 | 16 << 32, ABI_VERSION).is_none());
+
+        let arena = arena(1024, 64);
+        assert_eq!((arena.ptr, arena.len), (1024, 64));
+    }
+
+    #[test]
+    fn test_records_are_aligned() {
+        // Arena starting at an unaligned address.
+        let arena = arena(1028, 64);
+
+        assert_eq!(arena.reserve(6), Some((4, 18)));
+        arena.alloc(6).unwrap();
+        arena.commit();
+        assert_eq!(arena.reserve(12), Some((20, 40)));
+        arena.alloc(12).unwrap();
+        arena.commit();
+        assert_eq!(arena.count.get(), 2);
+
+        // Doesn't fit in what's left.
+        assert_eq!(arena.reserve(20), None);
+        assert_eq!(arena.reserve(12), Some((44, 64)));
+
+        arena.reset();
+        assert_eq!(arena.reserve(20), Some((4, 32)));
+
+        // Current headers take 24 more bytes.
+        let arena = EventArena::from_packed(1028 | 64 << 32, ABI_VERSION).unwrap();
+        assert_eq!(arena.reserve(6), Some((4, 42)));
+    }
+
+    #[test]
+    fn test_stale_handles() {
+        let store = wasmer::Store::default();
+        let memory = Memory::new(&store, wasmer::MemoryType::new(1, None, false)).unwrap();
+        let arena = arena(1024, 64);
+
+        let handle = arena.alloc(4).unwrap();
+        arena.write(&memory, handle, HEADER_SIZE, &7u32).unwrap();
+        assert_eq!(
+            arena.read(&memory, handle, HEADER_SIZE, 4).unwrap(),
+            7u32.to_le_bytes()
+        );
+        // Past the end of the record.
+        assert_eq!(
+            arena.write(&memory, handle, HEADER_SIZE + 4, &7u32),
+            Err(ArenaError::OutOfBounds)
+        );
+
+        arena.reset();
+        let stale = ArenaError::StaleHandle {
+            handle: 0,
+            arena: 1,
+        };
+        assert_eq!(
+            arena.read(&memory, handle, HEADER_SIZE, 4),
+            Err(stale.clone())
+        );
+        assert_eq!(arena.write(&memory, handle, HEADER_SIZE, &9u32), Err(stale));
+
+        // A record reserved since is unaffected.
+        let handle = arena.alloc(4).unwrap();
+        arena.write(&memory, handle, HEADER_SIZE, &9u32).unwrap();
+    }
+
+    /// Guest handling events one at a time and in batches, counting them.
+    const BENCH_GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $handled (mut i32) (i32.const 0))
+          (func (export "__gers_event_alloc") (param i32) (result i32)
+            i32.const 64)
+          (func (export "__gers_event_update") (param i32 i32) (result i32)
+            global.get $handled
+            i32.const 1
+            i32.add
+            global.set $handled
+            i32.const 0)
+          (func (export "__gers_event_arena") (result i64)
+            ;; 16KB at 1024.
+            i64.const 0x0000400000000400)
+          (func (export "__gers_event_batch") (param $count i32) (result i32)
+            global.get $handled
+            local.get $count
+            i32.add
+            global.set $handled
+            i32.const 0))
+    "#;
+
+    fn load_bench_guest() -> Result<Plugins, PluginError> {
+        let root = std::env::temp_dir().join(format!("gers-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&root)?;
+        std::fs::write(
+            root.join(crate::PLUGIN_FILENAME),
+            "name = \"bench\"\nversion = \"1.0.0\"\n",
+        )?;
+        std::fs::write(root.join("main.wasm"), BENCH_GUEST)?;
+
+        let mut plugins = Plugins::new();
+        plugins.set_trust_policy(TrustPolicy::Development);
+        let result = plugins.load_plugin_dir(&root);
+        std::fs::remove_dir_all(&root)?;
+        result.map(|_| plugins)
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_event_dispatch() {
+        const EVENTS: u32 = 100_000;
+        const EVENTS_PER_FRAME: u32 = 64;
+
+        let mut plugins = load_bench_guest().unwrap();
+        let plugin = plugins.iter_plugins_mut().next().unwrap();
+        plugin.data_ptr = Some(WasmPtr::new(64));
+        assert!(plugin.has_event_arena());
+
+        let event = HelloEvent {
+            data: 1,
+            padding: 0,
+            div: 0,
+        };
+
+        let start = Instant::now();
+        for _ in 0..EVENTS {
+            plugin.dispatch_event(1, &event).unwrap();
+        }
+        let single = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..EVENTS / EVENTS_PER_FRAME {
+            for _ in 0..EVENTS_PER_FRAME {
+                assert!(plugin.queue_event(1, &event).unwrap());
+            }
+            plugin.flush_events().unwrap();
+        }
+        let batched = start.elapsed();
+
+        let per_second = |elapsed: std::time::Duration| EVENTS as f64 / elapsed.as_secs_f64();
+        println!(
+            "one at a time: {:.0} events/s, batched by {}: {:.0} events/s, {:.1}x",
+            per_second(single),
+            EVENTS_PER_FRAME,
+            per_second(batched),
+            single.as_secs_f64() / batched.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Configuring the plugin loader before any plugins are compiled.
+//!
+//! Settings that affect how modules are compiled are fixed once the
+//! store exists, so they're given to a builder instead of `Plugins`.
+//!
+//! Deterministic mode is for lockstep multiplayer and replays, where
+//! every peer has to compute the same results from the same inputs.
+//! WebAssembly float operations are deterministic apart from the bit
+//! patterns of NaNs, which differ between CPUs, so they're canonicalized
+//! by the compiler. Host imports are the other source of divergence.
+//! Plugins using WASI are refused, because its clocks and random numbers
+//! bypass the host, and the host has to take anything else it provides,
+//! like the delta time and random seed, from its recorded frames.
+//!
+//! With a watchdog timeout, modules are compiled with checks that let
+//! a guest call be interrupted once it runs longer than the timeout.
+use gers_events::Capabilities;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use wasmer::CompilerConfig;
+use wasmer_compiler_cranelift::Cranelift;
+use wasmer_engine_universal::Universal;
+
+use crate::{
+    globals::Globals,
+    intercept::Interceptors,
+    shared::SharedMemories,
+    watchdog::{InterruptChecks, Watchdog},
+    EnabledList, ImportsBuilder, MissingImports, Plugins, TrustPolicy,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct PluginsBuilder {
+    deterministic: bool,
+    watchdog_timeout: Option<Duration>,
+    event_history: usize,
+}
+
+impl PluginsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile modules so they compute the same results on every machine.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Interrupt guest calls running longer than the timeout, and
+    /// fault their plugins.
+    pub fn watchdog_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.watchdog_timeout = timeout;
+        self
+    }
+
+    /// Keep the last `len` events delivered to each plugin, for
+    /// crash dumps.
+    pub fn event_history(mut self, len: usize) -> Self {
+        self.event_history = len;
+        self
+    }
+
+    pub fn build(self) -> Plugins {
+        let mut compiler = Cranelift::new();
+        compiler.canonicalize_nans(self.deterministic);
+        if self.watchdog_timeout.is_some() {
+            compiler.push_middleware(Arc::new(InterruptChecks::default()));
+        }
+
+        // Plugins importing a shared memory segment also have their own.
+        let mut features = wasmer::Features::new();
+        features.multi_memory(true);
+
+        let store = wasmer::Store::new(&Universal::new(compiler).features(features).engine());
+
+        Plugins {
+            plugins: vec![],
+            globals: Globals::new(&store),
+            store,
+            imports: ImportsBuilder::default(),
+            disabled: HashSet::new(),
+            enabled: EnabledList::default(),
+            trust_policy: TrustPolicy::default(),
+            trusted_keys: vec![],
+            shared_memories: SharedMemories::default(),
+            deterministic: self.deterministic,
+            interceptors: Interceptors::default(),
+            watchdog: self
+                .watchdog_timeout
+                .map(|timeout| Arc::new(Watchdog::new(timeout))),
+            event_history: self.event_history,
+            capabilities: Capabilities::NONE,
+            missing_imports: MissingImports::default(),
+            conflicts: vec![],
+        }
+    }
+}
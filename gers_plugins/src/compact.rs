@@ -0,0 +1,49 @@
+//! Incremental heap compaction protocol.
+//!
+//! Long running sessions can fragment a guest's heap. Guests that
+//! support compaction export `__gers_compact(budget_us) -> pages`,
+//! which performs a bounded amount of compaction work and returns the
+//! number of pages it reclaimed. The host requests compaction when the
+//! plugin's linear memory comes under pressure, and keeps calling the
+//! hook during idle frames until the guest reports no more progress.
+
+/// Size of a WebAssembly page in bytes.
+pub const WASM_PAGE_SIZE: u64 = 0x10000;
+
+/// Compaction state of a single plugin.
+#[derive(Debug, Default, Clone)]
+pub struct Compaction {
+    /// Compaction was requested and the guest has not yet
+    /// reported that it's done.
+    pub(crate) pending: bool,
+    /// Memory size in pages when pressure was last signalled.
+    pub(crate) baseline_pages: Option<u32>,
+    /// Total pages reclaimed by the guest.
+    pub(crate) reclaimed_pages: u64,
+    /// Number of compaction steps executed.
+    pub(crate) steps: u64,
+}
+
+impl Compaction {
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Total pages reclaimed by the guest over the session.
+    #[inline]
+    pub fn reclaimed_pages(&self) -> u64 {
+        self.reclaimed_pages
+    }
+
+    /// Total bytes reclaimed by the guest over the session.
+    #[inline]
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.reclaimed_pages * WASM_PAGE_SIZE
+    }
+
+    #[inline]
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
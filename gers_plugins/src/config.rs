@@ -0,0 +1,119 @@
+//! Configuration passed to guests when they're initialised.
+//!
+//! Plugins describe their configuration under `[config]` in
+//! `plugin.toml`, which can hold any TOML. Settings the plugin declares
+//! override the keys of the same name with the values the user chose,
+//! so declaring a key as a setting lets the user change it:
+//!
+//! ```toml
+//! [config]
+//! spawn_rate = 2.5
+//! enemies = ["orc", "goblin"]
+//!
+//! [settings]
+//! spawn_rate = { type = "float", default = 2.5, min = 0.0 }
+//! ```
+//!
+//! Guests built since ABI version 6 export `__gers_init(version,
+//! capabilities, config_ptr, config_len)`, and receive the configuration
+//! as TOML text, in memory allocated with `__gers_alloc` and released
+//! once the call returns. They have their configuration before their
+//! first update, without reading files or waiting for a
+//! `SettingsChangedEvent`, which still tells them about later changes.
+use toml::value::{Table, Value};
+
+use crate::{Plugin, PluginError, PluginMeta, SettingValue, Settings};
+
+/// Configuration of the plugin as TOML text, with the values of its
+/// settings when they're known.
+pub(crate) fn config_text(
+    meta: &PluginMeta,
+    settings: Option<&Settings>,
+) -> Result<String, PluginError> {
+    let mut config: Table = meta.config.clone();
+    if let Some(settings) = settings {
+        for (key, decl) in meta.settings.iter() {
+            let value = match settings.get(&meta.name, key, decl) {
+                SettingValue::Int(value) => Value::Integer(value),
+                SettingValue::Float(value) => Value::Float(value),
+                SettingValue::Bool(value) => Value::Boolean(value),
+                SettingValue::String(value) => Value::String(value),
+            };
+            config.insert(key.clone(), value);
+        }
+    }
+
+    if config.is_empty() {
+        return Ok(String::new());
+    }
+
+    // Values are written before tables this way.
+    Ok(toml::to_string(&Value::Table(config))?)
+}
+
+impl Plugin {
+    /// Configuration passed to the guest when it's initialised, as TOML
+    /// text. Empty when the plugin has none.
+    pub fn config(&self) -> &str {
+        &self.config
+    }
+
+    /// Override the configuration with the values of the plugin's
+    /// settings, for when the plugin is initialised.
+    pub fn apply_settings(&mut self, settings: &Settings) -> Result<(), PluginError> {
+        self.config = config_text(&self.meta, Some(settings))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> PluginMeta {
+        toml::from_str(
+            r#"
+            name = "configured"
+            version = "1.0.0"
+
+            [config]
+            spawn_rate = 2.5
+            enemies = ["orc", "goblin"]
+
+            [config.boss]
+            name = "dragon"
+
+            [settings]
+            spawn_rate = { type = "float", default = 1.0 }
+            hardcore = { type = "bool", default = false }
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn parse(text: &str) -> Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_config_with_settings() {
+        let meta = meta();
+
+        let config = parse(&config_text(&meta, None).unwrap());
+        assert_eq!(config, meta.config);
+        assert_eq!(config["spawn_rate"], Value::Float(2.5));
+
+        let mut settings = Settings::default();
+        let decl = &meta.settings["spawn_rate"];
+        settings
+            .set("configured", "spawn_rate", decl, SettingValue::Int(4))
+            .unwrap();
+        let config = parse(&config_text(&meta, Some(&settings)).unwrap());
+        assert_eq!(config["spawn_rate"], Value::Float(4.0));
+        assert_eq!(config["hardcore"], Value::Boolean(false));
+        assert_eq!(config["boss"]["name"], Value::String("dragon".into()));
+
+        let meta: PluginMeta = toml::from_str("name = \"empty\"\nversion = \"1.0.0\"").unwrap();
+        assert_eq!(config_text(&meta, None).unwrap(), "");
+    }
+}
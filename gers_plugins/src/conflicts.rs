@@ -0,0 +1,171 @@
+//! Conflicts between plugins over the same resource.
+//!
+//! Plugins can define the same custom event type, bind the same key to
+//! different actions, or register the same console command, and only
+//! one of them can have it. The plugin with the highest `priority` in
+//! its `plugin.toml` wins, and among plugins with the same priority,
+//! the one loaded first. Conflicts are recorded, so users can find out
+//! why a mod's key binding didn't take effect.
+//!
+//! ```toml
+//! priority = 10
+//! ```
+//!
+//! Resources declared up front, in event manifests and the `[input]`
+//! section, are checked once the plugins are loaded. Console commands
+//! are registered at runtime, so the host records their conflicts as
+//! they happen.
+use std::{collections::BTreeMap, fmt};
+
+use crate::{split_instance, Plugin, Plugins};
+
+/// Something a plugin can claim for itself.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    /// Custom event type defined in an event manifest.
+    CustomEvent(String),
+    /// Key bound to an action in the `[input]` section.
+    Key(String),
+    /// Console command.
+    Command(String),
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::CustomEvent(name) => write!(f, "custom event {}", name),
+            Resource::Key(name) => write!(f, "key {}", name),
+            Resource::Command(name) => write!(f, "console command {}", name),
+        }
+    }
+}
+
+/// Resource claimed by more than one plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub resource: Resource,
+    /// Name of the plugin that has the resource.
+    pub winner: String,
+    /// Names of the plugins whose claims were ignored.
+    pub losers: Vec<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} wins over {}",
+            self.resource,
+            self.winner,
+            self.losers.join(", ")
+        )
+    }
+}
+
+impl Plugins {
+    /// Loaded plugins in the order they win conflicts.
+    pub fn priority_order(&self) -> Vec<&Plugin> {
+        let mut plugins: Vec<&Plugin> = self.plugins.iter().collect();
+        // Stable, so ties keep the load order.
+        plugins.sort_by_key(|plugin| std::cmp::Reverse(plugin.meta().priority));
+
+        plugins
+    }
+
+    /// Check the resources the loaded plugins declare for conflicts,
+    /// replacing the conflicts found by the last check.
+    pub fn detect_conflicts(&mut self) -> &[Conflict] {
+        // Plugin names with the action they bind a key to, if any, in
+        // priority order.
+        let mut claims: BTreeMap<Resource, Vec<(&str, Option<&str>)>> = BTreeMap::new();
+        for plugin in self.priority_order() {
+            let name = plugin.meta().name.as_str();
+            for event_name in plugin.event_manifest().defines.iter() {
+                claims
+                    .entry(Resource::CustomEvent(event_name.clone()))
+                    .or_default()
+                    .push((name, None));
+            }
+            // Sorted, so keys bound to several actions resolve the same
+            // way every run.
+            let mut input: Vec<_> = plugin.meta().input.iter().collect();
+            input.sort();
+            for (action, keys) in input {
+                for key in keys {
+                    claims
+                        .entry(Resource::Key(key.clone()))
+                        .or_default()
+                        .push((name, Some(action.as_str())));
+                }
+            }
+        }
+
+        let mut conflicts = vec![];
+        for (resource, claims) in claims {
+            let (winner, winning_action) = claims[0];
+            let mut losers: Vec<String> = vec![];
+            for (name, action) in claims {
+                // Plugins binding a key to the same action share it, and
+                // instances of a plugin share everything.
+                let shared = (action.is_some() && action == winning_action)
+                    || split_instance(name).0 == split_instance(winner).0;
+                if name != winner && !shared && !losers.iter().any(|n| n == name) {
+                    losers.push(name.to_string());
+                }
+            }
+
+            if !losers.is_empty() {
+                conflicts.push(Conflict {
+                    resource,
+                    winner: winner.to_string(),
+                    losers,
+                });
+            }
+        }
+
+        // Conflicts recorded by the host are still in effect.
+        self.conflicts
+            .retain(|conflict| matches!(conflict.resource, Resource::Command(_)));
+        self.conflicts.extend(conflicts);
+
+        &self.conflicts
+    }
+
+    /// Record a conflict the host resolved, over a resource claimed at
+    /// runtime.
+    ///
+    /// Returns `false` when it was already recorded.
+    pub fn record_conflict(&mut self, resource: Resource, winner: &str, loser: &str) -> bool {
+        let existing = self
+            .conflicts
+            .iter_mut()
+            .find(|conflict| conflict.resource == resource && conflict.winner == winner);
+        match existing {
+            Some(conflict) if conflict.losers.iter().any(|name| name == loser) => false,
+            Some(conflict) => {
+                conflict.losers.push(loser.to_string());
+                true
+            }
+            None => {
+                self.conflicts.push(Conflict {
+                    resource,
+                    winner: winner.to_string(),
+                    losers: vec![loser.to_string()],
+                });
+                true
+            }
+        }
+    }
+
+    /// Conflicts between the loaded plugins.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// The named plugin lost its claim on the resource.
+    pub fn has_lost(&self, plugin: &str, resource: &Resource) -> bool {
+        self.conflicts.iter().any(|conflict| {
+            conflict.resource == *resource && conflict.losers.iter().any(|name| name == plugin)
+        })
+    }
+}
@@ -0,0 +1,156 @@
+//! State kept for diagnosing plugins that trap.
+//!
+//! When a plugin traps, the host can write a crash dump for the
+//! plugin's author. Besides the error itself, a dump holds what led up
+//! to the trap: the last events delivered to the plugin, which are kept
+//! in a ring buffer as the guest saw them, and the hash of the module
+//! that was running.
+//!
+//! Guests with a bump allocator can export
+//! `__gers_bump_region() -> u64`, with the pointer to the allocator's
+//! region in the low and its length in the high 32 bits. The region is
+//! requested once when the plugin is loaded, because a guest that
+//! trapped shouldn't be called again to describe itself, and is copied
+//! out of linear memory for the dump.
+use std::{cell::RefCell, collections::VecDeque};
+use wasmer::{Array, WasmPtr};
+
+use crate::{strings, Plugin};
+
+/// Event as it was written into the guest's memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveredEvent {
+    pub event_type: i32,
+    pub data: Vec<u8>,
+}
+
+/// Ring buffer of the last events delivered to a plugin.
+#[derive(Debug, Default)]
+pub(crate) struct EventHistory {
+    capacity: usize,
+    events: RefCell<VecDeque<DeliveredEvent>>,
+}
+
+impl EventHistory {
+    /// History of `capacity` events, or none when it's zero.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: RefCell::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub(crate) fn record(&self, event_type: i32, data: Vec<u8>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut events = self.events.borrow_mut();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(DeliveredEvent { event_type, data });
+    }
+}
+
+/// Region of guest memory used by the guest's bump allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BumpRegion {
+    pub(crate) ptr: u32,
+    pub(crate) len: u32,
+}
+
+impl BumpRegion {
+    /// Unpack the region returned by `__gers_bump_region`.
+    ///
+    /// Returns `None` when the guest has no region to give.
+    pub(crate) fn from_packed(packed: u64) -> Option<Self> {
+        let (ptr, len) = (packed as u32, (packed >> 32) as u32);
+        if len == 0 || ptr.checked_add(len).is_none() {
+            return None;
+        }
+
+        Some(Self { ptr, len })
+    }
+}
+
+impl Plugin {
+    /// Record an event written into guest memory at `ptr`.
+    pub(crate) fn record_event(&self, event_type: i32, ptr: u32, size: u32) {
+        if !self.event_history.is_enabled() {
+            return;
+        }
+
+        let data = self
+            .memory()
+            .ok()
+            .and_then(|memory| strings::read_bytes(memory, WasmPtr::<u8, Array>::new(ptr), size));
+        if let Some(data) = data {
+            self.event_history.record(event_type, data);
+        }
+    }
+
+    /// Last events delivered to the plugin, oldest first.
+    ///
+    /// Empty unless the loader keeps an event history.
+    pub fn delivered_events(&self) -> Vec<DeliveredEvent> {
+        self.event_history.events.borrow().iter().cloned().collect()
+    }
+
+    /// Hex encoded SHA-256 hash of the plugin's module, or `None` for
+    /// data-only plugins.
+    pub fn module_hash(&self) -> Option<&str> {
+        self.module_hash.as_deref()
+    }
+
+    /// Copy of the guest's bump allocator region, with its address,
+    /// when the guest exports `__gers_bump_region`.
+    pub fn bump_region(&self) -> Option<(u32, Vec<u8>)> {
+        let region = self.bump_region?;
+        let memory = self.memory().ok()?;
+        let bytes = strings::read_bytes(memory, WasmPtr::new(region.ptr), region.len)?;
+
+        Some((region.ptr, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_last_events() {
+        let history = EventHistory::new(2);
+        for event_type in 1..=3 {
+            history.record(event_type, vec![event_type as u8]);
+        }
+        let types: Vec<_> = history
+            .events
+            .borrow()
+            .iter()
+            .map(|event| event.event_type)
+            .collect();
+        assert_eq!(types, [2, 3]);
+
+        let history = EventHistory::new(0);
+        history.record(1, vec![]);
+        assert!(history.events.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_unpack_bump_region() {
+        assert_eq!(
+            BumpRegion::from_packed(0x100 << 32 | 0x2000),
+            Some(BumpRegion {
+                ptr: 0x2000,
+                len: 0x100
+            })
+        );
+        assert_eq!(BumpRegion::from_packed(0x2000), None);
+        assert_eq!(BumpRegion::from_packed(0x100 << 32 | 0xffff_ff00), None);
+    }
+}
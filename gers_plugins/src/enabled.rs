@@ -0,0 +1,119 @@
+//! Persisted list of enabled plugins.
+//!
+//! Plugins that are discovered for the first time are added
+//! as enabled, so users can find and toggle them in the file.
+//! Plugins that aren't found anymore were uninstalled, and are
+//! pruned from the list.
+//!
+//! ```toml
+//! [plugins]
+//! core-plugin = true
+//! some-mod = false
+//! ```
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{errors::PluginError, instances::split_instance};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EnabledList {
+    #[serde(default)]
+    plugins: BTreeMap<String, bool>,
+
+    /// File the list is persisted to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    /// Changed since the last save.
+    #[serde(skip)]
+    dirty: bool,
+
+    /// Plugins found while loading.
+    #[serde(skip)]
+    found: HashSet<String>,
+}
+
+impl EnabledList {
+    /// Load the list from a file, starting empty when it doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        let path = path.as_ref();
+
+        let mut list: EnabledList = if path.is_file() {
+            toml::from_str(&fs::read_to_string(path)?)?
+        } else {
+            EnabledList::default()
+        };
+        list.path = Some(path.to_path_buf());
+
+        Ok(list)
+    }
+
+    /// Plugins not in the list are enabled.
+    ///
+    /// Instances are enabled with their plugin.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        let name = split_instance(name).0;
+        self.plugins.get(name).copied().unwrap_or(true)
+    }
+
+    /// Enable or disable the plugin, and every instance of it.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        let name = split_instance(name).0;
+        if self.plugins.insert(name.to_string(), enabled) != Some(enabled) {
+            self.dirty = true;
+        }
+    }
+
+    /// Record a plugin found while loading.
+    pub(crate) fn discover(&mut self, name: &str) {
+        self.found.insert(name.to_string());
+        if !self.plugins.contains_key(name) {
+            self.plugins.insert(name.to_string(), true);
+            self.dirty = true;
+        }
+    }
+
+    /// Remove the plugins that weren't found while loading.
+    ///
+    /// Returns the names of the removed plugins.
+    pub fn prune(&mut self) -> Vec<String> {
+        let removed: Vec<String> = self
+            .plugins
+            .keys()
+            .filter(|name| !self.found.contains(*name))
+            .cloned()
+            .collect();
+        for name in removed.iter() {
+            self.plugins.remove(name);
+            self.dirty = true;
+        }
+
+        removed
+    }
+
+    /// Iterate plugin names and whether they're enabled.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.plugins
+            .iter()
+            .map(|(name, enabled)| (name.as_str(), *enabled))
+    }
+
+    /// Write the list to its file, if it changed.
+    ///
+    /// Lists that weren't loaded from a file aren't persisted.
+    pub fn save(&mut self) -> Result<(), PluginError> {
+        let path = match (&self.path, self.dirty) {
+            (Some(path), true) => path,
+            _ => return Ok(()),
+        };
+
+        fs::write(path, toml::to_string(self)?)?;
+        self.dirty = false;
+
+        Ok(())
+    }
+}
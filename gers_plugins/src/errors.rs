@@ -1,19 +1,180 @@
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use wasmer::RuntimeError;
 
+/// Errors from loading and running plugins.
+///
+/// Errors from loading a plugin are wrapped in `PluginError::Plugin`,
+/// saying which plugin failed. Use `inner` to match on the cause.
 #[derive(Error, Debug)]
 pub enum PluginError {
+    #[error("{}: {error}", describe_plugin(.name, .path))]
+    Plugin {
+        /// Name from the meta file, when it was read.
+        name: Option<String>,
+        /// Directory or archive the plugin was loaded from.
+        path: PathBuf,
+        #[source]
+        error: Box<PluginError>,
+    },
+
+    #[error("plugin has no {0} meta file")]
+    MissingMetaFile(&'static str),
+
+    #[error("plugin has no {0} module, but its meta file needs one")]
+    MissingWasmModule(&'static str),
+
     #[error("failed to read plugin file: {0}")]
     LoadFile(#[from] std::io::Error),
 
+    #[error("unsupported plugin path: {0}")]
+    InvalidPath(String),
+
     #[error("failed to deserialize file: {0}")]
     Deserialize(#[from] toml::de::Error),
 
+    #[error("failed to serialize file: {0}")]
+    Serialize(#[from] toml::ser::Error),
+
     #[error("failed to compile WebAssembly: {0}")]
     Compile(#[from] wasmer::CompileError),
 
     #[error("failed to instantiate WebAssembly module: {0}")]
-    Instantiate(#[from] wasmer::InstantiationError),
+    Instantiate(#[from] Box<wasmer::InstantiationError>),
+
+    #[error("plugin integrity check failed: {0}")]
+    IntegrityCheckFailed(String),
+
+    #[error("plugin {0} is disabled")]
+    Disabled(String),
+
+    #[error("failed to set up WASI: {0}")]
+    Wasi(String),
+
+    #[error("plugin requires WASI, but gers was built without the wasi feature")]
+    WasiUnsupported,
+
+    #[error("failed to initialise plugin module: {0}")]
+    Initialize(#[from] RuntimeError),
+
+    #[error("failed to restore plugin snapshot: {0}")]
+    Restore(String),
+
+    #[error("invalid event manifest: {0}")]
+    EventManifest(String),
+
+    #[error("invalid event layouts: {0}")]
+    EventLayouts(String),
+
+    #[error("conflicting host imports: {0}")]
+    ImportConflict(String),
+
+    #[error("invalid shared memory: {0}")]
+    SharedMemory(String),
+
+    #[error("unresolved plugin dependency: {0}")]
+    Dependency(String),
+
+    #[error("plugin can't run deterministically: {0}")]
+    Nondeterministic(String),
+
+    #[error("plugin exports invalid hooks: {0}")]
+    InvalidHooks(String),
+
+    #[error("plugin is missing required hooks: {0}")]
+    MissingHooks(String),
+
+    #[error("invalid settings: {0}")]
+    InvalidSettings(String),
+
+    #[error("invalid setting value: {0}")]
+    InvalidSettingValue(String),
+
+    #[error("invalid plugin instance: {0}")]
+    InvalidInstance(String),
+
+    #[error(
+        "plugin was built for ABI version {0}, the host supports versions {} to {}",
+        gers_events::LEGACY_ABI_VERSION,
+        gers_events::ABI_VERSION
+    )]
+    UnsupportedAbi(u32),
 
     #[error("module entrypoint function is incorrect type")]
     FunctionType,
 }
+
+impl PluginError {
+    /// Wrap the error with the plugin it came from.
+    ///
+    /// Errors that already say which plugin failed are kept as they are.
+    pub(crate) fn in_plugin(self, name: Option<&str>, path: &Path) -> Self {
+        match self {
+            PluginError::Plugin { .. } => self,
+            error => PluginError::Plugin {
+                name: name.map(str::to_string),
+                path: path.to_path_buf(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// The error without the plugin context.
+    pub fn inner(&self) -> &PluginError {
+        match self {
+            PluginError::Plugin { error, .. } => error.inner(),
+            error => error,
+        }
+    }
+
+    /// Name of the plugin that failed, when its meta file was read.
+    pub fn plugin_name(&self) -> Option<&str> {
+        match self {
+            PluginError::Plugin { name, .. } => name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Directory or archive of the plugin that failed.
+    pub fn plugin_path(&self) -> Option<&Path> {
+        match self {
+            PluginError::Plugin { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+fn describe_plugin(name: &Option<String>, path: &Path) -> String {
+    match name {
+        Some(name) => format!("plugin {} ({})", name, path.display()),
+        None => format!("plugin at {}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_context() {
+        let path = Path::new("plugins/broken");
+        let err = PluginError::FunctionType.in_plugin(None, path);
+        assert_eq!(
+            err.to_string(),
+            "plugin at plugins/broken: module entrypoint function is incorrect type"
+        );
+
+        // Errors keep the context they were given first.
+        let err = err.in_plugin(Some("broken"), Path::new("elsewhere"));
+        assert!(matches!(err.inner(), PluginError::FunctionType));
+        assert_eq!(err.plugin_path(), Some(path));
+        assert_eq!(err.plugin_name(), None);
+
+        let err = PluginError::MissingMetaFile("plugin.toml").in_plugin(Some("broken"), path);
+        assert_eq!(
+            err.to_string(),
+            "plugin broken (plugins/broken): plugin has no plugin.toml meta file"
+        );
+        assert_eq!(err.plugin_name(), Some("broken"));
+    }
+}
@@ -0,0 +1,76 @@
+//! Values of the current frame, shared with guests without calls.
+//!
+//! Calling an import for tiny values, like the delta time or the
+//! number of fixed updates so far, is overkill in hot loops. The loader provides them as mutable globals in the
+//! `gers` namespace instead, which the host updates once per frame,
+//! before the plugins are updated:
+//!
+//! ```wat
+//! (import "gers" "delta_time" (global $delta_time (mut f32)))
+//! (import "gers" "frame_index" (global $frame_index (mut i64)))
+//! (import "gers" "tick_index" (global $tick_index (mut i64)))
+//! ```
+//!
+//! Rust can't import globals, so guests can also export
+//! `__gers_frame_globals`, returning the address of a `FrameGlobals`
+//! in their memory, which the host writes the same values into.
+use gers_events::FrameGlobals;
+use wasmer::{Global, ImportObject, Store, Val};
+
+/// Globals shared by every plugin in the store.
+pub(crate) struct Globals {
+    delta_time: Global,
+    frame_index: Global,
+    tick_index: Global,
+}
+
+impl Globals {
+    pub(crate) fn new(store: &Store) -> Self {
+        Self {
+            delta_time: Global::new_mut(store, Val::F32(0.0)),
+            frame_index: Global::new_mut(store, Val::I64(0)),
+            tick_index: Global::new_mut(store, Val::I64(0)),
+        }
+    }
+
+    pub(crate) fn set(&self, frame: &FrameGlobals) {
+        // Both are mutable and set with their own types, so this can't fail.
+        let _ = self.delta_time.set(Val::F32(frame.delta_time));
+        let _ = self.frame_index.set(Val::I64(frame.frame_index as i64));
+        let _ = self.tick_index.set(Val::I64(frame.tick_index as i64));
+    }
+
+    /// Imports through which guests read the globals.
+    pub(crate) fn import_object(&self) -> ImportObject {
+        wasmer::imports! {
+            "gers" => {
+                "delta_time" => self.delta_time.clone(),
+                "frame_index" => self.frame_index.clone(),
+                "tick_index" => self.tick_index.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_globals() {
+        let store = crate::Plugins::new().store;
+        let globals = Globals::new(&store);
+        globals.set(&FrameGlobals {
+            frame_index: 42,
+            tick_index: 80,
+            delta_time: 0.5,
+        });
+
+        assert_eq!(globals.delta_time.get(), Val::F32(0.5));
+        assert_eq!(globals.frame_index.get(), Val::I64(42));
+        assert_eq!(globals.tick_index.get(), Val::I64(80));
+        let imports = globals.import_object();
+        assert!(imports.get_export("gers", "delta_time").is_some());
+        assert!(imports.get_export("gers", "frame_index").is_some());
+    }
+}
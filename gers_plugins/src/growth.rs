@@ -0,0 +1,86 @@
+//! Tracking growth of guest memory.
+//!
+//! Views into a plugin's linear memory dangle once the guest grows it,
+//! so the host must not hold on to them across calls into the guest.
+//! The host re-queries the memory size after every call that may grow
+//! it, and guests can report growth as it happens by calling the
+//! `gers.memory_grown(new_pages)` import. Each observed growth bumps a
+//! generation, which host caches compare against to know when to drop
+//! what they derived from the memory.
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc,
+};
+use wasmer::{Function, ImportObject, Store, WasmerEnv};
+
+/// Last known size of a plugin's linear memory.
+#[derive(Debug, Default)]
+pub struct MemoryGrowth {
+    pages: AtomicU32,
+    generation: AtomicU64,
+}
+
+impl MemoryGrowth {
+    /// Record the current size of the memory in pages.
+    ///
+    /// Returns `true` when the size changed since it was last observed.
+    pub fn observe(&self, pages: u32) -> bool {
+        let changed = self.pages.swap(pages, Ordering::Relaxed) != pages;
+        if changed {
+            self.generation.fetch_add(1, Ordering::Relaxed);
+        }
+        changed
+    }
+
+    /// Last observed size of the memory in pages.
+    pub fn pages(&self) -> u32 {
+        self.pages.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the memory was observed to change size.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(WasmerEnv, Clone)]
+struct GrowthEnv {
+    growth: Arc<MemoryGrowth>,
+}
+
+fn memory_grown(env: &GrowthEnv, new_pages: u32) {
+    env.growth.observe(new_pages);
+}
+
+/// Imports through which the guest reports memory growth.
+pub(crate) fn import_object(store: &Store, growth: &Arc<MemoryGrowth>) -> ImportObject {
+    let env = GrowthEnv {
+        growth: growth.clone(),
+    };
+
+    wasmer::imports! {
+        "gers" => {
+            "memory_grown" => Function::new_native_with_env(store, env, memory_grown),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_growth_bumps_generation() {
+        let growth = MemoryGrowth::default();
+        assert!(growth.observe(2));
+        assert_eq!(growth.generation(), 1);
+
+        // Same size as last observed.
+        assert!(!growth.observe(2));
+        assert_eq!(growth.generation(), 1);
+
+        assert!(growth.observe(3));
+        assert_eq!(growth.pages(), 3);
+        assert_eq!(growth.generation(), 2);
+    }
+}
@@ -0,0 +1,141 @@
+//! Protocol version and capability handshake.
+//!
+//! Guests export `__gers_abi_version() -> u32`, the version of the
+//! protocol they were built against, which is checked when the plugin
+//! is loaded. Guests built for a newer host are refused, rather than
+//! failing on hooks or events this host doesn't know. Guests that don't
+//! export it predate the handshake, and run in compatibility mode.
+//!
+//! When the plugin is initialised, the host calls the guest's
+//! `__gers_init(version: u32, capabilities: u32)` with its own version,
+//! and the subsystems it provides to the plugin, so the guest can
+//! detect what it may use. Subsystems behind a permission the plugin
+//! wasn't granted are left out. Guests built since ABI version 6 may
+//! also take their configuration, see `config`.
+use gers_events::{Capabilities, ABI_VERSION, LEGACY_ABI_VERSION};
+use wasmer::{NativeFunc, RuntimeError, WasmPtr};
+
+use crate::{Permission, Permissions, Plugin, PluginError};
+
+/// The guest's `__gers_init`.
+#[derive(Clone)]
+pub(crate) enum InitFn {
+    Handshake(NativeFunc<(u32, u32), ()>),
+    /// Also takes a pointer to the configuration and its length.
+    Configured(NativeFunc<(u32, u32, u32, u32), ()>),
+}
+
+/// Capabilities guarded by a permission.
+const GUARDED: &[(Permission, Capabilities)] = &[
+    (Permission::Storage, Capabilities::STORAGE),
+    (Permission::Network, Capabilities::NETWORK),
+    (Permission::Http, Capabilities::HTTP),
+    (Permission::Window, Capabilities::WINDOW),
+    (Permission::Clipboard, Capabilities::CLIPBOARD),
+];
+
+/// Check the version a guest reported, or the legacy version when it
+/// reports none.
+pub(crate) fn check_version(version: Option<u32>) -> Result<u32, PluginError> {
+    match version {
+        None => Ok(LEGACY_ABI_VERSION),
+        Some(version) if (LEGACY_ABI_VERSION..=ABI_VERSION).contains(&version) => Ok(version),
+        Some(version) => Err(PluginError::UnsupportedAbi(version)),
+    }
+}
+
+/// The host's capabilities, without those behind permissions that
+/// weren't granted.
+pub(crate) fn granted_capabilities(
+    capabilities: Capabilities,
+    permissions: &Permissions,
+) -> Capabilities {
+    let mut granted = capabilities;
+    for (permission, capability) in GUARDED.iter() {
+        if !permissions.is_granted(*permission) {
+            granted.remove(*capability);
+        }
+    }
+
+    granted
+}
+
+impl Plugin {
+    /// Protocol version the guest was built against.
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    /// The guest predates the handshake, and isn't told the host's
+    /// version or capabilities.
+    pub fn is_compatibility_mode(&self) -> bool {
+        self.abi_version < ABI_VERSION
+    }
+
+    /// Subsystems the host provides to the plugin.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Pass the host's version and capabilities to the guest, with its
+    /// configuration when it takes it, once.
+    pub(crate) fn handshake(&mut self) -> Result<(), RuntimeError> {
+        if self.handshake_done {
+            return Ok(());
+        }
+        let capabilities = self.capabilities.bits();
+        match self.init_fn.clone() {
+            Some(InitFn::Handshake(init_fn)) => {
+                self.intercept("__gers_init", || init_fn.call(ABI_VERSION, capabilities))?;
+            }
+            Some(InitFn::Configured(init_fn)) => {
+                // Empty configurations aren't allocated.
+                let (ptr, len) = if self.config.is_empty() {
+                    (WasmPtr::new(0), 0)
+                } else {
+                    self.send_bytes(self.config.as_bytes())?
+                };
+                let result = self.intercept("__gers_init", || {
+                    init_fn.call(ABI_VERSION, capabilities, ptr.offset(), len)
+                });
+                if len > 0 {
+                    self.free_string(ptr, len)?;
+                }
+                result?;
+            }
+            None => {}
+        }
+        self.handshake_done = true;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(check_version(None).unwrap(), LEGACY_ABI_VERSION);
+        assert_eq!(check_version(Some(ABI_VERSION)).unwrap(), ABI_VERSION);
+        assert!(matches!(
+            check_version(Some(ABI_VERSION + 1)),
+            Err(PluginError::UnsupportedAbi(_))
+        ));
+        assert!(check_version(Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_permissions_restrict_capabilities() {
+        let host = Capabilities::DRAW | Capabilities::STORAGE | Capabilities::HTTP;
+        let permissions = Permissions {
+            http: true,
+            ..Permissions::default()
+        };
+        assert_eq!(
+            granted_capabilities(host, &permissions),
+            Capabilities::DRAW | Capabilities::HTTP
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Host imports registered by namespace.
+//!
+//! The host and its subsystems each register the namespaces they
+//! provide, like `gers_draw` or `gers_audio`, before plugins are loaded.
+//! A namespace's functions are built separately for every plugin that
+//! is instantiated, so they can be bound to plugin specific state.
+//! Namespaces behind a permission have a second set of functions, with
+//! the same signatures, given to plugins that weren't granted it, so
+//! their modules still instantiate.
+//!
+//! A namespace can only be registered once, and the namespaces the
+//! loader provides itself are reserved.
+use wasmer::{Exports, ImportObject, Store};
+
+use crate::{Permission, PluginContext, PluginError, SHARED_MEMORY_MODULE};
+
+/// Builds a namespace's functions for a plugin that is being instantiated.
+pub type NamespaceFn = dyn Fn(&Store, &PluginContext) -> Exports;
+
+/// Namespaces provided by the loader, which the host can't register.
+const RESERVED_NAMESPACES: &[&str] = &[
+    SHARED_MEMORY_MODULE,
+    "wasi_unstable",
+    "wasi_snapshot_preview1",
+];
+
+struct Namespace {
+    name: String,
+    exports_fn: Box<NamespaceFn>,
+    /// Permission guarding the namespace, with the functions given to
+    /// plugins that weren't granted it.
+    guard: Option<(Permission, Box<NamespaceFn>)>,
+}
+
+/// Host imports, registered by namespace.
+#[derive(Default)]
+pub struct ImportsBuilder {
+    namespaces: Vec<Namespace>,
+}
+
+impl ImportsBuilder {
+    /// Provide a namespace to every plugin.
+    pub fn register<F>(&mut self, namespace: &str, exports_fn: F) -> Result<&mut Self, PluginError>
+    where
+        F: Fn(&Store, &PluginContext) -> Exports + 'static,
+    {
+        self.insert(namespace, Box::new(exports_fn), None)
+    }
+
+    /// Provide a namespace to plugins granted the permission, and the
+    /// `denied_fn` functions to the others.
+    pub fn register_guarded<F, D>(
+        &mut self,
+        namespace: &str,
+        permission: Permission,
+        exports_fn: F,
+        denied_fn: D,
+    ) -> Result<&mut Self, PluginError>
+    where
+        F: Fn(&Store, &PluginContext) -> Exports + 'static,
+        D: Fn(&Store, &PluginContext) -> Exports + 'static,
+    {
+        self.insert(
+            namespace,
+            Box::new(exports_fn),
+            Some((permission, Box::new(denied_fn))),
+        )
+    }
+
+    pub fn contains(&self, namespace: &str) -> bool {
+        self.namespaces.iter().any(|entry| entry.name == namespace)
+    }
+
+    /// Names of the registered namespaces, in the order they were registered.
+    pub fn namespaces(&self) -> impl Iterator<Item = &str> {
+        self.namespaces.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Imports for a plugin that is being instantiated.
+    pub(crate) fn build(&self, store: &Store, context: &PluginContext) -> ImportObject {
+        let mut imports = ImportObject::new();
+
+        for entry in self.namespaces.iter() {
+            let exports = match entry.guard {
+                Some((permission, ref denied_fn))
+                    if !context.meta.permissions.is_granted(permission) =>
+                {
+                    denied_fn(store, context)
+                }
+                _ => (entry.exports_fn)(store, context),
+            };
+            imports.register(entry.name.as_str(), exports);
+        }
+
+        imports
+    }
+
+    fn insert(
+        &mut self,
+        namespace: &str,
+        exports_fn: Box<NamespaceFn>,
+        guard: Option<(Permission, Box<NamespaceFn>)>,
+    ) -> Result<&mut Self, PluginError> {
+        if RESERVED_NAMESPACES.contains(&namespace) {
+            return Err(PluginError::ImportConflict(format!(
+                "namespace {} is reserved",
+                namespace
+            )));
+        }
+        if self.contains(namespace) {
+            return Err(PluginError::ImportConflict(format!(
+                "namespace {} is already registered",
+                namespace
+            )));
+        }
+
+        self.namespaces.push(Namespace {
+            name: namespace.to_string(),
+            exports_fn,
+            guard,
+        });
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PluginMeta, PluginSource, Plugins};
+    use std::path::Path;
+    use wasmer::Function;
+
+    fn exports(store: &Store, name: &str) -> Exports {
+        let mut exports = Exports::new();
+        exports.insert(name, Function::new_native(store, || {}));
+        exports
+    }
+
+    #[test]
+    fn test_namespace_conflicts() {
+        let mut builder = ImportsBuilder::default();
+        builder
+            .register("gers", |store, _| exports(store, "log_info"))
+            .unwrap()
+            .register("gers_draw", |store, _| exports(store, "rect"))
+            .unwrap();
+
+        assert!(matches!(
+            builder.register("gers", |store, _| exports(store, "log_info")),
+            Err(PluginError::ImportConflict(_))
+        ));
+        assert!(matches!(
+            builder.register(SHARED_MEMORY_MODULE, |_, _| Exports::new()),
+            Err(PluginError::ImportConflict(_))
+        ));
+        assert_eq!(
+            builder.namespaces().collect::<Vec<_>>(),
+            ["gers", "gers_draw"]
+        );
+    }
+
+    #[test]
+    fn test_guarded_namespace() {
+        let store = Plugins::new().store;
+        let mut builder = ImportsBuilder::default();
+        builder
+            .register_guarded(
+                "gers_clipboard",
+                Permission::Clipboard,
+                |store, _| exports(store, "granted"),
+                |store, _| exports(store, "denied"),
+            )
+            .unwrap();
+
+        let mut meta: PluginMeta = toml::from_str("name = \"test\"\nversion = \"1.0.0\"").unwrap();
+        let source = PluginSource::Directory(Path::new("test").to_path_buf());
+        let build = |meta: &PluginMeta| {
+            let context = PluginContext {
+                meta,
+                key: source.path(),
+                source: &source,
+            };
+            builder.build(&store, &context)
+        };
+
+        assert!(build(&meta)
+            .get_export("gers_clipboard", "denied")
+            .is_some());
+        meta.permissions.clipboard = true;
+        assert!(build(&meta)
+            .get_export("gers_clipboard", "granted")
+            .is_some());
+    }
+}
@@ -0,0 +1,90 @@
+//! Summaries of loaded plugins.
+//!
+//! Lets the host, and in-game UI, list and inspect the loaded plugins
+//! by name, rather than iterating them blindly.
+use std::path::PathBuf;
+
+use crate::{abi, EventManifest, MemoryStats, Permissions, Plugin, Plugins};
+
+/// What a loaded plugin is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginState {
+    /// Content without a module, like a content pack.
+    DataOnly,
+    /// A call ran past the watchdog timeout, and the plugin isn't
+    /// called anymore.
+    Faulted,
+    /// The plugin's init hooks haven't run, see `Plugin::init`.
+    Uninitialised,
+    /// Updated every frame.
+    Running,
+    /// Work of an earlier update is waiting to be resumed.
+    WorkPending,
+    /// Reclaiming memory, see `Plugin::compact`.
+    Compacting,
+}
+
+/// Summary of a loaded plugin.
+#[derive(Debug, Clone)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    /// Directory or archive the plugin was loaded from.
+    pub root: PathBuf,
+    pub state: PluginState,
+    pub permissions: Permissions,
+    pub memory: MemoryStats,
+    /// Known hooks the module exports.
+    pub hooks: Vec<&'static str>,
+    /// Custom event types the plugin defines and handles.
+    pub events: EventManifest,
+}
+
+impl Plugin {
+    pub fn state(&self) -> PluginState {
+        if self.instance.is_none() {
+            PluginState::DataOnly
+        } else if self.is_faulted() {
+            PluginState::Faulted
+        } else if self.event_alloc_fn.is_some() && self.data_ptr.is_none() {
+            PluginState::Uninitialised
+        } else if self.work_pending {
+            PluginState::WorkPending
+        } else if self.compaction.is_pending() {
+            PluginState::Compacting
+        } else {
+            PluginState::Running
+        }
+    }
+
+    /// Summary of the plugin.
+    pub fn info(&self) -> PluginInfo {
+        PluginInfo {
+            name: self.meta.name.clone(),
+            version: self.meta.version.clone(),
+            root: self.root().to_path_buf(),
+            state: self.state(),
+            permissions: self.meta.permissions.clone(),
+            memory: self.memory_stats(),
+            hooks: abi::lint(self.instance()).found,
+            events: self.event_manifest.clone(),
+        }
+    }
+}
+
+impl Plugins {
+    /// The loaded plugin with the name from its meta file.
+    pub fn get(&self, name: &str) -> Option<&Plugin> {
+        self.plugins.iter().find(|plugin| plugin.meta.name == name)
+    }
+
+    /// Names of the loaded plugins, in execution order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.meta.name.as_str())
+    }
+
+    /// Summaries of the loaded plugins, in execution order.
+    pub fn infos(&self) -> Vec<PluginInfo> {
+        self.plugins.iter().map(Plugin::info).collect()
+    }
+}
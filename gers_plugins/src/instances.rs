@@ -0,0 +1,209 @@
+//! Plugins loaded more than once, from the same module.
+//!
+//! Data-driven mods often need one generic plugin configured in several
+//! ways, like a spawner for each kind of creature. Rather than copying
+//! the plugin, it lists its instances in `plugin.toml`, each with the
+//! keys of its configuration that differ:
+//!
+//! ```toml
+//! name = "spawner"
+//!
+//! [config]
+//! spawn_rate = 1.0
+//!
+//! [[instance]]
+//! name = "wolf"
+//! config = { creature = "wolf", spawn_rate = 0.5 }
+//!
+//! [[instance]]
+//! name = "bat"
+//! config = { creature = "bat" }
+//! ```
+//!
+//! Each instance is instantiated from the module on its own, named
+//! `spawner#wolf` and `spawner#bat`, so it has its own memory, host
+//! environment, settings, data directory and event queues. Plugins
+//! without instances are loaded once, under their own name.
+//!
+//! Instances are enabled and disabled with their plugin, and are
+//! removed with it, so the enabled list only names the plugin.
+//!
+//! As instances share the root they're loaded from, the host tells
+//! plugins apart by their key instead, see `Plugin::key`.
+use std::path::{Path, PathBuf};
+
+use crate::{PluginError, PluginMeta};
+
+/// Separates the name of the plugin from the name of the instance.
+pub const INSTANCE_SEPARATOR: char = '#';
+
+/// Name of the plugin, and of the instance when the name is of one.
+pub fn split_instance(name: &str) -> (&str, Option<&str>) {
+    match name.split_once(INSTANCE_SEPARATOR) {
+        Some((plugin, instance)) => (plugin, Some(instance)),
+        None => (name, None),
+    }
+}
+
+impl PluginMeta {
+    /// Name of the plugin, without the instance.
+    pub fn plugin_name(&self) -> &str {
+        split_instance(&self.name).0
+    }
+
+    /// Name of the instance, when the plugin is loaded more than once.
+    pub fn instance(&self) -> Option<&str> {
+        split_instance(&self.name).1
+    }
+}
+
+/// Key of the plugin loaded from the root: the root itself, with the
+/// instance appended for instances, like `mods/spawner#wolf`.
+pub(crate) fn plugin_key(root: &Path, meta: &PluginMeta) -> PathBuf {
+    match meta.instance() {
+        Some(instance) => {
+            let mut key = root.as_os_str().to_os_string();
+            key.push(format!("{}{}", INSTANCE_SEPARATOR, instance));
+            PathBuf::from(key)
+        }
+        None => root.to_path_buf(),
+    }
+}
+
+fn is_valid_instance_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// Meta of each instance of the plugin, in the order they're declared,
+/// or the meta itself when it declares none.
+pub(crate) fn expand(meta: PluginMeta) -> Result<Vec<PluginMeta>, PluginError> {
+    if meta.name.contains(INSTANCE_SEPARATOR) {
+        return Err(PluginError::InvalidInstance(format!(
+            "plugin name {:?} contains {:?}",
+            meta.name, INSTANCE_SEPARATOR
+        )));
+    }
+    if meta.instances.is_empty() {
+        return Ok(vec![meta]);
+    }
+
+    let mut metas: Vec<PluginMeta> = Vec::with_capacity(meta.instances.len());
+    for decl in meta.instances.iter() {
+        if !is_valid_instance_name(&decl.name) {
+            return Err(PluginError::InvalidInstance(format!(
+                "instance name {:?} must be ASCII letters, digits, '-' or '_'",
+                decl.name
+            )));
+        }
+        let name = format!("{}{}{}", meta.name, INSTANCE_SEPARATOR, decl.name);
+        if metas.iter().any(|instance| instance.name == name) {
+            return Err(PluginError::InvalidInstance(format!(
+                "instance {:?} is declared twice",
+                decl.name
+            )));
+        }
+
+        let mut instance = meta.clone();
+        instance.name = name;
+        instance.instances.clear();
+        instance.config.extend(decl.config.clone());
+        metas.push(instance);
+    }
+
+    Ok(metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> PluginMeta {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_expand_instances() {
+        let meta = parse(
+            r#"
+            name = "spawner"
+            version = "1.0.0"
+
+            [config]
+            spawn_rate = 1.0
+
+            [[instance]]
+            name = "wolf"
+            config = { creature = "wolf", spawn_rate = 0.5 }
+
+            [[instance]]
+            name = "bat"
+            "#,
+        );
+
+        let metas = expand(meta).unwrap();
+        let names: Vec<&str> = metas.iter().map(|meta| meta.name.as_str()).collect();
+        assert_eq!(names, ["spawner#wolf", "spawner#bat"]);
+        assert_eq!(metas[0].plugin_name(), "spawner");
+        assert_eq!(metas[0].instance(), Some("wolf"));
+        assert_eq!(metas[0].config["spawn_rate"].as_float(), Some(0.5));
+        assert_eq!(metas[0].config["creature"].as_str(), Some("wolf"));
+        assert_eq!(metas[1].config["spawn_rate"].as_float(), Some(1.0));
+        assert!(metas[1].instances.is_empty());
+
+        let metas = expand(parse("name = \"single\"\nversion = \"1.0.0\"")).unwrap();
+        assert_eq!(metas.len(), 1);
+        assert_eq!(metas[0].instance(), None);
+    }
+
+    #[test]
+    fn test_plugin_keys() {
+        let meta = parse(
+            r#"
+            name = "spawner"
+            version = "1.0.0"
+
+            [[instance]]
+            name = "wolf"
+
+            [[instance]]
+            name = "bat"
+            "#,
+        );
+        let root = Path::new("mods/spawner");
+
+        let keys: Vec<PathBuf> = expand(meta)
+            .unwrap()
+            .iter()
+            .map(|instance| plugin_key(root, instance))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                Path::new("mods/spawner#wolf"),
+                Path::new("mods/spawner#bat")
+            ]
+        );
+
+        let meta = parse("name = \"single\"\nversion = \"1.0.0\"");
+        assert_eq!(plugin_key(root, &meta), root);
+    }
+
+    #[test]
+    fn test_invalid_instances() {
+        for toml in [
+            "name = \"a#b\"\nversion = \"1\"",
+            "name = \"a\"\nversion = \"1\"\n[[instance]]\nname = \"\"",
+            "name = \"a\"\nversion = \"1\"\n[[instance]]\nname = \"../b\"",
+            "name = \"a\"\nversion = \"1\"\n[[instance]]\nname = \"b\"\n[[instance]]\nname = \"b\"",
+        ] {
+            assert!(
+                matches!(expand(parse(toml)), Err(PluginError::InvalidInstance(_))),
+                "{}",
+                toml
+            );
+        }
+    }
+}
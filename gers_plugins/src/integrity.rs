@@ -0,0 +1,93 @@
+//! Integrity verification of plugin modules.
+//!
+//! A `plugin.toml` may pin the SHA-256 hash of its `main.wasm`, and
+//! carry an ed25519 signature of the module made with a key the host
+//! trusts. Both are hex encoded.
+//!
+//! ```toml
+//! sha256 = "9f86d081884c7d65..."
+//! signature = "e5564300c360ac72..."
+//! ```
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{errors::PluginError, meta::PluginMeta};
+
+/// How strictly plugin modules are verified before compiling.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustPolicy {
+    /// Skip verification, for developing plugins.
+    Development,
+    /// Verify the hash and signature when the plugin provides them.
+    #[default]
+    Verify,
+    /// Only load plugins signed by a trusted key.
+    RequireSigned,
+}
+
+/// Check a module's bytes against the hash and signature in its meta file.
+pub(crate) fn verify_module(
+    bytes: &[u8],
+    meta: &PluginMeta,
+    policy: TrustPolicy,
+    trusted_keys: &[PublicKey],
+) -> Result<(), PluginError> {
+    if policy == TrustPolicy::Development {
+        return Ok(());
+    }
+
+    if let Some(ref expected) = meta.sha256 {
+        let actual = module_hash(bytes);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(PluginError::IntegrityCheckFailed(format!(
+                "sha256 mismatch, expected {} found {}",
+                expected, actual
+            )));
+        }
+    }
+
+    match meta.signature {
+        Some(ref signature) => {
+            let signature = hex::decode(signature.trim())
+                .ok()
+                .and_then(|raw| Signature::from_bytes(&raw).ok())
+                .ok_or_else(|| {
+                    PluginError::IntegrityCheckFailed("malformed signature".to_string())
+                })?;
+
+            let trusted = trusted_keys
+                .iter()
+                .any(|key| key.verify(bytes, &signature).is_ok());
+            if !trusted {
+                return Err(PluginError::IntegrityCheckFailed(
+                    "signature not made by a trusted key".to_string(),
+                ));
+            }
+        }
+        None if policy == TrustPolicy::RequireSigned => {
+            return Err(PluginError::IntegrityCheckFailed(
+                "plugin is not signed".to_string(),
+            ));
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Hex encoded SHA-256 hash of a module's bytes.
+pub(crate) fn module_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Parse a hex encoded ed25519 public key.
+pub fn parse_public_key(hex_key: &str) -> Result<PublicKey, PluginError> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|raw| PublicKey::from_bytes(&raw).ok())
+        .ok_or_else(|| {
+            PluginError::IntegrityCheckFailed(format!("malformed public key: {}", hex_key))
+        })
+}
@@ -0,0 +1,85 @@
+//! Instrumenting the calls the host makes into guests.
+//!
+//! Interceptors added to `Plugins` see every call into a guest export,
+//! like `__gers_update` or `__gers_event_update`, of every plugin. They
+//! run in the order they were added before the call, and in reverse
+//! order after it, with how long the guest took. An interceptor can
+//! fail a call before it reaches the guest, for injecting faults.
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use wasmer::RuntimeError;
+
+/// Call into a guest export.
+#[derive(Debug, Clone, Copy)]
+pub struct GuestCall<'a> {
+    /// Name from the plugin's meta file.
+    pub plugin: &'a str,
+    /// Name of the export, like `__gers_update`.
+    pub function: &'a str,
+}
+
+/// Middleware invoked around calls into guest exports.
+pub trait PluginCallInterceptor: Send + Sync {
+    /// Called before the guest is. Returning an error fails the call
+    /// with it, and skips the interceptors after this one.
+    fn before_call(&self, _call: &GuestCall) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    /// Called once the call returned or failed, with the time spent in
+    /// the guest. Only called when `before_call` was.
+    fn after_call(&self, _call: &GuestCall, _duration: Duration, _error: Option<&RuntimeError>) {}
+}
+
+/// Interceptors shared by the plugins, so ones added later apply to
+/// plugins that are already loaded.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Arc<RwLock<Vec<Box<dyn PluginCallInterceptor>>>>);
+
+impl Interceptors {
+    pub(crate) fn add(&self, interceptor: Box<dyn PluginCallInterceptor>) {
+        self.0
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(interceptor);
+    }
+
+    /// Call into the plugin's guest export through the interceptors.
+    pub(crate) fn call<T>(
+        &self,
+        plugin: &str,
+        function: &str,
+        f: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        let interceptors = self.0.read().unwrap_or_else(|err| err.into_inner());
+        if interceptors.is_empty() {
+            return f();
+        }
+
+        let call = GuestCall { plugin, function };
+        let mut entered = 0;
+        let mut rejected = None;
+        for interceptor in interceptors.iter() {
+            if let Err(err) = interceptor.before_call(&call) {
+                rejected = Some(err);
+                break;
+            }
+            entered += 1;
+        }
+
+        let start = Instant::now();
+        let result = match rejected {
+            Some(err) => Err(err),
+            None => f(),
+        };
+        let duration = start.elapsed();
+
+        for interceptor in interceptors[..entered].iter().rev() {
+            interceptor.after_call(&call, duration, result.as_ref().err());
+        }
+
+        result
+    }
+}
@@ -0,0 +1,95 @@
+//! Layouts of the event types guests handle.
+//!
+//! Guests that export `__gers_event_layouts() -> u64` list the
+//! `EventLayout` of each event type they handle, with the pointer to
+//! the list in the low and the number of layouts in the high 32 bits.
+//! The host compares them with its own once, when the plugin is loaded,
+//! and doesn't send events of types whose layout differs, which the
+//! guest would misread. Guests without the export, and types the host
+//! doesn't know, aren't checked.
+use gers_events::{event_layouts, EventLayout, EventType};
+
+use crate::{strings, PluginError};
+
+/// Size of an `EventLayout` in the guest's memory.
+const LAYOUT_SIZE: u32 = std::mem::size_of::<EventLayout>() as u32;
+
+/// Event types whose layout in `guest` differs from the host's.
+pub(crate) fn mismatched_layouts(guest: &[EventLayout]) -> Vec<EventType> {
+    let host = event_layouts();
+    let mut mismatched = vec![];
+    for layout in guest {
+        let differs = host
+            .iter()
+            .any(|expected| expected.event_type == layout.event_type && expected != layout);
+        let event_type = EventType::from(layout.event_type);
+        if differs && !mismatched.contains(&event_type) {
+            mismatched.push(event_type);
+        }
+    }
+
+    mismatched
+}
+
+/// Read the layouts returned by `__gers_event_layouts` out of the
+/// instance's memory.
+pub(crate) fn read_event_layouts(
+    instance: &wasmer::Instance,
+    packed: u64,
+) -> Result<Vec<EventLayout>, PluginError> {
+    let (ptr, count) = (packed as u32, (packed >> 32) as u32);
+    if count == 0 {
+        return Ok(vec![]);
+    }
+
+    let bytes = count
+        .checked_mul(LAYOUT_SIZE)
+        .and_then(|len| {
+            let memory = instance.exports.get_memory("memory").ok()?;
+            strings::read_bytes(memory, wasmer::WasmPtr::new(ptr), len)
+        })
+        .ok_or_else(|| PluginError::EventLayouts("out of bounds".to_string()))?;
+
+    Ok(bytes
+        .chunks_exact(LAYOUT_SIZE as usize)
+        .map(decode_layout)
+        .collect())
+}
+
+/// Layout written with the C layout, in little endian.
+fn decode_layout(bytes: &[u8]) -> EventLayout {
+    let mut hash = [0; 8];
+    hash.copy_from_slice(&bytes[8..16]);
+    EventLayout {
+        event_type: i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        padding: 0,
+        hash: u64::from_le_bytes(hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gers_events::{DamageEvent, HelloEvent};
+
+    #[test]
+    fn test_mismatched_layouts() {
+        let hello = EventLayout::of::<HelloEvent>();
+        let damage = EventLayout::of::<DamageEvent>();
+        assert!(mismatched_layouts(&[hello, damage]).is_empty());
+
+        let changed = EventLayout {
+            hash: damage.hash ^ 1,
+            ..damage
+        };
+        let unknown = EventLayout {
+            event_type: 1000,
+            ..hello
+        };
+        assert_eq!(
+            mismatched_layouts(&[hello, changed, unknown, changed]),
+            [EventType::Damage]
+        );
+        assert_ne!(hello.hash, damage.hash);
+    }
+}
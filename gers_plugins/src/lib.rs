@@ -1,22 +1,105 @@
 //! gers modding framework
+use gers_events::{
+    encode_event, Capabilities, EncodedEvent, EventHeader, EventStamp, EventType, FrameGlobals,
+    MutableEvent, UpdateStatus, EVENT_BUFFER_ALIGN, EVENT_FLAG_ENCODED, EVENT_FLAG_MUTABLE,
+    EVENT_HEADER_SIZE, EVENT_SOURCE_HOST,
+};
 use std::{
-    fs::File,
-    io::prelude::*,
+    cell::Cell,
+    collections::HashSet,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use wasmer::{Array, ChainableNamedResolver, ImportObject, NativeFunc, WasmPtr};
-use wasmer_compiler_cranelift::Cranelift;
-use wasmer_engine_universal::Universal;
+use wasmer::{Array, ChainableNamedResolver, NativeFunc, RuntimeError, Val, WasmPtr};
 
 // mod builtins;
+mod abi;
+mod arena;
+mod builder;
+mod compact;
+mod config;
+mod conflicts;
+mod crash;
+mod enabled;
 mod errors;
+mod globals;
+mod growth;
+mod handshake;
+mod imports;
+mod info;
+mod instances;
+mod integrity;
+mod intercept;
+mod layouts;
+mod linking;
+mod manifest;
+mod memory;
 mod meta;
+mod paths;
+mod report;
+mod settings;
+mod shared;
+mod snapshot;
+mod source;
+pub mod strings;
+mod stubs;
+mod symbols;
+mod wasi;
+mod watchdog;
 
-use errors::PluginError;
-use meta::PluginMeta;
+pub use abi::HookLint;
+use arena::EventArena;
+pub use builder::PluginsBuilder;
+pub use compact::{Compaction, WASM_PAGE_SIZE};
+pub use conflicts::{Conflict, Resource};
+pub use crash::DeliveredEvent;
+use crash::{BumpRegion, EventHistory};
+pub use enabled::EnabledList;
+pub use errors::PluginError;
+use globals::Globals;
+pub use growth::MemoryGrowth;
+use handshake::InitFn;
+pub use imports::{ImportsBuilder, NamespaceFn};
+pub use info::{PluginInfo, PluginState};
+pub use instances::{split_instance, INSTANCE_SEPARATOR};
+pub use integrity::{parse_public_key, TrustPolicy};
+use intercept::Interceptors;
+pub use intercept::{GuestCall, PluginCallInterceptor};
+use layouts::{mismatched_layouts, read_event_layouts};
+pub use linking::PLUGIN_IMPORT_PREFIX;
+use manifest::read_event_manifest;
+pub use manifest::EventManifest;
+pub use memory::{MemoryReport, MemoryStats};
+pub use meta::{
+    EventDelivery, ExportDecl, ExportParam, InstanceDecl, Permission, Permissions, PluginMeta,
+    SharedMemoryDecl,
+};
+pub use paths::{check_plugin_path, find_file};
+use report::LoadTimes;
+pub use report::{PluginReport, StartupReport};
+pub use settings::{SettingDecl, SettingValue, Settings};
+use shared::SharedMemories;
+pub use shared::SHARED_MEMORY_MODULE;
+pub use snapshot::{MemorySnapshot, PluginsSnapshot};
+pub use source::{PluginSource, ARCHIVE_EXTENSION};
+use strings::{AllocFn, FreeFn};
+pub use stubs::MissingImports;
+pub use symbols::FrameSymbol;
+use symbols::Symbols;
+use wasi::WasiContext;
+pub use wasi::WasiOutput;
+use watchdog::{Watchdog, INTERRUPT_GLOBAL};
 
 /// Name of the plugin definition meta file.
-const PLUGIN_FILENAME: &str = "plugin.toml";
+pub const PLUGIN_FILENAME: &str = "plugin.toml";
+
+/// Size in bytes of the event buffer allocated in each plugin.
+pub const EVENT_BUFFER_SIZE: u32 = 0x1000;
+
+/// Version from which events sent one at a time start with their
+/// `EventHeader`.
+const HEADED_EVENTS_ABI_VERSION: u32 = 5;
 
 /// Name of WebAssembly module file to load.
 const PLUGIN_WASM_MODULE: &str = "main.wasm";
@@ -46,8 +129,44 @@ macro_rules! get_func {
     };
 }
 
-pub type EventAllocFn = NativeFunc<u32, WasmPtr<u8, Array>>;
+/// The guest's `__gers_event_alloc`.
+///
+/// Guests built before ABI version 3 don't take the alignment of the
+/// buffer, and are relied on to align it for every event type.
+#[derive(Clone)]
+pub enum EventAllocFn {
+    Unaligned(NativeFunc<u32, WasmPtr<u8, Array>>),
+    Aligned(NativeFunc<(u32, u32), WasmPtr<u8, Array>>),
+}
+
+impl EventAllocFn {
+    /// Allocate `size` bytes, aligned to `align` when the guest takes it.
+    pub fn call(&self, size: u32, align: u32) -> Result<WasmPtr<u8, Array>, RuntimeError> {
+        match self {
+            EventAllocFn::Unaligned(alloc_fn) => alloc_fn.call(size),
+            EventAllocFn::Aligned(alloc_fn) => alloc_fn.call(size, align),
+        }
+    }
+}
+
 pub type EventUpdateFn = NativeFunc<(i32, WasmPtr<u8, Array>), i32>;
+pub type EventArenaFn = NativeFunc<(), u64>;
+pub type EventBatchFn = NativeFunc<u32, i32>;
+pub type CompactFn = NativeFunc<u32, u32>;
+pub type ResumeFn = NativeFunc<u32, i32>;
+pub type SaveFn = NativeFunc<(), ()>;
+pub type LoadFn = NativeFunc<(WasmPtr<u8, Array>, u32), ()>;
+pub type ShutdownFn = NativeFunc<u32, ()>;
+pub type RenderFn = NativeFunc<f32, ()>;
+pub type BumpStatsFn = NativeFunc<(), u32>;
+
+/// Plugin that is being instantiated.
+pub struct PluginContext<'a> {
+    pub meta: &'a PluginMeta,
+    /// Tells the plugin apart from the others, see `Plugin::key`.
+    pub key: &'a Path,
+    pub source: &'a PluginSource,
+}
 
 /// Registry of instantiated plugin modules.
 pub struct Plugins {
@@ -56,17 +175,102 @@ pub struct Plugins {
     // logger: slog::Logger,
     plugins: Vec<Plugin>,
     store: wasmer::Store,
-    imports: Option<ImportObject>,
-    // TODO: Import object of engine API and builtins
+    /// Host imports, built for each plugin that is instantiated.
+    imports: ImportsBuilder,
+    /// Values of the current frame, imported by every plugin.
+    globals: Globals,
+    /// Names of plugins that are skipped when loading.
+    disabled: HashSet<String>,
+    /// Plugins the user enabled, persisted between runs.
+    enabled: EnabledList,
+    trust_policy: TrustPolicy,
+    /// Keys accepted for plugin signatures.
+    trusted_keys: Vec<ed25519_dalek::PublicKey>,
+    /// Memory segments shared between the plugins that declare them.
+    shared_memories: SharedMemories,
+    /// NaNs are canonicalized, and plugins using WASI are refused.
+    deterministic: bool,
+    /// Invoked around every call into a guest export.
+    interceptors: Interceptors,
+    /// Interrupts guest calls that run too long.
+    watchdog: Option<Arc<Watchdog>>,
+    /// Number of delivered events each plugin keeps for crash dumps.
+    event_history: usize,
+    /// Subsystems the host provides, passed to guests in `__gers_init`.
+    capabilities: Capabilities,
+    /// Whether host imports the build doesn't provide are stubbed.
+    missing_imports: MissingImports,
+    /// Resources claimed by more than one plugin.
+    conflicts: Vec<Conflict>,
 }
 
 pub struct Plugin {
-    instance: wasmer::Instance,
+    /// Directory or archive the plugin was loaded from.
+    source: PluginSource,
+    key: PathBuf,
+    /// Data-only plugins, like content packs, have no module instance.
+    instance: Option<wasmer::Instance>,
     pub data_ptr: Option<WasmPtr<u8, Array>>,
     meta: PluginMeta,
     update_fn: Option<wasmer::Function>,
     event_alloc_fn: Option<EventAllocFn>,
     event_update_fn: Option<EventUpdateFn>,
+    /// Arena the guest receives batched events in.
+    event_arena: Option<EventArena>,
+    event_batch_fn: Option<EventBatchFn>,
+    /// Custom event types the guest defines and handles.
+    event_manifest: EventManifest,
+    /// Event types the guest lays out differently than the host, which
+    /// aren't sent to it.
+    mismatched_layouts: Vec<EventType>,
+    compact_fn: Option<CompactFn>,
+    compaction: Compaction,
+    resume_fn: Option<ResumeFn>,
+    /// The last update returned `UpdateStatus::Continue`, and the
+    /// guest is resumed instead of updated until it's done.
+    work_pending: bool,
+    save_fn: Option<SaveFn>,
+    load_fn: Option<LoadFn>,
+    shutdown_fn: Option<ShutdownFn>,
+    render_fn: Option<RenderFn>,
+    wasi: Option<WasiContext>,
+    alloc_fn: Option<AllocFn>,
+    free_fn: Option<FreeFn>,
+    bump_stats_fn: Option<BumpStatsFn>,
+    /// Buffers allocated in guest memory by `send_bytes`.
+    host_allocations: Cell<u64>,
+    /// Buffers allocated by `send_bytes` and not yet released.
+    host_live_allocations: Cell<u64>,
+    memory_growth: Arc<MemoryGrowth>,
+    interceptors: Interceptors,
+    watchdog: Option<Arc<Watchdog>>,
+    /// Global the guest checks for being interrupted by the watchdog.
+    interrupt: Option<wasmer::Global>,
+    /// A call was interrupted by the watchdog, and the plugin isn't
+    /// called again.
+    faulted: Cell<bool>,
+    /// Hex encoded SHA-256 hash of the module.
+    module_hash: Option<String>,
+    /// Names and debug info of the module, for its stack traces.
+    symbols: Option<Symbols>,
+    /// Last events delivered to the guest.
+    event_history: EventHistory,
+    bump_region: Option<BumpRegion>,
+    /// Where the host writes the values of the current frame.
+    frame_globals: Option<WasmPtr<u8, Array>>,
+    /// Stamp of the events sent in the current frame.
+    stamp: Cell<EventStamp>,
+    /// Protocol version the guest was built against.
+    abi_version: u32,
+    init_fn: Option<InitFn>,
+    /// Subsystems provided to the plugin.
+    capabilities: Capabilities,
+    /// `__gers_init` was called.
+    handshake_done: bool,
+    /// Host imports the build doesn't provide, which were stubbed.
+    stubbed_imports: Vec<String>,
+    /// Configuration passed to `__gers_init`, as TOML text.
+    config: String,
 }
 
 impl Default for Plugins {
@@ -77,24 +281,133 @@ impl Default for Plugins {
 
 impl Plugins {
     pub fn new() -> Self {
-        let compiler = Cranelift::new();
+        PluginsBuilder::new().build()
+    }
 
-        let store = wasmer::Store::new(&Universal::new(compiler).engine());
+    pub fn builder() -> PluginsBuilder {
+        PluginsBuilder::new()
+    }
 
-        Plugins {
-            plugins: vec![],
-            store,
-            imports: None,
-        }
+    /// Modules are compiled to compute the same results on every machine.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Guest calls running longer than this are interrupted.
+    pub fn watchdog_timeout(&self) -> Option<Duration> {
+        self.watchdog.as_ref().map(|watchdog| watchdog.timeout())
+    }
+
+    /// How strictly modules are verified before they're compiled.
+    pub fn trust_policy(&self) -> TrustPolicy {
+        self.trust_policy
+    }
+
+    /// Use `TrustPolicy::Development` to skip verification
+    /// while working on plugins.
+    pub fn set_trust_policy(&mut self, trust_policy: TrustPolicy) {
+        self.trust_policy = trust_policy;
+    }
+
+    /// Subsystems the host provides, told to plugins when they're
+    /// initialised.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Set the subsystems the host provides. Applies to plugins loaded
+    /// afterwards.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// What happens to imports of host functions this build doesn't provide.
+    pub fn missing_imports(&self) -> MissingImports {
+        self.missing_imports
+    }
+
+    /// Stub host imports this build doesn't provide, instead of
+    /// refusing plugins that import them.
+    pub fn set_missing_imports(&mut self, missing_imports: MissingImports) {
+        self.missing_imports = missing_imports;
+    }
+
+    /// Accept signatures made with the given key.
+    pub fn add_trusted_key(&mut self, key: ed25519_dalek::PublicKey) {
+        self.trusted_keys.push(key);
+    }
+
+    /// Skip loading the plugin with the given name.
+    ///
+    /// Only applies to this run, unlike `set_enabled`.
+    pub fn disable(&mut self, name: impl Into<String>) {
+        self.disabled.insert(name.into());
+    }
+
+    /// Use the enabled plugin list persisted in the given file.
+    pub fn load_enabled_list(&mut self, path: impl AsRef<Path>) -> Result<(), PluginError> {
+        self.enabled = EnabledList::load(path)?;
+        Ok(())
+    }
+
+    pub fn enabled_list(&self) -> &EnabledList {
+        &self.enabled
+    }
+
+    /// Enable or disable a plugin, and persist the change.
+    ///
+    /// Takes effect the next time plugins are loaded. Naming an
+    /// instance applies to every instance of its plugin.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), PluginError> {
+        self.enabled.set_enabled(name, enabled);
+        self.enabled.save()
+    }
+
+    /// Remove plugins that weren't found while loading from the
+    /// enabled list, as they were uninstalled, and persist it.
+    ///
+    /// Returns the names of the removed plugins. Only call this after
+    /// loading every search path, or the plugins in the others are
+    /// taken for removed.
+    pub fn prune_enabled_list(&mut self) -> Result<Vec<String>, PluginError> {
+        let removed = self.enabled.prune();
+        self.enabled.save()?;
+
+        Ok(removed)
+    }
+
+    /// Persist plugins discovered while loading to the enabled list.
+    pub fn save_enabled_list(&mut self) -> Result<(), PluginError> {
+        self.enabled.save()
     }
 
     pub fn store(&self) -> &wasmer::Store {
         &self.store
     }
 
-    /// Push a resolver to the back of the resolver chain.
-    pub fn set_imports(&mut self, imports: ImportObject) {
-        self.imports = Some(imports);
+    /// Invoke the interceptor around every call into a guest export,
+    /// including those of plugins that are already loaded.
+    pub fn add_interceptor(&mut self, interceptor: impl PluginCallInterceptor + 'static) {
+        self.interceptors.add(Box::new(interceptor));
+    }
+
+    /// Register the namespaces of host imports.
+    ///
+    /// The imports are built when each plugin is instantiated, so
+    /// namespaces registered after startup are given to plugins loaded
+    /// at runtime, but not to the ones already loaded.
+    pub fn imports_mut(&mut self) -> &mut ImportsBuilder {
+        &mut self.imports
+    }
+
+    /// Update the values of the current frame, which guests read
+    /// without calling into the host, see `globals`. Events sent from
+    /// now on are stamped with the frame's counters.
+    pub fn set_frame_globals(&self, frame: &FrameGlobals) {
+        self.globals.set(frame);
+        for plugin in self.plugins.iter() {
+            plugin.set_frame(frame);
+        }
     }
 
     /// Iterate the plugins in execution order.
@@ -108,24 +421,225 @@ impl Plugins {
         self.plugins.iter_mut()
     }
 
+    /// Which known hooks the plugin with the name exports, for
+    /// debugging plugins that don't respond. `None` when no plugin
+    /// with the name is loaded.
+    pub fn lint(&self, name: &str) -> Option<HookLint> {
+        self.get(name).map(|plugin| abi::lint(plugin.instance()))
+    }
+
+    /// Snapshot the memory of every plugin with a module instance.
+    pub fn snapshot_all(&self) -> PluginsSnapshot {
+        PluginsSnapshot {
+            plugins: self
+                .plugins
+                .iter()
+                .filter_map(|plugin| {
+                    plugin
+                        .snapshot()
+                        .map(|snapshot| (plugin.key().to_path_buf(), snapshot))
+                })
+                .collect(),
+        }
+    }
+
+    /// Restore the plugins captured in the snapshot.
+    ///
+    /// Plugins loaded after the snapshot was taken are left as they are.
+    pub fn restore_all(&self, snapshot: &PluginsSnapshot) -> Result<(), PluginError> {
+        for (key, plugin_snapshot) in snapshot.plugins.iter() {
+            if let Some(plugin) = self.plugins.iter().find(|plugin| plugin.key() == key) {
+                plugin
+                    .restore(plugin_snapshot)
+                    .map_err(|err| err.in_plugin(Some(&plugin.meta.name), plugin.root()))?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Load a plugin contained in a directory.
+    ///
+    /// A plugin without a WebAssembly module is registered as data-only,
+    /// and skips instantiation entirely.
     pub fn load_plugin_dir(&mut self, dir_path: impl AsRef<Path>) -> Result<(), PluginError> {
-        let mut meta_path = PathBuf::new();
-        meta_path.push(dir_path.as_ref());
-        meta_path.push(PLUGIN_FILENAME);
+        self.load_plugin(PluginSource::Directory(dir_path.as_ref().to_path_buf()))
+            .map(|_| ())
+    }
 
-        let mut file = File::open(meta_path)?;
+    /// Load a plugin contained in a directory while the game is running,
+    /// like from a mod manager, and initialise it straight away.
+    ///
+    /// Plugins with instances have each of them loaded, and the first
+    /// is returned. They're all dropped again when initialising any of
+    /// them fails.
+    pub fn load_plugin_dir_at_runtime(
+        &mut self,
+        dir_path: impl AsRef<Path>,
+    ) -> Result<&mut Plugin, PluginError> {
+        let count = self.load_plugin(PluginSource::Directory(dir_path.as_ref().to_path_buf()))?;
+        let first = self.plugins.len() - count;
+
+        for index in first..self.plugins.len() {
+            let plugin = &mut self.plugins[index];
+            if let Err(err) = plugin.init() {
+                let err = PluginError::from(err).in_plugin(Some(&plugin.meta.name), plugin.root());
+                self.plugins.truncate(first);
+                return Err(err);
+            }
+        }
 
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
+        Ok(&mut self.plugins[first])
+    }
 
-        let plugin_meta: PluginMeta = toml::from_str(buf.as_str())?;
+    /// Load a plugin packaged as a `.gpak` zip archive.
+    ///
+    /// The archive has the same layout as a plugin directory. Files
+    /// are read straight from the archive, without unpacking to disk.
+    pub fn load_plugin_archive(
+        &mut self,
+        archive_path: impl AsRef<Path>,
+    ) -> Result<(), PluginError> {
+        self.load_plugin(PluginSource::Archive(archive_path.as_ref().to_path_buf()))
+            .map(|_| ())
+    }
 
-        let mut wasm_path = PathBuf::new();
-        wasm_path.push(dir_path);
-        wasm_path.push(PLUGIN_WASM_MODULE);
+    /// Load plugins from directories and archives, each after the
+    /// plugins it depends on, and otherwise in the given order.
+    ///
+    /// Returns the errors of the plugins that failed to load, which
+    /// don't stop the others from loading.
+    pub fn load_plugins(
+        &mut self,
+        sources: impl IntoIterator<Item = PluginSource>,
+    ) -> Vec<PluginError> {
+        self.load_all(sources).errors
+    }
 
-        let instance = self.load_wasm(wasm_path)?;
+    /// Load plugins like `load_plugins`, reporting how each plugin
+    /// loaded, with the errors of the plugins that failed to.
+    pub fn load_all(&mut self, sources: impl IntoIterator<Item = PluginSource>) -> StartupReport {
+        let mut report = StartupReport::default();
+        let mut plugins = vec![];
+        for source in sources {
+            match read_meta(&source) {
+                Ok(plugin_meta) => plugins.push((source, plugin_meta)),
+                Err(err) => report.errors.push(err.in_plugin(None, source.path())),
+            }
+        }
+
+        let metas: Vec<&PluginMeta> = plugins.iter().map(|(_, meta)| meta).collect();
+        let order = linking::load_order(&metas);
+        let mut plugins: Vec<Option<_>> = plugins.into_iter().map(Some).collect();
+        for index in order {
+            let (source, plugin_meta) = plugins[index].take().expect("plugins load once");
+            let name = plugin_meta.name.clone();
+            let instances = match instances::expand(plugin_meta) {
+                Ok(instances) => instances,
+                Err(err) => {
+                    report
+                        .errors
+                        .push(err.in_plugin(Some(&name), source.path()));
+                    continue;
+                }
+            };
+            for instance in instances {
+                match self.instantiate_named(source.clone(), instance) {
+                    Ok(plugin_report) => report.plugins.push(plugin_report),
+                    Err(err) => report.errors.push(err),
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Load each instance of the plugin. None are kept when one fails
+    /// to load.
+    ///
+    /// Returns the number of plugins that were loaded.
+    fn load_plugin(&mut self, source: PluginSource) -> Result<usize, PluginError> {
+        let plugin_meta = read_meta(&source).map_err(|err| err.in_plugin(None, source.path()))?;
+        let name = plugin_meta.name.clone();
+        let instances = instances::expand(plugin_meta)
+            .map_err(|err| err.in_plugin(Some(&name), source.path()))?;
+
+        let loaded = self.plugins.len();
+        for instance in instances {
+            if let Err(err) = self.instantiate_named(source.clone(), instance) {
+                self.plugins.truncate(loaded);
+                return Err(err);
+            }
+        }
+
+        Ok(self.plugins.len() - loaded)
+    }
+
+    /// Instantiate the plugin, saying which plugin failed in errors.
+    fn instantiate_named(
+        &mut self,
+        source: PluginSource,
+        plugin_meta: PluginMeta,
+    ) -> Result<PluginReport, PluginError> {
+        let name = plugin_meta.name.clone();
+        let path = source.path().to_path_buf();
+
+        self.instantiate(source, plugin_meta)
+            .map_err(|err| err.in_plugin(Some(&name), &path))
+    }
+
+    /// Register a plugin from its meta file, instantiating its module.
+    fn instantiate(
+        &mut self,
+        source: PluginSource,
+        plugin_meta: PluginMeta,
+    ) -> Result<PluginReport, PluginError> {
+        // Instances are enabled and disabled with their plugin.
+        let plugin_name = plugin_meta.plugin_name();
+        self.enabled.discover(plugin_name);
+
+        if self.disabled.contains(plugin_name)
+            || self.disabled.contains(&plugin_meta.name)
+            || !self.enabled.is_enabled(plugin_name)
+        {
+            return Err(PluginError::Disabled(plugin_meta.name));
+        }
+        settings::validate_settings(&plugin_meta)?;
+        let config = config::config_text(&plugin_meta, None)?;
+
+        if self.deterministic && plugin_meta.wasi {
+            return Err(PluginError::Nondeterministic(format!(
+                "plugin {} uses WASI",
+                plugin_meta.name
+            )));
+        }
+
+        let wasm_bytes = match source.read_file(PLUGIN_WASM_MODULE) {
+            Ok(wasm_bytes) => wasm_bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                if plugin_meta.needs_module() {
+                    return Err(PluginError::MissingWasmModule(PLUGIN_WASM_MODULE));
+                }
+                self.plugins
+                    .push(Plugin::data_only(source, plugin_meta, config));
+                let plugin = self.plugins.last().expect("plugin was just loaded");
+                return Ok(PluginReport::new(plugin, LoadTimes::default(), &[]));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let key = instances::plugin_key(source.path(), &plugin_meta);
+        let context = PluginContext {
+            meta: &plugin_meta,
+            key: &key,
+            source: &source,
+        };
+        let mut wasi = wasi::setup(&plugin_meta, &source)?;
+        let memory_growth = Arc::new(MemoryGrowth::default());
+        let module_hash = integrity::module_hash(&wasm_bytes);
+        let symbols = Symbols::parse(&wasm_bytes);
+        let (instance, stubbed_imports, load_times) =
+            self.load_wasm(wasm_bytes, &context, wasi.as_mut(), &memory_growth)?;
 
         // TODO: Decouple calls from plugin module into event framework
         // Frame Update entry point
@@ -134,71 +648,491 @@ impl Plugins {
         //     Err(wasmer::ExportError::Missing(..)) => None,
         //     Err(wasmer::ExportError::IncompatibleType) => return Err(PluginError::FunctionType),
         // };
+        let abi_version = match get_func!(instance.exports, "__gers_abi_version", (), u32) {
+            Some(abi_version_fn) => Some(self.interceptors.call(
+                &plugin_meta.name,
+                "__gers_abi_version",
+                || abi_version_fn.call(),
+            )?),
+            None => None,
+        };
+        let abi_version = handshake::check_version(abi_version)?;
+        // Either signature passed validation.
+        let init_fn = match get_func!(instance.exports, "__gers_init") {
+            Some(init_fn) if init_fn.ty().params().len() == 4 => Some(InitFn::Configured(
+                init_fn.native().map_err(|_| PluginError::FunctionType)?,
+            )),
+            Some(init_fn) => Some(InitFn::Handshake(
+                init_fn.native().map_err(|_| PluginError::FunctionType)?,
+            )),
+            None => None,
+        };
+        let capabilities =
+            handshake::granted_capabilities(self.capabilities, &plugin_meta.permissions);
         let update_fn = get_func!(instance.exports, "__gers_update");
-        let event_alloc_fn =
-            get_func!(instance.exports, "__gers_event_alloc", u32, WasmPtr<u8, Array>);
+        // Either signature passed validation.
+        let event_alloc_fn = match get_func!(instance.exports, "__gers_event_alloc") {
+            Some(alloc_fn) if alloc_fn.ty().params().len() == 2 => Some(EventAllocFn::Aligned(
+                alloc_fn.native().map_err(|_| PluginError::FunctionType)?,
+            )),
+            Some(alloc_fn) => Some(EventAllocFn::Unaligned(
+                alloc_fn.native().map_err(|_| PluginError::FunctionType)?,
+            )),
+            None => None,
+        };
         let event_update_fn = get_func!(
             instance.exports,
             "__gers_event_update",
             (i32, WasmPtr<u8, Array>),
             i32
         );
+        let event_arena_fn = get_func!(instance.exports, "__gers_event_arena", (), u64);
+        let event_batch_fn = get_func!(instance.exports, "__gers_event_batch", u32, i32);
+        // The arena is requested once, and kept for the plugin's lifetime.
+        let event_arena = match (&event_arena_fn, &event_batch_fn) {
+            (Some(event_arena_fn), Some(_)) => EventArena::from_packed(
+                self.interceptors
+                    .call(&plugin_meta.name, "__gers_event_arena", || {
+                        event_arena_fn.call()
+                    })?,
+                abi_version,
+            ),
+            _ => None,
+        };
+        let event_manifest = match get_func!(instance.exports, "__gers_event_manifest", (), u64) {
+            Some(manifest_fn) => {
+                let packed =
+                    self.interceptors
+                        .call(&plugin_meta.name, "__gers_event_manifest", || {
+                            manifest_fn.call()
+                        })?;
+                read_event_manifest(&instance, packed)?
+            }
+            None => EventManifest::default(),
+        };
+        let event_layouts = match get_func!(instance.exports, "__gers_event_layouts", (), u64) {
+            Some(layouts_fn) => {
+                let packed =
+                    self.interceptors
+                        .call(&plugin_meta.name, "__gers_event_layouts", || {
+                            layouts_fn.call()
+                        })?;
+                read_event_layouts(&instance, packed)?
+            }
+            None => vec![],
+        };
+        let mismatched_layouts = mismatched_layouts(&event_layouts);
+        let handled_events: Vec<EventType> = event_layouts
+            .iter()
+            .map(|layout| EventType::from(layout.event_type))
+            .collect();
+        let compact_fn = get_func!(instance.exports, "__gers_compact", u32, u32);
+        let resume_fn = get_func!(instance.exports, "__gers_resume", u32, i32);
+        let save_fn = get_func!(instance.exports, "__gers_save", (), ());
+        let load_fn = get_func!(
+            instance.exports,
+            "__gers_load",
+            (WasmPtr<u8, Array>, u32),
+            ()
+        );
+        let bump_stats_fn = get_func!(instance.exports, "__gers_bump_stats", (), u32);
+        // Requested once, like the event arena.
+        let bump_region = match get_func!(instance.exports, "__gers_bump_region", (), u64) {
+            Some(bump_region_fn) => BumpRegion::from_packed(self.interceptors.call(
+                &plugin_meta.name,
+                "__gers_bump_region",
+                || bump_region_fn.call(),
+            )?),
+            None => None,
+        };
+        let frame_globals = match get_func!(instance.exports, "__gers_frame_globals", (), u32) {
+            Some(frame_globals_fn) => Some(self.interceptors.call(
+                &plugin_meta.name,
+                "__gers_frame_globals",
+                || frame_globals_fn.call(),
+            )?),
+            None => None,
+        }
+        .filter(|ptr| *ptr != 0)
+        .map(WasmPtr::new);
+        let shutdown_fn = get_func!(instance.exports, "__gers_shutdown", u32, ());
+        let render_fn = get_func!(instance.exports, "__gers_render", f32, ());
+        let alloc_fn = get_func!(instance.exports, "__gers_alloc", u32, WasmPtr<u8, Array>);
+        let free_fn = get_func!(
+            instance.exports,
+            "__gers_free",
+            (WasmPtr<u8, Array>, u32),
+            ()
+        );
+        let interrupt = instance.exports.get_global(INTERRUPT_GLOBAL).ok().cloned();
 
         self.plugins.push(Plugin {
-            instance,
+            source,
+            key,
+            instance: Some(instance),
             data_ptr: None,
             meta: plugin_meta,
             update_fn,
             event_alloc_fn,
             event_update_fn,
+            event_arena,
+            event_batch_fn,
+            event_manifest,
+            mismatched_layouts,
+            compact_fn,
+            compaction: Compaction::default(),
+            resume_fn,
+            work_pending: false,
+            save_fn,
+            load_fn,
+            shutdown_fn,
+            render_fn,
+            wasi,
+            alloc_fn,
+            free_fn,
+            bump_stats_fn,
+            host_allocations: Cell::new(0),
+            host_live_allocations: Cell::new(0),
+            memory_growth,
+            interceptors: self.interceptors.clone(),
+            watchdog: self.watchdog.clone(),
+            interrupt,
+            faulted: Cell::new(false),
+            module_hash: Some(module_hash),
+            symbols,
+            event_history: EventHistory::new(self.event_history),
+            bump_region,
+            frame_globals,
+            stamp: Cell::default(),
+            abi_version,
+            init_fn,
+            capabilities,
+            handshake_done: false,
+            stubbed_imports,
+            config,
         });
 
-        Ok(())
+        let plugin = self.plugins.last().expect("plugin was just loaded");
+        Ok(PluginReport::new(plugin, load_times, &handled_events))
     }
 
-    /// Load a WebAssembly module file and instantiate it into an instance.
-    fn load_wasm(&self, module_path: impl AsRef<Path>) -> Result<wasmer::Instance, PluginError> {
-        let mut file = File::open(module_path)?;
-        let mut buf: Vec<u8> = vec![];
-        file.read_to_end(&mut buf)?;
+    /// Compile WebAssembly module bytes and instantiate it into an instance.
+    ///
+    /// Returns the instance, with the host imports that were stubbed, and
+    /// the time it took to compile and instantiate.
+    fn load_wasm(
+        &mut self,
+        buf: Vec<u8>,
+        context: &PluginContext,
+        wasi: Option<&mut WasiContext>,
+        memory_growth: &Arc<MemoryGrowth>,
+    ) -> Result<(wasmer::Instance, Vec<String>, LoadTimes), PluginError> {
+        let started = Instant::now();
+        integrity::verify_module(&buf, context.meta, self.trust_policy, &self.trusted_keys)?;
 
         let module = wasmer::Module::new(&self.store, buf)?;
+        abi::validate_exports(&module)?;
+        abi::check_required(&module, context.meta)?;
+        let compile = started.elapsed();
 
-        // TODO: Build import object according to dependencies in meta file
-        let dependencies = wasmer::imports! {};
+        // Functions of the plugins it depends on.
+        let dependencies =
+            linking::import_object(&self.store, context.meta, &module, &self.plugins)?;
 
         // Host can provide built-in imports.
-        let builtins = match self.imports {
-            Some(ref builtins) => builtins.clone(),
+        let builtins = self.imports.build(&self.store, context);
+
+        let wasi_imports = match wasi {
+            Some(wasi) => wasi.import_object(&module)?,
             None => wasmer::imports! {},
         };
 
+        let growth_imports = growth::import_object(&self.store, memory_growth);
+        let global_imports = self.globals.import_object();
+
+        // Only segments the plugin declared are provided.
+        let shared_imports =
+            self.shared_memories
+                .import_object(&self.store, context.meta, &module)?;
+
         // Module dependencies are resolved first.
-        let chain = dependencies.chain_back(builtins);
+        let chain = dependencies
+            .chain_back(builtins)
+            .chain_back(wasi_imports)
+            .chain_back(growth_imports)
+            .chain_back(global_imports)
+            .chain_back(shared_imports);
 
-        let instance = wasmer::Instance::new(&module, &chain)?;
+        // Only imports nothing else resolves are stubbed.
+        let (stubs, stubbed) =
+            stubs::import_object(&self.store, &module, &chain, self.missing_imports);
+        let chain = chain.chain_back(stubs);
 
-        Ok(instance)
+        let started = Instant::now();
+        let instance = wasmer::Instance::new(&module, &chain).map_err(Box::new)?;
+        if let Ok(memory) = instance.exports.get_memory("memory") {
+            memory_growth.observe(memory.size().0);
+        }
+
+        // WASI reactor modules initialise their runtime in this export.
+        if context.meta.wasi {
+            if let Ok(initialize) = instance.exports.get_function("_initialize") {
+                self.interceptors
+                    .call(&context.meta.name, "_initialize", || initialize.call(&[]))?;
+            }
+        }
+
+        let times = LoadTimes {
+            compile,
+            instantiate: started.elapsed(),
+        };
+
+        Ok((instance, stubbed, times))
     }
 }
 
+/// Read and parse the plugin's meta file.
+fn read_meta(source: &PluginSource) -> Result<PluginMeta, PluginError> {
+    paths::check_plugin_path(source.path())?;
+    let buf = match source.read_file(PLUGIN_FILENAME) {
+        Ok(buf) => buf,
+        // A missing directory or archive is an I/O error instead.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && source.path().exists() => {
+            return Err(PluginError::MissingMetaFile(PLUGIN_FILENAME))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let buf = String::from_utf8(buf)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+    Ok(toml::from_str(buf.as_str())?)
+}
+
 impl Plugin {
-    pub fn instance(&self) -> &wasmer::Instance {
-        &self.instance
+    fn data_only(source: PluginSource, meta: PluginMeta, config: String) -> Self {
+        Self {
+            key: instances::plugin_key(source.path(), &meta),
+            source,
+            instance: None,
+            data_ptr: None,
+            meta,
+            update_fn: None,
+            event_alloc_fn: None,
+            event_update_fn: None,
+            event_arena: None,
+            event_batch_fn: None,
+            event_manifest: EventManifest::default(),
+            mismatched_layouts: vec![],
+            compact_fn: None,
+            compaction: Compaction::default(),
+            resume_fn: None,
+            work_pending: false,
+            save_fn: None,
+            load_fn: None,
+            shutdown_fn: None,
+            render_fn: None,
+            wasi: None,
+            alloc_fn: None,
+            free_fn: None,
+            bump_stats_fn: None,
+            host_allocations: Cell::new(0),
+            host_live_allocations: Cell::new(0),
+            memory_growth: Arc::default(),
+            interceptors: Interceptors::default(),
+            watchdog: None,
+            interrupt: None,
+            faulted: Cell::new(false),
+            module_hash: None,
+            symbols: None,
+            event_history: EventHistory::default(),
+            bump_region: None,
+            frame_globals: None,
+            stamp: Cell::default(),
+            abi_version: gers_events::ABI_VERSION,
+            init_fn: None,
+            capabilities: Capabilities::NONE,
+            handshake_done: false,
+            stubbed_imports: vec![],
+            config,
+        }
+    }
+
+    /// Path of the directory or archive the plugin was loaded from.
+    pub fn root(&self) -> &Path {
+        self.source.path()
+    }
+
+    /// Tells the plugin apart from the others loaded, as the owner of
+    /// host resources and the receiver of their events.
+    ///
+    /// This is the root, with the instance appended for instances of a
+    /// plugin, which share their root.
+    pub fn key(&self) -> &Path {
+        &self.key
+    }
+
+    pub fn source(&self) -> &PluginSource {
+        &self.source
+    }
+
+    /// Module instance, or `None` for data-only plugins.
+    pub fn instance(&self) -> Option<&wasmer::Instance> {
+        self.instance.as_ref()
+    }
+
+    /// Plugin has no WebAssembly module, only content.
+    pub fn is_data_only(&self) -> bool {
+        self.instance.is_none()
     }
 
     pub fn memory(&self) -> Result<&wasmer::Memory, wasmer::ExportError> {
-        self.instance.exports.get_memory("memory")
+        match self.instance {
+            Some(ref instance) => instance.exports.get_memory("memory"),
+            None => Err(wasmer::ExportError::Missing("memory".to_string())),
+        }
+    }
+
+    /// Growth of the plugin's memory, as last observed by the host.
+    pub fn memory_growth(&self) -> &MemoryGrowth {
+        &self.memory_growth
+    }
+
+    /// Re-query the size of the plugin's memory after a call into the
+    /// guest, which may have grown it.
+    ///
+    /// Returns `true` when the memory grew, and views into it taken
+    /// before the call are stale.
+    pub fn sync_memory(&self) -> bool {
+        match self.memory() {
+            Ok(memory) => self.memory_growth.observe(memory.size().0),
+            Err(_) => false,
+        }
     }
 
     pub fn meta(&self) -> &PluginMeta {
         &self.meta
     }
 
+    /// Stamp of the events sent in the current frame.
+    pub fn stamp(&self) -> EventStamp {
+        self.stamp.get()
+    }
+
+    /// Write the values of the current frame into the guest's memory,
+    /// if it exports where.
+    fn set_frame(&self, frame: &FrameGlobals) -> bool {
+        self.stamp.set(frame.stamp());
+        match (self.frame_globals, self.memory()) {
+            (Some(ptr), Ok(memory)) => arena::write_value(memory, ptr, frame),
+            _ => false,
+        }
+    }
+
+    /// Custom event types the plugin defines and handles.
+    pub fn event_manifest(&self) -> &EventManifest {
+        &self.event_manifest
+    }
+
+    /// Event types the plugin lays out differently than the host, which
+    /// it isn't sent.
+    pub fn mismatched_layouts(&self) -> &[EventType] {
+        &self.mismatched_layouts
+    }
+
+    /// Events of the type are laid out the same by the plugin and the
+    /// host, as far as the plugin reported.
+    pub(crate) fn accepts_layout(&self, event_type: i32) -> bool {
+        !self
+            .mismatched_layouts
+            .contains(&EventType::from(event_type))
+    }
+
     pub fn update_fn(&self) -> Option<&wasmer::Function> {
         self.update_fn.as_ref()
     }
 
+    /// Call the plugin's frame update.
+    ///
+    /// Guests with more work than fits in a frame return
+    /// `UpdateStatus::Continue` from `__gers_update`, and are resumed
+    /// with `resume` on later frames until they report they're done.
+    /// The update isn't called while work is pending. Updates that
+    /// return nothing are always done.
+    pub fn update(&mut self) -> Result<UpdateStatus, RuntimeError> {
+        let update_fn = match self.update_fn {
+            Some(ref update_fn) if !self.work_pending => update_fn,
+            _ => return Ok(UpdateStatus::Done),
+        };
+
+        let result = self.intercept("__gers_update", || update_fn.call(&[]));
+        self.sync_memory();
+        let status = match result?.first() {
+            Some(Val::I32(code)) => UpdateStatus::from(*code),
+            _ => UpdateStatus::Done,
+        };
+        // Work can't be resumed without the hook.
+        self.work_pending = status == UpdateStatus::Continue && self.resume_fn.is_some();
+
+        Ok(status)
+    }
+
+    /// Work of an earlier update is waiting to be resumed.
+    pub fn has_pending_work(&self) -> bool {
+        self.work_pending
+    }
+
+    /// Resume pending work through the guest's `__gers_resume(budget_us)`.
+    ///
+    /// The budget is passed to the guest in microseconds, and the guest
+    /// is expected to return before it runs out.
+    pub fn resume(&mut self, budget: Duration) -> Result<UpdateStatus, RuntimeError> {
+        let resume_fn = match (&self.resume_fn, self.work_pending) {
+            (Some(resume_fn), true) => resume_fn,
+            _ => return Ok(UpdateStatus::Done),
+        };
+
+        let budget_us = budget.as_micros().min(u32::MAX as u128) as u32;
+        let result = self.intercept("__gers_resume", || resume_fn.call(budget_us));
+        self.sync_memory();
+        match result {
+            Ok(code) => {
+                let status = UpdateStatus::from(code);
+                self.work_pending = status == UpdateStatus::Continue;
+                Ok(status)
+            }
+            Err(err) => {
+                // Don't keep calling into a faulting hook.
+                self.work_pending = false;
+                Err(err)
+            }
+        }
+    }
+
+    /// Run the plugin's init hooks, allocating its event buffer.
+    ///
+    /// Called once for every plugin, before its first update. The guest
+    /// is first told the host's version and capabilities, see
+    /// `handshake`. Plugins that already have an event buffer are left
+    /// as they are. A guest returning a null pointer has no buffer, and
+    /// isn't sent events one at a time.
+    pub fn init(&mut self) -> Result<(), RuntimeError> {
+        self.handshake()?;
+        if self.data_ptr.is_some() {
+            return Ok(());
+        }
+        if let Some(alloc_fn) = &self.event_alloc_fn {
+            let data_ptr = self.intercept("__gers_event_alloc", || {
+                alloc_fn.call(EVENT_BUFFER_SIZE, EVENT_BUFFER_ALIGN)
+            })?;
+            if data_ptr.offset() == 0 {
+                return Err(RuntimeError::new("guest event buffer allocation failed"));
+            }
+            self.data_ptr = Some(data_ptr);
+        }
+
+        Ok(())
+    }
+
     pub fn event_alloc_fn(&self) -> Option<&EventAllocFn> {
         self.event_alloc_fn.as_ref()
     }
@@ -206,6 +1140,438 @@ impl Plugin {
     pub fn event_update_fn(&self) -> Option<&EventUpdateFn> {
         self.event_update_fn.as_ref()
     }
+
+    /// Header of an event sent to the plugin in the current frame.
+    pub(crate) fn event_header(
+        &self,
+        event_type: i32,
+        size: u32,
+        flags: u32,
+        source: u32,
+    ) -> EventHeader {
+        EventHeader {
+            event_type,
+            size,
+            stamp: self.stamp.get(),
+            flags,
+            source,
+        }
+    }
+
+    /// Where the data of an event sent one at a time goes, after its
+    /// header for guests that get one.
+    fn event_data_ptr(&self) -> Option<WasmPtr<u8, Array>> {
+        let data_ptr = self.data_ptr?;
+        if self.abi_version < HEADED_EVENTS_ABI_VERSION {
+            return Some(data_ptr);
+        }
+        Some(WasmPtr::new(data_ptr.offset() + EVENT_HEADER_SIZE as u32))
+    }
+
+    /// Marshal event data into the plugin's event buffer and
+    /// call its event hook.
+    ///
+    /// Returns `false` when the plugin doesn't handle events,
+    /// or the event doesn't fit in the buffer.
+    pub fn dispatch_event<T: Clone>(
+        &self,
+        event_type: i32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        self.send_event(EVENT_SOURCE_HOST, 0, event_type, event)
+    }
+
+    /// Send an event a plugin emitted, like `dispatch_event`, with the
+    /// `plugin_id` of its source.
+    pub fn dispatch_event_from<T: Clone>(
+        &self,
+        source: u32,
+        event_type: i32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        self.send_event(source, 0, event_type, event)
+    }
+
+    /// Send an event encoded with postcard, see `EncodedEvent`.
+    ///
+    /// Returns `false` when the plugin doesn't handle events, is from
+    /// before ABI version 5, or the encoded event doesn't fit in the
+    /// buffer.
+    pub fn dispatch_encoded_event<T: EncodedEvent>(&self, event: &T) -> Result<bool, RuntimeError> {
+        self.dispatch_encoded_event_from(EVENT_SOURCE_HOST, event)
+    }
+
+    /// Send an encoded event a plugin emitted, like
+    /// `dispatch_encoded_event`, with the `plugin_id` of its source.
+    pub fn dispatch_encoded_event_from<T: EncodedEvent>(
+        &self,
+        source: u32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        // Older guests can't tell the event is encoded.
+        if self.abi_version < HEADED_EVENTS_ABI_VERSION {
+            return Ok(false);
+        }
+        let bytes = encode_event(event).map_err(|err| RuntimeError::new(err.to_string()))?;
+        if bytes.len() > EVENT_BUFFER_SIZE as usize - EVENT_HEADER_SIZE {
+            return Ok(false);
+        }
+
+        let size = bytes.len() as u32;
+        self.send_with(
+            source,
+            EVENT_FLAG_ENCODED,
+            T::TYPE as i32,
+            size,
+            |memory, ptr| strings::write_bytes(memory, ptr, &bytes),
+        )
+    }
+
+    fn send_event<T: Clone>(
+        &self,
+        source: u32,
+        flags: u32,
+        event_type: i32,
+        event: &T,
+    ) -> Result<bool, RuntimeError> {
+        let size = std::mem::size_of::<T>() as u32;
+        self.send_with(source, flags, event_type, size, |memory, ptr| {
+            arena::write_value(memory, ptr, event)
+        })
+    }
+
+    /// Write the header and `size` bytes of event data, with `write`,
+    /// and call the plugin's event hook.
+    fn send_with(
+        &self,
+        source: u32,
+        flags: u32,
+        event_type: i32,
+        size: u32,
+        write: impl FnOnce(&wasmer::Memory, WasmPtr<u8, Array>) -> bool,
+    ) -> Result<bool, RuntimeError> {
+        if !self.accepts_layout(event_type) {
+            return Ok(false);
+        }
+
+        let (data_ptr, event_data_ptr, update_fn) =
+            match (self.data_ptr, self.event_data_ptr(), self.event_update_fn()) {
+                (Some(data_ptr), Some(event_data_ptr), Some(update_fn)) => {
+                    (data_ptr, event_data_ptr, update_fn)
+                }
+                _ => return Ok(false),
+            };
+
+        let memory = match self.memory() {
+            Ok(memory) => memory,
+            Err(_) => return Ok(false),
+        };
+
+        // Marshal the event data into the plugin's linear memory. No
+        // view is kept across the call into the guest, which may grow
+        // the memory and leave it dangling.
+        if event_data_ptr != data_ptr {
+            let header = self.event_header(event_type, size, flags, source);
+            if !strings::write_bytes(memory, data_ptr, &header.encode()) {
+                return Ok(false);
+            }
+        }
+        if !write(memory, event_data_ptr) {
+            return Ok(false);
+        }
+        self.record_event(event_type, event_data_ptr.offset(), size);
+
+        let result = self.intercept("__gers_event_update", || {
+            update_fn.call(event_type, data_ptr)
+        });
+        self.sync_memory();
+        result?;
+
+        Ok(true)
+    }
+
+    /// Dispatch an event the plugin may modify, reading the event back
+    /// out of the plugin's event buffer once the hook returns.
+    ///
+    /// Returns `false` when the plugin didn't handle the event, in
+    /// which case it's left unchanged.
+    pub fn dispatch_mutable_event<T: MutableEvent>(
+        &self,
+        event_type: i32,
+        event: &mut T,
+    ) -> Result<bool, RuntimeError> {
+        self.dispatch_mutable_event_from(EVENT_SOURCE_HOST, event_type, event)
+    }
+
+    /// Dispatch an event a plugin emitted, which the plugin may modify,
+    /// like `dispatch_mutable_event`, with the `plugin_id` of its source.
+    pub fn dispatch_mutable_event_from<T: MutableEvent>(
+        &self,
+        source: u32,
+        event_type: i32,
+        event: &mut T,
+    ) -> Result<bool, RuntimeError> {
+        if !self.send_event(source, EVENT_FLAG_MUTABLE, event_type, event)? {
+            return Ok(false);
+        }
+
+        let (data_ptr, memory) = match (self.event_data_ptr(), self.memory()) {
+            (Some(data_ptr), Ok(memory)) => (data_ptr, memory),
+            _ => return Ok(false),
+        };
+        let size = std::mem::size_of::<T>() as u32;
+        let bytes = match strings::read_bytes(memory, data_ptr, size) {
+            Some(bytes) => bytes,
+            None => return Ok(false),
+        };
+
+        // SAFETY: The buffer holds `size_of::<T>()` bytes, and any bit
+        //         pattern is valid for `MutableEvent` types.
+        *event = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) };
+
+        Ok(true)
+    }
+
+    /// Copy a string into memory allocated by the guest's `__gers_alloc`.
+    ///
+    /// The returned pointer and length can be passed to the guest, which
+    /// reads it with `gers_api::recv_str`. Release it with `free_string`
+    /// once the guest is done with it.
+    pub fn send_string(&self, string: &str) -> Result<(WasmPtr<u8, Array>, u32), RuntimeError> {
+        self.send_bytes(string.as_bytes())
+    }
+
+    /// Copy bytes into memory allocated by the guest's `__gers_alloc`.
+    ///
+    /// Release them with `free_string` once the guest is done with them.
+    pub fn send_bytes(&self, bytes: &[u8]) -> Result<(WasmPtr<u8, Array>, u32), RuntimeError> {
+        let alloc_fn = self
+            .alloc_fn
+            .as_ref()
+            .ok_or_else(|| RuntimeError::new("plugin doesn't export __gers_alloc"))?;
+        let memory = self
+            .memory()
+            .map_err(|err| RuntimeError::new(err.to_string()))?;
+
+        let len = bytes.len() as u32;
+        let ptr = self.intercept("__gers_alloc", || alloc_fn.call(len))?;
+        // Allocating may have grown the memory.
+        self.sync_memory();
+        if ptr.offset() == 0 {
+            return Err(RuntimeError::new("guest allocation failed"));
+        }
+        self.host_allocations.set(self.host_allocations.get() + 1);
+        self.host_live_allocations
+            .set(self.host_live_allocations.get() + 1);
+
+        if !strings::write_bytes(memory, ptr, bytes) {
+            return Err(RuntimeError::new("guest allocation out of bounds"));
+        }
+
+        Ok((ptr, len))
+    }
+
+    /// Release a string sent with `send_string` or `send_bytes`.
+    pub fn free_string(&self, ptr: WasmPtr<u8, Array>, len: u32) -> Result<(), RuntimeError> {
+        match self.free_fn {
+            Some(ref free_fn) => {
+                self.intercept("__gers_free", || free_fn.call(ptr, len))?;
+                self.host_live_allocations
+                    .set(self.host_live_allocations.get().saturating_sub(1));
+                Ok(())
+            }
+            // Without a free hook the memory is leaked.
+            None => Ok(()),
+        }
+    }
+
+    /// The plugin exports `__gers_save`, and has state to save.
+    pub fn is_saved(&self) -> bool {
+        self.save_fn.is_some()
+    }
+
+    /// Call the guest's `__gers_save`, during which the guest
+    /// writes its state through the host's save imports.
+    ///
+    /// Returns `false` when the plugin has nothing to save.
+    pub fn save(&self) -> Result<bool, RuntimeError> {
+        match self.save_fn {
+            Some(ref save_fn) => {
+                let result = self.intercept("__gers_save", || save_fn.call());
+                self.sync_memory();
+                result.map(|_| true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Pass saved state to the guest's `__gers_load(ptr, len)`.
+    ///
+    /// The state is copied into memory allocated by the guest, and
+    /// released once the hook returns. Returns `false` when the
+    /// plugin doesn't load state.
+    pub fn load(&self, data: &[u8]) -> Result<bool, RuntimeError> {
+        let load_fn = match self.load_fn {
+            Some(ref load_fn) => load_fn,
+            None => return Ok(false),
+        };
+
+        let (ptr, len) = self.send_bytes(data)?;
+        let result = self.intercept("__gers_load", || load_fn.call(ptr, len));
+        self.sync_memory();
+        self.free_string(ptr, len)?;
+
+        result.map(|_| true)
+    }
+
+    /// Call the guest's `__gers_shutdown(budget_us)` before the app quits.
+    ///
+    /// The budget is passed to the guest in microseconds, and the guest
+    /// is expected to return before it runs out. Returns `false` when
+    /// the plugin doesn't export the hook.
+    pub fn shutdown(&self, budget: Duration) -> Result<bool, RuntimeError> {
+        match self.shutdown_fn {
+            Some(ref shutdown_fn) => {
+                let budget_us = budget.as_micros().min(u32::MAX as u128) as u32;
+                self.intercept("__gers_shutdown", || shutdown_fn.call(budget_us))
+                    .map(|_| true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The plugin exports `__gers_render`.
+    pub fn has_render(&self) -> bool {
+        self.render_fn.is_some()
+    }
+
+    /// Call the guest's `__gers_render(alpha)` before a frame is drawn.
+    ///
+    /// Alpha is how far the frame is between the last fixed update and
+    /// the next, from `0.0` to `1.0`, for interpolating what's drawn.
+    pub fn render(&self, alpha: f32) -> Result<(), RuntimeError> {
+        match self.render_fn {
+            Some(ref render_fn) => {
+                let result = self.intercept("__gers_render", || render_fn.call(alpha));
+                self.sync_memory();
+                result
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Take what the plugin wrote to its WASI standard output and error.
+    ///
+    /// Returns `None` for plugins that don't use WASI.
+    pub fn take_wasi_output(&self) -> Option<WasiOutput> {
+        self.wasi.as_ref().map(|wasi| wasi.take_output())
+    }
+
+    pub fn compact_fn(&self) -> Option<&CompactFn> {
+        self.compact_fn.as_ref()
+    }
+
+    pub fn compaction(&self) -> &Compaction {
+        &self.compaction
+    }
+
+    /// Signal memory pressure when the plugin's linear memory has grown by
+    /// at least `growth_pages` since the last signal.
+    ///
+    /// Requests compaction and returns `true` when under pressure. Plugins
+    /// that don't export the compaction hook are never under pressure.
+    pub fn check_memory_pressure(&mut self, growth_pages: u32) -> bool {
+        if self.compact_fn.is_none() || self.compaction.pending {
+            return false;
+        }
+
+        let pages = match self.memory() {
+            Ok(memory) => memory.size().0,
+            Err(_) => return false,
+        };
+
+        match self.compaction.baseline_pages {
+            Some(baseline) if pages >= baseline.saturating_add(growth_pages) => {
+                self.compaction.baseline_pages = Some(pages);
+                self.compaction.pending = true;
+                true
+            }
+            Some(_) => false,
+            None => {
+                // First measurement establishes the baseline.
+                self.compaction.baseline_pages = Some(pages);
+                false
+            }
+        }
+    }
+
+    /// Run one incremental compaction step, if compaction was requested.
+    ///
+    /// The budget is passed to the guest in microseconds. Returns the number
+    /// of pages the guest reclaimed. Compaction stops being pending once the
+    /// guest reports that it reclaimed nothing.
+    pub fn compact(&mut self, budget: Duration) -> Result<u32, RuntimeError> {
+        let compact_fn = match (&self.compact_fn, self.compaction.pending) {
+            (Some(compact_fn), true) => compact_fn,
+            _ => return Ok(0),
+        };
+
+        let budget_us = budget.as_micros().min(u32::MAX as u128) as u32;
+        let result = self.intercept("__gers_compact", || compact_fn.call(budget_us));
+        self.compaction.steps += 1;
+        self.sync_memory();
+
+        match result {
+            Ok(pages) => {
+                self.compaction.reclaimed_pages += pages as u64;
+                if pages == 0 {
+                    self.compaction.pending = false;
+                }
+                Ok(pages)
+            }
+            Err(err) => {
+                // Don't keep calling into a faulting hook.
+                self.compaction.pending = false;
+                Err(err)
+            }
+        }
+    }
+
+    /// A guest call ran past the watchdog timeout, and the plugin
+    /// isn't called anymore.
+    pub fn is_faulted(&self) -> bool {
+        self.faulted.get()
+    }
+
+    /// Call into a guest export through the interceptors, and the
+    /// watchdog.
+    pub(crate) fn intercept<T>(
+        &self,
+        function: &str,
+        call: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        if self.is_faulted() {
+            return Err(RuntimeError::new(format!(
+                "plugin {} is faulted",
+                self.meta.name
+            )));
+        }
+
+        self.interceptors.call(&self.meta.name, function, || {
+            match (&self.watchdog, &self.interrupt) {
+                (Some(watchdog), Some(interrupt)) => {
+                    watchdog.watch(interrupt, call).unwrap_or_else(|timed_out| {
+                        self.faulted.set(true);
+                        Err(RuntimeError::new(format!(
+                            "plugin {} faulted calling {}: {}",
+                            self.meta.name, function, timed_out
+                        )))
+                    })
+                }
+                _ => call(),
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,353 @@
+//! Functions plugins export to each other.
+//!
+//! A library plugin lists functions of its module under `[[export]]` in
+//! its `plugin.toml`, and plugins using it list it in `dependencies`,
+//! then import the functions from the `plugin:<library>` module, like
+//! `(import "plugin:mathlib" "checksum" ...)`. Plugins are loaded after
+//! their dependencies, see `Plugins::load_plugins`, and each import is
+//! resolved against the library's instance when the importing plugin is
+//! instantiated.
+//!
+//! Calls are forwarded through the host, as the plugins don't share a
+//! memory. Numbers are passed as they are. `bytes` are copied out of the
+//! caller's memory into memory the library allocates with `__gers_alloc`,
+//! and `buf` is allocated in the library with the caller's capacity, and
+//! copied back once the call returns. Both are released with
+//! `__gers_free` afterwards. The library runs as part of the caller's
+//! call, so a trap in the library fails the caller's call.
+use std::collections::{BTreeMap, HashSet};
+use wasmer::{
+    Exports, ExternType, Function, FunctionType, ImportObject, LazyInit, Memory, Module,
+    RuntimeError, Store, Type, Val, WasmPtr, WasmerEnv,
+};
+
+use crate::{
+    strings::{self, AllocFn, FreeFn},
+    ExportDecl, ExportParam, Plugin, PluginError, PluginMeta,
+};
+
+/// Prefix of the import modules that resolve to other plugins.
+pub const PLUGIN_IMPORT_PREFIX: &str = "plugin:";
+
+/// Order to load plugins in, so each comes after the plugins it depends
+/// on, and otherwise in the given order.
+///
+/// Dependencies that aren't among the plugins, or are in a cycle, are
+/// left to fail when the plugins depending on them are loaded.
+pub(crate) fn load_order(metas: &[&PluginMeta]) -> Vec<usize> {
+    fn visit(
+        index: usize,
+        metas: &[&PluginMeta],
+        seen: &mut HashSet<usize>,
+        order: &mut Vec<usize>,
+    ) {
+        if !seen.insert(index) {
+            return;
+        }
+        for dependency in metas[index].dependencies.iter() {
+            if let Some(dependency) = metas.iter().position(|meta| meta.name == *dependency) {
+                visit(dependency, metas, seen, order);
+            }
+        }
+        order.push(index);
+    }
+
+    let mut seen = HashSet::new();
+    let mut order = Vec::with_capacity(metas.len());
+    for index in 0..metas.len() {
+        visit(index, metas, &mut seen, &mut order);
+    }
+
+    order
+}
+
+/// WebAssembly parameters of an exported function, with `bytes` and
+/// `buf` taking two.
+fn wasm_type(decl: &ExportDecl) -> Result<FunctionType, String> {
+    let mut params = vec![];
+    for param in decl.params.iter() {
+        match param {
+            ExportParam::I32 => params.push(Type::I32),
+            ExportParam::I64 => params.push(Type::I64),
+            ExportParam::F32 => params.push(Type::F32),
+            ExportParam::F64 => params.push(Type::F64),
+            ExportParam::Bytes | ExportParam::Buf => params.extend([Type::I32, Type::I32]),
+        }
+    }
+    let results = match decl.result {
+        None => vec![],
+        Some(ExportParam::I32) => vec![Type::I32],
+        Some(ExportParam::I64) => vec![Type::I64],
+        Some(ExportParam::F32) => vec![Type::F32],
+        Some(ExportParam::F64) => vec![Type::F64],
+        Some(param) => return Err(format!("{} can't return {:?}", decl.name, param)),
+    };
+
+    Ok(FunctionType::new(params, results))
+}
+
+/// Library an import is forwarded to.
+#[derive(Clone)]
+struct Library {
+    name: String,
+    function: Function,
+    params: Vec<ExportParam>,
+    memory: Option<Memory>,
+    alloc_fn: Option<AllocFn>,
+    free_fn: Option<FreeFn>,
+}
+
+#[derive(WasmerEnv, Clone)]
+struct LinkEnv {
+    /// Memory of the importing plugin.
+    #[wasmer(export(optional = true))]
+    memory: LazyInit<Memory>,
+    library: Library,
+}
+
+/// Imports of the plugin from the plugins it depends on.
+///
+/// Fails when a dependency isn't loaded, or the module imports a
+/// function that isn't exported, or with another signature.
+pub(crate) fn import_object(
+    store: &Store,
+    meta: &PluginMeta,
+    module: &Module,
+    plugins: &[Plugin],
+) -> Result<ImportObject, PluginError> {
+    let library = |name: &str| plugins.iter().find(|plugin| plugin.meta.name == name);
+    for dependency in meta.dependencies.iter() {
+        if library(dependency).is_none() {
+            return Err(PluginError::Dependency(format!(
+                "plugin {} depends on {}, which isn't loaded",
+                meta.name, dependency
+            )));
+        }
+    }
+
+    let mut namespaces: BTreeMap<String, Exports> = BTreeMap::new();
+    for import in module.imports() {
+        let library_name = match import.module().strip_prefix(PLUGIN_IMPORT_PREFIX) {
+            Some(library_name) => library_name,
+            None => continue,
+        };
+        let error = |reason: &str| {
+            PluginError::Dependency(format!(
+                "plugin {} imports {}.{}, but {}",
+                meta.name,
+                import.module(),
+                import.name(),
+                reason
+            ))
+        };
+
+        if !meta.dependencies.iter().any(|name| name == library_name) {
+            return Err(error("doesn't depend on the plugin"));
+        }
+        let plugin = library(library_name).expect("dependencies are loaded");
+        let decl = plugin
+            .meta
+            .exports
+            .iter()
+            .find(|decl| decl.name == import.name())
+            .ok_or_else(|| error("the plugin doesn't export it"))?;
+        let ty = wasm_type(decl).map_err(|reason| error(&reason))?;
+        if !matches!(import.ty(), ExternType::Function(import_ty) if *import_ty == ty) {
+            return Err(error(&format!("the plugin exports it as {}", ty)));
+        }
+
+        let function = plugin
+            .instance
+            .as_ref()
+            .and_then(|instance| instance.exports.get_function(&decl.name).ok())
+            .filter(|function| *function.ty() == ty)
+            .ok_or_else(|| error("the plugin's module doesn't export it"))?;
+        let copies = decl
+            .params
+            .iter()
+            .any(|param| matches!(param, ExportParam::Bytes | ExportParam::Buf));
+        if copies && (plugin.alloc_fn.is_none() || plugin.free_fn.is_none()) {
+            return Err(error(
+                "the plugin doesn't export __gers_alloc and __gers_free to copy its parameters",
+            ));
+        }
+
+        let env = LinkEnv {
+            memory: LazyInit::new(),
+            library: Library {
+                name: plugin.meta.name.clone(),
+                function: function.clone(),
+                params: decl.params.clone(),
+                memory: plugin.memory().ok().cloned(),
+                alloc_fn: plugin.alloc_fn.clone(),
+                free_fn: plugin.free_fn.clone(),
+            },
+        };
+        namespaces
+            .entry(import.module().to_string())
+            .or_default()
+            .insert(
+                import.name(),
+                Function::new_with_env(store, ty, env, forward),
+            );
+    }
+
+    let mut imports = ImportObject::new();
+    for (namespace, exports) in namespaces {
+        imports.register(namespace, exports);
+    }
+
+    Ok(imports)
+}
+
+/// Call the library's function with the caller's arguments, copying
+/// slices between their memories.
+fn forward(env: &LinkEnv, args: &[Val]) -> Result<Vec<Val>, RuntimeError> {
+    let library = &env.library;
+    let mut lowered = Vec::with_capacity(args.len());
+    // Allocations in the library, released after the call.
+    let mut allocations = vec![];
+    // Buffers copied back to the caller after the call.
+    let mut outputs = vec![];
+
+    let result = (|| {
+        let mut args = args.iter();
+        for param in library.params.iter() {
+            match param {
+                ExportParam::Bytes | ExportParam::Buf => {
+                    let (ptr, len) = match (args.next(), args.next()) {
+                        (Some(Val::I32(ptr)), Some(Val::I32(len))) => {
+                            (WasmPtr::new(*ptr as u32), *len as u32)
+                        }
+                        _ => return Err(RuntimeError::new("invalid slice argument")),
+                    };
+                    let library_ptr = allocate(library, len)?;
+                    allocations.push((library_ptr, len));
+
+                    if *param == ExportParam::Bytes {
+                        let bytes = env
+                            .memory
+                            .get_ref()
+                            .and_then(|memory| strings::read_bytes(memory, ptr, len))
+                            .ok_or_else(|| RuntimeError::new("bytes out of bounds"))?;
+                        write_library(library, library_ptr, &bytes)?;
+                    } else {
+                        // Whatever the library had in the memory isn't
+                        // leaked to the caller.
+                        write_library(library, library_ptr, &vec![0; len as usize])?;
+                        outputs.push((ptr, library_ptr, len));
+                    }
+                    lowered.push(Val::I32(library_ptr.offset() as i32));
+                    lowered.push(Val::I32(len as i32));
+                }
+                _ => lowered.extend(args.next().cloned()),
+            }
+        }
+
+        let results = library.function.call(&lowered)?;
+        for (ptr, library_ptr, len) in outputs.iter() {
+            let bytes = library
+                .memory
+                .as_ref()
+                .and_then(|memory| strings::read_bytes(memory, *library_ptr, *len))
+                .ok_or_else(|| RuntimeError::new("buffer out of bounds"))?;
+            let written = env
+                .memory
+                .get_ref()
+                .map(|memory| strings::write_bytes(memory, *ptr, &bytes))
+                .unwrap_or(false);
+            if !written {
+                return Err(RuntimeError::new("buffer out of bounds"));
+            }
+        }
+
+        Ok(results.into_vec())
+    })();
+
+    if let Some(free_fn) = library.free_fn.as_ref() {
+        for (ptr, len) in allocations {
+            free_fn.call(ptr, len)?;
+        }
+    }
+
+    result
+        .map_err(|err| RuntimeError::new(format!("calling into plugin {}: {}", library.name, err)))
+}
+
+/// Allocate `len` bytes in the library. Empty slices aren't allocated.
+fn allocate(library: &Library, len: u32) -> Result<WasmPtr<u8, wasmer::Array>, RuntimeError> {
+    if len == 0 {
+        return Ok(WasmPtr::new(0));
+    }
+
+    let alloc_fn = library
+        .alloc_fn
+        .as_ref()
+        .ok_or_else(|| RuntimeError::new("library doesn't export __gers_alloc"))?;
+    match alloc_fn.call(len)? {
+        ptr if ptr.offset() == 0 => Err(RuntimeError::new("library allocation failed")),
+        ptr => Ok(ptr),
+    }
+}
+
+fn write_library(
+    library: &Library,
+    ptr: WasmPtr<u8, wasmer::Array>,
+    bytes: &[u8],
+) -> Result<(), RuntimeError> {
+    let written = library
+        .memory
+        .as_ref()
+        .map(|memory| strings::write_bytes(memory, ptr, bytes))
+        .unwrap_or(false);
+    if written {
+        Ok(())
+    } else {
+        Err(RuntimeError::new("library allocation out of bounds"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(name: &str, dependencies: &[&str]) -> PluginMeta {
+        toml::from_str(&format!(
+            "name = {:?}\nversion = \"1.0.0\"\ndependencies = {:?}\n",
+            name, dependencies
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dependencies_load_first() {
+        let (app, ui, core, other) = (
+            meta("app", &["ui", "core"]),
+            meta("ui", &["core"]),
+            meta("core", &[]),
+            meta("other", &["missing"]),
+        );
+        assert_eq!(load_order(&[&app, &ui, &core, &other]), [2, 1, 0, 3]);
+
+        // Cycles don't stop the others from loading.
+        let (a, b) = (meta("a", &["b"]), meta("b", &["a"]));
+        assert_eq!(load_order(&[&a, &b, &core]), [1, 0, 2]);
+    }
+
+    #[test]
+    fn test_export_types() {
+        let decl: ExportDecl = toml::from_str(
+            "name = \"checksum\"\nparams = [\"bytes\", \"f32\", \"buf\"]\nresult = \"i64\"\n",
+        )
+        .unwrap();
+        let ty = wasm_type(&decl).unwrap();
+        assert_eq!(
+            ty.params(),
+            [Type::I32, Type::I32, Type::F32, Type::I32, Type::I32]
+        );
+        assert_eq!(ty.results(), [Type::I64]);
+
+        let decl: ExportDecl = toml::from_str("name = \"name\"\nresult = \"buf\"\n").unwrap();
+        assert!(wasm_type(&decl).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Custom event types declared by guests.
+//!
+//! Guests that export `__gers_event_manifest() -> u64` list the custom
+//! event types they define and handle, with the pointer to the list in
+//! the low and its length in the high 32 bits. The host reads the list
+//! once, when the plugin is loaded. It's UTF-8 text with one entry per
+//! line, either `defines <name>` or `handles <name>`:
+//!
+//! ```text
+//! defines score_changed
+//! handles score_changed
+//! handles level_loaded
+//! ```
+//!
+//! Names can't contain whitespace, and blank lines are skipped.
+use crate::{strings, PluginError};
+
+/// Custom event types of a plugin.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventManifest {
+    /// Names of the custom event types the plugin defines.
+    pub defines: Vec<String>,
+    /// Names of the custom event types the plugin receives.
+    pub handles: Vec<String>,
+}
+
+impl EventManifest {
+    /// Parse the list returned by `__gers_event_manifest`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut manifest = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let (kind, name) = match (words.next(), words.next(), words.next()) {
+                (None, _, _) => continue,
+                (Some(kind), Some(name), None) => (kind, name),
+                _ => return Err(format!("line {}: expected `<kind> <name>`", number + 1)),
+            };
+
+            let names = match kind {
+                "defines" => &mut manifest.defines,
+                "handles" => &mut manifest.handles,
+                _ => return Err(format!("line {}: unknown kind {:?}", number + 1, kind)),
+            };
+            if !names.iter().any(|existing| existing == name) {
+                names.push(name.to_string());
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Read the manifest returned by `__gers_event_manifest` out of the
+/// instance's memory.
+pub(crate) fn read_event_manifest(
+    instance: &wasmer::Instance,
+    packed: u64,
+) -> Result<EventManifest, PluginError> {
+    let (ptr, len) = (packed as u32, (packed >> 32) as u32);
+    if len == 0 {
+        return Ok(EventManifest::default());
+    }
+
+    let bytes = instance
+        .exports
+        .get_memory("memory")
+        .ok()
+        .and_then(|memory| strings::read_bytes(memory, wasmer::WasmPtr::new(ptr), len))
+        .ok_or_else(|| PluginError::EventManifest("out of bounds".to_string()))?;
+    let text = String::from_utf8(bytes)
+        .map_err(|_| PluginError::EventManifest("not valid UTF-8".to_string()))?;
+
+    EventManifest::parse(&text).map_err(PluginError::EventManifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest =
+            EventManifest::parse("defines score_changed\n\nhandles score_changed\nhandles level_loaded\nhandles level_loaded\n")
+                .unwrap();
+        assert_eq!(manifest.defines, ["score_changed"]);
+        assert_eq!(manifest.handles, ["score_changed", "level_loaded"]);
+
+        assert_eq!(EventManifest::parse("").unwrap(), EventManifest::default());
+        assert!(EventManifest::parse("defines").is_err());
+        assert!(EventManifest::parse("defines two words").is_err());
+        assert!(EventManifest::parse("publishes score_changed").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Memory usage of plugins.
+//!
+//! Reports how much linear memory each plugin's instance holds, so
+//! bloated plugins can be found. Guests with a bump allocator can
+//! export `__gers_bump_stats() -> bytes`, returning the allocator's
+//! high-water mark. The host counts the buffers it allocates in guest
+//! memory through `__gers_alloc`, which leak when the guest doesn't
+//! export `__gers_free`.
+use std::fmt;
+
+use crate::{Plugin, Plugins, WASM_PAGE_SIZE};
+
+/// Memory usage of a single plugin.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Size of the linear memory in bytes.
+    pub memory_bytes: u64,
+    /// Size of the linear memory in WebAssembly pages.
+    pub pages: u32,
+    /// High-water mark of the guest's bump allocator in bytes, when the
+    /// guest exports `__gers_bump_stats`.
+    pub bump_high_water: Option<u32>,
+    /// Buffers the host allocated in guest memory.
+    pub host_allocations: u64,
+    /// Host allocated buffers that weren't released.
+    pub host_live_allocations: u64,
+}
+
+/// Memory usage of all loaded plugins.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryReport {
+    /// Plugin names with their memory usage, in load order.
+    pub plugins: Vec<(String, MemoryStats)>,
+}
+
+impl MemoryReport {
+    /// Total linear memory of all plugins in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.plugins
+            .iter()
+            .map(|(_, stats)| stats.memory_bytes)
+            .sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    /// Table of plugins, largest first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut plugins: Vec<_> = self.plugins.iter().collect();
+        plugins.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.memory_bytes));
+
+        writeln!(
+            f,
+            "{:<24} {:>12} {:>6} {:>12} {:>10}",
+            "plugin", "memory", "pages", "bump", "host"
+        )?;
+        for (name, stats) in plugins {
+            let bump = match stats.bump_high_water {
+                Some(bytes) => bytes.to_string(),
+                None => "-".to_string(),
+            };
+            let host = format!("{}/{}", stats.host_live_allocations, stats.host_allocations);
+            writeln!(
+                f,
+                "{:<24} {:>12} {:>6} {:>12} {:>10}",
+                name, stats.memory_bytes, stats.pages, bump, host
+            )?;
+        }
+        write!(f, "total {} bytes", self.total_bytes())
+    }
+}
+
+impl Plugin {
+    /// Memory usage of the plugin's instance.
+    ///
+    /// Data-only plugins report no memory.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let pages = match self.memory() {
+            Ok(memory) => memory.size().0,
+            Err(_) => 0,
+        };
+        let bump_high_water = match self.bump_stats_fn {
+            // A faulting hook reports nothing rather than failing the report.
+            Some(ref bump_stats_fn) => self
+                .intercept("__gers_bump_stats", || bump_stats_fn.call())
+                .ok(),
+            None => None,
+        };
+
+        MemoryStats {
+            memory_bytes: pages as u64 * WASM_PAGE_SIZE,
+            pages,
+            bump_high_water,
+            host_allocations: self.host_allocations.get(),
+            host_live_allocations: self.host_live_allocations.get(),
+        }
+    }
+}
+
+impl Plugins {
+    /// Memory usage of every loaded plugin.
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            plugins: self
+                .plugins
+                .iter()
+                .map(|plugin| (plugin.meta.name.clone(), plugin.memory_stats()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lists_largest_first() {
+        let stats = |pages: u32| MemoryStats {
+            memory_bytes: pages as u64 * WASM_PAGE_SIZE,
+            pages,
+            ..MemoryStats::default()
+        };
+        let report = MemoryReport {
+            plugins: vec![
+                ("small".to_string(), stats(1)),
+                ("bloated".to_string(), stats(64)),
+                ("content".to_string(), MemoryStats::default()),
+            ],
+        };
+        assert_eq!(report.total_bytes(), 65 * WASM_PAGE_SIZE);
+
+        let table = report.to_string();
+        let position = |name: &str| table.find(name).unwrap();
+        assert!(position("bloated") < position("small"));
+        assert!(position("small") < position("content"));
+    }
+}
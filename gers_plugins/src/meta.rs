@@ -1,8 +1,342 @@
 //! Schema of the `plugin.toml` file.
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Deserialize)]
+use crate::SettingDecl;
+
+#[derive(Clone, Deserialize)]
 pub struct PluginMeta {
     pub name: String,
     pub version: String,
+
+    /// Hex encoded SHA-256 hash of the plugin's module.
+    #[serde(default)]
+    pub sha256: Option<String>,
+
+    /// Hex encoded ed25519 signature of the plugin's module.
+    #[serde(default)]
+    pub signature: Option<String>,
+
+    /// Actions the plugin registers, with their default key bindings.
+    ///
+    /// ```toml
+    /// [input]
+    /// jump = ["Space"]
+    /// ```
+    #[serde(default)]
+    pub input: HashMap<String, Vec<String>>,
+
+    /// Plugins with a higher priority win conflicts over custom events,
+    /// key bindings and console commands. See `Plugins::conflicts`.
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Refuse to load the plugin when its module doesn't export the
+    /// hooks in `hooks`, so misspelled exports don't go unnoticed.
+    #[serde(default)]
+    pub strict: bool,
+
+    /// Hooks the module exports, named without the `__gers_` prefix.
+    /// Only checked when `strict`, which requires `update`,
+    /// `event_alloc` and `event_update` when none are listed.
+    ///
+    /// ```toml
+    /// strict = true
+    /// hooks = ["update", "render"]
+    /// ```
+    #[serde(default)]
+    pub hooks: Vec<String>,
+
+    /// Plugin is built for `wasm32-wasi` and needs the WASI imports.
+    #[serde(default)]
+    pub wasi: bool,
+
+    /// Capabilities granted to the plugin.
+    #[serde(default)]
+    pub permissions: Permissions,
+
+    /// Memory segments shared with other plugins that declare them.
+    ///
+    /// ```toml
+    /// [[shared_memory]]
+    /// name = "particles"
+    /// pages = 16
+    /// ```
+    #[serde(default)]
+    pub shared_memory: Vec<SharedMemoryDecl>,
+
+    /// Plugins whose exported functions this plugin imports, which are
+    /// loaded before it.
+    ///
+    /// ```toml
+    /// dependencies = ["mathlib"]
+    /// ```
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+
+    /// Functions other plugins can import from this one, as
+    /// `plugin:<name>.<function>`.
+    ///
+    /// ```toml
+    /// [[export]]
+    /// name = "checksum"
+    /// params = ["bytes", "i32"]
+    /// result = "i32"
+    /// ```
+    #[serde(default, rename = "export")]
+    pub exports: Vec<ExportDecl>,
+
+    /// Settings the user can change, by key.
+    ///
+    /// ```toml
+    /// [settings]
+    /// difficulty = { type = "int", default = 2, min = 0, max = 5 }
+    /// ```
+    #[serde(default)]
+    pub settings: BTreeMap<String, SettingDecl>,
+
+    /// How many events of a type the plugin receives each frame, by
+    /// event name, like `gamepad_axis`. Types not listed are delivered
+    /// in full.
+    ///
+    /// ```toml
+    /// [events]
+    /// gamepad_axis = "coalesce"
+    /// action = 4
+    /// ```
+    #[serde(default)]
+    pub events: BTreeMap<String, EventDelivery>,
+
+    /// Configuration passed to the guest when it's initialised, see
+    /// `Plugin::config`.
+    ///
+    /// ```toml
+    /// [config]
+    /// spawn_rate = 2.5
+    /// ```
+    #[serde(default)]
+    pub config: toml::value::Table,
+
+    /// Instances of the plugin, each loaded from the same module as
+    /// `<name>#<instance>`, with its own configuration. See
+    /// `PluginMeta::instance`.
+    ///
+    /// ```toml
+    /// [[instance]]
+    /// name = "wolf"
+    /// config = { creature = "wolf", spawn_rate = 0.5 }
+    /// ```
+    #[serde(default, rename = "instance")]
+    pub instances: Vec<InstanceDecl>,
+}
+
+impl PluginMeta {
+    /// The meta file describes a module, so the plugin can't be
+    /// loaded as data-only without one.
+    pub fn needs_module(&self) -> bool {
+        self.wasi || self.strict || self.sha256.is_some() || self.signature.is_some()
+    }
+}
+
+/// An instance of a plugin loaded more than once.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstanceDecl {
+    /// Name of the instance, limited to ASCII letters, digits, `-` and
+    /// `_`.
+    pub name: String,
+    /// Keys overriding the plugin's `[config]` table.
+    #[serde(default)]
+    pub config: toml::value::Table,
+}
+
+/// Capabilities a plugin must be granted to receive the
+/// corresponding host imports. Everything is denied by default.
+///
+/// ```toml
+/// [permissions]
+/// storage = true
+/// network = false
+/// http = true
+/// time = false
+/// window = false
+/// clipboard = false
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Reading and writing files in the plugin's data directory.
+    pub storage: bool,
+    /// Opening network connections.
+    pub network: bool,
+    /// Making HTTP requests.
+    pub http: bool,
+    /// Changing the time scale of the game.
+    pub time: bool,
+    /// Opening and controlling windows.
+    pub window: bool,
+    /// Reading and writing the clipboard.
+    pub clipboard: bool,
+}
+
+/// A single capability from `Permissions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Storage,
+    Network,
+    Http,
+    Time,
+    Window,
+    Clipboard,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::Storage,
+        Permission::Network,
+        Permission::Http,
+        Permission::Time,
+        Permission::Window,
+        Permission::Clipboard,
+    ];
+
+    /// Key of the permission in `plugin.toml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Storage => "storage",
+            Permission::Network => "network",
+            Permission::Http => "http",
+            Permission::Time => "time",
+            Permission::Window => "window",
+            Permission::Clipboard => "clipboard",
+        }
+    }
+}
+
+impl Permissions {
+    /// Capabilities the plugin was granted.
+    pub fn granted(&self) -> Vec<Permission> {
+        Permission::ALL
+            .into_iter()
+            .filter(|permission| self.is_granted(*permission))
+            .collect()
+    }
+
+    /// The plugin was granted the capability.
+    pub fn is_granted(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Storage => self.storage,
+            Permission::Network => self.network,
+            Permission::Http => self.http,
+            Permission::Time => self.time,
+            Permission::Window => self.window,
+            Permission::Clipboard => self.clipboard,
+        }
+    }
+}
+
+/// A memory segment the plugin imports as `gers_shared.<name>`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SharedMemoryDecl {
+    /// Name of the segment, the same in every plugin sharing it.
+    pub name: String,
+    /// Fixed size of the segment in WebAssembly pages.
+    pub pages: u32,
+}
+
+/// A function the plugin exports to other plugins.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportDecl {
+    /// Name of the function, exported by the module under the same name.
+    pub name: String,
+    #[serde(default)]
+    pub params: Vec<ExportParam>,
+    /// Only numbers can be returned.
+    #[serde(default)]
+    pub result: Option<ExportParam>,
+}
+
+/// Parameter of a function exported to other plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportParam {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Bytes passed as a pointer and length, copied into the memory of
+    /// the exporting plugin for the call.
+    Bytes,
+    /// Buffer passed as a pointer and capacity, which the exporting
+    /// plugin writes into, copied back once the call returns.
+    Buf,
+}
+
+/// How events of a type are delivered to a plugin each frame.
+///
+/// Either `"all"`, `"coalesce"`, or the number of events delivered at
+/// most.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawEventDelivery")]
+pub enum EventDelivery {
+    /// Every event is delivered.
+    #[default]
+    All,
+    /// Only the latest event about the same thing is delivered, like
+    /// the latest position of each gamepad axis.
+    Coalesce,
+    /// Only the latest events, up to the number, are delivered.
+    PerFrame(u32),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEventDelivery {
+    Name(String),
+    PerFrame(u32),
+}
+
+impl TryFrom<RawEventDelivery> for EventDelivery {
+    type Error = String;
+
+    fn try_from(raw: RawEventDelivery) -> Result<Self, Self::Error> {
+        match raw {
+            RawEventDelivery::Name(name) => match name.as_str() {
+                "all" => Ok(EventDelivery::All),
+                "coalesce" => Ok(EventDelivery::Coalesce),
+                _ => Err(format!(
+                    "unknown event delivery {:?}, expected \"all\", \"coalesce\" or a number",
+                    name
+                )),
+            },
+            RawEventDelivery::PerFrame(0) => {
+                Err("events delivered per frame must be at least 1".to_string())
+            }
+            RawEventDelivery::PerFrame(limit) => Ok(EventDelivery::PerFrame(limit)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Events {
+        events: BTreeMap<String, EventDelivery>,
+    }
+
+    fn parse(value: &str) -> Result<EventDelivery, toml::de::Error> {
+        let events: Events = toml::from_str(&format!("[events]\naction = {}", value))?;
+        Ok(events.events["action"])
+    }
+
+    #[test]
+    fn test_parse_event_delivery() {
+        assert_eq!(parse("\"all\"").unwrap(), EventDelivery::All);
+        assert_eq!(parse("\"coalesce\"").unwrap(), EventDelivery::Coalesce);
+        assert_eq!(parse("4").unwrap(), EventDelivery::PerFrame(4));
+        assert!(parse("0").is_err());
+        assert!(parse("-1").is_err());
+        assert!(parse("\"latest\"").is_err());
+    }
 }
@@ -0,0 +1,227 @@
+//! Paths of plugins and their files, across platforms.
+//!
+//! Mod packs are often put together on one platform and played on
+//! another. File names on Windows and macOS are case-insensitive, so a
+//! pack with `Plugin.toml` or `textures\Orc.png` works there, and then
+//! fails to load on Linux. Files in plugins are looked up the same way
+//! everywhere: an exact match is preferred, and otherwise names are
+//! compared ignoring ASCII case, with `\` separating directories like
+//! `/` does.
+//!
+//! Plugin roots that can't be loaded the same way everywhere are
+//! refused with an error saying why, rather than failing in confusing
+//! ways later: names that aren't valid UTF-8, symbolic links, and UNC
+//! network paths.
+use std::{
+    fs,
+    path::{Component, Path, PathBuf, Prefix},
+};
+
+use crate::PluginError;
+
+/// Names of the directories and the file of a relative path, split on
+/// both `/` and `\`.
+///
+/// `None` when the path leaves the plugin root, or isn't valid UTF-8.
+pub(crate) fn relative_parts(relative: &Path) -> Option<Vec<&str>> {
+    let mut parts = vec![];
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.extend(
+                part.to_str()?
+                    .split('\\')
+                    .filter(|part| !part.is_empty() && *part != "."),
+            ),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if parts.contains(&"..") {
+        return None;
+    }
+
+    Some(parts)
+}
+
+/// Find a file or directory in `root`, ignoring case where the exact
+/// name doesn't exist.
+///
+/// When several names only differ in case, the first in sort order is
+/// found, so it's the same every run.
+pub fn find_file(root: &Path, relative: impl AsRef<Path>) -> Option<PathBuf> {
+    let parts = relative_parts(relative.as_ref())?;
+
+    let mut path = root.to_path_buf();
+    for part in parts {
+        let exact = path.join(part);
+        if fs::symlink_metadata(&exact).is_ok() {
+            path = exact;
+            continue;
+        }
+
+        path = fs::read_dir(&path)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(part))
+            })
+            .map(|entry| entry.path())
+            .min()?;
+    }
+
+    Some(path)
+}
+
+/// Name of the archive entry at the relative path, ignoring case and
+/// the separator the archive was made with, when the exact name isn't
+/// in it.
+pub(crate) fn find_archive_entry<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    parts: &[&str],
+) -> Option<&'a str> {
+    let wanted = parts.join("/");
+    let mut found = vec![];
+    for name in names {
+        if name == wanted {
+            return Some(name);
+        }
+        if name.replace('\\', "/").eq_ignore_ascii_case(&wanted) {
+            found.push(name);
+        }
+    }
+
+    found.into_iter().min()
+}
+
+/// Check that the plugin at the path loads the same on every platform.
+pub fn check_plugin_path(path: &Path) -> Result<(), PluginError> {
+    if is_unc(path) {
+        return Err(PluginError::InvalidPath(format!(
+            "{} is a network (UNC) path, copy the plugin to a local directory",
+            path.display()
+        )));
+    }
+    if let Some(name) = path.file_name() {
+        if name.to_str().is_none() {
+            return Err(PluginError::InvalidPath(format!(
+                "the name of {} isn't valid UTF-8, rename it",
+                path.display()
+            )));
+        }
+    }
+    let is_symlink = fs::symlink_metadata(path)
+        .map(|meta| meta.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_symlink {
+        return Err(PluginError::InvalidPath(format!(
+            "{} is a symbolic link, copy the plugin there instead",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Network paths, like `\\server\share\mods`, which are only resolved
+/// on Windows.
+fn is_unc(path: &Path) -> bool {
+    if let Some(Component::Prefix(prefix)) = path.components().next() {
+        if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..)) {
+            return true;
+        }
+    }
+
+    path.to_str().is_some_and(|path| path.starts_with(r"\\"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_parts() {
+        assert_eq!(
+            relative_parts(Path::new("textures/orc.png")).unwrap(),
+            ["textures", "orc.png"]
+        );
+        assert_eq!(
+            relative_parts(Path::new(r"lang\de.toml")).unwrap(),
+            ["lang", "de.toml"]
+        );
+        assert_eq!(
+            relative_parts(Path::new("./main.wasm")).unwrap(),
+            ["main.wasm"]
+        );
+        for path in ["../plugin.toml", r"lang\..\..\x", "/etc/passwd"] {
+            assert_eq!(relative_parts(Path::new(path)), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_find_file() {
+        let root = std::env::temp_dir().join(format!("gers-paths-{}", std::process::id()));
+        fs::create_dir_all(root.join("Textures")).unwrap();
+        fs::write(root.join("Plugin.TOML"), b"").unwrap();
+        fs::write(root.join("Textures").join("Orc.png"), b"").unwrap();
+
+        assert_eq!(
+            find_file(&root, "plugin.toml"),
+            Some(root.join("Plugin.TOML"))
+        );
+        assert_eq!(
+            find_file(&root, r"textures\orc.PNG"),
+            Some(root.join("Textures").join("Orc.png"))
+        );
+        assert_eq!(find_file(&root, "main.wasm"), None);
+        assert_eq!(find_file(&root, "../plugin.toml"), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_archive_entry() {
+        let names = ["Plugin.toml", r"Lang\DE.toml", "lang/de.toml", "MAIN.wasm"];
+        let find = |path: &str| {
+            let parts = relative_parts(Path::new(path)).unwrap();
+            find_archive_entry(names, &parts)
+        };
+
+        assert_eq!(find("plugin.toml"), Some("Plugin.toml"));
+        assert_eq!(find("main.wasm"), Some("MAIN.wasm"));
+        // Exact names win over names differing in case.
+        assert_eq!(find("lang/de.toml"), Some("lang/de.toml"));
+        assert_eq!(find("LANG/De.toml"), Some(r"Lang\DE.toml"));
+        assert_eq!(find("textures/orc.png"), None);
+    }
+
+    #[test]
+    fn test_check_plugin_path() {
+        let root = std::env::temp_dir().join(format!("gers-check-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        assert!(check_plugin_path(&root).is_ok());
+
+        let err = check_plugin_path(Path::new(r"\\server\share\mods\orcs")).unwrap_err();
+        assert!(matches!(err, PluginError::InvalidPath(_)));
+        assert!(err.to_string().contains("UNC"), "{}", err);
+
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+            let link = root.with_extension("link");
+            std::os::unix::fs::symlink(&root, &link).unwrap();
+            let err = check_plugin_path(&link).unwrap_err();
+            assert!(err.to_string().contains("symbolic link"), "{}", err);
+            fs::remove_file(link).unwrap();
+
+            let invalid = root.join(OsStr::from_bytes(b"orcs\xff"));
+            let err = check_plugin_path(&invalid).unwrap_err();
+            assert!(err.to_string().contains("UTF-8"), "{}", err);
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
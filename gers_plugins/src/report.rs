@@ -0,0 +1,186 @@
+//! Report of how the plugins loaded.
+//!
+//! `Plugins::load_all` returns what it took to load each plugin, and
+//! what the plugin brings along, so slow or misconfigured plugins stand
+//! out as soon as the game starts, rather than once they misbehave.
+use gers_events::EventType;
+use std::{fmt, path::PathBuf, time::Duration};
+
+use crate::{abi, Permission, Plugin, PluginError};
+
+/// Time spent loading a plugin's module.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct LoadTimes {
+    pub(crate) compile: Duration,
+    pub(crate) instantiate: Duration,
+}
+
+/// How a plugin loaded.
+#[derive(Debug, Clone)]
+pub struct PluginReport {
+    pub name: String,
+    pub version: String,
+    /// Directory or archive the plugin was loaded from.
+    pub root: PathBuf,
+    /// Time verifying and compiling the module, zero for data-only
+    /// plugins.
+    pub compile_time: Duration,
+    /// Time instantiating the module, with the guest's own
+    /// initialisation for WASI plugins.
+    pub instantiate_time: Duration,
+    /// Pages of memory the module started with.
+    pub memory_pages: u32,
+    /// Known hooks the module exports.
+    pub hooks: Vec<&'static str>,
+    /// Event types the guest declares handlers for, followed by the
+    /// custom event types it handles.
+    pub subscriptions: Vec<String>,
+    /// Permissions granted in `plugin.toml`.
+    pub permissions: Vec<Permission>,
+    /// What works differently than the plugin's author may expect.
+    pub warnings: Vec<String>,
+}
+
+impl PluginReport {
+    pub(crate) fn new(plugin: &Plugin, times: LoadTimes, handled_events: &[EventType]) -> Self {
+        let meta = plugin.meta();
+
+        let mut subscriptions: Vec<String> = handled_events
+            .iter()
+            .filter(|event_type| **event_type != EventType::NoOp)
+            .map(|event_type| format!("{:?}", event_type))
+            .collect();
+        subscriptions.extend(plugin.event_manifest().handles.iter().cloned());
+
+        let mut warnings = vec![];
+        if plugin.is_compatibility_mode() {
+            warnings.push(format!(
+                "built for ABI version {}, running in compatibility mode",
+                plugin.abi_version()
+            ));
+        }
+        if plugin.is_degraded() {
+            warnings.push(format!(
+                "imports host functions this build doesn't provide, running in degraded mode: {}",
+                plugin.stubbed_imports().join(", ")
+            ));
+        }
+        for event_type in plugin.mismatched_layouts() {
+            warnings.push(format!(
+                "{:?} events are laid out differently, and won't be sent",
+                event_type
+            ));
+        }
+
+        Self {
+            name: meta.name.clone(),
+            version: meta.version.clone(),
+            root: plugin.root().to_path_buf(),
+            compile_time: times.compile,
+            instantiate_time: times.instantiate,
+            memory_pages: plugin.memory().map(|memory| memory.size().0).unwrap_or(0),
+            hooks: abi::lint(plugin.instance()).found,
+            subscriptions,
+            permissions: meta.permissions.granted(),
+            warnings,
+        }
+    }
+
+    /// Time compiling and instantiating the module.
+    pub fn load_time(&self) -> Duration {
+        self.compile_time + self.instantiate_time
+    }
+}
+
+/// Names, or `none`.
+fn list<T: AsRef<str>>(names: impl IntoIterator<Item = T>) -> String {
+    let names: Vec<String> = names
+        .into_iter()
+        .map(|name| name.as_ref().to_string())
+        .collect();
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+impl fmt::Display for PluginReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: compiled in {:.2}ms, instantiated in {:.2}ms, {} pages; hooks: {}; \
+             subscriptions: {}; permissions: {}",
+            self.name,
+            self.version,
+            self.compile_time.as_secs_f64() * 1000.0,
+            self.instantiate_time.as_secs_f64() * 1000.0,
+            self.memory_pages,
+            list(self.hooks.iter()),
+            list(self.subscriptions.iter()),
+            list(self.permissions.iter().map(|permission| permission.name())),
+        )
+    }
+}
+
+/// How the plugins loaded, in the order they were loaded.
+#[derive(Debug, Default)]
+pub struct StartupReport {
+    pub plugins: Vec<PluginReport>,
+    /// Errors of the plugins that failed to load.
+    pub errors: Vec<PluginError>,
+}
+
+impl StartupReport {
+    /// Time loading all plugins.
+    pub fn load_time(&self) -> Duration {
+        self.plugins.iter().map(PluginReport::load_time).sum()
+    }
+
+    /// The plugin that took the longest to load.
+    pub fn slowest(&self) -> Option<&PluginReport> {
+        self.plugins.iter().max_by_key(|plugin| plugin.load_time())
+    }
+
+    /// Number of plugins that were skipped, as they're disabled.
+    pub fn disabled_count(&self) -> usize {
+        self.errors
+            .iter()
+            .filter(|err| matches!(err.inner(), PluginError::Disabled(_)))
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.plugins
+            .iter()
+            .map(|plugin| plugin.warnings.len())
+            .sum()
+    }
+}
+
+impl fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let disabled = self.disabled_count();
+        write!(
+            f,
+            "loaded {} plugins in {:.2}ms, {} failed, ",
+            self.plugins.len(),
+            self.load_time().as_secs_f64() * 1000.0,
+            self.errors.len() - disabled,
+        )?;
+        if disabled > 0 {
+            write!(f, "{} disabled, ", disabled)?;
+        }
+        write!(f, "{} warnings", self.warning_count())?;
+        if let Some(slowest) = self.slowest() {
+            write!(
+                f,
+                ", slowest {} in {:.2}ms",
+                slowest.name,
+                slowest.load_time().as_secs_f64() * 1000.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
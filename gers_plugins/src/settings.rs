@@ -0,0 +1,423 @@
+//! Settings plugins expose to the user.
+//!
+//! Plugins declare their settings in `plugin.toml`, each with a type
+//! and a default, and numbers optionally with a range:
+//!
+//! ```toml
+//! [settings]
+//! difficulty = { type = "int", default = 2, min = 0, max = 5 }
+//! volume = { type = "float", default = 0.8, min = 0.0, max = 1.0 }
+//! hardcore = { type = "bool", default = false }
+//! greeting = { type = "string", default = "Hello" }
+//! ```
+//!
+//! The host keeps the values the user chose in a file, by plugin, and
+//! plugins read them with the `gers_settings` imports. Values that don't
+//! fit the declaration anymore, like after an update of the plugin
+//! narrowed a range, fall back to the default.
+//!
+//! ```toml
+//! [plugins.my-plugin]
+//! difficulty = 4
+//! ```
+use gers_events::{SettingKind, SETTING_KEY_SIZE};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{PluginError, PluginMeta};
+
+/// Declaration of a setting in `plugin.toml`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SettingDecl {
+    Int {
+        default: i64,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Float {
+        default: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Bool {
+        default: bool,
+    },
+    String {
+        default: String,
+    },
+}
+
+/// Value of a setting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Int(value) => write!(f, "{}", value),
+            SettingValue::Float(value) => write!(f, "{}", value),
+            SettingValue::Bool(value) => write!(f, "{}", value),
+            SettingValue::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl SettingDecl {
+    pub fn kind(&self) -> SettingKind {
+        match self {
+            SettingDecl::Int { .. } => SettingKind::Int,
+            SettingDecl::Float { .. } => SettingKind::Float,
+            SettingDecl::Bool { .. } => SettingKind::Bool,
+            SettingDecl::String { .. } => SettingKind::String,
+        }
+    }
+
+    pub fn default_value(&self) -> SettingValue {
+        match self {
+            SettingDecl::Int { default, .. } => SettingValue::Int(*default),
+            SettingDecl::Float { default, .. } => SettingValue::Float(*default),
+            SettingDecl::Bool { default } => SettingValue::Bool(*default),
+            SettingDecl::String { default } => SettingValue::String(default.clone()),
+        }
+    }
+
+    /// Check a value has the declared type and is in range.
+    ///
+    /// Integers are accepted for floats.
+    pub fn check(&self, value: SettingValue) -> Result<SettingValue, String> {
+        match (self, value) {
+            (SettingDecl::Int { min, max, .. }, SettingValue::Int(value)) => {
+                check_range(value, *min, *max).map(SettingValue::Int)
+            }
+            (SettingDecl::Float { min, max, .. }, SettingValue::Int(value)) => {
+                check_range(value as f64, *min, *max).map(SettingValue::Float)
+            }
+            (SettingDecl::Float { min, max, .. }, SettingValue::Float(value)) => {
+                if !value.is_finite() {
+                    return Err(format!("{} is not a finite number", value));
+                }
+                check_range(value, *min, *max).map(SettingValue::Float)
+            }
+            (SettingDecl::Bool { .. }, value @ SettingValue::Bool(_))
+            | (SettingDecl::String { .. }, value @ SettingValue::String(_)) => Ok(value),
+            (decl, value) => Err(format!("expected {}, found {}", decl.type_name(), value)),
+        }
+    }
+
+    /// Parse a value typed by the user, like `4` or `true`.
+    ///
+    /// Strings are taken as they are, without quotes.
+    pub fn parse(&self, text: &str) -> Result<SettingValue, String> {
+        let value = match self {
+            SettingDecl::Int { .. } => text.parse().map(SettingValue::Int).ok(),
+            SettingDecl::Float { .. } => text.parse().map(SettingValue::Float).ok(),
+            SettingDecl::Bool { .. } => text.parse().map(SettingValue::Bool).ok(),
+            SettingDecl::String { .. } => Some(SettingValue::String(text.to_string())),
+        };
+
+        match value {
+            Some(value) => self.check(value),
+            None => Err(format!("expected {}, found {:?}", self.type_name(), text)),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            SettingDecl::Int { .. } => "int",
+            SettingDecl::Float { .. } => "float",
+            SettingDecl::Bool { .. } => "bool",
+            SettingDecl::String { .. } => "string",
+        }
+    }
+}
+
+fn check_range<T>(value: T, min: Option<T>, max: Option<T>) -> Result<T, String>
+where
+    T: PartialOrd + fmt::Display,
+{
+    match (min, max) {
+        (Some(min), _) if value < min => Err(format!("{} is less than {}", value, min)),
+        (_, Some(max)) if value > max => Err(format!("{} is more than {}", value, max)),
+        _ => Ok(value),
+    }
+}
+
+/// Check the settings declared in a plugin's meta file.
+///
+/// Keys are limited to ASCII letters, digits, `-`, `_` and `.`, and
+/// `SETTING_KEY_SIZE` bytes, so they fit a `SettingsChangedEvent`.
+pub(crate) fn validate_settings(meta: &PluginMeta) -> Result<(), PluginError> {
+    let mut problems = vec![];
+
+    for (key, decl) in meta.settings.iter() {
+        let valid_key = !key.is_empty()
+            && key.len() <= SETTING_KEY_SIZE
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_key {
+            problems.push(format!("invalid key {:?}", key));
+            continue;
+        }
+
+        let range_error = match decl {
+            SettingDecl::Int {
+                min: Some(min),
+                max: Some(max),
+                ..
+            } if min > max => Some(format!("{} is more than {}", min, max)),
+            SettingDecl::Float {
+                min: Some(min),
+                max: Some(max),
+                ..
+            } if min > max => Some(format!("{} is more than {}", min, max)),
+            _ => None,
+        };
+        let result = match range_error {
+            Some(err) => Err(format!("range: {}", err)),
+            None => decl
+                .check(decl.default_value())
+                .map_err(|err| format!("default: {}", err)),
+        };
+        if let Err(err) = result {
+            problems.push(format!("{} {}", key, err));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(PluginError::InvalidSettings(problems.join("; ")))
+    }
+}
+
+/// Values the user chose for plugins' settings, persisted between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    plugins: BTreeMap<String, BTreeMap<String, SettingValue>>,
+
+    /// File the values are persisted to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    /// Changed since the last save.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Settings {
+    /// Load the values from a file, starting empty when it doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        let path = path.as_ref();
+
+        let mut settings: Settings = if path.is_file() {
+            toml::from_str(&fs::read_to_string(path)?)?
+        } else {
+            Settings::default()
+        };
+        settings.path = Some(path.to_path_buf());
+
+        Ok(settings)
+    }
+
+    /// Value of a plugin's setting, or its default when the user
+    /// didn't choose a value that fits the declaration.
+    pub fn get(&self, plugin: &str, key: &str, decl: &SettingDecl) -> SettingValue {
+        self.plugins
+            .get(plugin)
+            .and_then(|values| values.get(key))
+            .and_then(|value| decl.check(value.clone()).ok())
+            .unwrap_or_else(|| decl.default_value())
+    }
+
+    /// Choose the value of a plugin's setting.
+    ///
+    /// Returns the value as it's stored, with integers given for
+    /// floats converted.
+    pub fn set(
+        &mut self,
+        plugin: &str,
+        key: &str,
+        decl: &SettingDecl,
+        value: SettingValue,
+    ) -> Result<SettingValue, PluginError> {
+        let value = decl
+            .check(value)
+            .map_err(|err| PluginError::InvalidSettingValue(format!("{}: {}", key, err)))?;
+
+        let values = self.plugins.entry(plugin.to_string()).or_default();
+        if values.get(key) != Some(&value) {
+            values.insert(key.to_string(), value.clone());
+            self.dirty = true;
+        }
+
+        Ok(value)
+    }
+
+    /// Write the values to their file, if they changed.
+    ///
+    /// Values that weren't loaded from a file aren't persisted.
+    pub fn save(&mut self) -> Result<(), PluginError> {
+        let path = match (&self.path, self.dirty) {
+            (Some(path), true) => path,
+            _ => return Ok(()),
+        };
+
+        fs::write(path, toml::to_string(self)?)?;
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(settings: &str) -> PluginMeta {
+        toml::from_str(&format!(
+            "name = \"test\"\nversion = \"1.0.0\"\n[settings]\n{}",
+            settings
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_declarations() {
+        let meta = meta(
+            r#"difficulty = { type = "int", default = 2, min = 0, max = 5 }
+            volume = { type = "float", default = 1, max = 1.0 }
+            hardcore = { type = "bool", default = false }
+            greeting = { type = "string", default = "Hello" }"#,
+        );
+        validate_settings(&meta).unwrap();
+
+        let difficulty = &meta.settings["difficulty"];
+        assert_eq!(difficulty.kind(), SettingKind::Int);
+        assert_eq!(
+            difficulty,
+            &SettingDecl::Int {
+                default: 2,
+                min: Some(0),
+                max: Some(5)
+            }
+        );
+        assert_eq!(
+            meta.settings["volume"].default_value(),
+            SettingValue::Float(1.0)
+        );
+        assert_eq!(
+            meta.settings["greeting"].parse("Hi there"),
+            Ok(SettingValue::String("Hi there".to_string()))
+        );
+        assert_eq!(difficulty.parse("4"), Ok(SettingValue::Int(4)));
+        assert!(difficulty.parse("6").is_err());
+        assert!(difficulty.parse("true").is_err());
+        assert!(meta.settings["volume"].parse("NaN").is_err());
+    }
+
+    #[test]
+    fn test_invalid_declarations() {
+        let meta = meta(
+            r#"difficulty = { type = "int", default = 7, min = 0, max = 5 }
+            range = { type = "float", default = 0.5, min = 1.0, max = 0.0 }
+            "two words" = { type = "bool", default = true }"#,
+        );
+
+        match validate_settings(&meta) {
+            Err(PluginError::InvalidSettings(report)) => assert_eq!(
+                report,
+                "difficulty default: 7 is more than 5; \
+                 range range: 1 is more than 0; \
+                 invalid key \"two words\""
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_values() {
+        let decl = SettingDecl::Int {
+            default: 2,
+            min: Some(0),
+            max: Some(5),
+        };
+        let narrowed = SettingDecl::Int {
+            default: 2,
+            min: Some(0),
+            max: Some(3),
+        };
+        let mut settings = Settings::default();
+        assert_eq!(
+            settings.get("test", "difficulty", &decl),
+            SettingValue::Int(2)
+        );
+
+        settings
+            .set("test", "difficulty", &decl, SettingValue::Int(4))
+            .unwrap();
+        assert_eq!(
+            settings.get("test", "difficulty", &decl),
+            SettingValue::Int(4)
+        );
+        assert_eq!(
+            settings.get("other", "difficulty", &decl),
+            SettingValue::Int(2)
+        );
+        // Values that don't fit the declaration fall back to the default.
+        assert_eq!(
+            settings.get("test", "difficulty", &narrowed),
+            SettingValue::Int(2)
+        );
+
+        assert!(matches!(
+            settings.set("test", "difficulty", &decl, SettingValue::Bool(true)),
+            Err(PluginError::InvalidSettingValue(_))
+        ));
+        assert_eq!(
+            settings.get("test", "difficulty", &decl),
+            SettingValue::Int(4)
+        );
+    }
+
+    #[test]
+    fn test_persisted_values() {
+        let path = std::env::temp_dir().join(format!("gers-settings-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let decl = SettingDecl::Float {
+            default: 0.5,
+            min: None,
+            max: None,
+        };
+
+        let mut settings = Settings::load(&path).unwrap();
+        settings
+            .set("my-plugin", "volume", &decl, SettingValue::Int(1))
+            .unwrap();
+        settings.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[plugins.my-plugin]\nvolume = 1.0\n"
+        );
+
+        let settings = Settings::load(&path).unwrap();
+        assert_eq!(
+            settings.get("my-plugin", "volume", &decl),
+            SettingValue::Float(1.0)
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Memory segments shared between plugins.
+//!
+//! Cooperating plugins can exchange large amounts of data through a
+//! memory segment they all import, instead of copying it through the
+//! host. Each plugin opts in by declaring the segment by name in its
+//! `plugin.toml`, and imports it as `gers_shared.<name>` next to its
+//! own memory, which needs the multi-memory proposal. The host creates
+//! the segment when the first plugin declaring it is loaded, and hands
+//! the same memory to every later one.
+//!
+//! Segments have a fixed size, so they never grow and views into them
+//! stay valid. A plugin importing a segment it didn't declare fails to
+//! load, and plugins that didn't declare a segment never receive it.
+use std::collections::HashMap;
+use wasmer::{ExternType, ImportObject, Memory, MemoryType, Module, Pages, Store};
+
+use crate::{PluginError, PluginMeta, Plugins};
+
+/// Import module the segments are provided under.
+pub const SHARED_MEMORY_MODULE: &str = "gers_shared";
+
+/// Segments created for the loaded plugins, by name.
+#[derive(Default)]
+pub(crate) struct SharedMemories {
+    memories: HashMap<String, Memory>,
+}
+
+impl SharedMemories {
+    pub(crate) fn get(&self, name: &str) -> Option<&Memory> {
+        self.memories.get(name)
+    }
+
+    /// Segment with the given name, created when it doesn't exist yet.
+    ///
+    /// Every plugin declaring the segment must agree on its size.
+    fn get_or_create(
+        &mut self,
+        store: &Store,
+        name: &str,
+        pages: u32,
+    ) -> Result<Memory, PluginError> {
+        if let Some(memory) = self.memories.get(name) {
+            if memory.size().0 != pages {
+                return Err(PluginError::SharedMemory(format!(
+                    "segment {} declared with {} pages, but it has {}",
+                    name,
+                    pages,
+                    memory.size().0
+                )));
+            }
+            return Ok(memory.clone());
+        }
+
+        if pages == 0 {
+            return Err(PluginError::SharedMemory(format!(
+                "segment {} must have at least one page",
+                name
+            )));
+        }
+        let ty = MemoryType::new(Pages(pages), Some(Pages(pages)), false);
+        let memory = Memory::new(store, ty)
+            .map_err(|err| PluginError::SharedMemory(format!("segment {}: {}", name, err)))?;
+        self.memories.insert(name.to_string(), memory.clone());
+
+        Ok(memory)
+    }
+
+    /// Imports with the segments the plugin declared.
+    ///
+    /// Fails when the module imports anything from `gers_shared` that
+    /// isn't a segment declared by the plugin.
+    pub(crate) fn import_object(
+        &mut self,
+        store: &Store,
+        meta: &PluginMeta,
+        module: &Module,
+    ) -> Result<ImportObject, PluginError> {
+        for import in module.imports() {
+            if import.module() != SHARED_MEMORY_MODULE {
+                continue;
+            }
+            let declared = meta
+                .shared_memory
+                .iter()
+                .any(|decl| decl.name == import.name());
+            if !declared || !matches!(import.ty(), ExternType::Memory(_)) {
+                return Err(PluginError::SharedMemory(format!(
+                    "plugin {} imports {}.{}, which it didn't declare as a shared memory",
+                    meta.name,
+                    SHARED_MEMORY_MODULE,
+                    import.name()
+                )));
+            }
+        }
+
+        let mut namespace = wasmer::Exports::new();
+        for decl in &meta.shared_memory {
+            let memory = self.get_or_create(store, &decl.name, decl.pages)?;
+            namespace.insert(decl.name.as_str(), memory);
+        }
+
+        let mut imports = ImportObject::new();
+        imports.register(SHARED_MEMORY_MODULE, namespace);
+
+        Ok(imports)
+    }
+}
+
+impl Plugins {
+    /// Shared memory segment with the given name, once a plugin
+    /// declaring it is loaded.
+    pub fn shared_memory(&self, name: &str) -> Option<&Memory> {
+        self.shared_memories.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(toml: &str) -> PluginMeta {
+        toml::from_str(&format!("name = \"test\"\nversion = \"1.0.0\"\n{}", toml)).unwrap()
+    }
+
+    #[test]
+    fn test_segment_is_shared() {
+        let store = Plugins::new().store;
+        let mut shared = SharedMemories::default();
+
+        let first = shared.get_or_create(&store, "particles", 2).unwrap();
+        let second = shared.get_or_create(&store, "particles", 2).unwrap();
+        assert!(first.same(&second));
+
+        assert!(shared.get_or_create(&store, "particles", 4).is_err());
+        assert!(shared.get_or_create(&store, "empty", 0).is_err());
+    }
+
+    #[test]
+    fn test_undeclared_import_is_rejected() {
+        let store = Plugins::new().store;
+        let module = Module::new(
+            &store,
+            r#"(module
+                 (import "gers_shared" "particles" (memory 1 1))
+                 (memory (export "memory") 1))"#,
+        )
+        .unwrap();
+
+        let mut shared = SharedMemories::default();
+        let result = shared.import_object(&store, &meta(""), &module);
+        assert!(matches!(result, Err(PluginError::SharedMemory(_))));
+
+        let declared = meta("[[shared_memory]]\nname = \"particles\"\npages = 1\n");
+        assert!(shared.import_object(&store, &declared, &module).is_ok());
+        assert!(shared.get("particles").is_some());
+    }
+}
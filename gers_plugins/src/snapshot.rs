@@ -0,0 +1,110 @@
+//! Snapshots of plugin state, for rewinding the simulation.
+//!
+//! A snapshot holds a copy of the instance's linear memory and the
+//! values of its exported mutable globals. Globals that the module
+//! doesn't export, like the shadow stack pointer, can't be reached
+//! through the instance. Snapshots must therefore be taken between
+//! calls into the guest, when the stack is unwound back to its base.
+use std::path::PathBuf;
+use wasmer::{Extern, Mutability, Val};
+
+use crate::{Plugin, PluginError, WASM_PAGE_SIZE};
+
+/// Copy of a single plugin's memory and globals.
+#[derive(Debug, Clone)]
+pub struct MemorySnapshot {
+    memory: Vec<u8>,
+    globals: Vec<(String, Val)>,
+}
+
+impl MemorySnapshot {
+    /// Size of the copied linear memory in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+/// Snapshots of all loaded plugins, taken at the same time.
+#[derive(Debug, Clone, Default)]
+pub struct PluginsSnapshot {
+    /// Keys of the plugins with their snapshots.
+    pub(crate) plugins: Vec<(PathBuf, MemorySnapshot)>,
+}
+
+impl PluginsSnapshot {
+    /// Total size of the copied memory in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.plugins
+            .iter()
+            .map(|(_, snapshot)| snapshot.memory_size())
+            .sum()
+    }
+}
+
+impl Plugin {
+    /// Copy the instance's linear memory and exported mutable globals.
+    ///
+    /// Returns `None` for data-only plugins.
+    pub fn snapshot(&self) -> Option<MemorySnapshot> {
+        let instance = self.instance.as_ref()?;
+        let memory = self.memory().ok()?;
+
+        // SAFETY: The guest isn't running while the host holds the
+        //         plugin, so the memory can't be grown from under us.
+        let memory = unsafe { memory.data_unchecked() }.to_vec();
+
+        let globals = instance
+            .exports
+            .iter()
+            .filter_map(|(name, export)| match export {
+                Extern::Global(global) if global.ty().mutability == Mutability::Var => {
+                    Some((name.clone(), global.get()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Some(MemorySnapshot { memory, globals })
+    }
+
+    /// Write a snapshot back into the instance.
+    ///
+    /// Linear memory can't shrink, so memory the guest grew since the
+    /// snapshot was taken is zeroed instead.
+    pub fn restore(&self, snapshot: &MemorySnapshot) -> Result<(), PluginError> {
+        let instance = self
+            .instance
+            .as_ref()
+            .ok_or_else(|| PluginError::Restore("plugin has no module instance".to_string()))?;
+        let memory = self
+            .memory()
+            .map_err(|err| PluginError::Restore(err.to_string()))?;
+
+        let current_size = memory.data_size() as usize;
+        if current_size < snapshot.memory.len() {
+            let pages = (snapshot.memory.len() - current_size) as u64 / WASM_PAGE_SIZE;
+            memory
+                .grow(pages as u32)
+                .map_err(|err| PluginError::Restore(err.to_string()))?;
+            self.sync_memory();
+        }
+
+        // SAFETY: See `snapshot`.
+        let data = unsafe { memory.data_unchecked_mut() };
+        let (restored, grown) = data.split_at_mut(snapshot.memory.len());
+        restored.copy_from_slice(&snapshot.memory);
+        grown.fill(0);
+
+        for (name, value) in snapshot.globals.iter() {
+            let global = instance
+                .exports
+                .get_global(name)
+                .map_err(|err| PluginError::Restore(err.to_string()))?;
+            global
+                .set(value.clone())
+                .map_err(|err| PluginError::Restore(err.message()))?;
+        }
+
+        Ok(())
+    }
+}
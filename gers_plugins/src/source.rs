@@ -0,0 +1,71 @@
+//! Locations plugins are loaded from.
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::paths;
+
+/// File extension of packaged plugins.
+pub const ARCHIVE_EXTENSION: &str = "gpak";
+
+/// Where a plugin's files are read from.
+#[derive(Debug, Clone)]
+pub enum PluginSource {
+    /// Unpacked plugin directory.
+    Directory(PathBuf),
+    /// Zip archive, read without unpacking to disk.
+    Archive(PathBuf),
+}
+
+impl PluginSource {
+    /// Path of the directory or archive.
+    pub fn path(&self) -> &Path {
+        match self {
+            PluginSource::Directory(path) | PluginSource::Archive(path) => path,
+        }
+    }
+
+    /// Read a file inside the plugin, given a path relative to the plugin root.
+    ///
+    /// Names differing only in case are found too, see `paths`.
+    ///
+    /// Fails with `io::ErrorKind::NotFound` when the file doesn't exist.
+    pub fn read_file(&self, relative: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let relative = relative.as_ref();
+
+        match self {
+            PluginSource::Directory(root) => match paths::find_file(root, relative) {
+                Some(path) => fs::read(path),
+                None => fs::read(root.join(relative)),
+            },
+            PluginSource::Archive(archive_path) => {
+                let parts = paths::relative_parts(relative).unwrap_or_default();
+                // Zip entries are always separated by forward slashes.
+                let name = parts.join("/");
+
+                let mut archive = zip::ZipArchive::new(File::open(archive_path)?)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                // Archives made on other platforms may name it differently.
+                let name = match paths::find_archive_entry(archive.file_names(), &parts) {
+                    Some(found) => found.to_string(),
+                    None => name,
+                };
+                let mut entry = match archive.by_name(&name) {
+                    Ok(entry) => entry,
+                    Err(zip::result::ZipError::FileNotFound) => {
+                        return Err(io::Error::new(io::ErrorKind::NotFound, name))
+                    }
+                    Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+                };
+
+                // The size in the entry's header isn't trusted, it only
+                // grows as far as the data goes.
+                let mut buf = vec![];
+                entry.read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+}
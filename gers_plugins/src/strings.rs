@@ -0,0 +1,97 @@
+//! Passing strings across the guest boundary.
+//!
+//! Guest to host strings are passed as a pointer and length into guest
+//! memory, which the host copies out. Host to guest strings are written
+//! into memory the guest allocates through its exported `__gers_alloc`,
+//! and released with `__gers_free` once the guest is done with them.
+//!
+//! The helpers look up the memory's data on every call and never keep a
+//! view of it, so they stay valid when the guest grows its memory.
+use wasmer::{Array, Memory, NativeFunc, WasmPtr};
+
+pub type AllocFn = NativeFunc<u32, WasmPtr<u8, Array>>;
+pub type FreeFn = NativeFunc<(WasmPtr<u8, Array>, u32), ()>;
+
+/// Copy a UTF-8 string out of guest memory.
+///
+/// Returns `None` when the range is out of bounds, or isn't valid UTF-8.
+pub fn read_str(memory: &Memory, ptr: WasmPtr<u8, Array>, len: u32) -> Option<String> {
+    // SAFETY: The string is copied before control returns to the guest,
+    //         so memory can't be mutated or grown while it's borrowed.
+    unsafe { ptr.get_utf8_str(memory, len) }.map(|string| string.to_string())
+}
+
+/// Copy bytes out of guest memory.
+///
+/// Returns `None` when the range is out of bounds.
+pub fn read_bytes(memory: &Memory, ptr: WasmPtr<u8, Array>, len: u32) -> Option<Vec<u8>> {
+    ptr.deref(memory, 0, len)
+        .map(|cells| cells.iter().map(|cell| cell.get()).collect())
+}
+
+/// Copy bytes into guest memory.
+///
+/// Returns `false` when the range is out of bounds.
+pub fn write_bytes(memory: &Memory, ptr: WasmPtr<u8, Array>, bytes: &[u8]) -> bool {
+    match ptr.deref(memory, 0, bytes.len() as u32) {
+        Some(cells) => {
+            for (cell, byte) in cells.iter().zip(bytes) {
+                cell.set(*byte);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::{MemoryType, Store, WASM_PAGE_SIZE};
+
+    const PAGE: u32 = WASM_PAGE_SIZE as u32;
+
+    fn memory() -> Memory {
+        Memory::new(&Store::default(), MemoryType::new(1, None, false)).unwrap()
+    }
+
+    #[test]
+    fn test_read_and_write_bytes() {
+        let memory = memory();
+
+        assert!(write_bytes(&memory, WasmPtr::new(8), b"hello"));
+        assert_eq!(read_bytes(&memory, WasmPtr::new(8), 5).unwrap(), b"hello");
+        assert_eq!(read_str(&memory, WasmPtr::new(8), 5).unwrap(), "hello");
+        assert_eq!(read_str(&memory, WasmPtr::new(8), 0).unwrap(), "");
+
+        // Ranges ending at the end of memory are in bounds.
+        assert!(write_bytes(&memory, WasmPtr::new(PAGE - 2), b"hi"));
+        assert_eq!(
+            read_bytes(&memory, WasmPtr::new(PAGE - 2), 2).unwrap(),
+            b"hi"
+        );
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let memory = memory();
+
+        assert!(!write_bytes(&memory, WasmPtr::new(PAGE - 1), b"hi"));
+        assert!(!write_bytes(&memory, WasmPtr::new(u32::MAX), b"hi"));
+        assert!(read_bytes(&memory, WasmPtr::new(PAGE - 1), 2).is_none());
+        assert!(read_bytes(&memory, WasmPtr::new(8), u32::MAX).is_none());
+        assert!(read_str(&memory, WasmPtr::new(PAGE), 1).is_none());
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let memory = memory();
+        write_bytes(&memory, WasmPtr::new(0), &[b'a', 0xff, b'b']);
+
+        assert!(read_str(&memory, WasmPtr::new(0), 3).is_none());
+        assert_eq!(
+            read_bytes(&memory, WasmPtr::new(0), 3).unwrap(),
+            [b'a', 0xff, b'b']
+        );
+    }
+}
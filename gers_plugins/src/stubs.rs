@@ -0,0 +1,151 @@
+//! Stubs for host imports this build doesn't provide.
+//!
+//! A plugin built against a host with more imports, or a different
+//! build of it, fails to instantiate when it imports a function the
+//! host doesn't have. Depending on the `MissingImports` policy, the
+//! loader generates stubs instead for unresolved functions in the
+//! host's namespaces, `gers` and `gers_*`, and the plugin loads in
+//! degraded mode. Imports of other modules are never stubbed.
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use wasmer::{
+    ExternType, Function, ImportObject, Module, NamedResolver, RuntimeError, Store, Type, Val,
+};
+
+use gers_events::HostError;
+
+use crate::Plugin;
+
+/// What happens to imports of host functions this build doesn't provide.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingImports {
+    /// Refuse to load the plugin.
+    #[default]
+    Fail,
+    /// Stub the imports with functions that trap when they're called.
+    Trap,
+    /// Stub the imports with functions returning
+    /// `HostError::Unsupported` from a single `i32` result, and zeros
+    /// from others. Functions with reference results still trap.
+    Error,
+}
+
+/// Namespace belongs to the host.
+fn is_host_namespace(namespace: &str) -> bool {
+    namespace == "gers" || namespace.starts_with("gers_")
+}
+
+/// Stubs for the module's function imports from host namespaces that
+/// `resolver` doesn't resolve.
+///
+/// Returns the stubs, with the names of the stubbed imports as
+/// `namespace.name`.
+pub(crate) fn import_object(
+    store: &Store,
+    module: &Module,
+    resolver: &dyn NamedResolver,
+    policy: MissingImports,
+) -> (ImportObject, Vec<String>) {
+    let mut imports = ImportObject::new();
+    let mut stubbed = vec![];
+    if policy == MissingImports::Fail {
+        return (imports, stubbed);
+    }
+
+    let mut namespaces: BTreeMap<String, wasmer::Exports> = BTreeMap::new();
+    for import in module.imports() {
+        let ty = match import.ty() {
+            ExternType::Function(ty) => ty.clone(),
+            _ => continue,
+        };
+        if !is_host_namespace(import.module())
+            || resolver
+                .resolve_by_name(import.module(), import.name())
+                .is_some()
+        {
+            continue;
+        }
+
+        let name = format!("{}.{}", import.module(), import.name());
+        let results = ty.results().to_vec();
+        let message = format!("{} isn't provided by this host", name);
+        let stub = Function::new(store, &ty, move |_| match policy {
+            MissingImports::Error => {
+                stub_results(&results).ok_or_else(|| RuntimeError::new(message.clone()))
+            }
+            _ => Err(RuntimeError::new(message.clone())),
+        });
+        namespaces
+            .entry(import.module().to_string())
+            .or_default()
+            .insert(import.name(), stub);
+        stubbed.push(name);
+    }
+
+    for (namespace, exports) in namespaces {
+        imports.register(namespace, exports);
+    }
+
+    (imports, stubbed)
+}
+
+impl Plugin {
+    /// Host imports this build doesn't provide, which were stubbed, as
+    /// `namespace.name`.
+    pub fn stubbed_imports(&self) -> &[String] {
+        &self.stubbed_imports
+    }
+
+    /// Some of the plugin's host imports were stubbed.
+    pub fn is_degraded(&self) -> bool {
+        !self.stubbed_imports.is_empty()
+    }
+}
+
+/// Results of an error returning stub, or `None` when they can't be
+/// made up.
+fn stub_results(results: &[Type]) -> Option<Vec<Val>> {
+    if let [Type::I32] = results {
+        return Some(vec![Val::I32(HostError::Unsupported.code())]);
+    }
+
+    results
+        .iter()
+        .map(|ty| match ty {
+            Type::I32 => Some(Val::I32(0)),
+            Type::I64 => Some(Val::I64(0)),
+            Type::F32 => Some(Val::F32(0.0)),
+            Type::F64 => Some(Val::F64(0.0)),
+            Type::V128 => Some(Val::V128(0)),
+            Type::ExternRef | Type::FuncRef => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_namespaces() {
+        assert!(is_host_namespace("gers"));
+        assert!(is_host_namespace("gers_audio"));
+        assert!(!is_host_namespace("gersx"));
+        assert!(!is_host_namespace("env"));
+    }
+
+    #[test]
+    fn test_stub_results() {
+        assert_eq!(
+            stub_results(&[Type::I32]),
+            Some(vec![Val::I32(HostError::Unsupported.code())])
+        );
+        assert_eq!(
+            stub_results(&[Type::I64, Type::F32]),
+            Some(vec![Val::I64(0), Val::F32(0.0)])
+        );
+        assert_eq!(stub_results(&[]), Some(vec![]));
+        assert_eq!(stub_results(&[Type::ExternRef]), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Source functions and locations of the frames in guests' stack traces.
+//!
+//! Traps only come with the function names wasmer finds in the module,
+//! and optimized modules often have none. With the `symbols` feature,
+//! modules are scanned once when they're loaded: function names come
+//! from the `name` section, demangled, and for modules built with debug
+//! info, the DWARF sections map each frame to its source location, with
+//! the functions inlined at it.
+//!
+//! DWARF addresses of WebAssembly modules are offsets into the contents
+//! of the code section, rather than into the module.
+use std::fmt;
+use wasmer::FrameInfo;
+
+use crate::Plugin;
+
+pub(crate) use imp::Symbols;
+
+/// Function and source location of a frame, or of a function inlined
+/// into it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FrameSymbol {
+    /// Demangled name of the function.
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for FrameSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function.as_deref().unwrap_or("<unknown>"))?;
+        if let Some(ref file) = self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Plugin {
+    /// Functions and source locations of a frame of the plugin's stack
+    /// trace, innermost inlined function first.
+    ///
+    /// Empty when the module has no symbols for the frame.
+    pub fn symbolicate(&self, frame: &FrameInfo) -> Vec<FrameSymbol> {
+        match self.symbols {
+            Some(ref symbols) => symbols.lookup(frame.func_index(), frame.module_offset()),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(feature = "symbols")]
+mod imp {
+    use std::{borrow::Cow, collections::HashMap, sync::Arc};
+    use wasmparser::{Name, NameSectionReader, Parser, Payload};
+
+    use super::FrameSymbol;
+
+    type Reader = gimli::EndianArcSlice<gimli::LittleEndian>;
+
+    pub(crate) struct Symbols {
+        /// Names from the name section, by function index.
+        names: HashMap<u32, String>,
+        /// Offset of the code section's contents in the module.
+        code_offset: usize,
+        dwarf: Option<addr2line::Context<Reader>>,
+    }
+
+    impl Symbols {
+        /// Symbols of a binary module, or `None` when it has neither
+        /// names nor debug info.
+        ///
+        /// Sections that fail to parse are skipped, as the module is
+        /// validated when it's compiled.
+        pub(crate) fn parse(wasm: &[u8]) -> Option<Self> {
+            let mut names = HashMap::new();
+            let mut code_offset = None;
+            let mut debug_sections = HashMap::new();
+
+            for payload in Parser::new(0).parse_all(wasm) {
+                // Nothing after a section that fails to parse can be read,
+                // but what was read before is kept.
+                let payload = match payload {
+                    Ok(payload) => payload,
+                    Err(_) => break,
+                };
+                match payload {
+                    Payload::CodeSectionStart { range, .. } => code_offset = Some(range.start),
+                    Payload::CustomSection {
+                        name: "name",
+                        data,
+                        data_offset,
+                        ..
+                    } => read_names(data, data_offset, &mut names),
+                    Payload::CustomSection { name, data, .. } if name.starts_with(".debug_") => {
+                        debug_sections.insert(name, data);
+                    }
+                    _ => {}
+                }
+            }
+
+            let dwarf = if debug_sections.contains_key(".debug_info") {
+                let dwarf = gimli::Dwarf::load(|id| {
+                    let data = debug_sections.get(id.name()).copied().unwrap_or_default();
+                    Ok::<_, gimli::Error>(Reader::new(Arc::from(data), gimli::LittleEndian))
+                });
+                dwarf
+                    .ok()
+                    .and_then(|dwarf| addr2line::Context::from_dwarf(dwarf).ok())
+            } else {
+                None
+            };
+
+            if names.is_empty() && dwarf.is_none() {
+                return None;
+            }
+
+            Some(Self {
+                names,
+                code_offset: code_offset.unwrap_or_default(),
+                dwarf,
+            })
+        }
+
+        /// Symbols of the function at `func_index`, at the byte offset
+        /// into the module, innermost inlined function first.
+        pub(crate) fn lookup(&self, func_index: u32, module_offset: usize) -> Vec<FrameSymbol> {
+            let mut symbols = vec![];
+
+            if let Some(ref dwarf) = self.dwarf {
+                let address = module_offset.saturating_sub(self.code_offset) as u64;
+                if let Ok(mut frames) = dwarf.find_frames(address) {
+                    while let Ok(Some(frame)) = frames.next() {
+                        let location = frame.location.unwrap_or(addr2line::Location {
+                            file: None,
+                            line: None,
+                            column: None,
+                        });
+                        symbols.push(FrameSymbol {
+                            function: frame
+                                .function
+                                .and_then(|function| function.demangle().ok().map(Cow::into_owned)),
+                            file: location.file.map(str::to_string),
+                            line: location.line,
+                            column: location.column,
+                        });
+                    }
+                }
+            }
+
+            match symbols.last_mut() {
+                // The outermost frame is the function itself.
+                Some(symbol) if symbol.function.is_none() => {
+                    symbol.function = self.names.get(&func_index).cloned();
+                }
+                Some(_) => {}
+                None => symbols.extend(self.names.get(&func_index).map(|name| FrameSymbol {
+                    function: Some(name.clone()),
+                    ..FrameSymbol::default()
+                })),
+            }
+
+            symbols
+        }
+    }
+
+    /// Read the demangled function names of a name section.
+    fn read_names(data: &[u8], offset: usize, names: &mut HashMap<u32, String>) {
+        let mut reader = match NameSectionReader::new(data, offset) {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+
+        while !reader.eof() {
+            let map = match reader.read() {
+                Ok(Name::Function(map)) => map,
+                Ok(_) => continue,
+                Err(_) => return,
+            };
+            let mut map = match map.get_map() {
+                Ok(map) => map,
+                Err(_) => return,
+            };
+            for _ in 0..map.get_count() {
+                match map.read() {
+                    Ok(naming) => {
+                        let name = addr2line::demangle_auto(Cow::from(naming.name), None);
+                        names.insert(naming.index, name.into_owned());
+                    }
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use gimli::write::{
+            Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections,
+        };
+
+        /// Module with a function named with a mangled Rust name.
+        const MODULE: &str = r#"(module
+            (func $_ZN4game6update17h0123456789abcdefE (result i32)
+                (i32.const 1)
+                (i32.const 2)
+                (i32.add)))"#;
+
+        fn custom_section(wasm: &mut Vec<u8>, name: &str, data: &[u8]) {
+            fn leb128(bytes: &mut Vec<u8>, mut value: usize) {
+                loop {
+                    let byte = (value & 0x7f) as u8;
+                    value >>= 7;
+                    if value == 0 {
+                        bytes.push(byte);
+                        return;
+                    }
+                    bytes.push(byte | 0x80);
+                }
+            }
+
+            let mut payload = vec![];
+            leb128(&mut payload, name.len());
+            payload.extend(name.as_bytes());
+            payload.extend(data);
+            wasm.push(0);
+            leb128(wasm, payload.len());
+            wasm.extend(payload);
+        }
+
+        /// Debug info with `update` at line 42 of `src/lib.rs`, covering
+        /// the first `len` bytes of the code section.
+        fn debug_info(len: u64) -> Vec<(&'static str, Vec<u8>)> {
+            let encoding = gimli::Encoding {
+                format: gimli::Format::Dwarf32,
+                version: 4,
+                address_size: 4,
+            };
+            let mut dwarf = DwarfUnit::new(encoding);
+
+            let mut program = LineProgram::new(
+                encoding,
+                gimli::LineEncoding::default(),
+                LineString::String(b"/game".to_vec()),
+                LineString::String(b"src/lib.rs".to_vec()),
+                None,
+            );
+            let directory = program.default_directory();
+            let file =
+                program.add_file(LineString::String(b"src/lib.rs".to_vec()), directory, None);
+            program.begin_sequence(Some(Address::Constant(0)));
+            program.row().file = file;
+            program.row().line = 42;
+            program.row().column = 5;
+            program.generate_row();
+            program.end_sequence(len);
+            dwarf.unit.line_program = program;
+
+            let root = dwarf.unit.root();
+            let unit = dwarf.unit.get_mut(root);
+            unit.set(
+                gimli::DW_AT_low_pc,
+                AttributeValue::Address(Address::Constant(0)),
+            );
+            unit.set(gimli::DW_AT_high_pc, AttributeValue::Udata(len));
+            unit.set(
+                gimli::DW_AT_comp_dir,
+                AttributeValue::String(b"/game".to_vec()),
+            );
+            let update = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+            let update = dwarf.unit.get_mut(update);
+            update.set(
+                gimli::DW_AT_name,
+                AttributeValue::String(b"update".to_vec()),
+            );
+            update.set(
+                gimli::DW_AT_low_pc,
+                AttributeValue::Address(Address::Constant(0)),
+            );
+            update.set(gimli::DW_AT_high_pc, AttributeValue::Udata(len));
+
+            let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+            dwarf.write(&mut sections).unwrap();
+            let mut debug_sections = vec![];
+            sections
+                .for_each(|id, data| {
+                    if !data.slice().is_empty() {
+                        debug_sections.push((id.name(), data.slice().to_vec()));
+                    }
+                    Ok::<_, gimli::write::Error>(())
+                })
+                .unwrap();
+
+            debug_sections
+        }
+
+        #[test]
+        fn test_function_names() {
+            let wasm = wasmer::wat2wasm(MODULE.as_bytes()).unwrap();
+            let symbols = Symbols::parse(&wasm).unwrap();
+            assert!(symbols.dwarf.is_none());
+
+            let name = |function: &str| FrameSymbol {
+                function: Some(function.to_string()),
+                ..FrameSymbol::default()
+            };
+            assert_eq!(symbols.lookup(0, 0), [name("game::update")]);
+            assert!(symbols.lookup(1, 0).is_empty());
+            assert_eq!(symbols.lookup(0, 0)[0].to_string(), "game::update");
+
+            // Text modules and modules without names have no symbols.
+            assert!(Symbols::parse(MODULE.as_bytes()).is_none());
+            let wasm = wasmer::wat2wasm(b"(module (func))").unwrap();
+            assert!(Symbols::parse(&wasm).is_none());
+        }
+
+        #[test]
+        fn test_source_locations() {
+            let mut wasm = wasmer::wat2wasm(MODULE.as_bytes()).unwrap().into_owned();
+            let code_offset = Parser::new(0)
+                .parse_all(&wasm)
+                .find_map(|payload| match payload.unwrap() {
+                    Payload::CodeSectionStart { range, .. } => Some(range.start),
+                    _ => None,
+                })
+                .unwrap();
+            for (name, data) in debug_info(16) {
+                custom_section(&mut wasm, name, &data);
+            }
+
+            let symbols = Symbols::parse(&wasm).unwrap();
+            let location = FrameSymbol {
+                function: Some("update".to_string()),
+                file: Some("/game/src/lib.rs".to_string()),
+                line: Some(42),
+                column: Some(5),
+            };
+            assert_eq!(
+                symbols.lookup(0, code_offset + 4),
+                std::slice::from_ref(&location)
+            );
+            assert_eq!(location.to_string(), "update at /game/src/lib.rs:42:5");
+
+            // Past the debug info, only the name is known.
+            assert_eq!(
+                symbols.lookup(0, code_offset + 64),
+                [FrameSymbol {
+                    function: Some("game::update".to_string()),
+                    ..FrameSymbol::default()
+                }]
+            );
+        }
+
+        #[test]
+        fn test_malformed_trailing_section() {
+            let mut wasm = wasmer::wat2wasm(MODULE.as_bytes()).unwrap().into_owned();
+            for (name, data) in debug_info(16) {
+                custom_section(&mut wasm, name, &data);
+            }
+            // A custom section claiming more bytes than are left.
+            wasm.extend([0, 100]);
+
+            let symbols = Symbols::parse(&wasm).unwrap();
+            assert!(symbols.dwarf.is_some());
+            assert_eq!(symbols.names[&0], "game::update");
+        }
+    }
+}
+
+#[cfg(not(feature = "symbols"))]
+mod imp {
+    use super::FrameSymbol;
+
+    /// Stand-in without the `symbols` feature, for which modules never
+    /// have symbols.
+    pub(crate) struct Symbols;
+
+    impl Symbols {
+        pub(crate) fn parse(_wasm: &[u8]) -> Option<Self> {
+            None
+        }
+
+        pub(crate) fn lookup(&self, _func_index: u32, _module_offset: usize) -> Vec<FrameSymbol> {
+            vec![]
+        }
+    }
+}
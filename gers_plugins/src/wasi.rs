@@ -0,0 +1,115 @@
+//! WASI support for plugins built for `wasm32-wasi`.
+//!
+//! Enabled per plugin with `wasi = true` in `plugin.toml`. Only the
+//! plugin's own directory is pre-opened, as the guest's working
+//! directory. Standard output and error are captured in pipes, which
+//! the host drains into its logger.
+use crate::{errors::PluginError, meta::PluginMeta, source::PluginSource};
+
+/// Output the guest wrote since it was last taken.
+#[derive(Debug, Default)]
+pub struct WasiOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub(crate) use imp::WasiContext;
+
+/// Set up WASI for the plugin, if its meta file asks for it.
+pub(crate) fn setup(
+    meta: &PluginMeta,
+    source: &PluginSource,
+) -> Result<Option<WasiContext>, PluginError> {
+    if meta.wasi {
+        WasiContext::new(meta, source).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "wasi")]
+mod imp {
+    use std::io::Read;
+    use wasmer::ImportObject;
+    use wasmer_wasi::{Pipe, WasiEnv, WasiState};
+
+    use super::WasiOutput;
+    use crate::{errors::PluginError, meta::PluginMeta, source::PluginSource};
+
+    pub(crate) struct WasiContext {
+        env: WasiEnv,
+    }
+
+    impl WasiContext {
+        pub(crate) fn new(meta: &PluginMeta, source: &PluginSource) -> Result<Self, PluginError> {
+            let mut builder = WasiState::new(&meta.name);
+            builder
+                .stdout(Box::new(Pipe::new()))
+                .stderr(Box::new(Pipe::new()));
+
+            // Files inside archives can't be pre-opened.
+            if let PluginSource::Directory(root) = source {
+                builder
+                    .map_dir(".", root)
+                    .map_err(|err| PluginError::Wasi(err.to_string()))?;
+            }
+
+            let env = builder
+                .finalize()
+                .map_err(|err| PluginError::Wasi(err.to_string()))?;
+
+            Ok(Self { env })
+        }
+
+        pub(crate) fn import_object(
+            &mut self,
+            module: &wasmer::Module,
+        ) -> Result<ImportObject, PluginError> {
+            self.env
+                .import_object(module)
+                .map_err(|err| PluginError::Wasi(err.to_string()))
+        }
+
+        pub(crate) fn take_output(&self) -> WasiOutput {
+            let mut output = WasiOutput::default();
+            let mut state = self.env.state();
+
+            if let Ok(Some(stdout)) = state.fs.stdout_mut() {
+                let _ = stdout.read_to_string(&mut output.stdout);
+            }
+            if let Ok(Some(stderr)) = state.fs.stderr_mut() {
+                let _ = stderr.read_to_string(&mut output.stderr);
+            }
+
+            output
+        }
+    }
+}
+
+#[cfg(not(feature = "wasi"))]
+mod imp {
+    use wasmer::ImportObject;
+
+    use super::WasiOutput;
+    use crate::{errors::PluginError, meta::PluginMeta, source::PluginSource};
+
+    /// Can't be constructed without the `wasi` feature.
+    pub(crate) enum WasiContext {}
+
+    impl WasiContext {
+        pub(crate) fn new(_meta: &PluginMeta, _source: &PluginSource) -> Result<Self, PluginError> {
+            Err(PluginError::WasiUnsupported)
+        }
+
+        pub(crate) fn import_object(
+            &mut self,
+            _module: &wasmer::Module,
+        ) -> Result<ImportObject, PluginError> {
+            match *self {}
+        }
+
+        pub(crate) fn take_output(&self) -> WasiOutput {
+            match *self {}
+        }
+    }
+}
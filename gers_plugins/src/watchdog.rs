@@ -0,0 +1,246 @@
+//! Interrupting guest calls that hang.
+//!
+//! Modules are compiled with a check of an exported global on entry to
+//! every function and loop, which traps once the global is set. While
+//! the host calls into a guest, a watchdog thread waits for the call's
+//! deadline, and sets the global of the plugin's instance when the call
+//! runs past it. The plugin is then faulted, and isn't called again,
+//! rather than freezing the app.
+use loupe::MemoryUsage;
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+use wasmer::{
+    wasmparser::{Operator, Type as WpType, TypeOrFuncType},
+    ExportIndex, FunctionMiddleware, Global, GlobalInit, GlobalType, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, RuntimeError, Type,
+    Value,
+};
+use wasmer_types::GlobalIndex;
+use wasmer_vm::ModuleInfo;
+
+/// Name of the global every module exports for interrupting it.
+pub(crate) const INTERRUPT_GLOBAL: &str = "gers:interrupt";
+
+/// Compiles the interrupt checks into modules.
+///
+/// Modules are compiled one at a time, so the global of the module
+/// that is being compiled is kept for its functions.
+#[derive(Debug, Default, MemoryUsage)]
+pub(crate) struct InterruptChecks {
+    global: Mutex<Option<GlobalIndex>>,
+}
+
+impl ModuleMiddleware for InterruptChecks {
+    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
+        let global = self
+            .global
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .expect("interrupt global is added before functions are compiled");
+
+        Box::new(FunctionChecks {
+            global,
+            entered: false,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let global = module_info
+            .globals
+            .push(GlobalType::new(Type::I32, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I32Const(0));
+        module_info
+            .exports
+            .insert(INTERRUPT_GLOBAL.to_string(), ExportIndex::Global(global));
+
+        *self.global.lock().unwrap_or_else(|err| err.into_inner()) = Some(global);
+    }
+}
+
+#[derive(Debug)]
+struct FunctionChecks {
+    global: GlobalIndex,
+    entered: bool,
+}
+
+impl FunctionChecks {
+    fn push_check(&self, state: &mut MiddlewareReaderState) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: self.global.as_u32(),
+            },
+            Operator::If {
+                ty: TypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+            Operator::Unreachable,
+            Operator::End,
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionChecks {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.entered {
+            self.entered = true;
+            self.push_check(state);
+        }
+
+        // Checked at the top of the loop body, on every iteration.
+        let is_loop = matches!(operator, Operator::Loop { .. });
+        state.push_operator(operator);
+        if is_loop {
+            self.push_check(state);
+        }
+
+        Ok(())
+    }
+}
+
+/// Guest call that ran past the watchdog timeout, and was interrupted.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest call interrupted after the watchdog timeout of {}ms",
+            self.0.as_millis()
+        )
+    }
+}
+
+/// Thread interrupting guest calls that run longer than the timeout.
+pub(crate) struct Watchdog {
+    timeout: Duration,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Calls being watched, innermost last, as guests can call into
+    /// the host which calls into guests again.
+    calls: Vec<WatchedCall>,
+    stopped: bool,
+}
+
+struct WatchedCall {
+    deadline: Instant,
+    interrupt: Global,
+    interrupted: bool,
+}
+
+impl Watchdog {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        let shared = Arc::new(Shared::default());
+        {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("gers-watchdog".to_string())
+                .spawn(move || shared.run())
+                .expect("failed to start watchdog thread");
+        }
+
+        Self { timeout, shared }
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Call into a guest, interrupting it through its `interrupt`
+    /// global if it runs past the timeout.
+    pub(crate) fn watch<T>(
+        &self,
+        interrupt: &Global,
+        call: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<Result<T, RuntimeError>, TimedOut> {
+        {
+            let mut state = self.shared.lock();
+            state.calls.push(WatchedCall {
+                deadline: Instant::now() + self.timeout,
+                interrupt: interrupt.clone(),
+                interrupted: false,
+            });
+        }
+        self.shared.changed.notify_one();
+
+        let result = call();
+
+        let interrupted = {
+            let mut state = self.shared.lock();
+            state.calls.pop().is_some_and(|call| call.interrupted)
+        };
+        if interrupted {
+            // The call may have returned just as it was interrupted.
+            let _ = interrupt.set(Value::I32(0));
+            if result.is_err() {
+                return Err(TimedOut(self.timeout));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn run(&self) {
+        let mut state = self.lock();
+
+        while !state.stopped {
+            let now = Instant::now();
+            let next = state
+                .calls
+                .iter_mut()
+                .filter(|call| !call.interrupted)
+                .min_by_key(|call| call.deadline);
+
+            state = match next {
+                Some(call) if call.deadline <= now => {
+                    // The guest traps on its next check.
+                    let _ = call.interrupt.set(Value::I32(1));
+                    call.interrupted = true;
+                    state
+                }
+                Some(call) => {
+                    let wait = call.deadline - now;
+                    self.changed
+                        .wait_timeout(state, wait)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|err| err.into_inner()),
+            };
+        }
+    }
+}
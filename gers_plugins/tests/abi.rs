@@ -0,0 +1,671 @@
+//! Conformance of the guest ABI, for plugins that aren't written in Rust.
+//!
+//! The host only relies on the exports below, called with WebAssembly
+//! numbers. Everything a guest hands the host is a pointer into its own
+//! memory, exported as `memory`, so guests built with AssemblyScript, C
+//! or Zig can implement the protocol as long as they export the same
+//! names and signatures:
+//!
+//! | Export                  | Signature                 |
+//! |-------------------------|---------------------------|
+//! | `__gers_update`         | `() -> ()` or `() -> i32` |
+//! | `__gers_resume`         | `(i32) -> i32`            |
+//! | `__gers_render`         | `(f32) -> ()`             |
+//! | `__gers_event_alloc`    | `(i32, i32) -> i32`       |
+//! | `__gers_event_update`   | `(i32, i32) -> i32`       |
+//! | `__gers_event_arena`    | `() -> i64`               |
+//! | `__gers_event_batch`    | `(i32) -> i32`            |
+//! | `__gers_event_manifest` | `() -> i64`               |
+//! | `__gers_event_layouts`  | `() -> i64`               |
+//! | `__gers_alloc`          | `(i32) -> i32`            |
+//! | `__gers_free`           | `(i32, i32) -> ()`        |
+//! | `__gers_save`           | `() -> ()`                |
+//! | `__gers_load`           | `(i32, i32) -> ()`        |
+//! | `__gers_shutdown`       | `(i32) -> ()`             |
+//! | `__gers_compact`        | `(i32) -> i32`            |
+//! | `__gers_bump_stats`     | `() -> i32`               |
+//! | `__gers_frame_globals`  | `() -> i32`               |
+//!
+//! All of them are optional, and other exports starting with `__gers_`
+//! are refused, so misspelled hooks don't go unnoticed. Events are
+//! written with the C layout of the structs in `gers_events`, at the
+//! address the guest gave, which must be aligned for the event. The
+//! host passes the alignment to `__gers_event_alloc` as its second
+//! parameter, which guests from before ABI version 3 leave out. The
+//! modules here lay out their memory the way other toolchains do,
+//! rather than the way Rust does.
+//!
+//! Values of the current frame are imported as the mutable globals
+//! `gers.delta_time: f32`, `gers.frame_index: i64` and
+//! `gers.tick_index: i64`, or written to the `FrameGlobals` at the
+//! address `__gers_frame_globals` returns. Since ABI version 4, event
+//! records in the arena carry the frame and tick index in their header,
+//! and since version 5, every event starts with an `EventHeader`.
+//!
+//! Guests can list the `EventLayout`s of the event types they handle,
+//! from `__gers_event_layouts`, and aren't sent events of types the
+//! host lays out differently.
+//!
+//! Events of `EncodedEvent` types are encoded with postcard instead, and
+//! only sent to guests of version 5 or later, with `EVENT_FLAG_ENCODED`
+//! set in the header and the encoded size.
+mod common;
+
+use std::time::Duration;
+
+use gers_events::{
+    encode_event, plugin_id, ActionEvent, DamageEvent, EncodedEvent, EventLayout, EventType,
+    FrameGlobals, HelloEvent, UpdateStatus, EVENT_BUFFER_ALIGN, EVENT_FLAG_ENCODED,
+    EVENT_FLAG_MUTABLE,
+};
+use gers_plugins::{strings::read_bytes, PluginError, Plugins, EVENT_BUFFER_SIZE};
+use serde::{Deserialize, Serialize};
+use wasmer::WasmPtr;
+
+use common::{global_i32, PluginDir};
+
+fn load(dir: &PluginDir) -> Plugins {
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    plugins
+}
+
+/// Exports with a fixed signature, as their parameters and result.
+const EXPORTS: &[(&str, &str, Option<&str>)] = &[
+    ("__gers_resume", "i32", Some("i32")),
+    ("__gers_render", "f32", None),
+    ("__gers_event_alloc", "i32 i32", Some("i32")),
+    ("__gers_event_alloc", "i32", Some("i32")),
+    ("__gers_event_update", "i32 i32", Some("i32")),
+    ("__gers_event_arena", "", Some("i64")),
+    ("__gers_event_batch", "i32", Some("i32")),
+    ("__gers_event_manifest", "", Some("i64")),
+    ("__gers_event_layouts", "", Some("i64")),
+    ("__gers_alloc", "i32", Some("i32")),
+    ("__gers_free", "i32 i32", None),
+    ("__gers_save", "", None),
+    ("__gers_load", "i32 i32", None),
+    ("__gers_shutdown", "i32", None),
+    ("__gers_compact", "i32", Some("i32")),
+    ("__gers_bump_stats", "", Some("i32")),
+    ("__gers_frame_globals", "", Some("i32")),
+];
+
+/// Module exporting one function, returning zero.
+fn export_module(name: &str, params: &str, result: Option<&str>) -> String {
+    let params = match params.trim() {
+        "" => String::new(),
+        params => format!("(param {})", params),
+    };
+    let (result, body) = match result {
+        Some(ty) => (format!("(result {})", ty), format!("({}.const 0)", ty)),
+        None => (String::new(), String::new()),
+    };
+    format!(
+        r#"(module
+        (memory (export "memory") 1)
+        (func (export "{}") {} {} {}))"#,
+        name, params, result, body
+    )
+}
+
+#[test]
+fn test_export_signatures() {
+    for (name, params, result) in EXPORTS {
+        let dir = PluginDir::new("signature", Some(&export_module(name, params, *result)));
+        let mut plugins = Plugins::new();
+        assert!(
+            plugins.load_plugin_dir(dir.path()).is_ok(),
+            "{} should load",
+            name
+        );
+        drop(dir);
+
+        // An extra parameter is enough to be refused, before the module
+        // is instantiated.
+        let wrong = format!("{} i64", params);
+        let dir = PluginDir::new("signature", Some(&export_module(name, &wrong, *result)));
+        let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+        assert!(
+            matches!(err.inner(), PluginError::InvalidHooks(report) if report.starts_with(name)),
+            "{} with ({}) should be refused",
+            name,
+            wrong
+        );
+    }
+}
+
+#[test]
+fn test_update_with_and_without_status() {
+    // C guests often return nothing.
+    let dir = PluginDir::new(
+        "update-void",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (func (export "__gers_update")))"#,
+        ),
+    );
+    let mut plugins = load(&dir);
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert_eq!(plugin.update().unwrap(), UpdateStatus::Done);
+
+    let dir = PluginDir::new(
+        "update-status",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (func (export "__gers_update") (result i32) (i32.const 1))
+        (func (export "__gers_resume") (param i32) (result i32) (i32.const 0)))"#,
+        ),
+    );
+    let mut plugins = load(&dir);
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert_eq!(plugin.update().unwrap(), UpdateStatus::Continue);
+    assert!(plugin.has_pending_work());
+    assert_eq!(
+        plugin.resume(Duration::from_millis(1)).unwrap(),
+        UpdateStatus::Done
+    );
+}
+
+/// C guest with a shadow stack below its heap, and a bump `malloc`
+/// returning 8 byte aligned blocks. Events are read field by field at
+/// the offsets of the C layout.
+const C_GUEST: &str = r#"(module
+    (memory (export "memory") 2)
+    (global $__stack_pointer (mut i32) (i32.const 65536))
+    (global $heap (mut i32) (i32.const 65544))
+    (global $requested (export "requested") (mut i32) (i32.const 0))
+    (global $event_type (export "event_type") (mut i32) (i32.const 0))
+    (global $data (export "data") (mut i32) (i32.const 0))
+    (global $div (export "div") (mut i32) (i32.const 0))
+    (func $malloc (param $size i32) (result i32)
+        (local $ptr i32)
+        (local.set $ptr (global.get $heap))
+        (global.set $heap
+            (i32.and
+                (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+                (i32.const -8)))
+        (local.get $ptr))
+    (func (export "__gers_event_alloc") (param $size i32) (result i32)
+        (global.set $requested (local.get $size))
+        (call $malloc (local.get $size)))
+    (func (export "__gers_event_update") (param $type i32) (param $ptr i32) (result i32)
+        (global.set $event_type (local.get $type))
+        (if (i32.eq (local.get $type) (i32.const 1))
+            (then
+                (global.set $data (i32.load offset=0 (local.get $ptr)))
+                (global.set $div (i32.load16_u offset=6 (local.get $ptr)))))
+        (if (i32.eq (local.get $type) (i32.const 7))
+            (then
+                ;; Halve the damage in place.
+                (f32.store offset=8 (local.get $ptr)
+                    (f32.mul (f32.load offset=8 (local.get $ptr)) (f32.const 0.5)))))
+        (i32.const 0)))"#;
+
+#[test]
+fn test_c_layout_events() {
+    let dir = PluginDir::new("c-guest", Some(C_GUEST));
+    let mut plugins = load(&dir);
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+
+    // The event buffer is requested once, when the plugin is initialised.
+    plugin.init().unwrap();
+    assert_eq!(plugin.data_ptr.map(|ptr| ptr.offset()), Some(65544));
+    assert_eq!(global_i32(&plugins, "requested"), EVENT_BUFFER_SIZE as i32);
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    let hello = HelloEvent {
+        data: 42,
+        padding: 0,
+        div: 3,
+    };
+    assert!(plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "event_type"), EventType::Hello as i32);
+    assert_eq!(global_i32(&plugins, "data"), 42);
+    assert_eq!(global_i32(&plugins, "div"), 3);
+
+    // Mutable events are read back from the same address.
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    let mut damage = DamageEvent {
+        entity: 1,
+        source: 0,
+        amount: 10.0,
+        cancelled: 0,
+    };
+    assert!(plugin
+        .dispatch_mutable_event(EventType::Damage as i32, &mut damage)
+        .unwrap());
+    assert_eq!(damage.amount, 5.0);
+    assert_eq!(damage.entity, 1);
+}
+
+#[test]
+fn test_event_buffer_alignment_is_requested() {
+    let dir = PluginDir::new(
+        "aligned-guest",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (global $align (export "align") (mut i32) (i32.const 0))
+        (func (export "__gers_event_alloc") (param $size i32) (param $align i32) (result i32)
+            (global.set $align (local.get $align))
+            (i32.const 1024)))"#,
+        ),
+    );
+    let mut plugins = load(&dir);
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+
+    assert_eq!(global_i32(&plugins, "align"), EVENT_BUFFER_ALIGN as i32);
+}
+
+/// AssemblyScript guest, whose allocator returns the payload of a
+/// managed object, with the object header in the 16 bytes before it.
+const ASSEMBLYSCRIPT_GUEST: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $calls (export "calls") (mut i32) (i32.const 0))
+    (global $freed (export "freed") (mut i32) (i32.const 0))
+    (func $__new (param $size i32) (result i32)
+        ;; Header with the runtime id and size, like `__new` writes.
+        (i32.store offset=0 (i32.const 1024) (i32.const 0xA5A5A5A5))
+        (i32.store offset=12 (i32.const 1024) (local.get $size))
+        (i32.const 1040))
+    (func (export "__gers_event_alloc") (param i32) (result i32)
+        (call $__new (local.get 0)))
+    (func (export "__gers_alloc") (param i32) (result i32)
+        (call $__new (local.get 0)))
+    (func (export "__gers_free") (param $ptr i32) (param $size i32)
+        (global.set $freed (local.get $size)))
+    (func (export "__gers_event_update") (param i32 i32) (result i32)
+        (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+        (i32.const 0)))"#;
+
+#[test]
+fn test_host_leaves_object_headers() {
+    let dir = PluginDir::new("assemblyscript-guest", Some(ASSEMBLYSCRIPT_GUEST));
+    let mut plugins = load(&dir);
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+
+    let action = ActionEvent {
+        action_id: 7,
+        pressed: true,
+        value: 1.0,
+    };
+    assert!(plugin
+        .dispatch_event(EventType::Action as i32, &action)
+        .unwrap());
+
+    // Strings are written at the payload too, and freed with the
+    // length they were sent with.
+    let (ptr, len) = plugin.send_string("hello").unwrap();
+    assert_eq!(ptr.offset(), 1040);
+    plugin.free_string(ptr, len).unwrap();
+
+    let memory = plugin.memory().unwrap();
+    let header = read_bytes(memory, WasmPtr::new(1024), 16).unwrap();
+    assert_eq!(header[..4], 0xA5A5A5A5u32.to_le_bytes());
+    assert_eq!(header[12..], 5u32.to_le_bytes());
+    let payload = read_bytes(memory, ptr, len).unwrap();
+    assert_eq!(payload, b"hello");
+    assert_eq!(global_i32(&plugins, "calls"), 1);
+    assert_eq!(global_i32(&plugins, "freed"), 5);
+}
+
+#[test]
+fn test_misaligned_event_buffer_is_refused() {
+    // Byte allocators, like a packed C arena, may hand out any address.
+    let dir = PluginDir::new(
+        "misaligned-guest",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (global $calls (export "calls") (mut i32) (i32.const 0))
+        (func (export "__gers_event_alloc") (param i32) (result i32) (i32.const 1025))
+        (func (export "__gers_event_update") (param i32 i32) (result i32)
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i32.const 0)))"#,
+        ),
+    );
+    let mut plugins = load(&dir);
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+
+    let hello = HelloEvent {
+        data: 1,
+        padding: 0,
+        div: 1,
+    };
+    // The guest isn't called with an event it can't read.
+    assert!(!plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "calls"), 0);
+}
+
+/// Zig guest with its arena in a global array at an odd address. The
+/// batch hook reads the first record, which starts at the next address
+/// aligned to `EVENT_RECORD_ALIGN`.
+const ZIG_GUEST: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $count (export "count") (mut i32) (i32.const 0))
+    (global $event_type (export "event_type") (mut i32) (i32.const 0))
+    (global $size (export "size") (mut i32) (i32.const 0))
+    (global $action_id (export "action_id") (mut i32) (i32.const 0))
+    (func (export "__gers_event_arena") (result i64)
+        (i64.or (i64.const 2051) (i64.shl (i64.const 256) (i64.const 32))))
+    (func (export "__gers_event_batch") (param $count i32) (result i32)
+        (global.set $count (local.get $count))
+        (global.set $event_type (i32.load offset=0 (i32.const 2056)))
+        (global.set $size (i32.load offset=4 (i32.const 2056)))
+        (global.set $action_id (i32.load offset=8 (i32.const 2056)))
+        (i32.const 0)))"#;
+
+#[test]
+fn test_event_records_are_aligned_by_address() {
+    let dir = PluginDir::new("zig-guest", Some(ZIG_GUEST));
+    let plugins = load(&dir);
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(plugin.has_event_arena());
+
+    let action = ActionEvent {
+        action_id: 9,
+        pressed: false,
+        value: 0.0,
+    };
+    assert!(plugin
+        .queue_event(EventType::Action as i32, &action)
+        .unwrap());
+    assert!(plugin
+        .queue_event(EventType::Action as i32, &action)
+        .unwrap());
+    assert_eq!(plugin.flush_events().unwrap(), 2);
+
+    assert_eq!(global_i32(&plugins, "count"), 2);
+    assert_eq!(global_i32(&plugins, "event_type"), EventType::Action as i32);
+    assert_eq!(
+        global_i32(&plugins, "size"),
+        std::mem::size_of::<ActionEvent>() as i32
+    );
+    assert_eq!(global_i32(&plugins, "action_id"), 9);
+}
+
+#[test]
+fn test_frame_globals() {
+    let dir = PluginDir::new(
+        "globals-guest",
+        Some(
+            r#"(module
+        (import "gers" "delta_time" (global $delta_time (mut f32)))
+        (import "gers" "frame_index" (global $frame_index (mut i64)))
+        (import "gers" "tick_index" (global $tick_index (mut i64)))
+        (memory (export "memory") 1)
+        (func (export "delta_time") (result f32) (global.get $delta_time))
+        (func (export "frame_index") (result i64) (global.get $frame_index))
+        (func (export "tick_index") (result i64) (global.get $tick_index))
+        (func (export "__gers_frame_globals") (result i32) (i32.const 2048)))"#,
+        ),
+    );
+    let plugins = load(&dir);
+    plugins.set_frame_globals(&FrameGlobals {
+        frame_index: 1 << 40,
+        tick_index: 3,
+        delta_time: 0.25,
+    });
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let exports = &plugin.instance().unwrap().exports;
+    let delta_time = exports.get_function("delta_time").unwrap();
+    assert_eq!(delta_time.call(&[]).unwrap()[0].unwrap_f32(), 0.25);
+    let frame_index = exports.get_function("frame_index").unwrap();
+    assert_eq!(frame_index.call(&[]).unwrap()[0].unwrap_i64(), 1 << 40);
+    let tick_index = exports.get_function("tick_index").unwrap();
+    assert_eq!(tick_index.call(&[]).unwrap()[0].unwrap_i64(), 3);
+
+    // Guests that can't import globals read the same values from memory.
+    let memory = plugin.memory().unwrap();
+    let frame = read_bytes(memory, WasmPtr::new(2048), 20).unwrap();
+    assert_eq!(frame[..8], (1u64 << 40).to_le_bytes());
+    assert_eq!(frame[8..16], 3u64.to_le_bytes());
+    assert_eq!(frame[16..], 0.25f32.to_le_bytes());
+}
+
+#[test]
+fn test_event_records_are_stamped() {
+    // Reads the frame and tick index after the type and size.
+    let dir = PluginDir::new(
+        "stamped-guest",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (global $frame (export "frame") (mut i32) (i32.const 0))
+        (global $tick (export "tick") (mut i32) (i32.const 0))
+        (global $action_id (export "action_id") (mut i32) (i32.const 0))
+        (func (export "__gers_abi_version") (result i32) (i32.const 4))
+        (func (export "__gers_event_arena") (result i64)
+            (i64.or (i64.const 2048) (i64.shl (i64.const 256) (i64.const 32))))
+        (func (export "__gers_event_batch") (param i32) (result i32)
+            (global.set $frame (i32.wrap_i64 (i64.load offset=8 (i32.const 2048))))
+            (global.set $tick (i32.wrap_i64 (i64.load offset=16 (i32.const 2048))))
+            (global.set $action_id (i32.load offset=24 (i32.const 2048)))
+            (i32.const 0)))"#,
+        ),
+    );
+    let plugins = load(&dir);
+    plugins.set_frame_globals(&FrameGlobals {
+        frame_index: 6,
+        tick_index: 11,
+        delta_time: 0.0,
+    });
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let action = ActionEvent {
+        action_id: 9,
+        pressed: true,
+        value: 1.0,
+    };
+    assert!(plugin
+        .queue_event(EventType::Action as i32, &action)
+        .unwrap());
+    assert_eq!(plugin.flush_events().unwrap(), 1);
+
+    assert_eq!(global_i32(&plugins, "frame"), 6);
+    assert_eq!(global_i32(&plugins, "tick"), 11);
+    assert_eq!(global_i32(&plugins, "action_id"), 9);
+}
+
+#[test]
+fn test_events_have_headers() {
+    // Reads the header before the event data, and halves the damage.
+    let dir = PluginDir::new(
+        "headed-guest",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (global $size (export "size") (mut i32) (i32.const 0))
+        (global $tick (export "tick") (mut i32) (i32.const 0))
+        (global $flags (export "flags") (mut i32) (i32.const 0))
+        (global $source (export "source") (mut i32) (i32.const 0))
+        (func (export "__gers_abi_version") (result i32) (i32.const 5))
+        (func (export "__gers_event_alloc") (param i32 i32) (result i32) (i32.const 1024))
+        (func (export "__gers_event_update") (param $type i32) (param $ptr i32) (result i32)
+            (global.set $size (i32.load offset=4 (local.get $ptr)))
+            (global.set $tick (i32.wrap_i64 (i64.load offset=16 (local.get $ptr))))
+            (global.set $flags (i32.load offset=24 (local.get $ptr)))
+            (global.set $source (i32.load offset=28 (local.get $ptr)))
+            (f32.store offset=40 (local.get $ptr)
+                (f32.mul (f32.load offset=40 (local.get $ptr)) (f32.const 0.5)))
+            (i32.const 0)))"#,
+        ),
+    );
+    let mut plugins = load(&dir);
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+    plugins.set_frame_globals(&FrameGlobals {
+        frame_index: 1,
+        tick_index: 2,
+        delta_time: 0.0,
+    });
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let hello = HelloEvent {
+        data: 1,
+        padding: 0,
+        div: 1,
+    };
+    assert!(plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(
+        global_i32(&plugins, "size"),
+        std::mem::size_of::<HelloEvent>() as i32
+    );
+    assert_eq!(global_i32(&plugins, "tick"), 2);
+    assert_eq!(global_i32(&plugins, "flags"), 0);
+    assert_eq!(global_i32(&plugins, "source"), 0);
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let mut damage = DamageEvent {
+        entity: 1,
+        source: 0,
+        amount: 10.0,
+        cancelled: 0,
+    };
+    let source = plugin_id("emitter");
+    assert!(plugin
+        .dispatch_mutable_event_from(source, EventType::Damage as i32, &mut damage)
+        .unwrap());
+    assert_eq!(damage.amount, 5.0);
+    assert_eq!(global_i32(&plugins, "flags"), EVENT_FLAG_MUTABLE as i32);
+    assert_eq!(global_i32(&plugins, "source") as u32, source);
+}
+
+#[test]
+fn test_mismatched_layouts_are_refused() {
+    // The guest's `DamageEvent` was built from a different version.
+    let damage = EventLayout::of::<DamageEvent>();
+    let layouts = [
+        EventLayout::of::<HelloEvent>(),
+        EventLayout {
+            hash: damage.hash ^ 1,
+            ..damage
+        },
+    ];
+    let mut data = String::new();
+    for layout in layouts.iter() {
+        let mut bytes = layout.event_type.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&layout.padding.to_le_bytes());
+        bytes.extend_from_slice(&layout.hash.to_le_bytes());
+        for byte in bytes {
+            data.push_str(&format!("\\{:02x}", byte));
+        }
+    }
+
+    let dir = PluginDir::new(
+        "layouts-guest",
+        Some(&format!(
+            r#"(module
+        (memory (export "memory") 1)
+        (data (i32.const 64) "{}")
+        (global $received (export "received") (mut i32) (i32.const 0))
+        (func (export "__gers_event_layouts") (result i64)
+            (i64.or (i64.const 64) (i64.shl (i64.const 2) (i64.const 32))))
+        (func (export "__gers_event_alloc") (param i32 i32) (result i32) (i32.const 1024))
+        (func (export "__gers_event_update") (param i32 i32) (result i32)
+            (global.set $received (i32.add (global.get $received) (i32.const 1)))
+            (i32.const 0)))"#,
+            data
+        )),
+    );
+    let mut plugins = load(&dir);
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert_eq!(plugin.mismatched_layouts(), [EventType::Damage]);
+
+    let hello = HelloEvent {
+        data: 1,
+        padding: 0,
+        div: 1,
+    };
+    assert!(plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    let mut damage = DamageEvent {
+        entity: 1,
+        source: 0,
+        amount: 10.0,
+        cancelled: 0,
+    };
+    assert!(!plugin
+        .dispatch_mutable_event(EventType::Damage as i32, &mut damage)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "received"), 1);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    sender: String,
+    text: String,
+}
+
+impl EncodedEvent for ChatMessage {
+    const TYPE: EventType = EventType::Custom;
+}
+
+/// Module of ABI `version`, keeping the header's size and flags, and
+/// copying the event data to offset 2048.
+fn encoded_guest(version: u32) -> String {
+    format!(
+        r#"(module
+        (memory (export "memory") 1)
+        (global $size (export "size") (mut i32) (i32.const 0))
+        (global $flags (export "flags") (mut i32) (i32.const 0))
+        (func (export "__gers_abi_version") (result i32) (i32.const {}))
+        (func (export "__gers_event_alloc") (param i32 i32) (result i32) (i32.const 1024))
+        (func (export "__gers_event_update") (param $type i32) (param $ptr i32) (result i32)
+            (global.set $size (i32.load offset=4 (local.get $ptr)))
+            (global.set $flags (i32.load offset=24 (local.get $ptr)))
+            (memory.copy (i32.const 2048) (i32.add (local.get $ptr) (i32.const 32))
+                (global.get $size))
+            (i32.const 0)))"#,
+        version
+    )
+}
+
+#[test]
+fn test_encoded_events() {
+    let message = ChatMessage {
+        sender: "host".to_string(),
+        text: "strings don't fit into a C struct".to_string(),
+    };
+    let encoded = encode_event(&message).unwrap();
+
+    let dir = PluginDir::new("encoded-guest", Some(&encoded_guest(5)));
+    let mut plugins = load(&dir);
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(plugin.dispatch_encoded_event(&message).unwrap());
+    assert_eq!(global_i32(&plugins, "size"), encoded.len() as i32);
+    assert_eq!(global_i32(&plugins, "flags"), EVENT_FLAG_ENCODED as i32);
+    let memory = plugin
+        .instance()
+        .unwrap()
+        .exports
+        .get_memory("memory")
+        .unwrap();
+    assert_eq!(
+        read_bytes(memory, WasmPtr::new(2048), encoded.len() as u32).unwrap(),
+        encoded
+    );
+
+    // Older guests would read the data as a struct.
+    let dir = PluginDir::new("encoded-legacy-guest", Some(&encoded_guest(4)));
+    let mut plugins = load(&dir);
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(!plugin.dispatch_encoded_event(&message).unwrap());
+    assert_eq!(global_i32(&plugins, "flags"), 0);
+}
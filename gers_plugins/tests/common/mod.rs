@@ -0,0 +1,50 @@
+//! Helpers shared by the integration tests.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use gers_plugins::Plugins;
+use wasmer::{wat2wasm, Val};
+
+/// Plugin directory in the system's temporary directory, removed on drop.
+pub struct PluginDir(PathBuf);
+
+impl PluginDir {
+    /// Write a plugin named `name`, with its module built from `wat`.
+    pub fn new(name: &str, wat: Option<&str>) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("gers-plugins-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("plugin.toml"),
+            format!("name = {:?}\nversion = \"1.0.0\"\n", name),
+        )
+        .unwrap();
+        if let Some(wat) = wat {
+            fs::write(dir.join("main.wasm"), wat2wasm(wat.as_bytes()).unwrap()).unwrap();
+        }
+
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for PluginDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Value of the exported `i32` global of the first plugin.
+pub fn global_i32(plugins: &Plugins, name: &str) -> i32 {
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let global = plugin.instance().unwrap().exports.get_global(name).unwrap();
+    match global.get() {
+        Val::I32(value) => value,
+        other => panic!("unexpected global value {:?}", other),
+    }
+}
@@ -0,0 +1,1582 @@
+//! Loading plugins from directories, with guest modules built from
+//! WebAssembly text at test time.
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use gers_events::{
+    Capabilities, EventType, HelloEvent, HostError, ABI_VERSION, LEGACY_ABI_VERSION,
+};
+use gers_plugins::{
+    strings, Conflict, EnabledList, GuestCall, MissingImports, Permission, PluginCallInterceptor,
+    PluginError, PluginSource, PluginState, Plugins, Resource, SettingValue, Settings, TrustPolicy,
+    EVENT_BUFFER_SIZE, WASM_PAGE_SIZE,
+};
+use wasmer::{wat2wasm, Exports, Function, RuntimeError, Val, WasmPtr};
+
+use common::{global_i32, PluginDir};
+
+/// Reclaims two pages on the first compaction step, then nothing.
+const COMPACTING: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $left (mut i32) (i32.const 2))
+    (func (export "grow") (param i32) (drop (memory.grow (local.get 0))))
+    (func (export "__gers_compact") (param i32) (result i32)
+        (local $pages i32)
+        (local.set $pages (global.get $left))
+        (global.set $left (i32.const 0))
+        (local.get $pages)))"#;
+
+#[test]
+fn test_memory_pressure_and_compaction() {
+    let dir = PluginDir::new("compacting", Some(COMPACTING));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    let grow = plugin
+        .instance()
+        .unwrap()
+        .exports
+        .get_function("grow")
+        .unwrap()
+        .clone();
+
+    // Nothing is requested until there's work.
+    assert_eq!(plugin.compact(Duration::from_millis(1)).unwrap(), 0);
+    assert_eq!(plugin.compaction().steps(), 0);
+
+    // The first check only measures the memory.
+    assert!(!plugin.check_memory_pressure(4));
+    grow.call(&[Val::I32(3)]).unwrap();
+    assert!(!plugin.check_memory_pressure(4));
+    grow.call(&[Val::I32(1)]).unwrap();
+    assert!(plugin.check_memory_pressure(4));
+    assert!(plugin.compaction().is_pending());
+    // Not signalled again while compacting.
+    grow.call(&[Val::I32(4)]).unwrap();
+    assert!(!plugin.check_memory_pressure(4));
+
+    assert_eq!(plugin.compact(Duration::from_millis(1)).unwrap(), 2);
+    assert!(plugin.compaction().is_pending());
+    assert_eq!(plugin.compact(Duration::from_millis(1)).unwrap(), 0);
+    assert!(!plugin.compaction().is_pending());
+    assert_eq!(plugin.compaction().steps(), 2);
+    assert_eq!(plugin.compaction().reclaimed_pages(), 2);
+    assert_eq!(plugin.compaction().reclaimed_bytes(), 2 * WASM_PAGE_SIZE);
+
+    // Growth is measured from where pressure was last signalled, so
+    // the growth while compacting counts.
+    assert!(plugin.check_memory_pressure(4));
+}
+
+#[test]
+fn test_memory_pressure_needs_compaction_hook() {
+    let dir = PluginDir::new(
+        "not-compacting",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (func (export "grow") (param i32) (drop (memory.grow (local.get 0)))))"#,
+        ),
+    );
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    let grow = plugin
+        .instance()
+        .unwrap()
+        .exports
+        .get_function("grow")
+        .unwrap()
+        .clone();
+
+    assert!(!plugin.check_memory_pressure(1));
+    grow.call(&[Val::I32(8)]).unwrap();
+    assert!(!plugin.check_memory_pressure(1));
+    assert!(!plugin.compaction().is_pending());
+}
+
+/// Records the calls into guests, and fails the ones to `fail`.
+#[derive(Clone, Default)]
+struct CallLog {
+    calls: Arc<Mutex<Vec<String>>>,
+    fail: Option<&'static str>,
+}
+
+impl PluginCallInterceptor for CallLog {
+    fn before_call(&self, call: &GuestCall) -> Result<(), RuntimeError> {
+        if self.fail == Some(call.function) {
+            return Err(RuntimeError::new("injected fault"));
+        }
+        Ok(())
+    }
+
+    fn after_call(&self, call: &GuestCall, _duration: Duration, error: Option<&RuntimeError>) {
+        let outcome = if error.is_some() { "failed" } else { "ok" };
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{} {} {}", call.plugin, call.function, outcome));
+    }
+}
+
+#[test]
+fn test_intercept_guest_calls() {
+    let dir = PluginDir::new(
+        "intercepted",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (func (export "__gers_update"))
+        (func (export "__gers_render") (param f32)))"#,
+        ),
+    );
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    // Interceptors apply to plugins that are already loaded.
+    let log = CallLog::default();
+    plugins.add_interceptor(log.clone());
+    let injector = CallLog {
+        fail: Some("__gers_render"),
+        ..CallLog::default()
+    };
+    plugins.add_interceptor(injector.clone());
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.update().unwrap();
+    // The fault is injected before the guest is called.
+    assert!(plugin.render(0.5).is_err());
+
+    assert_eq!(
+        *log.calls.lock().unwrap(),
+        [
+            "intercepted __gers_update ok",
+            "intercepted __gers_render failed"
+        ]
+    );
+    // Interceptors after the failing one aren't called.
+    assert_eq!(
+        *injector.calls.lock().unwrap(),
+        ["intercepted __gers_update ok"]
+    );
+}
+
+#[test]
+fn test_load_data_only_plugin() {
+    let dir = PluginDir::new("data-only", None);
+
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert!(plugin.is_data_only());
+    assert!(plugin.instance().is_none());
+    assert_eq!(plugin.root(), dir.path());
+    assert!(plugin.memory().is_err());
+
+    // Hooks are skipped, rather than failing.
+    assert!(plugin.update_fn().is_none());
+    assert!(plugin.event_alloc_fn().is_none());
+    assert!(!plugin.check_memory_pressure(0));
+}
+
+#[test]
+fn test_load_errors_name_the_plugin() {
+    let dir = PluginDir::new("no-meta", None);
+    fs::remove_file(dir.path().join("plugin.toml")).unwrap();
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::MissingMetaFile(_)));
+    assert_eq!(err.plugin_name(), None);
+    assert_eq!(err.plugin_path(), Some(dir.path()));
+    assert!(err.to_string().contains(&dir.path().display().to_string()));
+
+    // Plugins whose meta file describes a module can't be data-only.
+    let dir = PluginDir::new("no-module", None);
+    fs::write(
+        dir.path().join("plugin.toml"),
+        "name = \"no-module\"\nversion = \"1.0.0\"\nwasi = true\n",
+    )
+    .unwrap();
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::MissingWasmModule(_)));
+    assert_eq!(err.plugin_name(), Some("no-module"));
+    assert!(err.to_string().starts_with("plugin no-module ("), "{}", err);
+}
+
+/// Guest built for ABI version `version`, keeping what it's told in
+/// `__gers_init` in globals.
+fn handshaking(version: u32) -> String {
+    format!(
+        r#"(module
+        (global $host (export "host") (mut i32) (i32.const 0))
+        (global $capabilities (export "capabilities") (mut i32) (i32.const 0))
+        (func (export "__gers_abi_version") (result i32) (i32.const {}))
+        (func (export "__gers_init") (param i32 i32)
+            (global.set $host (local.get 0))
+            (global.set $capabilities (local.get 1))))"#,
+        version
+    )
+}
+
+#[test]
+fn test_abi_handshake() {
+    let dir = PluginDir::new("handshake", Some(&handshaking(ABI_VERSION)));
+    let mut plugins = Plugins::new();
+    plugins.set_capabilities(Capabilities::DRAW | Capabilities::HTTP);
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert_eq!(plugin.abi_version(), ABI_VERSION);
+    assert!(!plugin.is_compatibility_mode());
+    // The plugin wasn't granted the http permission.
+    assert_eq!(plugin.capabilities(), Capabilities::DRAW);
+
+    plugin.init().unwrap();
+    assert_eq!(global_i32(&plugins, "host"), ABI_VERSION as i32);
+    assert_eq!(
+        global_i32(&plugins, "capabilities"),
+        Capabilities::DRAW.bits() as i32
+    );
+
+    // Guests without a version predate the handshake.
+    let dir = PluginDir::new("legacy", Some("(module)"));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert_eq!(plugin.abi_version(), LEGACY_ABI_VERSION);
+    assert!(plugin.is_compatibility_mode());
+    plugin.init().unwrap();
+
+    let dir = PluginDir::new("future", Some(&handshaking(ABI_VERSION + 1)));
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(
+        matches!(err.inner(), PluginError::UnsupportedAbi(version) if *version == ABI_VERSION + 1)
+    );
+}
+
+/// Guest taking its configuration in `__gers_init`, keeping where it
+/// was and how many buffers were freed in globals.
+const CONFIGURED: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $config_ptr (export "config_ptr") (mut i32) (i32.const 0))
+    (global $config_len (export "config_len") (mut i32) (i32.const 0))
+    (global $freed (export "freed") (mut i32) (i32.const 0))
+    (func (export "__gers_abi_version") (result i32) (i32.const 6))
+    (func (export "__gers_alloc") (param i32) (result i32) (i32.const 1024))
+    (func (export "__gers_free") (param i32 i32)
+        (global.set $freed (i32.add (global.get $freed) (i32.const 1))))
+    (func (export "__gers_init") (param i32 i32 i32 i32)
+        (global.set $config_ptr (local.get 2))
+        (global.set $config_len (local.get 3))))"#;
+
+#[test]
+fn test_init_config() {
+    let dir = plugin_with_meta(
+        "configured",
+        "[config]\nspawn_rate = 2.5\nboss = \"dragon\"\n\
+         [settings]\nspawn_rate = { type = \"float\", default = 1.0 }\n",
+        CONFIGURED,
+    );
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert_eq!(plugin.config(), "boss = \"dragon\"\nspawn_rate = 2.5\n");
+
+    // The settings override the keys of the same name.
+    let mut settings = Settings::default();
+    let decl = &plugin.meta().settings["spawn_rate"];
+    settings
+        .set("configured", "spawn_rate", decl, SettingValue::Float(4.0))
+        .unwrap();
+    plugin.apply_settings(&settings).unwrap();
+    plugin.init().unwrap();
+
+    let expected = "boss = \"dragon\"\nspawn_rate = 4.0\n";
+    assert_eq!(global_i32(&plugins, "config_ptr"), 1024);
+    assert_eq!(global_i32(&plugins, "config_len"), expected.len() as i32);
+    assert_eq!(global_i32(&plugins, "freed"), 1);
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let memory = plugin.memory().unwrap();
+    let config = strings::read_str(memory, WasmPtr::new(1024), expected.len() as u32);
+    assert_eq!(config.unwrap(), expected);
+
+    // Empty configurations aren't allocated.
+    let dir = PluginDir::new("unconfigured", Some(CONFIGURED));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+    assert_eq!(global_i32(&plugins, "config_ptr"), 0);
+    assert_eq!(global_i32(&plugins, "freed"), 0);
+}
+
+#[test]
+fn test_plugin_instances() {
+    let dir = plugin_with_meta(
+        "spawner",
+        "[config]\nspawn_rate = 1.0\n\
+         [[instance]]\nname = \"wolf\"\nconfig = { creature = \"wolf\", spawn_rate = 0.5 }\n\
+         [[instance]]\nname = \"bat\"\nconfig = { creature = \"bat\" }\n",
+        CONFIGURED,
+    );
+    let mut plugins = Plugins::new();
+    let report = plugins.load_all([PluginSource::Directory(dir.path().to_path_buf())]);
+    assert!(report.errors.is_empty());
+
+    // Each instance has its own module instance and configuration.
+    let names: Vec<&str> = plugins
+        .iter_plugins()
+        .map(|plugin| plugin.meta().name.as_str())
+        .collect();
+    assert_eq!(names, ["spawner#wolf", "spawner#bat"]);
+    let configs: Vec<&str> = plugins
+        .iter_plugins()
+        .map(|plugin| plugin.config())
+        .collect();
+    assert_eq!(
+        configs,
+        [
+            "creature = \"wolf\"\nspawn_rate = 0.5\n",
+            "creature = \"bat\"\nspawn_rate = 1.0\n"
+        ]
+    );
+    for plugin in plugins.iter_plugins_mut() {
+        assert_eq!(plugin.meta().plugin_name(), "spawner");
+        plugin.init().unwrap();
+    }
+    let memories: Vec<_> = plugins
+        .iter_plugins()
+        .map(|plugin| plugin.memory().unwrap().data_ptr())
+        .collect();
+    assert_ne!(memories[0], memories[1]);
+
+    // Instances are enabled and disabled with their plugin.
+    assert_eq!(
+        plugins.enabled_list().iter().collect::<Vec<_>>(),
+        [("spawner", true)]
+    );
+    let mut plugins = Plugins::new();
+    plugins.disable("spawner#bat");
+    plugins.load_plugin_dir(dir.path()).unwrap_err();
+    assert_eq!(plugins.iter_plugins().count(), 0);
+    let mut plugins = Plugins::new();
+    plugins.disable("spawner");
+    let report = plugins.load_all([PluginSource::Directory(dir.path().to_path_buf())]);
+    assert_eq!(report.disabled_count(), 2);
+}
+
+#[test]
+fn test_stub_missing_imports() {
+    let wat = r#"(module
+        (import "gers_missing" "query" (func $query (result i32)))
+        (func (export "query") (result i32) (call $query)))"#;
+    let dir = PluginDir::new("stubbed", Some(wat));
+
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Instantiate(_)));
+
+    let mut plugins = Plugins::new();
+    plugins.set_missing_imports(MissingImports::Trap);
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(plugin.is_degraded());
+    assert_eq!(plugin.stubbed_imports(), ["gers_missing.query"]);
+    let query = plugin.instance().unwrap().exports.get_function("query");
+    let err = query.unwrap().call(&[]).unwrap_err();
+    assert!(err.message().contains("gers_missing.query"));
+
+    let mut plugins = Plugins::new();
+    plugins.set_missing_imports(MissingImports::Error);
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let query = plugin.instance().unwrap().exports.get_function("query");
+    assert_eq!(
+        query.unwrap().call(&[]).unwrap().to_vec(),
+        vec![Val::I32(HostError::Unsupported.code())]
+    );
+
+    // Imports outside the host's namespaces are never stubbed.
+    let dir = PluginDir::new(
+        "unstubbed",
+        Some(r#"(module (import "env" "query" (func (result i32))))"#),
+    );
+    let mut plugins = Plugins::new();
+    plugins.set_missing_imports(MissingImports::Trap);
+    assert!(plugins.load_plugin_dir(dir.path()).is_err());
+}
+
+#[test]
+fn test_load_plugin_at_runtime() {
+    let dir = PluginDir::new(
+        "runtime",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (global $size (export "size") (mut i32) (i32.const 0))
+        (func (export "__gers_event_alloc") (param i32) (result i32)
+            (global.set $size (local.get 0))
+            (i32.const 64)))"#,
+        ),
+    );
+    let faulty = PluginDir::new(
+        "runtime-faulty",
+        Some(
+            r#"(module
+        (memory (export "memory") 1)
+        (func (export "__gers_event_alloc") (param i32) (result i32) unreachable))"#,
+        ),
+    );
+    let mut plugins = Plugins::new();
+
+    // The init hooks have run by the time the plugin is returned.
+    let plugin = plugins.load_plugin_dir_at_runtime(dir.path()).unwrap();
+    assert_eq!(plugin.data_ptr.map(|ptr| ptr.offset()), Some(64));
+    assert_eq!(global_i32(&plugins, "size"), EVENT_BUFFER_SIZE as i32);
+
+    // Initialising again keeps the buffer.
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+    assert_eq!(plugin.data_ptr.map(|ptr| ptr.offset()), Some(64));
+
+    let err = plugins
+        .load_plugin_dir_at_runtime(faulty.path())
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Initialize(_)));
+    assert_eq!(plugins.iter_plugins().count(), 1);
+}
+
+/// Guest keeping the type and first field of the last event it was
+/// sent, doubled by the host, in globals.
+const ECHO: &str = r#"(module
+    (import "test_host" "double" (func $double (param i32) (result i32)))
+    (import "gers" "memory_grown" (func $memory_grown (param i32)))
+    (memory (export "memory") 1)
+    (global $event_type (export "event_type") (mut i32) (i32.const 0))
+    (global $value (export "value") (mut i32) (i32.const 0))
+    (func (export "__gers_event_alloc") (param i32) (result i32) (i32.const 256))
+    (func (export "__gers_event_update") (param $type i32) (param $ptr i32) (result i32)
+        (if (i32.eq (local.get $type) (i32.const 99)) (then unreachable))
+        (global.set $event_type (local.get $type))
+        (global.set $value (call $double (i32.load (local.get $ptr))))
+        (i32.const 0)))"#;
+
+/// Plugins providing the `test_host` imports.
+fn test_host() -> Plugins {
+    let mut plugins = Plugins::new();
+    plugins
+        .imports_mut()
+        .register("test_host", |store, _| {
+            let mut exports = Exports::new();
+            exports.insert(
+                "double",
+                Function::new_native(store, |value: i32| value * 2),
+            );
+            exports
+        })
+        .unwrap();
+    plugins
+}
+
+/// Guest with its event buffer at `ptr`, which counts its events.
+fn event_buffer_at(ptr: u32) -> String {
+    format!(
+        r#"(module
+        (memory (export "memory") 1)
+        (global $calls (export "calls") (mut i32) (i32.const 0))
+        (func (export "__gers_event_alloc") (param i32) (result i32) (i32.const {}))
+        (func (export "__gers_event_update") (param i32 i32) (result i32)
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i32.const 0)))"#,
+        ptr
+    )
+}
+
+#[test]
+fn test_event_round_trip() {
+    let dir = PluginDir::new("echo", Some(ECHO));
+    let mut plugins = test_host();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    // Events can't be sent before the buffer is allocated.
+    let hello = HelloEvent {
+        data: 21,
+        padding: 0,
+        div: 1,
+    };
+    assert!(!plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+
+    plugin.init().unwrap();
+    assert_eq!(plugin.data_ptr.map(|ptr| ptr.offset()), Some(256));
+    assert!(plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "event_type"), EventType::Hello as i32);
+    assert_eq!(global_i32(&plugins, "value"), 42);
+
+    // Traps in the hook are returned.
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert!(plugin.dispatch_event(99, &hello).is_err());
+}
+
+/// Guest that traps on events of type 99, with a bump allocator
+/// region of 16 bytes at 512.
+const CRASHING: &str = r#"(module
+    (memory (export "memory") 1)
+    (data (i32.const 512) "bump")
+    (func (export "__gers_event_alloc") (param i32) (result i32) (i32.const 256))
+    (func (export "__gers_event_update") (param $type i32) (param $ptr i32) (result i32)
+        (if (i32.eq (local.get $type) (i32.const 99)) (then unreachable))
+        (i32.const 0))
+    (func (export "__gers_bump_region") (result i64) (i64.const 0x10_0000_0200)))"#;
+
+#[test]
+fn test_crash_diagnostics() {
+    use sha2::Digest;
+
+    let dir = PluginDir::new("crashing", Some(CRASHING));
+    let module = fs::read(dir.path().join("main.wasm")).unwrap();
+    let mut plugins = Plugins::builder().event_history(2).build();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+
+    for data in 1..=2 {
+        let hello = HelloEvent {
+            data,
+            padding: 0,
+            div: 1,
+        };
+        plugin
+            .dispatch_event(EventType::Hello as i32, &hello)
+            .unwrap();
+    }
+    let hello = HelloEvent {
+        data: 3,
+        padding: 0,
+        div: 1,
+    };
+    assert!(plugin.dispatch_event(99, &hello).is_err());
+
+    // The event that trapped is the last one kept.
+    let events = plugin.delivered_events();
+    let types: Vec<_> = events.iter().map(|event| event.event_type).collect();
+    assert_eq!(types, [EventType::Hello as i32, 99]);
+    assert_eq!(events[0].data[0], 2);
+    assert_eq!(events[1].data.len(), std::mem::size_of::<HelloEvent>());
+
+    let sha256 = hex::encode(sha2::Sha256::digest(&module));
+    assert_eq!(plugin.module_hash(), Some(sha256.as_str()));
+
+    let (ptr, region) = plugin.bump_region().unwrap();
+    assert_eq!(ptr, 512);
+    assert_eq!(region.len(), 16);
+    assert_eq!(&region[..4], b"bump");
+
+    // Without an event history, nothing is kept.
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+    plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap();
+    assert!(plugin.delivered_events().is_empty());
+}
+
+#[test]
+fn test_null_event_buffer() {
+    let dir = PluginDir::new("null-buffer", Some(&event_buffer_at(0)));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    assert!(plugin.init().is_err());
+    assert!(plugin.data_ptr.is_none());
+    let hello = HelloEvent {
+        data: 1,
+        padding: 0,
+        div: 1,
+    };
+    assert!(!plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "calls"), 0);
+
+    // Plugins loaded at runtime are refused.
+    let err = Plugins::new()
+        .load_plugin_dir_at_runtime(dir.path())
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Initialize(_)));
+}
+
+#[test]
+fn test_event_buffer_without_space() {
+    // The buffer starts four bytes before the end of the memory.
+    let dir = PluginDir::new(
+        "full-buffer",
+        Some(&event_buffer_at(WASM_PAGE_SIZE as u32 - 4)),
+    );
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+    plugin.init().unwrap();
+    let hello = HelloEvent {
+        data: 1,
+        padding: 0,
+        div: 1,
+    };
+    assert!(!plugin
+        .dispatch_event(EventType::Hello as i32, &hello)
+        .unwrap());
+    assert_eq!(global_i32(&plugins, "calls"), 0);
+}
+
+#[test]
+fn test_faulty_modules_are_reported() {
+    let dir = PluginDir::new("faulty-module", Some("(module)"));
+    let load = |plugins: &mut Plugins, wat: &str| {
+        fs::write(
+            dir.path().join("main.wasm"),
+            wat2wasm(wat.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        plugins.load_plugin_dir(dir.path()).unwrap_err()
+    };
+
+    fs::write(dir.path().join("main.wasm"), b"not a module").unwrap();
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Compile(_)));
+
+    // Without the host providing `test_host`.
+    let err = load(&mut Plugins::new(), ECHO);
+    assert!(matches!(err.inner(), PluginError::Instantiate(_)));
+
+    // The import exists, but with another signature.
+    let err = load(
+        &mut test_host(),
+        r#"(module (import "test_host" "double" (func (param f32))))"#,
+    );
+    assert!(matches!(err.inner(), PluginError::Instantiate(_)));
+
+    let err = load(
+        &mut Plugins::new(),
+        r#"(module (func $start unreachable) (start $start))"#,
+    );
+    assert!(matches!(err.inner(), PluginError::Instantiate(_)));
+
+    // Hooks must be functions.
+    let err = load(
+        &mut Plugins::new(),
+        r#"(module (global (export "__gers_update") i32 (i32.const 0)))"#,
+    );
+    assert!(matches!(err.inner(), PluginError::InvalidHooks(_)));
+}
+
+/// Plugin directory whose meta file makes the plugin strict.
+fn strict_plugin(name: &str, hooks: &str, wat: &str) -> PluginDir {
+    let dir = PluginDir::new(name, Some(wat));
+    fs::write(
+        dir.path().join("plugin.toml"),
+        format!(
+            "name = {:?}\nversion = \"1.0.0\"\nstrict = true\n{}",
+            name, hooks
+        ),
+    )
+    .unwrap();
+
+    dir
+}
+
+const UPDATE_ONLY: &str = r#"(module
+    (memory (export "memory") 1)
+    (func (export "__gers_update")))"#;
+
+#[test]
+fn test_strict_plugins_require_hooks() {
+    // Without a list, the core hooks are required.
+    let dir = strict_plugin("strict-core", "", UPDATE_ONLY);
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    match err.inner() {
+        PluginError::MissingHooks(missing) => {
+            assert_eq!(missing, "__gers_event_alloc, __gers_event_update")
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+
+    let dir = strict_plugin("strict-listed", "hooks = [\"update\"]\n", UPDATE_ONLY);
+    Plugins::new().load_plugin_dir(dir.path()).unwrap();
+
+    let dir = strict_plugin("strict-unknown", "hooks = [\"updat\"]\n", UPDATE_ONLY);
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::MissingHooks(_)));
+}
+
+#[test]
+fn test_lint_hooks() {
+    let dir = PluginDir::new("linted", Some(UPDATE_ONLY));
+    let data_only = PluginDir::new("linted-data-only", None);
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    plugins.load_plugin_dir(data_only.path()).unwrap();
+
+    let lint = plugins.lint("linted").unwrap();
+    assert_eq!(lint.found, ["__gers_update"]);
+    assert!(lint.missing.contains(&"__gers_event_update"));
+    assert!(!lint.missing.contains(&"__gers_update"));
+
+    let lint = plugins.lint("linted-data-only").unwrap();
+    assert!(lint.found.is_empty());
+    assert!(plugins.lint("unknown").is_none());
+}
+
+#[test]
+fn test_plugin_info() {
+    let dir = PluginDir::new("inspected", Some(&event_buffer_at(64)));
+    let data_only = PluginDir::new("inspected-data-only", None);
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    plugins.load_plugin_dir(data_only.path()).unwrap();
+
+    assert_eq!(
+        plugins.names().collect::<Vec<_>>(),
+        ["inspected", "inspected-data-only"]
+    );
+    assert!(plugins.get("unknown").is_none());
+
+    let info = plugins.get("inspected").unwrap().info();
+    assert_eq!(info.version, "1.0.0");
+    assert_eq!(info.root, dir.path());
+    assert_eq!(info.state, PluginState::Uninitialised);
+    assert_eq!(info.memory.pages, 1);
+    assert_eq!(info.hooks, ["__gers_event_alloc", "__gers_event_update"]);
+    assert!(info.events.handles.is_empty());
+
+    plugins.iter_plugins_mut().next().unwrap().init().unwrap();
+    let infos = plugins.infos();
+    assert_eq!(infos[0].state, PluginState::Running);
+    assert_eq!(infos[1].state, PluginState::DataOnly);
+    assert!(infos[1].hooks.is_empty());
+}
+
+#[test]
+fn test_enabled_list_round_trip() {
+    let root = PluginDir::new("enabled-round-trip", None);
+    let path = root.path().join("enabled.toml");
+
+    let mut list = EnabledList::load(&path).unwrap();
+    assert_eq!(list.iter().count(), 0);
+    assert!(list.is_enabled("unknown"));
+
+    list.set_enabled("off", false);
+    list.set_enabled("on", true);
+    assert!(!list.is_enabled("off"));
+    assert!(list.is_enabled("on"));
+    list.save().unwrap();
+
+    let mut list = EnabledList::load(&path).unwrap();
+    assert_eq!(
+        list.iter().collect::<Vec<_>>(),
+        vec![("off", false), ("on", true)]
+    );
+    assert!(list.is_enabled("unknown"));
+
+    // Unchanged lists aren't written again.
+    fs::remove_file(&path).unwrap();
+    list.set_enabled("off", false);
+    list.save().unwrap();
+    assert!(!path.exists());
+
+    // Lists that weren't loaded from a file aren't persisted.
+    let mut list = EnabledList::default();
+    list.set_enabled("off", false);
+    list.save().unwrap();
+
+    fs::write(&path, "plugins = 1").unwrap();
+    assert!(EnabledList::load(&path).is_err());
+}
+
+#[test]
+fn test_enabled_list_discovers_plugins() {
+    let dir = PluginDir::new("enabled-discover", None);
+    let path = dir.path().join("enabled.toml");
+
+    let mut plugins = Plugins::new();
+    plugins.load_enabled_list(&path).unwrap();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    plugins.save_enabled_list().unwrap();
+    assert_eq!(
+        EnabledList::load(&path).unwrap().iter().collect::<Vec<_>>(),
+        vec![("enabled-discover", true)]
+    );
+
+    plugins.set_enabled("enabled-discover", false).unwrap();
+    plugins.set_enabled("not-installed", false).unwrap();
+
+    let mut plugins = Plugins::new();
+    plugins.load_enabled_list(&path).unwrap();
+    let err = plugins.load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Disabled(name) if name == "enabled-discover"));
+    assert_eq!(
+        plugins.enabled_list().iter().collect::<Vec<_>>(),
+        vec![("enabled-discover", false), ("not-installed", false)]
+    );
+
+    // Disabled plugins were still found.
+    assert_eq!(plugins.prune_enabled_list().unwrap(), ["not-installed"]);
+    assert_eq!(
+        EnabledList::load(&path).unwrap().iter().collect::<Vec<_>>(),
+        vec![("enabled-discover", false)]
+    );
+}
+
+fn keypair(seed: u8) -> ed25519_dalek::Keypair {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = ed25519_dalek::PublicKey::from(&secret);
+    ed25519_dalek::Keypair { secret, public }
+}
+
+fn load_signed(dir: &PluginDir, policy: TrustPolicy) -> Result<(), PluginError> {
+    let mut plugins = Plugins::new();
+    plugins.set_trust_policy(policy);
+    plugins.add_trusted_key(keypair(1).public);
+    plugins.load_plugin_dir(dir.path())
+}
+
+#[test]
+fn test_pinned_module_hash() {
+    use sha2::Digest;
+
+    let dir = PluginDir::new("pinned", Some("(module)"));
+    let module = fs::read(dir.path().join("main.wasm")).unwrap();
+    let write_meta = |sha256: &str| {
+        fs::write(
+            dir.path().join("plugin.toml"),
+            format!(
+                "name = \"pinned\"\nversion = \"1.0.0\"\nsha256 = {:?}\n",
+                sha256
+            ),
+        )
+        .unwrap();
+    };
+    let sha256 = hex::encode(sha2::Sha256::digest(&module));
+
+    write_meta(&sha256);
+    load_signed(&dir, TrustPolicy::Verify).unwrap();
+    write_meta(&sha256.to_uppercase());
+    load_signed(&dir, TrustPolicy::Verify).unwrap();
+
+    // Pinned hashes don't stand in for a signature.
+    let err = load_signed(&dir, TrustPolicy::RequireSigned).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::IntegrityCheckFailed(_)));
+
+    write_meta(&"0".repeat(64));
+    let err = load_signed(&dir, TrustPolicy::Verify).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::IntegrityCheckFailed(_)));
+    load_signed(&dir, TrustPolicy::Development).unwrap();
+}
+
+#[test]
+fn test_malformed_keys_and_signatures() {
+    let public = hex::encode(keypair(1).public.as_bytes());
+    assert_eq!(
+        gers_plugins::parse_public_key(&public).unwrap(),
+        keypair(1).public
+    );
+    for key in ["", "not hex", "abcd", &public[2..]] {
+        assert!(gers_plugins::parse_public_key(key).is_err(), "{:?}", key);
+    }
+
+    let dir = PluginDir::new("malformed-signature", Some("(module)"));
+    for signature in ["", "not hex", "abcd"] {
+        fs::write(
+            dir.path().join("plugin.toml"),
+            format!(
+                "name = \"malformed-signature\"\nversion = \"1.0.0\"\nsignature = {:?}\n",
+                signature
+            ),
+        )
+        .unwrap();
+        let err = load_signed(&dir, TrustPolicy::Verify).unwrap_err();
+        assert!(matches!(err.inner(), PluginError::IntegrityCheckFailed(_)));
+    }
+}
+
+/// Write a `.gpak` archive holding the given files.
+fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+    use std::io::Write;
+
+    let mut archive = zip::ZipWriter::new(fs::File::create(path).unwrap());
+    for (name, contents) in files {
+        archive
+            .start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        archive.write_all(contents).unwrap();
+    }
+    archive.finish().unwrap();
+}
+
+#[test]
+fn test_load_plugin_archive() {
+    let dir = PluginDir::new("archive", None);
+    let path = dir.path().join("archive.gpak");
+    let module = wat2wasm(b"(module)").unwrap();
+    write_archive(
+        &path,
+        &[
+            ("plugin.toml", b"name = \"packed\"\nversion = \"1.0.0\"\n"),
+            ("main.wasm", &module),
+            ("assets/a.txt", b"asset"),
+        ],
+    );
+
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_archive(&path).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert_eq!(plugin.meta().name, "packed");
+    assert!(!plugin.is_data_only());
+    assert_eq!(plugin.root(), path);
+    assert!(matches!(plugin.source(), PluginSource::Archive(archive) if archive == &path));
+
+    let source = plugin.source();
+    assert_eq!(source.read_file("assets/a.txt").unwrap(), b"asset");
+    assert_eq!(
+        source.read_file("assets/missing.txt").unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+    assert!(source.read_file("../archive.gpak").is_err());
+}
+
+#[test]
+fn test_faulty_archives_are_reported() {
+    let dir = PluginDir::new("faulty-archive", None);
+
+    let path = dir.path().join("no-meta.gpak");
+    write_archive(&path, &[("main.wasm", b"")]);
+    let err = Plugins::new().load_plugin_archive(&path).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::MissingMetaFile(_)));
+    assert_eq!(err.plugin_path(), Some(path.as_path()));
+
+    let path = dir.path().join("corrupt.gpak");
+    fs::write(&path, b"not a zip archive").unwrap();
+    assert!(Plugins::new().load_plugin_archive(&path).is_err());
+
+    let path = dir.path().join("missing.gpak");
+    let err = Plugins::new().load_plugin_archive(&path).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::LoadFile(_)));
+}
+
+/// Claim `size` as the uncompressed size of the archive's only entry,
+/// in a zip64 extra field of its central directory header.
+fn forge_entry_size(archive: &mut Vec<u8>, size: u64) {
+    let find = |archive: &[u8], signature: &[u8]| {
+        archive
+            .windows(signature.len())
+            .position(|window| window == signature)
+            .unwrap()
+    };
+    let u16_at = |archive: &[u8], at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]);
+
+    let central = find(archive, b"PK\x01\x02");
+    archive[central + 24..central + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+    let name_len = u16_at(archive, central + 28) as usize;
+    let extra_len = u16_at(archive, central + 30);
+    archive[central + 30..central + 32].copy_from_slice(&(extra_len + 12).to_le_bytes());
+
+    let mut zip64 = vec![];
+    zip64.extend_from_slice(&1u16.to_le_bytes());
+    zip64.extend_from_slice(&8u16.to_le_bytes());
+    zip64.extend_from_slice(&size.to_le_bytes());
+    let at = central + 46 + name_len + extra_len as usize;
+    archive.splice(at..at, zip64);
+
+    // The central directory grew by the extra field.
+    let end = find(archive, b"PK\x05\x06");
+    let dir_size = u32::from_le_bytes(archive[end + 12..end + 16].try_into().unwrap());
+    archive[end + 12..end + 16].copy_from_slice(&(dir_size + 12).to_le_bytes());
+}
+
+#[test]
+fn test_archive_entry_sizes_are_not_trusted() {
+    let dir = PluginDir::new("forged-archive", None);
+    let path = dir.path().join("forged.gpak");
+    write_archive(&path, &[("assets/a.txt", b"asset")]);
+    let mut archive = fs::read(&path).unwrap();
+    forge_entry_size(&mut archive, u64::MAX);
+    fs::write(&path, archive).unwrap();
+
+    let source = PluginSource::Archive(path);
+    assert_eq!(source.read_file("assets/a.txt").unwrap(), b"asset");
+}
+
+#[test]
+fn test_paths_from_other_platforms() {
+    // Made on a case-insensitive file system.
+    let dir = PluginDir::new("windows-made", None);
+    fs::rename(
+        dir.path().join("plugin.toml"),
+        dir.path().join("Plugin.TOML"),
+    )
+    .unwrap();
+    fs::create_dir_all(dir.path().join("Assets")).unwrap();
+    fs::write(dir.path().join("Assets").join("A.txt"), b"asset").unwrap();
+    fs::write(dir.path().join("MAIN.wasm"), wat2wasm(b"(module)").unwrap()).unwrap();
+
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(!plugin.is_data_only());
+    assert_eq!(
+        plugin.source().read_file(r"assets\a.txt").unwrap(),
+        b"asset"
+    );
+
+    // Zipped with backslashes.
+    let path = dir.path().join("windows-made.GPAK");
+    let module = wat2wasm(b"(module)").unwrap();
+    write_archive(
+        &path,
+        &[
+            ("PLUGIN.toml", b"name = \"zipped\"\nversion = \"1.0.0\"\n"),
+            ("Main.wasm", &module),
+            (r"Assets\A.txt", b"asset"),
+        ],
+    );
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_archive(&path).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(!plugin.is_data_only());
+    assert_eq!(plugin.source().read_file("assets/a.txt").unwrap(), b"asset");
+
+    #[cfg(unix)]
+    {
+        let link = dir.path().with_extension("link");
+        std::os::unix::fs::symlink(dir.path(), &link).unwrap();
+        let err = Plugins::new().load_plugin_dir(&link).unwrap_err();
+        fs::remove_file(&link).unwrap();
+        assert!(matches!(err.inner(), PluginError::InvalidPath(_)));
+        assert_eq!(err.plugin_path(), Some(link.as_path()));
+    }
+}
+
+/// Writes "hello\n" to standard output when initialised, like the
+/// runtime of a `wasm32-wasi` reactor module.
+#[cfg(feature = "wasi")]
+const WASI_HELLO: &str = r#"(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 16) "hello\n")
+    (func (export "_initialize")
+        (i32.store (i32.const 0) (i32.const 16))
+        (i32.store (i32.const 4) (i32.const 6))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))"#;
+
+/// Plugin directory whose meta file asks for WASI.
+fn wasi_plugin(name: &str, wat: &str) -> PluginDir {
+    let dir = PluginDir::new(name, Some(wat));
+    fs::write(
+        dir.path().join("plugin.toml"),
+        format!("name = {:?}\nversion = \"1.0.0\"\nwasi = true\n", name),
+    )
+    .unwrap();
+
+    dir
+}
+
+#[cfg(feature = "wasi")]
+#[test]
+fn test_wasi_output() {
+    let dir = wasi_plugin("wasi-hello", WASI_HELLO);
+
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+
+    let output = plugin.take_wasi_output().unwrap();
+    assert_eq!(output.stdout, "hello\n");
+    assert_eq!(output.stderr, "");
+    // Output is only taken once.
+    assert_eq!(plugin.take_wasi_output().unwrap().stdout, "");
+}
+
+#[cfg(not(feature = "wasi"))]
+#[test]
+fn test_wasi_needs_feature() {
+    let dir = wasi_plugin("wasi-unsupported", "(module)");
+
+    let err = Plugins::new().load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::WasiUnsupported));
+}
+
+#[test]
+fn test_deterministic_refuses_wasi() {
+    let dir = wasi_plugin("wasi-deterministic", "(module)");
+
+    let mut plugins = Plugins::builder().deterministic(true).build();
+    assert!(plugins.is_deterministic());
+    let err = plugins.load_plugin_dir(dir.path()).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Nondeterministic(_)));
+}
+
+#[test]
+fn test_deterministic_canonicalizes_nans() {
+    // Adding to a NaN with a payload keeps the payload on most CPUs.
+    let dir = PluginDir::new(
+        "deterministic-nan",
+        Some(
+            r#"(module
+        (func (export "nan") (result i32)
+            (i32.reinterpret_f32
+                (f32.add (f32.reinterpret_i32 (i32.const 0x7fc00001)) (f32.const 0)))))"#,
+        ),
+    );
+
+    let mut plugins = Plugins::builder().deterministic(true).build();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let nan = plugin
+        .instance()
+        .unwrap()
+        .exports
+        .get_function("nan")
+        .unwrap();
+    assert_eq!(nan.call(&[]).unwrap()[0].i32(), Some(0x7fc00000));
+}
+
+/// Spins forever on its first update, and returns on the ones after.
+const HANGING: &str = r#"(module
+    (global $calls (mut i32) (i32.const 0))
+    (func (export "__gers_update")
+        (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+        (if (i32.eq (global.get $calls) (i32.const 1))
+            (then (loop $spin (br $spin))))))"#;
+
+#[test]
+fn test_watchdog_interrupts_hung_calls() {
+    let dir = PluginDir::new("hanging", Some(HANGING));
+    let mut plugins = Plugins::builder()
+        .watchdog_timeout(Some(Duration::from_millis(50)))
+        .build();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    assert_eq!(plugins.watchdog_timeout(), Some(Duration::from_millis(50)));
+    let plugin = plugins.iter_plugins_mut().next().unwrap();
+
+    let err = plugin.update().unwrap_err();
+    assert!(err.message().contains("watchdog timeout"), "{}", err);
+    assert!(plugin.is_faulted());
+    assert_eq!(plugin.state(), PluginState::Faulted);
+
+    // Faulted plugins aren't called again.
+    let err = plugin.update().unwrap_err();
+    assert_eq!(err.message(), "plugin hanging is faulted");
+
+    // Without a watchdog, modules aren't instrumented.
+    let dir = PluginDir::new("unwatched", Some(HANGING));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    assert!(plugins.watchdog_timeout().is_none());
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let exports = &plugin.instance().unwrap().exports;
+    assert!(exports.get_global("gers:interrupt").is_err());
+}
+
+#[test]
+fn test_plugins_without_wasi_have_no_output() {
+    let dir = PluginDir::new("no-wasi", Some("(module)"));
+
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+    let plugin = plugins.iter_plugins().next().unwrap();
+    assert!(plugin.take_wasi_output().is_none());
+}
+
+/// Stores its argument at address 0 and in a global, and grows
+/// its memory, so every part of its state changes.
+const STATEFUL: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $value (export "value") (mut i32) (i32.const 0))
+    (global (export "constant") i32 (i32.const 7))
+    (func (export "set") (param i32)
+        (global.set $value (local.get 0))
+        (i32.store (i32.const 0) (local.get 0))
+        (drop (memory.grow (i32.const 1)))
+        (i32.store (i32.const 65536) (local.get 0))))"#;
+
+fn set_state(plugins: &Plugins, value: i32) {
+    let plugin = plugins.iter_plugins().next().unwrap();
+    let set = plugin
+        .instance()
+        .unwrap()
+        .exports
+        .get_function("set")
+        .unwrap();
+    set.call(&[Val::I32(value)]).unwrap();
+}
+
+fn stored_i32(plugins: &Plugins, offset: usize) -> i32 {
+    let memory = plugins.iter_plugins().next().unwrap().memory().unwrap();
+    let bytes = unsafe { memory.data_unchecked() };
+    i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_snapshot_and_restore() {
+    let dir = PluginDir::new("snapshot", Some(STATEFUL));
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(dir.path()).unwrap();
+
+    let snapshot = plugins.iter_plugins().next().unwrap().snapshot().unwrap();
+    assert_eq!(snapshot.memory_size(), WASM_PAGE_SIZE as usize);
+
+    set_state(&plugins, 42);
+    assert_eq!(global_i32(&plugins, "value"), 42);
+    assert_eq!(stored_i32(&plugins, 0), 42);
+
+    let plugin = plugins.iter_plugins().next().unwrap();
+    plugin.restore(&snapshot).unwrap();
+    assert_eq!(global_i32(&plugins, "value"), 0);
+    assert_eq!(global_i32(&plugins, "constant"), 7);
+    assert_eq!(stored_i32(&plugins, 0), 0);
+    // Memory can't shrink, so pages grown since are zeroed.
+    assert_eq!(plugin.memory().unwrap().data_size(), 2 * WASM_PAGE_SIZE);
+    assert_eq!(stored_i32(&plugins, WASM_PAGE_SIZE as usize), 0);
+}
+
+#[test]
+fn test_snapshot_all_plugins() {
+    let stateful = PluginDir::new("snapshot-all", Some(STATEFUL));
+    let data_only = PluginDir::new("snapshot-data-only", None);
+    let mut plugins = Plugins::new();
+    plugins.load_plugin_dir(stateful.path()).unwrap();
+    plugins.load_plugin_dir(data_only.path()).unwrap();
+
+    // Data-only plugins have no state to snapshot.
+    let data_plugin = plugins
+        .iter_plugins()
+        .find(|plugin| plugin.is_data_only())
+        .unwrap();
+    assert!(data_plugin.snapshot().is_none());
+    let module_snapshot = plugins.iter_plugins().next().unwrap().snapshot().unwrap();
+    let err = data_plugin.restore(&module_snapshot).unwrap_err();
+    assert!(matches!(err.inner(), PluginError::Restore(_)));
+
+    set_state(&plugins, 1);
+    let snapshot = plugins.snapshot_all();
+    assert_eq!(snapshot.memory_size(), 2 * WASM_PAGE_SIZE as usize);
+
+    set_state(&plugins, 2);
+    plugins.restore_all(&snapshot).unwrap();
+    assert_eq!(global_i32(&plugins, "value"), 1);
+    assert_eq!(stored_i32(&plugins, 0), 1);
+    assert_eq!(stored_i32(&plugins, WASM_PAGE_SIZE as usize), 1);
+}
+
+/// Defines the custom event type `score`.
+const DEFINES_SCORE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 16) "defines score\n")
+  (func (export "__gers_event_manifest") (result i64)
+    ;; 14 bytes at 16
+    i64.const 60129542160))
+"#;
+
+/// Plugin with the given priority and `[input]` section.
+fn prioritized_plugin(name: &str, priority: i32, input: &str) -> PluginDir {
+    let dir = PluginDir::new(name, Some(DEFINES_SCORE));
+    fs::write(
+        dir.path().join("plugin.toml"),
+        format!(
+            "name = {:?}\nversion = \"1.0.0\"\npriority = {}\n[input]\n{}\n",
+            name, priority, input
+        ),
+    )
+    .unwrap();
+
+    dir
+}
+
+#[test]
+fn test_conflicts_resolved_by_priority() {
+    let low = prioritized_plugin("conflict-low", 0, "jump = [\"Space\"]\nfire = [\"F\"]");
+    let high = prioritized_plugin("conflict-high", 5, "dash = [\"Space\"]");
+    let same = prioritized_plugin("conflict-same", 0, "fire = [\"F\"]");
+
+    let mut plugins = Plugins::new();
+    for dir in [&low, &high, &same] {
+        plugins.load_plugin_dir(dir.path()).unwrap();
+    }
+    let order: Vec<&str> = plugins
+        .priority_order()
+        .into_iter()
+        .map(|plugin| plugin.meta().name.as_str())
+        .collect();
+    assert_eq!(order, ["conflict-high", "conflict-low", "conflict-same"]);
+
+    plugins.detect_conflicts();
+    assert_eq!(
+        plugins.conflicts(),
+        [
+            // Ties go to the plugin loaded first.
+            Conflict {
+                resource: Resource::CustomEvent("score".to_string()),
+                winner: "conflict-high".to_string(),
+                losers: vec!["conflict-low".to_string(), "conflict-same".to_string()],
+            },
+            Conflict {
+                resource: Resource::Key("Space".to_string()),
+                winner: "conflict-high".to_string(),
+                losers: vec!["conflict-low".to_string()],
+            },
+        ]
+    );
+    // Binding a key to the same action isn't a conflict.
+    assert!(!plugins.has_lost("conflict-same", &Resource::Key("F".to_string())));
+    assert!(plugins.has_lost("conflict-low", &Resource::Key("Space".to_string())));
+    assert_eq!(
+        plugins.conflicts()[1].to_string(),
+        "key Space: conflict-high wins over conflict-low"
+    );
+
+    // Conflicts found at runtime are kept by later checks.
+    let command = Resource::Command("spawn".to_string());
+    assert!(plugins.record_conflict(command.clone(), "conflict-high", "conflict-low"));
+    assert!(!plugins.record_conflict(command.clone(), "conflict-high", "conflict-low"));
+    plugins.detect_conflicts();
+    assert_eq!(plugins.conflicts().len(), 3);
+    assert!(plugins.has_lost("conflict-low", &command));
+}
+
+/// Library exporting functions to other plugins, with a bump allocator
+/// from 1024.
+const MATHLIB: &str = r#"(module
+    (memory (export "memory") 1)
+    (global $next (mut i32) (i32.const 1024))
+    (func (export "__gers_alloc") (param $size i32) (result i32)
+        (global.get $next)
+        (global.set $next (i32.add (global.get $next) (local.get $size))))
+    (func (export "__gers_free") (param i32 i32))
+    (func (export "add") (param i32 i32) (result i32)
+        (i32.add (local.get 0) (local.get 1)))
+    (func (export "sum") (param $ptr i32) (param $len i32) (result i32)
+        (local $total i32)
+        (block $done (loop $next
+            (br_if $done (i32.eqz (local.get $len)))
+            (local.set $total
+                (i32.add (local.get $total) (i32.load8_u (local.get $ptr))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+            (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+            (br $next)))
+        (local.get $total))
+    (func (export "fill") (param $ptr i32) (param $len i32)
+        (memory.fill (local.get $ptr) (i32.const 7) (local.get $len))))"#;
+
+const MATHLIB_EXPORTS: &str = r#"
+[[export]]
+name = "add"
+params = ["i32", "i32"]
+result = "i32"
+
+[[export]]
+name = "sum"
+params = ["bytes"]
+result = "i32"
+
+[[export]]
+name = "fill"
+params = ["buf"]
+"#;
+
+/// Plugin importing from the `MATHLIB` named `library`.
+fn calculator(library: &str) -> String {
+    format!(
+        r#"(module
+    (import "plugin:{0}" "add" (func $add (param i32 i32) (result i32)))
+    (import "plugin:{0}" "sum" (func $sum (param i32 i32) (result i32)))
+    (import "plugin:{0}" "fill" (func $fill (param i32 i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 0) "\01\02\03")
+    (func (export "run_add") (result i32) (call $add (i32.const 2) (i32.const 3)))
+    (func (export "run_sum") (result i32) (call $sum (i32.const 0) (i32.const 3)))
+    (func (export "run_fill") (result i32)
+        (call $fill (i32.const 100) (i32.const 4))
+        (i32.add (i32.load8_u (i32.const 103)) (i32.load8_u (i32.const 104)))))"#,
+        library
+    )
+}
+
+/// Plugin directory with extra lines in its meta file.
+fn plugin_with_meta(name: &str, meta: &str, wat: &str) -> PluginDir {
+    let dir = PluginDir::new(name, Some(wat));
+    fs::write(
+        dir.path().join("plugin.toml"),
+        format!("name = {:?}\nversion = \"1.0.0\"\n{}", name, meta),
+    )
+    .unwrap();
+
+    dir
+}
+
+#[test]
+fn test_plugins_import_from_libraries() {
+    let library = plugin_with_meta("mathlib", MATHLIB_EXPORTS, MATHLIB);
+    let calculator = plugin_with_meta(
+        "calculator",
+        "dependencies = [\"mathlib\"]\n",
+        &calculator("mathlib"),
+    );
+
+    // Libraries are loaded first, whatever the order they're found in.
+    let mut plugins = Plugins::new();
+    let errors = plugins.load_plugins([
+        PluginSource::Directory(calculator.path().to_path_buf()),
+        PluginSource::Directory(library.path().to_path_buf()),
+    ]);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let names: Vec<&str> = plugins
+        .iter_plugins()
+        .map(|plugin| plugin.meta().name.as_str())
+        .collect();
+    assert_eq!(names, ["mathlib", "calculator"]);
+
+    let calculator = plugins.iter_plugins().nth(1).unwrap();
+    let call = |name: &str| {
+        let function = calculator
+            .instance()
+            .unwrap()
+            .exports
+            .get_function(name)
+            .unwrap();
+        function.call(&[]).unwrap()[0].clone()
+    };
+    assert_eq!(call("run_add"), Val::I32(5));
+    assert_eq!(call("run_sum"), Val::I32(6));
+    assert_eq!(call("run_fill"), Val::I32(7));
+}
+
+#[test]
+fn test_unresolved_plugin_imports() {
+    // The library isn't loaded.
+    let orphan = plugin_with_meta(
+        "orphan",
+        "dependencies = [\"missing\"]\n",
+        &calculator("missing"),
+    );
+    let errors =
+        Plugins::new().load_plugins([PluginSource::Directory(orphan.path().to_path_buf())]);
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].inner(), PluginError::Dependency(_)));
+    assert_eq!(errors[0].plugin_name(), Some("orphan"));
+
+    // Plugins only import from the plugins they depend on.
+    let library = plugin_with_meta("otherlib", MATHLIB_EXPORTS, MATHLIB);
+    let undeclared = plugin_with_meta("undeclared", "", &calculator("otherlib"));
+    let mut plugins = Plugins::new();
+    let errors = plugins.load_plugins([
+        PluginSource::Directory(library.path().to_path_buf()),
+        PluginSource::Directory(undeclared.path().to_path_buf()),
+    ]);
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0]
+            .to_string()
+            .contains("doesn't depend on the plugin"),
+        "{}",
+        errors[0]
+    );
+
+    // Nor functions the library doesn't export.
+    let library = plugin_with_meta("emptylib", "", MATHLIB);
+    let importer = plugin_with_meta(
+        "importer",
+        "dependencies = [\"emptylib\"]\n",
+        &calculator("emptylib"),
+    );
+    let errors = Plugins::new().load_plugins([
+        PluginSource::Directory(library.path().to_path_buf()),
+        PluginSource::Directory(importer.path().to_path_buf()),
+    ]);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains("doesn't export it"));
+}
+
+#[test]
+fn test_startup_report() {
+    let wat = format!(
+        r#"(module
+        (memory (export "memory") 2)
+        (data (i32.const 16) "handles score\n")
+        (func (export "__gers_abi_version") (result i32) (i32.const {}))
+        (func (export "__gers_update"))
+        (func (export "__gers_event_manifest") (result i64)
+            ;; 14 bytes at 16
+            i64.const 60129542160))"#,
+        ABI_VERSION
+    );
+    let reported = plugin_with_meta("reported", "[permissions]\nstorage = true\n", &wat);
+    let legacy = PluginDir::new("reported-legacy", Some("(module)"));
+    let data_only = PluginDir::new("reported-data-only", None);
+    let broken = PluginDir::new("reported-broken", None);
+    fs::remove_file(broken.path().join("plugin.toml")).unwrap();
+
+    let mut plugins = Plugins::new();
+    let report = plugins.load_all([
+        PluginSource::Directory(reported.path().to_path_buf()),
+        PluginSource::Directory(legacy.path().to_path_buf()),
+        PluginSource::Directory(data_only.path().to_path_buf()),
+        PluginSource::Directory(broken.path().to_path_buf()),
+    ]);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.plugins.len(), 3);
+
+    let plugin = &report.plugins[0];
+    assert_eq!(plugin.name, "reported");
+    assert_eq!(plugin.root, reported.path());
+    assert_eq!(plugin.memory_pages, 2);
+    assert_eq!(
+        plugin.hooks,
+        [
+            "__gers_abi_version",
+            "__gers_update",
+            "__gers_event_manifest"
+        ]
+    );
+    assert_eq!(plugin.subscriptions, ["score"]);
+    assert_eq!(plugin.permissions, [Permission::Storage]);
+    assert!(plugin.warnings.is_empty());
+    assert!(plugin.compile_time > Duration::ZERO);
+    let line = plugin.to_string();
+    assert!(line.starts_with("reported 1.0.0: compiled in "), "{}", line);
+    assert!(line.ends_with("subscriptions: score; permissions: storage"));
+
+    // Guests without a version run in compatibility mode.
+    assert_eq!(report.plugins[1].warnings.len(), 1);
+    assert!(report.plugins[1].warnings[0].contains("compatibility mode"));
+
+    let data_only = &report.plugins[2];
+    assert_eq!(data_only.load_time(), Duration::ZERO);
+    assert!(data_only.hooks.is_empty());
+    assert!(data_only
+        .to_string()
+        .ends_with("0 pages; hooks: none; subscriptions: none; permissions: none"));
+
+    assert_eq!(report.warning_count(), 1);
+    assert!(report.slowest().unwrap().load_time() >= plugin.load_time());
+    let summary = report.to_string();
+    assert!(summary.starts_with("loaded 3 plugins in "), "{}", summary);
+    assert!(
+        summary.contains("1 failed, 1 warnings, slowest "),
+        "{}",
+        summary
+    );
+}
@@ -0,0 +1,510 @@
+//! Entity-component storage owned by the host and shared by plugins.
+//!
+//! Entities are plain ids. Components are opaque byte blobs, identified
+//! by a component id in the range `0..MAX_COMPONENTS`, so the set of
+//! components on an entity fits in a `u64` mask. The layout of the bytes
+//! is a contract between the plugins that use the component.
+//!
+//! Queries collect the matching entities up front and are then stepped
+//! through with a token, so plugins can iterate across the import
+//! boundary without borrowing the world. Results can be stepped one
+//! entity at a time, or copied out in chunks of fixed-size rows, each
+//! holding the entity id followed by the data of one component.
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+/// Number of distinct component ids.
+pub const MAX_COMPONENTS: u32 = 64;
+
+/// Entity id. Zero is never a valid entity.
+pub type Entity = u32;
+
+/// Component id, less than `MAX_COMPONENTS`.
+pub type ComponentId = u32;
+
+/// Identifies an in-progress query. Zero is never a valid token.
+pub type QueryToken = u32;
+
+/// Size of the entity id at the start of a query row.
+pub const ROW_HEADER_SIZE: usize = std::mem::size_of::<Entity>();
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldError {
+    #[error("entity {0} does not exist")]
+    NoEntity(Entity),
+
+    #[error("entity {0} has no component {1}")]
+    NoComponent(Entity, ComponentId),
+
+    #[error("component id {0} out of range")]
+    InvalidComponent(ComponentId),
+
+    #[error("entity ids exhausted")]
+    EntitiesExhausted,
+
+    #[error("query {0} does not exist")]
+    NoQuery(QueryToken),
+
+    #[error("buffer too small for a single row")]
+    BufferTooSmall,
+}
+
+#[derive(Debug)]
+pub struct World {
+    /// Last entity id handed out.
+    last_entity: Entity,
+    /// Component mask of each live entity.
+    entities: BTreeMap<Entity, u64>,
+    /// Component data by component id, then entity.
+    components: Vec<HashMap<Entity, Vec<u8>>>,
+    last_query: QueryToken,
+    queries: HashMap<QueryToken, Query>,
+}
+
+/// Entities matched by a query, with the position of the next one.
+#[derive(Debug)]
+struct Query {
+    entities: Vec<Entity>,
+    cursor: usize,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            last_entity: 0,
+            entities: BTreeMap::new(),
+            components: (0..MAX_COMPONENTS).map(|_| HashMap::new()).collect(),
+            last_query: 0,
+            queries: HashMap::new(),
+        }
+    }
+
+    /// Number of live entities.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains_key(&entity)
+    }
+
+    /// Create an entity without components.
+    ///
+    /// Ids are not reused, so a stale id can't refer to a new entity.
+    pub fn spawn(&mut self) -> Result<Entity, WorldError> {
+        let entity = self
+            .last_entity
+            .checked_add(1)
+            .ok_or(WorldError::EntitiesExhausted)?;
+        self.last_entity = entity;
+        self.entities.insert(entity, 0);
+
+        Ok(entity)
+    }
+
+    /// Remove an entity and all of its components.
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), WorldError> {
+        let mask = self
+            .entities
+            .remove(&entity)
+            .ok_or(WorldError::NoEntity(entity))?;
+
+        for component in mask_components(mask) {
+            self.components[component as usize].remove(&entity);
+        }
+
+        Ok(())
+    }
+
+    /// Add a component to an entity, replacing any previous data.
+    pub fn set_component(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+        data: Vec<u8>,
+    ) -> Result<(), WorldError> {
+        let bit = component_bit(component)?;
+        let mask = self
+            .entities
+            .get_mut(&entity)
+            .ok_or(WorldError::NoEntity(entity))?;
+
+        *mask |= bit;
+        self.components[component as usize].insert(entity, data);
+
+        Ok(())
+    }
+
+    pub fn component(&self, entity: Entity, component: ComponentId) -> Result<&[u8], WorldError> {
+        component_bit(component)?;
+        if !self.contains(entity) {
+            return Err(WorldError::NoEntity(entity));
+        }
+
+        self.components[component as usize]
+            .get(&entity)
+            .map(Vec::as_slice)
+            .ok_or(WorldError::NoComponent(entity, component))
+    }
+
+    pub fn remove_component(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Result<(), WorldError> {
+        let bit = component_bit(component)?;
+        let mask = self
+            .entities
+            .get_mut(&entity)
+            .ok_or(WorldError::NoEntity(entity))?;
+
+        if *mask & bit == 0 {
+            return Err(WorldError::NoComponent(entity, component));
+        }
+        *mask &= !bit;
+        self.components[component as usize].remove(&entity);
+
+        Ok(())
+    }
+
+    /// Start a query for the entities that have all the
+    /// components in the mask.
+    ///
+    /// Entities are yielded in the order they were spawned. Entities
+    /// despawned while the query is in progress are skipped.
+    pub fn query(&mut self, mask: u64) -> QueryToken {
+        let entities = self
+            .entities
+            .iter()
+            .filter(|(_, entity_mask)| *entity_mask & mask == mask)
+            .map(|(entity, _)| *entity)
+            .collect();
+
+        // Tokens wrap around, skipping zero and any still in use.
+        let mut token = self.last_query;
+        loop {
+            token = token.wrapping_add(1);
+            if token != 0 && !self.queries.contains_key(&token) {
+                break;
+            }
+        }
+        self.last_query = token;
+        self.queries.insert(
+            token,
+            Query {
+                entities,
+                cursor: 0,
+            },
+        );
+
+        token
+    }
+
+    /// Next entity of a query, or `None` when the query is
+    /// exhausted or the token is unknown.
+    pub fn query_next(&mut self, token: QueryToken) -> Option<Entity> {
+        let query = self.queries.get_mut(&token)?;
+
+        while let Some(entity) = query.entities.get(query.cursor).copied() {
+            query.cursor += 1;
+            if self.entities.contains_key(&entity) {
+                return Some(entity);
+            }
+        }
+
+        None
+    }
+
+    /// Append rows for the next entities of a query, until `max_rows`
+    /// rows were written or the query is exhausted.
+    ///
+    /// Each row is the entity id in little endian, followed by
+    /// `data_size` bytes of the component's data. Longer data is cut
+    /// off, and shorter or missing data is padded with zeroes.
+    ///
+    /// Returns the number of rows written, which is zero once the
+    /// query is exhausted.
+    pub fn query_rows(
+        &mut self,
+        token: QueryToken,
+        component: ComponentId,
+        data_size: usize,
+        max_rows: usize,
+        out: &mut Vec<u8>,
+    ) -> Result<usize, WorldError> {
+        component_bit(component)?;
+        let query = self
+            .queries
+            .get_mut(&token)
+            .ok_or(WorldError::NoQuery(token))?;
+        let store = &self.components[component as usize];
+
+        let mut rows = 0;
+        while let Some(entity) = query.entities.get(query.cursor).copied() {
+            if !self.entities.contains_key(&entity) {
+                query.cursor += 1;
+                continue;
+            }
+            if rows == max_rows {
+                if rows == 0 {
+                    return Err(WorldError::BufferTooSmall);
+                }
+                break;
+            }
+            query.cursor += 1;
+
+            out.extend_from_slice(&entity.to_le_bytes());
+            let data = store.get(&entity).map(Vec::as_slice).unwrap_or_default();
+            let copy_len = data.len().min(data_size);
+            out.extend_from_slice(&data[..copy_len]);
+            out.resize(out.len() + data_size - copy_len, 0);
+
+            rows += 1;
+        }
+
+        Ok(rows)
+    }
+
+    /// Release a query. Returns `false` if the token was unknown.
+    pub fn end_query(&mut self, token: QueryToken) -> bool {
+        self.queries.remove(&token).is_some()
+    }
+
+    /// Number of queries that haven't been ended.
+    pub fn open_queries(&self) -> usize {
+        self.queries.len()
+    }
+}
+
+fn component_bit(component: ComponentId) -> Result<u64, WorldError> {
+    if component < MAX_COMPONENTS {
+        Ok(1 << component)
+    } else {
+        Err(WorldError::InvalidComponent(component))
+    }
+}
+
+fn mask_components(mask: u64) -> impl Iterator<Item = ComponentId> {
+    (0..MAX_COMPONENTS).filter(move |component| mask & (1 << component) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_and_despawn() {
+        let mut world = World::new();
+        assert!(world.is_empty());
+
+        let a = world.spawn().unwrap();
+        let b = world.spawn().unwrap();
+        assert_ne!(a, 0);
+        assert_eq!(world.len(), 2);
+
+        world.despawn(a).unwrap();
+        assert!(!world.contains(a));
+        assert!(world.contains(b));
+        assert_eq!(world.despawn(a), Err(WorldError::NoEntity(a)));
+
+        // Ids aren't reused.
+        let c = world.spawn().unwrap();
+        assert!(c != a && c != b);
+
+        world.last_entity = Entity::MAX;
+        assert_eq!(world.spawn(), Err(WorldError::EntitiesExhausted));
+    }
+
+    #[test]
+    fn test_components() {
+        let mut world = World::new();
+        let entity = world.spawn().unwrap();
+
+        world.set_component(entity, 3, vec![1, 2]).unwrap();
+        assert_eq!(world.component(entity, 3).unwrap(), [1, 2]);
+        world.set_component(entity, 3, vec![3]).unwrap();
+        assert_eq!(world.component(entity, 3).unwrap(), [3]);
+        assert_eq!(
+            world.component(entity, 4),
+            Err(WorldError::NoComponent(entity, 4))
+        );
+
+        world.remove_component(entity, 3).unwrap();
+        assert_eq!(
+            world.remove_component(entity, 3),
+            Err(WorldError::NoComponent(entity, 3))
+        );
+
+        // Components are removed with their entity.
+        world.set_component(entity, 5, vec![5]).unwrap();
+        world.despawn(entity).unwrap();
+        assert_eq!(
+            world.component(entity, 5),
+            Err(WorldError::NoEntity(entity))
+        );
+        assert!(world.components[5].is_empty());
+    }
+
+    #[test]
+    fn test_invalid_ids() {
+        let mut world = World::new();
+        let entity = world.spawn().unwrap();
+
+        assert_eq!(
+            world.set_component(entity, MAX_COMPONENTS, vec![]),
+            Err(WorldError::InvalidComponent(MAX_COMPONENTS))
+        );
+        assert_eq!(
+            world.component(entity, MAX_COMPONENTS),
+            Err(WorldError::InvalidComponent(MAX_COMPONENTS))
+        );
+        assert_eq!(
+            world.set_component(99, 0, vec![]),
+            Err(WorldError::NoEntity(99))
+        );
+        assert_eq!(world.remove_component(99, 0), Err(WorldError::NoEntity(99)));
+        assert_eq!(world.component(0, 0), Err(WorldError::NoEntity(0)));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut world = World::new();
+        let a = world.spawn().unwrap();
+        let b = world.spawn().unwrap();
+        let c = world.spawn().unwrap();
+        world.set_component(a, 0, vec![]).unwrap();
+        world.set_component(a, 63, vec![]).unwrap();
+        world.set_component(b, 0, vec![]).unwrap();
+        world.set_component(c, 63, vec![]).unwrap();
+
+        let collect = |world: &mut World, mask: u64| {
+            let token = world.query(mask);
+            let entities: Vec<_> = std::iter::from_fn(|| world.query_next(token)).collect();
+            assert!(world.end_query(token));
+            entities
+        };
+        assert_eq!(collect(&mut world, 1), vec![a, b]);
+        assert_eq!(collect(&mut world, 1 << 63), vec![a, c]);
+        assert_eq!(collect(&mut world, 1 | 1 << 63), vec![a]);
+        assert_eq!(collect(&mut world, 0), vec![a, b, c]);
+        assert_eq!(collect(&mut world, 2), vec![]);
+        assert_eq!(world.open_queries(), 0);
+    }
+
+    #[test]
+    fn test_despawn_during_query() {
+        let mut world = World::new();
+        let a = world.spawn().unwrap();
+        let b = world.spawn().unwrap();
+
+        let token = world.query(0);
+        world.despawn(a).unwrap();
+        // Entities spawned after the query started aren't yielded.
+        world.spawn().unwrap();
+        assert_eq!(world.query_next(token), Some(b));
+        assert_eq!(world.query_next(token), None);
+        assert_eq!(world.query_next(token), None);
+
+        assert!(world.end_query(token));
+        assert!(!world.end_query(token));
+        assert_eq!(world.query_next(token), None);
+    }
+
+    #[test]
+    fn test_query_tokens_skip_zero_and_open_queries() {
+        let mut world = World::new();
+        let open = world.query(0);
+        world.last_query = QueryToken::MAX - 1;
+
+        assert_eq!(world.query(0), QueryToken::MAX);
+        let wrapped = world.query(0);
+        assert_ne!(wrapped, 0);
+        assert_ne!(wrapped, open);
+        assert_eq!(world.open_queries(), 3);
+    }
+
+    #[test]
+    fn test_query_rows() {
+        let mut world = World::new();
+        let a = world.spawn().unwrap();
+        let b = world.spawn().unwrap();
+        let c = world.spawn().unwrap();
+        world.set_component(a, 1, vec![1, 2, 3]).unwrap();
+        world.set_component(b, 1, vec![4]).unwrap();
+        world.set_component(c, 1, vec![5, 6]).unwrap();
+
+        let token = world.query(1 << 1);
+        let mut rows = vec![];
+        assert_eq!(world.query_rows(token, 1, 2, 2, &mut rows), Ok(2));
+        // Longer data is cut off, and shorter data padded.
+        let mut expected = vec![];
+        expected.extend_from_slice(&a.to_le_bytes());
+        expected.extend_from_slice(&[1, 2]);
+        expected.extend_from_slice(&b.to_le_bytes());
+        expected.extend_from_slice(&[4, 0]);
+        assert_eq!(rows, expected);
+
+        // The token continues where the last chunk ended.
+        rows.clear();
+        assert_eq!(world.query_rows(token, 1, 2, 2, &mut rows), Ok(1));
+        assert_eq!(rows[..ROW_HEADER_SIZE], c.to_le_bytes());
+        assert_eq!(world.query_rows(token, 1, 2, 2, &mut rows), Ok(0));
+    }
+
+    #[test]
+    fn test_query_rows_of_other_components() {
+        let mut world = World::new();
+        let a = world.spawn().unwrap();
+        let b = world.spawn().unwrap();
+        world.set_component(b, 2, vec![9]).unwrap();
+
+        // Entities without the component get zeroed data.
+        let token = world.query(0);
+        world.despawn(a).unwrap();
+        let mut rows = vec![];
+        assert_eq!(world.query_rows(token, 2, 1, 8, &mut rows), Ok(1));
+        assert_eq!(rows[..ROW_HEADER_SIZE], b.to_le_bytes());
+        assert_eq!(rows[ROW_HEADER_SIZE..], [9]);
+
+        let token = world.query(0);
+        rows.clear();
+        assert_eq!(world.query_rows(token, 7, 2, 8, &mut rows), Ok(1));
+        assert_eq!(rows[ROW_HEADER_SIZE..], [0, 0]);
+    }
+
+    #[test]
+    fn test_query_rows_errors() {
+        let mut world = World::new();
+        world.spawn().unwrap();
+        let token = world.query(0);
+        let mut rows = vec![];
+
+        assert_eq!(
+            world.query_rows(token, 0, 4, 0, &mut rows),
+            Err(WorldError::BufferTooSmall)
+        );
+        assert_eq!(
+            world.query_rows(token, MAX_COMPONENTS, 4, 1, &mut rows),
+            Err(WorldError::InvalidComponent(MAX_COMPONENTS))
+        );
+        assert_eq!(
+            world.query_rows(token + 1, 0, 4, 1, &mut rows),
+            Err(WorldError::NoQuery(token + 1))
+        );
+        assert!(rows.is_empty());
+
+        // A buffer too small for exhausted queries is fine.
+        assert_eq!(world.query_rows(token, 0, 4, 1, &mut rows), Ok(1));
+        assert_eq!(world.query_rows(token, 0, 4, 0, &mut rows), Ok(0));
+    }
+}